@@ -55,59 +55,269 @@
 //! println!("TDO data: {:?}", tdo);
 //! ```
 //!
+//! Vectors whose length is not a multiple of 8 are easier to build as a [`BitVector`]:
+//!
+//! ```ignore
+//! use xvc_protocol::BitVector;
+//!
+//! let tms: BitVector = [true, true, false, true, false].into_iter().collect();
+//! let tdi = BitVector::zeros(tms.len());
+//! let tdo = client.shift_bits(&tms, &tdi).await?;
+//! assert_eq!(tdo.len(), 5);
+//! ```
+//!
+//! Vectors that are generated on the fly can be sent in chunks instead:
+//!
+//! ```ignore
+//! let tms = [[0x00; 512], [0x00; 512]];
+//! let tdi = (0..4).map(|_| vec![0xFF; 256]);
+//! let tdo = client.shift_chunks(8192, tms, tdi).await?;
+//! assert_eq!(tdo.len(), 1024);
+//! ```
+//!
+//! ### Protecting Shifts with CRCs
+//!
+//! On links that may corrupt data, e.g. through proxies, shifts and their TDO responses can be
+//! protected with CRCs if the server supports the [`crc`](xvc_protocol::crc) extension:
+//!
+//! ```ignore
+//! use xvc_client::Builder;
+//!
+//! let mut client = Builder::new().crc(true).connect("127.0.0.1:2542").await?;
+//! let tdo = client.shift(8, &[0x00], &[0xA5]).await?;
+//! // Whether the server supports the extension
+//! println!("CRC enabled: {}", client.crc_enabled());
+//! ```
+//!
+//! ### Capturing Traffic
+//!
+//! To see what is exchanged on the wire, wrap the connection with the tee wrappers of
+//! [`xvc_protocol::capture`]:
+//!
+//! ```ignore
+//! use xvc_protocol::capture::{CaptureSink, TeeReader, TeeWriter};
+//!
+//! let sink = CaptureSink::new(std::fs::File::create("session.cap")?);
+//! let tcp = tokio::net::TcpStream::connect("127.0.0.1:2542").await?;
+//! let mut client = XvcClient::new(TeeReader::new(TeeWriter::new(tcp, sink.clone()), sink));
+//! ```
+//!
 //! ## Related Crates
 //!
 //! - [`xvc_server`](https://docs.rs/xvc-server/) - Server implementation
 //! - [`xvc_protocol`](https://docs.rs/xvc-protocol/) - Protocol encoding/decoding
 //! - [`xvc_server_linux`](https://docs.rs/xvc-server-debugbridge/) - Linux server drivers
-use std::io;
+use std::{
+    io::{self, IoSlice},
+    time::Duration,
+};
 
 use bytes::BytesMut;
+use socket2::{SockRef, TcpKeepalive};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::{TcpStream, ToSocketAddrs},
 };
+#[cfg(feature = "tls")]
+pub use tokio_rustls::rustls;
 use tokio_util::codec::Decoder;
+#[cfg(unix)]
+use {std::path::Path, tokio::net::UnixStream};
+#[cfg(feature = "tls")]
+use {
+    std::sync::Arc,
+    tokio_rustls::{
+        TlsConnector,
+        client::TlsStream,
+        rustls::{ClientConfig, pki_types::ServerName},
+    },
+};
 
 use xvc_protocol::{
-    BorrowedMessage, Message, XvcInfo, error::ReadError, tokio_codec::XvcInfoDecoder,
+    BitVector, BorrowedMessage, Message, Response, XvcInfo,
+    crc::{self, Crc32},
+    error::ReadError,
+    rw::ShiftEncoder,
+    tokio_codec::ResponseDecoder,
 };
 
 /// XVC client for remote JTAG operations.
 ///
 /// Connects to an XVC server and provides async methods for JTAG operations.
-/// All methods share a single persistent TCP connection.
-pub struct XvcClient {
-    tcp: TcpStream,
+/// All methods share a single persistent connection, usually over TCP.
+pub struct XvcClient<S = TcpStream> {
+    stream: S,
+    crc: Crc,
+}
+
+/// The state of the CRC extension on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Crc {
+    Off,
+    /// Wanted by the user, but the server was not asked yet
+    Requested,
+    On,
+}
+
+/// Builder to configure an [`XvcClient`].
+///
+/// # Example
+///
+/// ```ignore
+/// use xvc_client::Builder;
+///
+/// let client = Builder::new().crc(true).connect("127.0.0.1:2542").await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder {
+    crc: bool,
+    tcp_nodelay: bool,
+    tcp_keepalive: Option<Duration>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            crc: false,
+            tcp_nodelay: true,
+            tcp_keepalive: None,
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+
+    /// Protect shifts with CRCs if the server supports the [`crc`](xvc_protocol::crc)
+    /// extension. Servers without it are used as usual.
+    pub fn crc(mut self, crc: bool) -> Self {
+        self.crc = crc;
+        self
+    }
+
+    /// Disable Nagle's algorithm on TCP connections, so shifts are sent right away (default:
+    /// `true`).
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Send TCP keepalive probes after the connection has been idle for `idle`, e.g. to keep
+    /// a stateful firewall from dropping an idle session (default: none).
+    pub fn tcp_keepalive(mut self, idle: Duration) -> Self {
+        self.tcp_keepalive = Some(idle);
+        self
+    }
+
+    /// Talk to an XVC server over an established `stream`.
+    pub fn build<S: AsyncRead + AsyncWrite + Unpin>(self, stream: S) -> XvcClient<S> {
+        XvcClient {
+            stream,
+            crc: if self.crc { Crc::Requested } else { Crc::Off },
+        }
+    }
+
+    /// Connect to an XVC server at `addr`.
+    pub async fn connect(self, addr: impl ToSocketAddrs) -> io::Result<XvcClient> {
+        let tcp = self.connect_tcp(addr).await?;
+        Ok(self.build(tcp))
+    }
+
+    /// Connect to an XVC server on the Unix domain socket at `path`.
+    #[cfg(unix)]
+    pub async fn connect_unix(self, path: impl AsRef<Path>) -> io::Result<XvcClient<UnixStream>> {
+        Ok(self.build(UnixStream::connect(path).await?))
+    }
+
+    /// Connect to an XVC server at `addr` over TLS, verifying that its certificate is valid
+    /// for `server_name`. Client certificates, if the server requires them, are part of
+    /// `config`.
+    #[cfg(feature = "tls")]
+    pub async fn connect_tls(
+        self,
+        addr: impl ToSocketAddrs,
+        config: Arc<ClientConfig>,
+        server_name: ServerName<'static>,
+    ) -> io::Result<XvcClient<TlsStream<TcpStream>>> {
+        let tcp = self.connect_tcp(addr).await?;
+        let tls = TlsConnector::from(config).connect(server_name, tcp).await?;
+        Ok(self.build(tls))
+    }
+
+    /// Connect to `addr` and apply the socket options.
+    async fn connect_tcp(&self, addr: impl ToSocketAddrs) -> io::Result<TcpStream> {
+        let tcp = TcpStream::connect(addr).await?;
+        tcp.set_nodelay(self.tcp_nodelay)?;
+        if let Some(idle) = self.tcp_keepalive {
+            SockRef::from(&tcp).set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+        Ok(tcp)
+    }
 }
 
 impl XvcClient {
     /// Connect to an XVC server at `addr`.
     pub async fn connect(addr: impl ToSocketAddrs) -> io::Result<XvcClient> {
-        Ok(XvcClient {
-            tcp: TcpStream::connect(addr).await?,
-        })
+        Builder::new().connect(addr).await
+    }
+
+    /// Connect to an XVC server on the Unix domain socket at `path`.
+    #[cfg(unix)]
+    pub async fn connect_unix(path: impl AsRef<Path>) -> io::Result<XvcClient<UnixStream>> {
+        Builder::new().connect_unix(path).await
+    }
+
+    /// Connect to an XVC server at `addr` over TLS, see [`Builder::connect_tls`].
+    #[cfg(feature = "tls")]
+    pub async fn connect_tls(
+        addr: impl ToSocketAddrs,
+        config: Arc<ClientConfig>,
+        server_name: ServerName<'static>,
+    ) -> io::Result<XvcClient<TlsStream<TcpStream>>> {
+        Builder::new().connect_tls(addr, config, server_name).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> XvcClient<S> {
+    /// Talk to an XVC server over an established `stream`, e.g. a connection that is wrapped
+    /// to capture its traffic with [`xvc_protocol::capture`].
+    pub fn new(stream: S) -> XvcClient<S> {
+        Builder::new().build(stream)
+    }
+
+    /// Whether shifts are protected with CRCs.
+    ///
+    /// A client that was built with [`Builder::crc`] enables the extension with its first
+    /// call to [`get_info`](Self::get_info), which the first shift makes if necessary. Until
+    /// then, and if the server does not support the extension, this is `false`.
+    pub fn crc_enabled(&self) -> bool {
+        self.crc == Crc::On
     }
 
     /// Query server capabilities and version information.
+    ///
+    /// The version is returned as reported by the server. Use [`Version::is_supported`]
+    /// to refuse servers that this client cannot talk to.
+    ///
+    /// [`Version::is_supported`]: xvc_protocol::Version::is_supported
     pub async fn get_info(&mut self) -> Result<XvcInfo, ReadError> {
-        self.write_message(Message::GetInfo).await?;
-
-        let mut buf = BytesMut::new();
-        loop {
-            match XvcInfoDecoder.decode(&mut buf)? {
-                Some(info) => return Ok(info),
-                None => {
-                    if self.tcp.read_buf(&mut buf).await? == 0 {
-                        return Err(io::Error::new(
-                            io::ErrorKind::UnexpectedEof,
-                            "connection closed while reading server info",
-                        )
-                        .into());
-                    }
-                }
+        let message = BorrowedMessage::GetInfo;
+        self.write_message(message.clone()).await?;
+        let info = match self.read_response(&message).await? {
+            Response::Info(info) => info,
+            _ => unreachable!("GetInfo is answered with info"),
+        };
+        if self.crc == Crc::Requested {
+            if info.capabilities().iter().any(|c| c == crc::CAPABILITY) {
+                self.stream.write_all(crc::CMD_ENABLE).await?;
+                self.crc = Crc::On;
+            } else {
+                self.crc = Crc::Off;
             }
         }
+        Ok(info)
     }
 
     /// Set the JTAG Test Clock (TCK) period.
@@ -115,10 +325,12 @@ impl XvcClient {
     /// Returns the actual period set by the server, which may differ from the
     /// requested value if the hardware has limited frequency resolution.
     pub async fn set_tck(&mut self, period_ns: u32) -> Result<u32, ReadError> {
-        self.write_message(Message::SetTck { period_ns }).await?;
-        let mut buf = [0u8; 4];
-        self.tcp.read_exact(&mut buf).await?;
-        Ok(u32::from_le_bytes(buf))
+        let message = BorrowedMessage::SetTck { period_ns };
+        self.write_message(message.clone()).await?;
+        match self.read_response(&message).await? {
+            Response::TckPeriod(period_ns) => Ok(period_ns),
+            _ => unreachable!("SetTck is answered with a period"),
+        }
     }
 
     /// Perform a JTAG shift operation.
@@ -132,30 +344,157 @@ impl XvcClient {
     /// # Returns
     ///
     /// Test Data Out vector from the JTAG chain of the same length as `tms` and `tdi`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with an I/O error of kind `InvalidInput`, without sending anything, if `tms` or
+    /// `tdi` do not have the length required by `num_bits`. Fails with
+    /// [`ReadError::CrcMismatch`] if CRCs are enabled and the TDO vector was corrupted.
     pub async fn shift(
         &mut self,
         num_bits: u32,
         tms: &[u8],
         tdi: &[u8],
     ) -> Result<Box<[u8]>, ReadError> {
-        let num_bytes = num_bits.div_ceil(8) as usize;
-        assert!(
-            tms.len() == num_bytes && tdi.len() == num_bytes,
-            "tms/tdi must be {num_bytes} bytes for {num_bits} bits, got {} / {}",
-            tms.len(),
-            tdi.len(),
-        );
-        self.write_message(BorrowedMessage::Shift { num_bits, tms, tdi })
-            .await?;
-        let mut buf = vec![0u8; num_bytes];
-        self.tcp.read_exact(&mut buf).await?;
-        Ok(buf.into_boxed_slice())
+        let message = BorrowedMessage::try_shift(num_bits, tms, tdi).map_err(io::Error::from)?;
+        self.negotiate_crc().await?;
+        let crc = (self.crc == Crc::On).then(|| crc::shift_crc(num_bits, tms, tdi).to_le_bytes());
+        // Only the header is encoded; the vectors are written straight from the caller's slices
+        let mut header = [0; 10];
+        let len = message
+            .encode_header(&mut header)
+            .map_err(io::Error::from)?;
+        self.write_all_vectored(&mut [
+            IoSlice::new(&header[..len]),
+            IoSlice::new(tms),
+            IoSlice::new(tdi),
+            IoSlice::new(crc.as_ref().map_or(&[], |crc| &crc[..])),
+        ])
+        .await?;
+        match self.read_response(&message).await? {
+            Response::Tdo(tdo) => Ok(tdo),
+            _ => unreachable!("Shift is answered with TDO"),
+        }
+    }
+
+    /// Perform a JTAG shift operation with vectors that are passed in chunks, e.g. as an SVF
+    /// interpreter generates them.
+    ///
+    /// Each chunk is sent as it is taken from `tms` or `tdi`, so the vectors never need to be
+    /// in memory as a whole. Together, the chunks of each vector must have the length that
+    /// [`shift`](Self::shift) requires.
+    ///
+    /// # Errors
+    ///
+    /// Fails with an I/O error of kind `InvalidInput` if the chunks of a vector are too long,
+    /// before the excess chunk is sent, or too short. As the server then still waits for the
+    /// rest of the message, the connection cannot be used afterwards.
+    pub async fn shift_chunks<T, D>(
+        &mut self,
+        num_bits: u32,
+        tms: T,
+        tdi: D,
+    ) -> Result<Box<[u8]>, ReadError>
+    where
+        T: IntoIterator<Item: AsRef<[u8]>>,
+        D: IntoIterator<Item: AsRef<[u8]>>,
+    {
+        self.negotiate_crc().await?;
+        let mut crc = Crc32::new();
+        crc.update(&num_bits.to_le_bytes());
+        let mut encoder = ShiftEncoder::new(num_bits, &mut self.stream);
+        for chunk in tms {
+            encoder.write_tms_chunk_async(chunk.as_ref()).await?;
+            crc.update(chunk.as_ref());
+        }
+        for chunk in tdi {
+            encoder.write_tdi_chunk_async(chunk.as_ref()).await?;
+            crc.update(chunk.as_ref());
+        }
+        let stream = encoder.finish_async().await?;
+        if self.crc == Crc::On {
+            stream.write_all(&crc.finish().to_le_bytes()).await?;
+        }
+        let message = Message::<()>::Shift {
+            num_bits,
+            tms: (),
+            tdi: (),
+        };
+        match self.read_response(&message).await? {
+            Response::Tdo(tdo) => Ok(tdo),
+            _ => unreachable!("Shift is answered with TDO"),
+        }
+    }
+
+    /// Perform a JTAG shift operation with the bits of `tms` and `tdi`.
+    ///
+    /// Returns the TDO bits, of the same length as `tms` and `tdi`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with an I/O error of kind `InvalidInput`, without sending anything, if the vectors
+    /// hold more than `u32::MAX` bits, which one shift cannot carry. Otherwise fails like
+    /// [`shift`](Self::shift).
+    ///
+    /// # Panics
+    ///
+    /// If `tms` and `tdi` differ in length.
+    pub async fn shift_bits(
+        &mut self,
+        tms: &BitVector,
+        tdi: &BitVector,
+    ) -> Result<BitVector, ReadError> {
+        assert_eq!(tms.len(), tdi.len(), "tms and tdi must be of equal length");
+        let num_bits = u32::try_from(tms.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} bits are too many for a shift", tms.len()),
+            )
+        })?;
+        let tdo = self.shift(num_bits, tms.as_bytes(), tdi.as_bytes()).await?;
+        Ok(BitVector::from_bytes(&tdo, num_bits as usize))
+    }
+
+    /// Ask the server whether it supports the CRC extension, if requested and not done yet.
+    async fn negotiate_crc(&mut self) -> Result<(), ReadError> {
+        if self.crc == Crc::Requested {
+            self.get_info().await?;
+        }
+        Ok(())
     }
 
     async fn write_message(&mut self, msg: BorrowedMessage<'_>) -> Result<(), ReadError> {
         let mut buf = Vec::new();
         msg.write_to(&mut buf)?;
-        self.tcp.write_all(&buf).await?;
+        self.stream.write_all(&buf).await?;
+        Ok(())
+    }
+
+    async fn read_response<B>(&mut self, message: &Message<B>) -> Result<Response, ReadError> {
+        let mut decoder = ResponseDecoder::new(message);
+        if self.crc == Crc::On {
+            decoder = decoder.with_crc();
+        }
+        let mut buf = BytesMut::new();
+        loop {
+            if let Some(response) = decoder.decode(&mut buf)? {
+                return Ok(response);
+            }
+            if self.stream.read_buf(&mut buf).await? == 0 {
+                // Reports whether the server disconnected before or within the response
+                return decoder.decode_eof(&mut buf)?.ok_or(ReadError::Disconnected);
+            }
+        }
+    }
+
+    async fn write_all_vectored(&mut self, mut bufs: &mut [IoSlice<'_>]) -> io::Result<()> {
+        IoSlice::advance_slices(&mut bufs, 0);
+        while !bufs.is_empty() {
+            match self.stream.write_vectored(bufs).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                written => IoSlice::advance_slices(&mut bufs, written),
+            }
+        }
         Ok(())
     }
 }
@@ -1,5 +1,49 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
 use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
-use xvc_protocol::BorrowedMessage;
+use xvc_protocol::{BorrowedMessage, Message, MessageIter};
+
+/// Counts allocations to show how many the decoding of a stream needs
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// A stream of `count` shifts of `num_bits` each, cycling through the given lengths
+fn shift_stream(num_bits: &[u32], count: usize) -> Vec<u8> {
+    let mut stream = Vec::new();
+    for &num_bits in num_bits.iter().cycle().take(count) {
+        let vector = vec![0xA5; num_bits.div_ceil(8) as usize];
+        let shift = Message::try_shift(num_bits, &vector[..], &vector[..]).unwrap();
+        shift.write_to(&mut stream).unwrap();
+    }
+    stream
+}
+
+fn decode_all(stream: &[u8]) -> usize {
+    let mut count = 0;
+    for message in MessageIter::new(stream, usize::MAX) {
+        message.expect("Cannot decode message");
+        count += 1;
+    }
+    count
+}
 
 fn criterion_benchmark(c: &mut Criterion) {
     let message = BorrowedMessage::GetInfo;
@@ -43,6 +87,23 @@ fn criterion_benchmark(c: &mut Criterion) {
             writer
         })
     });
+
+    // IR scans and TAP state changes only shift a few bits, so their vectors are stored inline
+    // and decoding them needs no allocations. Long DR scans need two per message.
+    for (name, num_bits) in [
+        ("ir_scans", &[4u32, 5, 6, 10, 18, 32, 64][..]),
+        ("dr_scans", &[8192][..]),
+    ] {
+        let stream = shift_stream(num_bits, 1000);
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let count = decode_all(&stream);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!("decode/{name}: {allocations} allocations for {count} messages");
+
+        c.bench_with_input(BenchmarkId::new("decode", name), &stream, |b, stream| {
+            b.iter(|| decode_all(stream))
+        });
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,21 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use xvc_protocol::Message;
+
+/// From rejecting every non-empty shift to the default limit of the server
+const MAX_SHIFT_BYTES: [usize; 4] = [0, 16, 1024, 10 * 1024 * 1024];
+
+fuzz_target!(|data: &[u8]| {
+    for max_shift_bytes in MAX_SHIFT_BYTES {
+        if let Ok(message) = Message::from_reader(&mut Cursor::new(data), max_shift_bytes) {
+            // A decoded message encodes to the bytes it was decoded from
+            let mut encoded = Vec::new();
+            message.write_to(&mut encoded).unwrap();
+            assert!(data.starts_with(&encoded), "{message:?}");
+        }
+        let _ = Message::from_reader_lenient(&mut Cursor::new(data), max_shift_bytes);
+    }
+});
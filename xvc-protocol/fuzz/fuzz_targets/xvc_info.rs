@@ -0,0 +1,18 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use xvc_protocol::XvcInfo;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(info) = XvcInfo::from_reader(&mut Cursor::new(data)) {
+        // The encoding of a decoded info decodes to the same info
+        let mut encoded = Vec::new();
+        info.write_to(&mut encoded).unwrap();
+        assert_eq!(
+            XvcInfo::from_reader(&mut Cursor::new(&encoded)).unwrap(),
+            info
+        );
+    }
+});
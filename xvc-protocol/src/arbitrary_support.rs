@@ -0,0 +1,162 @@
+//! Generation of arbitrary protocol values with `arbitrary`, e.g. for fuzzing and property
+//! tests.
+//!
+//! Generated `Shift` messages always carry vectors of `num_bits.div_ceil(8)` bytes, so every
+//! generated message can be encoded. Their length is bounded by the size of the input data.
+use alloc::{string::String, vec::Vec};
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{Message, VectorLen, Version, XvcInfo};
+
+impl<'a> Arbitrary<'a> for Version {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Version::new(u.arbitrary()?, u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for VectorLen {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(VectorLen::from_bytes(u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for XvcInfo {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let info = XvcInfo::new(u.arbitrary()?, u.arbitrary::<VectorLen>()?);
+        let mut capabilities = Vec::new();
+        for _ in 0..u.int_in_range(0..=3u8)? {
+            // Printable ASCII, without the `:` that separates tokens
+            let token = (0..u.int_in_range(1..=8u8)?)
+                .map(|_| match u.int_in_range(b'!'..=b'~')? {
+                    b':' => Ok('_'),
+                    c => Ok(char::from(c)),
+                })
+                .collect::<Result<String>>()?;
+            capabilities.push(token);
+        }
+        Ok(info.with_capabilities(capabilities))
+    }
+}
+
+impl<'a, B: From<Vec<u8>> + 'a> Arbitrary<'a> for Message<B> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=2u8)? {
+            0 => Message::GetInfo,
+            1 => Message::SetTck {
+                period_ns: u.arbitrary()?,
+            },
+            _ => {
+                // Both vectors should fit into the remaining data
+                let max_bits = u32::try_from(8 * (u.len() / 2)).unwrap_or(u32::MAX);
+                let num_bits = u.int_in_range(0..=max_bits)?;
+                let num_bytes = num_bits.div_ceil(8) as usize;
+                Message::Shift {
+                    num_bits,
+                    tms: vector(u, num_bytes)?,
+                    tdi: vector(u, num_bytes)?,
+                }
+            }
+        })
+    }
+}
+
+fn vector<B: From<Vec<u8>>>(u: &mut Unstructured<'_>, num_bytes: usize) -> Result<B> {
+    let mut bytes = alloc::vec![0; num_bytes];
+    u.fill_buffer(&mut bytes)?;
+    Ok(B::from(bytes))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::io::Cursor;
+
+    use arbitrary::{Arbitrary, Unstructured};
+
+    use crate::{Message, OwnedMessage, XvcInfo, error::ReadError, rw::Decoder};
+
+    /// xorshift, so that failures are reproducible
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+            let len = self.next() as usize % (max_len + 1);
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+
+        fn arbitrary<T: for<'a> Arbitrary<'a>>(&mut self) -> T {
+            let data = self.bytes(256);
+            T::arbitrary(&mut Unstructured::new(&data)).unwrap()
+        }
+    }
+
+    #[test]
+    fn messages_roundtrip() {
+        let mut rng = Rng(0x1234_5678);
+        for _ in 0..2000 {
+            let message: OwnedMessage = rng.arbitrary();
+            let mut encoded = Vec::new();
+            message.write_to(&mut encoded).unwrap();
+
+            let num_bytes = match &message {
+                Message::Shift { tms, .. } => tms.len(),
+                _ => 0,
+            };
+            // A shift exactly at the limit is accepted ...
+            let decoded = OwnedMessage::from_reader(&mut encoded.as_slice(), num_bytes);
+            assert_eq!(decoded.unwrap(), message);
+            // ... and one byte above is not
+            if num_bytes > 0 {
+                let result = OwnedMessage::from_reader(&mut encoded.as_slice(), num_bytes - 1);
+                assert!(
+                    matches!(result, Err(ReadError::TooManyBytes { .. })),
+                    "{message:?}: {result:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn xvc_info_roundtrips() {
+        let mut rng = Rng(0x8765_4321);
+        for _ in 0..2000 {
+            let info: XvcInfo = rng.arbitrary();
+            let mut encoded = Vec::new();
+            info.write_to(&mut encoded).unwrap();
+            assert_eq!(XvcInfo::from_reader(&mut encoded.as_slice()).unwrap(), info);
+        }
+    }
+
+    #[test]
+    fn random_streams_end_in_an_error() {
+        let mut rng = Rng(0xDEAD_BEEF);
+        for _ in 0..5000 {
+            // Mostly garbage, but some streams get far enough to reach the payload parsers
+            let prefix: &[u8] = match rng.next() % 4 {
+                0 => b"getinfo:",
+                1 => b"settck:",
+                2 => b"shift:",
+                _ => b"",
+            };
+            let mut stream = prefix.to_vec();
+            stream.extend(rng.bytes(64));
+
+            // Every message consumes bytes, so a finite stream must end in an error
+            let mut reader = Cursor::new(&stream);
+            let mut decoder = Decoder::new(16);
+            let error = (0..=stream.len())
+                .find_map(|_| decoder.read_message(&mut reader).err())
+                .unwrap_or_else(|| panic!("no error for {stream:?}"));
+            assert!(!matches!(error, ReadError::IoError(_)), "{error:?}");
+
+            let _ = XvcInfo::from_reader(&mut stream.as_slice());
+        }
+    }
+}
@@ -0,0 +1,491 @@
+//! Async read and write implementations for the protocol messages
+//!
+//! These mirror the blocking methods in [`rw`](crate::rw), but read exactly the bytes of one
+//! message, so a stream can be shared with other readers. As commands and the server info are
+//! read byte by byte, wrapping unbuffered streams like `TcpStream` in a
+//! [`tokio::io::BufReader`] is recommended.
+//!
+//! The [`capture`](crate::capture) wrappers implement `AsyncRead` and `AsyncWrite` here as well.
+use std::{
+    io::{self, IoSlice},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use crate::{
+    Message, OwnedMessage, ShiftVector, XvcCommand, XvcInfo,
+    capture::{Direction, TeeReader, TeeWriter},
+    codec::{ParseErr, Shift},
+    error::ReadError,
+    rw::{ShiftEncoder, Vector},
+};
+
+/// Upper bound for the length of the server info line, including the newline
+const MAX_INFO_LEN: usize = 64;
+
+impl Message<ShiftVector> {
+    /// Read a `Message` from the async `reader`.
+    ///
+    /// Like [`Message::from_reader`], `Shift` commands with TMS or TDI vectors larger than
+    /// `max_shift_bytes` are rejected with `ReadError::TooManyBytes` before their payload is read.
+    /// If the stream ends before the first byte, `ReadError::Disconnected` is returned; if it
+    /// ends within the message, `ReadError::TruncatedMessage` is returned.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let mut data = b"settck:\x64\x00\x00\x00".as_slice();
+    /// let msg = xvc_protocol::OwnedMessage::from_async_reader(&mut data, 1024)
+    ///     .await
+    ///     .unwrap();
+    /// assert!(matches!(msg, xvc_protocol::Message::SetTck { period_ns: 100 }));
+    /// # }
+    /// ```
+    pub async fn from_async_reader(
+        reader: &mut (impl AsyncRead + Unpin),
+        max_shift_bytes: usize,
+    ) -> Result<OwnedMessage, ReadError> {
+        let mut buf = Vec::with_capacity(16);
+        let command = loop {
+            match XvcCommand::parse(&mut buf.as_slice()) {
+                Ok(command) => break command,
+                Err(ParseErr::Incomplete) => fill(reader, &mut buf, 1).await?,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        match command {
+            XvcCommand::GetInfo => Ok(Message::GetInfo),
+            XvcCommand::SetTck => {
+                fill(reader, &mut buf, 4).await?;
+                let mut period = [0; 4];
+                period.copy_from_slice(&buf[buf.len() - 4..]);
+                Ok(Message::SetTck {
+                    period_ns: u32::from_le_bytes(period),
+                })
+            }
+            XvcCommand::Shift => {
+                fill(reader, &mut buf, 4).await?;
+                let mut num_bits = [0; 4];
+                num_bits.copy_from_slice(&buf[buf.len() - 4..]);
+                let num_bits = u32::from_le_bytes(num_bits);
+                let num_bytes = Shift::num_bytes(num_bits, max_shift_bytes)?;
+                let header = buf.len();
+                fill(reader, &mut buf, 2 * num_bytes).await?;
+                let tms = buf[header..header + num_bytes].into();
+                let tdi = buf[header + num_bytes..].into();
+                Ok(Message::Shift { num_bits, tms, tdi })
+            }
+        }
+    }
+}
+
+/// Append exactly `len` bytes from `reader` to the message read so far in `buf`.
+async fn fill(
+    reader: &mut (impl AsyncRead + Unpin),
+    buf: &mut Vec<u8>,
+    len: usize,
+) -> Result<(), ReadError> {
+    let start = buf.len();
+    buf.resize(start + len, 0);
+    let mut read = start;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]).await? {
+            0 if read == 0 => return Err(ReadError::Disconnected),
+            0 => return Err(ReadError::truncated_message(&buf[..read])),
+            n => read += n,
+        }
+    }
+    Ok(())
+}
+
+impl<B: AsRef<[u8]>> Message<B> {
+    /// Serialize this `Message` to the async `writer`, in the same format as
+    /// [`write_to`](Message::write_to).
+    ///
+    /// The vectors of a `Shift` command are written directly from the message.
+    pub async fn write_to_async(&self, writer: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+        // Longer than the header of any message
+        let mut header = [0; 16];
+        let len = self.encode_header(&mut header)?;
+        writer.write_all(&header[..len]).await?;
+        if let Message::Shift { tms, tdi, .. } = self {
+            writer.write_all(tms.as_ref()).await?;
+            writer.write_all(tdi.as_ref()).await?;
+        }
+        Ok(())
+    }
+}
+
+impl XvcInfo {
+    /// Read an `XvcInfo` from the async `reader`.
+    ///
+    /// Like [`XvcInfo::from_reader`], an info without newline is accepted at the end of the
+    /// stream, and `ReadError::Disconnected` is returned if the stream ends before any data.
+    pub async fn from_async_reader(
+        reader: &mut (impl AsyncRead + Unpin),
+    ) -> Result<XvcInfo, ReadError> {
+        let mut line = Vec::new();
+        loop {
+            let mut byte = [0];
+            if reader.read(&mut byte).await? == 0 {
+                if line.is_empty() {
+                    return Err(ReadError::Disconnected);
+                }
+                // Some servers do not terminate the info with a newline
+                return Ok(XvcInfo::parse_line(&line)?);
+            }
+            let [byte] = byte;
+            line.push(byte);
+            if byte == b'\n' {
+                return Ok(XvcInfo::parse(&mut line.as_slice())?);
+            }
+            if line.len() >= MAX_INFO_LEN {
+                return Err(ReadError::TooManyBytes {
+                    max: MAX_INFO_LEN,
+                    need: line.len() + 1,
+                });
+            }
+        }
+    }
+
+    /// Write this `XvcInfo` to the async `writer`, in the same format as
+    /// [`write_to`](XvcInfo::write_to).
+    pub async fn write_to_async(&self, writer: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+        let mut buf = Vec::new();
+        self.write_to(&mut buf)?;
+        writer.write_all(&buf).await
+    }
+}
+
+impl<W: AsyncWrite + Unpin> ShiftEncoder<W> {
+    /// Like [`write_tms_chunk`](ShiftEncoder::write_tms_chunk), but for an async `writer`.
+    pub async fn write_tms_chunk_async(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.write_chunk_async(Vector::Tms, chunk).await
+    }
+
+    /// Like [`write_tdi_chunk`](ShiftEncoder::write_tdi_chunk), but for an async `writer`.
+    pub async fn write_tdi_chunk_async(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.write_chunk_async(Vector::Tdi, chunk).await
+    }
+
+    async fn write_chunk_async(&mut self, vector: Vector, chunk: &[u8]) -> io::Result<()> {
+        self.accept(vector, chunk.len())?;
+        if let Some(header) = self.take_header() {
+            self.writer.write_all(&header).await?;
+        }
+        self.writer.write_all(chunk).await
+    }
+
+    /// Like [`finish`](ShiftEncoder::finish), but for an async `writer`.
+    pub async fn finish_async(mut self) -> io::Result<W> {
+        self.check_complete()?;
+        if let Some(header) = self.take_header() {
+            self.writer.write_all(&header).await?;
+        }
+        Ok(self.writer)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for TeeReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let start = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.sink.record(Direction::Read, &buf.filled()[start..])?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncWrite + Unpin> AsyncWrite for TeeReader<R> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for TeeWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.sink.record(Direction::Written, &buf[..n])?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.inner).poll_write_vectored(cx, bufs))?;
+        this.sink.record_vectored(Direction::Written, bufs, n)?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<W: AsyncRead + Unpin> AsyncRead for TeeWriter<W> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::duplex;
+
+    use crate::{
+        BorrowedMessage, Version,
+        capture::{CaptureSink, SharedBuf},
+    };
+
+    use super::*;
+
+    const DEFAULT_MAX_SHIFT_BYTES: usize = 1024;
+
+    async fn roundtrip(message: BorrowedMessage<'_>) -> OwnedMessage {
+        let (mut client, mut server) = duplex(4096);
+        message.write_to_async(&mut client).await.unwrap();
+        OwnedMessage::from_async_reader(&mut server, DEFAULT_MAX_SHIFT_BYTES)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn roundtrip_getinfo() {
+        assert_eq!(roundtrip(Message::GetInfo).await, Message::GetInfo);
+    }
+
+    #[tokio::test]
+    async fn roundtrip_settck() {
+        let message = roundtrip(Message::SetTck {
+            period_ns: 0x1234_5678,
+        })
+        .await;
+        assert_eq!(
+            message,
+            Message::SetTck {
+                period_ns: 0x1234_5678
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn roundtrip_shift() {
+        let tms = [0xAA, 0x1F];
+        let tdi = [0x55, 0x0E];
+        let message = roundtrip(Message::Shift {
+            num_bits: 13,
+            tms: &tms,
+            tdi: &tdi,
+        })
+        .await;
+        let expected: OwnedMessage = Message::Shift {
+            num_bits: 13,
+            tms: tms.into(),
+            tdi: tdi.into(),
+        };
+        assert_eq!(message, expected);
+    }
+
+    #[tokio::test]
+    async fn async_write_matches_sync_write() {
+        let tms = [0xAA; 3];
+        let tdi = [0x55; 3];
+        let message = BorrowedMessage::Shift {
+            num_bits: 20,
+            tms: &tms,
+            tdi: &tdi,
+        };
+        let mut sync = Vec::new();
+        message.write_to(&mut sync).unwrap();
+        let mut written = Vec::new();
+        message.write_to_async(&mut written).await.unwrap();
+        assert_eq!(written, sync);
+    }
+
+    #[tokio::test]
+    async fn read_leaves_following_message() {
+        let mut data: &[u8] = b"getinfo:settck:\x01\x00\x00\x00";
+        assert_eq!(
+            OwnedMessage::from_async_reader(&mut data, DEFAULT_MAX_SHIFT_BYTES)
+                .await
+                .unwrap(),
+            Message::GetInfo
+        );
+        assert_eq!(data, b"settck:\x01\x00\x00\x00");
+    }
+
+    #[tokio::test]
+    async fn invalid_prefix() {
+        let mut data: &[u8] = b"xx";
+        match OwnedMessage::from_async_reader(&mut data, DEFAULT_MAX_SHIFT_BYTES).await {
+            Err(ReadError::InvalidCommand(_)) => {}
+            other => panic!("expected InvalidCommand, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn too_many_bytes_shift() {
+        let num_bytes_exceed = DEFAULT_MAX_SHIFT_BYTES + 1;
+        let mut data = b"shift:".to_vec();
+        data.extend_from_slice(&((num_bytes_exceed * 8) as u32).to_le_bytes());
+        match OwnedMessage::from_async_reader(&mut data.as_slice(), DEFAULT_MAX_SHIFT_BYTES).await {
+            Err(ReadError::TooManyBytes { max, need }) => {
+                assert_eq!(max, DEFAULT_MAX_SHIFT_BYTES);
+                assert_eq!(need, num_bytes_exceed);
+            }
+            other => panic!("expected TooManyBytes, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn incomplete_shift_is_truncated() {
+        let mut data: &[u8] = b"shift:\x10\x00\x00\x00\xAA\xBB\x11";
+        match OwnedMessage::from_async_reader(&mut data, DEFAULT_MAX_SHIFT_BYTES).await {
+            Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::Shift),
+                read: 13,
+                expected: 14,
+            }) => {}
+            other => panic!("expected TruncatedMessage, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn read_at_eof_is_disconnected() {
+        let mut data: &[u8] = b"";
+        match OwnedMessage::from_async_reader(&mut data, DEFAULT_MAX_SHIFT_BYTES).await {
+            Err(ReadError::Disconnected) => {}
+            other => panic!("expected Disconnected, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn roundtrip_xvc_info() {
+        let (mut client, mut server) = duplex(64);
+        let info = XvcInfo::new(Version::V1_0, 32);
+        info.write_to_async(&mut server).await.unwrap();
+        assert_eq!(XvcInfo::from_async_reader(&mut client).await.unwrap(), info);
+    }
+
+    #[tokio::test]
+    async fn xvc_info_at_eof() {
+        let mut data: &[u8] = b"xvcServer_v1.0:2048";
+        assert_eq!(
+            XvcInfo::from_async_reader(&mut data).await.unwrap(),
+            XvcInfo::new(Version::V1_0, 2048)
+        );
+        let mut empty: &[u8] = b"";
+        assert!(matches!(
+            XvcInfo::from_async_reader(&mut empty).await,
+            Err(ReadError::Disconnected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn overlong_xvc_info_is_rejected() {
+        let data = [b'x'; 2 * MAX_INFO_LEN];
+        match XvcInfo::from_async_reader(&mut data.as_slice()).await {
+            Err(ReadError::TooManyBytes { max, .. }) => assert_eq!(max, MAX_INFO_LEN),
+            other => panic!("expected TooManyBytes, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn streamed_shift_is_decoded() {
+        let (mut client, mut server) = duplex(4096);
+        let mut encoder = ShiftEncoder::new(13, &mut client);
+        encoder.write_tms_chunk_async(&[0xAA]).await.unwrap();
+        encoder.write_tms_chunk_async(&[0x1F]).await.unwrap();
+        encoder.write_tdi_chunk_async(&[0x55, 0x0E]).await.unwrap();
+        assert!(encoder.write_tdi_chunk_async(&[0]).await.is_err());
+        encoder.finish_async().await.unwrap();
+
+        let message = OwnedMessage::from_async_reader(&mut server, DEFAULT_MAX_SHIFT_BYTES)
+            .await
+            .unwrap();
+        let expected = Message::try_shift(13, [0xAA, 0x1F].into(), [0x55, 0x0E].into());
+        assert_eq!(message, expected.unwrap());
+    }
+
+    #[tokio::test]
+    async fn tee_captures_async_stream() {
+        let capture = SharedBuf::default();
+        let sink = CaptureSink::new(capture.clone());
+        let (client, mut server) = duplex(4096);
+        let mut client = TeeReader::new(TeeWriter::new(client, sink.clone()), sink);
+
+        BorrowedMessage::GetInfo
+            .write_to_async(&mut client)
+            .await
+            .unwrap();
+        let message = OwnedMessage::from_async_reader(&mut server, DEFAULT_MAX_SHIFT_BYTES)
+            .await
+            .unwrap();
+        assert_eq!(message, Message::GetInfo);
+        let info = XvcInfo::new(Version::V1_0, 32);
+        info.write_to_async(&mut server).await.unwrap();
+        assert_eq!(XvcInfo::from_async_reader(&mut client).await.unwrap(), info);
+
+        let records = capture.records();
+        assert_eq!(records[0].direction, Direction::Written);
+        assert_eq!(records[0].data, b"getinfo:");
+        let read: Vec<u8> = records[1..]
+            .iter()
+            .inspect(|record| assert_eq!(record.direction, Direction::Read))
+            .flat_map(|record| record.data.clone())
+            .collect();
+        assert_eq!(read, b"xvcServer_v1.0:32\n");
+    }
+}
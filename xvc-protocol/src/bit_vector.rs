@@ -0,0 +1,273 @@
+//! Packed bit vectors for the TMS, TDI and TDO vectors of a `Shift` command.
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::bits;
+
+/// A vector of bits, packed LSB-first into bytes like the vectors of a `Shift` command.
+///
+/// Bit `i` is stored in bit `i % 8` of byte `i / 8`. Only the first [`len`](BitVector::len)
+/// bits are valid; the padding bits of the last byte are kept at zero, so equality and
+/// `Debug` only consider the valid bits.
+///
+/// ```
+/// use xvc_protocol::BitVector;
+///
+/// let mut tms = BitVector::new();
+/// tms.extend_from_bits([true, true, false]);
+/// tms.push_bit(true);
+/// assert_eq!(tms.len(), 4);
+/// assert_eq!(tms.as_bytes(), &[0b1011]);
+/// ```
+#[derive(Clone, Default, Eq, PartialEq, Hash)]
+pub struct BitVector {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitVector {
+    /// Create an empty `BitVector`.
+    pub fn new() -> BitVector {
+        BitVector::default()
+    }
+
+    /// Create a `BitVector` of `len` zero bits.
+    pub fn zeros(len: usize) -> BitVector {
+        BitVector {
+            bytes: alloc::vec![0; len.div_ceil(8)],
+            len,
+        }
+    }
+
+    /// Create a `BitVector` of `len` one bits.
+    pub fn ones(len: usize) -> BitVector {
+        let mut bytes = alloc::vec![0xFF; len.div_ceil(8)];
+        bits::mask_padding(&mut bytes, len);
+        BitVector { bytes, len }
+    }
+
+    /// Create a `BitVector` from the first `num_bits` bits of the packed `bytes`.
+    ///
+    /// Bits of `bytes` beyond `num_bits` are ignored.
+    ///
+    /// # Panics
+    ///
+    /// If `bytes` holds fewer than `num_bits` bits.
+    pub fn from_bytes(bytes: &[u8], num_bits: usize) -> BitVector {
+        let num_bytes = num_bits.div_ceil(8);
+        assert!(
+            bytes.len() >= num_bytes,
+            "{num_bits} bits need {num_bytes} bytes, got {}",
+            bytes.len()
+        );
+        let mut bytes: Vec<u8> = bytes[..num_bytes].into();
+        bits::mask_padding(&mut bytes, num_bits);
+        BitVector {
+            bytes,
+            len: num_bits,
+        }
+    }
+
+    /// The number of valid bits.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The packed bytes, with the padding bits of the last byte set to zero.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Convert into the packed bytes, with the padding bits of the last byte set to zero.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// The packed bytes and the number of valid bits, as used by `Message::Shift`.
+    pub fn as_raw(&self) -> (&[u8], usize) {
+        (&self.bytes, self.len)
+    }
+
+    /// The bit at `index`, or `None` if `index` is out of bounds.
+    pub fn get(&self, index: usize) -> Option<bool> {
+        (index < self.len).then(|| self.bytes[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    /// Set the bit at `index` to `bit`.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn set(&mut self, index: usize, bit: bool) {
+        assert!(
+            index < self.len,
+            "index {index} out of bounds for {} bits",
+            self.len
+        );
+        let mask = 1 << (index % 8);
+        if bit {
+            self.bytes[index / 8] |= mask;
+        } else {
+            self.bytes[index / 8] &= !mask;
+        }
+    }
+
+    /// Append a single bit.
+    pub fn push_bit(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        self.len += 1;
+        self.set(self.len - 1, bit);
+    }
+
+    /// Append all `bits` in order.
+    pub fn extend_from_bits(&mut self, bits: impl IntoIterator<Item = bool>) {
+        for bit in bits {
+            self.push_bit(bit);
+        }
+    }
+
+    /// Append all bits of `other`.
+    pub fn append(&mut self, other: &BitVector) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.extend_from_slice(&other.bytes);
+            self.len += other.len;
+        } else {
+            self.extend_from_bits(other.iter());
+        }
+    }
+
+    /// Iterate over the valid bits, starting with bit 0.
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|index| self.bytes[index / 8] & (1 << (index % 8)) != 0)
+    }
+}
+
+impl From<(&[u8], usize)> for BitVector {
+    fn from((bytes, num_bits): (&[u8], usize)) -> BitVector {
+        BitVector::from_bytes(bytes, num_bits)
+    }
+}
+
+impl FromIterator<bool> for BitVector {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> BitVector {
+        let mut vector = BitVector::new();
+        vector.extend_from_bits(iter);
+        vector
+    }
+}
+
+/// Formats the valid bits starting with bit 0, e.g. `BitVector(1101)`.
+impl fmt::Debug for BitVector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BitVector(")?;
+        for bit in self.iter() {
+            write!(f, "{}", u8::from(bit))?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+
+    const LENGTHS: [usize; 7] = [0, 1, 7, 8, 9, 13, 33];
+
+    /// Bits with an irregular pattern, so that misplaced bits are noticed
+    fn pattern(len: usize) -> impl Iterator<Item = bool> {
+        (0..len).map(|i| i % 3 == 0 || i % 7 == 1)
+    }
+
+    #[test]
+    fn push_and_get() {
+        for len in LENGTHS {
+            let vector: BitVector = pattern(len).collect();
+            assert_eq!(vector.len(), len);
+            assert_eq!(vector.as_bytes().len(), len.div_ceil(8));
+            for (i, bit) in pattern(len).enumerate() {
+                assert_eq!(vector.get(i), Some(bit), "bit {i} of {len}");
+            }
+            assert_eq!(vector.get(len), None);
+        }
+    }
+
+    #[test]
+    fn packs_lsb_first() {
+        let vector: BitVector = [true, false, false, false, false, false, false, false, false]
+            .into_iter()
+            .collect();
+        assert_eq!(vector.as_bytes(), &[0x01, 0x00]);
+
+        let vector = BitVector::from_bytes(&[0x80, 0x01], 9);
+        assert_eq!(vector.get(7), Some(true));
+        assert_eq!(vector.get(8), Some(true));
+        assert_eq!(vector.iter().filter(|bit| *bit).count(), 2);
+    }
+
+    #[test]
+    fn set_bits() {
+        for len in LENGTHS {
+            let mut vector = BitVector::zeros(len);
+            for (i, bit) in pattern(len).enumerate() {
+                vector.set(i, bit);
+            }
+            assert_eq!(vector, pattern(len).collect());
+            for i in 0..len {
+                vector.set(i, false);
+            }
+            assert_eq!(vector, BitVector::zeros(len));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn set_out_of_bounds_panics() {
+        BitVector::zeros(13).set(13, true);
+    }
+
+    #[test]
+    fn raw_roundtrip_ignores_padding() {
+        for len in LENGTHS {
+            let vector: BitVector = pattern(len).collect();
+            let mut bytes = vector.as_bytes().to_vec();
+            if len % 8 != 0 {
+                *bytes.last_mut().unwrap() |= !((1u8 << (len % 8)) - 1);
+            }
+            let from_raw = BitVector::from((bytes.as_slice(), len));
+            assert_eq!(from_raw, vector);
+            assert_eq!(from_raw.as_raw(), (vector.as_bytes(), len));
+            assert_eq!(format!("{:?}", from_raw), format!("{:?}", vector));
+        }
+    }
+
+    #[test]
+    fn append_at_any_offset() {
+        for first in LENGTHS {
+            for second in LENGTHS {
+                let mut vector: BitVector = pattern(first).collect();
+                let other: BitVector = pattern(second).map(|bit| !bit).collect();
+                vector.append(&other);
+                let expected: BitVector = pattern(first)
+                    .chain(pattern(second).map(|bit| !bit))
+                    .collect();
+                assert_eq!(vector, expected, "{first} + {second} bits");
+            }
+        }
+    }
+
+    #[test]
+    fn debug_shows_valid_bits() {
+        let vector = BitVector::from_bytes(&[0xFF, 0xFF], 9);
+        assert_eq!(format!("{:?}", vector), "BitVector(111111111)");
+        let vector = BitVector::from_bytes(&[0b1011], 4);
+        assert_eq!(format!("{:?}", vector), "BitVector(1101)");
+    }
+}
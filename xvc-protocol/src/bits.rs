@@ -0,0 +1,236 @@
+//! Functions for packed bit vectors in raw byte slices.
+//!
+//! Vectors are packed LSB-first like the vectors of a `Shift` command: bit `i` is stored in
+//! bit `i % 8` of byte `i / 8`. See [`BitVector`](crate::BitVector) for an owned vector type.
+use alloc::boxed::Box;
+
+use crate::BitVector;
+
+/// Reverse the order of the bits within each byte, e.g. for MSB-first TAP controllers.
+///
+/// ```
+/// let mut bytes = [0b0000_0001, 0b1100_1010];
+/// xvc_protocol::bits::reverse_bits_in_bytes(&mut bytes);
+/// assert_eq!(bytes, [0b1000_0000, 0b0101_0011]);
+/// ```
+pub fn reverse_bits_in_bytes(bytes: &mut [u8]) {
+    for byte in bytes {
+        *byte = byte.reverse_bits();
+    }
+}
+
+/// Reverse the order of the bytes within each 32-bit word.
+///
+/// # Panics
+///
+/// If the length of `bytes` is not a multiple of 4.
+///
+/// ```
+/// let mut bytes = [1, 2, 3, 4, 5, 6, 7, 8];
+/// xvc_protocol::bits::swap_u32_bytes(&mut bytes);
+/// assert_eq!(bytes, [4, 3, 2, 1, 8, 7, 6, 5]);
+/// ```
+pub fn swap_u32_bytes(bytes: &mut [u8]) {
+    assert!(
+        bytes.len().is_multiple_of(4),
+        "{} bytes are no whole number of 32-bit words",
+        bytes.len()
+    );
+    for word in bytes.chunks_exact_mut(4) {
+        word.reverse();
+    }
+}
+
+/// Clear all bits from `num_bits` on, i.e. the padding bits of a vector of `num_bits` bits.
+///
+/// ```
+/// let mut bytes = [0xFF, 0xFF, 0xFF];
+/// xvc_protocol::bits::mask_padding(&mut bytes, 13);
+/// assert_eq!(bytes, [0xFF, 0x1F, 0x00]);
+/// ```
+pub fn mask_padding(bytes: &mut [u8], num_bits: usize) {
+    let (whole, rest) = (num_bits / 8, num_bits % 8);
+    if let Some(tail) = bytes.get_mut(whole..)
+        && let Some((partial, unused)) = tail.split_first_mut()
+    {
+        *partial &= (1 << rest) - 1;
+        unused.fill(0);
+    }
+}
+
+/// Extract `len` bits from `start` on into the low bits of a `u64`, with bit `start` as the
+/// LSB.
+///
+/// # Panics
+///
+/// If `len` is larger than 64 or the bits are out of bounds of `bytes`.
+///
+/// ```
+/// let bytes = [0b1010_0000, 0b0000_0011];
+/// assert_eq!(xvc_protocol::bits::extract(&bytes, 5, 5), 0b11101);
+///
+/// // The first vector bit of a 32-bit register is its LSB
+/// assert_eq!(xvc_protocol::bits::extract(&[0x78, 0x56, 0x34], 0, 24), 0x345678);
+/// ```
+pub fn extract(bytes: &[u8], start: usize, len: usize) -> u64 {
+    assert!(len <= 64, "cannot extract {len} bits into a u64");
+    let end = start.checked_add(len).expect("bit range overflows");
+    assert!(
+        end <= 8 * bytes.len(),
+        "bits {start}..{end} out of bounds for {} bytes",
+        bytes.len()
+    );
+    let mut value = 0u64;
+    let mut filled = 0;
+    let mut index = start;
+    while index < end {
+        let offset = index % 8;
+        let take = (8 - offset).min(end - index);
+        let chunk = (bytes[index / 8] >> offset) & (((1u16 << take) - 1) as u8);
+        value |= u64::from(chunk) << filled;
+        filled += take;
+        index += take;
+    }
+    value
+}
+
+/// Concatenate the TDO vectors of consecutive shifts of `num_bits_per_part` bits into the
+/// TDO vector of a single shift, e.g. the parts of a [`Message::split`](crate::Message::split).
+///
+/// The parts may end within a byte; the padding bits of the result are zero.
+///
+/// # Panics
+///
+/// If `parts` and `num_bits_per_part` differ in length, or a part holds fewer than its number
+/// of bits.
+///
+/// ```
+/// let tdo = xvc_protocol::bits::concat_tdo(&[[0b101], [0b11]], &[3, 2]);
+/// assert_eq!(*tdo, [0b11101]);
+/// ```
+pub fn concat_tdo(parts: &[impl AsRef<[u8]>], num_bits_per_part: &[u32]) -> Box<[u8]> {
+    assert_eq!(
+        parts.len(),
+        num_bits_per_part.len(),
+        "every part needs a number of bits"
+    );
+    let mut tdo = BitVector::new();
+    for (part, num_bits) in parts.iter().zip(num_bits_per_part) {
+        tdo.append(&BitVector::from_bytes(part.as_ref(), *num_bits as usize));
+    }
+    tdo.into_bytes().into_boxed_slice()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Bytes with an irregular pattern, so that misplaced bits are noticed
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u8).wrapping_mul(73).wrapping_add(0x5A))
+            .collect()
+    }
+
+    fn naive_bit(bytes: &[u8], index: usize) -> bool {
+        bytes[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    #[test]
+    fn reverse_bits_matches_naive() {
+        let mut bytes: Vec<u8> = (0..=255).collect();
+        reverse_bits_in_bytes(&mut bytes);
+        for (original, reversed) in (0..=255u8).zip(bytes) {
+            for bit in 0..8 {
+                assert_eq!(
+                    naive_bit(&[original], bit),
+                    naive_bit(&[reversed], 7 - bit),
+                    "bit {bit} of {original:#04x}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn swap_u32_bytes_matches_naive() {
+        for words in 0..4 {
+            let original = pattern(4 * words);
+            let mut swapped = original.clone();
+            swap_u32_bytes(&mut swapped);
+            for (i, byte) in swapped.iter().enumerate() {
+                assert_eq!(*byte, original[i - i % 4 + 3 - i % 4]);
+            }
+            swap_u32_bytes(&mut swapped);
+            assert_eq!(swapped, original);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn swap_u32_bytes_rejects_partial_words() {
+        swap_u32_bytes(&mut [0; 6]);
+    }
+
+    #[test]
+    fn mask_padding_matches_naive() {
+        let original = pattern(5);
+        for num_bits in 0..=8 * original.len() + 8 {
+            let mut masked = original.clone();
+            mask_padding(&mut masked, num_bits);
+            for index in 0..8 * original.len() {
+                let expected = index < num_bits && naive_bit(&original, index);
+                assert_eq!(naive_bit(&masked, index), expected, "{num_bits} bits");
+            }
+        }
+    }
+
+    #[test]
+    fn extract_matches_naive() {
+        let bytes = pattern(10);
+        for start in 0..=8 * bytes.len() {
+            for len in 0..=64.min(8 * bytes.len() - start) {
+                let naive = (0..len)
+                    .filter(|i| naive_bit(&bytes, start + i))
+                    .fold(0u64, |value, i| value | (1 << i));
+                assert_eq!(extract(&bytes, start, len), naive, "{len} bits at {start}");
+            }
+        }
+    }
+
+    #[test]
+    fn concat_tdo_matches_naive() {
+        let bytes = pattern(6);
+        for first in 0..=16 {
+            for second in 0..=16 {
+                let parts = [&bytes[..2], &bytes[2..4]];
+                let tdo = concat_tdo(&parts, &[first, second]);
+                assert_eq!(tdo.len(), (first + second).div_ceil(8) as usize);
+                for index in 0..8 * tdo.len() {
+                    let index = index as u32;
+                    let expected = if index < first {
+                        naive_bit(parts[0], index as usize)
+                    } else if index < first + second {
+                        naive_bit(parts[1], (index - first) as usize)
+                    } else {
+                        false
+                    };
+                    assert_eq!(naive_bit(&tdo, index as usize), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn extract_out_of_bounds_panics() {
+        extract(&[0; 2], 9, 8);
+    }
+
+    #[test]
+    #[should_panic]
+    fn extract_more_than_64_bits_panics() {
+        extract(&[0; 16], 0, 65);
+    }
+}
@@ -0,0 +1,430 @@
+//! Wire-level captures of the bytes exchanged over a connection, e.g. to debug interoperability
+//! problems without access to the network of the target.
+//!
+//! [`TeeReader`] and [`TeeWriter`] wrap a stream and copy every byte that passes through them
+//! into a [`CaptureSink`]. With the `tokio` feature they also wrap `AsyncRead` and `AsyncWrite`
+//! streams. A capture is a sequence of records, each encoded as
+//!
+//! | Field     | Size         | Content                                                   |
+//! |-----------|--------------|-----------------------------------------------------------|
+//! | direction | 1 byte       | `0` for bytes read from the stream, `1` for bytes written |
+//! | timestamp | 8 bytes      | Microseconds since the Unix epoch, little-endian          |
+//! | length    | 4 bytes      | Number of payload bytes, little-endian                    |
+//! | payload   | length bytes | The bytes as they passed through the wrapper              |
+//!
+//! Captures are read back with [`CaptureReader`]. To capture both directions of a
+//! bidirectional stream, e.g. the connection of a client, wrap it in both:
+//!
+//! ```ignore
+//! use xvc_protocol::capture::{CaptureSink, TeeReader, TeeWriter};
+//!
+//! let sink = CaptureSink::new(std::fs::File::create("session.cap")?);
+//! let tcp = tokio::net::TcpStream::connect("127.0.0.1:2542").await?;
+//! let mut client = XvcClient::new(TeeReader::new(TeeWriter::new(tcp, sink.clone()), sink));
+//! ```
+use std::{
+    fmt,
+    io::{self, IoSlice, Read, Write},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The number of bytes of a record before its payload
+const HEADER_LEN: usize = 13;
+
+/// The direction of the bytes of a [`Record`], as seen from the wrapped stream.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// Bytes read from the stream, e.g. the messages a server receives
+    Read = 0,
+    /// Bytes written to the stream, e.g. the responses a server sends
+    Written = 1,
+}
+
+/// A chunk of bytes that passed through a [`TeeReader`] or [`TeeWriter`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    pub direction: Direction,
+    /// Microseconds since the Unix epoch
+    pub timestamp_us: u64,
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    /// Write the encoded record to `writer`.
+    ///
+    /// # Panics
+    ///
+    /// If `data` is longer than `u32::MAX` bytes.
+    pub fn write_to(&self, writer: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        let len = u32::try_from(self.data.len()).expect("record too long");
+        let mut header = [0; HEADER_LEN];
+        header[0] = self.direction as u8;
+        header[1..9].copy_from_slice(&self.timestamp_us.to_le_bytes());
+        header[9..].copy_from_slice(&len.to_le_bytes());
+        writer.write_all(&header)?;
+        writer.write_all(&self.data)
+    }
+}
+
+/// A shared destination for the records of one or more tee wrappers.
+///
+/// Cloned sinks write into the same destination, so the reader and writer of a connection
+/// produce a single capture. Records are written whole, in the order the bytes passed through.
+#[derive(Clone)]
+pub struct CaptureSink {
+    inner: Arc<Mutex<dyn Write + Send>>,
+}
+
+impl CaptureSink {
+    /// Write records into `writer`, e.g. a file or a writer that forwards into a channel.
+    pub fn new(writer: impl Write + Send + 'static) -> CaptureSink {
+        CaptureSink {
+            inner: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Record `data` that passed in `direction` now. Empty data is not recorded.
+    pub fn record(&self, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let timestamp_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_micros() as u64);
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for chunk in data.chunks(u32::MAX as usize) {
+            let record = Record {
+                direction,
+                timestamp_us,
+                data: chunk.to_vec(),
+            };
+            record.write_to(&mut *inner)?;
+        }
+        inner.flush()
+    }
+
+    /// Record the first `len` bytes of `bufs`.
+    pub(crate) fn record_vectored(
+        &self,
+        direction: Direction,
+        bufs: &[IoSlice<'_>],
+        len: usize,
+    ) -> io::Result<()> {
+        let mut data = Vec::with_capacity(len);
+        for buf in bufs {
+            let take = buf.len().min(len - data.len());
+            data.extend_from_slice(&buf[..take]);
+        }
+        self.record(direction, &data)
+    }
+}
+
+impl fmt::Debug for CaptureSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureSink").finish_non_exhaustive()
+    }
+}
+
+/// Records all bytes read from the wrapped stream. Writes pass through unrecorded, so a
+/// [`TeeWriter`] can be wrapped to capture both directions of a bidirectional stream.
+///
+/// An error of the sink is returned in place of the result of the read, so that an incomplete
+/// capture does not go unnoticed.
+#[derive(Debug)]
+pub struct TeeReader<R> {
+    pub(crate) inner: R,
+    pub(crate) sink: CaptureSink,
+}
+
+impl<R> TeeReader<R> {
+    pub fn new(inner: R, sink: CaptureSink) -> TeeReader<R> {
+        TeeReader { inner, sink }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for TeeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.sink.record(Direction::Read, &buf[..n])?;
+        Ok(n)
+    }
+}
+
+impl<R: Write> Write for TeeReader<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Records all bytes written to the wrapped stream. Reads pass through unrecorded, so a
+/// [`TeeReader`] can be wrapped to capture both directions of a bidirectional stream.
+///
+/// An error of the sink is returned in place of the result of the write. The bytes have been
+/// written to the stream nevertheless.
+#[derive(Debug)]
+pub struct TeeWriter<W> {
+    pub(crate) inner: W,
+    pub(crate) sink: CaptureSink,
+}
+
+impl<W> TeeWriter<W> {
+    pub fn new(inner: W, sink: CaptureSink) -> TeeWriter<W> {
+        TeeWriter { inner, sink }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for TeeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.sink.record(Direction::Written, &buf[..n])?;
+        Ok(n)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let n = self.inner.write_vectored(bufs)?;
+        self.sink.record_vectored(Direction::Written, bufs, n)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Read> Read for TeeWriter<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// Iterates over the [`Record`]s of a capture.
+///
+/// Ends at the end of the capture. A capture that ends within a record, e.g. because the
+/// capturing process was killed, yields an error of kind `UnexpectedEof` as its last item.
+#[derive(Debug)]
+pub struct CaptureReader<R> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(reader: R) -> CaptureReader<R> {
+        CaptureReader {
+            reader,
+            done: false,
+        }
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut header = [0; HEADER_LEN];
+        let mut filled = 0;
+        while filled < HEADER_LEN {
+            match self.reader.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("capture ends within a record header after {filled} bytes"),
+                    ));
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let direction = match header[0] {
+            0 => Direction::Read,
+            1 => Direction::Written,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid record direction {other}"),
+                ));
+            }
+        };
+        let timestamp_us = u64::from_le_bytes(header[1..9].try_into().unwrap());
+        let len = u32::from_le_bytes(header[9..].try_into().unwrap()) as usize;
+        let mut data = Vec::new();
+        self.reader
+            .by_ref()
+            .take(len as u64)
+            .read_to_end(&mut data)?;
+        if data.len() < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("capture ends after {} of {len} record bytes", data.len()),
+            ));
+        }
+        Ok(Some(Record {
+            direction,
+            timestamp_us,
+            data,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_record().transpose();
+        self.done = !matches!(result, Some(Ok(_)));
+        result
+    }
+}
+
+/// A writer into a buffer that stays accessible after it was moved into a sink
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl SharedBuf {
+    /// The records written so far
+    pub(crate) fn records(&self) -> Vec<Record> {
+        CaptureReader::new(&self.0.lock().unwrap()[..])
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stream that reads from `input` and writes into `output`
+    struct Duplex {
+        input: &'static [u8],
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+            self.output.write_vectored(bufs)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tee_records_both_directions() {
+        let capture = SharedBuf::default();
+        let sink = CaptureSink::new(capture.clone());
+        let duplex = Duplex {
+            input: b"\x64\x00\x00\x00",
+            output: Vec::new(),
+        };
+        let mut stream = TeeReader::new(TeeWriter::new(duplex, sink.clone()), sink);
+
+        stream.write_all(b"settck:\x64\x00\x00\x00").unwrap();
+        let mut response = [0; 4];
+        stream.read_exact(&mut response).unwrap();
+        let written = stream
+            .write_vectored(&[IoSlice::new(b"get"), IoSlice::new(b"info:")])
+            .unwrap();
+        assert_eq!(written, 8);
+        assert_eq!(
+            stream.into_inner().into_inner().output,
+            b"settck:\x64\x00\x00\x00getinfo:"
+        );
+
+        let records = capture.records();
+        let summary: Vec<_> = records
+            .iter()
+            .map(|record| (record.direction, record.data.as_slice()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Direction::Written, &b"settck:\x64\x00\x00\x00"[..]),
+                (Direction::Read, &b"\x64\x00\x00\x00"[..]),
+                (Direction::Written, &b"getinfo:"[..]),
+            ]
+        );
+        assert!(
+            records
+                .windows(2)
+                .all(|pair| pair[0].timestamp_us <= pair[1].timestamp_us)
+        );
+    }
+
+    #[test]
+    fn truncated_record_is_reported() {
+        let mut capture = Vec::new();
+        for direction in [Direction::Read, Direction::Written] {
+            let record = Record {
+                direction,
+                timestamp_us: 42,
+                data: b"getinfo:".to_vec(),
+            };
+            record.write_to(&mut capture).unwrap();
+        }
+        assert_eq!(CaptureReader::new(&capture[..]).count(), 2);
+
+        for len in [HEADER_LEN + 8 + 5, HEADER_LEN + 8 + HEADER_LEN + 3] {
+            let mut records = CaptureReader::new(&capture[..len]);
+            assert!(records.next().unwrap().is_ok());
+            let error = records.next().unwrap().unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof, "{len}: {error}");
+            assert!(records.next().is_none());
+        }
+
+        capture[0] = 2;
+        let error = CaptureReader::new(&capture[..])
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,8 +1,11 @@
-use std::{num::ParseIntError, str::Utf8Error};
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use alloc::string::String;
+use core::{num::ParseIntError, str::Utf8Error};
 
 use crate::{
-    XvcCommand,
-    error::ParseVersionError,
+    Message, OwnedMessage, Response, ShiftVector, XvcCommand,
+    error::{EncodeError, ParseVersionError, ReadError},
     protocol::{Version, XvcInfo},
 };
 
@@ -12,6 +15,34 @@ pub(crate) const CMD_GET_INFO: &[u8] = b"getinfo:";
 pub(crate) const CMD_SET_TCK: &[u8] = b"settck:";
 pub(crate) const CMD_SHIFT: &[u8] = b"shift:";
 
+/// Longest name of an unknown command that lenient decoders search for a `:` delimiter.
+#[cfg(feature = "std")]
+const MAX_UNKNOWN_COMMAND_LEN: usize = 64;
+
+/// Appends to the start of a borrowed byte slice.
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceWriter<'a> {
+    /// Fails if `buf` cannot hold the `needed` bytes that are going to be written.
+    fn new(buf: &'a mut [u8], needed: usize) -> Result<Self, EncodeError> {
+        if buf.len() < needed {
+            return Err(EncodeError::BufferTooSmall {
+                needed,
+                available: buf.len(),
+            });
+        }
+        Ok(SliceWriter { buf, len: 0 })
+    }
+
+    fn put(&mut self, data: &[u8]) {
+        self.buf[self.len..self.len + data.len()].copy_from_slice(data);
+        self.len += data.len();
+    }
+}
+
 /// A lightweight cursor over a borrowed byte slice.
 struct SliceReader<'a>(&'a [u8]);
 
@@ -44,6 +75,13 @@ impl XvcInfo {
         };
         let line = &buf[..newline_index];
         *buf = &buf[newline_index + 1..];
+        Self::parse_line(line)
+    }
+
+    /// Parse an info line without its newline. Surrounding whitespace, including the `\r` of
+    /// a `\r\n` line ending, is ignored.
+    pub(crate) fn parse_line(line: &[u8]) -> ParseResult<XvcInfo> {
+        let line = line.trim_ascii();
         let rest = line
             .strip_prefix(XVC_SERVER_PREFIX)
             .ok_or_else(|| ParseErr::InvalidCommand(line.into()))?;
@@ -52,8 +90,15 @@ impl XvcInfo {
             .position(|byte| *byte == b':')
             .ok_or_else(|| ParseErr::InvalidCommand(line.into()))?;
         let version = core::str::from_utf8(&rest[..colon_index])?.parse::<Version>()?;
-        let max_vector_len = core::str::from_utf8(&rest[colon_index + 1..])?.parse::<u32>()?;
-        Ok(XvcInfo::new(version, max_vector_len))
+        // Newer servers append capability tokens to the vector length
+        let is_separator = |c: char| c == ':' || c.is_ascii_whitespace();
+        let fields = core::str::from_utf8(&rest[colon_index + 1..])?;
+        let (max_vector_len, capabilities) =
+            fields.split_once(is_separator).unwrap_or((fields, ""));
+        let capabilities = capabilities
+            .split(is_separator)
+            .filter(|token| !token.is_empty());
+        Ok(XvcInfo::new(version, max_vector_len.parse::<u32>()?).with_capabilities(capabilities))
     }
 }
 
@@ -76,6 +121,8 @@ pub enum ParseErr {
     ParseIntError(ParseIntError),
     /// Parsing a version failed
     ParseVersionError(ParseVersionError),
+    /// The CRC of the [`crc`](crate::crc) extension does not match the data it follows.
+    CrcMismatch { expected: u32, received: u32 },
 }
 
 impl From<Utf8Error> for ParseErr {
@@ -144,6 +191,58 @@ impl XvcCommand {
         *buf = &buf[n..];
         Ok(cmd)
     }
+
+    /// Parse the name of an unknown command up to its `:` delimiter, for lenient decoders.
+    ///
+    /// Fails with `ParseErr::InvalidCommand` for an empty or non-UTF-8 name, or if no delimiter
+    /// follows within `MAX_UNKNOWN_COMMAND_LEN` bytes, so that garbage is not buffered
+    /// indefinitely.
+    #[cfg(feature = "std")]
+    pub(crate) fn parse_unknown(buf: &mut &[u8]) -> ParseResult<String> {
+        let Some(colon_index) = buf.iter().position(|byte| *byte == b':') else {
+            return if buf.len() < MAX_UNKNOWN_COMMAND_LEN {
+                Err(ParseErr::Incomplete)
+            } else {
+                Err(ParseErr::InvalidCommand((*buf).into()))
+            };
+        };
+        if colon_index == 0 || colon_index >= MAX_UNKNOWN_COMMAND_LEN {
+            return Err(ParseErr::InvalidCommand((*buf).into()));
+        }
+        let name = core::str::from_utf8(&buf[..colon_index])
+            .map_err(|_| ParseErr::InvalidCommand((*buf).into()))?
+            .into();
+        *buf = &buf[colon_index + 1..];
+        Ok(name)
+    }
+
+    /// The offset of the first plausible command in `buf`: a command name, or the start of one
+    /// at the end of `buf`.
+    #[cfg(feature = "std")]
+    pub(crate) fn find(buf: &[u8]) -> Option<usize> {
+        (0..buf.len()).find(|&start| {
+            matches!(
+                XvcCommand::parse(&mut &buf[start..]),
+                Ok(_) | Err(ParseErr::Incomplete)
+            )
+        })
+    }
+
+    /// The number of bytes of a message with this command, as far as known from the first
+    /// bytes of the `payload` that follows the command name.
+    pub(crate) fn message_len(&self, payload: &[u8]) -> usize {
+        match self {
+            XvcCommand::GetInfo => CMD_GET_INFO.len(),
+            XvcCommand::SetTck => CMD_SET_TCK.len() + 4,
+            XvcCommand::Shift => {
+                let vectors = match Shift::parse_num_bits(&mut &*payload) {
+                    Ok(num_bits) => (num_bits.div_ceil(8) as usize).saturating_mul(2),
+                    Err(_) => 0,
+                };
+                CMD_SHIFT.len() + 4 + vectors
+            }
+        }
+    }
 }
 
 pub struct SetTck {
@@ -170,8 +269,8 @@ impl SetTck {
 
 pub struct Shift {
     num_bits: u32,
-    tdi: Box<[u8]>,
-    tms: Box<[u8]>,
+    tdi: ShiftVector,
+    tms: ShiftVector,
 }
 
 impl Shift {
@@ -189,7 +288,7 @@ impl Shift {
         &self.tms
     }
 
-    pub fn into_tms_tdi(self) -> (Box<[u8]>, Box<[u8]>) {
+    pub fn into_tms_tdi(self) -> (ShiftVector, ShiftVector) {
         (self.tms, self.tdi)
     }
 }
@@ -205,12 +304,28 @@ impl Shift {
         Ok(n)
     }
 
+    /// The number of bytes of each vector of a shift of `num_bits`.
+    ///
+    /// Fails with `TooManyBytes` if they exceed `max_len`, or if both vectors together would
+    /// not fit in a `usize`, so callers may add up the vectors without overflow.
+    pub fn num_bytes(num_bits: u32, max_len: usize) -> ParseResult<usize> {
+        let max = max_len.min(usize::MAX / 2);
+        let num_bytes = usize::try_from(num_bits.div_ceil(8)).unwrap_or(usize::MAX);
+        if num_bytes > max {
+            return Err(ParseErr::TooManyBytes {
+                max,
+                got: num_bytes,
+            });
+        }
+        Ok(num_bytes)
+    }
+
     /// This is an internal convenience method when parsing the `Shift` command.
     pub fn parse_tdi_or_tms(
         buf: &mut &[u8],
         num_bytes: usize,
         max_len: usize,
-    ) -> ParseResult<Box<[u8]>> {
+    ) -> ParseResult<ShiftVector> {
         if num_bytes > max_len {
             return Err(ParseErr::TooManyBytes {
                 max: max_len,
@@ -221,26 +336,336 @@ impl Shift {
         if r.remaining() < num_bytes {
             return Err(ParseErr::Incomplete);
         }
-        let out = r.copy_to_boxed_slice(num_bytes);
+        let out = ShiftVector::from(&r.0[..num_bytes]);
+        r.advance(num_bytes);
         *buf = r.0;
         Ok(out)
     }
 
     pub fn parse(buf: &mut &[u8], max_len: usize) -> ParseResult<Shift> {
         let num_bits = Self::parse_num_bits(buf)?;
-        let num_bytes = num_bits.div_ceil(8) as usize;
+        let num_bytes = Self::num_bytes(num_bits, max_len)?;
         let tms = Self::parse_tdi_or_tms(buf, num_bytes, max_len)?;
         let tdi = Self::parse_tdi_or_tms(buf, num_bytes, max_len)?;
         Ok(Shift { num_bits, tdi, tms })
     }
 }
 
+impl Response {
+    /// The number of bytes of the response to `message`, or `None` for the newline-terminated
+    /// response to `GetInfo`.
+    pub(crate) fn len_for<B>(message: &Message<B>) -> Option<usize> {
+        match message {
+            Message::GetInfo => None,
+            Message::SetTck { .. } => Some(4),
+            Message::Shift { num_bits, .. } => Some(num_bits.div_ceil(8) as usize),
+            // Nothing is known about the response, which `parse` rejects
+            Message::Unknown { .. } => Some(0),
+        }
+    }
+
+    /// Parse the response to `message` from a buffer.
+    ///
+    /// The response to a `Message::Unknown` cannot be parsed and fails with
+    /// `ParseErr::InvalidCommand`.
+    pub fn parse<B>(buf: &mut &[u8], message: &Message<B>) -> ParseResult<Response> {
+        if let Message::Unknown { name } = message {
+            return Err(ParseErr::InvalidCommand(name.as_bytes().into()));
+        }
+        let Some(len) = Response::len_for(message) else {
+            return XvcInfo::parse(buf).map(Response::Info);
+        };
+        let mut r = SliceReader(buf);
+        if r.remaining() < len {
+            return Err(ParseErr::Incomplete);
+        }
+        let response = match message {
+            Message::SetTck { .. } => Response::TckPeriod(r.get_u32_le()),
+            _ => Response::Tdo(r.copy_to_boxed_slice(len)),
+        };
+        *buf = r.0;
+        Ok(response)
+    }
+}
+
+impl Message<ShiftVector> {
+    /// Parse a message from a buffer, advancing it past the message on success.
+    pub(crate) fn parse(buf: &mut &[u8], max_shift_bytes: usize) -> ParseResult<OwnedMessage> {
+        Ok(match XvcCommand::parse(buf)? {
+            XvcCommand::GetInfo => Message::GetInfo,
+            XvcCommand::SetTck => Message::SetTck {
+                period_ns: SetTck::parse(buf)?.period(),
+            },
+            XvcCommand::Shift => {
+                let shift = Shift::parse(buf, max_shift_bytes)?;
+                let num_bits = shift.num_bits();
+                let (tms, tdi) = shift.into_tms_tdi();
+                Message::Shift { num_bits, tms, tdi }
+            }
+        })
+    }
+
+    /// Parse the message at the start of a complete in-memory buffer, e.g. a captured packet.
+    ///
+    /// Returns the message together with the number of bytes it occupies in `buf`. If `buf`
+    /// ends within the message, `ReadError::Incomplete` tells how many more bytes are needed
+    /// at least. Shift vectors longer than `max_shift_bytes` are rejected.
+    ///
+    /// ```
+    /// use xvc_protocol::{Message, error::ReadError};
+    ///
+    /// let buf = b"settck:\x64\x00\x00\x00getinfo:";
+    /// let (message, len) = Message::from_bytes(buf, 1024).unwrap();
+    /// assert_eq!(message, Message::SetTck { period_ns: 100 });
+    /// assert_eq!(len, 11);
+    ///
+    /// assert!(matches!(
+    ///     Message::from_bytes(b"shift:\x10\x00\x00\x00\xFF", 1024),
+    ///     Err(ReadError::Incomplete { needed: 3 })
+    /// ));
+    /// ```
+    pub fn from_bytes(
+        buf: &[u8],
+        max_shift_bytes: usize,
+    ) -> Result<(OwnedMessage, usize), ReadError> {
+        let mut rest = buf;
+        match Message::parse(&mut rest, max_shift_bytes) {
+            Ok(message) => Ok((message, buf.len() - rest.len())),
+            Err(ParseErr::Incomplete) => Err(ReadError::incomplete(buf)),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> Message<B> {
+    /// Create a `Shift` message, checking that `tms` and `tdi` are exactly
+    /// `num_bits.div_ceil(8)` bytes long.
+    ///
+    /// ```
+    /// use xvc_protocol::{Message, error::EncodeError};
+    ///
+    /// assert!(Message::try_shift(12, &[0u8; 2][..], &[0u8; 2][..]).is_ok());
+    /// assert!(matches!(
+    ///     Message::try_shift(12, &[0u8; 2][..], &[0u8; 1][..]),
+    ///     Err(EncodeError::ShiftLength { .. })
+    /// ));
+    /// ```
+    pub fn try_shift(num_bits: u32, tms: B, tdi: B) -> Result<Message<B>, EncodeError> {
+        let message = Message::Shift { num_bits, tms, tdi };
+        message.check_shift_lengths()?;
+        Ok(message)
+    }
+
+    /// The number of bytes of the encoded message.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Message::Shift { tms, tdi, .. } => {
+                self.header_len() + tms.as_ref().len() + tdi.as_ref().len()
+            }
+            _ => self.header_len(),
+        }
+    }
+
+    /// The number of bytes of the encoded message without the vectors of a `Shift`.
+    fn header_len(&self) -> usize {
+        match self {
+            Message::GetInfo => CMD_GET_INFO.len(),
+            Message::SetTck { .. } => CMD_SET_TCK.len() + 4,
+            Message::Shift { .. } => CMD_SHIFT.len() + 4,
+            Message::Unknown { name } => name.len() + 1,
+        }
+    }
+
+    /// Encode this message to the start of `out` and return the number of bytes written.
+    ///
+    /// This defines the wire format, which `write_to` writes as well. Fails with
+    /// `EncodeError::ShiftLength` if the vectors of a `Shift` do not match its `num_bits`, and
+    /// with `EncodeError::BufferTooSmall` if `out` is shorter than
+    /// [`encoded_len`](Self::encoded_len).
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        self.check_shift_lengths()?;
+        let mut w = SliceWriter::new(out, self.encoded_len())?;
+        self.put_header(&mut w);
+        if let Message::Shift { tms, tdi, .. } = self {
+            w.put(tms.as_ref());
+            w.put(tdi.as_ref());
+        }
+        Ok(w.len)
+    }
+
+    /// Like [`encode`](Self::encode), but without the vectors of a `Shift`, so that they can
+    /// be written directly from where they are stored.
+    pub fn encode_header(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        self.check_shift_lengths()?;
+        let mut w = SliceWriter::new(out, self.header_len())?;
+        self.put_header(&mut w);
+        Ok(w.len)
+    }
+
+    fn put_header(&self, w: &mut SliceWriter) {
+        match self {
+            Message::GetInfo => w.put(CMD_GET_INFO),
+            Message::SetTck { period_ns } => {
+                w.put(CMD_SET_TCK);
+                w.put(&period_ns.to_le_bytes());
+            }
+            Message::Shift { num_bits, .. } => {
+                w.put(CMD_SHIFT);
+                w.put(&num_bits.to_le_bytes());
+            }
+            Message::Unknown { name } => {
+                w.put(name.as_bytes());
+                w.put(b":");
+            }
+        }
+    }
+
+    /// A peer parses exactly `num_bits.div_ceil(8)` bytes per vector, so any other length
+    /// would desynchronize the stream.
+    pub(crate) fn check_shift_lengths(&self) -> Result<(), EncodeError> {
+        if let Message::Shift { num_bits, tms, tdi } = self {
+            let expected = num_bits.div_ceil(8) as usize;
+            let (tms, tdi) = (tms.as_ref().len(), tdi.as_ref().len());
+            if tms != expected || tdi != expected {
+                return Err(EncodeError::ShiftLength {
+                    num_bits: *num_bits,
+                    tms,
+                    tdi,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The number of decimal digits of `value`.
+const fn decimal_len(value: u64) -> usize {
+    match value.checked_ilog10() {
+        Some(log) => log as usize + 1,
+        None => 1,
+    }
+}
+
+/// Format `value` in decimal into the end of `buf` and return the digits.
+fn decimal(mut value: u64, buf: &mut [u8; 20]) -> &[u8] {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            return &buf[start..];
+        }
+    }
+}
+
+impl XvcInfo {
+    /// The maximum number of bytes of an encoded server info without capabilities, e.g. to
+    /// size a buffer for [`encode`](Self::encode) statically. Each capability adds its length
+    /// plus one byte for the `:` delimiter.
+    pub const MAX_ENCODED_LEN: usize = XVC_SERVER_PREFIX.len()
+        + 2 * decimal_len(usize::MAX as u64)
+        + ".:".len()
+        + decimal_len(u32::MAX as u64)
+        + "\n".len();
+
+    /// The number of bytes of the encoded server info.
+    pub fn encoded_len(&self) -> usize {
+        let version = self.version();
+        XVC_SERVER_PREFIX.len()
+            + decimal_len(version.major() as u64)
+            + decimal_len(version.minor() as u64)
+            + ".:".len()
+            + decimal_len(self.max_vector_len().as_bytes().into())
+            + self
+                .capabilities()
+                .iter()
+                .map(|capability| 1 + capability.len())
+                .sum::<usize>()
+            + "\n".len()
+    }
+
+    /// Encode this server info to the start of `out` and return the number of bytes written.
+    ///
+    /// Does not allocate. Fails with `EncodeError::BufferTooSmall` if `out` is shorter than
+    /// [`encoded_len`](Self::encoded_len).
+    ///
+    /// ```
+    /// use xvc_protocol::{Version, XvcInfo};
+    ///
+    /// let mut buf = [0; XvcInfo::MAX_ENCODED_LEN];
+    /// let len = XvcInfo::new(Version::V1_0, 4096).encode(&mut buf)?;
+    /// assert_eq!(&buf[..len], b"xvcServer_v1.0:4096\n");
+    /// # Ok::<(), xvc_protocol::error::EncodeError>(())
+    /// ```
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        let mut w = SliceWriter::new(out, self.encoded_len())?;
+        let (version, max_vector_len) = (self.version(), self.max_vector_len().as_bytes());
+        let mut digits = [0; 20];
+        w.put(XVC_SERVER_PREFIX);
+        w.put(decimal(version.major() as u64, &mut digits));
+        w.put(b".");
+        w.put(decimal(version.minor() as u64, &mut digits));
+        w.put(b":");
+        w.put(decimal(max_vector_len.into(), &mut digits));
+        for capability in self.capabilities() {
+            w.put(b":");
+            w.put(capability.as_bytes());
+        }
+        w.put(b"\n");
+        Ok(w.len)
+    }
+}
+
+impl Response {
+    /// The number of bytes of the encoded response.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Response::Info(info) => info.encoded_len(),
+            Response::TckPeriod(_) => 4,
+            Response::Tdo(tdo) => tdo.len(),
+        }
+    }
+
+    /// Encode this response to the start of `out` and return the number of bytes written.
+    ///
+    /// Fails with `EncodeError::BufferTooSmall` if `out` is shorter than
+    /// [`encoded_len`](Self::encoded_len).
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        match self {
+            Response::Info(info) => info.encode(out),
+            Response::TckPeriod(period_ns) => {
+                let mut w = SliceWriter::new(out, 4)?;
+                w.put(&period_ns.to_le_bytes());
+                Ok(w.len)
+            }
+            Response::Tdo(tdo) => {
+                let mut w = SliceWriter::new(out, tdo.len())?;
+                w.put(tdo);
+                Ok(w.len)
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::vec::Vec;
 
     use super::*;
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn find_command() {
+        assert_eq!(XvcCommand::find(b"getinfo:"), Some(0));
+        assert_eq!(XvcCommand::find(b"xxsettck:"), Some(2));
+        assert_eq!(XvcCommand::find(b"shif shift:"), Some(5));
+        // The name may still be incomplete
+        assert_eq!(XvcCommand::find(b"junk\x00sh"), Some(5));
+        assert_eq!(XvcCommand::find(b"junk"), None);
+        assert_eq!(XvcCommand::find(b""), None);
+    }
+
     #[test]
     fn parses_valid_xvc_info() {
         let mut info1: &[u8] = b"xvcServer_v1.0:4\n";
@@ -301,6 +726,93 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn xvc_info_truncated_lines_are_errors() {
+        for line in [
+            &b"\n"[..],
+            b"xvcServer\n",
+            b"xvcServer_\n",
+            b"xvcServer_v\n",
+            b"xvcServer_v:32\n",
+            b"xvcServer_v.:32\n",
+            b"xvcServer_v1.:32\n",
+            b"xvcServer_v1.0:\n",
+        ] {
+            let mut buf = line;
+            let result = XvcInfo::parse(&mut buf);
+            assert!(
+                !matches!(result, Ok(_) | Err(ParseErr::Incomplete)),
+                "{:?} parsed as {:?}",
+                core::str::from_utf8(line),
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn xvc_info_tolerates_whitespace() {
+        for line in [
+            &b"xvcServer_v1.0:4\r\n"[..],
+            b"xvcServer_v1.0:4 \n",
+            b" xvcServer_v1.0:4\t\r\n",
+        ] {
+            let mut buf = line;
+            assert_eq!(
+                XvcInfo::parse(&mut buf),
+                Ok(XvcInfo::new(Version::new(1, 0), 4))
+            );
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn xvc_info_with_capabilities() {
+        for line in [
+            &b"xvcServer_v1.0:2048:lock:unlock\n"[..],
+            b"xvcServer_v1.0:2048 lock unlock\n",
+            b"xvcServer_v1.0:2048: lock::unlock \r\n",
+        ] {
+            let info = XvcInfo::parse(&mut &*line).unwrap();
+            assert_eq!(info.max_vector_len(), 2048);
+            assert_eq!(info.capabilities(), ["lock", "unlock"]);
+        }
+        assert!(XvcInfo::parse(&mut &b"xvcServer_v1.0::2048\n"[..]).is_err());
+
+        let info = XvcInfo::new(Version::V1_0, 32).with_capabilities(["x-lock", "crc=1"]);
+        let mut buf = [0; 64];
+        let len = info.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"xvcServer_v1.0:32:x-lock:crc=1\n");
+        assert_eq!(XvcInfo::parse(&mut &buf[..len]), Ok(info));
+    }
+
+    #[test]
+    fn xvc_info_encodes_without_formatting() {
+        for (version, max_len) in [
+            (Version::new(0, 0), 0),
+            (Version::V1_0, 10),
+            (Version::new(12, 345), 9_999_999),
+            (Version::new(usize::MAX, usize::MAX), u32::MAX),
+        ] {
+            let info = XvcInfo::new(version, max_len);
+            let expected = format!("xvcServer_v{version}:{max_len}\n");
+            let mut buf = [0; XvcInfo::MAX_ENCODED_LEN];
+            assert_eq!(info.encoded_len(), expected.len());
+            assert_eq!(info.encode(&mut buf), Ok(expected.len()));
+            assert_eq!(&buf[..expected.len()], expected.as_bytes());
+        }
+        let longest = XvcInfo::new(Version::new(usize::MAX, usize::MAX), u32::MAX);
+        assert_eq!(longest.encoded_len(), XvcInfo::MAX_ENCODED_LEN);
+    }
+
+    #[test]
+    fn xvc_info_line_without_newline() {
+        assert_eq!(
+            XvcInfo::parse_line(b"xvcServer_v1.0:2048"),
+            Ok(XvcInfo::new(Version::new(1, 0), 2048))
+        );
+        assert!(XvcInfo::parse_line(b"xvcServer_v1.0:").is_err());
+    }
+
     #[test]
     fn xvc_command_parse_valid_and_rest() {
         let mut buf: &[u8] = b"settck:\x64";
@@ -374,6 +886,20 @@ mod tests {
         assert!(buf.is_empty());
     }
 
+    #[test]
+    fn shift_num_bytes_is_checked() {
+        assert_eq!(Shift::num_bytes(0, 0), Ok(0));
+        assert_eq!(Shift::num_bytes(13, 2), Ok(2));
+        assert_eq!(
+            Shift::num_bytes(17, 2),
+            Err(ParseErr::TooManyBytes { max: 2, got: 3 })
+        );
+        assert_eq!(
+            Shift::num_bytes(u32::MAX, usize::MAX),
+            Ok(u32::MAX.div_ceil(8) as usize)
+        );
+    }
+
     #[test]
     fn shift_parse_ok() {
         let mut buf: Vec<u8> = Vec::new();
@@ -404,4 +930,110 @@ mod tests {
             Err(ParseErr::TooManyBytes { .. })
         ));
     }
+
+    #[test]
+    fn encode_messages() {
+        let cases: [(Message<&[u8]>, &[u8]); 3] = [
+            (Message::GetInfo, b"getinfo:"),
+            (
+                Message::SetTck { period_ns: 0x64 },
+                b"settck:\x64\x00\x00\x00",
+            ),
+            (
+                Message::Shift {
+                    num_bits: 12,
+                    tms: &[0xAA, 0x0B],
+                    tdi: &[0x11, 0x02],
+                },
+                b"shift:\x0c\x00\x00\x00\xAA\x0B\x11\x02",
+            ),
+        ];
+        for (message, expected) in cases {
+            let mut out = [0xFF; 32];
+            assert_eq!(message.encoded_len(), expected.len());
+            assert_eq!(message.encode(&mut out), Ok(expected.len()));
+            assert_eq!(&out[..expected.len()], expected);
+        }
+    }
+
+    #[test]
+    fn encode_responses() {
+        let info = Response::Info(XvcInfo::new(Version::new(1, 0), 32));
+        let mut out = [0u8; 32];
+        assert_eq!(info.encoded_len(), 18);
+        assert_eq!(info.encode(&mut out), Ok(18));
+        assert_eq!(&out[..18], b"xvcServer_v1.0:32\n");
+
+        assert_eq!(Response::TckPeriod(0x64).encode(&mut out), Ok(4));
+        assert_eq!(&out[..4], b"\x64\x00\x00\x00");
+
+        let tdo = Response::Tdo(Box::new([0x12, 0x34]));
+        assert_eq!(tdo.encode(&mut out), Ok(2));
+        assert_eq!(&out[..2], b"\x12\x34");
+    }
+
+    #[test]
+    fn encode_rejects_mismatched_shift_vectors() {
+        let cases: [(u32, &[u8], &[u8]); 4] = [
+            (12, &[0], &[0, 0]),
+            (12, &[0, 0, 0], &[0, 0]),
+            (12, &[0, 0], &[0, 0, 0]),
+            (0, &[0], &[0]),
+        ];
+        let mut out = [0u8; 32];
+        for (num_bits, tms, tdi) in cases {
+            let expected = Err(EncodeError::ShiftLength {
+                num_bits,
+                tms: tms.len(),
+                tdi: tdi.len(),
+            });
+            let message = Message::Shift { num_bits, tms, tdi };
+            assert_eq!(message.encode(&mut out), expected);
+            assert_eq!(message.encode_header(&mut out), expected);
+            assert_eq!(
+                Message::try_shift(num_bits, tms, tdi),
+                expected.map(|_| message)
+            );
+        }
+
+        let empty: &[u8] = &[];
+        let zero_bits = Message::try_shift(0, empty, empty).unwrap();
+        assert_eq!(zero_bits.encode(&mut out), Ok(10));
+        assert_eq!(&out[..10], b"shift:\x00\x00\x00\x00");
+    }
+
+    #[test]
+    fn encode_header_omits_shift_vectors() {
+        let message = Message::try_shift(12, [0xAA, 0x0B], [0x11, 0x02]).unwrap();
+        let mut out = [0u8; 32];
+        assert_eq!(message.encode_header(&mut out), Ok(10));
+        assert_eq!(&out[..10], b"shift:\x0c\x00\x00\x00");
+
+        let message = Message::<&[u8]>::SetTck { period_ns: 0x64 };
+        assert_eq!(
+            message.encode_header(&mut out),
+            message.encode(&mut [0; 11])
+        );
+    }
+
+    #[test]
+    fn encode_into_short_buffer_fails() {
+        let mut out = [0u8; 4];
+        assert_eq!(
+            Message::<&[u8]>::GetInfo.encode(&mut out),
+            Err(EncodeError::BufferTooSmall {
+                needed: 8,
+                available: 4
+            })
+        );
+        assert_eq!(
+            XvcInfo::new(Version::V1_0, 32).encode(&mut out),
+            Err(EncodeError::BufferTooSmall {
+                needed: 18,
+                available: 4
+            })
+        );
+        assert_eq!(Response::TckPeriod(1).encode(&mut out), Ok(4));
+        assert!(Response::Tdo(Box::new([0; 5])).encode(&mut out).is_err());
+    }
 }
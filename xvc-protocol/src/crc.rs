@@ -0,0 +1,152 @@
+//! An opt-in extension that protects shifts with CRCs, for links that may corrupt data on the
+//! way, e.g. through proxies.
+//!
+//! A server that supports the extension advertises the capability [`CAPABILITY`] in its answer
+//! to `getinfo:`. A client that wants to use it then sends the command `crc:`
+//! ([`CMD_ENABLE`]), which has no response. From then on, for the rest of the connection:
+//!
+//! - each `shift:` message is followed by the [`shift_crc`] of its `num_bits` and vectors
+//! - each TDO response is followed by the [`crc32`] of the TDO vector
+//!
+//! CRCs are CRC-32/ISO-HDLC, the CRC of Ethernet and zlib, and are sent as 4 bytes in
+//! little-endian order. A peer that receives a wrong CRC fails with
+//! `ReadError::CrcMismatch`. As the framing of a corrupted stream cannot be trusted, the
+//! server closes the connection in that case instead of shifting.
+//!
+//! Peers that do not use the extension are not affected: without `crc:`, all messages and
+//! responses are those of XVC 1.0.
+#[cfg(feature = "tokio")]
+use crate::codec::{ParseErr, ParseResult};
+
+/// The capability token that a server advertises if it supports the extension.
+pub const CAPABILITY: &str = "crc";
+
+/// The command that enables the extension for the rest of the connection.
+pub const CMD_ENABLE: &[u8] = b"crc:";
+
+/// The number of bytes of a CRC.
+pub const CRC_LEN: usize = 4;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// A CRC-32 that is computed over several parts, e.g. the chunks of a streamed shift.
+///
+/// ```
+/// use xvc_protocol::crc::{Crc32, crc32};
+///
+/// let mut crc = Crc32::new();
+/// crc.update(b"1234");
+/// crc.update(b"56789");
+/// assert_eq!(crc.finish(), crc32(b"123456789"));
+/// assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub const fn new() -> Crc32 {
+        Crc32(!0)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = TABLE[((self.0 ^ *byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+/// The CRC-32 of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+/// The CRC that follows a `shift:` message, over `num_bits` as sent and both vectors, i.e. all
+/// bytes after the command name.
+pub fn shift_crc(num_bits: u32, tms: &[u8], tdi: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(&num_bits.to_le_bytes());
+    crc.update(tms);
+    crc.update(tdi);
+    crc.finish()
+}
+
+/// Read the CRC at the start of `buf` and compare it to the `expected` CRC of the data before.
+#[cfg(feature = "tokio")]
+pub(crate) fn check(buf: &mut &[u8], expected: u32) -> ParseResult<()> {
+    let Some((received, rest)) = buf.split_first_chunk::<CRC_LEN>() else {
+        return Err(ParseErr::Incomplete);
+    };
+    let received = u32::from_le_bytes(*received);
+    *buf = rest;
+    if received != expected {
+        return Err(ParseErr::CrcMismatch { expected, received });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    #[test]
+    fn shift_crc_covers_everything_after_the_command() {
+        let shift = Message::try_shift(12, &[0xAA, 0x0B][..], &[0x11, 0x02][..]).unwrap();
+        let mut encoded = [0; 14];
+        shift.encode(&mut encoded).unwrap();
+        assert_eq!(
+            shift_crc(12, &[0xAA, 0x0B], &[0x11, 0x02]),
+            crc32(&encoded[b"shift:".len()..])
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn check_consumes_the_crc() {
+        let crc = crc32(b"tdo");
+        let mut buf: &[u8] = &[crc.to_le_bytes().as_slice(), b"rest"].concat();
+        assert_eq!(check(&mut buf, crc), Ok(()));
+        assert_eq!(buf, b"rest");
+
+        let mut buf: &[u8] = &crc.to_le_bytes()[..3];
+        assert_eq!(check(&mut buf, crc), Err(ParseErr::Incomplete));
+        let mut buf: &[u8] = &(!crc).to_le_bytes();
+        assert_eq!(
+            check(&mut buf, crc),
+            Err(ParseErr::CrcMismatch {
+                expected: crc,
+                received: !crc
+            })
+        );
+    }
+}
@@ -1,28 +1,137 @@
-use std::{
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use core::{
     error::Error,
     fmt::{self, Display},
-    io,
     num::ParseIntError,
     str::Utf8Error,
 };
+#[cfg(feature = "std")]
+use std::io;
 
-use crate::codec::ParseErr;
+#[cfg(feature = "jtag")]
+use crate::jtag::TapState;
+use crate::{
+    XvcCommand,
+    codec::{CMD_GET_INFO, CMD_SET_TCK, CMD_SHIFT, ParseErr},
+};
 
 /// Errors that may occur when reading a message from a stream.
 #[derive(Debug)]
 pub enum ReadError {
+    #[cfg(feature = "std")]
     IoError(io::Error),
     InvalidCommand(String),
     InvalidFormat(String),
-    TooManyBytes { max: usize, need: usize },
+    TooManyBytes {
+        max: usize,
+        need: usize,
+    },
+    /// The stream was closed between two messages or before a response.
+    Disconnected,
+    /// The stream was closed after `read` bytes of a message, which needed at least `expected`
+    /// bytes as far as known when the stream was closed. `command` is `None` if the stream
+    /// was closed within the command name.
+    TruncatedMessage {
+        command: Option<XvcCommand>,
+        read: usize,
+        expected: usize,
+    },
+    /// The stream was closed after `received` of the `expected` bytes of a response.
+    Truncated {
+        expected: usize,
+        received: usize,
+    },
+    /// An in-memory buffer ended within a message, which needs at least `needed` more bytes
+    /// as far as known from the buffer.
+    Incomplete {
+        needed: usize,
+    },
+    /// The CRC of the [`crc`](crate::crc) extension does not match the data it follows, which
+    /// was corrupted on the way.
+    CrcMismatch {
+        expected: u32,
+        received: u32,
+    },
+}
+
+impl ReadError {
+    /// The error for a stream that was closed after `received`, the first bytes of a message.
+    pub(crate) fn truncated_message(received: &[u8]) -> ReadError {
+        let mut payload = received;
+        let (command, expected) = match XvcCommand::parse(&mut payload) {
+            Ok(command) => {
+                let expected = command.message_len(payload);
+                (Some(command), expected)
+            }
+            Err(_) => {
+                let expected = [CMD_SHIFT, CMD_SET_TCK, CMD_GET_INFO]
+                    .into_iter()
+                    .find(|name| name.starts_with(received))
+                    .map_or(received.len(), <[u8]>::len);
+                (None, expected)
+            }
+        };
+        ReadError::TruncatedMessage {
+            command,
+            read: received.len(),
+            expected,
+        }
+    }
+
+    /// The error for a buffer that ends within the message that starts with `received`.
+    pub(crate) fn incomplete(received: &[u8]) -> ReadError {
+        match ReadError::truncated_message(received) {
+            ReadError::TruncatedMessage { read, expected, .. } => ReadError::Incomplete {
+                needed: expected.saturating_sub(read).max(1),
+            },
+            other => other,
+        }
+    }
 }
 
+#[cfg(feature = "std")]
+impl ReadError {
+    /// The [`io::ErrorKind`] that best describes this error.
+    ///
+    /// Protocol violations map to `InvalidData`, and streams that were closed before a
+    /// message or response was complete map to `UnexpectedEof`.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            ReadError::IoError(error) => error.kind(),
+            ReadError::InvalidCommand(_)
+            | ReadError::InvalidFormat(_)
+            | ReadError::TooManyBytes { .. }
+            | ReadError::CrcMismatch { .. } => io::ErrorKind::InvalidData,
+            ReadError::Disconnected
+            | ReadError::TruncatedMessage { .. }
+            | ReadError::Truncated { .. }
+            | ReadError::Incomplete { .. } => io::ErrorKind::UnexpectedEof,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<io::Error> for ReadError {
     fn from(value: io::Error) -> Self {
         ReadError::IoError(value)
     }
 }
 
+/// Returns the inner error of `ReadError::IoError` unchanged. Other errors are wrapped in an
+/// `io::Error` of their [`kind`](ReadError::kind), with the `ReadError` as its source.
+#[cfg(feature = "std")]
+impl From<ReadError> for io::Error {
+    fn from(value: ReadError) -> Self {
+        match value {
+            ReadError::IoError(error) => error,
+            other => io::Error::new(other.kind(), other),
+        }
+    }
+}
+
 impl From<Utf8Error> for ReadError {
     fn from(value: Utf8Error) -> Self {
         ReadError::InvalidFormat(format!("Invalid UTF8: {}", value))
@@ -44,10 +153,13 @@ impl From<ParseVersionError> for ReadError {
 impl From<crate::codec::ParseErr> for ReadError {
     fn from(value: crate::codec::ParseErr) -> Self {
         match value {
+            #[cfg(feature = "std")]
             ParseErr::Incomplete => ReadError::IoError(io::Error::new(
                 io::ErrorKind::UnexpectedEof,
                 "incomplete message",
             )),
+            #[cfg(not(feature = "std"))]
+            ParseErr::Incomplete => ReadError::InvalidFormat("incomplete message".to_string()),
             ParseErr::InvalidCommand(items) => {
                 ReadError::InvalidCommand(String::from_utf8_lossy(&items).to_string())
             }
@@ -62,6 +174,9 @@ impl From<crate::codec::ParseErr> for ReadError {
                 "Could not parse version: {}",
                 parse_version_error
             )),
+            ParseErr::CrcMismatch { expected, received } => {
+                ReadError::CrcMismatch { expected, received }
+            }
         }
     }
 }
@@ -69,17 +184,103 @@ impl From<crate::codec::ParseErr> for ReadError {
 impl Display for ReadError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            #[cfg(feature = "std")]
             ReadError::IoError(error) => write!(f, "{}", error),
             ReadError::InvalidCommand(cmd) => write!(f, "Received invalid command {}", cmd),
             ReadError::InvalidFormat(format) => write!(f, "{}", format),
             ReadError::TooManyBytes { max, need: got } => {
                 write!(f, "Message too large! Maximum is {}, but got {}", max, got)
             }
+            ReadError::Disconnected => write!(f, "Connection closed"),
+            ReadError::TruncatedMessage {
+                command: Some(command),
+                read,
+                expected,
+            } => write!(
+                f,
+                "Connection closed after {} of {} bytes of a {:?} message",
+                read, expected, command
+            ),
+            ReadError::TruncatedMessage {
+                command: None,
+                read,
+                ..
+            } => write!(
+                f,
+                "Connection closed after {} bytes of an incomplete command",
+                read
+            ),
+            ReadError::Truncated { expected, received } => write!(
+                f,
+                "Connection closed after {} of {} response bytes",
+                received, expected
+            ),
+            ReadError::Incomplete { needed } => write!(
+                f,
+                "Incomplete message, at least {} more bytes needed",
+                needed
+            ),
+            ReadError::CrcMismatch { expected, received } => write!(
+                f,
+                "CRC mismatch, expected {:08x} but received {:08x}",
+                expected, received
+            ),
         }
     }
 }
 
-impl Error for ReadError {}
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            ReadError::IoError(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Errors that may occur when encoding into a caller-provided buffer.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum EncodeError {
+    /// The buffer holds `available` bytes, but the encoding needs `needed` bytes.
+    BufferTooSmall { needed: usize, available: usize },
+    /// The `tms` and `tdi` vectors of a `Shift` of `num_bits` are not `num_bits.div_ceil(8)`
+    /// bytes long.
+    ShiftLength {
+        num_bits: u32,
+        tms: usize,
+        tdi: usize,
+    },
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::BufferTooSmall { needed, available } => write!(
+                f,
+                "Buffer too small! Need {} bytes, but only {} are available",
+                needed, available
+            ),
+            EncodeError::ShiftLength { num_bits, tms, tdi } => write!(
+                f,
+                "Shift of {} bits needs vectors of {} bytes, but TMS has {} and TDI {}",
+                num_bits,
+                num_bits.div_ceil(8),
+                tms,
+                tdi
+            ),
+        }
+    }
+}
+
+impl Error for EncodeError {}
+
+#[cfg(feature = "std")]
+impl From<EncodeError> for io::Error {
+    fn from(value: EncodeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, value)
+    }
+}
 
 /// Errors that may occur when parsing a Version.
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -104,3 +305,170 @@ impl Display for ParseVersionError {
 }
 
 impl Error for ParseVersionError {}
+
+/// Errors that may occur when reading a [`transcript`](crate::transcript).
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum TranscriptError {
+    IoError(io::Error),
+    /// The data does not start with the header of a transcript.
+    InvalidHeader,
+    /// The transcript was written in a format version that is not supported.
+    UnsupportedVersion(u8),
+    /// The transcript ends within the record at `index`, counted from 0.
+    Truncated {
+        index: usize,
+    },
+    /// The message of the record at `index` could not be decoded.
+    InvalidMessage {
+        index: usize,
+        error: ReadError,
+    },
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for TranscriptError {
+    fn from(value: io::Error) -> Self {
+        TranscriptError::IoError(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscriptError::IoError(error) => write!(f, "{}", error),
+            TranscriptError::InvalidHeader => write!(f, "Not a transcript"),
+            TranscriptError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported transcript version {}", version)
+            }
+            TranscriptError::Truncated { index } => {
+                write!(f, "Transcript ends within record {}", index)
+            }
+            TranscriptError::InvalidMessage { index, error } => {
+                write!(f, "Invalid message in record {}: {}", index, error)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for TranscriptError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TranscriptError::IoError(error) => Some(error),
+            TranscriptError::InvalidMessage { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// An error in an [`svf`](crate::jtag::svf) file, at the statement that starts on `line`.
+#[cfg(feature = "jtag")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvfError {
+    /// The line number, counted from 1
+    pub line: usize,
+    pub kind: SvfErrorKind,
+}
+
+/// The kind of an [`SvfError`].
+#[cfg(feature = "jtag")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SvfErrorKind {
+    /// The file ends within a statement, which is not terminated by `;`.
+    MissingSemicolon,
+    /// Scan data in parentheses is not closed.
+    UnterminatedData,
+    UnknownCommand(String),
+    /// A valid SVF command that cannot be played over XVC, e.g. `PIO`.
+    UnsupportedCommand(String),
+    UnexpectedToken(String),
+    /// The statement ends before a required argument.
+    MissingArgument(&'static str),
+    InvalidNumber(String),
+    InvalidState(String),
+    /// A state that the TAP controller cannot stay in where a stable state is required.
+    UnstableState(TapState),
+    InvalidHex(String),
+    /// Scan data of `digits` hex digits does not fit the length of `len` bits: it is too
+    /// short, or sets bits beyond the length.
+    DataLength {
+        len: usize,
+        digits: usize,
+    },
+    /// The length of a scan changed, but no new TDI was given.
+    MissingTdi,
+}
+
+#[cfg(feature = "jtag")]
+impl Display for SvfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: ", self.line)?;
+        match &self.kind {
+            SvfErrorKind::MissingSemicolon => write!(f, "Statement is not terminated by ';'"),
+            SvfErrorKind::UnterminatedData => write!(f, "Missing ')' after scan data"),
+            SvfErrorKind::UnknownCommand(command) => write!(f, "Unknown command {}", command),
+            SvfErrorKind::UnsupportedCommand(command) => {
+                write!(f, "Unsupported command {}", command)
+            }
+            SvfErrorKind::UnexpectedToken(token) => write!(f, "Unexpected {}", token),
+            SvfErrorKind::MissingArgument(argument) => write!(f, "Missing {}", argument),
+            SvfErrorKind::InvalidNumber(number) => write!(f, "Invalid number {}", number),
+            SvfErrorKind::InvalidState(state) => write!(f, "Invalid state {}", state),
+            SvfErrorKind::UnstableState(state) => write!(f, "{} is not a stable state", state),
+            SvfErrorKind::InvalidHex(data) => write!(f, "Invalid hex data ({})", data),
+            SvfErrorKind::DataLength { len, digits } => write!(
+                f,
+                "{} hex digits do not match a length of {} bits",
+                digits, len
+            ),
+            SvfErrorKind::MissingTdi => write!(f, "Scan length changed without new TDI"),
+        }
+    }
+}
+
+#[cfg(feature = "jtag")]
+impl Error for SvfError {}
+
+/// An error in an [`xsvf`](crate::jtag::xsvf) stream, in the instruction that starts at byte
+/// `offset` with `opcode`.
+#[cfg(feature = "jtag")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XsvfError {
+    pub offset: usize,
+    pub opcode: u8,
+    pub kind: XsvfErrorKind,
+}
+
+/// The kind of an [`XsvfError`].
+#[cfg(feature = "jtag")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XsvfErrorKind {
+    UnknownOpcode,
+    /// A valid instruction that the parser does not support, e.g. `XSDRINC`.
+    UnsupportedOpcode,
+    /// The stream ends within the instruction.
+    Truncated,
+    /// A state operand that is not a TAP state, or not one that the instruction accepts.
+    InvalidState(u8),
+}
+
+#[cfg(feature = "jtag")]
+impl Display for XsvfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Byte {}, opcode 0x{:02x}", self.offset, self.opcode)?;
+        if let Some(name) = crate::jtag::xsvf::opcode_name(self.opcode) {
+            write!(f, " ({})", name)?;
+        }
+        match &self.kind {
+            XsvfErrorKind::UnknownOpcode => write!(f, ": Unknown opcode"),
+            XsvfErrorKind::UnsupportedOpcode => write!(f, ": Unsupported instruction"),
+            XsvfErrorKind::Truncated => write!(f, ": Stream ends within the instruction"),
+            XsvfErrorKind::InvalidState(state) => write!(f, ": Invalid state {}", state),
+        }
+    }
+}
+
+#[cfg(feature = "jtag")]
+impl Error for XsvfError {}
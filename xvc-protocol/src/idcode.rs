@@ -0,0 +1,185 @@
+//! Decoding of the 32-bit IDCODE that devices shift out of their ID register.
+//!
+//! An IDCODE is laid out as defined by IEEE 1149.1:
+//!
+//! | Bits  | Field                                          |
+//! |-------|------------------------------------------------|
+//! | 31-28 | Version                                        |
+//! | 27-12 | Part number                                    |
+//! | 11-1  | Manufacturer, as JEP106 bank and code          |
+//! | 0     | Always 1; devices without IDCODE shift out 0   |
+use core::fmt;
+
+/// The IDCODE of a device.
+///
+/// ```
+/// use xvc_protocol::idcode::Idcode;
+///
+/// let idcode = Idcode::new(0x2372_7093);
+/// assert!(idcode.is_valid());
+/// assert_eq!(idcode.manufacturer_name(), Some("Xilinx"));
+/// assert_eq!(idcode.part_number(), 0x3727);
+/// assert_eq!(idcode.version(), 2);
+/// assert_eq!(idcode.to_string(), "Xilinx XC7Z020 rev 2 (0x23727093)");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Idcode(u32);
+
+impl Idcode {
+    pub const fn new(raw: u32) -> Idcode {
+        Idcode(raw)
+    }
+
+    /// Decode an IDCODE from the first four bytes of a TDO vector, as shifted out LSB first.
+    pub const fn from_le_bytes(bytes: [u8; 4]) -> Idcode {
+        Idcode(u32::from_le_bytes(bytes))
+    }
+
+    pub const fn raw(self) -> u32 {
+        self.0
+    }
+
+    /// Whether bit 0 is set, as required for an IDCODE. A device in BYPASS shifts out 0.
+    pub const fn is_valid(self) -> bool {
+        self.0 & 1 == 1
+    }
+
+    pub const fn version(self) -> u8 {
+        (self.0 >> 28) as u8
+    }
+
+    pub const fn part_number(self) -> u16 {
+        (self.0 >> 12) as u16
+    }
+
+    /// The JEP106 identity of the manufacturer: the bank, i.e. the number of continuation
+    /// codes, in bits 10-7, and the code without parity bit in bits 6-0.
+    pub const fn manufacturer_id(self) -> u16 {
+        ((self.0 >> 1) & 0x7FF) as u16
+    }
+
+    /// The name of the manufacturer, if it is one of the manufacturers in the embedded table.
+    ///
+    /// The table holds the JEP106 entries of manufacturers whose devices are commonly found in
+    /// JTAG chains, not the full list.
+    pub fn manufacturer_name(self) -> Option<&'static str> {
+        lookup(JEP106, self.manufacturer_id())
+    }
+
+    /// The name of the device, for known Xilinx parts.
+    pub fn part_name(self) -> Option<&'static str> {
+        if self.manufacturer_id() != XILINX {
+            return None;
+        }
+        lookup(XILINX_PARTS, self.part_number())
+    }
+}
+
+impl From<u32> for Idcode {
+    fn from(raw: u32) -> Idcode {
+        Idcode(raw)
+    }
+}
+
+impl From<Idcode> for u32 {
+    fn from(idcode: Idcode) -> u32 {
+        idcode.0
+    }
+}
+
+impl fmt::Display for Idcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_valid() {
+            return write!(f, "invalid IDCODE (0x{:08x})", self.0);
+        }
+        match self.manufacturer_name() {
+            Some(name) => write!(f, "{name} ")?,
+            None => write!(f, "manufacturer 0x{:03x} ", self.manufacturer_id())?,
+        }
+        match self.part_name() {
+            Some(name) => write!(f, "{name}")?,
+            None => write!(f, "part 0x{:04x}", self.part_number())?,
+        }
+        write!(f, " rev {} (0x{:08x})", self.version(), self.0)
+    }
+}
+
+fn lookup<K: Ord>(table: &[(K, &'static str)], key: K) -> Option<&'static str> {
+    table
+        .binary_search_by(|(k, _)| k.cmp(&key))
+        .ok()
+        .map(|i| table[i].1)
+}
+
+const XILINX: u16 = 0x049;
+
+/// JEP106 manufacturer identities and names, sorted by identity.
+const JEP106: &[(u16, &str)] = &[
+    (0x001, "AMD"),
+    (0x009, "Intel"),
+    (0x00E, "Motorola"),
+    (0x015, "NXP"),
+    (0x017, "Texas Instruments"),
+    (0x01F, "Atmel"),
+    (0x020, "STMicroelectronics"),
+    (0x021, "Lattice"),
+    (0x034, "Cypress"),
+    (0x041, "Infineon"),
+    (0x049, "Xilinx"),
+    (0x065, "Analog Devices"),
+    (0x06E, "Altera"),
+    (0x23B, "ARM"),
+    (0x489, "SiFive"),
+];
+
+/// Part numbers of Xilinx 7 series devices, sorted by part number.
+const XILINX_PARTS: &[(u16, &str)] = &[
+    (0x362C, "XC7A50T"),
+    (0x362D, "XC7A35T"),
+    (0x362E, "XC7A15T"),
+    (0x3631, "XC7A100T"),
+    (0x3632, "XC7A75T"),
+    (0x3636, "XC7A200T"),
+    (0x3647, "XC7K70T"),
+    (0x364C, "XC7K160T"),
+    (0x3651, "XC7K325T"),
+    (0x3656, "XC7K410T"),
+    (0x3722, "XC7Z010"),
+    (0x3727, "XC7Z020"),
+    (0x372C, "XC7Z030"),
+    (0x3731, "XC7Z045"),
+    (0x373B, "XC7Z015"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_sorted() {
+        assert!(JEP106.is_sorted_by_key(|(id, _)| *id));
+        assert!(XILINX_PARTS.is_sorted_by_key(|(part, _)| *part));
+    }
+
+    #[test]
+    fn fields_are_decoded() {
+        // The DAP of the Zynq-7000 processing system
+        let idcode = Idcode::from_le_bytes([0x77, 0x04, 0xA0, 0x4B]);
+        assert_eq!(idcode.raw(), 0x4BA0_0477);
+        assert_eq!(idcode.version(), 4);
+        assert_eq!(idcode.part_number(), 0xBA00);
+        assert_eq!(idcode.manufacturer_id(), 0x23B);
+        assert_eq!(idcode.manufacturer_name(), Some("ARM"));
+        assert_eq!(idcode.part_name(), None);
+        assert_eq!(idcode.to_string(), "ARM part 0xba00 rev 4 (0x4ba00477)");
+
+        let idcode = Idcode::new(0x0000_0FFF);
+        assert_eq!(idcode.manufacturer_name(), None);
+        assert_eq!(
+            idcode.to_string(),
+            "manufacturer 0x7ff part 0x0000 rev 0 (0x00000fff)"
+        );
+        assert!(!Idcode::new(0x2372_7092).is_valid());
+        assert_eq!(Idcode::new(0).to_string(), "invalid IDCODE (0x00000000)");
+    }
+}
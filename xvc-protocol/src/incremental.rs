@@ -0,0 +1,258 @@
+//! Non-blocking decoding of protocol messages.
+//!
+//! [`IncrementalDecoder`] does not read from a stream itself. Instead, the caller feeds it
+//! whatever bytes arrived, in chunks of any size, which makes it usable from event-driven
+//! servers (e.g. on top of `mio` or `epoll`) that must never block on a socket.
+//!
+//! ```rust
+//! use xvc_protocol::{Message, incremental::IncrementalDecoder};
+//!
+//! let mut decoder = IncrementalDecoder::new(1024);
+//! assert_eq!(decoder.feed(b"shift:\x0c\x00").unwrap(), None);
+//! assert_eq!(decoder.feed(b"\x00\x00\xAA\xBB\x11").unwrap(), None);
+//! assert_eq!(decoder.bytes_outstanding(), 1);
+//! let message = decoder.feed(b"\x22getinfo:").unwrap();
+//! assert!(matches!(message, Some(Message::Shift { num_bits: 12, .. })));
+//! // Bytes of the next message remain buffered
+//! assert_eq!(decoder.feed(&[]).unwrap(), Some(Message::GetInfo));
+//! ```
+use alloc::vec::Vec;
+
+use crate::{
+    Message, OwnedMessage, XvcCommand,
+    codec::{ParseErr, ParseResult, SetTck, Shift},
+    error::ReadError,
+};
+
+/// The part of a message that is expected next.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    Command,
+    SetTckPeriod,
+    ShiftNumBits,
+    /// TMS and TDI vectors of `num_bytes` each
+    ShiftPayload {
+        num_bits: u32,
+        num_bytes: usize,
+    },
+}
+
+/// Decodes [`Message`]s from bytes fed in arbitrary chunks.
+pub struct IncrementalDecoder {
+    state: State,
+    /// Bytes received but not yet consumed by a completed part of a message
+    buf: Vec<u8>,
+    /// Per-vector limit for `Shift` payloads
+    max_shift: usize,
+}
+
+impl IncrementalDecoder {
+    /// Create a new decoder.
+    ///
+    /// `max_shift` is the maximum number of bytes allowed for each of the TMS and TDI vectors
+    /// of a `Shift` command, like the `max_shift_bytes` of `Message::from_reader`.
+    pub fn new(max_shift: usize) -> Self {
+        Self {
+            state: State::Command,
+            buf: Vec::new(),
+            max_shift,
+        }
+    }
+
+    /// Appends `data` to the received bytes and returns the next message once it is complete.
+    ///
+    /// Returns `Ok(None)` while more bytes are needed. At most one message is returned per call;
+    /// bytes of following messages are kept, so `feed(&[])` should be called until it returns
+    /// `Ok(None)` before waiting for more data.
+    ///
+    /// After an error, the decoder discards all buffered bytes and expects a new command.
+    /// The stream is usually out of sync at that point and should be closed.
+    pub fn feed(&mut self, data: &[u8]) -> Result<Option<OwnedMessage>, ReadError> {
+        self.buf.extend_from_slice(data);
+        match self.advance() {
+            Ok(message) => Ok(Some(message)),
+            Err(ParseErr::Incomplete) => Ok(None),
+            Err(e) => {
+                self.state = State::Command;
+                self.buf.clear();
+                Err(e.into())
+            }
+        }
+    }
+
+    /// The number of bytes of the current `Shift` payload that have yet to arrive,
+    /// or zero if no payload is pending.
+    pub fn bytes_outstanding(&self) -> usize {
+        match self.state {
+            State::ShiftPayload { num_bytes, .. } => (2 * num_bytes).saturating_sub(self.buf.len()),
+            _ => 0,
+        }
+    }
+
+    /// Consumes the parts of a message that are complete, until the message itself is.
+    fn advance(&mut self) -> ParseResult<OwnedMessage> {
+        loop {
+            let mut rest: &[u8] = &self.buf;
+            let (next, message) = match self.state {
+                State::Command => match XvcCommand::parse(&mut rest)? {
+                    XvcCommand::GetInfo => (State::Command, Some(Message::GetInfo)),
+                    XvcCommand::SetTck => (State::SetTckPeriod, None),
+                    XvcCommand::Shift => (State::ShiftNumBits, None),
+                },
+                State::SetTckPeriod => {
+                    let period_ns = SetTck::parse(&mut rest)?.period();
+                    (State::Command, Some(Message::SetTck { period_ns }))
+                }
+                State::ShiftNumBits => {
+                    let num_bits = Shift::parse_num_bits(&mut rest)?;
+                    let num_bytes = Shift::num_bytes(num_bits, self.max_shift)?;
+                    (
+                        State::ShiftPayload {
+                            num_bits,
+                            num_bytes,
+                        },
+                        None,
+                    )
+                }
+                State::ShiftPayload {
+                    num_bits,
+                    num_bytes,
+                } => {
+                    if rest.len() < 2 * num_bytes {
+                        return Err(ParseErr::Incomplete);
+                    }
+                    let tms = Shift::parse_tdi_or_tms(&mut rest, num_bytes, self.max_shift)?;
+                    let tdi = Shift::parse_tdi_or_tms(&mut rest, num_bytes, self.max_shift)?;
+                    (State::Command, Some(Message::Shift { num_bits, tms, tdi }))
+                }
+            };
+            let consumed = self.buf.len() - rest.len();
+            self.buf.drain(..consumed);
+            self.state = next;
+            if let State::ShiftPayload { num_bytes, .. } = next {
+                self.buf
+                    .reserve((2 * num_bytes).saturating_sub(self.buf.len()));
+            }
+            if let Some(message) = message {
+                return Ok(message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(message: &OwnedMessage) -> Vec<u8> {
+        let mut data = vec![0; message.encoded_len()];
+        message.encode(&mut data).unwrap();
+        data
+    }
+
+    fn messages() -> Vec<OwnedMessage> {
+        vec![
+            Message::GetInfo,
+            Message::SetTck {
+                period_ns: 0x1234_5678,
+            },
+            Message::Shift {
+                num_bits: 13,
+                tms: [0xAA, 0x1F].into(),
+                tdi: [0x55, 0x0E].into(),
+            },
+            Message::Shift {
+                num_bits: 0,
+                tms: [].into(),
+                tdi: [].into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn messages_split_at_every_byte_boundary() {
+        for message in messages() {
+            let data = encode(&message);
+            for split in 0..=data.len() {
+                let mut decoder = IncrementalDecoder::new(1024);
+                let (first, second) = data.split_at(split);
+                let early = decoder.feed(first).unwrap();
+                if split < data.len() {
+                    assert_eq!(early, None, "{:?} split at {}", message, split);
+                    assert_eq!(
+                        decoder.feed(second).unwrap().as_ref(),
+                        Some(&message),
+                        "{:?} split at {}",
+                        message,
+                        split
+                    );
+                } else {
+                    assert_eq!(early.as_ref(), Some(&message));
+                }
+                assert_eq!(decoder.feed(&[]).unwrap(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn messages_fed_byte_by_byte() {
+        let mut data = Vec::new();
+        for message in messages() {
+            data.extend(encode(&message));
+        }
+        let mut decoder = IncrementalDecoder::new(1024);
+        let mut decoded = Vec::new();
+        for byte in data {
+            decoded.extend(decoder.feed(&[byte]).unwrap());
+        }
+        assert_eq!(decoded, messages());
+    }
+
+    #[test]
+    fn buffered_messages_are_returned_one_by_one() {
+        let mut data = Vec::new();
+        for message in messages() {
+            data.extend(encode(&message));
+        }
+        let mut decoder = IncrementalDecoder::new(1024);
+        let mut decoded = vec![decoder.feed(&data).unwrap().unwrap()];
+        while let Some(message) = decoder.feed(&[]).unwrap() {
+            decoded.push(message);
+        }
+        assert_eq!(decoded, messages());
+    }
+
+    #[test]
+    fn tracks_outstanding_payload() {
+        let mut decoder = IncrementalDecoder::new(1024);
+        assert_eq!(decoder.feed(b"shift:\x20\x00\x00").unwrap(), None);
+        assert_eq!(decoder.bytes_outstanding(), 0);
+        assert_eq!(decoder.feed(b"\x00\x01\x02").unwrap(), None);
+        assert_eq!(decoder.bytes_outstanding(), 6);
+        assert_eq!(decoder.feed(b"\x03\x04\x05\x06\x07").unwrap(), None);
+        assert_eq!(decoder.bytes_outstanding(), 1);
+        assert!(decoder.feed(b"\x08").unwrap().is_some());
+        assert_eq!(decoder.bytes_outstanding(), 0);
+    }
+
+    #[test]
+    fn oversized_shift_is_rejected_before_the_payload() {
+        let mut decoder = IncrementalDecoder::new(2);
+        let result = decoder.feed(b"shift:\x20\x00\x00\x00");
+        assert!(matches!(
+            result,
+            Err(ReadError::TooManyBytes { max: 2, need: 4 })
+        ));
+        // The decoder starts over with the next command
+        assert_eq!(decoder.feed(b"getinfo:").unwrap(), Some(Message::GetInfo));
+    }
+
+    #[test]
+    fn invalid_command_is_rejected() {
+        let mut decoder = IncrementalDecoder::new(1024);
+        assert!(matches!(
+            decoder.feed(b"xx"),
+            Err(ReadError::InvalidCommand(_))
+        ));
+    }
+}
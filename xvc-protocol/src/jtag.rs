@@ -0,0 +1,443 @@
+//! JTAG (IEEE 1149.1) on top of the XVC protocol, e.g. to play SVF or XSVF files through a
+//! client.
+//!
+//! Enable with the `jtag` feature flag.
+use core::fmt;
+
+use crate::BitVector;
+
+pub mod svf;
+pub mod xsvf;
+
+/// A state of the TAP controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TapState {
+    TestLogicReset,
+    RunTestIdle,
+    SelectDrScan,
+    CaptureDr,
+    ShiftDr,
+    Exit1Dr,
+    PauseDr,
+    Exit2Dr,
+    UpdateDr,
+    SelectIrScan,
+    CaptureIr,
+    ShiftIr,
+    Exit1Ir,
+    PauseIr,
+    Exit2Ir,
+    UpdateIr,
+}
+
+impl TapState {
+    /// All states, in the order of the standard.
+    pub const ALL: [TapState; 16] = [
+        TapState::TestLogicReset,
+        TapState::RunTestIdle,
+        TapState::SelectDrScan,
+        TapState::CaptureDr,
+        TapState::ShiftDr,
+        TapState::Exit1Dr,
+        TapState::PauseDr,
+        TapState::Exit2Dr,
+        TapState::UpdateDr,
+        TapState::SelectIrScan,
+        TapState::CaptureIr,
+        TapState::ShiftIr,
+        TapState::Exit1Ir,
+        TapState::PauseIr,
+        TapState::Exit2Ir,
+        TapState::UpdateIr,
+    ];
+
+    /// Whether the TAP controller stays in this state while TMS is held at the level that
+    /// leads back to it, i.e. whether a scan or a `RUNTEST` may end in it.
+    pub const fn is_stable(self) -> bool {
+        matches!(
+            self,
+            TapState::TestLogicReset
+                | TapState::RunTestIdle
+                | TapState::PauseDr
+                | TapState::PauseIr
+        )
+    }
+
+    /// The state that a rising edge of TCK leads to with the given level of TMS.
+    pub const fn next(self, tms: bool) -> TapState {
+        use TapState::*;
+        match (self, tms) {
+            (TestLogicReset, false) => RunTestIdle,
+            (TestLogicReset, true) => TestLogicReset,
+            (RunTestIdle, false) => RunTestIdle,
+            (RunTestIdle, true) => SelectDrScan,
+            (SelectDrScan, false) => CaptureDr,
+            (SelectDrScan, true) => SelectIrScan,
+            (CaptureDr | ShiftDr, false) => ShiftDr,
+            (CaptureDr | ShiftDr, true) => Exit1Dr,
+            (Exit1Dr | PauseDr, false) => PauseDr,
+            (Exit1Dr, true) => UpdateDr,
+            (PauseDr, true) => Exit2Dr,
+            (Exit2Dr, false) => ShiftDr,
+            (Exit2Dr, true) => UpdateDr,
+            (UpdateDr | UpdateIr, false) => RunTestIdle,
+            (UpdateDr | UpdateIr, true) => SelectDrScan,
+            (SelectIrScan, false) => CaptureIr,
+            (SelectIrScan, true) => TestLogicReset,
+            (CaptureIr | ShiftIr, false) => ShiftIr,
+            (CaptureIr | ShiftIr, true) => Exit1Ir,
+            (Exit1Ir | PauseIr, false) => PauseIr,
+            (Exit1Ir, true) => UpdateIr,
+            (PauseIr, true) => Exit2Ir,
+            (Exit2Ir, false) => ShiftIr,
+            (Exit2Ir, true) => UpdateIr,
+        }
+    }
+
+    /// The name of the state in SVF files, e.g. `IDLE` for Run-Test/Idle.
+    pub const fn svf_name(self) -> &'static str {
+        match self {
+            TapState::TestLogicReset => "RESET",
+            TapState::RunTestIdle => "IDLE",
+            TapState::SelectDrScan => "DRSELECT",
+            TapState::CaptureDr => "DRCAPTURE",
+            TapState::ShiftDr => "DRSHIFT",
+            TapState::Exit1Dr => "DREXIT1",
+            TapState::PauseDr => "DRPAUSE",
+            TapState::Exit2Dr => "DREXIT2",
+            TapState::UpdateDr => "DRUPDATE",
+            TapState::SelectIrScan => "IRSELECT",
+            TapState::CaptureIr => "IRCAPTURE",
+            TapState::ShiftIr => "IRSHIFT",
+            TapState::Exit1Ir => "IREXIT1",
+            TapState::PauseIr => "IRPAUSE",
+            TapState::Exit2Ir => "IREXIT2",
+            TapState::UpdateIr => "IRUPDATE",
+        }
+    }
+
+    /// The state of an SVF state name, ignoring case.
+    pub fn from_svf_name(name: &str) -> Option<TapState> {
+        TapState::ALL
+            .into_iter()
+            .find(|state| state.svf_name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Formats the name of the state in the standard, e.g. `Run-Test/Idle`.
+impl fmt::Display for TapState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TapState::TestLogicReset => "Test-Logic-Reset",
+            TapState::RunTestIdle => "Run-Test/Idle",
+            TapState::SelectDrScan => "Select-DR-Scan",
+            TapState::CaptureDr => "Capture-DR",
+            TapState::ShiftDr => "Shift-DR",
+            TapState::Exit1Dr => "Exit1-DR",
+            TapState::PauseDr => "Pause-DR",
+            TapState::Exit2Dr => "Exit2-DR",
+            TapState::UpdateDr => "Update-DR",
+            TapState::SelectIrScan => "Select-IR-Scan",
+            TapState::CaptureIr => "Capture-IR",
+            TapState::ShiftIr => "Shift-IR",
+            TapState::Exit1Ir => "Exit1-IR",
+            TapState::PauseIr => "Pause-IR",
+            TapState::Exit2Ir => "Exit2-IR",
+            TapState::UpdateIr => "Update-IR",
+        })
+    }
+}
+
+/// Tracks the state of a TAP controller through the TMS bits that are clocked into it.
+///
+/// ```
+/// use xvc_protocol::jtag::{TapState, TapStateMachine};
+///
+/// let mut tap = TapStateMachine::new();
+/// assert_eq!(tap.step(false), TapState::RunTestIdle);
+/// // TMS of a shift is sent LSB first: 1, 0, 0 goes to Shift-DR
+/// assert_eq!(tap.apply_tms_bits(&[0b001], 3), TapState::ShiftDr);
+/// assert!(!tap.is_stable());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TapStateMachine {
+    state: TapState,
+}
+
+impl TapStateMachine {
+    /// A state machine in Test-Logic-Reset, the state after power-up or five TMS high clocks.
+    pub const fn new() -> TapStateMachine {
+        TapStateMachine::with_state(TapState::TestLogicReset)
+    }
+
+    pub const fn with_state(state: TapState) -> TapStateMachine {
+        TapStateMachine { state }
+    }
+
+    pub const fn state(&self) -> TapState {
+        self.state
+    }
+
+    pub const fn is_stable(&self) -> bool {
+        self.state.is_stable()
+    }
+
+    /// Clock a single TMS bit and return the new state.
+    pub const fn step(&mut self, tms: bool) -> TapState {
+        self.state = self.state.next(tms);
+        self.state
+    }
+
+    /// Clock the first `num_bits` bits of the packed `tms`, LSB first as in a shift, and return
+    /// the new state.
+    ///
+    /// # Panics
+    ///
+    /// If `tms` holds fewer than `num_bits` bits.
+    pub fn apply_tms_bits(&mut self, tms: &[u8], num_bits: usize) -> TapState {
+        assert!(
+            tms.len() * 8 >= num_bits,
+            "{num_bits} bits need {} bytes, got {}",
+            num_bits.div_ceil(8),
+            tms.len()
+        );
+        for i in 0..num_bits {
+            self.step(tms[i / 8] >> (i % 8) & 1 == 1);
+        }
+        self.state
+    }
+}
+
+impl Default for TapStateMachine {
+    fn default() -> TapStateMachine {
+        TapStateMachine::new()
+    }
+}
+
+impl From<TapState> for TapStateMachine {
+    fn from(state: TapState) -> TapStateMachine {
+        TapStateMachine::with_state(state)
+    }
+}
+
+/// The TMS bits that move the TAP controller between two states, packed LSB first as in a
+/// shift.
+///
+/// ```
+/// use xvc_protocol::{BitVector, Message, jtag::{TapState, tms_path}};
+///
+/// let path = tms_path(TapState::RunTestIdle, TapState::ShiftDr);
+/// assert_eq!((path.as_bytes(), path.len()), (&[0b001][..], 3));
+/// let tms = BitVector::from(path);
+/// let tdi = BitVector::zeros(tms.len());
+/// let shift = Message::shift(&tms, &tdi);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TmsSequence {
+    bytes: [u8; 2],
+    len: u8,
+}
+
+impl TmsSequence {
+    pub const fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The packed bits, i.e. the TMS vector of a shift.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len().div_ceil(8)]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len()).map(|i| self.bytes[i / 8] >> (i % 8) & 1 == 1)
+    }
+
+    fn push(&mut self, tms: bool) {
+        let i = self.len();
+        self.bytes[i / 8] |= u8::from(tms) << (i % 8);
+        self.len += 1;
+    }
+}
+
+impl From<TmsSequence> for BitVector {
+    fn from(path: TmsSequence) -> BitVector {
+        BitVector::from_bytes(path.as_bytes(), path.len())
+    }
+}
+
+/// The shortest sequence of TMS bits that moves the TAP controller from `from` to `to`.
+///
+/// Where two paths are equally short, the one that takes TMS low first is chosen. Between the
+/// stable and shift states, this gives the same paths as OpenOCD and Vivado, e.g. `1, 0, 0` from
+/// Run-Test/Idle to Shift-DR.
+///
+/// A path to Test-Logic-Reset is always five TMS high clocks, which reach it from any state,
+/// so the reset works even if the actual state differs from `from`. Other paths from a state to
+/// itself are empty.
+pub fn tms_path(from: TapState, to: TapState) -> TmsSequence {
+    let mut path = TmsSequence {
+        bytes: [0; 2],
+        len: 0,
+    };
+    if to == TapState::TestLogicReset {
+        (0..5).for_each(|_| path.push(true));
+        return path;
+    }
+    // Breadth-first search, recording how each state was first reached
+    let mut reached_by: [Option<(TapState, bool)>; 16] = [None; 16];
+    let mut queue = [from; 16];
+    let (mut head, mut tail) = (0, 1);
+    while head < tail && to != from && reached_by[to as usize].is_none() {
+        let state = queue[head];
+        head += 1;
+        for tms in [false, true] {
+            let next = state.next(tms);
+            if next != from && reached_by[next as usize].is_none() {
+                reached_by[next as usize] = Some((state, tms));
+                queue[tail] = next;
+                tail += 1;
+            }
+        }
+    }
+    let mut bits = [false; 16];
+    let mut len = 0;
+    let mut state = to;
+    while let Some((previous, tms)) = reached_by[state as usize].filter(|_| state != from) {
+        bits[len] = tms;
+        len += 1;
+        state = previous;
+    }
+    bits[..len].iter().rev().for_each(|tms| path.push(*tms));
+    path
+}
+
+/// Whether the packed `tdo` matches `expected` in the bits that are set in `mask`.
+///
+/// # Panics
+///
+/// If `tdo` holds fewer bytes than `expected`.
+fn tdo_matches(expected: &BitVector, mask: &BitVector, tdo: &[u8]) -> bool {
+    let expected = expected.as_bytes();
+    assert!(tdo.len() >= expected.len(), "TDO too short for the scan");
+    // The padding of the mask is zero, so the bits beyond the length are not compared
+    (expected.iter().zip(mask.as_bytes()))
+        .zip(tdo)
+        .all(|((expected, mask), actual)| (expected ^ actual) & mask == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TapState::*;
+
+    /// The state diagram of IEEE 1149.1, figure 6-1: the next state for TMS low and high.
+    const TRANSITIONS: [(TapState, TapState, TapState); 16] = [
+        (TestLogicReset, RunTestIdle, TestLogicReset),
+        (RunTestIdle, RunTestIdle, SelectDrScan),
+        (SelectDrScan, CaptureDr, SelectIrScan),
+        (CaptureDr, ShiftDr, Exit1Dr),
+        (ShiftDr, ShiftDr, Exit1Dr),
+        (Exit1Dr, PauseDr, UpdateDr),
+        (PauseDr, PauseDr, Exit2Dr),
+        (Exit2Dr, ShiftDr, UpdateDr),
+        (UpdateDr, RunTestIdle, SelectDrScan),
+        (SelectIrScan, CaptureIr, TestLogicReset),
+        (CaptureIr, ShiftIr, Exit1Ir),
+        (ShiftIr, ShiftIr, Exit1Ir),
+        (Exit1Ir, PauseIr, UpdateIr),
+        (PauseIr, PauseIr, Exit2Ir),
+        (Exit2Ir, ShiftIr, UpdateIr),
+        (UpdateIr, RunTestIdle, SelectDrScan),
+    ];
+
+    #[test]
+    fn transitions_follow_the_standard() {
+        for (i, (state, low, high)) in TRANSITIONS.into_iter().enumerate() {
+            assert_eq!(state, TapState::ALL[i]);
+            for (tms, expected) in [(false, low), (true, high)] {
+                let mut tap = TapStateMachine::with_state(state);
+                assert_eq!(tap.step(tms), expected, "{state} with TMS {tms}");
+                assert_eq!(tap.state(), expected);
+            }
+            // Stable states are those with a loop in the diagram, apart from the shifts
+            assert_eq!(
+                state.is_stable(),
+                (low == state || high == state) && !matches!(state, ShiftDr | ShiftIr)
+            );
+        }
+    }
+
+    #[test]
+    fn five_tms_high_clocks_reset_from_any_state() {
+        for state in TapState::ALL {
+            let mut tap = TapStateMachine::from(state);
+            assert_eq!(tap.apply_tms_bits(&[0x1F], 5), TestLogicReset);
+        }
+    }
+
+    #[test]
+    fn tms_bits_are_applied_lsb_first() {
+        let mut tap = TapStateMachine::default();
+        // Idle, Select-DR, Select-IR, Capture-IR, Shift-IR x2, Exit1-IR, Update-IR, Idle
+        assert_eq!(tap.apply_tms_bits(&[0b1100_0110, 0b0], 9), RunTestIdle);
+        tap.apply_tms_bits(&[0b0110], 3);
+        assert_eq!(tap.state(), SelectIrScan);
+        // Bits beyond `num_bits` are ignored
+        assert_eq!(tap.apply_tms_bits(&[0xFE], 1), CaptureIr);
+        assert_eq!(tap.apply_tms_bits(&[], 0), CaptureIr);
+    }
+
+    #[test]
+    fn tms_paths_between_all_states() {
+        for from in TapState::ALL {
+            for to in TapState::ALL {
+                let path = tms_path(from, to);
+                let mut tap = TapStateMachine::from(from);
+                assert_eq!(tap.apply_tms_bits(path.as_bytes(), path.len()), to);
+                assert!(path.iter().eq(BitVector::from(path).iter()));
+                if to == TestLogicReset {
+                    continue;
+                }
+                // No shorter sequence of TMS bits reaches `to`
+                for len in 0..path.len() {
+                    for bits in 0..1u8 << len {
+                        let mut tap = TapStateMachine::from(from);
+                        assert_ne!(tap.apply_tms_bits(&[bits], len), to, "{from} to {to}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tms_paths_are_canonical() {
+        // As TMS bits in the order they are clocked
+        let paths: [(TapState, TapState, &[u8]); 10] = [
+            (TestLogicReset, RunTestIdle, &[0]),
+            (TestLogicReset, ShiftIr, &[0, 1, 1, 0, 0]),
+            (TestLogicReset, PauseDr, &[0, 1, 0, 1, 0]),
+            (RunTestIdle, ShiftDr, &[1, 0, 0]),
+            (RunTestIdle, PauseIr, &[1, 1, 0, 1, 0]),
+            (PauseDr, RunTestIdle, &[1, 1, 0]),
+            (PauseDr, ShiftDr, &[1, 0]),
+            (PauseIr, PauseDr, &[1, 1, 1, 0, 1, 0]),
+            (Exit1Ir, ShiftDr, &[1, 1, 0, 0]),
+            (RunTestIdle, RunTestIdle, &[]),
+        ];
+        for (from, to, bits) in paths {
+            let path = tms_path(from, to);
+            assert!(
+                path.iter().eq(bits.iter().map(|bit| *bit == 1)),
+                "{from} to {to}"
+            );
+        }
+        for from in TapState::ALL {
+            let path = tms_path(from, TestLogicReset);
+            assert_eq!((path.as_bytes(), path.len()), (&[0x1F][..], 5));
+        }
+    }
+}
@@ -0,0 +1,679 @@
+//! A parser for Serial Vector Format (SVF) files, as generated by vendor tools to program or
+//! test devices.
+//!
+//! [`Parser`] turns the statements of a file into [`Command`]s, which a player executes in
+//! order. Values that SVF carries over from previous statements, such as the TDI of a scan
+//! whose length did not change or the end state of `RUNTEST`, are already resolved, so each
+//! command is complete on its own.
+//!
+//! ```
+//! use xvc_protocol::jtag::{TapState, svf::{Command, Parser}};
+//!
+//! let svf = "
+//!     ENDDR IDLE;
+//!     ! Read the IDCODE of a Zynq-7020
+//!     SIR 6 TDI (09);
+//!     SDR 32 TDI (00000000) TDO (03727093) MASK (0fffffff);
+//! ";
+//! let commands = Parser::new(svf).collect::<Result<Vec<_>, _>>()?;
+//! assert_eq!(commands[0], Command::EndDr(TapState::RunTestIdle));
+//! let Command::Sdr(scan) = &commands[2] else { panic!() };
+//! assert_eq!(scan.len(), 32);
+//! assert!(scan.matches(&[0x93, 0x70, 0x72, 0x23]));
+//! # Ok::<(), xvc_protocol::error::SvfError>(())
+//! ```
+//!
+//! The commands of SVF that drive the TAP are supported: `SIR`, `SDR`, `HIR`, `HDR`, `TIR`,
+//! `TDR`, `ENDIR`, `ENDDR`, `STATE`, `RUNTEST`, `FREQUENCY` and `TRST`. The parallel I/O
+//! commands `PIO` and `PIOMAP` have no equivalent in XVC and are rejected.
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    BitVector,
+    error::{SvfError, SvfErrorKind},
+    jtag::{self, TapState},
+};
+
+/// A statement of an SVF file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `SIR`: shift the instruction register and go to the state of the last `ENDIR`.
+    Sir(Scan),
+    /// `SDR`: shift the data register and go to the state of the last `ENDDR`.
+    Sdr(Scan),
+    /// `HIR`: the header that is shifted before the data of every following `SIR`.
+    Hir(Scan),
+    /// `TIR`: the trailer that is shifted after the data of every following `SIR`.
+    Tir(Scan),
+    /// `HDR`: the header that is shifted before the data of every following `SDR`.
+    Hdr(Scan),
+    /// `TDR`: the trailer that is shifted after the data of every following `SDR`.
+    Tdr(Scan),
+    /// `ENDIR`: the stable state that following `SIR`s end in.
+    EndIr(TapState),
+    /// `ENDDR`: the stable state that following `SDR`s end in.
+    EndDr(TapState),
+    /// `STATE`: go through the given states, of which the last is stable. A single state is
+    /// reached on the shortest path.
+    State(Vec<TapState>),
+    RunTest(RunTest),
+    /// `FREQUENCY`: the highest TCK frequency in Hz, or `None` for the full speed.
+    Frequency(Option<f64>),
+    Trst(Trst),
+}
+
+/// The data of a scan.
+///
+/// All vectors have the length of the scan, with bit 0 shifted first. A `TDI`, `MASK` or
+/// `SMASK` that a statement leaves out is taken from the previous scan of the same command, or
+/// is all ones if the length changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scan {
+    pub tdi: BitVector,
+    /// The expected TDO, if the statement checks it.
+    pub tdo: Option<BitVector>,
+    /// The bits of `tdo` that are checked.
+    pub mask: BitVector,
+    /// The bits of `tdi` that matter; the others may be shifted with any value.
+    pub smask: BitVector,
+}
+
+impl Scan {
+    /// A scan of zero bits, i.e. no header or trailer.
+    fn empty() -> Scan {
+        Scan {
+            tdi: BitVector::new(),
+            tdo: None,
+            mask: BitVector::new(),
+            smask: BitVector::new(),
+        }
+    }
+
+    /// The number of bits to shift.
+    pub fn len(&self) -> usize {
+        self.tdi.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tdi.is_empty()
+    }
+
+    /// Whether the packed `tdo` that was shifted out matches the expected TDO, if any, in the
+    /// bits of the mask.
+    ///
+    /// # Panics
+    ///
+    /// If `tdo` holds fewer bytes than the scan needs.
+    pub fn matches(&self, tdo: &[u8]) -> bool {
+        match &self.tdo {
+            Some(expected) => jtag::tdo_matches(expected, &self.mask, tdo),
+            None => true,
+        }
+    }
+}
+
+/// The arguments of `RUNTEST`, with the states resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunTest {
+    /// The state in which the clocks run.
+    pub run_state: TapState,
+    /// The number of clocks to run, and on which clock.
+    pub run_count: Option<(u64, RunClock)>,
+    /// The minimum time to run, in seconds.
+    pub min_time: Option<f64>,
+    /// The maximum time to run, in seconds.
+    pub max_time: Option<f64>,
+    /// The state to go to afterwards.
+    pub end_state: TapState,
+}
+
+/// The clock that the count of `RUNTEST` refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunClock {
+    Tck,
+    /// The system clock of the device, which XVC cannot drive.
+    Sck,
+}
+
+/// The level of the optional TRST signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trst {
+    On,
+    Off,
+    Z,
+    /// The chain has no TRST signal.
+    Absent,
+}
+
+/// Parses the statements of an SVF file into [`Command`]s.
+///
+/// Keywords and state names are accepted in any case. After the first error, the iterator
+/// ends, as the following commands depend on the failed one.
+#[derive(Debug, Clone)]
+pub struct Parser<'a> {
+    rest: &'a str,
+    /// The current line, counted from 1
+    line: usize,
+    /// The last scan of each of `SIR`, `SDR`, `HIR`, `TIR`, `HDR` and `TDR`
+    scans: [Scan; 6],
+    run_state: TapState,
+    end_state: TapState,
+    failed: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Token<'a> {
+    Word(&'a str),
+    /// The contents of parentheses
+    Data(&'a str),
+}
+
+impl Token<'_> {
+    fn describe(self) -> String {
+        match self {
+            Token::Word(word) => word.to_string(),
+            Token::Data(data) => alloc::format!("({data})"),
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(svf: &'a str) -> Parser<'a> {
+        Parser {
+            rest: svf,
+            line: 1,
+            scans: core::array::from_fn(|_| Scan::empty()),
+            run_state: TapState::RunTestIdle,
+            end_state: TapState::RunTestIdle,
+            failed: false,
+        }
+    }
+
+    /// Split off the tokens of the next statement and the line that it starts on.
+    fn next_statement(&mut self) -> Option<Result<(usize, Vec<Token<'a>>), SvfError>> {
+        let mut tokens = Vec::new();
+        let mut start = self.line;
+        loop {
+            let Some(c) = self.rest.chars().next() else {
+                if tokens.is_empty() {
+                    return None;
+                }
+                return Some(Err(SvfError {
+                    line: start,
+                    kind: SvfErrorKind::MissingSemicolon,
+                }));
+            };
+            if tokens.is_empty() {
+                start = self.line;
+            }
+            if c == '\n' {
+                self.line += 1;
+                self.rest = &self.rest[1..];
+            } else if c.is_whitespace() {
+                self.rest = &self.rest[c.len_utf8()..];
+            } else if c == '!' || self.rest.starts_with("//") {
+                let end = self.rest.find('\n').unwrap_or(self.rest.len());
+                self.rest = &self.rest[end..];
+            } else if c == ';' {
+                self.rest = &self.rest[1..];
+                if !tokens.is_empty() {
+                    return Some(Ok((start, tokens)));
+                }
+            } else if c == '(' {
+                let Some(end) = self.rest.find(')') else {
+                    return Some(Err(SvfError {
+                        line: self.line,
+                        kind: SvfErrorKind::UnterminatedData,
+                    }));
+                };
+                let data = &self.rest[1..end];
+                self.line += data.matches('\n').count();
+                tokens.push(Token::Data(data));
+                self.rest = &self.rest[end + 1..];
+            } else {
+                let end = self
+                    .rest
+                    .find(|c: char| c.is_whitespace() || matches!(c, ';' | '(' | '!'))
+                    .unwrap_or(self.rest.len());
+                tokens.push(Token::Word(&self.rest[..end]));
+                self.rest = &self.rest[end..];
+            }
+        }
+    }
+
+    fn command(&mut self, tokens: &[Token<'a>]) -> Result<Command, SvfErrorKind> {
+        let mut args = Args(tokens.iter());
+        let name = args.word("command")?.to_ascii_uppercase();
+        let command = match name.as_str() {
+            "SIR" => Command::Sir(self.scan(0, &mut args)?),
+            "SDR" => Command::Sdr(self.scan(1, &mut args)?),
+            "HIR" => Command::Hir(self.scan(2, &mut args)?),
+            "TIR" => Command::Tir(self.scan(3, &mut args)?),
+            "HDR" => Command::Hdr(self.scan(4, &mut args)?),
+            "TDR" => Command::Tdr(self.scan(5, &mut args)?),
+            "ENDIR" => Command::EndIr(stable_state(args.word("state")?)?),
+            "ENDDR" => Command::EndDr(stable_state(args.word("state")?)?),
+            "STATE" => {
+                let mut path = Vec::new();
+                while let Some(name) = args.next_word()? {
+                    path.push(state(name)?);
+                }
+                match path.last() {
+                    None => return Err(SvfErrorKind::MissingArgument("state")),
+                    Some(last) if !last.is_stable() => {
+                        return Err(SvfErrorKind::UnstableState(*last));
+                    }
+                    Some(_) => Command::State(path),
+                }
+            }
+            "RUNTEST" => Command::RunTest(self.run_test(&mut args)?),
+            "FREQUENCY" => match args.next_word()? {
+                None => Command::Frequency(None),
+                Some(hz) => {
+                    let hz = number(hz)?;
+                    args.keyword("HZ")?;
+                    Command::Frequency(Some(hz))
+                }
+            },
+            "TRST" => {
+                let mode = args.word("TRST mode")?;
+                Command::Trst(match mode.to_ascii_uppercase().as_str() {
+                    "ON" => Trst::On,
+                    "OFF" => Trst::Off,
+                    "Z" => Trst::Z,
+                    "ABSENT" => Trst::Absent,
+                    _ => return Err(SvfErrorKind::UnexpectedToken(mode.into())),
+                })
+            }
+            "PIO" | "PIOMAP" => return Err(SvfErrorKind::UnsupportedCommand(name)),
+            _ => return Err(SvfErrorKind::UnknownCommand(name)),
+        };
+        args.end()?;
+        Ok(command)
+    }
+
+    /// Parse the arguments of the scan command with the given index into `scans`.
+    fn scan(&mut self, index: usize, args: &mut Args<'_, 'a>) -> Result<Scan, SvfErrorKind> {
+        let len = args.word("length")?;
+        let len = len
+            .parse::<usize>()
+            .map_err(|_| SvfErrorKind::InvalidNumber(len.into()))?;
+        let (mut tdi, mut tdo, mut mask, mut smask) = (None, None, None, None);
+        while let Some(key) = args.next_word()? {
+            let vector = match key.to_ascii_uppercase().as_str() {
+                "TDI" => &mut tdi,
+                "TDO" => &mut tdo,
+                "MASK" => &mut mask,
+                "SMASK" => &mut smask,
+                _ => return Err(SvfErrorKind::UnexpectedToken(key.into())),
+            };
+            *vector = Some(hex(args.data()?, len)?);
+        }
+
+        let previous = &self.scans[index];
+        let same_len = previous.len() == len;
+        let tdi = match tdi {
+            Some(tdi) => tdi,
+            None if same_len => previous.tdi.clone(),
+            None if len == 0 => BitVector::new(),
+            None => return Err(SvfErrorKind::MissingTdi),
+        };
+        let carry = |vector: Option<BitVector>, previous: &BitVector| {
+            vector.unwrap_or_else(|| match same_len {
+                true => previous.clone(),
+                false => BitVector::ones(len),
+            })
+        };
+        let scan = Scan {
+            tdi,
+            tdo,
+            mask: carry(mask, &previous.mask),
+            smask: carry(smask, &previous.smask),
+        };
+        self.scans[index] = Scan {
+            tdo: None,
+            ..scan.clone()
+        };
+        Ok(scan)
+    }
+
+    fn run_test(&mut self, args: &mut Args<'_, 'a>) -> Result<RunTest, SvfErrorKind> {
+        let mut word = args.word("run count or time")?;
+        let run_state = match TapState::from_svf_name(word) {
+            Some(run_state) => {
+                word = args.word("run count or time")?;
+                Some(stable(run_state)?)
+            }
+            None => None,
+        };
+        let value = number(word)?;
+        let (mut run_count, mut min_time, mut max_time, mut end_state) = (None, None, None, None);
+        let unit = args.word("unit")?;
+        match unit.to_ascii_uppercase().as_str() {
+            "TCK" => run_count = Some((count(word, value)?, RunClock::Tck)),
+            "SCK" => run_count = Some((count(word, value)?, RunClock::Sck)),
+            "SEC" => min_time = Some(value),
+            _ => return Err(SvfErrorKind::UnexpectedToken(unit.into())),
+        }
+        while let Some(word) = args.next_word()? {
+            if word.eq_ignore_ascii_case("MAXIMUM") {
+                max_time = Some(number(args.word("maximum time")?)?);
+                args.keyword("SEC")?;
+            } else if word.eq_ignore_ascii_case("ENDSTATE") {
+                end_state = Some(stable_state(args.word("state")?)?);
+            } else if min_time.is_none() && max_time.is_none() && end_state.is_none() {
+                min_time = Some(number(word)?);
+                args.keyword("SEC")?;
+            } else {
+                return Err(SvfErrorKind::UnexpectedToken(word.into()));
+            }
+        }
+
+        // Without an end state, a new run state is also the end state
+        self.end_state = end_state.or(run_state).unwrap_or(self.end_state);
+        self.run_state = run_state.unwrap_or(self.run_state);
+        Ok(RunTest {
+            run_state: self.run_state,
+            run_count,
+            min_time,
+            max_time,
+            end_state: self.end_state,
+        })
+    }
+}
+
+impl Iterator for Parser<'_> {
+    type Item = Result<Command, SvfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = match self.next_statement()? {
+            Ok((line, tokens)) => self
+                .command(&tokens)
+                .map_err(|kind| SvfError { line, kind }),
+            Err(e) => Err(e),
+        };
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+/// The arguments of a statement.
+struct Args<'t, 'a>(core::slice::Iter<'t, Token<'a>>);
+
+impl<'a> Args<'_, 'a> {
+    fn next_word(&mut self) -> Result<Option<&'a str>, SvfErrorKind> {
+        match self.0.next() {
+            None => Ok(None),
+            Some(Token::Word(word)) => Ok(Some(word)),
+            Some(token) => Err(SvfErrorKind::UnexpectedToken(token.describe())),
+        }
+    }
+
+    fn word(&mut self, argument: &'static str) -> Result<&'a str, SvfErrorKind> {
+        self.next_word()?
+            .ok_or(SvfErrorKind::MissingArgument(argument))
+    }
+
+    fn keyword(&mut self, keyword: &'static str) -> Result<(), SvfErrorKind> {
+        match self.word(keyword)? {
+            word if word.eq_ignore_ascii_case(keyword) => Ok(()),
+            word => Err(SvfErrorKind::UnexpectedToken(word.into())),
+        }
+    }
+
+    fn data(&mut self) -> Result<&'a str, SvfErrorKind> {
+        match self.0.next() {
+            None => Err(SvfErrorKind::MissingArgument("scan data")),
+            Some(Token::Data(data)) => Ok(data),
+            Some(token) => Err(SvfErrorKind::UnexpectedToken(token.describe())),
+        }
+    }
+
+    fn end(mut self) -> Result<(), SvfErrorKind> {
+        match self.0.next() {
+            None => Ok(()),
+            Some(token) => Err(SvfErrorKind::UnexpectedToken(token.describe())),
+        }
+    }
+}
+
+fn state(name: &str) -> Result<TapState, SvfErrorKind> {
+    TapState::from_svf_name(name).ok_or_else(|| SvfErrorKind::InvalidState(name.into()))
+}
+
+fn stable(state: TapState) -> Result<TapState, SvfErrorKind> {
+    match state.is_stable() {
+        true => Ok(state),
+        false => Err(SvfErrorKind::UnstableState(state)),
+    }
+}
+
+fn stable_state(name: &str) -> Result<TapState, SvfErrorKind> {
+    stable(state(name)?)
+}
+
+/// A non-negative number, e.g. `1.0E-3`.
+fn number(word: &str) -> Result<f64, SvfErrorKind> {
+    match word.parse::<f64>() {
+        Ok(value) if value >= 0.0 && value.is_finite() => Ok(value),
+        _ => Err(SvfErrorKind::InvalidNumber(word.into())),
+    }
+}
+
+/// A clock count, which tools may also write like a real number, e.g. `1.00E+04`.
+fn count(word: &str, value: f64) -> Result<u64, SvfErrorKind> {
+    // Casts saturate, so values that are too large do not survive the round trip either
+    let count = value as u64;
+    if count as f64 != value {
+        return Err(SvfErrorKind::InvalidNumber(word.into()));
+    }
+    Ok(count)
+}
+
+/// Decode the hex `data` of a scan of `len` bits. The last digit holds the first bits.
+fn hex(data: &str, len: usize) -> Result<BitVector, SvfErrorKind> {
+    let digits: Vec<u8> = data
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .map(|byte| match byte {
+            b'0'..=b'9' => Ok(byte - b'0'),
+            b'a'..=b'f' => Ok(byte - b'a' + 10),
+            b'A'..=b'F' => Ok(byte - b'A' + 10),
+            _ => Err(SvfErrorKind::InvalidHex(data.trim().into())),
+        })
+        .collect::<Result<_, _>>()?;
+    let length_error = SvfErrorKind::DataLength {
+        len,
+        digits: digits.len(),
+    };
+    if digits.len() < len.div_ceil(4) {
+        return Err(length_error);
+    }
+    let mut bytes = alloc::vec![0; len.div_ceil(8)];
+    for (index, digit) in digits.iter().rev().enumerate() {
+        let first_bit = 4 * index;
+        // Leading digits beyond the length must not set any bits
+        if first_bit + 4 > len && *digit >> len.saturating_sub(first_bit).min(4) != 0 {
+            return Err(length_error);
+        }
+        if let Some(byte) = bytes.get_mut(first_bit / 8) {
+            *byte |= digit << (first_bit % 8);
+        }
+    }
+    Ok(BitVector::from_bytes(&bytes, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(svf: &str) -> Result<Vec<Command>, SvfError> {
+        Parser::new(svf).collect()
+    }
+
+    fn error(svf: &str) -> SvfError {
+        parse(svf).unwrap_err()
+    }
+
+    fn bits(bytes: &[u8], len: usize) -> BitVector {
+        BitVector::from_bytes(bytes, len)
+    }
+
+    #[test]
+    fn vivado_idcode() {
+        let commands = parse(include_str!("testdata/vivado_idcode.svf")).unwrap();
+        assert_eq!(commands.len(), 20);
+        assert_eq!(commands[0], Command::Trst(Trst::Off));
+        assert_eq!(commands[1], Command::EndIr(TapState::RunTestIdle));
+        assert_eq!(
+            commands[3],
+            Command::State(alloc::vec![TapState::TestLogicReset])
+        );
+        assert_eq!(commands[5], Command::Frequency(Some(1e7)));
+        assert_eq!(commands[6], Command::Tir(Scan::empty()));
+        let Command::Sir(sir) = &commands[14] else {
+            panic!("expected SIR, got {:?}", commands[14]);
+        };
+        assert_eq!(sir.tdi, bits(&[0x09], 6));
+        assert_eq!(sir.smask, BitVector::ones(6));
+        assert_eq!(sir.tdo, None);
+        let Command::Sdr(sdr) = &commands[15] else {
+            panic!("expected SDR, got {:?}", commands[15]);
+        };
+        assert_eq!(sdr.tdo, Some(bits(&[0x93, 0x70, 0x72, 0x03], 32)));
+        assert_eq!(sdr.mask, bits(&[0xFF, 0xFF, 0xFF, 0x0F], 32));
+        assert!(sdr.matches(&[0x93, 0x70, 0x72, 0x13]));
+        assert!(!sdr.matches(&[0x93, 0x70, 0x72, 0x04]));
+        assert_eq!(
+            commands[16],
+            Command::RunTest(RunTest {
+                run_state: TapState::RunTestIdle,
+                run_count: Some((10_000, RunClock::Tck)),
+                min_time: None,
+                max_time: None,
+                end_state: TapState::RunTestIdle,
+            })
+        );
+    }
+
+    #[test]
+    fn openocd_style_file() {
+        let commands = parse(include_str!("testdata/openocd_bypass.svf")).unwrap();
+        let [
+            Command::Hir(hir),
+            Command::Sir(sir),
+            Command::Sdr(first),
+            Command::Sdr(second),
+            Command::RunTest(run_test),
+            Command::State(path),
+            Command::RunTest(carried),
+        ] = commands.as_slice()
+        else {
+            panic!("unexpected commands {commands:?}");
+        };
+        assert_eq!(hir.tdi, BitVector::ones(4));
+        assert_eq!(sir.tdi, bits(&[0xFF, 0x03], 10));
+        // Hex data may span lines
+        assert_eq!(first.len(), 72);
+        assert_eq!(
+            first.tdi.as_bytes(),
+            &[0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01, 0xA5]
+        );
+        // TDI is carried over for the same length, TDO is not
+        assert_eq!(second.tdi, first.tdi);
+        assert_eq!(second.tdo, None);
+        assert_eq!(second.smask, first.smask);
+        assert_eq!(
+            *run_test,
+            RunTest {
+                run_state: TapState::PauseDr,
+                run_count: Some((1000, RunClock::Tck)),
+                min_time: Some(1e-3),
+                max_time: Some(0.5),
+                end_state: TapState::RunTestIdle,
+            }
+        );
+        assert_eq!(
+            path,
+            &[
+                TapState::SelectDrScan,
+                TapState::SelectIrScan,
+                TapState::TestLogicReset
+            ]
+        );
+        // The run state is carried over, the end state of the last RUNTEST as well
+        assert_eq!(carried.run_state, TapState::PauseDr);
+        assert_eq!(carried.end_state, TapState::RunTestIdle);
+        assert_eq!(carried.min_time, Some(0.01));
+    }
+
+    #[test]
+    fn hex_data_must_fit_the_length() {
+        assert_eq!(hex("1f", 5).unwrap(), bits(&[0x1F], 5));
+        assert_eq!(hex("001f", 5).unwrap(), bits(&[0x1F], 5));
+        assert_eq!(hex("", 0).unwrap(), BitVector::new());
+        for (data, len) in [("3f", 5), ("f", 5), ("100", 8)] {
+            assert_eq!(
+                hex(data, len),
+                Err(SvfErrorKind::DataLength {
+                    len,
+                    digits: data.len()
+                })
+            );
+        }
+        assert_eq!(hex("1g", 8), Err(SvfErrorKind::InvalidHex("1g".into())));
+    }
+
+    #[test]
+    fn errors_carry_the_line() {
+        let svf = "SIR 6 TDI (09);\n\n// comment\nSDR 8\n  TDI (00)\n  MASK;\n";
+        assert_eq!(
+            error(svf),
+            SvfError {
+                line: 4,
+                kind: SvfErrorKind::MissingArgument("scan data")
+            }
+        );
+        assert_eq!(error("SDR 8;").kind, SvfErrorKind::MissingTdi);
+        assert_eq!(
+            error("ENDIR IRSHIFT;").kind,
+            SvfErrorKind::UnstableState(TapState::ShiftIr)
+        );
+        assert_eq!(
+            error("STATE RESET IDLE\n").kind,
+            SvfErrorKind::MissingSemicolon
+        );
+        assert_eq!(
+            error("\nSDR 8 TDI (00;").kind,
+            SvfErrorKind::UnterminatedData
+        );
+        assert_eq!(
+            error("PIO (HLX);").kind,
+            SvfErrorKind::UnsupportedCommand("PIO".into())
+        );
+        assert_eq!(
+            error("FREQUENCY 1E6 HZ 2;").kind,
+            SvfErrorKind::UnexpectedToken("2".into())
+        );
+    }
+
+    #[test]
+    fn parsing_stops_after_an_error() {
+        let mut parser = Parser::new("SIR 6 TDI (09);\nBOGUS;\nSIR 6;");
+        assert!(parser.next().unwrap().is_ok());
+        assert_eq!(
+            parser.next().unwrap().unwrap_err(),
+            SvfError {
+                line: 2,
+                kind: SvfErrorKind::UnknownCommand("BOGUS".into())
+            }
+        );
+        assert!(parser.next().is_none());
+    }
+}
@@ -0,0 +1,509 @@
+//! A parser for XSVF, the compact binary form of SVF that Xilinx tools and embedded programmers
+//! use, as specified in Xilinx application note XAPP503.
+//!
+//! An XSVF stream is a sequence of instructions, each an opcode byte followed by its operands.
+//! Numbers are big-endian. Vectors are big-endian as well, i.e. the last bit of the last byte
+//! is shifted first; the parser converts them to LSB-first [`BitVector`]s. The length of
+//! data register vectors is set by `XSDRSIZE`, and the expected TDO is compared in the bits of
+//! the last `XTDOMASK`. These instructions only affect how the following instructions are read,
+//! so [`Parser`] folds them into the [`Scan`]s instead of returning them.
+//!
+//! ```
+//! use xvc_protocol::{BitVector, jtag::xsvf::{Command, Parser}};
+//!
+//! let xsvf = [
+//!     0x02, 0x06, 0x09, // XSIR 6 bits: IDCODE
+//!     0x08, 0x00, 0x00, 0x00, 0x20, // XSDRSIZE 32
+//!     0x01, 0x0F, 0xFF, 0xFF, 0xFF, // XTDOMASK
+//!     0x09, 0x00, 0x00, 0x00, 0x00, 0x03, 0x72, 0x70, 0x93, // XSDRTDO
+//!     0x00, // XCOMPLETE
+//! ];
+//! let commands = Parser::new(&xsvf).collect::<Result<Vec<_>, _>>()?;
+//! assert_eq!(commands[0], Command::Sir(BitVector::from_bytes(&[0x09], 6)));
+//! let Command::Sdr(scan) = &commands[1] else { panic!() };
+//! assert!(scan.matches(&[0x93, 0x70, 0x72, 0x23]));
+//! assert_eq!(commands[2], Command::Complete);
+//! # Ok::<(), xvc_protocol::error::XsvfError>(())
+//! ```
+//!
+//! The address increment instructions `XSETSDRMASKS` and `XSDRINC` are not supported.
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+    BitVector,
+    error::{XsvfError, XsvfErrorKind},
+    jtag::{self, TapState},
+};
+
+const XCOMPLETE: u8 = 0x00;
+const XTDOMASK: u8 = 0x01;
+const XSIR: u8 = 0x02;
+const XSDR: u8 = 0x03;
+const XRUNTEST: u8 = 0x04;
+const XREPEAT: u8 = 0x07;
+const XSDRSIZE: u8 = 0x08;
+const XSDRTDO: u8 = 0x09;
+const XSETSDRMASKS: u8 = 0x0A;
+const XSDRINC: u8 = 0x0B;
+const XSDRB: u8 = 0x0C;
+const XSDRC: u8 = 0x0D;
+const XSDRE: u8 = 0x0E;
+const XSDRTDOB: u8 = 0x0F;
+const XSDRTDOC: u8 = 0x10;
+const XSDRTDOE: u8 = 0x11;
+const XSTATE: u8 = 0x12;
+const XENDIR: u8 = 0x13;
+const XENDDR: u8 = 0x14;
+const XSIR2: u8 = 0x15;
+const XCOMMENT: u8 = 0x16;
+const XWAIT: u8 = 0x17;
+
+/// The names of the opcodes, indexed by opcode.
+const NAMES: [Option<&str>; 24] = [
+    Some("XCOMPLETE"),
+    Some("XTDOMASK"),
+    Some("XSIR"),
+    Some("XSDR"),
+    Some("XRUNTEST"),
+    None,
+    None,
+    Some("XREPEAT"),
+    Some("XSDRSIZE"),
+    Some("XSDRTDO"),
+    Some("XSETSDRMASKS"),
+    Some("XSDRINC"),
+    Some("XSDRB"),
+    Some("XSDRC"),
+    Some("XSDRE"),
+    Some("XSDRTDOB"),
+    Some("XSDRTDOC"),
+    Some("XSDRTDOE"),
+    Some("XSTATE"),
+    Some("XENDIR"),
+    Some("XENDDR"),
+    Some("XSIR2"),
+    Some("XCOMMENT"),
+    Some("XWAIT"),
+];
+
+/// The name of `opcode`, e.g. `XSDRTDO`, if it is defined.
+pub(crate) fn opcode_name(opcode: u8) -> Option<&'static str> {
+    NAMES.get(usize::from(opcode)).copied().flatten()
+}
+
+/// An instruction of an XSVF stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `XCOMPLETE`: the end of the stream.
+    Complete,
+    /// `XSIR`, `XSIR2`: shift the instruction register with the given TDI and go to the state
+    /// of the last `XENDIR`.
+    Sir(BitVector),
+    /// `XSDR`, `XSDRTDO`: shift the data register and go to the state of the last `XENDDR`.
+    /// If the TDO does not match, the scan is retried as often as the last `XREPEAT` allows.
+    Sdr(Scan),
+    /// `XSDRB`, `XSDRC`, `XSDRE` and their `XSDRTDO` variants: a part of a data register scan
+    /// that is split into several instructions.
+    SdrSegment(Segment, Scan),
+    /// `XRUNTEST`: the microseconds to wait in Run-Test/Idle after each following scan.
+    RunTest(u32),
+    /// `XREPEAT`: how often a following `XSDR` is retried if its TDO does not match.
+    Repeat(u8),
+    /// `XSTATE`: go to the given state.
+    State(TapState),
+    /// `XENDIR`: Run-Test/Idle or Pause-IR, the state that following `XSIR`s end in.
+    EndIr(TapState),
+    /// `XENDDR`: Run-Test/Idle or Pause-DR, the state that following `XSDR`s end in.
+    EndDr(TapState),
+    /// `XWAIT`: go to `wait_state`, wait for `micros` microseconds, then go to `end_state`.
+    Wait {
+        wait_state: TapState,
+        end_state: TapState,
+        micros: u32,
+    },
+    /// `XCOMMENT`: a comment, e.g. the SVF statement that an instruction was converted from.
+    Comment(String),
+}
+
+/// A data register scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scan {
+    pub tdi: BitVector,
+    /// The expected TDO: that of the instruction itself, or for `XSDR` the one of the last
+    /// `XSDRTDO`.
+    pub tdo: Option<BitVector>,
+    /// The bits of `tdo` that are compared, from the last `XTDOMASK`. Zeros if no mask was set
+    /// for the current `XSDRSIZE`.
+    pub mask: BitVector,
+}
+
+impl Scan {
+    /// The number of bits to shift.
+    pub fn len(&self) -> usize {
+        self.tdi.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tdi.is_empty()
+    }
+
+    /// Whether the packed `tdo` that was shifted out matches the expected TDO, if any, in the
+    /// bits of the mask.
+    ///
+    /// # Panics
+    ///
+    /// If `tdo` holds fewer bytes than the scan needs.
+    pub fn matches(&self, tdo: &[u8]) -> bool {
+        match &self.tdo {
+            Some(expected) => jtag::tdo_matches(expected, &self.mask, tdo),
+            None => true,
+        }
+    }
+}
+
+/// The part of a split data register scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    /// Go to Shift-DR and shift, staying in Shift-DR.
+    Begin,
+    /// Shift, staying in Shift-DR.
+    Continue,
+    /// Shift and go to the state of the last `XENDDR`.
+    End,
+}
+
+/// Parses the instructions of an XSVF stream into [`Command`]s.
+///
+/// The iterator ends after `XCOMPLETE`, at the end of the stream, or after the first error.
+#[derive(Debug, Clone)]
+pub struct Parser<'a> {
+    rest: &'a [u8],
+    /// The offset of `rest` in the stream
+    offset: usize,
+    /// The length of data register vectors in bits, from `XSDRSIZE`
+    sdr_size: usize,
+    tdo_mask: BitVector,
+    tdo_expected: Option<BitVector>,
+    done: bool,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(xsvf: &'a [u8]) -> Parser<'a> {
+        Parser {
+            rest: xsvf,
+            offset: 0,
+            sdr_size: 0,
+            tdo_mask: BitVector::new(),
+            tdo_expected: None,
+            done: false,
+        }
+    }
+
+    /// Parse the operands of `opcode`. Returns `None` for instructions that are folded into the
+    /// following ones.
+    fn instruction(
+        &mut self,
+        opcode: u8,
+        operands: &mut &'a [u8],
+    ) -> Result<Option<Command>, XsvfErrorKind> {
+        let size = self.sdr_size;
+        let command = match opcode {
+            XCOMPLETE => Command::Complete,
+            XTDOMASK => {
+                self.tdo_mask = vector(operands, size)?;
+                return Ok(None);
+            }
+            XSDRSIZE => {
+                self.sdr_size = u32::from_be_bytes(take(operands)?) as usize;
+                return Ok(None);
+            }
+            XSIR => {
+                let [len] = take(operands)?;
+                Command::Sir(vector(operands, len.into())?)
+            }
+            XSIR2 => {
+                let len = u16::from_be_bytes(take(operands)?);
+                Command::Sir(vector(operands, len.into())?)
+            }
+            XSDR => {
+                let tdi = vector(operands, size)?;
+                let tdo = self.tdo_expected.clone().filter(|tdo| tdo.len() == size);
+                Command::Sdr(self.masked(tdi, tdo))
+            }
+            XSDRTDO => {
+                let tdi = vector(operands, size)?;
+                let tdo = vector(operands, size)?;
+                self.tdo_expected = Some(tdo.clone());
+                Command::Sdr(self.masked(tdi, Some(tdo)))
+            }
+            XSDRB | XSDRC | XSDRE => {
+                let tdi = vector(operands, size)?;
+                Command::SdrSegment(segment(opcode - XSDRB), self.masked(tdi, None))
+            }
+            XSDRTDOB | XSDRTDOC | XSDRTDOE => {
+                let tdi = vector(operands, size)?;
+                let tdo = vector(operands, size)?;
+                self.tdo_expected = Some(tdo.clone());
+                Command::SdrSegment(segment(opcode - XSDRTDOB), self.masked(tdi, Some(tdo)))
+            }
+            XRUNTEST => Command::RunTest(u32::from_be_bytes(take(operands)?)),
+            XREPEAT => Command::Repeat(u8::from_be_bytes(take(operands)?)),
+            XSTATE => Command::State(state(take(operands)?)?),
+            XENDIR => Command::EndIr(end_state(take(operands)?, TapState::PauseIr)?),
+            XENDDR => Command::EndDr(end_state(take(operands)?, TapState::PauseDr)?),
+            XWAIT => Command::Wait {
+                wait_state: state(take(operands)?)?,
+                end_state: state(take(operands)?)?,
+                micros: u32::from_be_bytes(take(operands)?),
+            },
+            XCOMMENT => {
+                let end = operands
+                    .iter()
+                    .position(|byte| *byte == 0)
+                    .ok_or(XsvfErrorKind::Truncated)?;
+                let comment = String::from_utf8_lossy(&operands[..end]).into_owned();
+                *operands = &operands[end + 1..];
+                Command::Comment(comment)
+            }
+            XSETSDRMASKS | XSDRINC => return Err(XsvfErrorKind::UnsupportedOpcode),
+            _ => return Err(XsvfErrorKind::UnknownOpcode),
+        };
+        Ok(Some(command))
+    }
+
+    /// A scan with the mask of the last `XTDOMASK`, if it was set for the length of `tdi`.
+    fn masked(&self, tdi: BitVector, tdo: Option<BitVector>) -> Scan {
+        let mask = match self.tdo_mask.len() == tdi.len() {
+            true => self.tdo_mask.clone(),
+            false => BitVector::zeros(tdi.len()),
+        };
+        Scan { tdi, tdo, mask }
+    }
+}
+
+impl Iterator for Parser<'_> {
+    type Item = Result<Command, XsvfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let (&opcode, mut operands) = self.rest.split_first()?;
+            let offset = self.offset;
+            let result = self.instruction(opcode, &mut operands);
+            self.offset += self.rest.len() - operands.len();
+            self.rest = operands;
+            match result {
+                Ok(None) => {}
+                Ok(Some(command)) => {
+                    self.done = command == Command::Complete;
+                    return Some(Ok(command));
+                }
+                Err(kind) => {
+                    self.done = true;
+                    return Some(Err(XsvfError {
+                        offset,
+                        opcode,
+                        kind,
+                    }));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Split off the next `N` bytes.
+fn take<const N: usize>(operands: &mut &[u8]) -> Result<[u8; N], XsvfErrorKind> {
+    let (bytes, rest) = operands
+        .split_first_chunk::<N>()
+        .ok_or(XsvfErrorKind::Truncated)?;
+    *operands = rest;
+    Ok(*bytes)
+}
+
+/// Split off a big-endian vector of `len` bits.
+fn vector(operands: &mut &[u8], len: usize) -> Result<BitVector, XsvfErrorKind> {
+    let num_bytes = len.div_ceil(8);
+    if operands.len() < num_bytes {
+        return Err(XsvfErrorKind::Truncated);
+    }
+    let (bytes, rest) = operands.split_at(num_bytes);
+    *operands = rest;
+    let bytes: Vec<u8> = bytes.iter().rev().copied().collect();
+    Ok(BitVector::from_bytes(&bytes, len))
+}
+
+fn state([code]: [u8; 1]) -> Result<TapState, XsvfErrorKind> {
+    // The codes of XSTATE follow the order of the standard
+    TapState::ALL
+        .get(usize::from(code))
+        .copied()
+        .ok_or(XsvfErrorKind::InvalidState(code))
+}
+
+/// The end state of `XENDIR` or `XENDDR`: 0 for Run-Test/Idle, 1 for `pause`.
+fn end_state([code]: [u8; 1], pause: TapState) -> Result<TapState, XsvfErrorKind> {
+    match code {
+        0 => Ok(TapState::RunTestIdle),
+        1 => Ok(pause),
+        _ => Err(XsvfErrorKind::InvalidState(code)),
+    }
+}
+
+/// The segment of the opcodes `XSDRB` to `XSDRE` or `XSDRTDOB` to `XSDRTDOE`, counted from 0.
+fn segment(index: u8) -> Segment {
+    match index {
+        0 => Segment::Begin,
+        1 => Segment::Continue,
+        _ => Segment::End,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(xsvf: &[u8]) -> Result<Vec<Command>, XsvfError> {
+        Parser::new(xsvf).collect()
+    }
+
+    fn bits(bytes: &[u8], len: usize) -> BitVector {
+        BitVector::from_bytes(bytes, len)
+    }
+
+    /// The IDCODE check of `testdata/vivado_idcode.svf`, as converted to XSVF, followed by a
+    /// split scan.
+    #[rustfmt::skip]
+    const IDCODE: &[u8] = &[
+        XREPEAT, 0x20,
+        XENDIR, 0x00,
+        XENDDR, 0x00,
+        XSTATE, 0x00,
+        XSTATE, 0x01,
+        XRUNTEST, 0x00, 0x00, 0x00, 0x00,
+        XCOMMENT, b'S', b'I', b'R', b' ', b'6', 0x00,
+        XSIR, 0x06, 0x09,
+        XSDRSIZE, 0x00, 0x00, 0x00, 0x20,
+        XTDOMASK, 0x0F, 0xFF, 0xFF, 0xFF,
+        XSDRTDO, 0x00, 0x00, 0x00, 0x00, 0x03, 0x72, 0x70, 0x93,
+        XSDR, 0xFF, 0xFF, 0xFF, 0xFF,
+        XSDRSIZE, 0x00, 0x00, 0x00, 0x0C,
+        XSDRB, 0x0A, 0xBC,
+        XSDRTDOE, 0x01, 0x23, 0x04, 0x56,
+        XWAIT, 0x01, 0x06, 0x00, 0x00, 0x27, 0x10,
+        XSIR2, 0x00, 0x0A, 0x03, 0xFF,
+        XCOMPLETE,
+        // Ignored after XCOMPLETE
+        0xFF,
+    ];
+
+    #[test]
+    fn idcode_check() {
+        let commands = parse(IDCODE).unwrap();
+        let idcode = bits(&[0x93, 0x70, 0x72, 0x03], 32);
+        let mask = bits(&[0xFF, 0xFF, 0xFF, 0x0F], 32);
+        assert_eq!(
+            commands,
+            [
+                Command::Repeat(32),
+                Command::EndIr(TapState::RunTestIdle),
+                Command::EndDr(TapState::RunTestIdle),
+                Command::State(TapState::TestLogicReset),
+                Command::State(TapState::RunTestIdle),
+                Command::RunTest(0),
+                Command::Comment("SIR 6".into()),
+                Command::Sir(bits(&[0x09], 6)),
+                Command::Sdr(Scan {
+                    tdi: BitVector::zeros(32),
+                    tdo: Some(idcode.clone()),
+                    mask: mask.clone(),
+                }),
+                // XSDR compares with the TDO of the last XSDRTDO
+                Command::Sdr(Scan {
+                    tdi: BitVector::ones(32),
+                    tdo: Some(idcode),
+                    mask,
+                }),
+                // The mask was set for another length
+                Command::SdrSegment(
+                    Segment::Begin,
+                    Scan {
+                        tdi: bits(&[0xBC, 0x0A], 12),
+                        tdo: None,
+                        mask: BitVector::zeros(12),
+                    }
+                ),
+                Command::SdrSegment(
+                    Segment::End,
+                    Scan {
+                        tdi: bits(&[0x23, 0x01], 12),
+                        tdo: Some(bits(&[0x56, 0x04], 12)),
+                        mask: BitVector::zeros(12),
+                    }
+                ),
+                Command::Wait {
+                    wait_state: TapState::RunTestIdle,
+                    end_state: TapState::PauseDr,
+                    micros: 10_000,
+                },
+                Command::Sir(bits(&[0xFF, 0x03], 10)),
+                Command::Complete,
+            ]
+        );
+    }
+
+    #[test]
+    fn operand_length_follows_sdr_size() {
+        let mut xsvf = alloc::vec![XSDRSIZE, 0x00, 0x00, 0x01, 0x00];
+        xsvf.push(XSDR);
+        xsvf.extend((0..32).map(|i| i as u8));
+        xsvf.push(XCOMPLETE);
+        let commands = parse(&xsvf).unwrap();
+        let Command::Sdr(scan) = &commands[0] else {
+            panic!("expected XSDR, got {:?}", commands[0]);
+        };
+        assert_eq!(scan.len(), 256);
+        assert_eq!(scan.tdi.as_bytes()[0], 31);
+        assert_eq!(scan.tdo, None);
+        assert!(scan.matches(&[0; 32]));
+        assert_eq!(commands[1], Command::Complete);
+    }
+
+    #[test]
+    fn errors_identify_offset_and_opcode() {
+        let error = |xsvf: &[u8]| parse(xsvf).unwrap_err();
+        assert_eq!(
+            error(&[
+                XSIR, 0x06, 0x09, XSDRSIZE, 0x00, 0x00, 0x00, 0x20, XSDR, 0x00, 0x00
+            ]),
+            XsvfError {
+                offset: 8,
+                opcode: XSDR,
+                kind: XsvfErrorKind::Truncated
+            }
+        );
+        assert_eq!(
+            error(&[XREPEAT, 0x00, 0x05]),
+            XsvfError {
+                offset: 2,
+                opcode: 0x05,
+                kind: XsvfErrorKind::UnknownOpcode
+            }
+        );
+        assert_eq!(error(&[XENDDR, 0x02]).kind, XsvfErrorKind::InvalidState(2));
+        assert_eq!(error(&[XSTATE, 0x10]).kind, XsvfErrorKind::InvalidState(16));
+        assert_eq!(error(&[XSDRINC]).kind, XsvfErrorKind::UnsupportedOpcode);
+        assert_eq!(error(&[XCOMMENT, b'x']).kind, XsvfErrorKind::Truncated);
+        assert_eq!(
+            error(&[XSDRSIZE, 0x00, 0x00, 0x00, 0x08, XSDRTDO, 0x00]).to_string(),
+            "Byte 5, opcode 0x09 (XSDRTDO): Stream ends within the instruction"
+        );
+    }
+
+    #[test]
+    fn parsing_stops_after_an_error() {
+        let mut parser = Parser::new(&[XREPEAT, 0x00, 0xFF, XREPEAT, 0x01]);
+        assert_eq!(parser.next(), Some(Ok(Command::Repeat(0))));
+        assert!(parser.next().unwrap().is_err());
+        assert_eq!(parser.next(), None);
+    }
+}
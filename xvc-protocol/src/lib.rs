@@ -60,10 +60,10 @@
 //! let tms = vec![0xAA; num_bytes];
 //! let tdi = vec![0x55; num_bytes];
 //!
-//! let shift_msg = BorrowedMessage::Shift { num_bits: 2 * num_bytes as u32, tms: &tms, tdi: &tdi };
+//! let shift_msg = BorrowedMessage::Shift { num_bits: 8 * num_bytes as u32, tms: &tms, tdi: &tdi };
 //! let mut output = Vec::new();
 //! shift_msg.write_to(&mut output).expect("Writing to vector shouldn't fail");
-//! assert_eq!(output, b"shift:\x04\x00\x00\x00\xAA\xAA\x55\x55");
+//! assert_eq!(output, b"shift:\x10\x00\x00\x00\xAA\xAA\x55\x55");
 //! ```
 //!
 //! ## Message Format
@@ -73,7 +73,28 @@
 //! - **GetInfo**: `getinfo:`
 //! - **SetTck**: `settck:<period in ns: u32>`
 //! - **Shift**: `shift:<num_bits: u32><TMS vector><TDI vector>`
-//! - **XvcInfo**: `xvcServer_v{version}:<max_vector_len: u32>\n`
+//! - **XvcInfo**: `xvcServer_v{version}:<max_vector_len: u32>\n`, with the length in bytes of
+//!   each vector, see [`VectorLen`]
+//!
+//! ## `no_std` Support
+//!
+//! Without the default `std` feature, the crate is `no_std` and only requires `alloc`.
+//! The `rw` module and all other `std::io` based methods are unavailable then. Instead,
+//! messages are decoded with [`incremental::IncrementalDecoder`] and encoded with the
+//! slice-based `encode` methods:
+//!
+//! ```
+//! use xvc_protocol::{Message, Response, incremental::IncrementalDecoder};
+//!
+//! let mut decoder = IncrementalDecoder::new(1024);
+//! let message = decoder.feed(b"settck:\x64\x00\x00\x00").unwrap().unwrap();
+//! assert_eq!(message, Message::SetTck { period_ns: 100 });
+//!
+//! let response = Response::TckPeriod(100);
+//! let mut tx = [0u8; 64];
+//! let len = response.encode(&mut tx).unwrap();
+//! assert_eq!(&tx[..len], b"\x64\x00\x00\x00");
+//! ```
 //!
 //! ## Error Handling
 //!
@@ -84,10 +105,37 @@
 //! The types in this library are thread-safe and can be safely shared across threads.
 //! However, I/O operations (reading/writing) are not synchronized and require external coordination.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 pub mod protocol;
 pub use protocol::*;
+#[cfg(feature = "testing")]
+mod arbitrary_support;
+#[cfg(feature = "tokio")]
+mod async_rw;
+pub mod bit_vector;
+pub use bit_vector::BitVector;
+pub mod bits;
+#[cfg(feature = "std")]
+pub mod capture;
 pub(crate) mod codec;
+pub mod crc;
 pub mod error;
+pub mod idcode;
+pub mod incremental;
+#[cfg(feature = "jtag")]
+pub mod jtag;
+#[cfg(feature = "pcapng")]
+pub mod pcapng;
+#[cfg(feature = "std")]
 pub mod rw;
+#[cfg(feature = "serde")]
+mod serde_support;
+pub mod shift_vector;
+pub use shift_vector::ShiftVector;
 #[cfg(feature = "tokio")]
 pub mod tokio_codec;
+#[cfg(feature = "std")]
+pub mod transcript;
@@ -0,0 +1,441 @@
+//! Export of [captures](crate::capture) as pcapng files, e.g. to compare sessions in Wireshark.
+//!
+//! Enable with the `pcapng` feature flag.
+//!
+//! Captures hold the bytes of a stream, but no network headers. [`PcapngWriter`] wraps the
+//! records in synthetic IPv4 and TCP headers between two fixed endpoints, the client
+//! [`CLIENT_ADDR`] and the server [`SERVER_ADDR`] on the XVC port 2542. The connection starts with
+//! a handshake and ends with a FIN from either side, and the sequence numbers follow the payload,
+//! so Wireshark's "Follow TCP Stream" and dissectors for port 2542 work on the exported file.
+//!
+//! The capture is converted record by record, so only one record is held in memory at a time:
+//!
+//! ```no_run
+//! use std::{fs::File, io::{BufReader, BufWriter}};
+//! use xvc_protocol::pcapng::{self, Endpoint};
+//!
+//! let capture = BufReader::new(File::open("session.cap")?);
+//! let pcapng = BufWriter::new(File::create("session.pcapng")?);
+//! pcapng::export(capture, pcapng, Endpoint::Server)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, SocketAddrV4},
+};
+
+use crate::capture::{CaptureReader, Direction, Record};
+
+/// The synthetic address of the client.
+pub const CLIENT_ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 50000);
+/// The synthetic address of the server.
+pub const SERVER_ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 2542);
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+/// Packets are IPv4 without a link-layer header
+const LINKTYPE_RAW: u16 = 101;
+
+const IPV4_HEADER_LEN: usize = 20;
+const TCP_HEADER_LEN: usize = 20;
+/// The largest payload of a segment, limited by the total length field of IPv4
+const MAX_SEGMENT_LEN: usize = u16::MAX as usize - IPV4_HEADER_LEN - TCP_HEADER_LEN;
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+
+/// The initial sequence numbers of the client and the server
+const INITIAL_SEQUENCE: [u32; 2] = [0x1000_0000, 0x2000_0000];
+
+/// The side of the connection whose stream was captured.
+///
+/// This determines the direction of the records: the bytes a server reads were sent by the
+/// client, while the bytes a client reads were sent by the server.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Endpoint {
+    Client,
+    Server,
+}
+
+impl Endpoint {
+    /// The index of the endpoint in the per-endpoint state
+    fn index(self) -> usize {
+        match self {
+            Endpoint::Client => 0,
+            Endpoint::Server => 1,
+        }
+    }
+
+    fn addr(self) -> SocketAddrV4 {
+        match self {
+            Endpoint::Client => CLIENT_ADDR,
+            Endpoint::Server => SERVER_ADDR,
+        }
+    }
+
+    fn peer(self) -> Endpoint {
+        match self {
+            Endpoint::Client => Endpoint::Server,
+            Endpoint::Server => Endpoint::Client,
+        }
+    }
+}
+
+/// Writes capture records as TCP segments into a pcapng file.
+///
+/// The handshake is written with the timestamp of the first record, and the connection is
+/// closed by [`finish`](PcapngWriter::finish) with the timestamp of the last one.
+#[derive(Debug)]
+pub struct PcapngWriter<W: Write> {
+    writer: W,
+    captured: Endpoint,
+    /// The next sequence numbers of the client and the server
+    seq: [u32; 2],
+    connected: bool,
+    ip_id: u16,
+    last_timestamp_us: u64,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Start a pcapng file of a capture of the stream of `captured`.
+    pub fn new(mut writer: W, captured: Endpoint) -> io::Result<PcapngWriter<W>> {
+        let mut body = Vec::with_capacity(16);
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // The length of the section is not known in advance
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        write_block(&mut writer, BLOCK_SECTION_HEADER, &body)?;
+
+        let mut body = Vec::with_capacity(8);
+        body.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // No limit on the captured length. Timestamps default to microseconds.
+        body.extend_from_slice(&0u32.to_le_bytes());
+        write_block(&mut writer, BLOCK_INTERFACE_DESCRIPTION, &body)?;
+
+        Ok(PcapngWriter {
+            writer,
+            captured,
+            seq: INITIAL_SEQUENCE,
+            connected: false,
+            ip_id: 0,
+            last_timestamp_us: 0,
+        })
+    }
+
+    /// Write the bytes of `record` as segments of the endpoint that sent them.
+    pub fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let timestamp_us = record.timestamp_us;
+        if !self.connected {
+            self.segment(Endpoint::Client, SYN, &[], timestamp_us)?;
+            self.segment(Endpoint::Server, SYN | ACK, &[], timestamp_us)?;
+            self.segment(Endpoint::Client, ACK, &[], timestamp_us)?;
+            self.connected = true;
+        }
+        let sender = match record.direction {
+            Direction::Read => self.captured.peer(),
+            Direction::Written => self.captured,
+        };
+        for chunk in record.data.chunks(MAX_SEGMENT_LEN) {
+            self.segment(sender, PSH | ACK, chunk, timestamp_us)?;
+        }
+        self.last_timestamp_us = timestamp_us;
+        Ok(())
+    }
+
+    /// Close the connection, if any record was written, and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.connected {
+            let timestamp_us = self.last_timestamp_us;
+            self.segment(Endpoint::Client, FIN | ACK, &[], timestamp_us)?;
+            self.segment(Endpoint::Server, FIN | ACK, &[], timestamp_us)?;
+            self.segment(Endpoint::Client, ACK, &[], timestamp_us)?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Write a TCP segment with `payload` from `sender` to its peer as an enhanced packet block.
+    fn segment(
+        &mut self,
+        sender: Endpoint,
+        flags: u8,
+        payload: &[u8],
+        timestamp_us: u64,
+    ) -> io::Result<()> {
+        let (src, dst) = (sender.addr(), sender.peer().addr());
+        let seq = self.seq[sender.index()];
+        let ack = match flags & ACK {
+            0 => 0,
+            _ => self.seq[sender.peer().index()],
+        };
+        let total_len = IPV4_HEADER_LEN + TCP_HEADER_LEN + payload.len();
+
+        let mut packet = Vec::with_capacity(total_len);
+        packet.extend_from_slice(&[0x45, 0x00]);
+        packet.extend_from_slice(&(total_len as u16).to_be_bytes());
+        packet.extend_from_slice(&self.ip_id.to_be_bytes());
+        // Don't fragment, TTL 64, TCP
+        packet.extend_from_slice(&[0x40, 0x00, 64, 6, 0, 0]);
+        packet.extend_from_slice(&src.ip().octets());
+        packet.extend_from_slice(&dst.ip().octets());
+        let checksum = internet_checksum(0, &packet);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+        packet.extend_from_slice(&src.port().to_be_bytes());
+        packet.extend_from_slice(&dst.port().to_be_bytes());
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&ack.to_be_bytes());
+        packet.extend_from_slice(&[(TCP_HEADER_LEN as u8 / 4) << 4, flags]);
+        packet.extend_from_slice(&u16::MAX.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0]);
+        packet.extend_from_slice(payload);
+        let tcp_len = (TCP_HEADER_LEN + payload.len()) as u16;
+        let mut pseudo_header = [0; 12];
+        pseudo_header[..4].copy_from_slice(&src.ip().octets());
+        pseudo_header[4..8].copy_from_slice(&dst.ip().octets());
+        pseudo_header[9] = 6;
+        pseudo_header[10..].copy_from_slice(&tcp_len.to_be_bytes());
+        let sum = ones_complement_sum(0, &pseudo_header);
+        let checksum = internet_checksum(sum, &packet[IPV4_HEADER_LEN..]);
+        packet[IPV4_HEADER_LEN + 16..IPV4_HEADER_LEN + 18].copy_from_slice(&checksum.to_be_bytes());
+
+        let mut body = Vec::with_capacity(20 + packet.len().next_multiple_of(4));
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&((timestamp_us >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(timestamp_us as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&packet);
+        write_block(&mut self.writer, BLOCK_ENHANCED_PACKET, &body)?;
+
+        let syn_fin = u32::from(flags & (SYN | FIN) != 0);
+        self.seq[sender.index()] = seq.wrapping_add(payload.len() as u32).wrapping_add(syn_fin);
+        self.ip_id = self.ip_id.wrapping_add(1);
+        Ok(())
+    }
+}
+
+/// Convert the capture read from `capture`, which recorded the stream of `captured`, into a
+/// pcapng file written to `pcapng`.
+pub fn export(capture: impl Read, pcapng: impl Write, captured: Endpoint) -> io::Result<()> {
+    let mut writer = PcapngWriter::new(pcapng, captured)?;
+    for record in CaptureReader::new(capture) {
+        writer.write_record(&record?)?;
+    }
+    writer.finish().map(drop)
+}
+
+/// Write a block of `block_type` with `body`, padded to 32 bits.
+fn write_block(writer: &mut impl Write, block_type: u32, body: &[u8]) -> io::Result<()> {
+    let padding = body.len().next_multiple_of(4) - body.len();
+    let total_len = (12 + body.len() + padding) as u32;
+    writer.write_all(&block_type.to_le_bytes())?;
+    writer.write_all(&total_len.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&[0; 3][..padding])?;
+    writer.write_all(&total_len.to_le_bytes())
+}
+
+/// Add the 16-bit words of `bytes` to `sum`, padding an odd length with a zero byte.
+fn ones_complement_sum(sum: u32, bytes: &[u8]) -> u32 {
+    let mut chunks = bytes.chunks_exact(2);
+    let mut sum = (&mut chunks).fold(sum, |sum, word| {
+        sum + u32::from(u16::from_be_bytes([word[0], word[1]]))
+    });
+    if let [last] = chunks.remainder() {
+        sum += u32::from(*last) << 8;
+    }
+    // Fold the carries so far, so the sum of a 64 KiB packet cannot overflow
+    (sum & 0xFFFF) + (sum >> 16)
+}
+
+/// The checksum of IPv4 and TCP headers over `bytes`, starting with the partial `sum`.
+fn internet_checksum(sum: u32, bytes: &[u8]) -> u16 {
+    let mut sum = ones_complement_sum(sum, bytes);
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An enhanced packet block, parsed down to the TCP segment
+    #[derive(Debug)]
+    struct Segment {
+        timestamp_us: u64,
+        src: SocketAddrV4,
+        seq: u32,
+        ack: u32,
+        flags: u8,
+        payload: Vec<u8>,
+    }
+
+    fn u16_be(bytes: &[u8]) -> u16 {
+        u16::from_be_bytes(bytes[..2].try_into().unwrap())
+    }
+
+    fn u32_be(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes(bytes[..4].try_into().unwrap())
+    }
+
+    fn u32_le(bytes: &[u8]) -> u32 {
+        u32::from_le_bytes(bytes[..4].try_into().unwrap())
+    }
+
+    /// Check the block structure of `pcapng` and return its segments.
+    fn parse(mut pcapng: &[u8]) -> Vec<Segment> {
+        let mut block_types = Vec::new();
+        let mut segments = Vec::new();
+        while !pcapng.is_empty() {
+            let block_type = u32_le(pcapng);
+            let len = u32_le(&pcapng[4..]) as usize;
+            assert_eq!(len % 4, 0);
+            assert_eq!(u32_le(&pcapng[len - 4..]) as usize, len);
+            let body = &pcapng[8..len - 4];
+            block_types.push(block_type);
+            if block_type == BLOCK_ENHANCED_PACKET {
+                assert_eq!(u32_le(body), 0);
+                let timestamp_us =
+                    u64::from(u32_le(&body[4..])) << 32 | u64::from(u32_le(&body[8..]));
+                let captured_len = u32_le(&body[12..]) as usize;
+                assert_eq!(u32_le(&body[16..]) as usize, captured_len);
+                let packet = &body[20..20 + captured_len];
+                assert_eq!(u16_be(&packet[2..]) as usize, packet.len());
+                assert_eq!(internet_checksum(0, &packet[..IPV4_HEADER_LEN]), 0);
+                let tcp = &packet[IPV4_HEADER_LEN..];
+                let src = SocketAddrV4::new(u32_be(&packet[12..]).into(), u16_be(tcp));
+                segments.push(Segment {
+                    timestamp_us,
+                    src,
+                    seq: u32_be(&tcp[4..]),
+                    ack: u32_be(&tcp[8..]),
+                    flags: tcp[13],
+                    payload: tcp[TCP_HEADER_LEN..].to_vec(),
+                });
+            }
+            pcapng = &pcapng[len..];
+        }
+        assert_eq!(
+            block_types[..2],
+            [BLOCK_SECTION_HEADER, BLOCK_INTERFACE_DESCRIPTION]
+        );
+        assert!(block_types[2..].iter().all(|t| *t == BLOCK_ENHANCED_PACKET));
+        segments
+    }
+
+    fn record(direction: Direction, timestamp_us: u64, data: &[u8]) -> Record {
+        Record {
+            direction,
+            timestamp_us,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn session_round_trips() {
+        let records = [
+            record(Direction::Read, 1_700_000_000_000_001, b"getinfo:"),
+            record(
+                Direction::Written,
+                1_700_000_000_000_002,
+                b"xvcServer_v1.0:2048\n",
+            ),
+            record(
+                Direction::Read,
+                1_700_000_000_000_003,
+                b"shift:\x0D\x00\x00\x00\x01\x10\xFF",
+            ),
+            record(Direction::Read, 1_700_000_000_000_004, b"\x1F"),
+            record(Direction::Written, 1_700_000_000_000_005, b"\xFF\x1F"),
+        ];
+        let mut capture = Vec::new();
+        records
+            .iter()
+            .for_each(|r| r.write_to(&mut capture).unwrap());
+        let mut pcapng = Vec::new();
+        export(&capture[..], &mut pcapng, Endpoint::Server).unwrap();
+
+        let segments = parse(&pcapng);
+        let flags: Vec<u8> = segments.iter().map(|s| s.flags).collect();
+        assert_eq!(
+            flags,
+            [
+                SYN,
+                SYN | ACK,
+                ACK,
+                PSH | ACK,
+                PSH | ACK,
+                PSH | ACK,
+                PSH | ACK,
+                PSH | ACK,
+                FIN | ACK,
+                FIN | ACK,
+                ACK
+            ]
+        );
+        let data = &segments[3..8];
+        for (segment, record) in data.iter().zip(&records) {
+            assert_eq!(segment.payload, record.data);
+            assert_eq!(segment.timestamp_us, record.timestamp_us);
+            let src = match record.direction {
+                Direction::Read => CLIENT_ADDR,
+                Direction::Written => SERVER_ADDR,
+            };
+            assert_eq!(segment.src, src);
+        }
+        // Each segment acknowledges everything its peer sent before
+        assert_eq!(data[0].seq, INITIAL_SEQUENCE[0] + 1);
+        assert_eq!(data[1].ack, data[0].seq + 8);
+        assert_eq!(data[2].seq, data[0].seq + 8);
+        assert_eq!(data[3].seq, data[2].seq + 13);
+        assert_eq!(data[4].ack, data[3].seq + 1);
+        assert_eq!(segments[10].ack, data[4].seq + 2 + 1);
+        assert_eq!(segments[10].timestamp_us, records[4].timestamp_us);
+    }
+
+    #[test]
+    fn client_captures_are_reversed_and_large_records_split() {
+        let mut pcapng = PcapngWriter::new(Vec::new(), Endpoint::Client).unwrap();
+        let data: Vec<u8> = (0..2 * MAX_SEGMENT_LEN + 10).map(|i| i as u8).collect();
+        pcapng
+            .write_record(&record(Direction::Read, 7, &data))
+            .unwrap();
+        let segments = parse(&pcapng.finish().unwrap());
+        let data_segments = &segments[3..6];
+        assert!(data_segments.iter().all(|s| s.src == SERVER_ADDR));
+        let payload: Vec<u8> = data_segments
+            .iter()
+            .flat_map(|s| s.payload.clone())
+            .collect();
+        assert_eq!(payload, data);
+        assert_eq!(data_segments[2].payload.len(), 10);
+    }
+
+    #[test]
+    fn empty_capture_has_no_packets() {
+        let mut pcapng = Vec::new();
+        export(&[][..], &mut pcapng, Endpoint::Server).unwrap();
+        assert!(parse(&pcapng).is_empty());
+        assert_eq!(pcapng.len(), 28 + 20);
+    }
+
+    #[test]
+    fn truncated_capture_is_an_error() {
+        let mut capture = Vec::new();
+        record(Direction::Read, 1, b"getinfo:")
+            .write_to(&mut capture)
+            .unwrap();
+        capture.pop();
+        let error = export(&capture[..], Vec::new(), Endpoint::Server).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,6 +1,10 @@
-use std::{fmt::Display, str::FromStr};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{fmt::Display, str::FromStr};
 
-use crate::error::ParseVersionError;
+use crate::{
+    BitVector, ShiftVector,
+    error::{ParseVersionError, ReadError},
+};
 
 /// The version of the protocol.
 /// A version always consists of a major and a minor part.
@@ -24,6 +28,14 @@ impl Version {
         Version::V1_0
     }
 
+    /// Whether this library can talk to a peer of this version.
+    ///
+    /// Minor versions only extend the protocol, so all versions with the major part of
+    /// [`latest`](Self::latest) support the commands implemented here.
+    pub fn is_supported(&self) -> bool {
+        self.major == Self::latest().major
+    }
+
     /// The major part of the version
     pub fn major(&self) -> usize {
         self.major
@@ -41,6 +53,14 @@ fn version_ordering() {
     assert!(Version { major: 2, minor: 0 } > Version { major: 1, minor: 0 });
 }
 
+#[test]
+fn supported_versions() {
+    assert!(Version::V1_0.is_supported());
+    assert!(Version::new(1, 1).is_supported());
+    assert!(!Version::new(2, 0).is_supported());
+    assert!(!Version::new(0, 9).is_supported());
+}
+
 impl Default for Version {
     fn default() -> Self {
         Self::V1_0
@@ -48,7 +68,7 @@ impl Default for Version {
 }
 
 impl Display for Version {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}.{}", self.major, self.minor)
     }
 }
@@ -89,65 +109,661 @@ fn incorrect_version_from_str() {
 /// The server needs to process each message in the order received and promptly provide a response.
 /// For the XVC 1.0 protocol, only one connection is assumed.
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub enum Message<B = Box<[u8]>> {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "B: AsRef<[u8]>",
+        deserialize = "B: From<alloc::vec::Vec<u8>>"
+    ))
+)]
+pub enum Message<B = ShiftVector> {
     /// Requests info from the server. This is used to determine protocol capabilities of the server.
     GetInfo,
     /// Configures the TCK period. When sending JTAG vectors the TCK rate may need to be varied to accommodate cable and board signal integrity conditions.
     /// This command is used by clients to adjust the TCK rate in order to slow down or speed up the shifting of JTAG vectors.
     SetTck { period_ns: u32 },
     /// Used to shift JTAG vectors in-and out of a device.
+    ///
+    /// A shift of zero bits is valid: its vectors are empty, and so is its response. Readers
+    /// reject shifts whose vectors exceed their limit with `ReadError::TooManyBytes` before
+    /// reading the vectors, including `num_bits` close to `u32::MAX`.
     Shift {
         /// represents the number of TCK clk toggles needed to shift the vectors out
         num_bits: u32,
         /// a byte sized vector with all the TMS data.
         /// The vector is num_bits and rounds up to the nearest byte.
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::hex"))]
         tms: B,
         /// a byte sized vector with all the TDI data.
         /// The vector is num_bits and rounds up to the nearest byte.
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::hex"))]
         tdi: B,
     },
+    /// A command that is not part of the protocol, e.g. a vendor extension such as `lock:`.
+    ///
+    /// Only decoded in lenient mode (see [`rw::Decoder::lenient`](crate::rw::Decoder::lenient)).
+    /// By convention, an unknown command consists of its name and the `:` delimiter only: any
+    /// payload that follows is decoded as the next message, so callers that know the payload
+    /// of a command have to consume it themselves. Encoded as `<name>:`.
+    Unknown {
+        /// The command name without the `:` delimiter
+        name: String,
+    },
 }
 
-pub type OwnedMessage = Message<Box<[u8]>>;
+pub type OwnedMessage = Message<ShiftVector>;
 pub type BorrowedMessage<'a> = Message<&'a [u8]>;
 
+impl<'a> Message<&'a [u8]> {
+    /// A `Shift` message of the `tms` and `tdi` vectors.
+    ///
+    /// # Panics
+    ///
+    /// If `tms` and `tdi` differ in length or hold more than `u32::MAX` bits.
+    pub fn shift(tms: &'a BitVector, tdi: &'a BitVector) -> BorrowedMessage<'a> {
+        assert_eq!(tms.len(), tdi.len(), "tms and tdi must be of equal length");
+        Message::Shift {
+            num_bits: u32::try_from(tms.len()).expect("too many bits for a shift"),
+            tms: tms.as_bytes(),
+            tdi: tdi.as_bytes(),
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> Message<B> {
+    /// Split a `Shift` into consecutive shifts with vectors of at most `max_bytes` bytes, e.g.
+    /// the `max_vector_len` announced by a server. Other messages are returned unchanged.
+    ///
+    /// All shifts but the last cover exactly `8 * max_bytes` bits, so their vectors are
+    /// borrowed from this message and only the last one may end within a byte. The TDO
+    /// vectors of the shifts are reassembled with [`bits::concat_tdo`](crate::bits::concat_tdo).
+    ///
+    /// # Panics
+    ///
+    /// If `max_bytes` is 0 or the vectors of a `Shift` do not match its `num_bits`.
+    ///
+    /// ```
+    /// use xvc_protocol::{BorrowedMessage, Message};
+    ///
+    /// let shift = Message::try_shift(20, &[1u8, 2, 3][..], &[4u8, 5, 6][..]).unwrap();
+    /// let parts: Vec<BorrowedMessage> = shift.split(2).collect();
+    /// assert_eq!(
+    ///     parts,
+    ///     [
+    ///         Message::Shift { num_bits: 16, tms: &[1, 2][..], tdi: &[4, 5][..] },
+    ///         Message::Shift { num_bits: 4, tms: &[3][..], tdi: &[6][..] },
+    ///     ]
+    /// );
+    /// ```
+    pub fn split(&self, max_bytes: usize) -> impl Iterator<Item = BorrowedMessage<'_>> {
+        assert!(max_bytes > 0, "cannot split into shifts of 0 bytes");
+        if let Err(e) = self.check_shift_lengths() {
+            panic!("{e}");
+        }
+        let mut rest = Some(match self {
+            Message::GetInfo => Message::GetInfo,
+            Message::SetTck { period_ns } => Message::SetTck {
+                period_ns: *period_ns,
+            },
+            Message::Shift { num_bits, tms, tdi } => Message::Shift {
+                num_bits: *num_bits,
+                tms: tms.as_ref(),
+                tdi: tdi.as_ref(),
+            },
+            Message::Unknown { name } => Message::Unknown { name: name.clone() },
+        });
+        core::iter::from_fn(move || match rest.take()? {
+            Message::Shift { num_bits, tms, tdi } if tms.len() > max_bytes => {
+                // Less than num_bits, which fits into a u32
+                let part_bits = 8 * max_bytes as u32;
+                rest = Some(Message::Shift {
+                    num_bits: num_bits - part_bits,
+                    tms: &tms[max_bytes..],
+                    tdi: &tdi[max_bytes..],
+                });
+                Some(Message::Shift {
+                    num_bits: part_bits,
+                    tms: &tms[..max_bytes],
+                    tdi: &tdi[..max_bytes],
+                })
+            }
+            message => Some(message),
+        })
+    }
+}
+
+#[test]
+fn split_shift() {
+    use crate::bits;
+    use alloc::vec;
+
+    // xorshift, so that the lengths are arbitrary but reproducible
+    let mut state = 0x2545_F491_u32;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+    for _ in 0..200 {
+        let num_bits = random() % 200;
+        let max_bytes = 1 + random() as usize % 8;
+        let num_bytes = num_bits.div_ceil(8) as usize;
+        let tms: Vec<u8> = (0..num_bytes).map(|_| random() as u8).collect();
+        let tdi: Vec<u8> = (0..num_bytes).map(|_| random() as u8).collect();
+        let shift = Message::try_shift(num_bits, &tms[..], &tdi[..]).unwrap();
+
+        let parts: Vec<BorrowedMessage> = shift.split(max_bytes).collect();
+        let mut tms_parts = Vec::new();
+        let mut tdi_parts = Vec::new();
+        let mut bits_per_part = Vec::new();
+        for part in &parts {
+            let Message::Shift { num_bits, tms, tdi } = part else {
+                panic!("expected only shifts, got {part:?}");
+            };
+            assert!(tms.len() <= max_bytes);
+            part.check_shift_lengths().unwrap();
+            tms_parts.push(*tms);
+            tdi_parts.push(*tdi);
+            bits_per_part.push(*num_bits);
+        }
+        assert_eq!(bits_per_part.iter().sum::<u32>(), num_bits);
+        assert_eq!(parts.len(), num_bytes.div_ceil(max_bytes).max(1));
+
+        let mut expected = tdi.clone();
+        bits::mask_padding(&mut expected, num_bits as usize);
+        assert_eq!(*bits::concat_tdo(&tdi_parts, &bits_per_part), expected);
+        let mut expected = tms.clone();
+        bits::mask_padding(&mut expected, num_bits as usize);
+        assert_eq!(*bits::concat_tdo(&tms_parts, &bits_per_part), expected);
+    }
+
+    let settck = Message::<&[u8]>::SetTck { period_ns: 10 };
+    assert_eq!(settck.split(1).collect::<Vec<_>>(), vec![settck.clone()]);
+}
+
+/// Iterates over the messages in a complete in-memory buffer, e.g. a captured stream, with
+/// [`Message::from_bytes`].
+///
+/// Ends after the last message, or after the first error. A buffer that ends within a message
+/// yields `ReadError::Incomplete`, and the unparsed bytes are left in
+/// [`remaining`](MessageIter::remaining).
+///
+/// ```
+/// use xvc_protocol::{Message, MessageIter, error::ReadError};
+///
+/// let mut messages = MessageIter::new(b"getinfo:settck:\x64\x00\x00\x00shift:", 1024);
+/// assert_eq!(messages.next().unwrap().unwrap(), Message::GetInfo);
+/// assert_eq!(messages.next().unwrap().unwrap(), Message::SetTck { period_ns: 100 });
+/// assert!(matches!(messages.next(), Some(Err(ReadError::Incomplete { needed: 4 }))));
+/// assert!(messages.next().is_none());
+/// assert_eq!(messages.remaining(), b"shift:");
+/// ```
+#[derive(Clone, Debug)]
+pub struct MessageIter<'a> {
+    buf: &'a [u8],
+    max_shift_bytes: usize,
+    failed: bool,
+}
+
+impl<'a> MessageIter<'a> {
+    /// Iterate over the messages in `buf`, rejecting shift vectors longer than
+    /// `max_shift_bytes`.
+    pub fn new(buf: &'a [u8], max_shift_bytes: usize) -> MessageIter<'a> {
+        MessageIter {
+            buf,
+            max_shift_bytes,
+            failed: false,
+        }
+    }
+
+    /// The bytes that were not parsed yet, starting with the failed message after an error.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+}
+
+impl Iterator for MessageIter<'_> {
+    type Item = Result<OwnedMessage, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.buf.is_empty() {
+            return None;
+        }
+        match Message::from_bytes(self.buf, self.max_shift_bytes) {
+            Ok((message, len)) => {
+                self.buf = &self.buf[len..];
+                Some(Ok(message))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl core::iter::FusedIterator for MessageIter<'_> {}
+
+#[test]
+fn from_bytes_reports_needed_bytes() {
+    let shift = Message::try_shift(20, &[1u8, 2, 3][..], &[4u8, 5, 6][..]).unwrap();
+    let len = shift.encoded_len();
+    let mut encoded = alloc::vec![0; len];
+    shift.encode(&mut encoded).unwrap();
+    encoded.extend_from_slice(b"getinfo:");
+
+    let (message, consumed) = Message::from_bytes(&encoded, 3).unwrap();
+    assert_eq!(consumed, len);
+    assert_eq!(
+        message,
+        Message::Shift {
+            num_bits: 20,
+            tms: ShiftVector::from([1, 2, 3]),
+            tdi: ShiftVector::from([4, 5, 6]),
+        }
+    );
+
+    // Only the parts that were started are known to be missing
+    for end in 0..len {
+        let needed = match end {
+            0..6 => 6 - end,
+            6..10 => 10 - end,
+            _ => len - end,
+        };
+        let result = Message::from_bytes(&encoded[..end], 3);
+        assert!(
+            matches!(result, Err(ReadError::Incomplete { needed: n }) if n == needed),
+            "{end} bytes: {result:?}"
+        );
+    }
+    assert!(matches!(
+        Message::from_bytes(&encoded, 2),
+        Err(ReadError::TooManyBytes { .. })
+    ));
+    assert!(matches!(
+        Message::from_bytes(b"junk", 2),
+        Err(ReadError::InvalidCommand(_))
+    ));
+}
+
+#[test]
+fn zero_bit_shift_has_empty_vectors() {
+    let (message, consumed) = Message::from_bytes(b"shift:\0\0\0\0getinfo:", 0).unwrap();
+    assert_eq!(consumed, 10);
+    assert_eq!(
+        message,
+        Message::Shift {
+            num_bits: 0,
+            tms: ShiftVector::new(),
+            tdi: ShiftVector::new(),
+        }
+    );
+    assert_eq!(crate::Response::len_for(&message), Some(0));
+}
+
+#[test]
+fn largest_shift_does_not_overflow() {
+    let encoded = b"shift:\xFF\xFF\xFF\xFF";
+    let num_bytes = u32::MAX.div_ceil(8) as usize;
+    assert!(matches!(
+        Message::from_bytes(encoded, usize::MAX),
+        Err(ReadError::Incomplete { needed }) if needed == 2 * num_bytes
+    ));
+    assert!(matches!(
+        Message::from_bytes(encoded, 1024),
+        Err(ReadError::TooManyBytes { max: 1024, need }) if need == num_bytes
+    ));
+}
+
+#[test]
+fn message_iter_stops_after_error() {
+    let messages: Vec<_> = MessageIter::new(b"getinfo:getinfo:", 16).collect();
+    assert!(matches!(
+        messages[..],
+        [Ok(Message::GetInfo), Ok(Message::GetInfo)]
+    ));
+
+    let mut messages = MessageIter::new(b"getinfo:junk:getinfo:", 16);
+    assert!(matches!(messages.next(), Some(Ok(Message::GetInfo))));
+    assert!(matches!(
+        messages.next(),
+        Some(Err(ReadError::InvalidCommand(_)))
+    ));
+    assert!(messages.next().is_none());
+    assert_eq!(messages.remaining(), b"junk:getinfo:");
+}
+
+#[test]
+fn shift_from_bit_vectors() {
+    let tms: BitVector = [true, true, false, true, false, false, false, false, true]
+        .into_iter()
+        .collect();
+    let tdi = BitVector::from_bytes(&[0xA5, 0xFF], 9);
+    assert_eq!(
+        Message::shift(&tms, &tdi),
+        Message::Shift {
+            num_bits: 9,
+            tms: &[0x0B, 0x01][..],
+            tdi: &[0xA5, 0x01][..],
+        }
+    );
+}
+
+/// The number of bytes at the start and end of each vector that `Display` shows for a `Shift`.
+const DISPLAY_BYTES: usize = 8;
+
+impl<B: AsRef<[u8]>> Message<B> {
+    /// A human-readable summary of this message for logs, which shows only the first and last
+    /// `max_bytes` bytes of the vectors of a `Shift`.
+    ///
+    /// ```
+    /// use xvc_protocol::Message;
+    ///
+    /// let tdi: Vec<u8> = (0..=255).collect();
+    /// let shift = Message::try_shift(2048, &[0u8; 256][..], &tdi[..]).unwrap();
+    /// assert_eq!(
+    ///     shift.summary(2).to_string(),
+    ///     "shift: num_bits=2048 len=256 tms=0000...0000 tdi=0001...feff"
+    /// );
+    /// ```
+    pub fn summary(&self, max_bytes: usize) -> MessageSummary<'_, B> {
+        MessageSummary {
+            message: self,
+            max_bytes,
+        }
+    }
+}
+
+/// Same as [`summary`](Message::summary) with 8 bytes.
+impl<B: AsRef<[u8]>> Display for Message<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.summary(DISPLAY_BYTES).fmt(f)
+    }
+}
+
+/// Displays a [`Message`] with abbreviated vectors, see [`Message::summary`].
+pub struct MessageSummary<'a, B> {
+    message: &'a Message<B>,
+    max_bytes: usize,
+}
+
+impl<B: AsRef<[u8]>> Display for MessageSummary<'_, B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.message {
+            Message::GetInfo => write!(f, "getinfo:"),
+            Message::SetTck { period_ns } => write!(f, "settck: period_ns={period_ns}"),
+            Message::Shift { num_bits, tms, tdi } => write!(
+                f,
+                "shift: num_bits={num_bits} len={} tms={} tdi={}",
+                tms.as_ref().len(),
+                HexSummary::new(tms.as_ref(), self.max_bytes),
+                HexSummary::new(tdi.as_ref(), self.max_bytes),
+            ),
+            Message::Unknown { name } => write!(f, "{name}: (unknown)"),
+        }
+    }
+}
+
+/// Displays bytes as lowercase hex, abbreviated to the first and last `max_bytes` bytes with
+/// `...` in between if there are more than `2 * max_bytes`.
+///
+/// ```
+/// use xvc_protocol::HexSummary;
+///
+/// assert_eq!(HexSummary::new(&[0xAB, 0x0C], 1).to_string(), "ab0c");
+/// assert_eq!(HexSummary::new(&[1, 2, 3], 1).to_string(), "01...03");
+/// ```
+pub struct HexSummary<'a> {
+    bytes: &'a [u8],
+    max_bytes: usize,
+}
+
+impl<'a> HexSummary<'a> {
+    /// Summarize `bytes`, showing at most `max_bytes` bytes at either end.
+    pub fn new(bytes: &'a [u8], max_bytes: usize) -> HexSummary<'a> {
+        HexSummary { bytes, max_bytes }
+    }
+}
+
+impl Display for HexSummary<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let hex = |f: &mut core::fmt::Formatter<'_>, bytes: &[u8]| {
+            bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+        };
+        if self.bytes.len() <= self.max_bytes.saturating_mul(2) {
+            return hex(f, self.bytes);
+        }
+        hex(f, &self.bytes[..self.max_bytes])?;
+        write!(f, "...")?;
+        hex(f, &self.bytes[self.bytes.len() - self.max_bytes..])
+    }
+}
+
+#[test]
+fn display_messages() {
+    use alloc::string::ToString;
+
+    assert_eq!(Message::<&[u8]>::GetInfo.to_string(), "getinfo:");
+    assert_eq!(
+        Message::<&[u8]>::SetTck { period_ns: 100 }.to_string(),
+        "settck: period_ns=100"
+    );
+    let unknown = Message::<&[u8]>::Unknown {
+        name: "lock".into(),
+    };
+    assert_eq!(unknown.to_string(), "lock: (unknown)");
+
+    let tms: Vec<u8> = (0..17).collect();
+    let shift = Message::try_shift(136, &tms[..], &tms[..]).unwrap();
+    assert_eq!(
+        shift.to_string(),
+        "shift: num_bits=136 len=17 \
+         tms=0001020304050607...090a0b0c0d0e0f10 tdi=0001020304050607...090a0b0c0d0e0f10"
+    );
+    // Vectors up to twice the limit are shown in full
+    assert_eq!(
+        shift.summary(9).to_string(),
+        "shift: num_bits=136 len=17 \
+         tms=000102030405060708090a0b0c0d0e0f10 tdi=000102030405060708090a0b0c0d0e0f10"
+    );
+    assert_eq!(
+        shift.summary(0).to_string(),
+        "shift: num_bits=136 len=17 tms=... tdi=..."
+    );
+    let empty = Message::try_shift(0, &[][..], &[][..]).unwrap();
+    assert_eq!(
+        empty.summary(0).to_string(),
+        "shift: num_bits=0 len=0 tms= tdi="
+    );
+}
+
+/// Reusable storage for the TMS and TDI vectors of decoded `Shift` messages.
+///
+/// Decoding with the `read_into` and `decode_into` methods borrows the vectors of a `Shift`
+/// message from these buffers instead of allocating them for every message. The buffers grow
+/// to the largest vectors decoded so far and never shrink.
+#[derive(Clone, Debug, Default)]
+pub struct ShiftBuffers {
+    tms: Vec<u8>,
+    tdi: Vec<u8>,
+}
+
+impl ShiftBuffers {
+    pub fn new() -> ShiftBuffers {
+        ShiftBuffers::default()
+    }
+
+    /// The number of bytes each vector can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.tms.capacity().min(self.tdi.capacity())
+    }
+
+    /// Resize both vectors to `num_bytes` and return them for writing.
+    #[cfg(feature = "std")]
+    pub(crate) fn resize(&mut self, num_bytes: usize) -> (&mut [u8], &mut [u8]) {
+        self.tms.resize(num_bytes, 0);
+        self.tdi.resize(num_bytes, 0);
+        (&mut self.tms, &mut self.tdi)
+    }
+
+    /// The `Shift` message of `num_bits` with the vectors in these buffers.
+    #[cfg(feature = "std")]
+    pub(crate) fn shift(&self, num_bits: u32) -> BorrowedMessage<'_> {
+        Message::Shift {
+            num_bits,
+            tms: &self.tms,
+            tdi: &self.tdi,
+        }
+    }
+}
+
+/// The maximum length of the vectors of a `Shift`, as announced by a server in its
+/// [`XvcInfo`].
+///
+/// The length counts the bytes of *each* of the TMS and TDI vectors, so a server announcing
+/// 2048 accepts shifts of up to `8 * 2048` bits with 4096 bytes of vectors in total. A shift
+/// is accepted if `num_bits.div_ceil(8)` does not exceed [`as_bytes`](VectorLen::as_bytes).
+///
+/// Converts from and to `u32` bytes, which is the value on the wire.
+///
+/// ```
+/// use xvc_protocol::VectorLen;
+///
+/// let len = VectorLen::from_bytes(2048);
+/// assert_eq!(len.as_bytes(), 2048);
+/// assert_eq!(len.max_bits(), 16384);
+/// assert_eq!(len, 2048);
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct VectorLen(u32);
+
+impl VectorLen {
+    /// A length of `bytes` bytes per vector.
+    pub const fn from_bytes(bytes: u32) -> VectorLen {
+        VectorLen(bytes)
+    }
+
+    /// The number of bytes per vector.
+    pub const fn as_bytes(self) -> u32 {
+        self.0
+    }
+
+    /// The highest `num_bits` of a `Shift` with vectors of this length.
+    pub const fn max_bits(self) -> u64 {
+        8 * self.0 as u64
+    }
+}
+
+impl From<u32> for VectorLen {
+    fn from(bytes: u32) -> VectorLen {
+        VectorLen::from_bytes(bytes)
+    }
+}
+
+impl From<VectorLen> for u32 {
+    fn from(len: VectorLen) -> u32 {
+        len.as_bytes()
+    }
+}
+
+impl PartialEq<u32> for VectorLen {
+    fn eq(&self, bytes: &u32) -> bool {
+        self.0 == *bytes
+    }
+}
+
+/// Formats the number of bytes, as on the wire.
+impl Display for VectorLen {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 /// Contains static information about the server capabilities that are transferred between
 /// client and server in the beginning.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct XvcInfo {
     version: Version,
-    max_vector_len: u32,
+    max_vector_len: VectorLen,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    capabilities: Vec<String>,
 }
 
 impl XvcInfo {
     /// Creates a new info object from version and the maximum receivable vector length.
-    pub fn new(version: Version, max_vector_len: u32) -> XvcInfo {
+    pub fn new(version: Version, max_vector_len: impl Into<VectorLen>) -> XvcInfo {
         XvcInfo {
             version,
-            max_vector_len,
+            max_vector_len: max_vector_len.into(),
+            capabilities: Vec::new(),
         }
     }
 
+    /// Adds capability tokens, which are advertised after the vector length, e.g.
+    /// `xvcServer_v1.0:2048:lock` for the token `lock`.
+    ///
+    /// Tokens must not be empty or contain `:` or whitespace, which separate them on the wire.
+    pub fn with_capabilities<S: Into<String>>(
+        mut self,
+        capabilities: impl IntoIterator<Item = S>,
+    ) -> XvcInfo {
+        self.capabilities
+            .extend(capabilities.into_iter().map(Into::into));
+        self
+    }
+
     /// The version of the protocol
     pub fn version(&self) -> Version {
         self.version
     }
 
-    /// the max width of the vector that can be shifted into the server
-    pub fn max_vector_len(&self) -> u32 {
+    /// The maximum length of each vector of a shift that the server accepts
+    pub fn max_vector_len(&self) -> VectorLen {
         self.max_vector_len
     }
+
+    /// The raw tokens that follow the vector length, as advertised by newer server variants.
+    /// Empty for the plain 1.0 format.
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
 }
 
 impl Default for XvcInfo {
     fn default() -> XvcInfo {
         XvcInfo {
             version: Version::default(),
-            max_vector_len: 10 * 1024 * 1024, // 10 MiB default
+            max_vector_len: VectorLen::from_bytes(10 * 1024 * 1024), // 10 MiB default
+            capabilities: Vec::new(),
         }
     }
 }
 
+/// A Response is transferred from the server to the client for each [`Message`].
+/// The format of a response depends on the message it answers, which therefore has to be known
+/// to decode it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Response {
+    /// Answers `GetInfo` with the capabilities of the server.
+    Info(XvcInfo),
+    /// Answers `SetTck` with the TCK period that the server actually applied.
+    TckPeriod(u32),
+    /// Answers `Shift` with the TDO vector, which has the same length as the TMS and TDI vectors.
+    Tdo(#[cfg_attr(feature = "serde", serde(with = "crate::serde_support::hex"))] Box<[u8]>),
+}
+
 /// Possible commands that are known to the XVC protocol.
 #[derive(Eq, PartialEq, Clone, Debug)]
 pub enum XvcCommand {
@@ -1,10 +1,11 @@
 /// Read and write implementations for the protocol messages
-use std::io::{self, Read, Write};
+use std::io::{self, BufRead, Read, Write};
 
 use crate::{
-    BorrowedMessage, Message, OwnedMessage, XvcCommand, XvcInfo,
-    codec::{ParseErr, SetTck, Shift},
-    error::ReadError,
+    BorrowedMessage, Message, OwnedMessage, Response, ShiftBuffers, ShiftVector, XvcCommand,
+    XvcInfo,
+    codec::{CMD_SHIFT, ParseErr, SetTck, Shift},
+    error::{EncodeError, ReadError},
 };
 
 /// Protocol decoder.
@@ -31,6 +32,8 @@ pub struct Decoder {
     max_buf: usize,
     /// Per-vector limit for `Shift` payloads, enforced by the codec parser.
     max_shift: usize,
+    /// Decode unrecognized commands as `Message::Unknown` instead of failing
+    lenient: bool,
 }
 
 impl Decoder {
@@ -48,10 +51,31 @@ impl Decoder {
             buf: Vec::new(),
             max_buf,
             max_shift,
+            lenient: false,
         }
     }
 
-    fn read_chunk(&mut self, reader: &mut impl Read) -> Result<(), ReadError> {
+    /// Create a decoder that decodes commands it does not recognize, e.g. vendor extensions,
+    /// as [`Message::Unknown`] instead of failing with `ReadError::InvalidCommand`.
+    ///
+    /// The name of an unknown command is everything up to the next `:`.
+    ///
+    /// ```rust
+    /// use xvc_protocol::{Message, rw::Decoder};
+    ///
+    /// let mut dec = Decoder::lenient(1024);
+    /// let msg = dec.read_message(&mut b"lock:".as_slice()).unwrap();
+    /// assert_eq!(msg, Message::Unknown { name: "lock".into() });
+    /// ```
+    pub fn lenient(max_shift: usize) -> Self {
+        Self {
+            lenient: true,
+            ..Self::new(max_shift)
+        }
+    }
+
+    /// Read more data into the buffer. Returns `false` if `reader` is at EOF.
+    fn read_chunk(&mut self, reader: &mut impl Read) -> Result<bool, ReadError> {
         let mut temp = [0u8; 1024];
         let read = loop {
             match reader.read(&mut temp) {
@@ -63,12 +87,7 @@ impl Decoder {
             }
         };
         if read == 0 {
-            // EOF with partial data or on an empty buffer — either way unexpected.
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "unexpected EOF while reading",
-            )
-            .into());
+            return Ok(false);
         }
 
         if self.max_buf < read + self.buf.len() {
@@ -79,15 +98,18 @@ impl Decoder {
         }
         self.buf.extend_from_slice(&temp[..read]);
 
-        Ok(())
+        Ok(true)
     }
 
     /// Read an `XvcInfo` frame from `reader`.
     ///
     /// This method incrementally fills the internal buffer from `reader` until
     /// a complete XVC server info frame is available and returns the parsed
-    /// `XvcInfo`. If EOF is encountered with partial data buffered, a
-    /// `ReadError::InvalidCommand` is returned.
+    /// `XvcInfo`. If EOF is encountered before any data, `ReadError::Disconnected`
+    /// is returned.
+    ///
+    /// Some servers do not terminate the info with a newline. If `reader` reaches EOF,
+    /// would block or times out after a complete info without newline, that info is returned.
     pub fn read_xvc_info(&mut self, reader: &mut impl Read) -> Result<XvcInfo, ReadError> {
         self.buf.clear();
         loop {
@@ -96,9 +118,15 @@ impl Decoder {
                 Ok(frame) => {
                     return Ok(frame);
                 }
-                Err(ParseErr::Incomplete) => {
-                    self.read_chunk(reader)?;
-                }
+                Err(ParseErr::Incomplete) => match self.read_chunk(reader) {
+                    Ok(true) => {}
+                    Ok(false) if self.buf.is_empty() => return Err(ReadError::Disconnected),
+                    Ok(false) => return Ok(XvcInfo::parse_line(&self.buf)?),
+                    Err(ReadError::IoError(e)) if !self.buf.is_empty() && is_stall(&e) => {
+                        return XvcInfo::parse_line(&self.buf).map_err(|_| e.into());
+                    }
+                    Err(e) => return Err(e),
+                },
                 Err(other) => return Err(other.into()),
             }
         }
@@ -108,8 +136,9 @@ impl Decoder {
     ///
     /// The decoder reads from `reader` until a full command and its payload
     /// are available, enforces negotiated limits (e.g. maximum shift buffer
-    /// size) and returns the parsed `Message`. On EOF with a partial
-    /// command present, a `ReadError::InvalidCommand` is returned.
+    /// size) and returns the parsed `Message`. On EOF before the first byte,
+    /// `ReadError::Disconnected` is returned; on EOF within a message,
+    /// `ReadError::TruncatedMessage` is returned.
     ///
     /// Example:
     ///
@@ -122,16 +151,17 @@ impl Decoder {
     /// ```
     pub fn read_message(&mut self, reader: &mut impl Read) -> Result<OwnedMessage, ReadError> {
         self.buf.clear();
-        let cmd = loop {
+        let (cmd, start) = loop {
             let mut slice: &[u8] = &self.buf;
             match XvcCommand::parse(&mut slice) {
-                Ok(cmd) => {
-                    let consumed = self.buf.len() - slice.len();
-                    self.buf.drain(..consumed);
-                    break cmd;
-                }
-                Err(ParseErr::Incomplete) => {
-                    self.read_chunk(reader)?;
+                Ok(cmd) => break (cmd, self.buf.len() - slice.len()),
+                Err(ParseErr::Incomplete) => self.fill(reader)?,
+                Err(ParseErr::InvalidCommand(_)) if self.lenient => {
+                    match XvcCommand::parse_unknown(&mut &*self.buf) {
+                        Ok(name) => return Ok(Message::Unknown { name }),
+                        Err(ParseErr::Incomplete) => self.fill(reader)?,
+                        Err(other) => return Err(other.into()),
+                    }
                 }
                 Err(other) => return Err(other.into()),
             }
@@ -139,35 +169,93 @@ impl Decoder {
         match cmd {
             XvcCommand::GetInfo => Ok(Message::GetInfo),
             XvcCommand::SetTck => loop {
-                let mut slice: &[u8] = &self.buf;
+                let mut slice: &[u8] = &self.buf[start..];
                 match SetTck::parse(&mut slice) {
                     Ok(tck) => {
                         return Ok(Message::SetTck {
                             period_ns: tck.period(),
                         });
                     }
-                    Err(ParseErr::Incomplete) => {
-                        self.read_chunk(reader)?;
-                    }
+                    Err(ParseErr::Incomplete) => self.fill(reader)?,
                     Err(other) => return Err(other.into()),
                 }
             },
             XvcCommand::Shift => loop {
-                let mut slice: &[u8] = &self.buf;
+                let mut slice: &[u8] = &self.buf[start..];
                 match Shift::parse(&mut slice, self.max_shift) {
                     Ok(shift) => {
                         let num_bits = shift.num_bits();
                         let (tms, tdi) = shift.into_tms_tdi();
                         return Ok(Message::Shift { num_bits, tms, tdi });
                     }
-                    Err(ParseErr::Incomplete) => {
-                        self.read_chunk(reader)?;
-                    }
+                    Err(ParseErr::Incomplete) => self.fill(reader)?,
                     Err(other) => return Err(other.into()),
                 }
             },
         }
     }
+
+    /// Read more data of a message, failing if `reader` is at EOF.
+    fn fill(&mut self, reader: &mut impl Read) -> Result<(), ReadError> {
+        if self.read_chunk(reader)? {
+            Ok(())
+        } else if self.buf.is_empty() {
+            Err(ReadError::Disconnected)
+        } else {
+            Err(ReadError::truncated_message(&self.buf))
+        }
+    }
+}
+
+/// The maximum number of bytes that [`resync`] discards before it gives up.
+pub const MAX_RESYNC_BYTES: usize = 64 * 1024;
+
+/// Skip to the next command in `reader`, e.g. after a malformed message.
+///
+/// Bytes are discarded until `reader` is positioned just before the name of a command. A name
+/// at the end of the buffered bytes of `reader` may still be incomplete, in which case the next
+/// read decides whether it is a command. Fails with `ReadError::TooManyBytes` if no command
+/// starts within [`MAX_RESYNC_BYTES`], and with `ReadError::Disconnected` if `reader` reaches
+/// EOF before one.
+///
+/// ```rust
+/// use xvc_protocol::{Message, OwnedMessage, rw::resync};
+///
+/// let mut stream = b"\xFF\xFEnot a command getinfo:".as_slice();
+/// resync(&mut stream).unwrap();
+/// assert_eq!(stream, b"getinfo:");
+/// assert_eq!(OwnedMessage::from_reader(&mut stream, 1024).unwrap(), Message::GetInfo);
+/// ```
+pub fn resync(reader: &mut impl BufRead) -> Result<(), ReadError> {
+    let mut skipped = 0;
+    while skipped < MAX_RESYNC_BYTES {
+        let buf = match reader.fill_buf() {
+            Ok([]) => return Err(ReadError::Disconnected),
+            Ok(buf) => buf,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let window = &buf[..buf.len().min(MAX_RESYNC_BYTES - skipped)];
+        if let Some(start) = XvcCommand::find(window) {
+            reader.consume(start);
+            return Ok(());
+        }
+        let len = window.len();
+        reader.consume(len);
+        skipped += len;
+    }
+    Err(ReadError::TooManyBytes {
+        max: MAX_RESYNC_BYTES,
+        need: skipped + 1,
+    })
+}
+
+/// Whether `error` means that no more data is going to arrive for now.
+fn is_stall(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
 }
 
 impl XvcInfo {
@@ -177,12 +265,15 @@ impl XvcInfo {
     /// This is the canonical representation sent by servers to announce
     /// capabilities to clients.
     pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
-        writeln!(
-            writer,
-            "xvcServer_v{}:{}",
-            self.version(),
-            self.max_vector_len()
-        )
+        let mut buf = [0; XvcInfo::MAX_ENCODED_LEN];
+        if let Ok(len) = self.encode(&mut buf) {
+            return writer.write_all(&buf[..len]);
+        }
+        // Only capabilities make the info longer than the stack buffer
+        let mut buf = vec![0; self.encoded_len()];
+        self.encode(&mut buf)
+            .expect("buffer is sized to the encoded length");
+        writer.write_all(&buf)
     }
 
     /// Read an `XvcInfo` from `reader` using an internal `Decoder`.
@@ -200,7 +291,7 @@ impl XvcInfo {
     }
 }
 
-impl Message<Box<[u8]>> {
+impl Message<ShiftVector> {
     /// Read a `Message` from `reader` using an internal `Decoder`.
     ///
     /// This is a convenience wrapper that constructs a `Decoder` configured
@@ -221,6 +312,15 @@ impl Message<Box<[u8]>> {
         Decoder::new(max_shift_bytes).read_message(reader)
     }
 
+    /// Like [`from_reader`](Self::from_reader), but returns commands that are not part of the
+    /// protocol as [`Message::Unknown`] instead of failing. See [`Decoder::lenient`].
+    pub fn from_reader_lenient(
+        reader: &mut impl Read,
+        max_shift_bytes: usize,
+    ) -> Result<OwnedMessage, ReadError> {
+        Decoder::lenient(max_shift_bytes).read_message(reader)
+    }
+
     /// Borrows this message into a [BorrowedMessage]
     pub fn borrow<'a>(&'a self) -> BorrowedMessage<'a> {
         match self {
@@ -233,10 +333,103 @@ impl Message<Box<[u8]>> {
                 tms,
                 tdi,
             },
+            Message::Unknown { name } => Message::Unknown { name: name.clone() },
         }
     }
 }
 
+impl<'a> Message<&'a [u8]> {
+    /// Read a `Message` from `reader`, decoding the vectors of a `Shift` message into `buffers`.
+    ///
+    /// Unlike [`Message::from_reader`], this reads exactly the bytes of one message and does not
+    /// allocate per message: the returned `Shift` borrows its vectors from `buffers`, which can
+    /// be reused for all messages of a connection. `GetInfo` and `SetTck` leave `buffers`
+    /// untouched. `Shift` vectors larger than `max_shift_bytes` are rejected with
+    /// `ReadError::TooManyBytes` before their payload is read.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use xvc_protocol::{BorrowedMessage, Message, ShiftBuffers};
+    ///
+    /// let mut data = b"shift:\x0c\x00\x00\x00\xAA\x0B\x11\x02".as_slice();
+    /// let mut buffers = ShiftBuffers::new();
+    /// let msg = BorrowedMessage::read_into(&mut data, 1024, &mut buffers).unwrap();
+    /// assert_eq!(
+    ///     msg,
+    ///     Message::Shift { num_bits: 12, tms: &[0xAA, 0x0B][..], tdi: &[0x11, 0x02][..] }
+    /// );
+    /// ```
+    pub fn read_into(
+        reader: &mut impl Read,
+        max_shift_bytes: usize,
+        buffers: &'a mut ShiftBuffers,
+    ) -> Result<BorrowedMessage<'a>, ReadError> {
+        // The longest command name followed by num_bits or the period
+        let mut header = [0; 12];
+        let mut len = 0;
+        let command = loop {
+            match XvcCommand::parse(&mut &header[..len]) {
+                Ok(command) => break command,
+                Err(ParseErr::Incomplete) => {
+                    if read_full(reader, &mut header[len..len + 1])? == 0 {
+                        return Err(if len == 0 {
+                            ReadError::Disconnected
+                        } else {
+                            ReadError::truncated_message(&header[..len])
+                        });
+                    }
+                    len += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+        if command == XvcCommand::GetInfo {
+            return Ok(Message::GetInfo);
+        }
+        let start = len;
+        len += read_full(reader, &mut header[start..start + 4])?;
+        if len < start + 4 {
+            return Err(ReadError::truncated_message(&header[..len]));
+        }
+        let mut value = [0; 4];
+        value.copy_from_slice(&header[start..len]);
+        let value = u32::from_le_bytes(value);
+        if command == XvcCommand::SetTck {
+            return Ok(Message::SetTck { period_ns: value });
+        }
+
+        let num_bytes = Shift::num_bytes(value, max_shift_bytes)?;
+        let (tms, tdi) = buffers.resize(num_bytes);
+        let mut read = read_full(reader, tms)?;
+        if read == num_bytes {
+            read += read_full(reader, tdi)?;
+        }
+        if read < 2 * num_bytes {
+            return Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::Shift),
+                read: len + read,
+                expected: len + 2 * num_bytes,
+            });
+        }
+        Ok(buffers.shift(value))
+    }
+}
+
+/// Read into `buf` until it is full or `reader` is at EOF and return the number of bytes read.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
 impl<B: AsRef<[u8]>> Message<B> {
     /// Serialize this `Message` to `writer` in the protocol command format.
     ///
@@ -245,24 +438,222 @@ impl<B: AsRef<[u8]>> Message<B> {
     /// - `Shift` is written as `shift:` followed by a 4-byte little-endian `num_bits`,
     ///   then the `tms` and `tdi` payload bytes
     ///
-    /// The function writes raw bytes and returns any I/O error encountered.
+    /// The bytes are the same as those of [`encode`](Message::encode). A `Shift` whose vectors
+    /// do not match its `num_bits` is rejected with an `InvalidInput` error before anything
+    /// is written. Returns any I/O error encountered.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        // Longer than the header of any message but `Unknown` with a long name
+        let mut header = [0; 16];
+        match self.encode_header(&mut header) {
+            Ok(len) => writer.write_all(&header[..len])?,
+            Err(EncodeError::BufferTooSmall { .. }) => {
+                let mut buf = vec![0; self.encoded_len()];
+                self.encode(&mut buf)?;
+                return writer.write_all(&buf);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        // The vectors of a `Shift` are written directly from the message
+        if let Message::Shift { tms, tdi, .. } = self {
+            writer.write_all(tms.as_ref())?;
+            writer.write_all(tdi.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+/// The vectors of a `Shift` message in the order they are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Vector {
+    Tms,
+    Tdi,
+}
+
+/// Writes a `Shift` message whose vectors are passed in chunks, e.g. as they are generated,
+/// so that the vectors never need to be in memory as a whole.
+///
+/// The header is written with the first chunk, followed by all TMS chunks and then all TDI
+/// chunks. A chunk that would make its vector longer than `num_bits.div_ceil(8)` bytes, or a
+/// TDI chunk before the TMS vector is complete, is rejected with an `InvalidInput` error
+/// holding an `EncodeError::ShiftLength`, and nothing is written. [`finish`](Self::finish)
+/// fails the same way if a vector is incomplete. As the peer still waits for the missing
+/// bytes, the stream cannot be used for other messages after that.
+///
+/// ```rust
+/// use xvc_protocol::{Message, rw::ShiftEncoder};
+///
+/// let mut encoder = ShiftEncoder::new(12, Vec::new());
+/// encoder.write_tms_chunk(&[0xAA])?;
+/// encoder.write_tms_chunk(&[0x0B])?;
+/// encoder.write_tdi_chunk(&[0x11, 0x02])?;
+/// let streamed = encoder.finish()?;
+///
+/// let mut expected = Vec::new();
+/// Message::try_shift(12, [0xAA, 0x0B], [0x11, 0x02])?.write_to(&mut expected)?;
+/// assert_eq!(streamed, expected);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct ShiftEncoder<W> {
+    pub(crate) writer: W,
+    num_bits: u32,
+    /// The number of bytes of the TMS and TDI vectors written so far
+    written: [usize; 2],
+    header_written: bool,
+}
+
+impl<W> ShiftEncoder<W> {
+    /// Create an encoder for a shift of `num_bits` into `writer`. Nothing is written yet.
+    pub fn new(num_bits: u32, writer: W) -> ShiftEncoder<W> {
+        ShiftEncoder {
+            writer,
+            num_bits,
+            written: [0; 2],
+            header_written: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Return the writer without checking that the vectors are complete.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn length_error(&self, [tms, tdi]: [usize; 2]) -> io::Error {
+        EncodeError::ShiftLength {
+            num_bits: self.num_bits,
+            tms,
+            tdi,
+        }
+        .into()
+    }
+
+    /// Count a chunk of `len` bytes of `vector`, failing if it does not fit the layout.
+    pub(crate) fn accept(&mut self, vector: Vector, len: usize) -> io::Result<()> {
+        let num_bytes = self.num_bits.div_ceil(8) as usize;
+        let mut written = self.written;
+        written[vector as usize] = written[vector as usize].saturating_add(len);
+        let tms_incomplete = vector == Vector::Tdi && len > 0 && written[0] < num_bytes;
+        if written[vector as usize] > num_bytes || tms_incomplete {
+            return Err(self.length_error(written));
+        }
+        self.written = written;
+        Ok(())
+    }
+
+    /// Fails unless both vectors are complete.
+    pub(crate) fn check_complete(&self) -> io::Result<()> {
+        let num_bytes = self.num_bits.div_ceil(8) as usize;
+        if self.written != [num_bytes; 2] {
+            return Err(self.length_error(self.written));
+        }
+        Ok(())
+    }
+
+    /// The header of the message, if it has not been taken yet.
+    pub(crate) fn take_header(&mut self) -> Option<[u8; CMD_SHIFT.len() + 4]> {
+        if self.header_written {
+            return None;
+        }
+        self.header_written = true;
+        let mut header = [0; CMD_SHIFT.len() + 4];
+        header[..CMD_SHIFT.len()].copy_from_slice(CMD_SHIFT);
+        header[CMD_SHIFT.len()..].copy_from_slice(&self.num_bits.to_le_bytes());
+        Some(header)
+    }
+}
+
+impl<W: Write> ShiftEncoder<W> {
+    /// Write the next chunk of the TMS vector.
+    pub fn write_tms_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.write_chunk(Vector::Tms, chunk)
+    }
+
+    /// Write the next chunk of the TDI vector. The TMS vector must be complete.
+    pub fn write_tdi_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.write_chunk(Vector::Tdi, chunk)
+    }
+
+    fn write_chunk(&mut self, vector: Vector, chunk: &[u8]) -> io::Result<()> {
+        self.accept(vector, chunk.len())?;
+        if let Some(header) = self.take_header() {
+            self.writer.write_all(&header)?;
+        }
+        self.writer.write_all(chunk)
+    }
+
+    /// Check that both vectors are complete and return the writer.
+    ///
+    /// Writes the header if no chunk was written, i.e. for a shift of zero bits.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.check_complete()?;
+        if let Some(header) = self.take_header() {
+            self.writer.write_all(&header)?;
+        }
+        Ok(self.writer)
+    }
+}
+
+impl Response {
+    /// Write this `Response` to `writer`.
+    ///
+    /// - `Info` is written in the server-info format (see [`XvcInfo::write_to`])
+    /// - `TckPeriod` is written as a 4-byte little-endian period
+    /// - `Tdo` is written as the raw TDO bytes
     pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
-        use crate::codec::{CMD_GET_INFO, CMD_SET_TCK, CMD_SHIFT};
         match self {
-            Message::GetInfo => writer.write_all(CMD_GET_INFO),
-            Message::SetTck {
-                period_ns: period_in_ns,
-            } => {
-                writer.write_all(CMD_SET_TCK)?;
-                writer.write_all(&period_in_ns.to_le_bytes())
+            Response::Info(info) => info.write_to(writer),
+            // The TDO vector is the whole encoding, so it is written without a copy
+            Response::Tdo(tdo) => writer.write_all(tdo),
+            Response::TckPeriod(_) => {
+                let mut buf = [0; 4];
+                let len = self
+                    .encode(&mut buf)
+                    .expect("buffer is sized to the encoded length");
+                writer.write_all(&buf[..len])
             }
-            Message::Shift { num_bits, tms, tdi } => {
-                writer.write_all(CMD_SHIFT)?;
-                writer.write_all(&num_bits.to_le_bytes())?;
-                writer.write_all(tms.as_ref())?;
-                writer.write_all(tdi.as_ref())
+        }
+    }
+
+    /// Read the response to `message` from `reader`.
+    ///
+    /// Responses to `SetTck` and `Shift` are read exactly, without consuming any following
+    /// bytes. If the stream ends before the response starts, `ReadError::Disconnected` is
+    /// returned; if it ends within the response, `ReadError::Truncated` is returned. The
+    /// response to `GetInfo` is read like [`XvcInfo::from_reader`].
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use xvc_protocol::{BorrowedMessage, Response};
+    ///
+    /// let shift = BorrowedMessage::Shift { num_bits: 12, tms: &[0, 0], tdi: &[0xAB, 0x0C] };
+    /// let mut data = b"\xAB\x0C".as_slice();
+    /// let response = Response::from_reader(&shift, &mut data).unwrap();
+    /// assert_eq!(response, Response::Tdo(Box::new([0xAB, 0x0C])));
+    /// ```
+    pub fn from_reader<B>(
+        message: &Message<B>,
+        reader: &mut impl Read,
+    ) -> Result<Response, ReadError> {
+        let Some(len) = Response::len_for(message) else {
+            return XvcInfo::from_reader(reader).map(Response::Info);
+        };
+        let mut buf = vec![0; len];
+        match read_full(reader, &mut buf)? {
+            0 if len > 0 => return Err(ReadError::Disconnected),
+            received if received < len => {
+                return Err(ReadError::Truncated {
+                    expected: len,
+                    received,
+                });
             }
+            _ => {}
         }
+        Ok(Response::parse(&mut buf.as_slice(), message)?)
     }
 }
 
@@ -292,6 +683,77 @@ mod test {
         assert_eq!(info.max_vector_len(), 32)
     }
 
+    #[test]
+    fn read_server_info_of_newer_minor_version() {
+        let mut data: &[u8] = b"xvcServer_v1.1:32\n";
+        let info = XvcInfo::from_reader(&mut data).unwrap();
+        assert_eq!(info.version(), crate::protocol::Version::new(1, 1));
+        assert!(info.version().is_supported());
+    }
+
+    #[test]
+    fn read_malformed_server_info() {
+        let mut empty: &[u8] = b"";
+        assert!(matches!(
+            XvcInfo::from_reader(&mut empty),
+            Err(ReadError::Disconnected)
+        ));
+        let mut prefix_only: &[u8] = b"xvcServer\n";
+        assert!(matches!(
+            XvcInfo::from_reader(&mut prefix_only),
+            Err(ReadError::InvalidCommand(_))
+        ));
+        let mut missing_digits: &[u8] = b"xvcServer_v:32\n";
+        assert!(matches!(
+            XvcInfo::from_reader(&mut missing_digits),
+            Err(ReadError::InvalidFormat(_))
+        ));
+        let mut non_numeric_len: &[u8] = b"xvcServer_v1.0:lots\n";
+        assert!(matches!(
+            XvcInfo::from_reader(&mut non_numeric_len),
+            Err(ReadError::InvalidFormat(_))
+        ));
+    }
+
+    /// Returns its data, then fails like a socket whose read timeout elapsed.
+    struct Stalling<'a>(&'a [u8]);
+
+    impl Read for Stalling<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn read_server_info_without_newline() {
+        let info = XvcInfo::from_reader(&mut Stalling(b"xvcServer_v1.0:2048")).unwrap();
+        assert_eq!(info.max_vector_len(), 2048);
+
+        let mut at_eof: &[u8] = b"xvcServer_v1.0:2048";
+        assert_eq!(
+            XvcInfo::from_reader(&mut at_eof).unwrap().max_vector_len(),
+            2048
+        );
+
+        let mut crlf = Cursor::new(b"xvcServer_v1.0:32\r\n");
+        assert_eq!(
+            XvcInfo::from_reader(&mut crlf).unwrap().max_vector_len(),
+            32
+        );
+    }
+
+    #[test]
+    fn read_incomplete_server_info_without_newline_fails() {
+        let result = XvcInfo::from_reader(&mut Stalling(b"xvcServer_v1.0:"));
+        assert!(matches!(
+            result,
+            Err(ReadError::IoError(e)) if e.kind() == io::ErrorKind::WouldBlock
+        ));
+    }
+
     #[test]
     fn read_getinfo() {
         let data = b"getinfo:".to_vec();
@@ -510,8 +972,12 @@ mod test {
         let data = b"settck:".to_vec();
         let mut cursor = Cursor::new(data);
         match OwnedMessage::from_reader(&mut cursor, DEFAULT_MAX_SHIFT_BYTES) {
-            Err(ReadError::IoError(_)) => {}
-            other => panic!("expected IoError (unexpected EOF), got {:?}", other),
+            Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::SetTck),
+                read: 7,
+                expected: 11,
+            }) => {}
+            other => panic!("expected TruncatedMessage, got {:?}", other),
         }
     }
 
@@ -521,8 +987,12 @@ mod test {
         data.extend_from_slice(&[0xAA, 0xBB]);
         let mut cursor = Cursor::new(data);
         match OwnedMessage::from_reader(&mut cursor, DEFAULT_MAX_SHIFT_BYTES) {
-            Err(ReadError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {}
-            other => panic!("expected UnexpectedEof, got {:?}", other),
+            Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::SetTck),
+                read: 9,
+                expected: 11,
+            }) => {}
+            other => panic!("expected TruncatedMessage, got {:?}", other),
         }
     }
 
@@ -593,8 +1063,12 @@ mod test {
 
         let mut cursor = Cursor::new(data);
         match OwnedMessage::from_reader(&mut cursor, DEFAULT_MAX_SHIFT_BYTES) {
-            Err(ReadError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {}
-            other => panic!("expected UnexpectedEof, got {:?}", other),
+            Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::Shift),
+                read: 8,
+                expected: 10,
+            }) => {}
+            other => panic!("expected TruncatedMessage, got {:?}", other),
         }
     }
 
@@ -607,8 +1081,12 @@ mod test {
 
         let mut cursor = Cursor::new(data);
         match OwnedMessage::from_reader(&mut cursor, DEFAULT_MAX_SHIFT_BYTES) {
-            Err(ReadError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {}
-            other => panic!("expected UnexpectedEof, got {:?}", other),
+            Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::Shift),
+                read: 11,
+                expected: 14,
+            }) => {}
+            other => panic!("expected TruncatedMessage, got {:?}", other),
         }
     }
 
@@ -621,11 +1099,302 @@ mod test {
 
         let mut cursor = Cursor::new(data);
         match OwnedMessage::from_reader(&mut cursor, DEFAULT_MAX_SHIFT_BYTES) {
-            Err(ReadError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {}
-            other => panic!("expected UnexpectedEof, got {:?}", other),
+            Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::Shift),
+                read: 11,
+                expected: 12,
+            }) => {}
+            other => panic!("expected TruncatedMessage, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_message_at_eof_is_disconnected() {
+        let mut empty: &[u8] = b"";
+        let mut decoder = Decoder::new(DEFAULT_MAX_SHIFT_BYTES);
+        assert!(matches!(
+            decoder.read_message(&mut empty),
+            Err(ReadError::Disconnected)
+        ));
+
+        let mut data: &[u8] = b"getinfo:";
+        decoder.read_message(&mut data).unwrap();
+        assert!(matches!(
+            decoder.read_message(&mut data),
+            Err(ReadError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn read_truncated_command_name() {
+        let mut data: &[u8] = b"sett";
+        let err = OwnedMessage::from_reader(&mut data, DEFAULT_MAX_SHIFT_BYTES).unwrap_err();
+        assert!(matches!(
+            err,
+            ReadError::TruncatedMessage {
+                command: None,
+                read: 4,
+                expected: 7,
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Connection closed after 4 bytes of an incomplete command"
+        );
+    }
+
+    #[test]
+    fn truncated_message_error_names_command() {
+        let mut data: &[u8] = b"shift:\x10\x00\x00\x00\xAA";
+        let err = OwnedMessage::from_reader(&mut data, DEFAULT_MAX_SHIFT_BYTES).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Connection closed after 11 of 14 bytes of a Shift message"
+        );
+    }
+
+    #[test]
+    fn io_error_is_source() {
+        use std::error::Error;
+
+        let err = ReadError::from(io::Error::from(io::ErrorKind::ConnectionReset));
+        let source = err.source().unwrap();
+        assert_eq!(
+            source.downcast_ref::<io::Error>().unwrap().kind(),
+            io::ErrorKind::ConnectionReset
+        );
+        assert!(ReadError::Disconnected.source().is_none());
+    }
+
+    #[test]
+    fn read_into_reuses_shift_buffers() {
+        let mut data = Vec::new();
+        BorrowedMessage::Shift {
+            num_bits: 64,
+            tms: &[0x11; 8],
+            tdi: &[0x22; 8],
+        }
+        .write_to(&mut data)
+        .unwrap();
+        BorrowedMessage::SetTck { period_ns: 100 }
+            .write_to(&mut data)
+            .unwrap();
+        BorrowedMessage::GetInfo.write_to(&mut data).unwrap();
+        BorrowedMessage::Shift {
+            num_bits: 4,
+            tms: &[0x03],
+            tdi: &[0x04],
+        }
+        .write_to(&mut data)
+        .unwrap();
+        let mut data = data.as_slice();
+
+        let mut buffers = ShiftBuffers::new();
+        let message = BorrowedMessage::read_into(&mut data, DEFAULT_MAX_SHIFT_BYTES, &mut buffers);
+        assert_eq!(
+            message.unwrap(),
+            Message::Shift {
+                num_bits: 64,
+                tms: &[0x11; 8][..],
+                tdi: &[0x22; 8][..],
+            }
+        );
+        let capacity = buffers.capacity();
+        assert!(capacity >= 8);
+
+        let message = BorrowedMessage::read_into(&mut data, DEFAULT_MAX_SHIFT_BYTES, &mut buffers);
+        assert_eq!(message.unwrap(), Message::SetTck { period_ns: 100 });
+        let message = BorrowedMessage::read_into(&mut data, DEFAULT_MAX_SHIFT_BYTES, &mut buffers);
+        assert_eq!(message.unwrap(), Message::GetInfo);
+        assert_eq!(
+            buffers.shift(64),
+            Message::Shift {
+                num_bits: 64,
+                tms: &[0x11; 8][..],
+                tdi: &[0x22; 8][..],
+            }
+        );
+
+        let message = BorrowedMessage::read_into(&mut data, DEFAULT_MAX_SHIFT_BYTES, &mut buffers);
+        assert_eq!(
+            message.unwrap(),
+            Message::Shift {
+                num_bits: 4,
+                tms: &[0x03][..],
+                tdi: &[0x04][..],
+            }
+        );
+        assert_eq!(buffers.capacity(), capacity);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn read_into_errors() {
+        let mut buffers = ShiftBuffers::new();
+        let mut empty: &[u8] = b"";
+        assert!(matches!(
+            BorrowedMessage::read_into(&mut empty, DEFAULT_MAX_SHIFT_BYTES, &mut buffers),
+            Err(ReadError::Disconnected)
+        ));
+
+        let mut data: &[u8] = b"shift:\x10\x00\x00\x00\xAA\xBB\x11";
+        assert!(matches!(
+            BorrowedMessage::read_into(&mut data, DEFAULT_MAX_SHIFT_BYTES, &mut buffers),
+            Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::Shift),
+                read: 13,
+                expected: 14,
+            })
+        ));
+
+        let mut data: &[u8] = b"settck:\x01";
+        assert!(matches!(
+            BorrowedMessage::read_into(&mut data, DEFAULT_MAX_SHIFT_BYTES, &mut buffers),
+            Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::SetTck),
+                read: 8,
+                expected: 11,
+            })
+        ));
+
+        let mut data: &[u8] = b"shift:\x00\x01\x00\x00";
+        let mut unused = ShiftBuffers::new();
+        assert!(matches!(
+            BorrowedMessage::read_into(&mut data, 16, &mut unused),
+            Err(ReadError::TooManyBytes { max: 16, need: 32 })
+        ));
+        assert_eq!(unused.capacity(), 0);
+
+        let mut data: &[u8] = b"shoot:";
+        assert!(matches!(
+            BorrowedMessage::read_into(&mut data, DEFAULT_MAX_SHIFT_BYTES, &mut buffers),
+            Err(ReadError::InvalidCommand(_))
+        ));
+    }
+
+    #[test]
+    fn write_to_matches_encode() {
+        let (tms, tdi) = (vec![0x5A; 64 * 1024], vec![0xC3; 64 * 1024]);
+        let messages: [BorrowedMessage; 6] = [
+            Message::GetInfo,
+            Message::SetTck {
+                period_ns: 0xDEAD_BEEF,
+            },
+            Message::Shift {
+                num_bits: 0,
+                tms: &[],
+                tdi: &[],
+            },
+            Message::Shift {
+                num_bits: 20,
+                tms: &[0x01, 0x02, 0x03],
+                tdi: &[0x0A, 0x0B, 0x0C],
+            },
+            Message::Shift {
+                num_bits: 8 * 64 * 1024,
+                tms: &tms,
+                tdi: &tdi,
+            },
+            Message::Unknown {
+                name: "a_command_name_longer_than_any_header".to_owned(),
+            },
+        ];
+        for message in messages {
+            let mut written = Vec::new();
+            message.write_to(&mut written).unwrap();
+            let mut encoded = vec![0; message.encoded_len()];
+            assert_eq!(message.encode(&mut encoded), Ok(written.len()));
+            assert_eq!(written, encoded);
+        }
+
+        let responses = [
+            Response::Info(XvcInfo::new(
+                crate::protocol::Version::new(255, 255),
+                u32::MAX,
+            )),
+            Response::TckPeriod(100),
+            Response::Tdo(Box::new([0xAB, 0xCD])),
+            Response::Tdo(tdi.clone().into_boxed_slice()),
+        ];
+        for response in responses {
+            let mut written = Vec::new();
+            response.write_to(&mut written).unwrap();
+            let mut encoded = vec![0; response.encoded_len()];
+            assert_eq!(response.encode(&mut encoded), Ok(written.len()));
+            assert_eq!(written, encoded);
         }
     }
 
+    #[test]
+    fn write_mismatched_shift_fails() {
+        let message = BorrowedMessage::Shift {
+            num_bits: 64,
+            tms: &[0; 4],
+            tdi: &[0; 8],
+        };
+        let mut out = Vec::new();
+        let err = message.write_to(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "Shift of 64 bits needs vectors of 8 bytes, but TMS has 4 and TDI 8"
+        );
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn read_error_into_io_error() {
+        let reset = io::Error::new(io::ErrorKind::ConnectionReset, "reset by peer");
+        let err = io::Error::from(ReadError::from(reset));
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(err.to_string(), "reset by peer");
+        assert!(err.get_ref().unwrap().downcast_ref::<ReadError>().is_none());
+
+        let err = io::Error::from(ReadError::InvalidCommand("foo".into()));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(
+            err.get_ref().unwrap().downcast_ref::<ReadError>(),
+            Some(ReadError::InvalidCommand(cmd)) if cmd == "foo"
+        ));
+
+        let mut data: &[u8] = b"settck:";
+        let err = io::Error::from(
+            OwnedMessage::from_reader(&mut data, DEFAULT_MAX_SHIFT_BYTES).unwrap_err(),
+        );
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_error_kind() {
+        assert_eq!(
+            ReadError::TooManyBytes { max: 1, need: 2 }.kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            ReadError::InvalidFormat("bad".into()).kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(ReadError::Disconnected.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            ReadError::Truncated {
+                expected: 4,
+                received: 1
+            }
+            .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn read_response_at_eof_is_disconnected() {
+        let message = BorrowedMessage::SetTck { period_ns: 100 };
+        let mut empty: &[u8] = b"";
+        assert!(matches!(
+            Response::from_reader(&message, &mut empty),
+            Err(ReadError::Disconnected)
+        ));
+    }
+
     #[test]
     fn read_shift_large_vectors() {
         let num_bits: u32 = 1000;
@@ -713,14 +1482,11 @@ mod test {
             tms: &[0xFFu8; 512],
             tdi: &[0xAAu8; 512],
         };
+        // The vectors of u32::MAX bits would be 512 MiB each
         let mut out = Vec::new();
-        cmd.write_to(&mut out).unwrap();
-
-        let mut expected = b"shift:".to_vec();
-        expected.extend_from_slice(&u32::MAX.to_le_bytes());
-        expected.extend_from_slice(&[0xFF; 512]);
-        expected.extend_from_slice(&[0xAA; 512]);
-        assert_eq!(out, expected);
+        let err = cmd.write_to(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(out.is_empty());
     }
 
     #[test]
@@ -738,8 +1504,8 @@ mod test {
         let data = b"".to_vec();
         let mut cursor = Cursor::new(data);
         match OwnedMessage::from_reader(&mut cursor, DEFAULT_MAX_SHIFT_BYTES) {
-            Err(ReadError::IoError(_)) => {}
-            other => panic!("expected IoError, got {:?}", other),
+            Err(ReadError::Disconnected) => {}
+            other => panic!("expected Disconnected, got {:?}", other),
         }
     }
 
@@ -763,6 +1529,99 @@ mod test {
         }
     }
 
+    #[test]
+    fn lenient_reads_unknown_command() {
+        let mut cursor = Cursor::new(b"lock:\x01".to_vec());
+        let message = OwnedMessage::from_reader_lenient(&mut cursor, DEFAULT_MAX_SHIFT_BYTES);
+        assert_eq!(
+            message.unwrap(),
+            Message::Unknown {
+                name: "lock".into()
+            }
+        );
+
+        // Known commands are unaffected
+        let mut cursor = Cursor::new(b"settck:\x64\x00\x00\x00".to_vec());
+        let message = OwnedMessage::from_reader_lenient(&mut cursor, DEFAULT_MAX_SHIFT_BYTES);
+        assert_eq!(message.unwrap(), Message::SetTck { period_ns: 100 });
+        // ... including a prefix that turns out to be unknown
+        let mut cursor = Cursor::new(b"shiftx:".to_vec());
+        let message = OwnedMessage::from_reader_lenient(&mut cursor, DEFAULT_MAX_SHIFT_BYTES);
+        assert_eq!(
+            message.unwrap(),
+            Message::Unknown {
+                name: "shiftx".into()
+            }
+        );
+    }
+
+    #[test]
+    fn lenient_rejects_invalid_names() {
+        for data in [&b":"[..], &[0xFF, b':'], &[b'x'; 100]] {
+            let mut cursor = Cursor::new(data);
+            match OwnedMessage::from_reader_lenient(&mut cursor, DEFAULT_MAX_SHIFT_BYTES) {
+                Err(ReadError::InvalidCommand(_)) => {}
+                other => panic!("expected InvalidCommand for {data:?}, got {:?}", other),
+            }
+        }
+        let mut cursor = Cursor::new(b"unlo".to_vec());
+        let result = OwnedMessage::from_reader_lenient(&mut cursor, DEFAULT_MAX_SHIFT_BYTES);
+        assert!(matches!(
+            result,
+            Err(ReadError::TruncatedMessage { command: None, .. })
+        ));
+    }
+
+    #[test]
+    fn write_unknown_command() {
+        let mut out = Vec::new();
+        let message = BorrowedMessage::Unknown {
+            name: "unlock".into(),
+        };
+        message.write_to(&mut out).unwrap();
+        assert_eq!(out, b"unlock:");
+        assert!(Response::from_reader(&message, &mut b"".as_slice()).is_err());
+    }
+
+    #[test]
+    fn resync_skips_garbage() {
+        let mut stream = Cursor::new(b"xxshiftx\x00\xFFgetinfo:".to_vec());
+        assert!(matches!(
+            OwnedMessage::from_reader(&mut stream.clone(), DEFAULT_MAX_SHIFT_BYTES),
+            Err(ReadError::InvalidCommand(_))
+        ));
+        resync(&mut stream).unwrap();
+        assert_eq!(stream.position(), 10);
+        let message = OwnedMessage::from_reader(&mut stream, DEFAULT_MAX_SHIFT_BYTES);
+        assert_eq!(message.unwrap(), Message::GetInfo);
+
+        // Garbage spanning several buffer refills
+        let mut data = vec![b'x'; 3000];
+        data.extend_from_slice(b"settck:\x01\x00\x00\x00");
+        let mut stream = io::BufReader::with_capacity(128, data.as_slice());
+        resync(&mut stream).unwrap();
+        let message = OwnedMessage::from_reader(&mut stream, DEFAULT_MAX_SHIFT_BYTES);
+        assert_eq!(message.unwrap(), Message::SetTck { period_ns: 1 });
+    }
+
+    #[test]
+    fn resync_gives_up() {
+        assert!(matches!(
+            resync(&mut b"no command".as_slice()),
+            Err(ReadError::Disconnected)
+        ));
+        let data = vec![0; MAX_RESYNC_BYTES + 8];
+        let mut stream = data.as_slice();
+        assert!(matches!(
+            resync(&mut stream),
+            Err(ReadError::TooManyBytes {
+                max: MAX_RESYNC_BYTES,
+                ..
+            })
+        ));
+        assert_eq!(stream.len(), 8);
+    }
+
     #[test]
     fn roundtrip_xvc_info() {
         let original = XvcInfo::new(crate::protocol::Version::new(1, 0), 8192);
@@ -808,8 +1667,8 @@ mod test {
         let num_bytes = (num_bits / 8) as usize;
         let original = OwnedMessage::Shift {
             num_bits,
-            tms: vec![0xAA; num_bytes].into_boxed_slice(),
-            tdi: vec![0x55; num_bytes].into_boxed_slice(),
+            tms: vec![0xAA; num_bytes].into(),
+            tdi: vec![0x55; num_bytes].into(),
         };
         let mut buffer = Vec::new();
         original.write_to(&mut buffer).unwrap();
@@ -820,6 +1679,73 @@ mod test {
         assert_eq!(parsed, original);
     }
 
+    /// Stream `tms` and `tdi` in chunks of the given sizes, repeated until the vectors end.
+    fn stream_shift(num_bits: u32, tms: &[u8], tdi: &[u8], sizes: &[usize]) -> Vec<u8> {
+        let mut encoder = ShiftEncoder::new(num_bits, Vec::new());
+        let mut sizes = sizes.iter().cycle();
+        for (vector, mut rest) in [(Vector::Tms, tms), (Vector::Tdi, tdi)] {
+            while !rest.is_empty() {
+                let (chunk, tail) = rest.split_at((*sizes.next().unwrap()).min(rest.len()));
+                match vector {
+                    Vector::Tms => encoder.write_tms_chunk(chunk).unwrap(),
+                    Vector::Tdi => encoder.write_tdi_chunk(chunk).unwrap(),
+                }
+                rest = tail;
+            }
+        }
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn streamed_shift_matches_write_to() {
+        for num_bits in [0u32, 1, 13, 64, 1000] {
+            let num_bytes = num_bits.div_ceil(8) as usize;
+            let tms: Vec<u8> = (0..num_bytes).map(|i| i as u8).collect();
+            let tdi: Vec<u8> = (0..num_bytes).map(|i| !i as u8).collect();
+            let mut expected = Vec::new();
+            Message::try_shift(num_bits, &tms[..], &tdi[..])
+                .unwrap()
+                .write_to(&mut expected)
+                .unwrap();
+            for sizes in [&[1][..], &[3, 0, 7], &[64, 1, 2], &[usize::MAX]] {
+                assert_eq!(
+                    stream_shift(num_bits, &tms, &tdi, sizes),
+                    expected,
+                    "{num_bits} bits in chunks of {sizes:?}"
+                );
+            }
+        }
+    }
+
+    fn is_shift_length(error: io::Error, expected: EncodeError) -> bool {
+        error.kind() == io::ErrorKind::InvalidInput
+            && error.get_ref().and_then(|e| e.downcast_ref()) == Some(&expected)
+    }
+
+    #[test]
+    fn streamed_shift_enforces_layout() {
+        let length = |tms, tdi| EncodeError::ShiftLength {
+            num_bits: 12,
+            tms,
+            tdi,
+        };
+        let mut encoder = ShiftEncoder::new(12, Vec::new());
+        encoder.write_tms_chunk(&[0xAA]).unwrap();
+        let error = encoder.write_tdi_chunk(&[0x11]).unwrap_err();
+        assert!(is_shift_length(error, length(1, 1)));
+        let error = encoder.write_tms_chunk(&[0x0B, 0x00]).unwrap_err();
+        assert!(is_shift_length(error, length(3, 0)));
+        // Rejected chunks are not written
+        assert_eq!(encoder.get_ref(), b"shift:\x0c\x00\x00\x00\xAA");
+
+        encoder.write_tms_chunk(&[0x0B]).unwrap();
+        encoder.write_tdi_chunk(&[0x11]).unwrap();
+        let error = encoder.write_tdi_chunk(&[0x02, 0x00]).unwrap_err();
+        assert!(is_shift_length(error, length(2, 3)));
+        let error = ShiftEncoder::new(12, Vec::new()).finish().unwrap_err();
+        assert!(is_shift_length(error, length(0, 0)));
+    }
+
     #[test]
     fn shift_num_bits_rounding() {
         let num_bits: u32 = 13;
@@ -939,4 +1865,63 @@ mod test {
             Message::SetTck { period_ns: 0x42 }
         ));
     }
+
+    #[test]
+    fn roundtrip_responses() {
+        let shift = BorrowedMessage::Shift {
+            num_bits: 20,
+            tms: &[0; 3],
+            tdi: &[0; 3],
+        };
+        let cases = [
+            (BorrowedMessage::GetInfo, Response::Info(XvcInfo::default())),
+            (
+                BorrowedMessage::SetTck { period_ns: 100 },
+                Response::TckPeriod(0xDEAD_BEEF),
+            ),
+            (shift, Response::Tdo(Box::new([0x12, 0x34, 0x05]))),
+        ];
+        for (message, response) in cases {
+            let mut out = Vec::new();
+            response.write_to(&mut out).unwrap();
+            let mut cursor = Cursor::new(out);
+            assert_eq!(
+                Response::from_reader(&message, &mut cursor).unwrap(),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn read_response_leaves_following_bytes() {
+        let message = BorrowedMessage::SetTck { period_ns: 100 };
+        let mut data: &[u8] = b"\x64\x00\x00\x00\x11\x01";
+        assert_eq!(
+            Response::from_reader(&message, &mut data).unwrap(),
+            Response::TckPeriod(100)
+        );
+        assert_eq!(data, b"\x11\x01");
+    }
+
+    #[test]
+    fn read_truncated_shift_response() {
+        let shift = BorrowedMessage::Shift {
+            num_bits: 32,
+            tms: &[0; 4],
+            tdi: &[0; 4],
+        };
+        let mut data: &[u8] = b"\x01\x02\x03";
+        let err = Response::from_reader(&shift, &mut data).unwrap_err();
+        assert!(matches!(
+            err,
+            ReadError::Truncated {
+                expected: 4,
+                received: 3
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Connection closed after 3 of 4 response bytes"
+        );
+    }
 }
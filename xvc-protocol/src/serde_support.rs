@@ -0,0 +1,187 @@
+//! Serialization with serde.
+//!
+//! TMS, TDI and TDO vectors are serialized as lowercase hex strings, and versions as
+//! `major.minor` strings:
+//!
+//! ```rust
+//! use xvc_protocol::{Message, OwnedMessage};
+//!
+//! let message: OwnedMessage = Message::Shift {
+//!     num_bits: 12,
+//!     tms: [0xAA, 0x0B].into(),
+//!     tdi: [0x11, 0x02].into(),
+//! };
+//! let json = serde_json::to_string(&message).unwrap();
+//! assert_eq!(json, r#"{"Shift":{"num_bits":12,"tms":"aa0b","tdi":"1102"}}"#);
+//! ```
+use alloc::string::String;
+use core::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+use crate::Version;
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Version {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let version = String::deserialize(deserializer)?;
+        Version::from_str(&version).map_err(D::Error::custom)
+    }
+}
+
+/// Byte vectors as hex strings, for use with `#[serde(with = ...)]`.
+pub(crate) mod hex {
+    use alloc::{string::String, vec::Vec};
+    use core::fmt::Write;
+
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<B: AsRef<[u8]>, S: Serializer>(
+        bytes: &B,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let bytes = bytes.as_ref();
+        let mut hex = String::with_capacity(2 * bytes.len());
+        for byte in bytes {
+            let _ = write!(hex, "{:02x}", byte);
+        }
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, B: From<Vec<u8>>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<B, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if !hex.len().is_multiple_of(2) {
+            return Err(D::Error::custom("hex string has an odd number of digits"));
+        }
+        let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+        hex.as_bytes()
+            .chunks(2)
+            .map(|pair| match (digit(pair[0]), digit(pair[1])) {
+                (Some(high), Some(low)) => Ok((high << 4) | low),
+                _ => Err(D::Error::custom("invalid hex digit")),
+            })
+            .collect::<Result<Vec<u8>, _>>()
+            .map(B::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Message, OwnedMessage, Response, Version, XvcInfo};
+
+    fn roundtrip(message: OwnedMessage) -> String {
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(
+            serde_json::from_str::<OwnedMessage>(&json).unwrap(),
+            message
+        );
+        json
+    }
+
+    #[test]
+    fn roundtrip_getinfo() {
+        assert_eq!(roundtrip(Message::GetInfo), r#""GetInfo""#);
+    }
+
+    #[test]
+    fn roundtrip_settck() {
+        assert_eq!(
+            roundtrip(Message::SetTck { period_ns: 100 }),
+            r#"{"SetTck":{"period_ns":100}}"#
+        );
+    }
+
+    #[test]
+    fn roundtrip_shift() {
+        let json = roundtrip(Message::Shift {
+            num_bits: 16,
+            tms: [0x00, 0xFF].into(),
+            tdi: [0xDE, 0xAD].into(),
+        });
+        assert_eq!(
+            json,
+            r#"{"Shift":{"num_bits":16,"tms":"00ff","tdi":"dead"}}"#
+        );
+    }
+
+    #[test]
+    fn roundtrip_shift_not_byte_aligned() {
+        let json = roundtrip(Message::Shift {
+            num_bits: 13,
+            tms: [0xAA, 0x1F].into(),
+            tdi: [0x55, 0x0E].into(),
+        });
+        assert_eq!(
+            json,
+            r#"{"Shift":{"num_bits":13,"tms":"aa1f","tdi":"550e"}}"#
+        );
+    }
+
+    #[test]
+    fn borrowed_message_serializes_like_owned() {
+        let message = Message::Shift {
+            num_bits: 8,
+            tms: [0x01].as_slice(),
+            tdi: [0xAB].as_slice(),
+        };
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"Shift":{"num_bits":8,"tms":"01","tdi":"ab"}}"#
+        );
+    }
+
+    #[test]
+    fn roundtrip_xvc_info_and_responses() {
+        let info = XvcInfo::new(Version::new(1, 0), 32);
+        let json = serde_json::to_string(&info).unwrap();
+        assert_eq!(json, r#"{"version":"1.0","max_vector_len":32}"#);
+        assert_eq!(serde_json::from_str::<XvcInfo>(&json).unwrap(), info);
+
+        let with_capabilities = info.clone().with_capabilities(["lock"]);
+        let json = serde_json::to_string(&with_capabilities).unwrap();
+        assert_eq!(
+            json,
+            r#"{"version":"1.0","max_vector_len":32,"capabilities":["lock"]}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<XvcInfo>(&json).unwrap(),
+            with_capabilities
+        );
+
+        for response in [
+            Response::Info(info),
+            Response::TckPeriod(100),
+            Response::Tdo(Box::new([0x12, 0x34])),
+        ] {
+            let json = serde_json::to_string(&response).unwrap();
+            assert_eq!(serde_json::from_str::<Response>(&json).unwrap(), response);
+        }
+    }
+
+    #[test]
+    fn invalid_hex_is_rejected() {
+        for json in [
+            r#"{"Shift":{"num_bits":8,"tms":"0","tdi":"00"}}"#,
+            r#"{"Shift":{"num_bits":8,"tms":"zz","tdi":"00"}}"#,
+            r#"{"Shift":{"num_bits":8,"tms":"+f","tdi":"00"}}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<OwnedMessage>(json).is_err(),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_version_is_rejected() {
+        assert!(serde_json::from_str::<Version>(r#""1""#).is_err());
+    }
+}
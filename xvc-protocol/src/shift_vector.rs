@@ -0,0 +1,224 @@
+//! Owned TMS and TDI vectors that keep short vectors out of the allocator.
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    borrow::Borrow,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::{Deref, DerefMut},
+};
+
+/// The number of bytes a [`ShiftVector`] stores inline, i.e. shifts of up to 128 bits.
+pub const INLINE_LEN: usize = 16;
+
+/// The owned TMS or TDI vector of an [`OwnedMessage`](crate::OwnedMessage).
+///
+/// IR scans, TAP state changes and most register accesses shift only a few bits. Vectors of
+/// up to [`INLINE_LEN`] bytes are therefore stored inline instead of in their own heap
+/// allocation; longer vectors are boxed. The representation is not observable: a
+/// `ShiftVector` dereferences to `[u8]`, and equality and hashing only consider the bytes.
+///
+/// ```
+/// use xvc_protocol::ShiftVector;
+///
+/// let tms = ShiftVector::from([0x1F, 0x00]);
+/// assert_eq!(tms, [0x1F, 0x00]);
+/// assert_eq!(tms.len(), 2);
+/// assert!(tms.is_inline());
+/// assert!(!ShiftVector::from(vec![0; 1024]).is_inline());
+/// ```
+#[derive(Clone)]
+pub struct ShiftVector(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Inline { len: u8, bytes: [u8; INLINE_LEN] },
+    Heap(Box<[u8]>),
+}
+
+impl ShiftVector {
+    /// Create an empty `ShiftVector`.
+    pub const fn new() -> ShiftVector {
+        ShiftVector(Repr::Inline {
+            len: 0,
+            bytes: [0; INLINE_LEN],
+        })
+    }
+
+    /// Whether the bytes are stored inline rather than in a heap allocation.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Inline { len, bytes } => &bytes[..*len as usize],
+            Repr::Heap(bytes) => bytes,
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        match &mut self.0 {
+            Repr::Inline { len, bytes } => &mut bytes[..*len as usize],
+            Repr::Heap(bytes) => bytes,
+        }
+    }
+
+    pub fn into_boxed_slice(self) -> Box<[u8]> {
+        match self.0 {
+            Repr::Inline { .. } => self.as_slice().into(),
+            Repr::Heap(bytes) => bytes,
+        }
+    }
+}
+
+impl Default for ShiftVector {
+    fn default() -> ShiftVector {
+        ShiftVector::new()
+    }
+}
+
+impl Deref for ShiftVector {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl DerefMut for ShiftVector {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+impl AsRef<[u8]> for ShiftVector {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsMut<[u8]> for ShiftVector {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+impl Borrow<[u8]> for ShiftVector {
+    fn borrow(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl fmt::Debug for ShiftVector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+impl PartialEq for ShiftVector {
+    fn eq(&self, other: &ShiftVector) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for ShiftVector {}
+
+impl PartialEq<[u8]> for ShiftVector {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<const N: usize> PartialEq<[u8; N]> for ShiftVector {
+    fn eq(&self, other: &[u8; N]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl Hash for ShiftVector {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl From<&[u8]> for ShiftVector {
+    fn from(value: &[u8]) -> ShiftVector {
+        if value.len() <= INLINE_LEN {
+            let mut bytes = [0; INLINE_LEN];
+            bytes[..value.len()].copy_from_slice(value);
+            ShiftVector(Repr::Inline {
+                len: value.len() as u8,
+                bytes,
+            })
+        } else {
+            ShiftVector(Repr::Heap(value.into()))
+        }
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for ShiftVector {
+    fn from(value: [u8; N]) -> ShiftVector {
+        ShiftVector::from(&value[..])
+    }
+}
+
+impl From<Vec<u8>> for ShiftVector {
+    fn from(value: Vec<u8>) -> ShiftVector {
+        if value.len() <= INLINE_LEN {
+            ShiftVector::from(value.as_slice())
+        } else {
+            ShiftVector(Repr::Heap(value.into_boxed_slice()))
+        }
+    }
+}
+
+/// Keeps the existing allocation, regardless of the length.
+impl From<Box<[u8]>> for ShiftVector {
+    fn from(value: Box<[u8]>) -> ShiftVector {
+        ShiftVector(Repr::Heap(value))
+    }
+}
+
+impl From<ShiftVector> for Box<[u8]> {
+    fn from(value: ShiftVector) -> Box<[u8]> {
+        value.into_boxed_slice()
+    }
+}
+
+impl From<ShiftVector> for Vec<u8> {
+    fn from(value: ShiftVector) -> Vec<u8> {
+        value.into_boxed_slice().into_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn representation_depends_on_length() {
+        for len in [0, 1, INLINE_LEN] {
+            let bytes: Vec<u8> = (0..len as u8).collect();
+            let vector = ShiftVector::from(bytes.as_slice());
+            assert!(vector.is_inline(), "{len} bytes");
+            assert_eq!(*vector, *bytes);
+            assert_eq!(ShiftVector::from(bytes.clone()), vector);
+        }
+        let bytes = alloc::vec![0xA5; INLINE_LEN + 1];
+        assert!(!ShiftVector::from(bytes.as_slice()).is_inline());
+        assert!(!ShiftVector::from(bytes.clone()).is_inline());
+        assert_eq!(Vec::from(ShiftVector::from(bytes.clone())), bytes);
+    }
+
+    #[test]
+    fn equality_ignores_representation() {
+        let boxed = ShiftVector::from(Box::<[u8]>::from([1, 2, 3]));
+        let inline = ShiftVector::from([1, 2, 3]);
+        assert!(!boxed.is_inline());
+        assert_eq!(boxed, inline);
+        let mut inline = inline;
+        inline[2] = 4;
+        assert_eq!(inline, [1, 2, 4]);
+        assert_ne!(boxed, inline);
+    }
+}
@@ -1,6 +1,6 @@
 //! [`tokio_util::codec`] implementations for the XVC protocol.
 //!
-//! This module provides [`MessageDecoder`] and [`XvcInfoDecoder`], which implement
+//! This module provides [`MessageDecoder`], [`XvcInfoDecoder`] and [`ResponseDecoder`], which implement
 //! [`tokio_util::codec::Decoder`] and can be used with [`tokio_util::codec::FramedRead`]
 //! to drive async XVC message parsing over a [`tokio::net::TcpStream`] (or any other
 //! [`tokio::io::AsyncRead`] source).
@@ -47,8 +47,9 @@ use bytes::{Buf, BytesMut};
 use tokio_util::codec::Decoder;
 
 use crate::{
-    Message, XvcCommand, XvcInfo,
+    BorrowedMessage, Message, Response, ShiftBuffers, XvcCommand, XvcInfo,
     codec::{ParseErr, SetTck, Shift},
+    crc,
     error::ReadError,
 };
 
@@ -63,29 +64,137 @@ use crate::{
 /// [`ReadError::TooManyBytes`] error.
 pub struct MessageDecoder {
     max_shift: usize,
+    /// Decode unrecognized commands as `Message::Unknown` instead of failing
+    lenient: bool,
+    crc: Crc,
+}
+
+/// The state of the [`crc`] extension on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Crc {
+    Off,
+    /// Advertised to the client, which has not enabled it (yet)
+    Offered,
+    Enabled,
 }
 
 impl MessageDecoder {
     /// Create a new decoder.
     ///
     /// `max_shift` is the per-vector byte limit for `Shift` payloads (each of
-    /// TMS and TDI independently). Should match the `max_vector_size` advertised
-    /// via [`XvcInfo`].
+    /// TMS and TDI independently). Should match the
+    /// [`VectorLen`](crate::VectorLen) advertised via [`XvcInfo`].
     pub fn new(max_shift: usize) -> Self {
-        Self { max_shift }
+        Self {
+            max_shift,
+            lenient: false,
+            crc: Crc::Off,
+        }
     }
-}
 
-impl Decoder for MessageDecoder {
-    type Item = Message;
-    type Error = ReadError;
+    /// Create a decoder that decodes commands it does not recognize as [`Message::Unknown`]
+    /// instead of failing, like [`rw::Decoder::lenient`](crate::rw::Decoder::lenient).
+    pub fn lenient(max_shift: usize) -> Self {
+        Self {
+            max_shift,
+            lenient: true,
+            crc: Crc::Off,
+        }
+    }
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+    /// Accept the command `crc:` that enables the [`crc`] extension, for servers that advertise
+    /// it. The command is decoded as `Message::Unknown` with the name [`crc::CAPABILITY`]; all
+    /// `Shift` messages after it must be followed by their CRC.
+    pub fn offer_crc(&mut self) {
+        if self.crc == Crc::Off {
+            self.crc = Crc::Offered;
+        }
+    }
+
+    /// Whether the client enabled the [`crc`] extension, i.e. whether TDO responses must be
+    /// followed by their CRC.
+    pub fn crc_enabled(&self) -> bool {
+        self.crc == Crc::Enabled
+    }
+
+    /// Decode the command `crc:` if the extension was offered. Returns `None` if `src` holds a
+    /// different command.
+    fn decode_crc_enable(&mut self, src: &mut BytesMut) -> Option<Option<String>> {
+        if self.crc != Crc::Offered {
+            return None;
+        }
+        if src.starts_with(crc::CMD_ENABLE) {
+            src.advance(crc::CMD_ENABLE.len());
+            self.crc = Crc::Enabled;
+            Some(Some(crc::CAPABILITY.into()))
+        } else if crc::CMD_ENABLE.starts_with(src) {
+            Some(None)
+        } else {
+            None
+        }
+    }
+
+    /// The length of the `len` bytes long `msg` including its CRC, if the extension is enabled
+    /// and `msg` is a shift. Returns `None` if the CRC is incomplete; a `msg` with a wrong CRC is
+    /// consumed.
+    fn check_crc<B: AsRef<[u8]>>(
+        &self,
+        src: &mut BytesMut,
+        len: usize,
+        msg: &Message<B>,
+    ) -> Result<Option<usize>, ReadError> {
+        let Message::Shift { num_bits, tms, tdi } = msg else {
+            return Ok(Some(len));
+        };
+        if self.crc != Crc::Enabled {
+            return Ok(Some(len));
+        }
+        let expected = crc::shift_crc(*num_bits, tms.as_ref(), tdi.as_ref());
+        match crc::check(&mut &src[len..], expected) {
+            Ok(()) => Ok(Some(len + crc::CRC_LEN)),
+            Err(ParseErr::Incomplete) => Ok(None),
+            Err(e) => {
+                src.advance(len + crc::CRC_LEN);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Decode the name of an unknown command, if lenient.
+    fn decode_unknown(&self, src: &mut BytesMut) -> Result<Option<String>, ReadError> {
+        let mut slice: &[u8] = src;
+        match XvcCommand::parse_unknown(&mut slice) {
+            Ok(name) => {
+                let consumed = src.len() - slice.len();
+                src.advance(consumed);
+                Ok(Some(name))
+            }
+            Err(ParseErr::Incomplete) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Like [`Decoder::decode`], but decodes the vectors of a `Shift` message into `buffers`
+    /// instead of allocating them.
+    ///
+    /// `GetInfo` and `SetTck` leave `buffers` untouched.
+    pub fn decode_into<'a>(
+        &mut self,
+        src: &mut BytesMut,
+        buffers: &'a mut ShiftBuffers,
+    ) -> Result<Option<BorrowedMessage<'a>>, ReadError> {
+        if let Some(name) = self.decode_crc_enable(src) {
+            return Ok(name.map(|name| Message::Unknown { name }));
+        }
         let mut slice: &[u8] = src;
 
         let cmd = match XvcCommand::parse(&mut slice) {
             Ok(cmd) => cmd,
             Err(ParseErr::Incomplete) => return Ok(None),
+            Err(ParseErr::InvalidCommand(_)) if self.lenient => {
+                let name = self.decode_unknown(src)?;
+                return Ok(name.map(|name| Message::Unknown { name }));
+            }
             Err(e) => return Err(e.into()),
         };
 
@@ -98,23 +207,91 @@ impl Decoder for MessageDecoder {
                 Err(ParseErr::Incomplete) => return Ok(None),
                 Err(e) => return Err(e.into()),
             },
-            XvcCommand::Shift => match Shift::parse(&mut slice, self.max_shift) {
-                Ok(shift) => {
-                    let num_bits = shift.num_bits();
-                    let (tms, tdi) = shift.into_tms_tdi();
-                    Message::Shift { num_bits, tms, tdi }
+            XvcCommand::Shift => {
+                let num_bits = match Shift::parse_num_bits(&mut slice) {
+                    Ok(num_bits) => num_bits,
+                    Err(ParseErr::Incomplete) => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                };
+                let num_bytes = Shift::num_bytes(num_bits, self.max_shift)?;
+                if slice.len() < 2 * num_bytes {
+                    return Ok(None);
                 }
-                Err(ParseErr::Incomplete) => return Ok(None),
-                Err(e) => return Err(e.into()),
-            },
+                let (tms, tdi) = buffers.resize(num_bytes);
+                tms.copy_from_slice(&slice[..num_bytes]);
+                tdi.copy_from_slice(&slice[num_bytes..2 * num_bytes]);
+                slice = &slice[2 * num_bytes..];
+                buffers.shift(num_bits)
+            }
         };
 
         let consumed = src.len() - slice.len();
+        let Some(consumed) = self.check_crc(src, consumed, &msg)? else {
+            return Ok(None);
+        };
         src.advance(consumed);
         Ok(Some(msg))
     }
 }
 
+impl Decoder for MessageDecoder {
+    type Item = Message;
+    type Error = ReadError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(name) = self.decode_crc_enable(src) {
+            return Ok(name.map(|name| Message::Unknown { name }));
+        }
+        let mut slice: &[u8] = src;
+
+        let msg = match Message::parse(&mut slice, self.max_shift) {
+            Ok(msg) => msg,
+            Err(ParseErr::Incomplete) => return Ok(None),
+            Err(ParseErr::InvalidCommand(_)) if self.lenient => {
+                let name = self.decode_unknown(src)?;
+                return Ok(name.map(|name| Message::Unknown { name }));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let consumed = src.len() - slice.len();
+        let Some(consumed) = self.check_crc(src, consumed, &msg)? else {
+            return Ok(None);
+        };
+        src.advance(consumed);
+        Ok(Some(msg))
+    }
+
+    /// Fails with [`ReadError::TruncatedMessage`] if the stream ends within a message.
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(msg) => Ok(Some(msg)),
+            None if src.is_empty() => Ok(None),
+            None => Err(ReadError::truncated_message(src)),
+        }
+    }
+}
+
+/// Discard bytes from the start of `src` up to the next command, e.g. after [`MessageDecoder`]
+/// failed on a malformed message.
+///
+/// Returns `false` if `src` holds no command, in which case all bytes are discarded. Like
+/// [`rw::resync`](crate::rw::resync), a command name at the end of `src` may be incomplete.
+/// A malformed message may start with a valid command name, so skip at least its first byte
+/// before resynchronizing.
+pub fn resync(src: &mut BytesMut) -> bool {
+    match XvcCommand::find(src) {
+        Some(start) => {
+            src.advance(start);
+            true
+        }
+        None => {
+            src.clear();
+            false
+        }
+    }
+}
+
 /// Decodes an [`XvcInfo`] frame from an inbound byte stream (server → client direction).
 ///
 /// Intended for use with [`tokio_util::codec::FramedRead`] on the client side,
@@ -138,18 +315,169 @@ impl Decoder for XvcInfoDecoder {
             Err(e) => Err(e.into()),
         }
     }
+
+    /// Also accepts an info without newline at the end of the stream, as sent by some servers.
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(info) => Ok(Some(info)),
+            None if src.is_empty() => Ok(None),
+            None => {
+                let info = XvcInfo::parse_line(src)?;
+                src.clear();
+                Ok(Some(info))
+            }
+        }
+    }
+}
+
+/// Decodes the [`Response`] to a single message (server → client direction).
+///
+/// The format of a response depends on the message it answers, so a new decoder is created for
+/// every message that was sent. Unlike the other decoders, reaching the end of the stream before
+/// the response is complete is always an error, even if no byte of it was received.
+pub struct ResponseDecoder {
+    /// The message that is answered, without its vectors
+    message: Message<()>,
+    /// Whether a TDO response is followed by its CRC
+    crc: bool,
+}
+
+impl ResponseDecoder {
+    /// Create a decoder for the response to `message`.
+    pub fn new<B>(message: &Message<B>) -> Self {
+        let message = match message {
+            Message::GetInfo => Message::GetInfo,
+            Message::SetTck { period_ns } => Message::SetTck {
+                period_ns: *period_ns,
+            },
+            Message::Shift { num_bits, .. } => Message::Shift {
+                num_bits: *num_bits,
+                tms: (),
+                tdi: (),
+            },
+            Message::Unknown { name } => Message::Unknown { name: name.clone() },
+        };
+        Self {
+            message,
+            crc: false,
+        }
+    }
+
+    /// Expect TDO responses to be followed by their CRC, after the [`crc`] extension was
+    /// enabled. Responses to other messages are not affected.
+    pub fn with_crc(self) -> Self {
+        Self { crc: true, ..self }
+    }
+
+    /// The length of the response, if it is known in advance.
+    fn len(&self) -> Option<usize> {
+        let len = Response::len_for(&self.message)?;
+        match self.message {
+            Message::Shift { .. } if self.crc => Some(len + crc::CRC_LEN),
+            _ => Some(len),
+        }
+    }
+}
+
+impl Decoder for ResponseDecoder {
+    type Item = Response;
+    type Error = ReadError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let mut slice: &[u8] = src;
+        let response = match Response::parse(&mut slice, &self.message) {
+            Ok(response) => response,
+            Err(ParseErr::Incomplete) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if let Response::Tdo(tdo) = &response
+            && self.crc
+        {
+            match crc::check(&mut slice, crc::crc32(tdo)) {
+                Ok(()) => {}
+                Err(ParseErr::Incomplete) => return Ok(None),
+                Err(e) => {
+                    let consumed = src.len() - slice.len();
+                    src.advance(consumed);
+                    return Err(e.into());
+                }
+            }
+        }
+        let consumed = src.len() - slice.len();
+        src.advance(consumed);
+        Ok(Some(response))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(response) = self.decode(src)? {
+            return Ok(Some(response));
+        }
+        if src.is_empty() {
+            return Err(ReadError::Disconnected);
+        }
+        Err(match self.len() {
+            Some(expected) => ReadError::Truncated {
+                expected,
+                received: src.len(),
+            },
+            None => {
+                // Some servers do not terminate the info with a newline
+                let info = XvcInfo::parse_line(src)?;
+                src.clear();
+                return Ok(Some(Response::Info(info)));
+            }
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use bytes::BytesMut;
+    use bytes::{Buf, BytesMut};
     use tokio_util::codec::Decoder;
 
-    use super::{MessageDecoder, XvcInfoDecoder};
-    use crate::{Message, Version, XvcInfo};
+    use super::{MessageDecoder, ResponseDecoder, XvcInfoDecoder};
+    use crate::{
+        Message, Response, ShiftBuffers, Version, XvcCommand, XvcInfo, crc, error::ReadError,
+    };
 
     // MARK: MessageDecoder
 
+    #[test]
+    fn decode_into_reuses_shift_buffers() {
+        let mut dec = MessageDecoder::new(1024);
+        let mut buffers = ShiftBuffers::new();
+        let mut buf = BytesMut::from(&b"shift:\x10\x00\x00\x00\xAA\xBB\x11"[..]);
+        assert_eq!(dec.decode_into(&mut buf, &mut buffers).unwrap(), None);
+        assert_eq!(buffers.capacity(), 0);
+
+        buf.extend_from_slice(b"\x22settck:\x64\x00\x00\x00");
+        assert_eq!(
+            dec.decode_into(&mut buf, &mut buffers).unwrap(),
+            Some(Message::Shift {
+                num_bits: 16,
+                tms: &[0xAA, 0xBB][..],
+                tdi: &[0x11, 0x22][..],
+            })
+        );
+        assert_eq!(
+            dec.decode_into(&mut buf, &mut buffers).unwrap(),
+            Some(Message::SetTck { period_ns: 100 })
+        );
+        assert!(buf.is_empty());
+        assert!(buffers.capacity() >= 2);
+    }
+
+    #[test]
+    fn decode_into_rejects_oversized_shift() {
+        let mut dec = MessageDecoder::new(1);
+        let mut buffers = ShiftBuffers::new();
+        let mut buf = BytesMut::from(&b"shift:\x10\x00\x00\x00"[..]);
+        assert!(matches!(
+            dec.decode_into(&mut buf, &mut buffers),
+            Err(ReadError::TooManyBytes { max: 1, need: 2 })
+        ));
+    }
+
     #[test]
     fn decode_getinfo() {
         let mut dec = MessageDecoder::new(1024);
@@ -218,6 +546,33 @@ mod tests {
         assert_eq!(&buf[..], b"getinfo:");
     }
 
+    #[test]
+    fn decode_unknown_command() {
+        let mut buf = BytesMut::from(&b"lock:getinfo:"[..]);
+        assert!(matches!(
+            MessageDecoder::new(1024).decode(&mut buf),
+            Err(ReadError::InvalidCommand(_))
+        ));
+
+        let mut dec = MessageDecoder::lenient(1024);
+        let mut buf = BytesMut::from(&b"lo"[..]);
+        assert_eq!(dec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"ck:getinfo:");
+        let lock = Message::Unknown {
+            name: "lock".into(),
+        };
+        assert_eq!(dec.decode(&mut buf).unwrap(), Some(lock));
+        assert_eq!(dec.decode(&mut buf).unwrap(), Some(Message::GetInfo));
+
+        let mut buffers = ShiftBuffers::new();
+        let mut buf = BytesMut::from(&b"lock:"[..]);
+        assert!(matches!(
+            dec.decode_into(&mut buf, &mut buffers).unwrap(),
+            Some(Message::Unknown { name }) if name == "lock"
+        ));
+        assert!(buf.is_empty());
+    }
+
     #[test]
     fn decode_too_many_bytes() {
         let max_shift = 2;
@@ -232,6 +587,65 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn resync_after_invalid_command() {
+        let mut dec = MessageDecoder::new(2);
+        let mut buf = BytesMut::from(&b"shift:\x20\x00\x00\x00\xFF"[..]);
+        assert!(dec.decode(&mut buf).is_err());
+        buf.advance(1);
+        assert!(!super::resync(&mut buf));
+        assert!(buf.is_empty());
+
+        buf.extend_from_slice(b"\x00\x00getinfo:");
+        assert!(dec.decode(&mut buf).is_err());
+        assert!(super::resync(&mut buf));
+        assert_eq!(&buf[..], b"getinfo:");
+        assert_eq!(dec.decode(&mut buf).unwrap(), Some(Message::GetInfo));
+    }
+
+    #[test]
+    fn decode_crc_protected_shifts() {
+        let mut dec = MessageDecoder::new(1024);
+        let mut buf = BytesMut::from(&b"crc:"[..]);
+        assert!(dec.decode(&mut buf).is_err());
+
+        dec.offer_crc();
+        let mut buf = BytesMut::from(&b"cr"[..]);
+        assert_eq!(dec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"c:getinfo:");
+        assert_eq!(
+            dec.decode(&mut buf).unwrap(),
+            Some(Message::Unknown { name: "crc".into() })
+        );
+        assert!(dec.crc_enabled());
+        assert_eq!(dec.decode(&mut buf).unwrap(), Some(Message::GetInfo));
+
+        let crc = crc::shift_crc(8, &[0xAA], &[0xBB]).to_le_bytes();
+        let mut buf = BytesMut::from(&b"shift:\x08\x00\x00\x00\xAA\xBB"[..]);
+        buf.extend_from_slice(&crc[..2]);
+        assert_eq!(dec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&crc[2..]);
+        let mut buffers = ShiftBuffers::new();
+        assert_eq!(
+            dec.decode_into(&mut buf, &mut buffers).unwrap(),
+            Some(Message::Shift {
+                num_bits: 8,
+                tms: &[0xAA][..],
+                tdi: &[0xBB][..],
+            })
+        );
+        assert!(buf.is_empty());
+
+        let mut buf = BytesMut::from(&b"shift:\x08\x00\x00\x00\xAA\xBC"[..]);
+        buf.extend_from_slice(&crc);
+        buf.extend_from_slice(b"getinfo:");
+        assert!(matches!(
+            dec.decode(&mut buf),
+            Err(ReadError::CrcMismatch { .. })
+        ));
+        assert_eq!(&buf[..], b"getinfo:");
+    }
+
     // MARK: XvcInfoDecoder
 
     #[test]
@@ -260,4 +674,138 @@ mod tests {
         assert_eq!(info, XvcInfo::new(Version::V1_0, 32));
         assert_eq!(&buf[..], b"extra");
     }
+
+    // MARK: ResponseDecoder
+
+    #[test]
+    fn decode_responses() {
+        let shift = Message::Shift {
+            num_bits: 9,
+            tms: [0u8, 0].as_slice(),
+            tdi: [0u8, 0].as_slice(),
+        };
+        let mut buf = BytesMut::from(&b"\x11\x01\xFF"[..]);
+        assert_eq!(
+            ResponseDecoder::new(&shift).decode(&mut buf).unwrap(),
+            Some(Response::Tdo(Box::new([0x11, 0x01])))
+        );
+        assert_eq!(&buf[..], b"\xFF");
+
+        let settck = Message::<&[u8]>::SetTck { period_ns: 10 };
+        let mut dec = ResponseDecoder::new(&settck);
+        let mut buf = BytesMut::from(&b"\x0A\x00\x00"[..]);
+        assert_eq!(dec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"\x00");
+        assert_eq!(dec.decode(&mut buf).unwrap(), Some(Response::TckPeriod(10)));
+
+        let mut buf = BytesMut::from(&b"xvcServer_v1.0:32\n"[..]);
+        assert_eq!(
+            ResponseDecoder::new(&Message::<&[u8]>::GetInfo)
+                .decode(&mut buf)
+                .unwrap(),
+            Some(Response::Info(XvcInfo::new(Version::V1_0, 32)))
+        );
+    }
+
+    #[test]
+    fn decode_eof_mid_response_is_truncated() {
+        let shift = Message::Shift {
+            num_bits: 32,
+            tms: [0u8; 4].as_slice(),
+            tdi: [0u8; 4].as_slice(),
+        };
+        let mut dec = ResponseDecoder::new(&shift);
+        let mut buf = BytesMut::from(&b"\x01\x02"[..]);
+        assert!(matches!(
+            dec.decode_eof(&mut buf),
+            Err(ReadError::Truncated {
+                expected: 4,
+                received: 2
+            })
+        ));
+        let mut buf = BytesMut::new();
+        assert!(matches!(
+            dec.decode_eof(&mut buf),
+            Err(ReadError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn decode_tdo_with_crc() {
+        let shift = Message::Shift {
+            num_bits: 16,
+            tms: [0u8; 2].as_slice(),
+            tdi: [0u8; 2].as_slice(),
+        };
+        let mut dec = ResponseDecoder::new(&shift).with_crc();
+        let mut buf = BytesMut::from(&b"\x12\x34"[..]);
+        assert_eq!(dec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&crc::crc32(&[0x12, 0x34]).to_le_bytes()[..3]);
+        assert!(matches!(
+            dec.decode_eof(&mut buf.clone()),
+            Err(ReadError::Truncated {
+                expected: 6,
+                received: 5
+            })
+        ));
+        buf.clear();
+        buf.extend_from_slice(b"\x12\x34");
+        buf.extend_from_slice(&crc::crc32(&[0x12, 0x34]).to_le_bytes());
+        assert_eq!(
+            dec.decode(&mut buf).unwrap(),
+            Some(Response::Tdo(Box::new([0x12, 0x34])))
+        );
+
+        let mut buf = BytesMut::from(&b"\x12\x35"[..]);
+        buf.extend_from_slice(&crc::crc32(&[0x12, 0x34]).to_le_bytes());
+        assert!(matches!(
+            dec.decode(&mut buf),
+            Err(ReadError::CrcMismatch { .. })
+        ));
+
+        let settck = Message::<&[u8]>::SetTck { period_ns: 10 };
+        let mut buf = BytesMut::from(&b"\x0A\x00\x00\x00"[..]);
+        assert_eq!(
+            ResponseDecoder::new(&settck)
+                .with_crc()
+                .decode(&mut buf)
+                .unwrap(),
+            Some(Response::TckPeriod(10))
+        );
+    }
+
+    #[test]
+    fn decode_eof_mid_message_is_truncated() {
+        let mut dec = MessageDecoder::new(1024);
+        let mut buf = BytesMut::new();
+        assert!(dec.decode_eof(&mut buf).unwrap().is_none());
+
+        let mut buf = BytesMut::from(&b"settck:\x64"[..]);
+        assert!(matches!(
+            dec.decode_eof(&mut buf),
+            Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::SetTck),
+                read: 8,
+                expected: 11,
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_eof_accepts_info_without_newline() {
+        let mut buf = BytesMut::from(&b"xvcServer_v1.0:2048"[..]);
+        assert_eq!(XvcInfoDecoder.decode(&mut buf).unwrap(), None);
+        assert_eq!(
+            XvcInfoDecoder.decode_eof(&mut buf).unwrap(),
+            Some(XvcInfo::new(Version::V1_0, 2048))
+        );
+
+        let mut buf = BytesMut::from(&b"xvcServer_v1.0:2048"[..]);
+        assert_eq!(
+            ResponseDecoder::new(&Message::<&[u8]>::GetInfo)
+                .decode_eof(&mut buf)
+                .unwrap(),
+            Some(Response::Info(XvcInfo::new(Version::V1_0, 2048)))
+        );
+    }
 }
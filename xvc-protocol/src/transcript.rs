@@ -0,0 +1,377 @@
+//! Transcripts of the messages of a session and the responses they received, e.g. to replay
+//! the exact request stream of a real tool against a backend in regression tests.
+//!
+//! A transcript starts with the magic bytes `XVCT` and a format version byte, currently
+//! [`VERSION`]. Each record that follows holds one exchange:
+//!
+//! | Field    | Size              | Content                                          |
+//! |----------|-------------------|--------------------------------------------------|
+//! | message  | 4 bytes           | Length of the encoded message, little-endian     |
+//! |          | message bytes     | The message as sent on the wire                  |
+//! | response | 4 bytes           | Length of the response, little-endian            |
+//! |          | response bytes    | The response as sent on the wire, possibly empty |
+//!
+//! A shift that a server discarded unread, as its vectors exceeded the limit of the server, is
+//! recorded with only its header, `shift:` and the number of bits, as the message. Such records
+//! are written with [`TranscriptWriter::record_discarded_shift`] and skipped by
+//! [`TranscriptReader`].
+//!
+//! ```
+//! use xvc_protocol::{Message, transcript::{TranscriptReader, TranscriptWriter}};
+//!
+//! let mut writer = TranscriptWriter::new(Vec::new())?;
+//! writer.record(&Message::<&[u8]>::SetTck { period_ns: 100 }, &[0x64, 0, 0, 0])?;
+//! let transcript = writer.into_inner();
+//!
+//! for record in TranscriptReader::new(transcript.as_slice())? {
+//!     let (message, expected_response) = record?;
+//!     assert_eq!(message, Message::SetTck { period_ns: 100 });
+//!     assert_eq!(expected_response, [0x64, 0, 0, 0]);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use std::io::{self, Read, Write};
+
+use crate::{
+    Message, OwnedMessage, XvcCommand,
+    error::{ReadError, TranscriptError},
+};
+
+const MAGIC: &[u8; 4] = b"XVCT";
+/// The command of a shift, and the length of its header up to the vectors.
+const CMD_SHIFT: &[u8] = b"shift:";
+const SHIFT_HEADER_LEN: usize = CMD_SHIFT.len() + 4;
+
+/// The format version written by [`TranscriptWriter`] and read by [`TranscriptReader`].
+pub const VERSION: u8 = 1;
+
+/// Records messages and their responses into a transcript.
+#[derive(Debug)]
+pub struct TranscriptWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TranscriptWriter<W> {
+    /// Start a transcript in `writer` by writing its header.
+    pub fn new(mut writer: W) -> io::Result<TranscriptWriter<W>> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        Ok(TranscriptWriter { writer })
+    }
+
+    /// Record `message` and the bytes of the `response` it received, which are empty for a
+    /// message that was not answered.
+    ///
+    /// # Panics
+    ///
+    /// If the message or the response is longer than `u32::MAX` bytes.
+    pub fn record<B: AsRef<[u8]>>(
+        &mut self,
+        message: &Message<B>,
+        response: &[u8],
+    ) -> io::Result<()> {
+        let mut encoded = vec![0; message.encoded_len()];
+        message.encode(&mut encoded)?;
+        for part in [&encoded[..], response] {
+            let len = u32::try_from(part.len()).expect("record too long");
+            self.writer.write_all(&len.to_le_bytes())?;
+            self.writer.write_all(part)?;
+        }
+        Ok(())
+    }
+
+    /// Record a shift of `num_bits` whose vectors were discarded unread, and that was answered
+    /// with zeros.
+    pub fn record_discarded_shift(&mut self, num_bits: u32) -> io::Result<()> {
+        let len = num_bits.div_ceil(8);
+        let mut header = [0; SHIFT_HEADER_LEN];
+        header[..CMD_SHIFT.len()].copy_from_slice(CMD_SHIFT);
+        header[CMD_SHIFT.len()..].copy_from_slice(&num_bits.to_le_bytes());
+        self.writer
+            .write_all(&(SHIFT_HEADER_LEN as u32).to_le_bytes())?;
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        io::copy(&mut io::repeat(0).take(u64::from(len)), &mut self.writer)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Iterates over the `(message, expected_response)` pairs of a transcript.
+///
+/// Ends after the last record or the first error. A transcript that ends within a record, e.g.
+/// because the recording process was killed, yields [`TranscriptError::Truncated`] as its last
+/// item. Messages of unknown commands are returned as `Message::Unknown`, while the records of
+/// discarded shifts are skipped.
+#[derive(Debug)]
+pub struct TranscriptReader<R: Read> {
+    reader: R,
+    index: usize,
+    done: bool,
+}
+
+impl<R: Read> TranscriptReader<R> {
+    /// Read the header of the transcript in `reader`.
+    ///
+    /// Fails with `TranscriptError::InvalidHeader` if `reader` does not hold a transcript, and
+    /// with `TranscriptError::UnsupportedVersion` if it was written in a newer format.
+    pub fn new(mut reader: R) -> Result<TranscriptReader<R>, TranscriptError> {
+        let mut header = [0; MAGIC.len() + 1];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(TranscriptError::InvalidHeader);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        if header[..MAGIC.len()] != *MAGIC {
+            return Err(TranscriptError::InvalidHeader);
+        }
+        match header[MAGIC.len()] {
+            VERSION => Ok(TranscriptReader {
+                reader,
+                index: 0,
+                done: false,
+            }),
+            version => Err(TranscriptError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Read the length of the next part of a record. `Ok(None)` at the end of the transcript is
+    /// only allowed for the first part.
+    fn read_len(&mut self, first: bool) -> Result<Option<u64>, TranscriptError> {
+        let mut len = [0; 4];
+        let mut filled = 0;
+        while filled < len.len() {
+            match self.reader.read(&mut len[filled..]) {
+                Ok(0) if first && filled == 0 => return Ok(None),
+                Ok(0) => return Err(TranscriptError::Truncated { index: self.index }),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(Some(u64::from(u32::from_le_bytes(len))))
+    }
+
+    /// Read the next length-prefixed part of a record. `Ok(None)` at the end of the transcript
+    /// is only allowed for the first part.
+    fn read_part(&mut self, first: bool) -> Result<Option<Vec<u8>>, TranscriptError> {
+        let Some(len) = self.read_len(first)? else {
+            return Ok(None);
+        };
+        let mut part = Vec::new();
+        self.reader.by_ref().take(len).read_to_end(&mut part)?;
+        if (part.len() as u64) < len {
+            return Err(TranscriptError::Truncated { index: self.index });
+        }
+        Ok(Some(part))
+    }
+
+    /// Skip the next length-prefixed part of a record, which is not the first.
+    fn skip_part(&mut self) -> Result<(), TranscriptError> {
+        let len = self.read_len(false)?.unwrap_or_default();
+        if io::copy(&mut self.reader.by_ref().take(len), &mut io::sink())? < len {
+            return Err(TranscriptError::Truncated { index: self.index });
+        }
+        Ok(())
+    }
+
+    fn read_record(&mut self) -> Result<Option<(OwnedMessage, Vec<u8>)>, TranscriptError> {
+        let message = loop {
+            let Some(message) = self.read_part(true)? else {
+                return Ok(None);
+            };
+            if !is_discarded_shift(&message) {
+                break message;
+            }
+            self.skip_part()?;
+            self.index += 1;
+        };
+        let response = self.read_part(false)?.unwrap_or_default();
+        let message = decode(&message).map_err(|error| TranscriptError::InvalidMessage {
+            index: self.index,
+            error,
+        })?;
+        self.index += 1;
+        Ok(Some((message, response)))
+    }
+}
+
+/// Whether a record holds only the header of a shift with vectors, see
+/// [`TranscriptWriter::record_discarded_shift`].
+fn is_discarded_shift(bytes: &[u8]) -> bool {
+    bytes.len() == SHIFT_HEADER_LEN
+        && bytes.starts_with(CMD_SHIFT)
+        && bytes[CMD_SHIFT.len()..] != [0; 4]
+}
+
+/// Decode a record that holds exactly one message.
+fn decode(bytes: &[u8]) -> Result<OwnedMessage, ReadError> {
+    let (message, len) = match Message::from_bytes(bytes, usize::MAX) {
+        Err(ReadError::InvalidCommand(_)) => {
+            let mut rest = bytes;
+            let name = XvcCommand::parse_unknown(&mut rest)?;
+            (Message::Unknown { name }, bytes.len() - rest.len())
+        }
+        result => result?,
+    };
+    if len < bytes.len() {
+        return Err(ReadError::InvalidFormat(format!(
+            "{} bytes after the message",
+            bytes.len() - len
+        )));
+    }
+    Ok(message)
+}
+
+impl<R: Read> Iterator for TranscriptReader<R> {
+    type Item = Result<(OwnedMessage, Vec<u8>), TranscriptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_record().transpose();
+        self.done = !matches!(result, Some(Ok(_)));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ShiftVector;
+
+    fn transcript() -> Vec<u8> {
+        let mut writer = TranscriptWriter::new(Vec::new()).unwrap();
+        writer
+            .record(&Message::<&[u8]>::GetInfo, b"xvcServer_v1.0:32\n")
+            .unwrap();
+        let shift = Message::try_shift(12, &[0x0F, 0x00][..], &[0xAB, 0x0C][..]).unwrap();
+        writer.record(&shift, &[0x12, 0x03]).unwrap();
+        let unknown = Message::<&[u8]>::Unknown {
+            name: "lock".into(),
+        };
+        writer.record(&unknown, &[]).unwrap();
+        writer.into_inner()
+    }
+
+    #[test]
+    fn records_roundtrip() {
+        let records: Vec<_> = TranscriptReader::new(transcript().as_slice())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            [
+                (Message::GetInfo, b"xvcServer_v1.0:32\n".to_vec()),
+                (
+                    Message::Shift {
+                        num_bits: 12,
+                        tms: ShiftVector::from([0x0F, 0x00]),
+                        tdi: ShiftVector::from([0xAB, 0x0C]),
+                    },
+                    vec![0x12, 0x03]
+                ),
+                (
+                    Message::Unknown {
+                        name: "lock".into()
+                    },
+                    vec![]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncation_is_reported_once() {
+        let transcript = transcript();
+        // The header, the GetInfo record and the Unknown record at the end
+        let (first_end, last_len) = (5 + 4 + 8 + 4 + 18, 4 + 5 + 4);
+        // Every cut within the second record, from its message length to its response
+        for len in first_end + 1..transcript.len() - last_len {
+            let mut reader = TranscriptReader::new(&transcript[..len]).unwrap();
+            assert!(reader.next().unwrap().is_ok());
+            assert!(
+                matches!(
+                    reader.next(),
+                    Some(Err(TranscriptError::Truncated { index: 1 }))
+                ),
+                "{len} bytes"
+            );
+            assert!(reader.next().is_none());
+        }
+    }
+
+    #[test]
+    fn discarded_shifts_are_skipped() {
+        let mut writer = TranscriptWriter::new(Vec::new()).unwrap();
+        writer.record_discarded_shift(8 * 100 + 1).unwrap();
+        writer
+            .record(
+                &Message::<&[u8]>::SetTck { period_ns: 100 },
+                &[0x64, 0, 0, 0],
+            )
+            .unwrap();
+        let transcript = writer.into_inner();
+        // The header, and the discarded shift with its 101 zero bytes of TDO
+        assert_eq!(&transcript[5..9], 10u32.to_le_bytes());
+        assert_eq!(&transcript[9..19], b"shift:\x21\x03\x00\x00");
+        assert_eq!(&transcript[19..23], 101u32.to_le_bytes());
+        assert_eq!(transcript[23..124], [0; 101]);
+
+        let records: Vec<_> = TranscriptReader::new(transcript.as_slice())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            [(Message::SetTck { period_ns: 100 }, vec![0x64, 0, 0, 0])]
+        );
+        // A truncated discarded shift is still reported
+        let mut reader = TranscriptReader::new(&transcript[..100]).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(TranscriptError::Truncated { index: 0 }))
+        ));
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected() {
+        assert!(matches!(
+            TranscriptReader::new(&b"XVC"[..]),
+            Err(TranscriptError::InvalidHeader)
+        ));
+        assert!(matches!(
+            TranscriptReader::new(&b"getinfo:"[..]),
+            Err(TranscriptError::InvalidHeader)
+        ));
+        assert!(matches!(
+            TranscriptReader::new(&b"XVCT\x02"[..]),
+            Err(TranscriptError::UnsupportedVersion(2))
+        ));
+        assert_eq!(TranscriptReader::new(&b"XVCT\x01"[..]).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn trailing_bytes_are_invalid() {
+        let mut transcript = b"XVCT\x01".to_vec();
+        transcript.extend_from_slice(&9u32.to_le_bytes());
+        transcript.extend_from_slice(b"getinfo:x");
+        transcript.extend_from_slice(&0u32.to_le_bytes());
+        let mut reader = TranscriptReader::new(transcript.as_slice()).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(TranscriptError::InvalidMessage { index: 0, .. }))
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+//! Listening sockets passed by systemd socket activation.
+//!
+//! Implements the protocol of `sd_listen_fds(3)` directly: if `LISTEN_PID` is the PID of this
+//! process, the `LISTEN_FDS` sockets that systemd passed are open as the file descriptors
+//! starting at 3.
+use std::{
+    ops::Range,
+    os::fd::{FromRawFd, OwnedFd, RawFd},
+};
+
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Takes ownership of the sockets that systemd passed to this process, if any.
+///
+/// Must be called at most once, as the file descriptors are closed when the returned sockets
+/// are dropped.
+pub fn listen_fds() -> Vec<OwnedFd> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let count = std::env::var("LISTEN_FDS").ok();
+    passed_fds(pid.as_deref(), count.as_deref(), std::process::id())
+        // SAFETY: systemd passes these file descriptors to this process, which does not use
+        // them elsewhere
+        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+        .collect()
+}
+
+/// The file descriptors named by `LISTEN_PID` and `LISTEN_FDS`, if they were set for the
+/// process `own_pid` and not inherited from a parent.
+fn passed_fds(pid: Option<&str>, count: Option<&str>, own_pid: u32) -> Range<RawFd> {
+    let none = LISTEN_FDS_START..LISTEN_FDS_START;
+    if pid.and_then(|pid| pid.parse::<u32>().ok()) != Some(own_pid) {
+        return none;
+    }
+    let Some(count) = count else {
+        return none;
+    };
+    match count.parse::<RawFd>() {
+        Ok(passed) if passed > 0 => LISTEN_FDS_START..LISTEN_FDS_START + passed,
+        _ => {
+            log::warn!("Ignoring invalid LISTEN_FDS={}", count);
+            none
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sockets_passed_to_this_process_are_used() {
+        assert_eq!(passed_fds(Some("42"), Some("2"), 42), 3..5);
+    }
+
+    #[test]
+    fn sockets_passed_to_another_process_are_ignored() {
+        assert!(passed_fds(Some("41"), Some("2"), 42).is_empty());
+        assert!(passed_fds(None, Some("2"), 42).is_empty());
+    }
+
+    #[test]
+    fn invalid_counts_are_ignored() {
+        assert!(passed_fds(Some("42"), None, 42).is_empty());
+        assert!(passed_fds(Some("42"), Some("0"), 42).is_empty());
+        assert!(passed_fds(Some("42"), Some("two"), 42).is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+//! Helpers shared between the backends.
+use std::{
+    ffi::c_int,
+    io,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// The number of bytes at the start and end of each vector that are logged at trace level.
+pub(super) const TRACE_VECTOR_BYTES: usize = 32;
+
+/// Checks that `tms`, `tdi` and `tdo` each hold ⌈`num_bits` / 8⌉ bytes.
+pub(super) fn check_vector_lengths(
+    num_bits: u32,
+    tms: &[u8],
+    tdi: &[u8],
+    tdo: &[u8],
+) -> io::Result<()> {
+    let num_bytes = num_bits.div_ceil(8) as usize;
+    for (name, len) in [("TMS", tms.len()), ("TDI", tdi.len()), ("TDO", tdo.len())] {
+        if len != num_bytes {
+            log::error!(
+                "{} buffer size mismatch: expected {}, got {}",
+                name,
+                num_bytes,
+                len
+            );
+            return Err(io::Error::other(format!("{} has incorrect size", name)));
+        }
+    }
+    Ok(())
+}
+
+/// Converts the result of a shift ioctl, counting failures in `errors`.
+pub(super) fn ioctl_result(result: nix::Result<c_int>, errors: &AtomicU64) -> io::Result<()> {
+    result.map(|_| ()).map_err(|e| {
+        errors.fetch_add(1, Ordering::Relaxed);
+        e.into()
+    })
+}
+
+/// Computes an ioctl request code the way the C `_IOC` macro does.
+///
+/// The Xilinx drivers pass a 32-bit magic (`"XVCD"`) where the macro expects an 8-bit type,
+/// so the magic overflows into the size field. The resulting codes differ from those
+/// that `nix::ioctl_readwrite!` would compute and have to be replicated here.
+pub(super) const fn ioc(dir: u32, magic: u32, nr: u32, size: usize) -> u32 {
+    (dir << 30) | ((size as u32) << 16) | magic.wrapping_shl(8) | nr
+}
+
+/// `_IOC_READ | _IOC_WRITE`
+pub(super) const IOC_READ_WRITE: u32 = 3;
+/// `_IOC_READ`
+pub(super) const IOC_READ: u32 = 2;
+
+/// Magic of the Xilinx XVC drivers, `"XVCD"`
+pub(super) const XVC_MAGIC: u32 = 0x58564344;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_mismatched_vectors() {
+        assert!(check_vector_lengths(9, &[0, 0], &[0, 0], &[0, 0]).is_ok());
+        assert!(check_vector_lengths(0, &[], &[], &[]).is_ok());
+        let err = check_vector_lengths(9, &[0, 0], &[0], &[0, 0]).unwrap_err();
+        assert_eq!(err.to_string(), "TDI has incorrect size");
+        let err = check_vector_lengths(8, &[0], &[0], &[0, 0]).unwrap_err();
+        assert_eq!(err.to_string(), "TDO has incorrect size");
+    }
+
+    #[test]
+    fn counts_ioctl_failures() {
+        let errors = AtomicU64::new(0);
+        assert!(ioctl_result(Ok(0), &errors).is_ok());
+        let err = ioctl_result(Err(nix::errno::Errno::EIO), &errors).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(nix::libc::EIO));
+        assert_eq!(errors.load(Ordering::Relaxed), 1);
+    }
+}
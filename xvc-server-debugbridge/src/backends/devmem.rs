@@ -14,12 +14,14 @@
 //! server.listen("127.0.0.1:2542")?;
 //! ```
 
-use std::{fs::OpenOptions, io, num::NonZero, path::Path, ptr::NonNull, time::Duration};
+use std::{fs::OpenOptions, io, path::Path, time::Duration};
 
-use nix::sys::mman::{MapFlags, ProtFlags, mmap, munmap};
 use xvc_server::XvcServer;
 
-use crate::backends::memory_mapped::{MAP_SIZE, MemoryMappedBackend};
+use crate::backends::{
+    memory_mapped::{MemoryMappedBackend, StatusRegister},
+    mmio::{MAP_SIZE, MmioRegion},
+};
 
 /// Debug bridge driver based on a Uio device
 pub struct DevMemBackend(MemoryMappedBackend);
@@ -42,34 +44,22 @@ impl DevMemBackend {
             .open(device_path)?;
         log::debug!("DevMem file opened successfully");
 
-        let mem = unsafe {
-            log::debug!(
-                "Mapping DevMem (address=0x{:x}; size=0x{:x})",
-                address,
-                MAP_SIZE
-            );
-            let ptr = mmap(
-                None,
-                NonZero::new(MAP_SIZE).unwrap(),
-                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
-                MapFlags::MAP_SHARED,
-                file,
-                address,
-            )?;
-            log::info!("DevMem memory mapped successfully");
-            ptr.as_ptr() as *mut u32
-        };
-        Ok(DevMemBackend(MemoryMappedBackend::new(mem, poll_timeout)))
+        log::debug!(
+            "Mapping DevMem (address=0x{:x}; size=0x{:x})",
+            address,
+            MAP_SIZE
+        );
+        let region = MmioRegion::map_file(&file, address)?;
+        log::info!("DevMem memory mapped successfully");
+        Ok(DevMemBackend(MemoryMappedBackend::new(
+            region,
+            poll_timeout,
+        )))
     }
-}
 
-impl Drop for DevMemBackend {
-    fn drop(&mut self) {
-        if let Some(ptr) = NonNull::new(self.0.mem) {
-            unsafe {
-                let _ = munmap(ptr.cast(), MAP_SIZE);
-            }
-        }
+    /// Checks the sticky AXI error bits of `status` after every transfer.
+    pub fn with_status_register(self, status: StatusRegister) -> DevMemBackend {
+        DevMemBackend(self.0.with_status_register(status))
     }
 }
 
@@ -89,4 +79,8 @@ impl XvcServer for DevMemBackend {
     ) -> Result<(), Self::Err> {
         self.0.shift_data(num_bits, tms, tdi, tdo)
     }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        self.0.stats()
+    }
 }
@@ -0,0 +1,216 @@
+//! Emulation of the memory-mapped debug bridge for tests.
+//!
+//! [`FakeBridge`] runs a background thread that watches the CONTROL register of an anonymous
+//! [`MmioRegion`], consumes LENGTH/TMS/TDI, stores the TDO word computed by a model and clears
+//! CONTROL, like the AXI to JTAG bridge does.
+//!
+//! [`FifoBridge`] models a bridge with FIFOs behind its data registers. It observes every
+//! register access and therefore implements [`Registers`] directly instead of using a mapping.
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::backends::{
+    memory_mapped::{
+        CONTROL_REG_OFFSET, LENGTH_OFFSET, TDI_REG_OFFSET, TDO_REG_OFFSET, TMS_REG_OFFSET,
+    },
+    mmio::{MmioRegion, Registers},
+};
+
+/// One transfer of at most 32 bits, as seen by the bridge.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Transfer {
+    pub length: u32,
+    pub tms: u32,
+    pub tdi: u32,
+}
+
+pub struct FakeBridge {
+    region: MmioRegion,
+    transfers: Arc<Mutex<Vec<Transfer>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FakeBridge {
+    /// Starts a bridge whose TDO word is computed by `model`.
+    /// If `model` returns `None`, the transfer never completes.
+    pub fn spawn(mut model: impl FnMut(&Transfer) -> Option<u32> + Send + 'static) -> FakeBridge {
+        let region = MmioRegion::anonymous().unwrap();
+        let transfers = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let region = region.clone();
+            let transfers = Arc::clone(&transfers);
+            let stop = Arc::clone(&stop);
+            move || {
+                while !stop.load(Ordering::Relaxed) {
+                    if region.read(CONTROL_REG_OFFSET) & 0x01 == 0 {
+                        std::hint::spin_loop();
+                        continue;
+                    }
+                    region.read_barrier();
+                    let transfer = Transfer {
+                        length: region.read(LENGTH_OFFSET),
+                        tms: region.read(TMS_REG_OFFSET),
+                        tdi: region.read(TDI_REG_OFFSET),
+                    };
+                    transfers.lock().unwrap().push(transfer);
+                    match model(&transfer) {
+                        Some(tdo) => {
+                            region.write(TDO_REG_OFFSET, tdo);
+                            region.write_barrier();
+                            region.write(CONTROL_REG_OFFSET, 0);
+                        }
+                        None => break,
+                    }
+                }
+            }
+        });
+        FakeBridge {
+            region,
+            transfers,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// A bridge that returns TDI as TDO.
+    pub fn loopback() -> FakeBridge {
+        FakeBridge::spawn(|transfer| Some(transfer.tdi))
+    }
+
+    /// A bridge that never clears CONTROL.
+    pub fn stuck() -> FakeBridge {
+        FakeBridge::spawn(|_| None)
+    }
+
+    /// The register block to pass to the backend under test.
+    pub fn region(&self) -> MmioRegion {
+        self.region.clone()
+    }
+
+    pub fn transfers(&self) -> Vec<Transfer> {
+        self.transfers.lock().unwrap().clone()
+    }
+}
+
+impl Drop for FakeBridge {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// One transfer of a [`FifoBridge`], as seen by the bridge.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Burst {
+    pub length: u32,
+    pub tms: Vec<u32>,
+    pub tdi: Vec<u32>,
+}
+
+#[derive(Default)]
+struct FifoState {
+    length: u32,
+    tms: VecDeque<u32>,
+    tdi: VecDeque<u32>,
+    tdo: VecDeque<u32>,
+    status: u32,
+    bursts: Vec<Burst>,
+}
+
+/// A loopback bridge with a FIFO of `depth` bits behind each of TMS, TDI and TDO,
+/// and a status register with write-one-to-clear bits at [`STATUS_REG_OFFSET`](Self::STATUS_REG_OFFSET).
+///
+/// Starting a transfer checks that LENGTH matches the number of pushed words, that the TDO of
+/// the previous transfer was drained and that the FIFOs did not overflow; violations panic and
+/// thereby fail the shift under test.
+pub struct FifoBridge {
+    depth_words: usize,
+    state: Mutex<FifoState>,
+}
+
+impl FifoBridge {
+    pub const STATUS_REG_OFFSET: usize = 8;
+
+    pub fn new(depth: u32) -> FifoBridge {
+        FifoBridge {
+            depth_words: depth as usize / 32,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn bursts(&self) -> Vec<Burst> {
+        self.state.lock().unwrap().bursts.clone()
+    }
+
+    pub fn status(&self) -> u32 {
+        self.state.lock().unwrap().status
+    }
+
+    /// Sets status bits, as the bridge does on a failed AXI transaction.
+    pub fn raise(&self, bits: u32) {
+        self.state.lock().unwrap().status |= bits;
+    }
+}
+
+impl Registers for &FifoBridge {
+    fn read(&self, offset: usize) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        match offset {
+            TDO_REG_OFFSET => state.tdo.pop_front().expect("TDO FIFO underflow"),
+            // Transfers complete immediately
+            CONTROL_REG_OFFSET => 0,
+            FifoBridge::STATUS_REG_OFFSET => state.status,
+            _ => panic!("read from unexpected register {}", offset),
+        }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        let mut state = self.state.lock().unwrap();
+        match offset {
+            LENGTH_OFFSET => state.length = value,
+            TMS_REG_OFFSET => state.tms.push_back(value),
+            TDI_REG_OFFSET => state.tdi.push_back(value),
+            CONTROL_REG_OFFSET => {
+                assert_eq!(value, 0x01);
+                let words = state.length.div_ceil(32) as usize;
+                assert!(words <= self.depth_words, "FIFO overflow");
+                assert_eq!(
+                    state.tms.len(),
+                    words,
+                    "TMS words for {} bits",
+                    state.length
+                );
+                assert_eq!(
+                    state.tdi.len(),
+                    words,
+                    "TDI words for {} bits",
+                    state.length
+                );
+                assert!(state.tdo.is_empty(), "TDO not drained");
+                let burst = Burst {
+                    length: state.length,
+                    tms: state.tms.drain(..).collect(),
+                    tdi: state.tdi.drain(..).collect(),
+                };
+                state.tdo.extend(&burst.tdi);
+                state.bursts.push(burst);
+            }
+            FifoBridge::STATUS_REG_OFFSET => state.status &= !value,
+            _ => panic!("write to unexpected register {}", offset),
+        }
+    }
+
+    fn write_barrier(&self) {}
+
+    fn read_barrier(&self) {}
+}
@@ -21,9 +21,17 @@ use std::{
     mem::MaybeUninit,
     os::fd::AsRawFd,
     path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use xvc_protocol::HexSummary;
+
+use crate::{
+    XvcServer,
+    backends::common::{
+        IOC_READ, IOC_READ_WRITE, TRACE_VECTOR_BYTES, XVC_MAGIC, check_vector_lengths, ioc,
+        ioctl_result,
+    },
 };
-
-use crate::XvcServer;
 
 /// Properties that the user can read from the debug bridge.
 #[repr(C)]
@@ -66,26 +74,22 @@ struct XvcIoc {
     tdo_buf: *mut c_uchar,
 }
 
-const XDMA_RDXVC_PROPS_NR: u32 = 0xD6534402;
-const XDMA_IOCXVC_NR: u32 = 0xD6634401;
+// Defined in XilinxVirtualCable/jtag/zynqMP/src/driver/xvc_ioctl.h.
+// `nix::ioctl_readwrite!` computes different codes than the kernel for the 32-bit magic,
+// see [`ioc`].
+const XDMA_RDXVC_PROPS_NR: u32 = ioc(IOC_READ, XVC_MAGIC, 2, size_of::<XvcProperties>());
+const XDMA_IOCXVC_NR: u32 = ioc(IOC_READ_WRITE, XVC_MAGIC, 1, size_of::<XvcIoc>());
 
 // Read properties from the device
 ioctl_read_bad!(xvc_read_properties, XDMA_RDXVC_PROPS_NR, XvcProperties);
 // Perform a shift operation
 ioctl_readwrite_bad!(xvc_do_ioc, XDMA_IOCXVC_NR, XvcIoc);
 
-// Note: The following lines cause a mismatch between the ioctl code reported by the kernel
-// and the ioctl code reported by userland.
-// Therefore, the ioctl request codes are hardcoded above.
-//
-// Defined in XilinxVirtualCable/jtag/zynqMP/src/driver/xvc_ioctl.h
-// const XVC_MAGIC: u32 = 0x58564344;
-// ioctl_readwrite!(xvc_do_ioc, XVC_MAGIC, 1, XvcIoc);
-// ioctl_read!(xvc_read_properties, XVC_MAGIC, 2, XvcProperties);
-
 /// A device that communicates with a Xilinx Debug Bridge through the dedicated Kernel Driver.
 pub struct KernelDriverBackend {
     file: File,
+    /// Number of failed shift ioctls
+    ioctl_errors: AtomicU64,
 }
 
 impl KernelDriverBackend {
@@ -114,7 +118,10 @@ impl KernelDriverBackend {
             properties.debug_bridge_compat_string()
         );
 
-        Ok(KernelDriverBackend { file })
+        Ok(KernelDriverBackend {
+            file,
+            ioctl_errors: AtomicU64::new(0),
+        })
     }
 
     /// Transfers JTAG data.
@@ -127,39 +134,22 @@ impl KernelDriverBackend {
         tdi: &[u8],
         tdo: &mut [u8],
     ) -> io::Result<()> {
+        check_vector_lengths(num_bits, tms, tdi, tdo)?;
         let num_bytes = num_bits.div_ceil(8) as usize;
-        if tms.len() != num_bytes {
-            log::error!(
-                "TMS buffer size mismatch: expected {}, got {}",
-                num_bytes,
-                tms.len()
-            );
-            return Err(io::Error::other("TMS has incorrect size"));
-        }
-        if tdi.len() != num_bytes {
-            log::error!(
-                "TDI buffer size mismatch: expected {}, got {}",
-                num_bytes,
-                tdi.len()
-            );
-            return Err(io::Error::other("TDI has incorrect size"));
-        }
-        if tdo.len() != num_bytes {
-            log::error!(
-                "TDO buffer size mismatch: expected {}, got {}",
-                num_bytes,
-                tdo.len()
-            );
-            return Err(io::Error::other("TDO has incorrect size"));
-        }
 
         log::debug!(
             "Kernel driver shift: num_bits={}, num_bytes={}",
             num_bits,
             num_bytes
         );
-        log::trace!("Kernel driver shift TMS: {:02x?}", tms);
-        log::trace!("Kernel driver shift TDI: {:02x?}", tdi);
+        log::trace!(
+            "Kernel driver shift TMS: {}",
+            HexSummary::new(tms, TRACE_VECTOR_BYTES)
+        );
+        log::trace!(
+            "Kernel driver shift TDI: {}",
+            HexSummary::new(tdi, TRACE_VECTOR_BYTES)
+        );
 
         let mut xvc_ioc = XvcIoc {
             opcode: 1,
@@ -172,11 +162,8 @@ impl KernelDriverBackend {
         // - File descriptor is valid (self.file is open)
         // - Buffers are valid for the duration of the call
         // - Buffer sizes match the num_bits parameter
-        unsafe {
-            xvc_do_ioc(self.file.as_raw_fd(), &mut xvc_ioc)?;
-        }
-
-        Ok(())
+        let result = unsafe { xvc_do_ioc(self.file.as_raw_fd(), &mut xvc_ioc) };
+        ioctl_result(result, &self.ioctl_errors)
     }
 }
 
@@ -196,4 +183,38 @@ impl XvcServer for KernelDriverBackend {
     ) -> Result<(), Self::Err> {
         self.shift_data(num_bits, tms, tdi, tdo)
     }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        vec![("ioctl_errors", self.ioctl_errors.load(Ordering::Relaxed))]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+
+    use super::*;
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn ioctl_struct_layout() {
+        assert_eq!(size_of::<XvcIoc>(), 32);
+        assert_eq!(offset_of!(XvcIoc, opcode), 0);
+        assert_eq!(offset_of!(XvcIoc, length), 4);
+        assert_eq!(offset_of!(XvcIoc, tms_buf), 8);
+        assert_eq!(offset_of!(XvcIoc, tdi_buf), 16);
+        assert_eq!(offset_of!(XvcIoc, tdo_buf), 24);
+
+        assert_eq!(size_of::<XvcProperties>(), 80);
+        assert_eq!(offset_of!(XvcProperties, debug_bridge_size), 8);
+        assert_eq!(offset_of!(XvcProperties, debug_bridge_compat_string), 16);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn ioctl_codes_match_driver() {
+        // As reported by the kernel driver
+        assert_eq!(XDMA_RDXVC_PROPS_NR, 0xD6534402);
+        assert_eq!(XDMA_IOCXVC_NR, 0xD6634401);
+    }
 }
@@ -1,41 +1,158 @@
 use std::{
+    error::Error,
+    fmt::{self, Display},
     io::{self, Cursor, Write},
-    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicU64, Ordering},
     time::{Duration, Instant},
 };
 
-pub(super) const MAP_SIZE: usize = 0x10000;
+use xvc_protocol::{HexSummary, bits};
+
+use crate::backends::{
+    common::{TRACE_VECTOR_BYTES, check_vector_lengths},
+    mmio::{MmioRegion, Registers},
+};
 
 // Word (u32) offsets into the memory-mapped register block
-const LENGTH_OFFSET: usize = 0;
-const TMS_REG_OFFSET: usize = 1;
-const TDI_REG_OFFSET: usize = 2;
-const TDO_REG_OFFSET: usize = 3;
-const CONTROL_REG_OFFSET: usize = 4;
+pub(super) const LENGTH_OFFSET: usize = 0;
+pub(super) const TMS_REG_OFFSET: usize = 1;
+pub(super) const TDI_REG_OFFSET: usize = 2;
+pub(super) const TDO_REG_OFFSET: usize = 3;
+pub(crate) const CONTROL_REG_OFFSET: usize = 4;
+
+/// A status register of a bridge wrapper with sticky bits that are set when the AXI
+/// transaction behind a transfer fails. The bits are cleared by writing ones to them.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StatusRegister {
+    /// Word offset into the register block
+    pub offset: usize,
+    /// Bit signalling a slave error (SLVERR)
+    pub slverr_bit: u32,
+    /// Bit signalling a decode error (DECERR)
+    pub decerr_bit: u32,
+}
+
+/// An AXI error reported by the [`StatusRegister`]. Shifts that fail with it carry it as the
+/// inner error of the returned [`io::Error`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BusError {
+    SlaveError,
+    DecodeError,
+}
+
+impl Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusError::SlaveError => write!(
+                f,
+                "AXI slave error (SLVERR) during JTAG transfer; is the PL clock of the bridge running?"
+            ),
+            BusError::DecodeError => write!(
+                f,
+                "AXI decode error (DECERR) during JTAG transfer; is the bridge mapped at this address?"
+            ),
+        }
+    }
+}
+
+impl Error for BusError {}
 
 /// A backend that uses the memory-mapped AXI to JTAG bridge.
 /// Used by the UIO and the DevMem Backend.
-pub struct MemoryMappedBackend {
-    pub mem: *mut u32,
+pub struct MemoryMappedBackend<R = MmioRegion> {
+    region: R,
     /// The driver must poll the Debug Bridge since there are no interrupt lines.
     /// This timeout defines how long a poll may take before issuing a timeout error.
     pub poll_timeout: Duration,
+    /// Accumulated time spent polling for the bridge to finish a transfer
+    poll_wait_ns: AtomicU64,
+    /// Number of polls that ran into `poll_timeout`
+    poll_timeouts: AtomicU64,
+    /// Number of 32-bit words the bridge buffers behind TMS, TDI and TDO
+    fifo_words: usize,
+    status: Option<StatusRegister>,
+    slave_errors: AtomicU64,
+    decode_errors: AtomicU64,
 }
 
-// SAFETY: `mem` points to a memory-mapped hardware register block that is
-// stable for the lifetime of the backend.
-unsafe impl Send for MemoryMappedBackend {}
-
-fn u32_from_u8_slice(slice: &[u8]) -> u32 {
-    assert!(slice.len() <= 4);
-    let mut buf = [0u8; 4];
-    buf[..slice.len()].copy_from_slice(slice);
-    u32::from_ne_bytes(buf)
+/// The bridge registers hold the first vector bit in the LSB, independent of the CPU endianness.
+fn register_word(slice: &[u8]) -> u32 {
+    bits::extract(slice, 0, 8 * slice.len()) as u32
 }
 
-impl MemoryMappedBackend {
-    pub fn new(mem: *mut u32, poll_timeout: Duration) -> MemoryMappedBackend {
-        MemoryMappedBackend { mem, poll_timeout }
+impl<R: Registers> MemoryMappedBackend<R> {
+    pub fn new(region: R, poll_timeout: Duration) -> MemoryMappedBackend<R> {
+        MemoryMappedBackend {
+            region,
+            poll_timeout,
+            poll_wait_ns: AtomicU64::new(0),
+            poll_timeouts: AtomicU64::new(0),
+            fifo_words: 1,
+            status: None,
+            slave_errors: AtomicU64::new(0),
+            decode_errors: AtomicU64::new(0),
+        }
+    }
+
+    /// Uses burst transfers for a bridge that buffers `fifo_depth` bits behind each of
+    /// the TMS, TDI and TDO registers. Every write to TMS or TDI then pushes a word,
+    /// every read from TDO pops one, and up to `fifo_depth` bits are shifted per transfer.
+    ///
+    /// The depth is rounded down to whole words; the default of 32 bits shifts a single word
+    /// per transfer.
+    pub fn with_fifo_depth(mut self, fifo_depth: u32) -> MemoryMappedBackend<R> {
+        self.fifo_words = (fifo_depth as usize / 32).max(1);
+        self
+    }
+
+    /// Checks `status` after every transfer and fails the shift with a [`BusError`]
+    /// if one of its error bits is set.
+    pub fn with_status_register(mut self, status: StatusRegister) -> MemoryMappedBackend<R> {
+        self.status = Some(status);
+        self
+    }
+
+    /// Backend counters, see [`XvcServer::stats`](xvc_server::XvcServer::stats)
+    pub fn stats(&self) -> Vec<(&'static str, u64)> {
+        let mut stats = vec![
+            (
+                "poll_wait_us",
+                self.poll_wait_ns.load(Ordering::Relaxed) / 1000,
+            ),
+            ("poll_timeouts", self.poll_timeouts.load(Ordering::Relaxed)),
+        ];
+        if self.status.is_some() {
+            stats.push((
+                "axi_slave_errors",
+                self.slave_errors.load(Ordering::Relaxed),
+            ));
+            stats.push((
+                "axi_decode_errors",
+                self.decode_errors.load(Ordering::Relaxed),
+            ));
+        }
+        stats
+    }
+
+    /// Reports and clears the sticky error bits of the status register, if configured.
+    fn check_status(&self) -> io::Result<()> {
+        let Some(status) = self.status else {
+            return Ok(());
+        };
+        let slverr = 1 << status.slverr_bit;
+        let decerr = 1 << status.decerr_bit;
+        let value = self.region.read(status.offset);
+        let (error, counter) = if value & decerr != 0 {
+            (BusError::DecodeError, &self.decode_errors)
+        } else if value & slverr != 0 {
+            (BusError::SlaveError, &self.slave_errors)
+        } else {
+            return Ok(());
+        };
+        self.region.write(status.offset, value & (slverr | decerr));
+        counter.fetch_add(1, Ordering::Relaxed);
+        log::error!("{}", error);
+        Err(io::Error::other(error))
     }
 
     // Note this is an adapted version of the Xilinx driver
@@ -46,43 +163,30 @@ impl MemoryMappedBackend {
         mut tdi: &[u8],
         tdo: &mut [u8],
     ) -> io::Result<()> {
+        check_vector_lengths(num_bits, tms, tdi, tdo)?;
         let num_bytes = num_bits.div_ceil(8) as usize;
-        if tms.len() != num_bytes {
-            log::error!(
-                "TMS buffer size mismatch: expected {}, got {}",
-                num_bytes,
-                tms.len()
-            );
-            return Err(io::Error::other("TMS has incorrect size"));
-        }
-        if tdi.len() != num_bytes {
-            log::error!(
-                "TDI buffer size mismatch: expected {}, got {}",
-                num_bytes,
-                tdi.len()
-            );
-            return Err(io::Error::other("TDI has incorrect size"));
-        }
-        if tdo.len() != num_bytes {
-            log::error!(
-                "TDO buffer size mismatch: expected {}, got {}",
-                num_bytes,
-                tdo.len()
-            );
-            return Err(io::Error::other("TDO has incorrect size"));
-        }
 
         log::debug!("UIO shift: num_bits={}, num_bytes={}", num_bits, num_bytes);
-        log::trace!("UIO shift TMS: {:02x?}", tms);
-        log::trace!("UIO shift TDI: {:02x?}", tdi);
+        log::trace!(
+            "UIO shift TMS: {}",
+            HexSummary::new(tms, TRACE_VECTOR_BYTES)
+        );
+        log::trace!(
+            "UIO shift TDI: {}",
+            HexSummary::new(tdi, TRACE_VECTOR_BYTES)
+        );
 
         let mut bits_left = num_bits;
         let mut iteration = 0u32;
         let mut tdo = Cursor::new(tdo);
+        let burst_bytes = 4 * self.fifo_words;
 
         while !tms.is_empty() {
-            let shift_num_bits = if tms.len() <= 4 { bits_left } else { 32 };
-            let shift_num_bytes = shift_num_bits.div_ceil(8);
+            let (shift_num_bits, shift_num_bytes) = if tms.len() <= burst_bytes {
+                (bits_left, tms.len())
+            } else {
+                (8 * burst_bytes as u32, burst_bytes)
+            };
 
             log::trace!(
                 "UIO shift iteration {}: bytes_left={}, bits_left={}, shift_num_bits={}",
@@ -92,46 +196,67 @@ impl MemoryMappedBackend {
                 shift_num_bits
             );
 
-            let read = unsafe {
-                write_volatile(self.mem.add(LENGTH_OFFSET), shift_num_bits);
-                write_volatile(
-                    self.mem.add(TMS_REG_OFFSET),
-                    u32_from_u8_slice(&tms[..shift_num_bytes as usize]),
-                );
-                write_volatile(
-                    self.mem.add(TDI_REG_OFFSET),
-                    u32_from_u8_slice(&tdi[..shift_num_bytes as usize]),
-                );
-                write_volatile(self.mem.add(CONTROL_REG_OFFSET), 0x01);
-
-                let poll_until_ready = || {
-                    let start = Instant::now();
-                    while start.elapsed() < self.poll_timeout {
-                        if read_volatile(self.mem.add(CONTROL_REG_OFFSET)) == 0 {
-                            return Ok(());
-                        }
-                    }
-                    Err(io::Error::new(
-                        io::ErrorKind::TimedOut,
-                        "Timed out while waiting for JTAG response",
-                    ))
-                };
-                poll_until_ready()?;
+            let region = &self.region;
+            region.write(LENGTH_OFFSET, shift_num_bits);
+            let tms_words = tms[..shift_num_bytes].chunks(4);
+            let tdi_words = tdi[..shift_num_bytes].chunks(4);
+            for (tms_word, tdi_word) in tms_words.zip(tdi_words) {
+                region.write(TMS_REG_OFFSET, register_word(tms_word));
+                region.write(TDI_REG_OFFSET, register_word(tdi_word));
+            }
+            // LENGTH, TMS and TDI must reach the bridge before the transfer is started
+            region.write_barrier();
+            region.write(CONTROL_REG_OFFSET, 0x01);
 
-                &read_volatile(self.mem.add(TDO_REG_OFFSET)).to_ne_bytes()
-                    [..shift_num_bytes as usize]
+            let poll_until_ready = || {
+                let start = Instant::now();
+                let record_wait = || {
+                    let waited = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+                    self.poll_wait_ns.fetch_add(waited, Ordering::Relaxed);
+                };
+                while start.elapsed() < self.poll_timeout {
+                    if region.read(CONTROL_REG_OFFSET) == 0 {
+                        record_wait();
+                        return Ok(());
+                    }
+                }
+                record_wait();
+                self.poll_timeouts.fetch_add(1, Ordering::Relaxed);
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Timed out while waiting for JTAG response",
+                ))
             };
+            let ready = poll_until_ready();
+            // TDO and the status are only valid once CONTROL was observed to be cleared
+            region.read_barrier();
+            // A failed AXI transaction explains a timeout
+            if let Err(e) = self.check_status() {
+                if ready.is_ok() {
+                    // Pop the TDO of the completed transfer, or the next one would return it
+                    for _ in 0..shift_num_bytes.div_ceil(4) {
+                        region.read(TDO_REG_OFFSET);
+                    }
+                }
+                return Err(e);
+            }
+            ready?;
 
-            log::trace!(
-                "UIO shift iteration {} result: tdo: {:02x?}",
-                iteration,
-                read
-            );
+            for tms_word in tms[..shift_num_bytes].chunks(4) {
+                let tdo_word = region.read(TDO_REG_OFFSET).to_le_bytes();
+                let read = &tdo_word[..tms_word.len()];
 
-            tdo.write_all(read)?;
+                log::trace!(
+                    "UIO shift iteration {} result: tdo: {:02x?}",
+                    iteration,
+                    read
+                );
 
-            tms = &tms[shift_num_bytes as usize..];
-            tdi = &tdi[shift_num_bytes as usize..];
+                tdo.write_all(read)?;
+            }
+
+            tms = &tms[shift_num_bytes..];
+            tdi = &tdi[shift_num_bytes..];
 
             bits_left -= shift_num_bits;
             iteration += 1;
@@ -140,3 +265,140 @@ impl MemoryMappedBackend {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::fake_bridge::{Burst, FifoBridge};
+
+    fn shift(bridge: &FifoBridge, depth: u32, num_bits: u32) {
+        let backend =
+            MemoryMappedBackend::new(bridge, Duration::from_secs(1)).with_fifo_depth(depth);
+        let num_bytes = num_bits.div_ceil(8) as usize;
+        let tdi: Vec<u8> = (0..num_bytes).map(|i| i as u8).collect();
+        let mut tdo = vec![0u8; num_bytes];
+        backend
+            .shift_data(num_bits, &vec![0; num_bytes], &tdi, &mut tdo)
+            .unwrap();
+        assert_eq!(tdo, tdi, "{num_bits} bits");
+    }
+
+    fn burst_lengths(depth: u32, num_bits: u32) -> Vec<u32> {
+        let bridge = FifoBridge::new(depth);
+        shift(&bridge, depth, num_bits);
+        bridge.bursts().iter().map(|b| b.length).collect()
+    }
+
+    #[test]
+    fn bursts_fill_the_fifo() {
+        assert_eq!(burst_lengths(512, 1), [1]);
+        assert_eq!(burst_lengths(512, 512), [512]);
+        assert_eq!(burst_lengths(512, 513), [512, 1]);
+        assert_eq!(burst_lengths(512, 1200), [512, 512, 176]);
+        assert_eq!(burst_lengths(128, 300), [128, 128, 44]);
+    }
+
+    #[test]
+    fn word_sized_fifo_shifts_word_by_word() {
+        assert_eq!(burst_lengths(32, 72), [32, 32, 8]);
+        // Depths are rounded down to whole words
+        assert_eq!(burst_lengths(40, 72), [32, 32, 8]);
+    }
+
+    #[test]
+    fn burst_words_hold_first_bit_in_lsb() {
+        let bridge = FifoBridge::new(64);
+        let backend = MemoryMappedBackend::new(&bridge, Duration::from_secs(1)).with_fifo_depth(64);
+        let mut tdo = [0u8; 5];
+        backend
+            .shift_data(
+                36,
+                &[0x01, 0x02, 0x03, 0x04, 0x05],
+                &[0x10, 0x20, 0x30, 0x40, 0x0F],
+                &mut tdo,
+            )
+            .unwrap();
+        assert_eq!(tdo, [0x10, 0x20, 0x30, 0x40, 0x0F]);
+        assert_eq!(
+            bridge.bursts(),
+            [Burst {
+                length: 36,
+                tms: vec![0x0403_0201, 0x05],
+                tdi: vec![0x4030_2010, 0x0F],
+            }]
+        );
+    }
+
+    fn with_status(bridge: &FifoBridge) -> MemoryMappedBackend<&FifoBridge> {
+        MemoryMappedBackend::new(bridge, Duration::from_secs(1)).with_status_register(
+            StatusRegister {
+                offset: FifoBridge::STATUS_REG_OFFSET,
+                slverr_bit: 0,
+                decerr_bit: 1,
+            },
+        )
+    }
+
+    #[test]
+    fn bus_errors_are_reported_and_cleared() {
+        let bridge = FifoBridge::new(32);
+        let backend = with_status(&bridge);
+
+        bridge.raise(0b01);
+        let err = backend.shift_data(8, &[0], &[0], &mut [0]).unwrap_err();
+        assert_eq!(
+            err.get_ref().and_then(|e| e.downcast_ref()),
+            Some(&BusError::SlaveError)
+        );
+        assert_eq!(bridge.status(), 0);
+
+        bridge.raise(0b11);
+        let err = backend.shift_data(8, &[0], &[0], &mut [0]).unwrap_err();
+        assert_eq!(
+            err.get_ref().and_then(|e| e.downcast_ref()),
+            Some(&BusError::DecodeError)
+        );
+        assert_eq!(bridge.status(), 0);
+
+        // The error is not repeated once the bits are cleared
+        backend.shift_data(8, &[0], &[0x5A], &mut [0]).unwrap();
+        let stats = backend.stats();
+        assert!(stats.contains(&("axi_slave_errors", 1)));
+        assert!(stats.contains(&("axi_decode_errors", 1)));
+    }
+
+    #[test]
+    fn burst_after_bus_error_returns_its_own_tdo() {
+        let bridge = FifoBridge::new(128);
+        let backend = with_status(&bridge).with_fifo_depth(128);
+
+        bridge.raise(0b01);
+        backend
+            .shift_data(96, &[0; 12], &[0xFF; 12], &mut [0; 12])
+            .unwrap_err();
+
+        let tdi: Vec<u8> = (0..12).collect();
+        let mut tdo = [0u8; 12];
+        backend.shift_data(96, &[0; 12], &tdi, &mut tdo).unwrap();
+        assert_eq!(tdo[..], tdi[..]);
+    }
+
+    #[test]
+    fn unrelated_status_bits_are_left_alone() {
+        let bridge = FifoBridge::new(32);
+        let backend = with_status(&bridge);
+        bridge.raise(0b100);
+        backend.shift_data(8, &[0], &[0], &mut [0]).unwrap();
+        assert_eq!(bridge.status(), 0b100);
+    }
+
+    #[test]
+    fn status_is_ignored_unless_configured() {
+        let bridge = FifoBridge::new(32);
+        let backend = MemoryMappedBackend::new(&bridge, Duration::from_secs(1));
+        bridge.raise(0b11);
+        backend.shift_data(8, &[0], &[0], &mut [0]).unwrap();
+        assert_eq!(bridge.status(), 0b11);
+        assert_eq!(backend.stats().len(), 2);
+    }
+}
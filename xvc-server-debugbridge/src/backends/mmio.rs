@@ -0,0 +1,151 @@
+//! Mapped register block of a memory-mapped debug bridge.
+//!
+//! # Memory ordering
+//!
+//! Volatile accesses are neither reordered nor elided by the compiler, but the CPU may still
+//! reorder them. On ARM, depending on the memory type of the mapping, the write that starts a
+//! transfer can reach the bridge before the data written ahead of it. Users of [`MmioRegion`]
+//! therefore have to order the accesses themselves:
+//!
+//! - [`write_barrier`](Registers::write_barrier) after writing the data registers and before
+//!   the register write that hands them to the bridge,
+//! - [`read_barrier`](Registers::read_barrier) after observing that the bridge completed and
+//!   before reading the registers it produced.
+//!
+//! The barriers are `dmb` instructions for the outer shareable domain on ARM and compile to
+//! compiler fences only on x86, where stores and loads are not reordered with each other.
+use std::{
+    fs::File,
+    io,
+    num::NonZero,
+    ptr::{NonNull, read_volatile, write_volatile},
+    sync::{
+        Arc,
+        atomic::{Ordering, compiler_fence},
+    },
+};
+
+use nix::sys::mman::{MapFlags, ProtFlags, mmap, munmap};
+
+/// Size of the mapped register block in bytes
+pub const MAP_SIZE: usize = 0x10000;
+
+/// Access to the 32-bit registers of a debug bridge, addressed by word offset.
+///
+/// Implemented by [`MmioRegion`] for real hardware and by register models in tests.
+pub trait Registers {
+    fn read(&self, offset: usize) -> u32;
+
+    fn write(&self, offset: usize, value: u32);
+
+    /// Makes all preceding register writes visible to the device before any subsequent one.
+    fn write_barrier(&self);
+
+    /// Completes all preceding register reads before any subsequent register access.
+    fn read_barrier(&self);
+}
+
+/// A shared mapping of [`MAP_SIZE`] bytes, unmapped when the last clone is dropped.
+///
+/// Registers are accessed as 32-bit words with volatile reads and writes.
+#[derive(Clone)]
+pub struct MmioRegion(Arc<Mapping>);
+
+struct Mapping(NonNull<u32>);
+
+// SAFETY: the mapping is valid until `Mapping` is dropped and only accessed with
+// volatile reads and writes, like hardware registers.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = munmap(self.0.cast(), MAP_SIZE);
+        }
+    }
+}
+
+impl MmioRegion {
+    /// Maps the register block of `file` (a UIO device or `/dev/mem`) at `offset`.
+    pub fn map_file(file: &File, offset: i64) -> io::Result<MmioRegion> {
+        let ptr = unsafe {
+            mmap(
+                None,
+                NonZero::new(MAP_SIZE).unwrap(),
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED,
+                file,
+                offset,
+            )?
+        };
+        Ok(MmioRegion(Arc::new(Mapping(ptr.cast()))))
+    }
+
+    /// Maps zeroed memory that is not backed by any device, e.g. to emulate a bridge in tests.
+    #[cfg(test)]
+    pub fn anonymous() -> io::Result<MmioRegion> {
+        let ptr = unsafe {
+            nix::sys::mman::mmap_anonymous(
+                None,
+                NonZero::new(MAP_SIZE).unwrap(),
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED,
+            )?
+        };
+        Ok(MmioRegion(Arc::new(Mapping(ptr.cast()))))
+    }
+}
+
+impl Registers for MmioRegion {
+    fn read(&self, offset: usize) -> u32 {
+        assert!(offset < MAP_SIZE / 4);
+        unsafe { read_volatile(self.0.0.as_ptr().add(offset)) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        assert!(offset < MAP_SIZE / 4);
+        unsafe { write_volatile(self.0.0.as_ptr().add(offset), value) }
+    }
+
+    #[inline]
+    fn write_barrier(&self) {
+        compiler_fence(Ordering::SeqCst);
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            std::arch::asm!("dmb oshst", options(nostack, preserves_flags));
+        }
+        #[cfg(target_arch = "arm")]
+        unsafe {
+            std::arch::asm!("dmb oshst", options(nostack, preserves_flags));
+        }
+        #[cfg(not(any(
+            target_arch = "aarch64",
+            target_arch = "arm",
+            target_arch = "x86",
+            target_arch = "x86_64"
+        )))]
+        std::sync::atomic::fence(Ordering::SeqCst);
+    }
+
+    #[inline]
+    fn read_barrier(&self) {
+        compiler_fence(Ordering::SeqCst);
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            std::arch::asm!("dmb oshld", options(nostack, preserves_flags));
+        }
+        // ARMv7 has no load-only variant
+        #[cfg(target_arch = "arm")]
+        unsafe {
+            std::arch::asm!("dmb osh", options(nostack, preserves_flags));
+        }
+        #[cfg(not(any(
+            target_arch = "aarch64",
+            target_arch = "arm",
+            target_arch = "x86",
+            target_arch = "x86_64"
+        )))]
+        std::sync::atomic::fence(Ordering::SeqCst);
+    }
+}
@@ -1,5 +1,10 @@
 //! Implementation of different Debug Bridge devices.
+pub(crate) mod common;
 pub mod devmem;
+#[cfg(test)]
+pub(crate) mod fake_bridge;
 pub mod kernel_driver;
 pub(crate) mod memory_mapped;
+pub mod mmio;
 pub mod uio;
+pub mod xdma;
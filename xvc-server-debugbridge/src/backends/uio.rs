@@ -13,18 +13,23 @@
 //! let server = Server::new(driver, Config::default());
 //! server.listen("127.0.0.1:2542")?;
 //! ```
-use std::{fs::OpenOptions, io, num::NonZero, path::Path, ptr::NonNull, time::Duration};
-
-use nix::sys::mman::{MapFlags, ProtFlags, mmap, munmap};
+use std::{fs::OpenOptions, io, path::Path, time::Duration};
 
 use crate::{
     XvcServer,
-    backends::memory_mapped::{MAP_SIZE, MemoryMappedBackend},
+    backends::{
+        memory_mapped::{MemoryMappedBackend, StatusRegister},
+        mmio::{MAP_SIZE, MmioRegion},
+    },
 };
 
 /// Debug bridge driver based on a Uio device
 pub struct UioDriverBackend(MemoryMappedBackend);
 
+/// The bytes of each vector that are shifted at a time. The registers take 32 bits at a time,
+/// so long shifts are streamed rather than buffered.
+const SHIFT_CHUNK_LEN: usize = 64 * 1024;
+
 impl UioDriverBackend {
     pub fn new(path: impl AsRef<Path>, poll_timeout: Duration) -> io::Result<UioDriverBackend> {
         let device_path = path.as_ref();
@@ -32,33 +37,26 @@ impl UioDriverBackend {
         let file = OpenOptions::new().read(true).write(true).open(path)?;
         log::debug!("UIO device file opened successfully");
 
-        let jtag = unsafe {
-            log::debug!("Mapping UIO memory (size=0x{:x})", MAP_SIZE);
-            let ptr = mmap(
-                None,
-                NonZero::new(MAP_SIZE).unwrap(),
-                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
-                MapFlags::MAP_SHARED,
-                file,
-                0,
-            )?;
-            log::info!("UIO memory mapped successfully");
-            ptr.as_ptr() as *mut u32
-        };
-        Ok(UioDriverBackend(MemoryMappedBackend::new(
-            jtag,
-            poll_timeout,
-        )))
+        log::debug!("Mapping UIO memory (size=0x{:x})", MAP_SIZE);
+        let region = MmioRegion::map_file(&file, 0)?;
+        log::info!("UIO memory mapped successfully");
+        Ok(UioDriverBackend::from_region(region, poll_timeout))
     }
-}
 
-impl Drop for UioDriverBackend {
-    fn drop(&mut self) {
-        if let Some(ptr) = NonNull::new(self.0.mem) {
-            unsafe {
-                let _ = munmap(ptr.cast(), MAP_SIZE);
-            }
-        }
+    /// Uses an already mapped register block instead of opening a UIO device.
+    pub fn from_region(region: MmioRegion, poll_timeout: Duration) -> UioDriverBackend {
+        UioDriverBackend(MemoryMappedBackend::new(region, poll_timeout))
+    }
+
+    /// Shifts in bursts of up to `fifo_depth` bits for bridges with FIFOs behind the data
+    /// registers. The depth is rounded down to whole 32-bit words.
+    pub fn with_fifo_depth(self, fifo_depth: u32) -> UioDriverBackend {
+        UioDriverBackend(self.0.with_fifo_depth(fifo_depth))
+    }
+
+    /// Checks the sticky AXI error bits of `status` after every transfer.
+    pub fn with_status_register(self, status: StatusRegister) -> UioDriverBackend {
+        UioDriverBackend(self.0.with_status_register(status))
     }
 }
 
@@ -78,4 +76,110 @@ impl XvcServer for UioDriverBackend {
     ) -> Result<(), Self::Err> {
         self.0.shift_data(num_bits, tms, tdi, tdo)
     }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        self.0.stats()
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        Some(SHIFT_CHUNK_LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::fake_bridge::{FakeBridge, Transfer};
+
+    fn backend(bridge: &FakeBridge) -> UioDriverBackend {
+        UioDriverBackend::from_region(bridge.region(), Duration::from_secs(1))
+    }
+
+    #[test]
+    fn loopback_shift() {
+        let bridge = FakeBridge::loopback();
+        let backend = backend(&bridge);
+        let mut tdo = [0u8; 1];
+        backend.shift(8, &[0xFF], &[0xA5], &mut tdo).unwrap();
+        assert_eq!(tdo, [0xA5]);
+        assert_eq!(
+            bridge.transfers(),
+            [Transfer {
+                length: 8,
+                tms: 0xFF,
+                tdi: 0xA5
+            }]
+        );
+    }
+
+    #[test]
+    fn shifts_are_split_at_word_boundaries() {
+        for (num_bits, lengths) in [
+            (1, &[1][..]),
+            (32, &[32]),
+            (33, &[32, 1]),
+            (64, &[32, 32]),
+            (72, &[32, 32, 8]),
+        ] {
+            let bridge = FakeBridge::loopback();
+            let backend = backend(&bridge);
+            let num_bytes = (num_bits as usize).div_ceil(8);
+            let tdi: Vec<u8> = (0..num_bytes as u8).collect();
+            let mut tdo = vec![0u8; num_bytes];
+            backend
+                .shift(num_bits, &vec![0; num_bytes], &tdi, &mut tdo)
+                .unwrap();
+            assert_eq!(tdo, tdi, "{num_bits} bits");
+            let transfer_lengths: Vec<u32> = bridge.transfers().iter().map(|t| t.length).collect();
+            assert_eq!(transfer_lengths, lengths, "{num_bits} bits");
+        }
+    }
+
+    #[test]
+    fn registers_hold_first_bit_in_lsb() {
+        let bridge = FakeBridge::spawn(|_| Some(0x4433_2211));
+        let backend = backend(&bridge);
+
+        let mut tdo = [0u8; 4];
+        backend
+            .shift(
+                32,
+                &[0x01, 0x02, 0x03, 0x04],
+                &[0x10, 0x20, 0x30, 0x40],
+                &mut tdo,
+            )
+            .unwrap();
+        assert_eq!(tdo, [0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(
+            bridge.transfers()[0],
+            Transfer {
+                length: 32,
+                tms: 0x0403_0201,
+                tdi: 0x4030_2010
+            }
+        );
+
+        // Only the bytes covering the shifted bits are returned
+        let mut tdo = [0u8; 2];
+        backend.shift(12, &[0, 0], &[0, 0], &mut tdo).unwrap();
+        assert_eq!(tdo, [0x11, 0x22]);
+    }
+
+    #[test]
+    fn bridge_that_never_completes_times_out() {
+        let bridge = FakeBridge::stuck();
+        let backend = UioDriverBackend::from_region(bridge.region(), Duration::from_millis(20));
+        let err = backend.shift(8, &[0], &[0], &mut [0]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(backend.stats().contains(&("poll_timeouts", 1)));
+    }
+
+    #[test]
+    fn mismatched_vector_sizes_are_rejected() {
+        let bridge = FakeBridge::loopback();
+        let backend = backend(&bridge);
+        assert!(backend.shift(16, &[0], &[0, 0], &mut [0, 0]).is_err());
+        assert!(backend.shift(16, &[0, 0], &[0, 0], &mut [0]).is_err());
+        assert!(bridge.transfers().is_empty());
+    }
 }
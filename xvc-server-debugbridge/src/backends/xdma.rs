@@ -0,0 +1,210 @@
+//! # XDMA Driver Backend
+//!
+//! For PCIe boards that expose XVC through the
+//! [XDMA driver](https://github.com/Xilinx/dma_ip_drivers/tree/master/XDMA/linux-kernel)
+//! as a `/dev/xdma0_xvc` character device.
+//!
+//! Driver versions without the XVC node still expose the user BAR (`/dev/xdma0_user`).
+//! If the debug bridge registers are located in that BAR, they can be accessed directly
+//! with [`XdmaBackend::with_bar_offset`].
+//!
+//! ## Example Usage
+//!
+//! ```ignore
+//! use xvc_server_debugbridge::backends::xdma::XdmaBackend;
+//! use xvc_server::server::{Server, Config};
+//!
+//! let driver = XdmaBackend::new("/dev/xdma0_xvc")?;
+//! let server = Server::new(driver, Config::default());
+//! server.listen("127.0.0.1:2542")?;
+//! ```
+use nix::ioctl_readwrite_bad;
+use std::{
+    ffi::{c_uchar, c_uint},
+    fs::{File, OpenOptions},
+    io,
+    os::fd::AsRawFd,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use xvc_protocol::HexSummary;
+
+use crate::{
+    XvcServer,
+    backends::{
+        common::{
+            IOC_READ_WRITE, TRACE_VECTOR_BYTES, XVC_MAGIC, check_vector_lengths, ioc, ioctl_result,
+        },
+        memory_mapped::MemoryMappedBackend,
+        mmio::MmioRegion,
+    },
+};
+
+/// `struct xvc_ioc` from the XDMA driver's `cdev_xvc.h`
+#[repr(C)]
+#[derive(Clone, Debug)]
+struct XdmaXvcIoc {
+    opcode: c_uint,
+    length: c_uint,
+    tms_buf: *const c_uchar,
+    tdi_buf: *const c_uchar,
+    tdo_buf: *mut c_uchar,
+}
+
+/// Opcode of a plain shift
+const XDMA_XVC_OPCODE_SHIFT: c_uint = 0x01;
+
+const XDMA_IOCXVC_NR: u32 = ioc(IOC_READ_WRITE, XVC_MAGIC, 1, size_of::<XdmaXvcIoc>());
+
+// Perform a shift operation
+ioctl_readwrite_bad!(xdma_xvc_ioc, XDMA_IOCXVC_NR, XdmaXvcIoc);
+
+enum Access {
+    Ioctl { file: File, ioctl_errors: AtomicU64 },
+    Bar(MemoryMappedBackend),
+}
+
+/// A debug bridge behind the XDMA PCIe driver.
+pub struct XdmaBackend(Access);
+
+impl XdmaBackend {
+    /// Shifts through the XVC node of the driver, e.g. `/dev/xdma0_xvc`.
+    pub fn new(device_path: impl AsRef<Path>) -> io::Result<XdmaBackend> {
+        let path = device_path.as_ref();
+        log::debug!("Opening XDMA XVC device: {}", path.display());
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        log::info!("XDMA XVC device {} opened", path.display());
+        Ok(XdmaBackend(Access::Ioctl {
+            file,
+            ioctl_errors: AtomicU64::new(0),
+        }))
+    }
+
+    /// Accesses the debug bridge registers at `bar_offset` in the user BAR,
+    /// e.g. `/dev/xdma0_user`, without going through the driver's XVC support.
+    pub fn with_bar_offset(
+        user_bar_path: impl AsRef<Path>,
+        bar_offset: u64,
+        poll_timeout: Duration,
+    ) -> io::Result<XdmaBackend> {
+        let path = user_bar_path.as_ref();
+        log::debug!(
+            "Mapping XDMA user BAR {} at offset 0x{:x}",
+            path.display(),
+            bar_offset
+        );
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let offset = i64::try_from(bar_offset)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "BAR offset too large"))?;
+        let region = MmioRegion::map_file(&file, offset)?;
+        log::info!("XDMA user BAR mapped successfully");
+        Ok(XdmaBackend(Access::Bar(MemoryMappedBackend::new(
+            region,
+            poll_timeout,
+        ))))
+    }
+
+    fn shift_ioctl(
+        file: &File,
+        ioctl_errors: &AtomicU64,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> io::Result<()> {
+        check_vector_lengths(num_bits, tms, tdi, tdo)?;
+        log::debug!("XDMA shift: num_bits={}", num_bits);
+        log::trace!(
+            "XDMA shift TMS: {}",
+            HexSummary::new(tms, TRACE_VECTOR_BYTES)
+        );
+        log::trace!(
+            "XDMA shift TDI: {}",
+            HexSummary::new(tdi, TRACE_VECTOR_BYTES)
+        );
+
+        let mut xvc_ioc = XdmaXvcIoc {
+            opcode: XDMA_XVC_OPCODE_SHIFT,
+            length: num_bits as c_uint,
+            tms_buf: tms.as_ptr(),
+            tdi_buf: tdi.as_ptr(),
+            tdo_buf: tdo.as_mut_ptr(),
+        };
+        // SAFETY: The file descriptor is open, the buffers are valid for the duration of the
+        // call and their sizes match `num_bits`.
+        let result = unsafe { xdma_xvc_ioc(file.as_raw_fd(), &mut xvc_ioc) };
+        ioctl_result(result, ioctl_errors)
+    }
+}
+
+impl XvcServer for XdmaBackend {
+    type Err = io::Error;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Self::Err> {
+        Ok(period_ns)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err> {
+        match &self.0 {
+            Access::Ioctl { file, ioctl_errors } => {
+                Self::shift_ioctl(file, ioctl_errors, num_bits, tms, tdi, tdo)
+            }
+            Access::Bar(backend) => backend.shift_data(num_bits, tms, tdi, tdo),
+        }
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        match &self.0 {
+            Access::Ioctl { ioctl_errors, .. } => {
+                vec![("ioctl_errors", ioctl_errors.load(Ordering::Relaxed))]
+            }
+            Access::Bar(backend) => backend.stats(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+
+    use super::*;
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn ioctl_struct_layout() {
+        assert_eq!(size_of::<XdmaXvcIoc>(), 32);
+        assert_eq!(offset_of!(XdmaXvcIoc, opcode), 0);
+        assert_eq!(offset_of!(XdmaXvcIoc, length), 4);
+        assert_eq!(offset_of!(XdmaXvcIoc, tms_buf), 8);
+        assert_eq!(offset_of!(XdmaXvcIoc, tdi_buf), 16);
+        assert_eq!(offset_of!(XdmaXvcIoc, tdo_buf), 24);
+        assert_eq!(XDMA_IOCXVC_NR, 0xD6634401);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "32")]
+    fn ioctl_struct_layout() {
+        assert_eq!(size_of::<XdmaXvcIoc>(), 20);
+        assert_eq!(offset_of!(XdmaXvcIoc, tms_buf), 8);
+        assert_eq!(offset_of!(XdmaXvcIoc, tdi_buf), 12);
+        assert_eq!(offset_of!(XdmaXvcIoc, tdo_buf), 16);
+    }
+
+    #[test]
+    fn shift_on_non_xdma_device_counts_error() {
+        // Not an XDMA device: the ioctl is rejected
+        let file = File::open("/dev/null").unwrap();
+        let errors = AtomicU64::new(0);
+        let mut tdo = [0u8; 1];
+        let result = XdmaBackend::shift_ioctl(&file, &errors, 8, &[0], &[0], &mut tdo);
+        assert!(result.is_err());
+        assert_eq!(errors.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,219 @@
+//! Binding the listening socket with retries.
+//!
+//! When the service is restarted quickly, the previous instance may still hold the port.
+//! [`bind_with_retry`] keeps retrying with exponential backoff until a time budget is exhausted,
+//! unless the port is held by another live XVC server, in which case it fails immediately.
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout},
+};
+use xvc_protocol::{BorrowedMessage, XvcInfo};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// What is holding a port that could not be bound.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PortOccupant {
+    /// A live XVC server answered `getinfo:`
+    XvcServer(XvcInfo),
+    /// Nothing answered `getinfo:`. This is usually a socket from a previous instance that
+    /// has not been released yet.
+    Unknown,
+}
+
+/// Checks whether a live XVC server is answering on `addr`.
+pub async fn probe_port(addr: SocketAddr, probe_timeout: Duration) -> PortOccupant {
+    match timeout(probe_timeout, request_info(probe_address(addr))).await {
+        Ok(Ok(info)) => PortOccupant::XvcServer(info),
+        Ok(Err(e)) => {
+            log::debug!("Port probe on {} failed: {}", addr, e);
+            PortOccupant::Unknown
+        }
+        Err(_) => {
+            log::debug!("Port probe on {} timed out", addr);
+            PortOccupant::Unknown
+        }
+    }
+}
+
+/// Wildcard addresses cannot be connected to; probe the loopback address instead.
+fn probe_address(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), addr.port())
+        }
+        IpAddr::V6(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), addr.port())
+        }
+        _ => addr,
+    }
+}
+
+async fn request_info(addr: SocketAddr) -> io::Result<XvcInfo> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let mut request = Vec::new();
+    BorrowedMessage::GetInfo.write_to(&mut request)?;
+    stream.write_all(&request).await?;
+
+    let mut buf = Vec::new();
+    while !buf.contains(&b'\n') {
+        if stream.read_buf(&mut buf).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+    XvcInfo::from_reader(&mut buf.as_slice()).map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// Binds to `addr`, retrying with exponential backoff for at most `budget` while the address
+/// is in use. Fails immediately if the address is held by a live XVC server.
+pub async fn bind_with_retry(addr: SocketAddr, budget: Duration) -> io::Result<TcpListener> {
+    let start = Instant::now();
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1u32;
+    loop {
+        let err = match TcpListener::bind(addr).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => e,
+            Err(e) => return Err(e),
+        };
+        if let PortOccupant::XvcServer(info) = probe_port(addr, PROBE_TIMEOUT).await {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!(
+                    "another XVC server (version {}) is already serving on {}",
+                    info.version(),
+                    addr
+                ),
+            ));
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= budget {
+            if attempt > 1 {
+                log::error!("Giving up binding {} after {} attempts", addr, attempt);
+            }
+            return Err(err);
+        }
+        let wait = backoff.min(budget - elapsed);
+        log::warn!(
+            "Address {} in use (attempt {}), retrying in {} ms",
+            addr,
+            attempt,
+            wait.as_millis()
+        );
+        sleep(wait).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        attempt += 1;
+    }
+}
+
+/// Parses a duration such as `30s`, `500ms` or `2m`. A plain number is interpreted as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let invalid = || format!("invalid duration '{s}'");
+    let value: u64 = value.parse().map_err(|_| invalid())?;
+    match unit {
+        "" | "s" => Ok(Duration::from_secs(value)),
+        "ms" => Ok(Duration::from_millis(value)),
+        "m" => value
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(invalid),
+        other => Err(format!("unknown duration unit '{other}' (use ms, s or m)")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xvc_protocol::Version;
+
+    use super::*;
+
+    /// Occupies a port with a listener that answers `getinfo:` like an XVC server.
+    async fn answering_listener() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 8];
+                if stream.read_exact(&mut buf).await.is_ok() {
+                    let _ = stream.write_all(b"xvcServer_v1.0:2048\n").await;
+                }
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert!(parse_duration("2h").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("307445734561825861m").is_err());
+    }
+
+    #[tokio::test]
+    async fn probe_detects_live_xvc_server() {
+        let addr = answering_listener().await;
+        assert_eq!(
+            probe_port(addr, PROBE_TIMEOUT).await,
+            PortOccupant::XvcServer(XvcInfo::new(Version::V1_0, 2048))
+        );
+    }
+
+    #[tokio::test]
+    async fn probe_classifies_silent_listener_as_unknown() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert_eq!(
+            probe_port(addr, Duration::from_millis(100)).await,
+            PortOccupant::Unknown
+        );
+    }
+
+    #[tokio::test]
+    async fn bind_fails_immediately_when_xvc_server_is_live() {
+        let addr = answering_listener().await;
+        let start = Instant::now();
+        let err = bind_with_retry(addr, Duration::from_secs(10))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(err.to_string().contains("another XVC server"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn bind_retries_until_port_is_released() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(300)).await;
+            drop(listener);
+        });
+        bind_with_retry(addr, Duration::from_secs(10))
+            .await
+            .expect("bind should succeed once the port is released");
+    }
+
+    #[tokio::test]
+    async fn bind_without_budget_fails_on_busy_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let err = bind_with_retry(addr, Duration::ZERO).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+}
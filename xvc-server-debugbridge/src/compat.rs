@@ -0,0 +1,343 @@
+//! Compatibility with the command line of the reference Xilinx `xvcserver`.
+//!
+//! The reference implementation selects the listening address with
+//! `-s <transport>:<host>:<port>` (e.g. `-s TCP::2542`) and enables verbose output with `-v`.
+//! [`translate_legacy_args`] rewrites such invocations into the native `--ip`/`--port` options
+//! before they are handed to the argument parser, so that existing scripts keep working.
+use std::fmt::{self, Display};
+
+/// Subcommands; everything after a subcommand is passed through unchanged.
+const SUBCOMMANDS: &[&str] = &[
+    "kernel-driver",
+    "uio-driver",
+    "dev-mem-driver",
+    "xdma-driver",
+    "list-devices",
+    "help",
+];
+
+/// Subcommands whose poll timeout used to be `-p`, before `-p` became the device path.
+const RENAMED_POLL_TIMEOUT: &[&str] = &["dev-mem-driver"];
+
+/// Native global options that take a value.
+const OPTIONS_WITH_VALUE: &[&str] = &[
+    "-p",
+    "--port",
+    "-i",
+    "--ip",
+    "--compatible",
+    "--bind-retry",
+    "--stats-file",
+    "--throttle",
+    "--latency",
+    "--config",
+];
+
+/// Flags of the reference implementation that have no direct equivalent, with a hint
+/// naming the native way to achieve the same.
+const UNSUPPORTED_LEGACY_FLAGS: &[(&str, &str)] = &[(
+    "-d",
+    "select the device with a subcommand instead, e.g. `kernel-driver /dev/xilinx_xvc_driver` or `uio-driver /dev/uio0`",
+)];
+
+/// Errors when translating legacy arguments.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CompatError {
+    /// `-s` was given without a value
+    MissingAddress,
+    /// The `-s` value is not of the form `<transport>:<host>:<port>`
+    InvalidAddress(String),
+    /// Only TCP is supported as transport
+    UnsupportedTransport(String),
+    /// Both the legacy and a native option specify the same setting
+    Conflict { legacy: String, native: String },
+    /// A flag of the reference implementation that is not supported
+    UnsupportedFlag { flag: String, hint: &'static str },
+}
+
+impl Display for CompatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompatError::MissingAddress => {
+                write!(f, "-s expects an address like tcp::2542")
+            }
+            CompatError::InvalidAddress(address) => write!(
+                f,
+                "invalid address '{}': expected <transport>:<host>:<port>, e.g. tcp::2542",
+                address
+            ),
+            CompatError::UnsupportedTransport(transport) => {
+                write!(
+                    f,
+                    "unsupported transport '{}': only tcp is supported",
+                    transport
+                )
+            }
+            CompatError::Conflict { legacy, native } => write!(
+                f,
+                "'{}' conflicts with '{}': use either the legacy -s option or --ip/--port",
+                legacy, native
+            ),
+            CompatError::UnsupportedFlag { flag, hint } => {
+                write!(f, "the legacy option '{}' is not supported: {}", flag, hint)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompatError {}
+
+/// Translates `-s <transport>:<host>:<port>` into `--ip <host> --port <port>`.
+fn translate_address(address: &str) -> Result<[String; 4], CompatError> {
+    let mut parts = address.splitn(3, ':');
+    let (Some(transport), Some(host), Some(port)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(CompatError::InvalidAddress(address.to_owned()));
+    };
+    if !transport.eq_ignore_ascii_case("tcp") {
+        return Err(CompatError::UnsupportedTransport(transport.to_owned()));
+    }
+    if port.parse::<u16>().is_err() {
+        return Err(CompatError::InvalidAddress(address.to_owned()));
+    }
+    // The reference implementation listens on all interfaces if no host is given
+    let host = match host {
+        "" => "0.0.0.0",
+        "localhost" => "127.0.0.1",
+        host => host,
+    };
+    Ok([
+        "--ip".to_owned(),
+        host.to_owned(),
+        "--port".to_owned(),
+        port.to_owned(),
+    ])
+}
+
+/// Returns the native option if `arg` sets the listening address or port.
+fn native_address_option(arg: &str) -> Option<&str> {
+    let name = arg.split_once('=').map_or(arg, |(name, _)| name);
+    match name {
+        "--ip" | "--port" => Some(name),
+        _ if arg.starts_with("-i") || arg.starts_with("-p") => Some(&arg[..2]),
+        _ => None,
+    }
+}
+
+/// Rewrites `-p <microseconds>` of subcommands in [`RENAMED_POLL_TIMEOUT`] into `-t`.
+///
+/// A purely numeric value can only be the old poll timeout, never a device path.
+fn translate_poll_timeout(args: impl Iterator<Item = String>) -> Vec<String> {
+    let is_timeout = |value: &str| !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit());
+    let mut args = args.peekable();
+    let mut translated = Vec::new();
+    while let Some(arg) = args.next() {
+        let value = arg
+            .strip_prefix("-p")
+            .map(|v| v.strip_prefix('=').unwrap_or(v));
+        match value {
+            Some("") if args.peek().is_some_and(|next| is_timeout(next)) => {
+                translated.push("-t".to_owned());
+            }
+            Some(value) if is_timeout(value) => translated.push(format!("-t{}", value)),
+            _ => {
+                translated.push(arg);
+                continue;
+            }
+        }
+        eprintln!("hint: the poll timeout is now set with -t, -p selects the device path");
+    }
+    translated
+}
+
+/// Rewrites legacy `xvcserver` arguments into native ones.
+///
+/// `args` includes the program name. Arguments after a subcommand are not touched, except
+/// for the renamed poll timeout of the subcommands in [`RENAMED_POLL_TIMEOUT`].
+pub fn translate_legacy_args(
+    args: impl IntoIterator<Item = String>,
+) -> Result<Vec<String>, CompatError> {
+    let mut args = args.into_iter();
+    let mut translated: Vec<String> = args.next().into_iter().collect();
+    let mut legacy_address = None;
+    let mut native_address = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "--" || SUBCOMMANDS.contains(&arg.as_str()) {
+            let renamed = RENAMED_POLL_TIMEOUT.contains(&arg.as_str());
+            translated.push(arg);
+            if renamed {
+                translated.extend(translate_poll_timeout(&mut args));
+            }
+            break;
+        }
+        if let Some(value) = arg.strip_prefix("-s") {
+            let address = if value.is_empty() {
+                args.next().ok_or(CompatError::MissingAddress)?
+            } else {
+                value.to_owned()
+            };
+            translated.extend(translate_address(&address)?);
+            legacy_address = Some(format!("-s {}", address));
+            continue;
+        }
+        if let Some((flag, hint)) = UNSUPPORTED_LEGACY_FLAGS
+            .iter()
+            .find(|(flag, _)| arg.starts_with(flag))
+        {
+            return Err(CompatError::UnsupportedFlag {
+                flag: flag.to_string(),
+                hint,
+            });
+        }
+        if let Some(option) = native_address_option(&arg) {
+            native_address = Some(option.to_owned());
+        }
+        let takes_value = OPTIONS_WITH_VALUE.contains(&arg.as_str());
+        translated.push(arg);
+        if takes_value && let Some(value) = args.next() {
+            translated.push(value);
+        }
+    }
+    translated.extend(args);
+
+    if let (Some(legacy), Some(native)) = (legacy_address, native_address) {
+        return Err(CompatError::Conflict { legacy, native });
+    }
+    Ok(translated)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::*;
+    use crate::{Args, DeviceImpl};
+
+    fn translate(args: &[&str]) -> Result<Vec<String>, CompatError> {
+        translate_legacy_args(
+            std::iter::once("xvc-bridge")
+                .chain(args.iter().copied())
+                .map(str::to_owned),
+        )
+    }
+
+    fn parse(args: &[&str]) -> Args {
+        Args::try_parse_from(translate(args).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn native_arguments_are_unchanged() {
+        let args = [
+            "--port",
+            "2543",
+            "-i",
+            "127.0.0.1",
+            "uio-driver",
+            "/dev/uio0",
+        ];
+        assert_eq!(translate(&args).unwrap()[1..], args);
+    }
+
+    #[test]
+    fn reference_invocation_listens_on_all_interfaces() {
+        let args = parse(&["-s", "TCP::2542"]);
+        assert_eq!(args.ip.to_string(), "0.0.0.0");
+        assert_eq!(args.port, 2542);
+    }
+
+    #[test]
+    fn reference_invocation_with_host_and_verbose() {
+        let args = parse(&["-v", "-s", "tcp:127.0.0.1:10200"]);
+        assert_eq!(args.ip.to_string(), "127.0.0.1");
+        assert_eq!(args.port, 10200);
+        assert!(args.verbose);
+    }
+
+    #[test]
+    fn attached_address_value() {
+        let args = parse(&["-stcp:localhost:2542"]);
+        assert_eq!(args.ip.to_string(), "127.0.0.1");
+        assert_eq!(args.port, 2542);
+    }
+
+    #[test]
+    fn subcommand_arguments_are_not_translated() {
+        let args = parse(&[
+            "-s",
+            "tcp::2542",
+            "dev-mem-driver",
+            "0xA0000000",
+            "-p",
+            "/dev/mem",
+        ]);
+        assert_eq!(args.port, 2542);
+        assert!(matches!(
+            args.device,
+            Some(DeviceImpl::DevMemDriver {
+                address: 0xA000_0000,
+                path: Some(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn old_dev_mem_poll_timeout_is_translated() {
+        for timeout in [&["-p", "500"][..], &["-p500"], &["-p=500"]] {
+            let args = parse(&[&["dev-mem-driver", "0xA0000000"][..], timeout].concat());
+            assert!(matches!(
+                args.device,
+                Some(DeviceImpl::DevMemDriver {
+                    poll_timeout_us: 500,
+                    path: None,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn arguments_of_all_subcommands_are_unique() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn legacy_and_native_address_conflict() {
+        assert_eq!(
+            translate(&["-s", "tcp::2542", "--port", "2543"]),
+            Err(CompatError::Conflict {
+                legacy: "-s tcp::2542".to_owned(),
+                native: "--port".to_owned()
+            })
+        );
+        assert!(matches!(
+            translate(&["-i", "127.0.0.1", "-s", "tcp::2542"]),
+            Err(CompatError::Conflict { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_legacy_addresses() {
+        assert_eq!(translate(&["-s"]), Err(CompatError::MissingAddress));
+        assert_eq!(
+            translate(&["-s", "udp::2542"]),
+            Err(CompatError::UnsupportedTransport("udp".to_owned()))
+        );
+        assert!(matches!(
+            translate(&["-s", "tcp:2542"]),
+            Err(CompatError::InvalidAddress(_))
+        ));
+        assert!(matches!(
+            translate(&["-s", "tcp::port"]),
+            Err(CompatError::InvalidAddress(_))
+        ));
+    }
+
+    #[test]
+    fn unsupported_legacy_flag_names_native_equivalent() {
+        let err = translate(&["-d", "/dev/xilinx_xvc_driver"]).unwrap_err();
+        assert!(matches!(err, CompatError::UnsupportedFlag { .. }));
+        assert!(err.to_string().contains("kernel-driver"));
+    }
+}
@@ -0,0 +1,582 @@
+//! Auto-detection of debug bridges present on the system.
+//!
+//! Candidates are collected from four sources, in priority order:
+//!
+//! 1. The Xilinx kernel driver node (`/dev/xilinx_xvc_driver`)
+//! 2. XVC nodes of the XDMA PCIe driver (`/dev/xdma*_xvc*`, or `xvc*` inside `/dev/xdma*/`)
+//! 3. UIO devices whose name is `debug_bridge`
+//! 4. Device-tree nodes whose `compatible` property matches one of the configured patterns.
+//!    The `reg` base address of such a node is correlated with the UIO devices in
+//!    `/sys/class/uio`; if no UIO device maps that address, the `/dev/mem` backend
+//!    is suggested instead.
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+
+const KERNEL_DRIVER_PATH: &str = "/dev/xilinx_xvc_driver";
+const UIO_CLASS_PATH: &str = "/sys/class/uio";
+const DEVICE_TREE_PATH: &str = "/sys/firmware/devicetree/base";
+
+/// Default substrings matched against the device-tree `compatible` property.
+pub const DEFAULT_COMPATIBLE: [&str; 2] = ["xlnx,xvc", "debug_bridge"];
+
+/// Where a candidate was found.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Provenance {
+    /// The kernel driver node exists at its well-known path.
+    KernelDriverNode,
+    /// A node created by the XDMA driver.
+    XdmaNode,
+    /// A UIO device is named `debug_bridge`.
+    UioName,
+    /// A device-tree node matched one of the compatible patterns.
+    DeviceTree { node: PathBuf },
+}
+
+impl Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provenance::KernelDriverNode => write!(f, "kernel driver node"),
+            Provenance::XdmaNode => write!(f, "XDMA driver node"),
+            Provenance::UioName => write!(f, "UIO device name"),
+            Provenance::DeviceTree { node } => write!(f, "device tree node {}", node.display()),
+        }
+    }
+}
+
+/// A debug bridge that could be served, together with the backend that should be used.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Candidate {
+    KernelDriver {
+        path: PathBuf,
+        provenance: Provenance,
+    },
+    Xdma {
+        path: PathBuf,
+        provenance: Provenance,
+    },
+    Uio {
+        path: PathBuf,
+        provenance: Provenance,
+    },
+    DevMem {
+        address: u64,
+        provenance: Provenance,
+    },
+}
+
+impl Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Candidate::KernelDriver { path, provenance } => {
+                write!(f, "kernel-driver {} (from {})", path.display(), provenance)
+            }
+            Candidate::Xdma { path, provenance } => {
+                write!(f, "xdma-driver {} (from {})", path.display(), provenance)
+            }
+            Candidate::Uio { path, provenance } => {
+                write!(f, "uio-driver {} (from {})", path.display(), provenance)
+            }
+            Candidate::DevMem {
+                address,
+                provenance,
+            } => write!(f, "dev-mem-driver 0x{:x} (from {})", address, provenance),
+        }
+    }
+}
+
+/// Paths that are inspected during detection.
+/// Overridable so that the detection logic can be exercised against fixture directories.
+#[derive(Debug, Clone)]
+pub struct DetectionRoots {
+    pub kernel_driver: PathBuf,
+    pub uio_class: PathBuf,
+    pub device_tree: PathBuf,
+    pub dev: PathBuf,
+}
+
+impl Default for DetectionRoots {
+    fn default() -> Self {
+        DetectionRoots {
+            kernel_driver: PathBuf::from(KERNEL_DRIVER_PATH),
+            uio_class: PathBuf::from(UIO_CLASS_PATH),
+            device_tree: PathBuf::from(DEVICE_TREE_PATH),
+            dev: PathBuf::from("/dev"),
+        }
+    }
+}
+
+/// Attempts to automatically find the path to the Debug Bridge kernel driver
+pub fn kernel_driver_path() -> Option<PathBuf> {
+    kernel_driver_path_in(&DetectionRoots::default())
+}
+
+fn kernel_driver_path_in(roots: &DetectionRoots) -> Option<PathBuf> {
+    if roots.kernel_driver.exists() {
+        Some(roots.kernel_driver.clone())
+    } else {
+        None
+    }
+}
+
+/// Attempts to automatically find the XVC node of the XDMA driver
+pub fn xdma_driver_path() -> Option<PathBuf> {
+    xdma_driver_paths_in(&DetectionRoots::default())
+        .into_iter()
+        .next()
+}
+
+/// XVC nodes of the XDMA driver: `/dev/xdma0_xvc` style nodes, or `xvc*` nodes within the
+/// per-card subdirectories (e.g. `/dev/xdma/card0/xvc`) that some driver versions create.
+fn xdma_driver_paths_in(roots: &DetectionRoots) -> Vec<PathBuf> {
+    fn collect_xvc_nodes(dir: &Path, depth: usize, paths: &mut Vec<PathBuf>) {
+        let Ok(entries) = dir.read_dir() else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if path.is_dir() {
+                if depth > 0 {
+                    collect_xvc_nodes(&path, depth - 1, paths);
+                }
+            } else if name.starts_with("xvc") {
+                paths.push(path);
+            }
+        }
+    }
+
+    let Ok(entries) = roots.dev.read_dir() else {
+        return Vec::new();
+    };
+    let mut paths = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !name.starts_with("xdma") {
+            continue;
+        }
+        if path.is_dir() {
+            collect_xvc_nodes(&path, 2, &mut paths);
+        } else if name.contains("_xvc") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths
+}
+
+/// Attempts to automatically find the path to the Debug Bridge via the UIO driver
+pub fn uio_driver_path() -> Option<PathBuf> {
+    uio_driver_path_in(&DetectionRoots::default())
+}
+
+fn uio_driver_path_in(roots: &DetectionRoots) -> Option<PathBuf> {
+    for entry in roots.uio_class.read_dir().ok()? {
+        let mut path = entry.ok()?.path();
+        log::debug!("Looking at UIO path {}", path.display());
+        path.push("name");
+        let name = match fs::read_to_string(&path) {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let uio_name = name.trim();
+        log::debug!("UIO has name {}", uio_name);
+        if uio_name == "debug_bridge" {
+            // This will be something like 'uio2'
+            let uio_indexed_name = path.parent()?.file_name()?;
+            // This will be something like '/dev/uio2'
+            return Some(roots.dev.join(uio_indexed_name));
+        }
+    }
+    None
+}
+
+/// A device-tree node describing a debug bridge.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeviceTreeBridge {
+    pub node: PathBuf,
+    pub base_address: u64,
+}
+
+/// Walks the device tree below `root` and returns every node whose `compatible`
+/// property contains one of `patterns` and that has a parsable `reg` property.
+pub fn find_device_tree_bridges(root: &Path, patterns: &[String]) -> Vec<DeviceTreeBridge> {
+    let mut bridges = Vec::new();
+    walk_device_tree(root, patterns, &mut bridges);
+    bridges.sort_by(|a, b| a.node.cmp(&b.node));
+    bridges
+}
+
+fn walk_device_tree(dir: &Path, patterns: &[String], bridges: &mut Vec<DeviceTreeBridge>) {
+    let Ok(entries) = dir.read_dir() else {
+        return;
+    };
+    if let Ok(compatible) = fs::read(dir.join("compatible"))
+        && compatible_matches(&compatible, patterns)
+    {
+        match read_base_address(dir) {
+            Some(base_address) => {
+                log::debug!(
+                    "Device tree node {} matches with base address 0x{:x}",
+                    dir.display(),
+                    base_address
+                );
+                bridges.push(DeviceTreeBridge {
+                    node: dir.to_path_buf(),
+                    base_address,
+                });
+            }
+            None => log::debug!(
+                "Device tree node {} matches but has no usable reg property",
+                dir.display()
+            ),
+        }
+    }
+    for entry in entries.flatten() {
+        // Symlinks are not followed to avoid cycles
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            walk_device_tree(&entry.path(), patterns, bridges);
+        }
+    }
+}
+
+/// The `compatible` property is a list of NUL-terminated strings.
+fn compatible_matches(compatible: &[u8], patterns: &[String]) -> bool {
+    compatible
+        .split(|b| *b == 0)
+        .filter_map(|entry| std::str::from_utf8(entry).ok())
+        .any(|entry| {
+            patterns
+                .iter()
+                .any(|pattern| entry.contains(pattern.as_str()))
+        })
+}
+
+/// Reads a big-endian cell count property such as `#address-cells`.
+fn read_cells(path: &Path) -> Option<usize> {
+    let bytes = fs::read(path).ok()?;
+    let cells: [u8; 4] = bytes.get(..4)?.try_into().ok()?;
+    Some(u32::from_be_bytes(cells) as usize)
+}
+
+/// Extracts the first address of the node's `reg` property.
+/// The width of the address is given by `#address-cells` of the parent node,
+/// defaulting to 2 as mandated by the devicetree specification.
+fn read_base_address(node: &Path) -> Option<u64> {
+    let address_cells = node
+        .parent()
+        .and_then(|parent| read_cells(&parent.join("#address-cells")))
+        .unwrap_or(2);
+    if address_cells == 0 || address_cells > 2 {
+        return None;
+    }
+    let reg = fs::read(node.join("reg")).ok()?;
+    let address_bytes = reg.get(..address_cells * 4)?;
+    Some(address_bytes.chunks_exact(4).fold(0u64, |acc, cell| {
+        (acc << 32) | u32::from_be_bytes(cell.try_into().unwrap()) as u64
+    }))
+}
+
+/// Finds the UIO device whose first memory map starts at `address`.
+fn uio_for_address(roots: &DetectionRoots, address: u64) -> Option<PathBuf> {
+    for entry in roots.uio_class.read_dir().ok()?.flatten() {
+        let addr_path = entry.path().join("maps/map0/addr");
+        let Ok(content) = fs::read_to_string(&addr_path) else {
+            continue;
+        };
+        let content = content.trim();
+        let digits = content
+            .strip_prefix("0x")
+            .or_else(|| content.strip_prefix("0X"))
+            .unwrap_or(content);
+        if u64::from_str_radix(digits, 16) == Ok(address) {
+            return Some(roots.dev.join(entry.file_name()));
+        }
+    }
+    None
+}
+
+/// Collects all debug bridge candidates in priority order.
+pub fn detect_candidates(roots: &DetectionRoots, compatible: &[String]) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    if let Some(path) = kernel_driver_path_in(roots) {
+        candidates.push(Candidate::KernelDriver {
+            path,
+            provenance: Provenance::KernelDriverNode,
+        });
+    }
+    for path in xdma_driver_paths_in(roots) {
+        candidates.push(Candidate::Xdma {
+            path,
+            provenance: Provenance::XdmaNode,
+        });
+    }
+    if let Some(path) = uio_driver_path_in(roots) {
+        candidates.push(Candidate::Uio {
+            path,
+            provenance: Provenance::UioName,
+        });
+    }
+    for bridge in find_device_tree_bridges(&roots.device_tree, compatible) {
+        let provenance = Provenance::DeviceTree { node: bridge.node };
+        let candidate = match uio_for_address(roots, bridge.base_address) {
+            Some(path) => Candidate::Uio { path, provenance },
+            None => Candidate::DevMem {
+                address: bridge.base_address,
+                provenance,
+            },
+        };
+        // The same UIO device may have been found by its name already
+        let duplicate = candidates
+            .iter()
+            .any(|existing| match (existing, &candidate) {
+                (Candidate::Uio { path: a, .. }, Candidate::Uio { path: b, .. }) => a == b,
+                _ => false,
+            });
+        if !duplicate {
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    /// A scratch directory that is removed on drop.
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new() -> Fixture {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "xvc-detection-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir_all(&path).unwrap();
+            Fixture(path)
+        }
+
+        fn roots(&self) -> DetectionRoots {
+            DetectionRoots {
+                kernel_driver: self.0.join("dev/xilinx_xvc_driver"),
+                uio_class: self.0.join("sys/class/uio"),
+                device_tree: self.0.join("devicetree"),
+                dev: self.0.join("dev"),
+            }
+        }
+
+        fn write(&self, path: impl AsRef<Path>, content: &[u8]) {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn default_patterns() -> Vec<String> {
+        DEFAULT_COMPATIBLE.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn add_bridge_node(fixture: &Fixture, node: &str, compatible: &[u8], address: u64) {
+        fixture.write("devicetree/axi/#address-cells", &2u32.to_be_bytes());
+        fixture.write(format!("devicetree/axi/{node}/compatible"), compatible);
+        let mut reg = Vec::new();
+        reg.extend_from_slice(&((address >> 32) as u32).to_be_bytes());
+        reg.extend_from_slice(&(address as u32).to_be_bytes());
+        reg.extend_from_slice(&0u32.to_be_bytes());
+        reg.extend_from_slice(&0x10000u32.to_be_bytes());
+        fixture.write(format!("devicetree/axi/{node}/reg"), &reg);
+    }
+
+    #[test]
+    fn finds_device_tree_node_by_compatible() {
+        let fixture = Fixture::new();
+        add_bridge_node(
+            &fixture,
+            "debug_bridge@a0000000",
+            b"xlnx,xvc\0generic-uio\0",
+            0xa000_0000,
+        );
+        add_bridge_node(
+            &fixture,
+            "serial@ff000000",
+            b"xlnx,zynqmp-uart\0",
+            0xff00_0000,
+        );
+
+        let bridges = find_device_tree_bridges(&fixture.roots().device_tree, &default_patterns());
+        assert_eq!(bridges.len(), 1);
+        assert_eq!(bridges[0].base_address, 0xa000_0000);
+        assert!(bridges[0].node.ends_with("debug_bridge@a0000000"));
+    }
+
+    #[test]
+    fn honours_address_cells_of_parent() {
+        let fixture = Fixture::new();
+        fixture.write("devicetree/amba/#address-cells", &1u32.to_be_bytes());
+        fixture.write(
+            "devicetree/amba/bridge@43c00000/compatible",
+            b"debug_bridge\0",
+        );
+        let mut reg = Vec::new();
+        reg.extend_from_slice(&0x43c0_0000u32.to_be_bytes());
+        reg.extend_from_slice(&0x10000u32.to_be_bytes());
+        fixture.write("devicetree/amba/bridge@43c00000/reg", &reg);
+
+        let bridges = find_device_tree_bridges(&fixture.roots().device_tree, &default_patterns());
+        assert_eq!(bridges.len(), 1);
+        assert_eq!(bridges[0].base_address, 0x43c0_0000);
+    }
+
+    #[test]
+    fn custom_compatible_patterns() {
+        let fixture = Fixture::new();
+        add_bridge_node(
+            &fixture,
+            "jtag@b0000000",
+            b"acme,jtag-bridge\0",
+            0xb000_0000,
+        );
+
+        let roots = fixture.roots();
+        assert!(find_device_tree_bridges(&roots.device_tree, &default_patterns()).is_empty());
+        let bridges = find_device_tree_bridges(&roots.device_tree, &["acme,jtag".to_owned()]);
+        assert_eq!(bridges.len(), 1);
+    }
+
+    #[test]
+    fn device_tree_node_correlates_with_uio() {
+        let fixture = Fixture::new();
+        add_bridge_node(
+            &fixture,
+            "debug_bridge@a0000000",
+            b"xlnx,xvc\0",
+            0xa000_0000,
+        );
+        fixture.write("sys/class/uio/uio0/name", b"axi-gpio\n");
+        fixture.write("sys/class/uio/uio0/maps/map0/addr", b"0x0000000080000000\n");
+        fixture.write("sys/class/uio/uio3/name", b"bridge\n");
+        fixture.write("sys/class/uio/uio3/maps/map0/addr", b"0x00000000a0000000\n");
+
+        let roots = fixture.roots();
+        let candidates = detect_candidates(&roots, &default_patterns());
+        assert_eq!(candidates.len(), 1);
+        match &candidates[0] {
+            Candidate::Uio { path, provenance } => {
+                assert_eq!(path, &roots.dev.join("uio3"));
+                assert!(matches!(provenance, Provenance::DeviceTree { .. }));
+            }
+            other => panic!("expected UIO candidate, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn device_tree_node_without_uio_suggests_dev_mem() {
+        let fixture = Fixture::new();
+        add_bridge_node(
+            &fixture,
+            "debug_bridge@a0010000",
+            b"xlnx,xvc\0",
+            0xa001_0000,
+        );
+
+        let candidates = detect_candidates(&fixture.roots(), &default_patterns());
+        assert_eq!(candidates.len(), 1);
+        assert!(matches!(
+            candidates[0],
+            Candidate::DevMem {
+                address: 0xa001_0000,
+                provenance: Provenance::DeviceTree { .. }
+            }
+        ));
+    }
+
+    #[test]
+    fn candidates_are_ordered_and_deduplicated() {
+        let fixture = Fixture::new();
+        fixture.write("dev/xilinx_xvc_driver", b"");
+        fixture.write("sys/class/uio/uio1/name", b"debug_bridge\n");
+        fixture.write("sys/class/uio/uio1/maps/map0/addr", b"0xa0000000\n");
+        add_bridge_node(
+            &fixture,
+            "debug_bridge@a0000000",
+            b"xlnx,xvc\0",
+            0xa000_0000,
+        );
+
+        let roots = fixture.roots();
+        let candidates = detect_candidates(&roots, &default_patterns());
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(
+            candidates[0],
+            Candidate::KernelDriver {
+                path: roots.kernel_driver.clone(),
+                provenance: Provenance::KernelDriverNode
+            }
+        );
+        assert_eq!(
+            candidates[1],
+            Candidate::Uio {
+                path: roots.dev.join("uio1"),
+                provenance: Provenance::UioName
+            }
+        );
+    }
+
+    #[test]
+    fn finds_xdma_nodes() {
+        let fixture = Fixture::new();
+        fixture.write("dev/xdma1_xvc", b"");
+        fixture.write("dev/xdma0_xvc", b"");
+        fixture.write("dev/xdma0_user", b"");
+        fixture.write("dev/xdma/card2/xvc", b"");
+        fixture.write("dev/xdma/card2/user", b"");
+        fixture.write("dev/xvc_other", b"");
+
+        let roots = fixture.roots();
+        let candidates = detect_candidates(&roots, &default_patterns());
+        let paths: Vec<PathBuf> = candidates
+            .into_iter()
+            .map(|candidate| match candidate {
+                Candidate::Xdma {
+                    path,
+                    provenance: Provenance::XdmaNode,
+                } => path,
+                other => panic!("unexpected candidate {}", other),
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                roots.dev.join("xdma/card2/xvc"),
+                roots.dev.join("xdma0_xvc"),
+                roots.dev.join("xdma1_xvc"),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_device_tree_yields_no_candidates() {
+        let fixture = Fixture::new();
+        assert!(detect_candidates(&fixture.roots(), &default_patterns()).is_empty());
+    }
+}
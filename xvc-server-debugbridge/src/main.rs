@@ -6,31 +6,58 @@
 //! ## Overview
 //!
 //! This crate extends [`xvc_server`](https://docs.rs/xvc-server/) with concrete implementations
-//! for Linux platforms. It provides three backend drivers:
+//! for Linux platforms. It provides four backend drivers:
 //!
 //! - **kernel-driver**: communicates via the Xilinx kernel driver (`/dev/xilinx_xvc_driver`)
 //! - **uio-driver**: memory-mapped access via a userspace I/O device (`/dev/uioN`)
 //! - **dev-mem-driver**: memory-mapped access via `/dev/mem` at a given physical address
+//! - **xdma-driver**: communicates via the XVC node of the XDMA PCIe driver (`/dev/xdma0_xvc`)
+pub mod activation;
 pub mod backends;
+pub mod bind;
+pub mod compat;
+pub mod detection;
+pub mod notify;
+pub mod probe;
+pub mod reload;
+pub mod report;
 
 use std::error::Error;
 use std::net::{IpAddr, SocketAddr};
-use std::path::{Path, PathBuf};
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 use clap::Parser;
 use clap_num::maybe_hex;
 use env_logger::Env;
 use tokio::net::TcpListener;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::task::block_in_place;
 use tokio_util::sync::CancellationToken;
 use xvc_server::{
     XvcServer,
-    server::{Config, Server},
+    decorators::{Switchable, Throttled},
+    server::{Builder, Server},
 };
 
+use crate::backends::{
+    memory_mapped::{CONTROL_REG_OFFSET, StatusRegister},
+    mmio::MAP_SIZE,
+};
+use crate::detection::{
+    Candidate, DetectionRoots, detect_candidates, kernel_driver_path, uio_driver_path,
+    xdma_driver_path,
+};
+use crate::notify::Notifier;
+use crate::probe::DynBackend;
+use crate::reload::{Settings, Swap};
+use crate::report::{ConnectionLog, StatsReport};
+
 const DEFAULT_TIMEOUT_US: u64 = 1000;
 
-#[derive(Parser, Eq, PartialEq, Clone)]
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
 #[allow(clippy::enum_variant_names)]
 enum DeviceImpl {
     KernelDriver {
@@ -45,13 +72,19 @@ enum DeviceImpl {
             default_value = "1000"
         )]
         poll_timeout_us: u64,
+        /// Depth in bits of the FIFOs behind the TMS/TDI/TDO registers of the bridge.
+        /// Depths above 32 shift up to that many bits per transfer.
+        #[arg(long, default_value = "32", value_parser = parse_fifo_depth)]
+        fifo_depth: u32,
+        #[command(flatten)]
+        status: StatusArgs,
     },
     DevMemDriver {
         /// Start address of the memory mapped region
         #[clap(value_parser=maybe_hex::<u64>)]
         address: u64,
         #[arg(
-            short,
+            short = 't',
             long,
             help = "The timeout in microseconds",
             default_value = "1000"
@@ -59,10 +92,77 @@ enum DeviceImpl {
         poll_timeout_us: u64,
         #[arg(short, long)]
         path: Option<PathBuf>,
+        #[command(flatten)]
+        status: StatusArgs,
+    },
+    XdmaDriver {
+        path: Option<PathBuf>,
+        /// Access the debug bridge at this offset of the user BAR directly.
+        /// PATH must then be the user BAR node, e.g. /dev/xdma0_user.
+        #[arg(long, value_parser=maybe_hex::<u64>)]
+        bar_offset: Option<u64>,
+        #[arg(
+            short,
+            long,
+            help = "The timeout in microseconds when using --bar-offset",
+            default_value = "1000"
+        )]
+        poll_timeout_us: u64,
     },
+    /// List all detected debug bridges and exit
+    ListDevices,
+}
+
+/// Optional status register of a memory-mapped bridge wrapper
+#[derive(clap::Args, Debug, Eq, PartialEq, Clone)]
+struct StatusArgs {
+    /// Byte offset of a status register with sticky AXI error bits (write one to clear).
+    /// Failed AXI transactions are only reported if this is given.
+    #[arg(long, value_parser = parse_status_offset)]
+    status_offset: Option<usize>,
+    /// Bit of the status register that signals SLVERR
+    #[arg(long, default_value = "0", value_parser = clap::value_parser!(u32).range(0..32))]
+    slverr_bit: u32,
+    /// Bit of the status register that signals DECERR
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(0..32))]
+    decerr_bit: u32,
+}
+
+impl Default for StatusArgs {
+    fn default() -> Self {
+        StatusArgs {
+            status_offset: None,
+            slverr_bit: 0,
+            decerr_bit: 1,
+        }
+    }
+}
+
+impl StatusArgs {
+    fn register(&self) -> Option<StatusRegister> {
+        self.status_offset.map(|offset| StatusRegister {
+            offset: offset / 4,
+            slverr_bit: self.slverr_bit,
+            decerr_bit: self.decerr_bit,
+        })
+    }
+}
+
+/// Parses the byte offset of the status register, which has to be a word inside the mapping
+/// behind the registers of the bridge itself.
+fn parse_status_offset(s: &str) -> Result<usize, String> {
+    let offset = maybe_hex::<usize>(s)?;
+    let first = (CONTROL_REG_OFFSET + 1) * 4;
+    if !offset.is_multiple_of(4) || offset < first || offset >= MAP_SIZE {
+        return Err(format!(
+            "0x{:x} is not a word-aligned offset from 0x{:x} below 0x{:x}",
+            offset, first, MAP_SIZE
+        ));
+    }
+    Ok(offset)
 }
 
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[command(about = "Xilinx Virtual Cable (XVC) JTAG interface for ZynqMP", long_about=None, version)]
 struct Args {
     #[arg(short, long, default_value = "2542")]
@@ -71,173 +171,557 @@ struct Args {
     #[arg(short, long, default_value = "0.0.0.0")]
     ip: IpAddr,
 
-    #[clap(subcommand)]
-    device: Option<DeviceImpl>,
-}
+    /// Substring matched against the device-tree `compatible` property during auto-detection.
+    /// May be given multiple times.
+    #[arg(long = "compatible", default_values = detection::DEFAULT_COMPATIBLE)]
+    compatible: Vec<String>,
 
-async fn run<T: XvcServer + Send + 'static>(
-    backend: T,
-    config: Config,
-    listener: TcpListener,
-    token: CancellationToken,
-) -> std::io::Result<()> {
-    Server::new(backend, config)
-        .listen_on(listener, token)
-        .await
-}
+    /// How long to keep retrying when the port is in use, e.g. `30s` or `500ms`.
+    /// Retrying stops immediately if another XVC server answers on the port.
+    #[arg(long, default_value = "0s", value_parser = bind::parse_duration)]
+    bind_retry: Duration,
 
-/// Attempts to automatically find the path to the Debug Bridge kernel driver
-fn kernel_driver_path() -> Option<PathBuf> {
-    let p = PathBuf::from("/dev/xilinx_xvc_driver");
-    if p.exists() { Some(p) } else { None }
-}
+    /// Append the statistics report dumped on SIGUSR1 to this file instead of logging it.
+    #[arg(long)]
+    stats_file: Option<PathBuf>,
 
-/// Attempts to automatically find the path to the Debug Bridge via the UIO driver
-fn uio_driver_path() -> Option<PathBuf> {
-    let uio_class_path = Path::new("/sys/class/uio");
-    for entry in uio_class_path.read_dir().ok()? {
-        use std::fs;
+    /// Limit the shift throughput to this many bits per second to emulate a slow link.
+    #[arg(long, value_name = "BITS_PER_SEC")]
+    throttle: Option<u64>,
 
-        let mut path = entry.ok()?.path();
-        log::debug!("Looking at UIO path {}", path.display());
-        path.push("name");
-        let name = match fs::read_to_string(&path) {
-            Ok(name) => name,
-            Err(_) => continue,
-        };
-        let uio_name = name.trim();
-        log::debug!("UIO has name {}", uio_name);
-        if uio_name == "debug_bridge" {
-            // This will be something like 'uio2'
-            let uio_indexed_name = path.parent()?.file_name()?;
-            let mut dev_path = PathBuf::from("/dev");
-            // This will be something like '/dev/uio2'
-            dev_path.push(uio_indexed_name);
-            return Some(dev_path);
-        }
-    }
-    None
-}
+    /// Delay every message by this many milliseconds to emulate a slow link.
+    #[arg(long, value_name = "MS", default_value = "0")]
+    latency: u64,
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    log::info!("Starting XVC server");
+    /// When auto-detecting, open each detected debug bridge in turn and use the first that
+    /// completes a one-bit shift with TMS high. This toggles TCK once on every probed bridge.
+    #[arg(long)]
+    probe_backends: bool,
 
-    let args = Args::parse();
-    log::debug!("Parsed arguments: ip={}, port={}", args.ip, args.port);
+    /// Read settings from this file. The file is read again on SIGHUP, together with
+    /// re-running the device detection. See the README for the format.
+    #[arg(long)]
+    config: Option<PathBuf>,
 
-    let config = Config::default();
-    log::debug!("Server config: max_vector_size={}", config.max_vector_size);
+    /// Log debug output. Also accepted in the `xvcserver` style together with `-s tcp::<port>`.
+    #[arg(short, long)]
+    verbose: bool,
 
-    let addr = SocketAddr::new(args.ip, args.port);
+    #[clap(subcommand)]
+    device: Option<DeviceImpl>,
+}
 
-    let device_impl = args.device.or_else(|| {
-        if let Some(path) = kernel_driver_path() {
-            log::info!("Auto-detected Kernel driver at {}", path.display());
-            Some(DeviceImpl::KernelDriver { path: Some(path) })
-        } else if let Some(path) = uio_driver_path() {
-            log::info!("Auto-detected UIO driver at {}", path.display());
-            Some(DeviceImpl::UioDriver {
-                path: Some(path),
-                poll_timeout_us: DEFAULT_TIMEOUT_US,
-            })
-        } else {
-            None
-        }
-    });
+/// Parses a FIFO depth, which has to be a non-zero multiple of the 32-bit register width.
+fn parse_fifo_depth(s: &str) -> Result<u32, String> {
+    let depth: u32 = s.parse().map_err(|e| format!("{}", e))?;
+    if depth == 0 || !depth.is_multiple_of(32) {
+        return Err(format!("{} is not a non-zero multiple of 32", depth));
+    }
+    Ok(depth)
+}
 
-    let Some(device_impl) = device_impl else {
-        println!(
-            "No debug bridge could be auto detected. Use xvc-server kernel-driver <path>, xvc-server uio-driver <path>, or xvc-server dev-mem-driver <address> to manually specify a driver."
-        );
-        return Ok(());
-    };
+/// The backend stack served by the server
+type Backend = Switchable<Throttled<DynBackend>>;
 
-    let listener = TcpListener::bind(addr).await?;
-    log::info!("Listening on {}", addr);
+/// The device suggested by an auto-detected `candidate`.
+fn device_for(candidate: Candidate) -> DeviceImpl {
+    match candidate {
+        Candidate::KernelDriver { path, .. } => DeviceImpl::KernelDriver { path: Some(path) },
+        Candidate::Xdma { path, .. } => DeviceImpl::XdmaDriver {
+            path: Some(path),
+            bar_offset: None,
+            poll_timeout_us: DEFAULT_TIMEOUT_US,
+        },
+        Candidate::Uio { path, .. } => DeviceImpl::UioDriver {
+            path: Some(path),
+            poll_timeout_us: DEFAULT_TIMEOUT_US,
+            fifo_depth: 32,
+            status: StatusArgs::default(),
+        },
+        Candidate::DevMem { address, .. } => DeviceImpl::DevMemDriver {
+            address,
+            poll_timeout_us: DEFAULT_TIMEOUT_US,
+            path: None,
+            status: StatusArgs::default(),
+        },
+    }
+}
 
-    let token = CancellationToken::new();
-    tokio::spawn({
-        let token = token.clone();
-        async move {
-            if tokio::signal::ctrl_c().await.is_ok() {
-                log::info!("Received Ctrl+C, shutting down gracefully");
-                token.cancel();
-            }
+/// Fills in the detected path if `device` was given without one.
+/// Returns a hint for the user if no path could be detected.
+fn resolve_device(device: DeviceImpl) -> Result<DeviceImpl, &'static str> {
+    Ok(match device {
+        DeviceImpl::KernelDriver { path } => DeviceImpl::KernelDriver {
+            path: Some(path.or_else(kernel_driver_path).ok_or(
+                "No debug bridge could be detected. Explicitly specify a path using xvc-server kernel-driver <path> to manually specify a driver.",
+            )?),
+        },
+        DeviceImpl::UioDriver {
+            path,
+            poll_timeout_us,
+            fifo_depth,
+            status,
+        } => DeviceImpl::UioDriver {
+            path: Some(path.or_else(uio_driver_path).ok_or(
+                "No debug bridge could be detected. Explicitly specify a path using xvc-server uio-driver <path> to manually specify a driver.",
+            )?),
+            poll_timeout_us,
+            fifo_depth,
+            status,
+        },
+        DeviceImpl::XdmaDriver {
+            path: None,
+            bar_offset: Some(_),
+            ..
+        } => {
+            return Err(
+                "--bar-offset requires the path of the user BAR, e.g. xvc-server xdma-driver /dev/xdma0_user --bar-offset <offset>.",
+            );
         }
-    });
+        DeviceImpl::XdmaDriver {
+            path,
+            bar_offset,
+            poll_timeout_us,
+        } => DeviceImpl::XdmaDriver {
+            path: Some(path.or_else(xdma_driver_path).ok_or(
+                "No XDMA XVC node could be detected. Explicitly specify a path using xvc-server xdma-driver <path> to manually specify a driver.",
+            )?),
+            bar_offset,
+            poll_timeout_us,
+        },
+        device @ (DeviceImpl::DevMemDriver { .. } | DeviceImpl::ListDevices) => device,
+    })
+}
+
+/// Opens the backend for a device returned by [`resolve_device`].
+fn open_device(device: &DeviceImpl) -> std::io::Result<DynBackend> {
+    use crate::backends::{
+        devmem::DevMemBackend, kernel_driver::KernelDriverBackend, uio::UioDriverBackend,
+        xdma::XdmaBackend,
+    };
 
-    match device_impl {
+    let unresolved = || std::io::Error::new(std::io::ErrorKind::NotFound, "no device path");
+    let backend: DynBackend = match device {
+        DeviceImpl::ListDevices => unreachable!("handled before binding"),
         DeviceImpl::KernelDriver { path } => {
-            use crate::backends::kernel_driver::KernelDriverBackend;
-
-            let device_path = match path.or_else(kernel_driver_path) {
-                None => {
-                    println!(
-                        "No debug bridge could be detected. Explicitly specify a path using xvc-server kernel-driver <path> to manually specify a driver."
-                    );
-                    return Ok(());
-                }
-                Some(path) => path,
-            };
+            let device_path = path.as_ref().ok_or_else(unresolved)?;
             log::info!(
                 "Initializing kernel driver backend from {}",
                 device_path.display()
             );
-            run(
-                KernelDriverBackend::new(device_path)?,
-                config,
-                listener,
-                token,
-            )
-            .await?;
+            Box::new(KernelDriverBackend::new(device_path)?)
         }
         DeviceImpl::UioDriver {
             path,
             poll_timeout_us,
+            fifo_depth,
+            status,
         } => {
-            use crate::backends::uio::UioDriverBackend;
-
-            let uio_path = match path.or_else(uio_driver_path) {
-                None => {
-                    println!(
-                        "No debug bridge could be detected. Explicitly specify a path using xvc-server uio-driver <path> to manually specify a driver."
-                    );
-                    return Ok(());
-                }
-                Some(path) => path,
-            };
+            let uio_path = path.as_ref().ok_or_else(unresolved)?;
             log::info!(
                 "Initializing UIO driver backend from {}",
                 uio_path.display()
             );
-            run(
-                UioDriverBackend::new(uio_path, Duration::from_micros(poll_timeout_us))?,
-                config,
-                listener,
-                token,
-            )
-            .await?;
+            if *fifo_depth > 32 {
+                log::info!("Using burst transfers of up to {} bits", fifo_depth);
+            }
+            let mut uio = UioDriverBackend::new(uio_path, Duration::from_micros(*poll_timeout_us))?
+                .with_fifo_depth(*fifo_depth);
+            if let Some(status) = status.register() {
+                uio = uio.with_status_register(status);
+            }
+            Box::new(uio)
         }
         DeviceImpl::DevMemDriver {
             path,
             address,
             poll_timeout_us,
+            status,
         } => {
-            use crate::backends::devmem::DevMemBackend;
-
-            let poll_timeout = Duration::from_micros(poll_timeout_us);
-            let dev_mem = match path {
-                Some(path) => DevMemBackend::new_with_path(path, address as i64, poll_timeout),
-                None => DevMemBackend::new(address as i64, poll_timeout),
+            let poll_timeout = Duration::from_micros(*poll_timeout_us);
+            let mut dev_mem = match path {
+                Some(path) => DevMemBackend::new_with_path(path, *address as i64, poll_timeout),
+                None => DevMemBackend::new(*address as i64, poll_timeout),
             }?;
+            if let Some(status) = status.register() {
+                dev_mem = dev_mem.with_status_register(status);
+            }
             log::info!(
                 "Initializing DevMem driver backend using address 0x{:.x}",
                 address
             );
-            run(dev_mem, config, listener, token).await?;
+            Box::new(dev_mem)
         }
+        DeviceImpl::XdmaDriver {
+            path,
+            bar_offset: Some(bar_offset),
+            poll_timeout_us,
+        } => {
+            let user_bar_path = path.as_ref().ok_or_else(unresolved)?;
+            log::info!(
+                "Initializing XDMA backend from {} at BAR offset 0x{:x}",
+                user_bar_path.display(),
+                bar_offset
+            );
+            Box::new(XdmaBackend::with_bar_offset(
+                user_bar_path,
+                *bar_offset,
+                Duration::from_micros(*poll_timeout_us),
+            )?)
+        }
+        DeviceImpl::XdmaDriver {
+            path,
+            bar_offset: None,
+            ..
+        } => {
+            let device_path = path.as_ref().ok_or_else(unresolved)?;
+            log::info!("Initializing XDMA backend from {}", device_path.display());
+            Box::new(XdmaBackend::new(device_path)?)
+        }
+    };
+    Ok(backend)
+}
+
+/// Selects the device given on the command line, or an auto-detected one.
+///
+/// With `--probe-backends`, the selected device is returned already opened. `current`, the
+/// device in use, is kept as long as it is still detected so that it is not probed again.
+/// Returns a hint for the user if no device could be selected.
+fn select_device(
+    args: &Args,
+    current: Option<&DeviceImpl>,
+) -> Result<(DeviceImpl, Option<DynBackend>), &'static str> {
+    if let Some(device) = &args.device {
+        return resolve_device(device.clone()).map(|device| (device, None));
+    }
+    let candidates = detect_candidates(&DetectionRoots::default(), &args.compatible);
+    if !args.probe_backends {
+        let candidate = candidates.into_iter().next().ok_or(
+            "No debug bridge could be auto detected. Use xvc-server kernel-driver <path>, xvc-server xdma-driver <path>, xvc-server uio-driver <path>, or xvc-server dev-mem-driver <address> to manually specify a driver.",
+        )?;
+        log::info!("Auto-detected {}", candidate);
+        return Ok((device_for(candidate), None));
+    }
+    if let Some(current) = current
+        && candidates.iter().any(|c| device_for(c.clone()) == *current)
+    {
+        return Ok((current.clone(), None));
+    }
+    let (selected, skipped) = probe::select_backend(
+        candidates,
+        |candidate| open_device(&device_for(candidate.clone())),
+        probe::PROBE_TIMEOUT,
+    );
+    for skipped in skipped {
+        log::warn!("Skipping {}: {}", skipped.candidate, skipped.reason);
     }
+    let (candidate, backend) = selected.ok_or(
+        "None of the detected debug bridges passed the probe shift. Use xvc-server list-devices to show them.",
+    )?;
+    log::info!("Selected {} after probing", candidate);
+    Ok((device_for(candidate), Some(backend)))
+}
+
+/// The settings given on the command line, before the config file is applied.
+fn base_settings(args: &Args) -> Settings {
+    Settings {
+        throttle: args.throttle.unwrap_or(0),
+        latency: Duration::from_millis(args.latency),
+        ..Settings::default()
+    }
+}
+
+/// Reads the config file given with `--config`, if any, on top of the command line.
+fn load_settings(args: &Args) -> Result<Settings, String> {
+    let base = base_settings(args);
+    match &args.config {
+        Some(path) => base
+            .load(path)
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        None => Ok(base),
+    }
+}
+
+/// State of the SIGHUP handler
+struct Reloader {
+    args: Args,
+    settings: Settings,
+    /// The device the backend was opened from
+    device: DeviceImpl,
+    /// A device to swap to once the connected client disconnected, with its backend if it was
+    /// already opened by probing
+    pending: Option<(DeviceImpl, Option<DynBackend>)>,
+    backend: Backend,
+}
+
+impl Reloader {
+    fn reload(&mut self, server: &Server<Backend>) {
+        let mut settings = match load_settings(&self.args) {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::error!(
+                    "Rejecting new configuration, keeping the current one: {}",
+                    e
+                );
+                return;
+            }
+        };
+        let (device, probed) =
+            match block_in_place(|| select_device(&self.args, Some(&self.device))) {
+                Ok(selected) => selected,
+                Err(hint) => {
+                    log::warn!("Keeping the current device: {}", hint);
+                    (self.device.clone(), None)
+                }
+            };
+        let plan = reload::plan_reload(
+            &self.settings,
+            &settings,
+            &self.device,
+            &device,
+            server.status().client.is_some(),
+        );
+        if plan.changes.is_empty() {
+            log::info!("Reloaded, nothing changed");
+        } else {
+            log::info!("Reloaded: {}", plan.changes.join(", "));
+        }
+
+        if plan.update_config
+            && let Err(e) = server.update_config(settings.server_config())
+        {
+            log::error!("Keeping the previous server configuration: {}", e);
+            // Remember what the server still runs with, so that the next reload retries it
+            settings.max_vector_size = self.settings.max_vector_size;
+            settings.idle_timeout = self.settings.idle_timeout;
+            settings.message_timeout = self.settings.message_timeout;
+        }
+        if plan.update_throttle {
+            self.backend.with_mut(|backend| {
+                backend.set_bandwidth(settings.throttle);
+                backend.set_latency(settings.latency);
+            });
+        }
+        self.settings = settings;
+        self.pending = None;
+        match plan.swap {
+            Swap::Keep => {}
+            Swap::Now => self.swap(device, probed),
+            Swap::AfterDisconnect => self.pending = Some((device, probed)),
+        }
+    }
+
+    /// Replaces the backend with `probed`, or with `device` opened if it was not probed.
+    /// The current device stays in use if it cannot be opened.
+    fn swap(&mut self, device: DeviceImpl, probed: Option<DynBackend>) {
+        let opened = match probed {
+            Some(backend) => Ok(backend),
+            None => block_in_place(|| open_device(&device)),
+        };
+        match opened {
+            Ok(backend) => {
+                self.backend
+                    .with_mut(|throttled| *throttled.inner_mut() = backend);
+                log::info!("Switched backend to {:?}", device);
+                self.device = device;
+            }
+            Err(e) => log::error!(
+                "Could not open {:?}, keeping the current device: {}",
+                device,
+                e
+            ),
+        }
+    }
+}
+
+/// The socket that clients connect to.
+enum Listening {
+    Bound(TcpListener),
+    /// Passed by systemd socket activation, a TCP or Unix domain socket.
+    Activated(OwnedFd),
+}
+
+async fn run(
+    backend: DynBackend,
+    device: DeviceImpl,
+    settings: Settings,
+    listening: Listening,
+    token: CancellationToken,
+    args: &Args,
+    notifier: Arc<Notifier>,
+) -> std::io::Result<()> {
+    let backend = Switchable::new(
+        Throttled::new(backend)
+            .with_bandwidth(settings.throttle)
+            .with_latency(settings.latency),
+    );
+    if settings.throttle > 0 {
+        log::info!("Throttling shifts to {} bits/s", settings.throttle);
+    }
+    if !settings.latency.is_zero() {
+        log::info!("Delaying every message by {:?}", settings.latency);
+    }
+    let server = Arc::new(
+        Builder::from(settings.server_config())
+            .observer(Arc::new(ConnectionLog))
+            .build(backend.clone())
+            .map_err(std::io::Error::other)?,
+    );
+    let stats_file = args.stats_file.clone();
+
+    let mut usr1 = signal(SignalKind::user_defined1())?;
+    tokio::spawn({
+        let server = Arc::clone(&server);
+        async move {
+            while usr1.recv().await.is_some() {
+                let report = block_in_place(|| StatsReport::collect(&server));
+                if let Err(e) = report.dump(stats_file.as_deref()) {
+                    log::error!("Could not write statistics report: {}", e);
+                }
+            }
+        }
+    });
+
+    let mut hup = signal(SignalKind::hangup())?;
+    tokio::spawn({
+        let server = Arc::clone(&server);
+        let notifier = Arc::clone(&notifier);
+        let mut status = server.subscribe_status();
+        let mut reloader = Reloader {
+            args: args.clone(),
+            settings,
+            device,
+            pending: None,
+            backend,
+        };
+        async move {
+            loop {
+                tokio::select! {
+                    Some(()) = hup.recv() => {
+                        log::info!("Received SIGHUP, reloading");
+                        notifier.reloading();
+                        reloader.reload(&server);
+                        notifier.ready();
+                    }
+                    Ok(()) = status.changed(), if reloader.pending.is_some() => {
+                        let disconnected = status.borrow_and_update().client.is_none();
+                        if disconnected && let Some((device, probed)) = reloader.pending.take() {
+                            reloader.swap(device, probed);
+                        }
+                    }
+                    else => break,
+                }
+            }
+        }
+    });
+
+    tokio::spawn({
+        let status = server.subscribe_status();
+        let notifier = Arc::clone(&notifier);
+        async move { notify::forward_status(status, &notifier).await }
+    });
+    notifier.ready();
+
+    match listening {
+        Listening::Bound(listener) => server.listen_on(listener, token).await,
+        Listening::Activated(fd) => server.serve_on_fd(fd, token).await,
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let raw_args: Vec<String> = std::env::args().collect();
+    let native_args = match compat::translate_legacy_args(raw_args.clone()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let args = Args::parse_from(&native_args);
+
+    let default_filter = if args.verbose { "debug" } else { "info" };
+    env_logger::Builder::from_env(Env::default().default_filter_or(default_filter)).init();
+    log::info!("Starting XVC server");
+    if native_args != raw_args {
+        log::info!(
+            "Translated legacy xvcserver arguments to: {}",
+            native_args[1..].join(" ")
+        );
+    }
+    log::debug!("Parsed arguments: ip={}, port={}", args.ip, args.port);
+
+    let settings = load_settings(&args)?;
+    log::debug!(
+        "Server config: max_vector_size={}",
+        settings.max_vector_size
+    );
+
+    let addr = SocketAddr::new(args.ip, args.port);
+
+    if args.device == Some(DeviceImpl::ListDevices) {
+        let candidates = detect_candidates(&DetectionRoots::default(), &args.compatible);
+        if candidates.is_empty() {
+            println!("No debug bridge detected");
+        }
+        for candidate in candidates {
+            println!("{}", candidate);
+        }
+        return Ok(());
+    }
+
+    let (device, probed) = match select_device(&args, None) {
+        Ok(selection) => selection,
+        Err(hint) => {
+            println!("{}", hint);
+            return Ok(());
+        }
+    };
+
+    let mut activated = activation::listen_fds();
+    let listening = if activated.is_empty() {
+        let listener = bind::bind_with_retry(addr, args.bind_retry).await?;
+        log::info!("Listening on {}", listener.local_addr()?);
+        Listening::Bound(listener)
+    } else {
+        if activated.len() > 1 {
+            log::warn!(
+                "Received {} sockets from systemd, serving only the first",
+                activated.len()
+            );
+        }
+        log::info!(
+            "Serving the socket passed by systemd instead of binding {}",
+            addr
+        );
+        Listening::Activated(activated.swap_remove(0))
+    };
+
+    let notifier = Arc::new(Notifier::from_env());
+    let token = CancellationToken::new();
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::spawn({
+        let token = token.clone();
+        let notifier = Arc::clone(&notifier);
+        async move {
+            tokio::select! {
+                Ok(()) = tokio::signal::ctrl_c() => {
+                    log::info!("Received Ctrl+C, shutting down gracefully");
+                }
+                Some(()) = terminate.recv() => {
+                    log::info!("Received SIGTERM, shutting down gracefully");
+                }
+                else => return,
+            }
+            notifier.stopping();
+            token.cancel();
+        }
+    });
+
+    let backend = match probed {
+        Some(backend) => backend,
+        None => open_device(&device)?,
+    };
+    run(backend, device, settings, listening, token, &args, notifier).await?;
     Ok(())
 }
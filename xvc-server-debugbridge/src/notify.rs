@@ -0,0 +1,259 @@
+//! Readiness and status notifications for systemd units with `Type=notify`.
+//!
+//! Implements the datagram protocol of `sd_notify(3)` directly: state assignments such as
+//! `READY=1` are sent to the unix socket named by the `NOTIFY_SOCKET` environment variable.
+//! If the variable is unset, all notifications are silently dropped.
+use std::{
+    ffi::OsStr,
+    io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+};
+
+use nix::time::{ClockId, clock_gettime};
+use tokio::sync::watch;
+use xvc_server::stats::ServerStatus;
+
+pub struct Notifier {
+    target: Option<(UnixDatagram, SocketAddr)>,
+}
+
+impl Notifier {
+    /// Notifies the socket named by `NOTIFY_SOCKET`, if set.
+    pub fn from_env() -> Notifier {
+        Notifier::new(std::env::var_os("NOTIFY_SOCKET").as_deref())
+    }
+
+    /// Notifies the socket at `address`. Addresses starting with `@` are in the abstract namespace.
+    pub fn new(address: Option<&OsStr>) -> Notifier {
+        let target = address.and_then(|address| match connect(address) {
+            Ok(target) => Some(target),
+            Err(e) => {
+                log::warn!(
+                    "Cannot use notification socket {}: {}",
+                    address.to_string_lossy(),
+                    e
+                );
+                None
+            }
+        });
+        Notifier { target }
+    }
+
+    /// Sends the newline-separated `KEY=VALUE` assignments in `state`.
+    pub fn notify(&self, state: &str) {
+        let Some((socket, address)) = &self.target else {
+            return;
+        };
+        if let Err(e) = socket.send_to_addr(state.as_bytes(), address) {
+            log::debug!("Could not send notification '{}': {}", state, e);
+        }
+    }
+
+    /// All devices are opened and all listeners are bound.
+    pub fn ready(&self) {
+        self.notify("READY=1");
+    }
+
+    /// The service is reloading its configuration; [`ready`](Self::ready) has to follow
+    /// once the reload completed.
+    pub fn reloading(&self) {
+        match clock_gettime(ClockId::CLOCK_MONOTONIC) {
+            Ok(now) => self.notify(&format!(
+                "RELOADING=1\nMONOTONIC_USEC={}",
+                now.tv_sec() as u64 * 1_000_000 + now.tv_nsec() as u64 / 1000
+            )),
+            Err(_) => self.notify("RELOADING=1"),
+        }
+    }
+
+    /// The service is shutting down.
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    /// A human-readable status shown by `systemctl status`.
+    pub fn status(&self, status: &str) {
+        self.notify(&format!("STATUS={}", status));
+    }
+}
+
+fn connect(address: &OsStr) -> io::Result<(UnixDatagram, SocketAddr)> {
+    let address = match address.as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(address)?,
+    };
+    Ok((UnixDatagram::unbound()?, address))
+}
+
+/// Describes `status` for the `STATUS=` notification.
+fn describe(status: &ServerStatus) -> String {
+    let mut description = match status.client {
+        Some(client) => format!("Serving client {}", client),
+        None => "Waiting for a client".to_owned(),
+    };
+    if !status.backend_healthy {
+        description.push_str("; backend failing");
+    }
+    description
+}
+
+/// Sends a `STATUS=` notification for the current status and every subsequent change,
+/// until the server is dropped.
+pub async fn forward_status(mut status: watch::Receiver<ServerStatus>, notifier: &Notifier) {
+    loop {
+        let description = describe(&status.borrow_and_update());
+        notifier.status(&description);
+        if status.changed().await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, path::PathBuf, sync::Arc, time::Duration};
+
+    use tokio::{
+        net::{TcpListener, TcpStream, UnixDatagram},
+        time::timeout,
+    };
+    use tokio_util::sync::CancellationToken;
+    use xvc_server::{
+        XvcServer,
+        server::{Config, Server},
+    };
+
+    use super::*;
+
+    /// A temporary socket standing in for the one systemd passes in `NOTIFY_SOCKET`.
+    struct NotifySocket {
+        dir: PathBuf,
+        socket: UnixDatagram,
+    }
+
+    impl NotifySocket {
+        fn new(name: &str) -> NotifySocket {
+            let dir =
+                std::env::temp_dir().join(format!("xvc-notify-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let socket = UnixDatagram::bind(dir.join("notify")).unwrap();
+            NotifySocket { dir, socket }
+        }
+
+        fn notifier(&self) -> Notifier {
+            Notifier::new(Some(self.dir.join("notify").as_os_str()))
+        }
+
+        async fn recv(&self) -> String {
+            let mut buf = [0u8; 256];
+            let len = timeout(Duration::from_secs(5), self.socket.recv(&mut buf))
+                .await
+                .expect("no notification received")
+                .unwrap();
+            String::from_utf8(buf[..len].to_vec()).unwrap()
+        }
+    }
+
+    impl Drop for NotifySocket {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    struct Backend;
+
+    impl XvcServer for Backend {
+        type Err = Infallible;
+
+        fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+            Ok(period_ns)
+        }
+
+        fn shift(&self, _: u32, _: &[u8], _: &[u8], _: &mut [u8]) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn unset_socket_is_a_no_op() {
+        let notifier = Notifier::new(None);
+        notifier.ready();
+        notifier.status("idle");
+        notifier.stopping();
+    }
+
+    #[tokio::test]
+    async fn sends_state_assignments() {
+        let socket = NotifySocket::new("states");
+        let notifier = socket.notifier();
+        notifier.ready();
+        notifier.status("Waiting for a client");
+        notifier.stopping();
+        assert_eq!(socket.recv().await, "READY=1");
+        assert_eq!(socket.recv().await, "STATUS=Waiting for a client");
+        assert_eq!(socket.recv().await, "STOPPING=1");
+    }
+
+    #[tokio::test]
+    async fn reloading_carries_timestamp() {
+        let socket = NotifySocket::new("reload");
+        socket.notifier().reloading();
+        let message = socket.recv().await;
+        let (state, timestamp) = message.split_once('\n').unwrap();
+        assert_eq!(state, "RELOADING=1");
+        let usec = timestamp.strip_prefix("MONOTONIC_USEC=").unwrap();
+        assert!(usec.parse::<u64>().unwrap() > 0);
+    }
+
+    #[test]
+    fn describes_backend_health() {
+        let status = ServerStatus {
+            client: None,
+            backend_healthy: false,
+        };
+        assert_eq!(describe(&status), "Waiting for a client; backend failing");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reports_client_connect_and_disconnect() {
+        let socket = NotifySocket::new("session");
+        let notifier = Arc::new(socket.notifier());
+        let server = Arc::new(Server::new(Backend, Config::default()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let token = CancellationToken::new();
+
+        tokio::spawn({
+            let status = server.subscribe_status();
+            let notifier = Arc::clone(&notifier);
+            async move { forward_status(status, &notifier).await }
+        });
+        assert_eq!(socket.recv().await, "STATUS=Waiting for a client");
+        notifier.ready();
+        assert_eq!(socket.recv().await, "READY=1");
+
+        tokio::spawn({
+            let server = Arc::clone(&server);
+            let token = token.clone();
+            async move { server.listen_on(listener, token).await }
+        });
+        let client = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(
+            socket.recv().await,
+            format!("STATUS=Serving client {}", client.local_addr().unwrap())
+        );
+        drop(client);
+        assert_eq!(socket.recv().await, "STATUS=Waiting for a client");
+
+        notifier.stopping();
+        token.cancel();
+        assert_eq!(socket.recv().await, "STOPPING=1");
+    }
+}
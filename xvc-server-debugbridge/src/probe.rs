@@ -0,0 +1,184 @@
+//! Selection of a working backend by probing the detected candidates.
+//!
+//! Each candidate is opened in priority order and has to complete a single shift of one bit
+//! with TMS high within a timeout. Such a shift moves the TAP towards Test-Logic-Reset and
+//! does not disturb a device that is being debugged. Note that this still toggles TCK once;
+//! probing is therefore only done on request.
+use std::{fmt::Display, io, sync::mpsc, thread, time::Duration};
+
+use xvc_server::XvcServer;
+
+/// Default time a probe shift may take
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A backend of any type, as selected at runtime.
+pub type DynBackend = Box<dyn XvcServer<Err = io::Error> + Send>;
+
+/// A candidate that was not selected, and why.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Skipped {
+    pub candidate: String,
+    pub reason: String,
+}
+
+/// Runs the probe shift on a separate thread, so that a hanging backend can be abandoned.
+/// The backend is handed back if the probe succeeded.
+fn probe<B>(backend: B, timeout: Duration) -> Result<B, String>
+where
+    B: XvcServer + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut tdo = [0u8; 1];
+        let result = backend
+            .shift(1, &[0x01], &[0x00], &mut tdo)
+            .map_err(|e| e.to_string());
+        let _ = sender.send(result.map(|()| backend));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(backend)) => Ok(backend),
+        Ok(Err(e)) => Err(format!("probe shift failed: {}", e)),
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(format!(
+            "probe shift did not complete within {} ms",
+            timeout.as_millis()
+        )),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err("probe shift panicked".to_owned()),
+    }
+}
+
+/// Opens and probes `candidates` in order and returns the first that works,
+/// together with the reasons the preceding candidates were skipped.
+pub fn select_backend<C, B>(
+    candidates: impl IntoIterator<Item = C>,
+    mut open: impl FnMut(&C) -> io::Result<B>,
+    timeout: Duration,
+) -> (Option<(C, B)>, Vec<Skipped>)
+where
+    C: Display,
+    B: XvcServer + Send + 'static,
+{
+    let mut skipped = Vec::new();
+    for candidate in candidates {
+        let result = open(&candidate)
+            .map_err(|e| format!("could not be opened: {}", e))
+            .and_then(|backend| probe(backend, timeout));
+        match result {
+            Ok(backend) => return (Some((candidate, backend)), skipped),
+            Err(reason) => skipped.push(Skipped {
+                candidate: candidate.to_string(),
+                reason,
+            }),
+        }
+    }
+    (None, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    };
+
+    use super::*;
+
+    #[derive(Clone, Copy)]
+    enum Behavior {
+        Succeed,
+        Fail,
+        Hang,
+    }
+
+    struct MockBackend {
+        behavior: Behavior,
+        shifts: Arc<AtomicU32>,
+    }
+
+    impl XvcServer for MockBackend {
+        type Err = io::Error;
+
+        fn set_tck(&self, period_ns: u32) -> io::Result<u32> {
+            Ok(period_ns)
+        }
+
+        fn shift(&self, num_bits: u32, tms: &[u8], _: &[u8], _: &mut [u8]) -> io::Result<()> {
+            assert_eq!((num_bits, tms), (1, &[0x01][..]));
+            self.shifts.fetch_add(1, Ordering::Relaxed);
+            match self.behavior {
+                Behavior::Succeed => Ok(()),
+                Behavior::Fail => Err(io::Error::other("ioctl failed")),
+                Behavior::Hang => {
+                    thread::sleep(Duration::from_secs(60));
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// A candidate that opens a [`MockBackend`], or fails to open if `behavior` is `None`.
+    #[derive(Clone, Copy)]
+    struct MockCandidate(&'static str, Option<Behavior>);
+
+    impl Display for MockCandidate {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    fn select(candidates: &[MockCandidate]) -> (Option<&'static str>, Vec<Skipped>, u32) {
+        let shifts = Arc::new(AtomicU32::new(0));
+        let (selected, skipped) = select_backend(
+            candidates.iter().copied(),
+            |candidate| match candidate.1 {
+                Some(behavior) => Ok(MockBackend {
+                    behavior,
+                    shifts: Arc::clone(&shifts),
+                }),
+                None => Err(io::Error::from(io::ErrorKind::NotFound)),
+            },
+            Duration::from_millis(50),
+        );
+        let selected = selected.map(|(candidate, _)| candidate.0);
+        (selected, skipped, shifts.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn first_working_candidate_is_selected() {
+        let (selected, skipped, shifts) = select(&[
+            MockCandidate("kernel", Some(Behavior::Succeed)),
+            MockCandidate("uio", Some(Behavior::Succeed)),
+        ]);
+        assert_eq!(selected, Some("kernel"));
+        assert!(skipped.is_empty());
+        assert_eq!(shifts, 1);
+    }
+
+    #[test]
+    fn failing_and_hanging_candidates_are_skipped() {
+        let (selected, skipped, _) = select(&[
+            MockCandidate("kernel", Some(Behavior::Fail)),
+            MockCandidate("xdma", Some(Behavior::Hang)),
+            MockCandidate("missing", None),
+            MockCandidate("uio", Some(Behavior::Succeed)),
+        ]);
+        assert_eq!(selected, Some("uio"));
+        let candidates: Vec<&str> = skipped.iter().map(|s| s.candidate.as_str()).collect();
+        assert_eq!(candidates, ["kernel", "xdma", "missing"]);
+        let reasons: Vec<&str> = skipped.iter().map(|s| s.reason.as_str()).collect();
+        assert_eq!(
+            reasons,
+            [
+                "probe shift failed: ioctl failed",
+                "probe shift did not complete within 50 ms",
+                "could not be opened: entity not found",
+            ]
+        );
+    }
+
+    #[test]
+    fn no_working_candidate() {
+        let (selected, skipped, _) = select(&[MockCandidate("kernel", Some(Behavior::Fail))]);
+        assert_eq!(selected, None);
+        assert_eq!(skipped.len(), 1);
+    }
+}
@@ -0,0 +1,435 @@
+//! Reloading of the configuration and the device on SIGHUP.
+//!
+//! Settings are read from a file of `key = value` lines given with `--config`. Lines starting
+//! with `#` are comments. Settings in the file override the command line:
+//!
+//! ```text
+//! max_vector_size = 1048576
+//! idle_timeout = none
+//! message_timeout = 10s
+//! throttle = 1000000
+//! latency = 5ms
+//! defer_swap = true
+//! reopen_device = false
+//! ```
+//!
+//! On SIGHUP, the file is read again and [`plan_reload`] decides what has to be applied.
+//! A file that cannot be read or contains a single invalid line is rejected as a whole.
+use std::{
+    fmt::{self, Debug, Display},
+    fs, io,
+    path::Path,
+    time::Duration,
+};
+
+use xvc_server::server::Config;
+
+use crate::bind::parse_duration;
+
+/// Settings that can be changed without restarting the server.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Settings {
+    pub max_vector_size: u32,
+    /// `None` to keep idle clients connected
+    pub idle_timeout: Option<Duration>,
+    pub message_timeout: Duration,
+    /// Shift throughput in bits per second, or zero for no limit
+    pub throttle: u64,
+    pub latency: Duration,
+    /// Wait for a connected client to disconnect before swapping the backend
+    pub defer_swap: bool,
+    /// Reopen the device on every reload, even if its path did not change,
+    /// e.g. after the PL was re-flashed
+    pub reopen_device: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let config = Config::default();
+        Settings {
+            max_vector_size: config.max_vector_size.as_bytes(),
+            idle_timeout: config.idle_timeout,
+            message_timeout: config.message_timeout,
+            throttle: 0,
+            latency: Duration::ZERO,
+            defer_swap: true,
+            reopen_device: false,
+        }
+    }
+}
+
+impl Settings {
+    /// The configuration of the server for these settings.
+    pub fn server_config(&self) -> Config {
+        Config {
+            max_vector_size: self.max_vector_size.into(),
+            idle_timeout: self.idle_timeout,
+            message_timeout: self.message_timeout,
+            ..Config::default()
+        }
+    }
+
+    /// Reads the config file at `path`, overriding the settings in `self`.
+    pub fn load(&self, path: &Path) -> Result<Settings, ConfigError> {
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        self.parse(&text)
+    }
+
+    /// Parses the contents of a config file, overriding the settings in `self`.
+    pub fn parse(&self, text: &str) -> Result<Settings, ConfigError> {
+        let mut settings = self.clone();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let number = index + 1;
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ConfigError::Syntax(number));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = |reason: String| ConfigError::InvalidValue {
+                line: number,
+                key: key.to_owned(),
+                reason,
+            };
+            match key {
+                "max_vector_size" => {
+                    settings.max_vector_size = match value.parse() {
+                        Ok(0) => return Err(invalid("must not be zero".to_owned())),
+                        Ok(size) => size,
+                        Err(e) => return Err(invalid(format!("{}", e))),
+                    }
+                }
+                "idle_timeout" if value == "none" => settings.idle_timeout = None,
+                "idle_timeout" => {
+                    settings.idle_timeout = Some(parse_timeout(value).map_err(invalid)?)
+                }
+                "message_timeout" => {
+                    settings.message_timeout = parse_timeout(value).map_err(invalid)?
+                }
+                // Set both, as the single timeout of older versions did
+                "read_write_timeout" => {
+                    let timeout = parse_timeout(value).map_err(invalid)?;
+                    settings.idle_timeout = Some(timeout);
+                    settings.message_timeout = timeout;
+                }
+                "throttle" => {
+                    settings.throttle = value.parse().map_err(|e| invalid(format!("{}", e)))?
+                }
+                "latency" => settings.latency = parse_duration(value).map_err(invalid)?,
+                "defer_swap" => {
+                    settings.defer_swap = value.parse().map_err(|e| invalid(format!("{}", e)))?
+                }
+                "reopen_device" => {
+                    settings.reopen_device = value.parse().map_err(|e| invalid(format!("{}", e)))?
+                }
+                _ => {
+                    return Err(ConfigError::UnknownKey {
+                        line: number,
+                        key: key.to_owned(),
+                    });
+                }
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// Parses a timeout, which must not be zero.
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    match parse_duration(value)? {
+        Duration::ZERO => Err("must not be zero".to_owned()),
+        timeout => Ok(timeout),
+    }
+}
+
+/// Errors when reading a config file.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    /// The line is not of the form `key = value`
+    Syntax(usize),
+    UnknownKey {
+        line: usize,
+        key: String,
+    },
+    InvalidValue {
+        line: usize,
+        key: String,
+        reason: String,
+    },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Syntax(line) => write!(f, "line {}: expected key = value", line),
+            ConfigError::UnknownKey { line, key } => {
+                write!(f, "line {}: unknown setting '{}'", line, key)
+            }
+            ConfigError::InvalidValue { line, key, reason } => {
+                write!(f, "line {}: invalid value for {}: {}", line, key, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// When to replace the backend with a newly opened device.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Swap {
+    /// Keep the current backend
+    Keep,
+    Now,
+    /// Once the connected client disconnected
+    AfterDisconnect,
+}
+
+/// What a reload has to apply.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ReloadPlan {
+    /// The server configuration for new connections changed
+    pub update_config: bool,
+    /// Throttling or latency changed
+    pub update_throttle: bool,
+    pub swap: Swap,
+    /// Human-readable summary of the changes
+    pub changes: Vec<String>,
+}
+
+/// Decides what a reload from `old` to `new` settings and from `old_device` to the
+/// newly detected `new_device` has to apply.
+pub fn plan_reload<D: PartialEq + Debug>(
+    old: &Settings,
+    new: &Settings,
+    old_device: &D,
+    new_device: &D,
+    client_connected: bool,
+) -> ReloadPlan {
+    let mut changes = Vec::new();
+    let mut compare = |name: &str, old: &dyn Debug, new: &dyn Debug, changed: bool| {
+        if changed {
+            changes.push(format!("{}: {:?} -> {:?}", name, old, new));
+        }
+        changed
+    };
+
+    let update_config = [
+        compare(
+            "max_vector_size",
+            &old.max_vector_size,
+            &new.max_vector_size,
+            old.max_vector_size != new.max_vector_size,
+        ),
+        compare(
+            "idle_timeout",
+            &old.idle_timeout,
+            &new.idle_timeout,
+            old.idle_timeout != new.idle_timeout,
+        ),
+        compare(
+            "message_timeout",
+            &old.message_timeout,
+            &new.message_timeout,
+            old.message_timeout != new.message_timeout,
+        ),
+    ]
+    .contains(&true);
+    let update_throttle = [
+        compare(
+            "throttle",
+            &old.throttle,
+            &new.throttle,
+            old.throttle != new.throttle,
+        ),
+        compare(
+            "latency",
+            &old.latency,
+            &new.latency,
+            old.latency != new.latency,
+        ),
+    ]
+    .contains(&true);
+    compare(
+        "defer_swap",
+        &old.defer_swap,
+        &new.defer_swap,
+        old.defer_swap != new.defer_swap,
+    );
+    compare(
+        "reopen_device",
+        &old.reopen_device,
+        &new.reopen_device,
+        old.reopen_device != new.reopen_device,
+    );
+
+    let device_changed = compare("device", old_device, new_device, old_device != new_device);
+    let swap = if !device_changed && !new.reopen_device {
+        Swap::Keep
+    } else if client_connected && new.defer_swap {
+        Swap::AfterDisconnect
+    } else {
+        Swap::Now
+    };
+    match swap {
+        Swap::Keep => {}
+        Swap::Now if !device_changed => changes.push("reopening device".to_owned()),
+        Swap::Now => {}
+        Swap::AfterDisconnect => {
+            changes.push("device swap deferred until the client disconnects".to_owned())
+        }
+    }
+
+    ReloadPlan {
+        update_config,
+        update_throttle,
+        swap,
+        changes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_overrides_base_settings() {
+        let base = Settings {
+            throttle: 1000,
+            ..Settings::default()
+        };
+        let settings = base
+            .parse(
+                "# slow link\n\
+                 latency = 5ms\n\
+                 \n\
+                 max_vector_size=4096\n  reopen_device = true\n",
+            )
+            .unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                max_vector_size: 4096,
+                throttle: 1000,
+                latency: Duration::from_millis(5),
+                reopen_device: true,
+                ..Settings::default()
+            }
+        );
+    }
+
+    #[test]
+    fn timeouts_are_parsed() {
+        let base = Settings::default();
+        let settings = base
+            .parse("idle_timeout = none\nmessage_timeout = 5s")
+            .unwrap();
+        assert_eq!(settings.idle_timeout, None);
+        assert_eq!(settings.message_timeout, Duration::from_secs(5));
+
+        let settings = base.parse("read_write_timeout = 1m").unwrap();
+        assert_eq!(settings.idle_timeout, Some(Duration::from_secs(60)));
+        assert_eq!(settings.message_timeout, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        let base = Settings::default();
+        let error = |text| base.parse(text).unwrap_err().to_string();
+        assert_eq!(error("throttle 1000"), "line 1: expected key = value");
+        assert_eq!(
+            error("latency = 5ms\nmax_vector = 12"),
+            "line 2: unknown setting 'max_vector'"
+        );
+        assert_eq!(
+            error("max_vector_size = 0"),
+            "line 1: invalid value for max_vector_size: must not be zero"
+        );
+        assert_eq!(
+            error("idle_timeout = 5h"),
+            "line 1: invalid value for idle_timeout: unknown duration unit 'h' (use ms, s or m)"
+        );
+        assert_eq!(
+            error("message_timeout = 0s"),
+            "line 1: invalid value for message_timeout: must not be zero"
+        );
+        assert_eq!(
+            error("defer_swap = yes"),
+            "line 1: invalid value for defer_swap: provided string was not `true` or `false`"
+        );
+    }
+
+    #[test]
+    fn unchanged_reload_does_nothing() {
+        let settings = Settings::default();
+        let plan = plan_reload(&settings, &settings, &"/dev/uio0", &"/dev/uio0", true);
+        assert_eq!(
+            plan,
+            ReloadPlan {
+                update_config: false,
+                update_throttle: false,
+                swap: Swap::Keep,
+                changes: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn hot_settings_do_not_touch_the_device() {
+        let old = Settings::default();
+        let new = Settings {
+            max_vector_size: 1024,
+            latency: Duration::from_millis(2),
+            ..Settings::default()
+        };
+        let plan = plan_reload(&old, &new, &"/dev/uio0", &"/dev/uio0", false);
+        assert!(plan.update_config);
+        assert!(plan.update_throttle);
+        assert_eq!(plan.swap, Swap::Keep);
+        assert_eq!(
+            plan.changes,
+            ["max_vector_size: 10485760 -> 1024", "latency: 0ns -> 2ms"]
+        );
+    }
+
+    #[test]
+    fn changed_device_is_swapped() {
+        let settings = Settings::default();
+        let plan = plan_reload(&settings, &settings, &"/dev/uio0", &"/dev/uio1", false);
+        assert!(!plan.update_config);
+        assert_eq!(plan.swap, Swap::Now);
+        assert_eq!(plan.changes, [r#"device: "/dev/uio0" -> "/dev/uio1""#]);
+    }
+
+    #[test]
+    fn swap_is_deferred_while_a_client_is_connected() {
+        let settings = Settings::default();
+        let plan = plan_reload(&settings, &settings, &"/dev/uio0", &"/dev/uio1", true);
+        assert_eq!(plan.swap, Swap::AfterDisconnect);
+
+        let immediate = Settings {
+            defer_swap: false,
+            ..Settings::default()
+        };
+        let plan = plan_reload(&settings, &immediate, &"/dev/uio0", &"/dev/uio1", true);
+        assert_eq!(plan.swap, Swap::Now);
+    }
+
+    #[test]
+    fn reopen_swaps_the_same_device() {
+        let old = Settings::default();
+        let new = Settings {
+            reopen_device: true,
+            ..Settings::default()
+        };
+        let plan = plan_reload(&old, &new, &"/dev/uio0", &"/dev/uio0", false);
+        assert_eq!(plan.swap, Swap::Now);
+        assert_eq!(
+            plan.changes,
+            ["reopen_device: false -> true", "reopening device"]
+        );
+    }
+}
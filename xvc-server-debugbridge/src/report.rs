@@ -0,0 +1,228 @@
+//! Statistics report that is dumped on `SIGUSR1`, and the summary logged when a client
+//! disconnects.
+use std::{
+    fmt::{self, Display},
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+};
+
+use xvc_server::{
+    XvcServer,
+    observer::{ObserverResult, ServerObserver},
+    server::Server,
+    stats::{ConnectionStats, Peer, StatsSnapshot},
+};
+
+/// Server and backend statistics at one point in time.
+pub struct StatsReport {
+    pub server: StatsSnapshot,
+    pub backend: Vec<(&'static str, u64)>,
+}
+
+impl StatsReport {
+    pub fn collect<T: XvcServer>(server: &Server<T>) -> StatsReport {
+        StatsReport {
+            server: server.stats(),
+            backend: server.backend_stats(),
+        }
+    }
+
+    /// Logs the report at info level, or appends it to `file` if given.
+    pub fn dump(&self, file: Option<&Path>) -> io::Result<()> {
+        match file {
+            Some(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", self)
+            }
+            None => {
+                log::info!("{}", self);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for StatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = &self.server;
+        writeln!(f, "XVC server statistics")?;
+        writeln!(f, "  uptime: {} s", stats.uptime.as_secs())?;
+        writeln!(
+            f,
+            "  connections: {} ({} active)",
+            stats.connections, stats.active_connections
+        )?;
+        writeln!(f, "  connection errors: {}", stats.errors)?;
+        match stats.current_peer {
+            Some(peer) => writeln!(f, "  client: {}", peer)?,
+            None => writeln!(f, "  client: none")?,
+        }
+        writeln!(f, "  shifts: {}", stats.shifts)?;
+        writeln!(f, "  bits shifted: {}", stats.bits_shifted)?;
+        writeln!(f, "  bytes sent: {}", stats.bytes_written)?;
+        match stats.average_backend_time() {
+            Some(average) => writeln!(f, "  average backend latency: {} us", average.as_micros())?,
+            None => writeln!(f, "  average backend latency: n/a")?,
+        }
+        writeln!(
+            f,
+            "  worst backend latency: {} us",
+            stats.worst_backend_time.as_micros()
+        )?;
+        writeln!(f, "  shift latency: {}", stats.shift_latency)?;
+        write!(f, "  settck latency: {}", stats.set_tck_latency)?;
+        for (name, value) in &self.backend {
+            write!(f, "\n  backend {}: {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Logs a summary line of every connection when the client disconnects.
+pub struct ConnectionLog;
+
+impl ServerObserver for ConnectionLog {
+    fn on_disconnect(&self, peer: Peer, stats: &ConnectionStats) -> ObserverResult {
+        log::info!("{}", ConnectionSummary { peer, stats });
+        Ok(())
+    }
+}
+
+struct ConnectionSummary<'a> {
+    peer: Peer,
+    stats: &'a ConnectionStats,
+}
+
+impl Display for ConnectionSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = self.stats;
+        write!(
+            f,
+            "Client {} (connection #{}) disconnected after {:.1} s: {} messages, {} shifts, {} bits shifted, {} bytes sent",
+            self.peer,
+            stats.connection,
+            stats.duration.as_secs_f64(),
+            stats.messages,
+            stats.shifts,
+            stats.bits_shifted,
+            stats.bytes_written
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_util::sync::CancellationToken;
+    use xvc_server::server::Config;
+
+    use super::*;
+
+    struct CountingBackend;
+
+    impl XvcServer for CountingBackend {
+        type Err = Infallible;
+
+        fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+            Ok(period_ns)
+        }
+
+        fn shift(
+            &self,
+            _num_bits: u32,
+            _tms: &[u8],
+            tdi: &[u8],
+            tdo: &mut [u8],
+        ) -> Result<(), Infallible> {
+            tdo.copy_from_slice(tdi);
+            Ok(())
+        }
+
+        fn stats(&self) -> Vec<(&'static str, u64)> {
+            vec![("poll_wait_us", 42)]
+        }
+    }
+
+    #[test]
+    fn report_of_idle_server() {
+        let server = Server::new(CountingBackend, Config::default());
+        let report = StatsReport::collect(&server).to_string();
+        assert!(report.contains("client: none"));
+        assert!(report.contains("connections: 0 (0 active)"));
+        assert!(report.contains("shifts: 0"));
+        assert!(report.contains("average backend latency: n/a"));
+        assert!(report.contains("shift latency: none"));
+        assert!(report.contains("backend poll_wait_us: 42"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn report_after_scripted_session() {
+        let server = std::sync::Arc::new(Server::new(CountingBackend, Config::default()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let token = CancellationToken::new();
+        tokio::spawn({
+            let server = server.clone();
+            let token = token.clone();
+            async move { server.listen_on(listener, token).await }
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        for _ in 0..2 {
+            client
+                .write_all(b"shift:\x0c\x00\x00\x00\x00\x00\xAB\x0C")
+                .await
+                .unwrap();
+            let mut tdo = [0u8; 2];
+            client.read_exact(&mut tdo).await.unwrap();
+            assert_eq!(tdo, [0xAB, 0x0C]);
+        }
+
+        let report = StatsReport::collect(&server).to_string();
+        assert!(report.contains(&format!("client: {}", client.local_addr().unwrap())));
+        assert!(report.contains("shifts: 2"));
+        assert!(report.contains("bits shifted: 24"));
+        assert!(report.contains("connections: 1 (1 active)"));
+        assert!(report.contains("backend poll_wait_us: 42"));
+
+        // The connection handler notices the disconnect asynchronously
+        drop(client);
+        for _ in 0..100 {
+            if StatsReport::collect(&server).server.current_peer.is_none() {
+                token.cancel();
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("client still reported as connected after disconnect");
+    }
+
+    #[test]
+    fn summary_of_a_connection() {
+        let stats = ConnectionStats {
+            connection: 7,
+            duration: Duration::from_millis(2500),
+            messages: 3,
+            shifts: 2,
+            bits_shifted: 24,
+            bytes_written: 28,
+            throttled: Duration::ZERO,
+        };
+        let peer = Peer::Tcp("192.0.2.1:50000".parse().unwrap());
+        assert_eq!(
+            ConnectionSummary {
+                peer,
+                stats: &stats
+            }
+            .to_string(),
+            "Client 192.0.2.1:50000 (connection #7) disconnected after 2.5 s: 3 messages, \
+             2 shifts, 24 bits shifted, 28 bytes sent"
+        );
+    }
+}
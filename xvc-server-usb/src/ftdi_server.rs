@@ -1,5 +1,5 @@
 use ftdi_mpsse::MpsseCmdBuilder;
-use xvc_server::XvcServer;
+use xvc_server::{BackendCapabilities, XvcServer};
 
 use crate::ftdi_device::FtdiJtagDevice;
 
@@ -26,6 +26,11 @@ fn count_for_frequency(frequency: u32) -> (u32, u32) {
     (count - 1, actual) // return the count register directly
 }
 
+/// The TCK period that `set_tck` reports for the clock divisor `count`.
+fn period_for_count(count: u32) -> u32 {
+    1_000_000_000 / (FTDI_CLOCK_RATE / (2 * count))
+}
+
 impl FtdiServer {
     fn set_clock_speed(&self, frequency: u32) -> rusb::Result<u32> {
         let (count, actual) = count_for_frequency(frequency);
@@ -56,4 +61,13 @@ impl XvcServer for FtdiServer {
     ) -> Result<(), Self::Err> {
         self.device.shift_chunks(num_bits, tdi, tms, tdo)
     }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            min_tck_period_ns: Some(period_for_count(1)),
+            max_tck_period_ns: Some(period_for_count(0x10000)),
+            description: Some("FTDI MPSSE JTAG adapter".to_owned()),
+            ..BackendCapabilities::default()
+        }
+    }
 }
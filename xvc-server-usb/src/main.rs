@@ -48,7 +48,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     log::debug!("Server config: max_vector_size={}", config.max_vector_size);
 
     let available_devices =
-        ftdi_device::list_available_devices(args.ftdi_port, config.read_write_timeout)?;
+        ftdi_device::list_available_devices(args.ftdi_port, config.message_timeout)?;
 
     let interactive = !args.non_interactive && stdin().is_terminal() && stderr().is_terminal();
     let Some(device) = disambiguate_available_devices(available_devices, interactive) else {
@@ -61,7 +61,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let addr = SocketAddr::new(args.ip, args.port);
 
     let listener = TcpListener::bind(addr).await?;
-    log::info!("Listening on {addr}");
+    log::info!("Listening on {}", listener.local_addr()?);
 
     let token = CancellationToken::new();
     tokio::spawn({
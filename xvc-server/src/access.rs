@@ -0,0 +1,128 @@
+//! Restricting the hosts that may connect to a [`Server`](crate::server::Server).
+//!
+//! See [`Config::allows`](crate::server::Config::allows) for how the allow and deny lists of
+//! the configuration are evaluated.
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    net::IpAddr,
+    str::FromStr,
+};
+
+/// A range of IPv4 or IPv6 addresses, given by an address and the length of the prefix that
+/// the addresses in the range share.
+///
+/// Parsed from a single address, e.g. `192.168.1.20` or `::1`, or a range in CIDR notation,
+/// e.g. `192.168.1.0/24` or `fd00::/8`:
+///
+/// ```
+/// # use xvc_server::access::IpNetwork;
+/// let lab: IpNetwork = "192.168.1.0/24".parse().unwrap();
+/// assert!(lab.contains("192.168.1.20".parse().unwrap()));
+/// assert!(!lab.contains("192.168.2.20".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    /// The range of addresses that share the first `prefix_len` bits with `addr`, or `None` if
+    /// `prefix_len` exceeds the length of the address.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<IpNetwork> {
+        (prefix_len <= max_prefix_len(addr)).then_some(IpNetwork { addr, prefix_len })
+    }
+
+    /// The range that only contains `addr`.
+    pub fn host(addr: IpAddr) -> IpNetwork {
+        IpNetwork {
+            addr,
+            prefix_len: max_prefix_len(addr),
+        }
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Whether `ip` is in this range. IPv4 addresses that are mapped to IPv6, as reported for
+    /// IPv4 clients of dual-stack sockets, match IPv4 ranges.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => shares_prefix(
+                network.to_bits().into(),
+                ip.to_bits().into(),
+                32,
+                self.prefix_len,
+            ),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                shares_prefix(network.to_bits(), ip.to_bits(), 128, self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn max_prefix_len(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// Whether the first `prefix_len` of the `bits` lowest bits of `a` and `b` are equal.
+fn shares_prefix(a: u128, b: u128, bits: u8, prefix_len: u8) -> bool {
+    (a ^ b)
+        .checked_shr(u32::from(bits - prefix_len))
+        .unwrap_or(0)
+        == 0
+}
+
+impl From<IpAddr> for IpNetwork {
+    fn from(addr: IpAddr) -> Self {
+        IpNetwork::host(addr)
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = ParseNetworkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseNetworkError(s.to_owned());
+        match s.split_once('/') {
+            Some((addr, prefix_len)) => {
+                let addr = addr.parse().map_err(|_| error())?;
+                let prefix_len = prefix_len.parse().map_err(|_| error())?;
+                IpNetwork::new(addr, prefix_len).ok_or_else(error)
+            }
+            None => s.parse().map(IpNetwork::host).map_err(|_| error()),
+        }
+    }
+}
+
+impl Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.prefix_len == max_prefix_len(self.addr) {
+            write!(f, "{}", self.addr)
+        } else {
+            write!(f, "{}/{}", self.addr, self.prefix_len)
+        }
+    }
+}
+
+/// A string that is neither an IP address nor a range in CIDR notation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseNetworkError(String);
+
+impl Display for ParseNetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid IP address or network {:?}", self.0)
+    }
+}
+
+impl Error for ParseNetworkError {}
@@ -0,0 +1,267 @@
+//! Golden protocol vectors to check the wire compatibility of servers and backends.
+//!
+//! Enable with the `testing` feature flag.
+//!
+//! Each [`Vector`] holds the bytes of a request, the message it decodes to and the response
+//! of a server with the [`Loopback`] backend. Backend authors can run the vectors against their
+//! driver with [`run_conformance`], e.g. in a test of their own CI:
+//!
+//! ```ignore
+//! #[tokio::test(flavor = "multi_thread")]
+//! async fn conforms_to_xvc() {
+//!     let report = xvc_server::conformance::run_conformance(MyDriver::new()).await;
+//!     assert!(report.is_pass(), "{report}");
+//! }
+//! ```
+//!
+//! Implementations of the protocol that do not use this crate can send the
+//! [`request`](Vector::request)s to their server and compare the answers with the
+//! [`response`](Vector::response)s instead.
+use std::fmt;
+
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use xvc_protocol::{Message, OwnedMessage, VectorLen};
+
+use crate::{
+    XvcServerMut,
+    server::{Config, Server},
+};
+
+/// The maximum vector size of the server that the vectors assume, in bytes.
+pub const MAX_VECTOR_SIZE: usize = 64;
+
+/// A request and the answer of a conforming server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vector {
+    pub description: &'static str,
+    /// The bytes a client sends, followed by the end of the stream.
+    pub request: Vec<u8>,
+    /// The message `request` decodes to, or `None` if it is malformed. A server closes the
+    /// connection after a malformed message without answering it, except for a shift that is
+    /// too long.
+    pub message: Option<OwnedMessage>,
+    /// The bytes a server with the [`Loopback`] backend sends before it closes the connection.
+    pub response: Vec<u8>,
+    /// Whether `response` comes from the backend, i.e. the period of `settck:` or the TDO of a
+    /// shift, and may therefore differ for other backends.
+    pub backend_dependent: bool,
+}
+
+/// The backend that the responses of the vectors come from.
+pub use crate::test_util::LoopbackBackend as Loopback;
+
+/// The golden vectors, for a server with a maximum vector size of [`MAX_VECTOR_SIZE`] bytes.
+pub fn vectors() -> Vec<Vector> {
+    let max_bits = MAX_VECTOR_SIZE as u32 * 8;
+    let max_tdi: Vec<u8> = (0..MAX_VECTOR_SIZE as u8).collect();
+    vec![
+        Vector {
+            description: "getinfo",
+            request: b"getinfo:".to_vec(),
+            message: Some(Message::GetInfo),
+            response: b"xvcServer_v1.0:64\n".to_vec(),
+            backend_dependent: false,
+        },
+        Vector {
+            description: "settck",
+            request: b"settck:\xE8\x03\x00\x00".to_vec(),
+            message: Some(Message::SetTck { period_ns: 1000 }),
+            response: b"\xE8\x03\x00\x00".to_vec(),
+            backend_dependent: true,
+        },
+        Vector {
+            description: "byte-aligned shift",
+            request: b"shift:\x10\x00\x00\x00\x00\x00\xA5\x5A".to_vec(),
+            message: Some(shift(16, &[0x00, 0x00], &[0xA5, 0x5A])),
+            response: b"\xA5\x5A".to_vec(),
+            backend_dependent: true,
+        },
+        Vector {
+            description: "unaligned shift",
+            request: b"shift:\x0D\x00\x00\x00\x01\x10\xFF\x1F".to_vec(),
+            message: Some(shift(13, &[0x01, 0x10], &[0xFF, 0x1F])),
+            response: b"\xFF\x1F".to_vec(),
+            backend_dependent: true,
+        },
+        Vector {
+            description: "shift of a single bit",
+            request: b"shift:\x01\x00\x00\x00\x01\x01".to_vec(),
+            message: Some(shift(1, &[0x01], &[0x01])),
+            response: b"\x01".to_vec(),
+            backend_dependent: true,
+        },
+        Vector {
+            description: "shift of zero bits",
+            request: b"shift:\x00\x00\x00\x00".to_vec(),
+            message: Some(shift(0, &[], &[])),
+            response: Vec::new(),
+            backend_dependent: false,
+        },
+        Vector {
+            description: "maximum-size shift",
+            request: shift_request(max_bits, &[0; MAX_VECTOR_SIZE], &max_tdi),
+            message: Some(shift(max_bits, &[0; MAX_VECTOR_SIZE], &max_tdi)),
+            response: max_tdi,
+            backend_dependent: true,
+        },
+        Vector {
+            description: "shift exceeding the maximum vector size",
+            request: shift_request(
+                max_bits + 1,
+                &[0; MAX_VECTOR_SIZE + 1],
+                &[0; MAX_VECTOR_SIZE + 1],
+            ),
+            message: None,
+            // The vectors are discarded and the shift is answered with zeros
+            response: vec![0; MAX_VECTOR_SIZE + 1],
+            backend_dependent: false,
+        },
+        Vector {
+            description: "shift truncated within the vectors",
+            request: b"shift:\x10\x00\x00\x00\x00\x00\xA5".to_vec(),
+            message: None,
+            response: Vec::new(),
+            backend_dependent: false,
+        },
+        Vector {
+            description: "settck truncated within the period",
+            request: b"settck:\xE8\x03".to_vec(),
+            message: None,
+            response: Vec::new(),
+            backend_dependent: false,
+        },
+        Vector {
+            description: "unknown command",
+            request: b"lock:".to_vec(),
+            message: None,
+            response: Vec::new(),
+            backend_dependent: false,
+        },
+        Vector {
+            description: "message after an unknown command",
+            request: b"bogus:getinfo:".to_vec(),
+            message: None,
+            response: Vec::new(),
+            backend_dependent: false,
+        },
+    ]
+}
+
+fn shift(num_bits: u32, tms: &[u8], tdi: &[u8]) -> OwnedMessage {
+    Message::Shift {
+        num_bits,
+        tms: tms.into(),
+        tdi: tdi.into(),
+    }
+}
+
+fn shift_request(num_bits: u32, tms: &[u8], tdi: &[u8]) -> Vec<u8> {
+    let mut request = b"shift:".to_vec();
+    request.extend_from_slice(&num_bits.to_le_bytes());
+    request.extend_from_slice(tms);
+    request.extend_from_slice(tdi);
+    request
+}
+
+/// The outcome of [`run_conformance`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub passed: usize,
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    pub fn is_pass(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} vectors passed",
+            self.passed,
+            self.passed + self.failures.len()
+        )?;
+        for failure in &self.failures {
+            write!(f, "\n{failure}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A vector whose response differs from the golden one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub description: &'static str,
+    pub expected: Vec<u8>,
+    pub received: Vec<u8>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {:02x?}, received {:02x?}",
+            self.description, self.expected, self.received
+        )
+    }
+}
+
+/// Serve each of the [`vectors`] with `backend` over an in-memory stream and compare the
+/// responses with the golden ones.
+///
+/// Responses that depend on the backend are only compared in length, as e.g. hardware returns
+/// the TDO of its JTAG chain rather than that of the [`Loopback`] backend.
+///
+/// # Panics
+///
+/// The backend is called via `block_in_place` like in [`Server`], so this panics outside a
+/// multi-thread tokio runtime.
+pub async fn run_conformance<T: XvcServerMut + Send + 'static>(backend: T) -> Report {
+    let server = Server::new(
+        backend,
+        Config {
+            max_vector_size: VectorLen::from_bytes(MAX_VECTOR_SIZE as u32),
+            ..Config::default()
+        },
+    );
+    let mut report = Report::default();
+    for vector in vectors() {
+        let received = exchange(&server, &vector.request)
+            .await
+            .expect("in-memory streams do not fail");
+        let passed = if vector.backend_dependent {
+            received.len() == vector.response.len()
+        } else {
+            received == vector.response
+        };
+        if passed {
+            report.passed += 1;
+        } else {
+            report.failures.push(Failure {
+                description: vector.description,
+                expected: vector.response,
+                received,
+            });
+        }
+    }
+    report
+}
+
+/// Serve `request` on a new connection and return everything the server answers until it
+/// closes the connection.
+async fn exchange<T: XvcServerMut + Send + 'static>(
+    server: &Server<T>,
+    request: &[u8],
+) -> io::Result<Vec<u8>> {
+    let (mut client, connection) = io::duplex(request.len() + 2 * MAX_VECTOR_SIZE);
+    client.write_all(request).await?;
+    client.shutdown().await?;
+    // A malformed request closes the connection with an error, which is the expected outcome
+    let _ = server.handle_stream(connection).await;
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).await?;
+    Ok(response)
+}
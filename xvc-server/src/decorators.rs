@@ -0,0 +1,330 @@
+//! Backends that wrap another [`XvcServer`] to alter its behavior.
+//!
+//! Decorators implement [`XvcServer`] themselves and can therefore be passed to
+//! [`Server::new`](crate::server::Server::new) wherever the wrapped backend could be used.
+use std::{
+    mem,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{BackendCapabilities, XvcServer};
+
+/// Slows down a backend to emulate a slow link.
+///
+/// Every shift is delayed so that the effective throughput (`num_bits / elapsed`) does not
+/// exceed the configured bandwidth, and every message is delayed by an optional fixed latency.
+/// This is useful for testing the timeout behavior of clients without shaping the network.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use xvc_server::decorators::Throttled;
+/// # fn wrap<T: xvc_server::XvcServer>(backend: T) -> Throttled<T> {
+/// Throttled::new(backend)
+///     .with_bandwidth(1_000_000)
+///     .with_latency(Duration::from_millis(5))
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Throttled<T> {
+    inner: T,
+    bits_per_sec: Option<u64>,
+    latency: Duration,
+}
+
+impl<T> Throttled<T> {
+    /// Wraps `inner` without any throttling.
+    pub fn new(inner: T) -> Throttled<T> {
+        Throttled {
+            inner,
+            bits_per_sec: None,
+            latency: Duration::ZERO,
+        }
+    }
+
+    /// Caps the throughput of shifts at `bits_per_sec`. A value of zero disables the cap.
+    pub fn with_bandwidth(mut self, bits_per_sec: u64) -> Throttled<T> {
+        self.set_bandwidth(bits_per_sec);
+        self
+    }
+
+    /// Adds a fixed delay to every message.
+    pub fn with_latency(mut self, latency: Duration) -> Throttled<T> {
+        self.set_latency(latency);
+        self
+    }
+
+    /// See [`with_bandwidth`](Self::with_bandwidth)
+    pub fn set_bandwidth(&mut self, bits_per_sec: u64) {
+        self.bits_per_sec = (bits_per_sec > 0).then_some(bits_per_sec);
+    }
+
+    /// See [`with_latency`](Self::with_latency)
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = latency;
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// The minimum time a shift of `num_bits` takes.
+    fn shift_duration(&self, num_bits: u32) -> Duration {
+        let transfer = match self.bits_per_sec {
+            Some(rate) => Duration::from_nanos((num_bits as u64 * 1_000_000_000).div_ceil(rate)),
+            None => Duration::ZERO,
+        };
+        self.latency + transfer
+    }
+}
+
+/// Sleeps for the remainder of `duration`, measured from `start`.
+fn pace(start: Instant, duration: Duration) {
+    if let Some(remaining) = duration.checked_sub(start.elapsed()) {
+        thread::sleep(remaining);
+    }
+}
+
+impl<T: XvcServer> XvcServer for Throttled<T> {
+    type Err = T::Err;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Self::Err> {
+        let start = Instant::now();
+        let result = self.inner.set_tck(period_ns);
+        pace(start, self.latency);
+        result
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err> {
+        let start = Instant::now();
+        let result = self.inner.shift(num_bits, tms, tdi, tdo);
+        pace(start, self.shift_duration(num_bits));
+        result
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        self.inner.stats()
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        self.inner.shift_chunk_len()
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn on_connect(&self) {
+        self.inner.on_connect()
+    }
+
+    fn on_disconnect(&self) {
+        self.inner.on_disconnect()
+    }
+}
+
+/// Allows modifying or replacing a backend while the server is serving it.
+///
+/// Clones share the same backend: pass one clone to [`Server::new`](crate::server::Server::new)
+/// and keep another to [`switch`](Self::switch) the backend later, e.g. after the device
+/// was re-enumerated. A backend call in progress completes before the switch takes effect.
+/// The server reads the [capabilities](XvcServer::capabilities) of the backend only when it is
+/// created, so a replacement should not support shorter vectors than the first backend.
+#[derive(Debug)]
+pub struct Switchable<T>(Arc<Mutex<T>>);
+
+impl<T> Clone for Switchable<T> {
+    fn clone(&self) -> Self {
+        Switchable(Arc::clone(&self.0))
+    }
+}
+
+impl<T> Switchable<T> {
+    pub fn new(inner: T) -> Switchable<T> {
+        Switchable(Arc::new(Mutex::new(inner)))
+    }
+
+    /// Replaces the backend, returning the previous one.
+    pub fn switch(&self, inner: T) -> T {
+        mem::replace(&mut *self.lock(), inner)
+    }
+
+    /// Runs `f` with exclusive access to the backend, e.g. to change its settings.
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, T> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T: XvcServer> XvcServer for Switchable<T> {
+    type Err = T::Err;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Self::Err> {
+        self.lock().set_tck(period_ns)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err> {
+        self.lock().shift(num_bits, tms, tdi, tdo)
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        self.lock().stats()
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        self.lock().shift_chunk_len()
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        self.lock().capabilities()
+    }
+
+    fn on_connect(&self) {
+        self.lock().on_connect()
+    }
+
+    fn on_disconnect(&self) {
+        self.lock().on_disconnect()
+    }
+}
+
+/// Drives the TAP controller of a backend to Test-Logic-Reset when a client disconnects.
+///
+/// A client that crashes or loses its connection in the middle of a sequence can leave the
+/// chain in e.g. Shift-DR with a partially loaded instruction, which the next client would
+/// start from. On [`on_disconnect`](XvcServer::on_disconnect), this shifts five bits with TMS
+/// high, which reach Test-Logic-Reset from any state, and optionally a sixth with TMS low to
+/// settle in Run-Test/Idle. TDI is held low. Between the messages of a connection, the backend
+/// is left alone.
+///
+/// With [`exclusive_client`](crate::server::Config::exclusive_client) disabled, the reset is
+/// only shifted once the last of the clients that are served at the same time disconnected, as
+/// it would break into the sessions of the others. The clients are counted by
+/// [`on_connect`](XvcServer::on_connect).
+///
+/// A failing reset is logged, the disconnect is passed on to the backend regardless.
+///
+/// ```
+/// # use xvc_server::decorators::TapSafety;
+/// # fn wrap<T: xvc_server::XvcServer>(backend: T) -> TapSafety<T> {
+/// TapSafety::new(backend).settle_in_idle(true)
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TapSafety<T> {
+    inner: T,
+    settle_in_idle: bool,
+    /// The clients that are connected
+    sessions: AtomicUsize,
+}
+
+impl<T> TapSafety<T> {
+    /// Wraps `inner`, resetting its TAP controller and leaving it in Test-Logic-Reset.
+    pub fn new(inner: T) -> TapSafety<T> {
+        TapSafety {
+            inner,
+            settle_in_idle: false,
+            sessions: AtomicUsize::new(0),
+        }
+    }
+
+    /// Move on to Run-Test/Idle after the reset with one more clock with TMS low.
+    pub fn settle_in_idle(mut self, settle: bool) -> TapSafety<T> {
+        self.settle_in_idle = settle;
+        self
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: XvcServer> TapSafety<T> {
+    fn reset(&self) {
+        let num_bits = if self.settle_in_idle { 6 } else { 5 };
+        if let Err(e) = self.inner.shift(num_bits, &[0b1_1111], &[0], &mut [0]) {
+            log::error!("Could not reset the TAP controller after disconnect: {}", e);
+        }
+    }
+}
+
+impl<T: XvcServer> XvcServer for TapSafety<T> {
+    type Err = T::Err;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Self::Err> {
+        self.inner.set_tck(period_ns)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err> {
+        self.inner.shift(num_bits, tms, tdi, tdo)
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        self.inner.stats()
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        self.inner.shift_chunk_len()
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn on_connect(&self) {
+        self.sessions.fetch_add(1, Ordering::Relaxed);
+        self.inner.on_connect()
+    }
+
+    fn on_disconnect(&self) {
+        let sessions = self
+            .sessions
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sessions| {
+                Some(sessions.saturating_sub(1))
+            })
+            .unwrap_or_default();
+        if sessions <= 1 {
+            self.reset();
+        }
+        self.inner.on_disconnect()
+    }
+}
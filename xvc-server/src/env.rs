@@ -0,0 +1,238 @@
+//! Reading a [`Config`] from environment variables, see [`Config::from_env`].
+use std::{collections::HashMap, env, fmt::Display, path::PathBuf, str::FromStr, time::Duration};
+
+use xvc_protocol::VectorLen;
+
+use crate::{
+    access::IpNetwork,
+    server::{Config, ConfigError, ShiftErrorPolicy},
+};
+
+/// The prefix of the variables that are read.
+const PREFIX: &str = "XVC_";
+
+impl Config {
+    /// The default configuration with the settings of the `XVC_*` environment variables, e.g.
+    /// for embedded targets that are set up by an init script rather than command line flags.
+    ///
+    /// | Variable | Setting |
+    /// |---|---|
+    /// | `XVC_MAX_VECTOR_SIZE` | [`max_vector_size`](Config::max_vector_size) in bytes |
+    /// | `XVC_RW_TIMEOUT_MS` | Both `idle_timeout` and `message_timeout`, overridden by the next two |
+    /// | `XVC_IDLE_TIMEOUT_MS` | [`idle_timeout`](Config::idle_timeout), or `none` |
+    /// | `XVC_MESSAGE_TIMEOUT_MS` | [`message_timeout`](Config::message_timeout) |
+    /// | `XVC_TCP_NODELAY` | [`tcp_nodelay`](Config::tcp_nodelay) |
+    /// | `XVC_TCP_KEEPALIVE_MS` | [`tcp_keepalive`](Config::tcp_keepalive) |
+    /// | `XVC_WRITE_BUFFER_SIZE` | [`write_buffer_size`](Config::write_buffer_size) in bytes |
+    /// | `XVC_MAX_SHIFT_BYTES_PER_SEC` | [`max_shift_bytes_per_sec`](Config::max_shift_bytes_per_sec) |
+    /// | `XVC_MAX_MESSAGES_PER_SEC` | [`max_messages_per_sec`](Config::max_messages_per_sec) |
+    /// | `XVC_TCK_PERIOD_RANGE_NS` | [`tck_period_range`](Config::tck_period_range) as `min-max` |
+    /// | `XVC_SHIFT_ERROR_POLICY` | [`shift_error_policy`](Config::shift_error_policy): `send-tdo`, `zero-fill` or `close` |
+    /// | `XVC_SLOW_OP_WARN_MS` | [`slow_op_warn_threshold`](Config::slow_op_warn_threshold) |
+    /// | `XVC_SKIP_UNKNOWN_COMMANDS` | [`skip_unknown_commands`](Config::skip_unknown_commands) |
+    /// | `XVC_RESYNC_ON_ERROR` | [`resync_on_error`](Config::resync_on_error) |
+    /// | `XVC_EXCLUSIVE_CLIENT` | [`exclusive_client`](Config::exclusive_client) |
+    /// | `XVC_MAX_CLIENTS` | [`max_clients`](Config::max_clients) |
+    /// | `XVC_MAX_CONNECTIONS` | [`max_connections`](Config::max_connections) |
+    /// | `XVC_ALLOWED_PEERS` | [`allowed_peers`](Config::allowed_peers), separated by commas |
+    /// | `XVC_DENIED_PEERS` | [`denied_peers`](Config::denied_peers), separated by commas |
+    /// | `XVC_EXPECT_PROXY_PROTOCOL` | [`expect_proxy_protocol`](Config::expect_proxy_protocol) |
+    /// | `XVC_CRC` | [`crc`](Config::crc) |
+    /// | `XVC_TRANSCRIPT_PATH` | [`transcript_path`](Config::transcript_path) |
+    ///
+    /// Switches are `true` or `1`, and `false` or `0`. Variables that are unset or empty keep
+    /// the default. Other `XVC_*` variables are logged as unknown and ignored. The first value
+    /// that cannot be parsed is returned as [`ConfigError::InvalidVariable`], and the
+    /// configuration is [validated](Config::validate) as a whole.
+    pub fn from_env() -> Result<Config, ConfigError> {
+        let mut vars = Vec::new();
+        for (name, value) in env::vars_os() {
+            let Some(name) = name.to_str().filter(|name| name.starts_with(PREFIX)) else {
+                continue;
+            };
+            match value.into_string() {
+                Ok(value) => vars.push((name.to_owned(), value)),
+                Err(value) => {
+                    return Err(ConfigError::InvalidVariable {
+                        name: name.to_owned(),
+                        value: value.to_string_lossy().into_owned(),
+                        reason: "not valid Unicode".to_owned(),
+                    });
+                }
+            }
+        }
+        Config::from_vars(vars)
+    }
+
+    /// Like [`from_env`](Config::from_env), but reads the variables from `vars` instead of the
+    /// environment of the process, e.g. from a file of `NAME=value` lines.
+    pub fn from_vars<K, V>(vars: impl IntoIterator<Item = (K, V)>) -> Result<Config, ConfigError>
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let mut vars = Vars(
+            vars.into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .filter(|(name, _)| name.starts_with(PREFIX))
+                .collect(),
+        );
+        let mut config = Config::default();
+        if let Some(size) = vars.get("XVC_MAX_VECTOR_SIZE", non_zero::<u32>)? {
+            config.max_vector_size = VectorLen::from_bytes(size);
+        }
+        if let Some(timeout) = vars.get("XVC_RW_TIMEOUT_MS", timeout_ms)? {
+            config.idle_timeout = Some(timeout);
+            config.message_timeout = timeout;
+        }
+        if let Some(timeout) = vars.get("XVC_IDLE_TIMEOUT_MS", |value| match value {
+            "none" => Ok(None),
+            value => timeout_ms(value).map(Some),
+        })? {
+            config.idle_timeout = timeout;
+        }
+        if let Some(timeout) = vars.get("XVC_MESSAGE_TIMEOUT_MS", timeout_ms)? {
+            config.message_timeout = timeout;
+        }
+        if let Some(nodelay) = vars.get("XVC_TCP_NODELAY", switch)? {
+            config.tcp_nodelay = nodelay;
+        }
+        if let Some(idle) = vars.get("XVC_TCP_KEEPALIVE_MS", timeout_ms)? {
+            config.tcp_keepalive = Some(idle);
+        }
+        if let Some(size) = vars.get("XVC_WRITE_BUFFER_SIZE", parsed)? {
+            config.write_buffer_size = size;
+        }
+        if let Some(bytes) = vars.get("XVC_MAX_SHIFT_BYTES_PER_SEC", non_zero)? {
+            config.max_shift_bytes_per_sec = Some(bytes);
+        }
+        if let Some(messages) = vars.get("XVC_MAX_MESSAGES_PER_SEC", non_zero)? {
+            config.max_messages_per_sec = Some(messages);
+        }
+        if let Some(range) = vars.get("XVC_TCK_PERIOD_RANGE_NS", |value| {
+            let (min, max) = value
+                .split_once('-')
+                .ok_or_else(|| "expected a range like 10-1000".to_owned())?;
+            let (min, max) = (parsed::<u32>(min.trim())?, parsed::<u32>(max.trim())?);
+            if min > max {
+                return Err("the minimum is greater than the maximum".to_owned());
+            }
+            Ok(min..=max)
+        })? {
+            config.tck_period_range = Some(range);
+        }
+        if let Some(policy) = vars.get("XVC_SHIFT_ERROR_POLICY", |value| match value {
+            "send-tdo" => Ok(ShiftErrorPolicy::SendTdo),
+            "zero-fill" => Ok(ShiftErrorPolicy::ZeroFill),
+            "close" => Ok(ShiftErrorPolicy::CloseConnection),
+            _ => Err("expected send-tdo, zero-fill or close".to_owned()),
+        })? {
+            config.shift_error_policy = policy;
+        }
+        if let Some(threshold) = vars.get("XVC_SLOW_OP_WARN_MS", millis)? {
+            config.slow_op_warn_threshold = Some(threshold);
+        }
+        if let Some(skip) = vars.get("XVC_SKIP_UNKNOWN_COMMANDS", switch)? {
+            config.skip_unknown_commands = skip;
+        }
+        if let Some(resync) = vars.get("XVC_RESYNC_ON_ERROR", switch)? {
+            config.resync_on_error = resync;
+        }
+        if let Some(exclusive) = vars.get("XVC_EXCLUSIVE_CLIENT", switch)? {
+            config.exclusive_client = exclusive;
+        }
+        if let Some(clients) = vars.get("XVC_MAX_CLIENTS", non_zero)? {
+            config.max_clients = clients;
+        }
+        if let Some(connections) = vars.get("XVC_MAX_CONNECTIONS", parsed)? {
+            config.max_connections = Some(connections);
+        }
+        if let Some(peers) = vars.get("XVC_ALLOWED_PEERS", networks)? {
+            config.allowed_peers = peers;
+        }
+        if let Some(peers) = vars.get("XVC_DENIED_PEERS", networks)? {
+            config.denied_peers = peers;
+        }
+        if let Some(expect) = vars.get("XVC_EXPECT_PROXY_PROTOCOL", switch)? {
+            config.expect_proxy_protocol = expect;
+        }
+        if let Some(crc) = vars.get("XVC_CRC", switch)? {
+            config.crc = crc;
+        }
+        if let Some(path) = vars.get("XVC_TRANSCRIPT_PATH", |value| Ok(PathBuf::from(value)))? {
+            config.transcript_path = Some(path);
+        }
+        let mut unknown: Vec<_> = vars.0.into_keys().collect();
+        unknown.sort();
+        for name in unknown {
+            log::warn!("Ignoring unknown environment variable {name}");
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// The variables that were not read yet.
+struct Vars(HashMap<String, String>);
+
+impl Vars {
+    /// Parse the variable `name`, or return `None` if it is unset or empty.
+    fn get<T>(
+        &mut self,
+        name: &str,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Result<Option<T>, ConfigError> {
+        let Some(value) = self.0.remove(name) else {
+            return Ok(None);
+        };
+        let trimmed = value.trim();
+        if trimmed.is_empty() {
+            return Ok(None);
+        }
+        parse(trimmed)
+            .map(Some)
+            .map_err(|reason| ConfigError::InvalidVariable {
+                name: name.to_owned(),
+                value,
+                reason,
+            })
+    }
+}
+
+fn parsed<T: FromStr<Err: Display>>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|e: T::Err| e.to_string())
+}
+
+fn non_zero<T: FromStr<Err: Display> + Default + PartialEq>(value: &str) -> Result<T, String> {
+    match parsed(value)? {
+        zero if zero == T::default() => Err("must not be zero".to_owned()),
+        value => Ok(value),
+    }
+}
+
+fn millis(value: &str) -> Result<Duration, String> {
+    parsed(value).map(Duration::from_millis)
+}
+
+/// Milliseconds, which must not be zero.
+fn timeout_ms(value: &str) -> Result<Duration, String> {
+    non_zero(value).map(Duration::from_millis)
+}
+
+fn switch(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err("expected true or false".to_owned()),
+    }
+}
+
+/// Addresses and CIDR ranges separated by commas.
+fn networks(value: &str) -> Result<Vec<IpNetwork>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|network| !network.is_empty())
+        .map(parsed)
+        .collect()
+}
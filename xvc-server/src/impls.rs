@@ -0,0 +1,164 @@
+//! [`XvcServer`] for references, smart pointers and mutexes of backends.
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{BackendCapabilities, XvcServer, XvcServerMut};
+
+impl<T: XvcServer + ?Sized> XvcServer for &T {
+    type Err = T::Err;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Self::Err> {
+        XvcServer::set_tck(&**self, period_ns)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err> {
+        XvcServer::shift(&**self, num_bits, tms, tdi, tdo)
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        XvcServer::stats(&**self)
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        XvcServer::shift_chunk_len(&**self)
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        XvcServer::capabilities(&**self)
+    }
+
+    fn on_connect(&self) {
+        XvcServer::on_connect(&**self)
+    }
+
+    fn on_disconnect(&self) {
+        XvcServer::on_disconnect(&**self)
+    }
+}
+
+impl<T: XvcServer + ?Sized> XvcServer for Box<T> {
+    type Err = T::Err;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Self::Err> {
+        XvcServer::set_tck(&**self, period_ns)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err> {
+        XvcServer::shift(&**self, num_bits, tms, tdi, tdo)
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        XvcServer::stats(&**self)
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        XvcServer::shift_chunk_len(&**self)
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        XvcServer::capabilities(&**self)
+    }
+
+    fn on_connect(&self) {
+        XvcServer::on_connect(&**self)
+    }
+
+    fn on_disconnect(&self) {
+        XvcServer::on_disconnect(&**self)
+    }
+}
+
+impl<T: XvcServer + ?Sized> XvcServer for Arc<T> {
+    type Err = T::Err;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Self::Err> {
+        XvcServer::set_tck(&**self, period_ns)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err> {
+        XvcServer::shift(&**self, num_bits, tms, tdi, tdo)
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        XvcServer::stats(&**self)
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        XvcServer::shift_chunk_len(&**self)
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        XvcServer::capabilities(&**self)
+    }
+
+    fn on_connect(&self) {
+        XvcServer::on_connect(&**self)
+    }
+
+    fn on_disconnect(&self) {
+        XvcServer::on_disconnect(&**self)
+    }
+}
+
+/// Locks the backend for a single call. A backend that panicked while it was locked is used
+/// as it was left, like the server does with the backend it owns.
+impl<T: XvcServerMut> XvcServer for Mutex<T> {
+    type Err = T::Err;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Self::Err> {
+        XvcServerMut::set_tck(&mut *lock(self), period_ns)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err> {
+        XvcServerMut::shift(&mut *lock(self), num_bits, tms, tdi, tdo)
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        XvcServerMut::stats(&*lock(self))
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        XvcServerMut::shift_chunk_len(&*lock(self))
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        XvcServerMut::capabilities(&*lock(self))
+    }
+
+    fn on_connect(&self) {
+        XvcServerMut::on_connect(&mut *lock(self))
+    }
+
+    fn on_disconnect(&self) {
+        XvcServerMut::on_disconnect(&mut *lock(self))
+    }
+}
+
+fn lock<T>(backend: &Mutex<T>) -> MutexGuard<'_, T> {
+    backend
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -18,6 +18,27 @@
 //! - **[`server::Server`]**: A generic server that handles XVC protocol communication,
 //!   message parsing, and client connections
 //!
+//! Backends can additionally be wrapped in the [`decorators`], e.g. to emulate a slow link or
+//! to reset the TAP controller when a client disconnects.
+//! An [`observer::ServerObserver`] is told about connections and messages, e.g. for usage
+//! accounting.
+//! Besides TCP, [`server::Server::handle_stream`] serves a client over any async transport, such
+//! as a serial port or an in-memory pipe.
+//! With the `tls` feature, the `tls` module secures TCP connections with TLS.
+//! On Unix, [`server::Server::listen_unix`] serves clients on a Unix domain socket instead of a
+//! TCP port, e.g. for a debugger front-end that runs on the same host.
+//! [`server::Server::serve_on_fd`] serves a socket that was bound outside of the server, e.g.
+//! by systemd socket activation.
+//! [`multi::MultiServer`] serves several backends on ports of their own from one process.
+//! A [`mirror::Mirror`] sends a copy of the traffic of the clients to read-only observers.
+//! With the `metrics` feature, the `metrics` module exposes Prometheus metrics of the server.
+//! With the `tracing` feature, every connection and message is served in a [`tracing`] span,
+//! e.g. to correlate slow operations of a client with the backend calls they caused.
+//! With the `testing` feature, the `conformance` module checks a backend against golden
+//! protocol vectors, and the `test_util` module has loopback, constant, recording and replay backends
+//! for tests of clients. The `simulated_tap` module models a chain of JTAG devices, e.g. to
+//! test the IDCODE scan of a client without hardware.
+//!
 //! ## How It Works
 //!
 //! 1. A backend driver (e.g., kernel driver, UIO device) implements the [`XvcServer`] trait
@@ -64,6 +85,35 @@
 //! }
 //! ```
 //!
+//! Backends with state that changes on every call, e.g. a simulated TAP controller or a
+//! bit-banged port, can implement [`XvcServerMut`] instead, whose methods take `&mut self`.
+//! The server calls one backend method at a time, so no interior mutability is needed:
+//!
+//! ```no_run
+//! use std::convert::Infallible;
+//! use xvc_server::XvcServerMut;
+//!
+//! #[derive(Default)]
+//! struct SimulatedTap {
+//!     period_ns: u32,
+//!     cycles: u64,
+//! }
+//!
+//! impl XvcServerMut for SimulatedTap {
+//!     type Err = Infallible;
+//!
+//!     fn set_tck(&mut self, period_ns: u32) -> Result<u32, Self::Err> {
+//!         self.period_ns = period_ns;
+//!         Ok(period_ns)
+//!     }
+//!
+//!     fn shift(&mut self, num_bits: u32, tms: &[u8], tdi: &[u8], tdo: &mut [u8]) -> Result<(), Self::Err> {
+//!         self.cycles += u64::from(num_bits);
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
 //! ### Starting the Server
 //!
 //! ```ignore
@@ -84,10 +134,54 @@
 //!
 //! ## Configuration
 //!
-//! Server behavior can be customized via [`server::Config`]:
-//!
-//! - **max_vector_size**: Maximum size of JTAG vectors (default: 10 MiB)
-//! - **read_write_timeout**: Socket I/O timeout duration (default: 30 seconds)
+//! Server behavior can be customized via [`server::Config`], which
+//! [`server::Config::from_env`] also reads from `XVC_*` environment variables. A config that
+//! cannot serve clients, e.g. one with a zero vector size, fails [`server::Builder::build`] and
+//! [`server::Server::update_config`] with a [`server::ConfigError`]:
+//!
+//! - **max_vector_size**: Maximum size of each of the TMS and TDI vectors, lowered to the limit
+//!   of the backend if it reports one; longer shifts are discarded (default: 10 MiB)
+//! - **idle_timeout**: Time a client may wait before its next command, or none for no limit
+//!   (default: 30 seconds)
+//! - **message_timeout**: Time a client may take to complete a command it started (default: 30
+//!   seconds)
+//! - **tcp_nodelay**: Send small responses right away instead of batching them (default: true)
+//! - **tcp_keepalive**: Idle time before TCP keepalive probes detect dead peers (default: none)
+//! - **write_buffer_size**: Buffer in which the answers to pipelined messages are sent together
+//!   (default: 64 KiB)
+//! - **max_shift_bytes_per_sec** / **max_messages_per_sec**: Per-connection rate limits; a
+//!   connection over its budget is not read from until it is restored (default: none)
+//! - **tck_period_range**: TCK periods that clients may set; others are clamped into the range
+//!   (default: none)
+//! - **shift_error_policy**: Answer to a shift that the backend failed: the TDO as it is, zeros,
+//!   or none, closing the connection (default: the TDO as it is)
+//! - **slow_op_warn_threshold**: Warn about backend calls that take longer, timing only the
+//!   backend and not the socket I/O (default: none)
+//! - **resync_on_error**: Skip a malformed message up to the next command, within
+//!   `resync_scan_limit` bytes and at most `max_resyncs` times per connection (default: false)
+//! - **tck_on_connect**: TCK period set at the start of each connection, a fixed one or the one
+//!   last set by a client (default: keep the current period)
+//! - **exclusive_client**: Serve only one client at a time (default: true)
+//! - **max_clients**: Number of clients served at the same time without `exclusive_client`
+//!   (default: 1)
+//! - **client_policy**: Reject connections while all clients are active, or hold them open
+//!   in a queue (default: reject)
+//! - **pool**: Serve clients with a fixed number of workers and a pending queue (default: none)
+//! - **max_connections**: Stop listening after this many connections (default: none)
+//! - **max_accept_errors**: Stop listening after this many failed accepts in a row, which back
+//!   off exponentially (default: none)
+//! - **bind_policy**: Whether `bind_all` fails if any of its addresses cannot be bound, or
+//!   serves the others (default: fail fast)
+//! - **unix_socket_mode**: Permissions of the socket file of a Unix domain socket listener
+//!   (default: none)
+//! - **allowed_peers** / **denied_peers**: Addresses and CIDR ranges of TCP clients that may or
+//!   may not connect; denied ranges take precedence (default: all clients allowed)
+//! - **expect_proxy_protocol**: Read the client address from a PROXY protocol header, for
+//!   servers behind a load balancer (default: false)
+//! - **mirror**: Observers that receive a copy of the bytes exchanged with every client
+//!   (default: none)
+//! - **transcript_path**: Directory in which the messages and answers of every connection are
+//!   recorded, in a transcript file per connection (default: none)
 //!
 //! ## Logging
 //!
@@ -96,6 +190,12 @@
 //! - Protocol messages being processed
 //! - Configuration details and error conditions
 //!
+//! The lines that are logged while a client is served start with the number of its
+//! connection, its address and the address it connected to, e.g.
+//! `[#3 192.0.2.1:50000 -> 192.0.2.2:2542]`, so the lines of concurrent clients, and those of
+//! the endpoints of a [`multi::MultiServer`], can be told apart. The number is also in the
+//! [`stats::ConnectionStats`] that observers receive.
+//!
 //! Configure logging with an implementation like `env_logger`:
 //!
 //! ```ignore
@@ -104,14 +204,52 @@
 //!
 //! ## Thread Model
 //!
-//! The server is async (tokio) and accepts connections concurrently, but enforces
+//! The server is async (tokio) and accepts connections concurrently, but by default enforces
 //! **at-most-one active client** at a time. A second connection attempt while a client
 //! is active is immediately rejected. This matches the XVC protocol assumption of a
 //! single JTAG session and prevents interleaved access to the hardware state machine.
 //!
+//! With [`server::ClientPolicy::Queue`], such connections wait instead, and are served one
+//! after another in the order they connected. Waiting clients are answered `getinfo:` only.
+//!
+//! With [`server::Config::exclusive_client`] disabled, [`server::Config::max_clients`] allows
+//! several clients to be served at once, each in its own task. The backend is shared behind a
+//! mutex, so it needs to be `Send` but not `Sync`, and each backend call completes before the
+//! next one starts.
+//!
+//! Alternatively, [`server::Config::pool`] serves clients with a fixed number of workers and
+//! a bounded queue of connections waiting for them, e.g. to limit the resources on small
+//! targets.
+//!
 //! Backend methods (`set_tck`, `shift`) are called via `block_in_place`, so the server
 //! requires a multi-thread tokio runtime.
+//!
+//! The server runs on the runtime of the caller, so it can be embedded in other tokio
+//! applications. Dropping the future of [`server::Server::listen`] stops the server and closes
+//! the connected clients, like cancelling the token of [`server::Server::listen_on`].
+pub mod access;
+#[cfg(feature = "testing")]
+pub mod conformance;
+pub mod decorators;
+mod env;
+mod impls;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod mirror;
+pub mod multi;
+pub mod observer;
+pub mod proxy;
 pub mod server;
+#[cfg(feature = "testing")]
+pub mod simulated_tap;
+#[cfg(feature = "tracing")]
+mod spans;
+pub mod stats;
+#[cfg(feature = "testing")]
+pub mod test_util;
+#[cfg(feature = "tls")]
+pub mod tls;
+mod transcript;
 
 /// Trait that backend drivers must implement to provide JTAG functionality.
 ///
@@ -120,6 +258,10 @@ pub mod server;
 /// high-level JTAG operations into hardware-specific commands.
 ///
 /// See the [`xvc-server-debugbridge`](https://docs.rs/xvc-server-debugbridge/) crate for examples.
+///
+/// References, [`Box`]es and [`Arc`](std::sync::Arc)s of backends are backends too, so one
+/// backend can be served by several [`server::Server`]s, and a backend that is chosen at runtime
+/// can be served as `Box<dyn XvcServer<Err = E> + Send>`.
 pub trait XvcServer {
     type Err: std::error::Error;
     /// Set the TCK (Test Clock) period.
@@ -150,7 +292,8 @@ pub trait XvcServer {
     /// 1. Shifting `tms` and `tdi` data into the JTAG chain
     /// 2. Capturing and the corresponding TDO data to `tdo`
     ///
-    /// The operation is atomic with respect to the JTAG state machine.
+    /// The operation is atomic with respect to the JTAG state machine. The server answers
+    /// shifts of zero bits itself, so `num_bits` is never zero.
     ///
     /// # Arguments
     ///
@@ -166,7 +309,165 @@ pub trait XvcServer {
     /// Returns [`Self::Err`] if the hardware shift fails. The XVC 1.0 protocol has no
     /// error channel, so the server cannot report the failure to the client: it logs
     /// the error and sends the current contents of `tdo` (zeroed by the caller) as the
-    /// TDO response. Implementations should leave `tdo` as-is on error.
+    /// TDO response, unless [`server::Config::shift_error_policy`] says otherwise.
+    /// Implementations should leave `tdo` as-is on error.
     fn shift(&self, num_bits: u32, tms: &[u8], tdi: &[u8], tdo: &mut [u8])
     -> Result<(), Self::Err>;
+
+    /// Backend-specific counters for diagnostics, as `(name, value)` pairs.
+    ///
+    /// These complement the statistics collected by the server itself
+    /// (see [`server::Server::stats`]), e.g. with time spent polling the hardware
+    /// or the number of failed driver calls. The default implementation reports nothing.
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        Vec::new()
+    }
+
+    /// The number of bytes of each vector that [`shift`](Self::shift) is given at a time for
+    /// long shifts, or `None` to always be given whole shifts (the default).
+    ///
+    /// A shift whose vectors are longer than this is streamed through the backend: the server
+    /// reads its TMS vector, which precedes TDI on the wire, and then calls `shift` for each
+    /// chunk of TDI as it arrives, sending the TDO of each chunk back as soon as the client
+    /// reads it. Only TMS is held in memory as a whole, along with the TDO the client did not
+    /// read yet, which is all of it for clients that only read the answer once they sent the
+    /// whole shift. The last chunk may be shorter, and may end within a byte. Shifts are not
+    /// streamed while the CRC extension is enabled, as the CRC of a shift can only be checked
+    /// once all of it was received. Observers are given an empty TDI vector for streamed shifts
+    /// (see [`ServerObserver::on_message`](crate::observer::ServerObserver::on_message)).
+    ///
+    /// Suited to backends that shift a few bits at a time anyway, e.g. through memory-mapped
+    /// registers, unlike backends that pass the whole vectors to a driver in one call. Queried
+    /// once per connection.
+    fn shift_chunk_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// The limits of the hardware and a description of the backend, see
+    /// [`BackendCapabilities`]. The default reports no limits. Queried once when the
+    /// [`server::Server`] is created.
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities::default()
+    }
+
+    /// Called when a client is about to be served, e.g. to bring the hardware into the state
+    /// that clients expect of a cable they connected to. The default does nothing.
+    ///
+    /// The server calls `on_connect` before it answers the first message of the client, and
+    /// then sets the TCK period of [`server::Config::tck_on_connect`], if any. Both calls
+    /// complete before any other call of the connection. With
+    /// [`exclusive_client`](server::Config::exclusive_client) disabled, the calls of other
+    /// connections may precede or follow them.
+    fn on_connect(&self) {}
+
+    /// Called once the connection of a client was closed, after its last answer was sent. The
+    /// default does nothing.
+    ///
+    /// Every [`on_connect`](Self::on_connect) is followed by exactly one `on_disconnect`, also
+    /// if serving the client panicked or was aborted. A client that is served alone is
+    /// disconnected before the next client is connected.
+    fn on_disconnect(&self) {}
+}
+
+/// A backend that needs exclusive access to its state, like [`XvcServer`] but with methods
+/// that take `&mut self`.
+///
+/// The server owns its backend and calls one method at a time, also when several clients are
+/// served, so a backend can keep e.g. the state of a simulated TAP controller or the handle of
+/// a bit-banged port in plain fields. [`server::Server`] accepts either trait, as every
+/// [`XvcServer`] is also an `XvcServerMut`. See [`XvcServer`] for the contract of the methods.
+///
+/// Implement [`XvcServer`] instead for backends that are shared, e.g. to be wrapped in the
+/// [`decorators`] or swapped at runtime. A [`Mutex`](std::sync::Mutex) of an `XvcServerMut` is
+/// an [`XvcServer`] that locks it for each call.
+pub trait XvcServerMut {
+    type Err: std::error::Error;
+
+    /// Set the TCK period, see [`XvcServer::set_tck`].
+    fn set_tck(&mut self, period_ns: u32) -> Result<u32, Self::Err>;
+
+    /// Shift the TMS and TDI vectors and capture the TDO vector, see [`XvcServer::shift`].
+    fn shift(
+        &mut self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err>;
+
+    /// Backend-specific counters for diagnostics, see [`XvcServer::stats`].
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        Vec::new()
+    }
+
+    /// The length of the chunks in which long shifts are streamed, see
+    /// [`XvcServer::shift_chunk_len`].
+    fn shift_chunk_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// The limits of the backend, see [`XvcServer::capabilities`].
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities::default()
+    }
+
+    /// Called when a client is about to be served, see [`XvcServer::on_connect`].
+    fn on_connect(&mut self) {}
+
+    /// Called once the connection of a client was closed, see [`XvcServer::on_disconnect`].
+    fn on_disconnect(&mut self) {}
+}
+
+impl<T: XvcServer> XvcServerMut for T {
+    type Err = T::Err;
+
+    fn set_tck(&mut self, period_ns: u32) -> Result<u32, Self::Err> {
+        XvcServer::set_tck(self, period_ns)
+    }
+
+    fn shift(
+        &mut self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err> {
+        XvcServer::shift(self, num_bits, tms, tdi, tdo)
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        XvcServer::stats(self)
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        XvcServer::shift_chunk_len(self)
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        XvcServer::capabilities(self)
+    }
+
+    fn on_connect(&mut self) {
+        XvcServer::on_connect(self)
+    }
+
+    fn on_disconnect(&mut self) {
+        XvcServer::on_disconnect(self)
+    }
+}
+
+/// The limits of a backend, as reported by [`XvcServer::capabilities`]. Limits that are `None`
+/// are not imposed by the backend.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackendCapabilities {
+    /// The longest TMS and TDI vectors that the backend can shift at once, e.g. because a
+    /// driver limits the size of a single call. The server announces and accepts the shorter
+    /// of this and [`server::Config::max_vector_size`].
+    pub max_vector_size: Option<xvc_protocol::VectorLen>,
+    /// The shortest TCK period that the hardware achieves, in nanoseconds.
+    pub min_tck_period_ns: Option<u32>,
+    /// The longest TCK period that the hardware achieves, in nanoseconds.
+    pub max_tck_period_ns: Option<u32>,
+    /// What the backend drives, e.g. the driver and device, for the log of the server.
+    pub description: Option<String>,
 }
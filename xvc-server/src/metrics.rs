@@ -0,0 +1,277 @@
+//! [Prometheus](https://prometheus.io/) metrics of a [`Server`], with the `metrics` feature.
+//!
+//! The server counts connections, shifts and protocol errors with atomics, so the counters do
+//! not slow down shifts. [`Server::metrics`] renders them in the Prometheus text format, e.g.
+//! for the HTTP endpoint of the host application, and [`Server::listen_metrics`] serves them
+//! on a port of their own:
+//!
+//! ```ignore
+//! let server = Arc::new(Server::new(my_server, Config::default()));
+//! tokio::spawn({
+//!     let server = Arc::clone(&server);
+//!     async move { server.listen_metrics("0.0.0.0:9542").await }
+//! });
+//! server.listen("0.0.0.0:2542").await?;
+//! ```
+//!
+//! The metrics are:
+//!
+//! - `xvc_connections_total`: Accepted connections, including rejected ones
+//! - `xvc_accept_errors_total`: Failed attempts to accept a connection
+//! - `xvc_clients`: Clients that are served
+//! - `xvc_shifts_total`: Shifts passed to the backend
+//! - `xvc_bits_shifted_total`: Bits of these shifts
+//! - `xvc_shift_duration_seconds`: Histogram of the backend time of a shift, from 1 µs to 1 s
+//! - `xvc_protocol_errors_total`: Connections closed by a protocol error, by `kind`
+//! - `xvc_tck_period_nanoseconds`: The TCK period that the backend last set
+//!
+//! [`Server`]: crate::server::Server
+//! [`Server::metrics`]: crate::server::Server::metrics
+//! [`Server::listen_metrics`]: crate::server::Server::listen_metrics
+use std::{
+    fmt::Write,
+    io,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use xvc_protocol::error::ReadError;
+
+/// Upper bounds of the buckets of the shift duration histogram, in nanoseconds.
+const SHIFT_DURATION_BUCKETS_NS: [u64; 13] = [
+    1_000,
+    5_000,
+    10_000,
+    50_000,
+    100_000,
+    500_000,
+    1_000_000,
+    5_000_000,
+    10_000_000,
+    50_000_000,
+    100_000_000,
+    500_000_000,
+    1_000_000_000,
+];
+
+/// The `kind` labels of protocol errors.
+const ERROR_KINDS: [&str; 7] = [
+    "io",
+    "timeout",
+    "invalid_command",
+    "invalid_format",
+    "too_many_bytes",
+    "truncated",
+    "crc_mismatch",
+];
+
+/// The counters behind the metrics of a server.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    connections: AtomicU64,
+    accept_errors: AtomicU64,
+    clients: AtomicU64,
+    shifts: AtomicU64,
+    bits_shifted: AtomicU64,
+    /// Shifts per bucket of `SHIFT_DURATION_BUCKETS_NS`, and those that took longer
+    shift_durations: [AtomicU64; SHIFT_DURATION_BUCKETS_NS.len() + 1],
+    shift_duration_sum_ns: AtomicU64,
+    errors: [AtomicU64; ERROR_KINDS.len()],
+    tck_period_ns: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn record_connection(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_accept_error(&self) {
+        self.accept_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn client_connected(&self) {
+        self.clients.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn client_disconnected(&self) {
+        self.clients.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_shift(&self, num_bits: u32, elapsed: Duration) {
+        let elapsed_ns = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        let bucket = SHIFT_DURATION_BUCKETS_NS.partition_point(|&bound| bound < elapsed_ns);
+        self.shifts.fetch_add(1, Ordering::Relaxed);
+        self.bits_shifted
+            .fetch_add(num_bits.into(), Ordering::Relaxed);
+        self.shift_durations[bucket].fetch_add(1, Ordering::Relaxed);
+        self.shift_duration_sum_ns
+            .fetch_add(elapsed_ns, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_error(&self, error: &ReadError) {
+        let kind = match error {
+            ReadError::IoError(e) if e.kind() == io::ErrorKind::TimedOut => 1,
+            ReadError::IoError(_) | ReadError::Disconnected => 0,
+            ReadError::InvalidCommand(_) => 2,
+            ReadError::InvalidFormat(_) => 3,
+            ReadError::TooManyBytes { .. } => 4,
+            ReadError::TruncatedMessage { .. }
+            | ReadError::Truncated { .. }
+            | ReadError::Incomplete { .. } => 5,
+            ReadError::CrcMismatch { .. } => 6,
+        };
+        self.errors[kind].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_tck_period(&self, period_ns: u32) {
+        self.tck_period_ns
+            .store(period_ns.into(), Ordering::Relaxed);
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub(crate) fn render(&self) -> String {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = writeln!(
+                text,
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}"
+            );
+        };
+        metric(
+            "xvc_connections_total",
+            "counter",
+            "Accepted connections, including rejected ones.",
+            load(&self.connections),
+        );
+        metric(
+            "xvc_accept_errors_total",
+            "counter",
+            "Failed attempts to accept a connection.",
+            load(&self.accept_errors),
+        );
+        metric(
+            "xvc_clients",
+            "gauge",
+            "Clients that are served.",
+            load(&self.clients),
+        );
+        metric(
+            "xvc_shifts_total",
+            "counter",
+            "Shifts passed to the backend.",
+            load(&self.shifts),
+        );
+        metric(
+            "xvc_bits_shifted_total",
+            "counter",
+            "Bits shifted by the backend.",
+            load(&self.bits_shifted),
+        );
+        metric(
+            "xvc_tck_period_nanoseconds",
+            "gauge",
+            "The TCK period that the backend last set, or 0 if it was never set.",
+            load(&self.tck_period_ns),
+        );
+
+        let name = "xvc_shift_duration_seconds";
+        let _ = writeln!(text, "# HELP {name} Backend time of a shift.");
+        let _ = writeln!(text, "# TYPE {name} histogram");
+        let mut count = 0;
+        for (bound_ns, shifts) in SHIFT_DURATION_BUCKETS_NS.iter().zip(&self.shift_durations) {
+            count += load(shifts);
+            let bound = Duration::from_nanos(*bound_ns).as_secs_f64();
+            let _ = writeln!(text, "{name}_bucket{{le=\"{bound}\"}} {count}");
+        }
+        count += load(&self.shift_durations[SHIFT_DURATION_BUCKETS_NS.len()]);
+        let _ = writeln!(text, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let sum = Duration::from_nanos(load(&self.shift_duration_sum_ns)).as_secs_f64();
+        let _ = writeln!(text, "{name}_sum {sum}");
+        let _ = writeln!(text, "{name}_count {count}");
+
+        let name = "xvc_protocol_errors_total";
+        let _ = writeln!(
+            text,
+            "# HELP {name} Connections closed by a protocol error.\n# TYPE {name} counter"
+        );
+        for (kind, errors) in ERROR_KINDS.iter().zip(&self.errors) {
+            let _ = writeln!(text, "{name}{{kind=\"{kind}\"}} {}", load(errors));
+        }
+        text
+    }
+}
+
+/// The longest HTTP request that is read, to limit the memory of a misbehaving client.
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+/// The time a client has to send its request and read the response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Answers `GET /metrics` on `listener` with the `metrics`, until the future is dropped.
+///
+/// Requests are answered one after another, which is plenty for the occasional scrape.
+pub(crate) async fn listen(listener: TcpListener, metrics: &Metrics) -> io::Result<()> {
+    log::info!("Serving metrics on {}", listener.local_addr()?);
+    loop {
+        let (mut stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::error!("Metrics connection error: {}", e);
+                continue;
+            }
+        };
+        match timeout(REQUEST_TIMEOUT, respond(&mut stream, metrics)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::debug!("Metrics request from {} failed: {}", addr, e),
+            Err(_elapsed) => log::debug!("Metrics request from {} timed out", addr),
+        }
+    }
+}
+
+/// Reads an HTTP request from `stream` and answers it.
+async fn respond(stream: &mut TcpStream, metrics: &Metrics) -> io::Result<()> {
+    let mut request = Vec::new();
+    while !request.windows(4).any(|end| end == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_LEN {
+            return write_response(stream, "431 Request Header Fields Too Large", "").await;
+        }
+        let mut chunk = [0; 1024];
+        let len = stream.read(&mut chunk).await?;
+        if len == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&chunk[..len]);
+    }
+    let request_line = request
+        .split(|&byte| byte == b'\r')
+        .next()
+        .unwrap_or_default();
+    let mut parts = request_line.split(|&byte| byte == b' ');
+    match (parts.next(), parts.next()) {
+        (Some(b"GET"), Some(b"/metrics")) => {
+            write_response(stream, "200 OK", &metrics.render()).await
+        }
+        (_, Some(b"/metrics")) => write_response(stream, "405 Method Not Allowed", "").await,
+        _ => write_response(stream, "404 Not Found", "").await,
+    }
+}
+
+async fn write_response(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,251 @@
+//! Read-only observer connections that receive a copy of the traffic of the served clients,
+//! e.g. to watch the session of a debugger with a flaky board from another machine.
+//!
+//! A [`Mirror`] is set as [`Config::mirror`](crate::server::Config::mirror) and serves
+//! observers on a listener of its own. Every observer receives the bytes that the server reads
+//! from and writes to its clients as they pass, framed as the records of a
+//! [`capture`](xvc_protocol::capture), so the stream of an observer can be read with a
+//! [`CaptureReader`](xvc_protocol::capture::CaptureReader). The records of several clients
+//! that are served at the same time are interleaved. Observers are never read from, so they
+//! cannot send anything to the server.
+//!
+//! ```ignore
+//! let mirror = Mirror::new(1024);
+//! let server = Builder::new().mirror(mirror.clone()).build(backend)?;
+//! tokio::join!(
+//!     mirror.listen_on(TcpListener::bind("127.0.0.1:2543").await?, token.clone()),
+//!     server.listen_on(TcpListener::bind("0.0.0.0:2542").await?, token),
+//! );
+//! ```
+//!
+//! Records are queued for each observer. An observer that falls behind by more than the
+//! backlog of the mirror is disconnected, so a slow observer never holds up the clients. An
+//! observer that stops reading altogether is disconnected once a record could not be written
+//! to it for [`WRITE_TIMEOUT`]. While no observer is attached, the traffic is not recorded at
+//! all.
+use std::{
+    fmt, io,
+    pin::Pin,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, ready},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpListener,
+    sync::mpsc::{self, error::TrySendError},
+};
+use tokio_util::sync::CancellationToken;
+use xvc_protocol::capture::{Direction, Record};
+
+/// How long writing a record to an observer may take before the observer is disconnected.
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The observers of the traffic of a server, see the [module documentation](self).
+///
+/// Clones share the observers, so one clone can be passed to the server while another serves
+/// the observers.
+#[derive(Clone)]
+pub struct Mirror {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    observers: Mutex<Vec<mpsc::Sender<Bytes>>>,
+    /// The number of `observers`, read without locking them for every read and write
+    attached: AtomicUsize,
+    backlog: usize,
+}
+
+impl Mirror {
+    /// A mirror without observers that queues up to `backlog` records for each observer.
+    ///
+    /// # Panics
+    ///
+    /// If `backlog` is zero.
+    pub fn new(backlog: usize) -> Mirror {
+        assert!(backlog > 0, "the backlog of a mirror must not be empty");
+        Mirror {
+            shared: Arc::new(Shared {
+                observers: Mutex::new(Vec::new()),
+                attached: AtomicUsize::new(0),
+                backlog,
+            }),
+        }
+    }
+
+    /// The number of attached observers.
+    pub fn observers(&self) -> usize {
+        self.shared.attached.load(Ordering::Relaxed)
+    }
+
+    /// Serve observers that connect to `listener` until `shutdown` is cancelled, which also
+    /// disconnects the attached observers.
+    pub async fn listen_on(
+        &self,
+        listener: TcpListener,
+        shutdown: CancellationToken,
+    ) -> io::Result<()> {
+        log::info!("Serving observers on {}", listener.local_addr()?);
+        loop {
+            let (mut stream, addr) = tokio::select! {
+                () = shutdown.cancelled() => return Ok(()),
+                accepted = listener.accept() => accepted?,
+            };
+            let mut records = self.attach();
+            log::info!("Observer {} attached", addr);
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                loop {
+                    let record = tokio::select! {
+                        biased;
+                        () = shutdown.cancelled() => break,
+                        record = records.recv() => record,
+                    };
+                    // Ends once the observer was dropped for falling behind
+                    let Some(record) = record else {
+                        break;
+                    };
+                    let write = tokio::time::timeout(WRITE_TIMEOUT, stream.write_all(&record));
+                    let written = tokio::select! {
+                        biased;
+                        () = shutdown.cancelled() => break,
+                        written = write => written,
+                    };
+                    match written {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => {
+                            log::info!("Observer {} disconnected: {}", addr, e);
+                            break;
+                        }
+                        Err(_) => {
+                            log::warn!(
+                                "Observer {} did not read a record within {:?}, disconnecting it",
+                                addr,
+                                WRITE_TIMEOUT
+                            );
+                            break;
+                        }
+                    }
+                }
+                log::info!("Observer {} detached", addr);
+            });
+        }
+    }
+
+    /// Attach an observer that receives the encoded records from the returned channel.
+    fn attach(&self) -> mpsc::Receiver<Bytes> {
+        let (sender, receiver) = mpsc::channel(self.shared.backlog);
+        let mut observers = self.lock();
+        observers.push(sender);
+        self.shared
+            .attached
+            .store(observers.len(), Ordering::Relaxed);
+        receiver
+    }
+
+    /// Send `data` that passed in `direction` to all observers. Observers whose queue is full
+    /// are dropped, which ends their connection once they received the queued records.
+    fn publish(&self, direction: Direction, data: &[u8]) {
+        if data.is_empty() || self.observers() == 0 {
+            return;
+        }
+        let timestamp_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_micros() as u64);
+        let mut encoded = Vec::new();
+        for chunk in data.chunks(u32::MAX as usize) {
+            let record = Record {
+                direction,
+                timestamp_us,
+                data: chunk.to_vec(),
+            };
+            // Writing into a `Vec` cannot fail
+            let _ = record.write_to(&mut encoded);
+        }
+        let encoded = Bytes::from(encoded);
+        let mut observers = self.lock();
+        let backlog = self.shared.backlog;
+        observers.retain(|observer| match observer.try_send(encoded.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                log::warn!("An observer fell behind by {backlog} records, disconnecting it");
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        });
+        self.shared
+            .attached
+            .store(observers.len(), Ordering::Relaxed);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<mpsc::Sender<Bytes>>> {
+        self.shared
+            .observers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Debug for Mirror {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mirror")
+            .field("observers", &self.observers())
+            .field("backlog", &self.shared.backlog)
+            .finish()
+    }
+}
+
+/// The stream of a client, whose traffic is published to the observers of `mirror`, if any.
+pub(crate) struct Mirrored<S> {
+    inner: S,
+    mirror: Option<Mirror>,
+}
+
+impl<S> Mirrored<S> {
+    pub(crate) fn new(inner: S, mirror: Option<Mirror>) -> Mirrored<S> {
+        Mirrored { inner, mirror }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Mirrored<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        if let Some(mirror) = &self.mirror {
+            mirror.publish(Direction::Read, &buf.filled()[filled..]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Mirrored<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        if let Some(mirror) = &self.mirror {
+            mirror.publish(Direction::Written, &buf[..written]);
+        }
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,177 @@
+//! Serving several backends on ports of their own from one process, e.g. the debug bridges of
+//! a board with several FPGAs.
+//!
+//! Each endpoint is a [`Server`] of its own, with its own backend, configuration and
+//! statistics, but all endpoints run on the same runtime and stop with the same
+//! [`CancellationToken`]:
+//!
+//! ```ignore
+//! let server = MultiServer::new(Config::default())
+//!     .endpoint("0.0.0.0:2542".parse()?, first_bridge)
+//!     .endpoint_with("0.0.0.0:2543".parse()?, second_bridge, |config| Config {
+//!         max_vector_size: VectorLen::from_bytes(4096),
+//!         ..config
+//!     });
+//! server.listen_until(token).await?;
+//! ```
+//!
+//! With the `tracing` feature, each endpoint is served in an `info` level `endpoint` span with
+//! its `addr`, which the spans of its connections are nested in.
+use std::{io, net::SocketAddr, panic, sync::Arc};
+
+use tokio::{net::TcpListener, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "tracing")]
+use crate::spans;
+use crate::{
+    XvcServerMut,
+    server::{Config, Server},
+    stats::StatsSnapshot,
+};
+
+/// A set of [`Server`]s, each listening on an address of its own.
+#[derive(Debug)]
+pub struct MultiServer<T: XvcServerMut> {
+    defaults: Config,
+    endpoints: Vec<(SocketAddr, Arc<Server<T>>)>,
+}
+
+impl<T: XvcServerMut> MultiServer<T> {
+    /// A server without endpoints, which are configured with `defaults` unless they override it.
+    pub fn new(defaults: Config) -> MultiServer<T> {
+        MultiServer {
+            defaults,
+            endpoints: Vec::new(),
+        }
+    }
+
+    /// Serve `backend` on `addr` with the default configuration.
+    pub fn endpoint(self, addr: SocketAddr, backend: T) -> Self {
+        self.endpoint_with(addr, backend, |config| config)
+    }
+
+    /// Serve `backend` on `addr` with the configuration that `configure` makes of the defaults.
+    pub fn endpoint_with(
+        mut self,
+        addr: SocketAddr,
+        backend: T,
+        configure: impl FnOnce(Config) -> Config,
+    ) -> Self {
+        let config = configure(self.defaults.clone());
+        self.endpoints
+            .push((addr, Arc::new(Server::new(backend, config))));
+        self
+    }
+
+    /// The servers of the endpoints and the addresses they listen on, in the order they were
+    /// added, e.g. to [update the configuration](Server::update_config) of one of them.
+    pub fn servers(&self) -> impl Iterator<Item = (SocketAddr, &Server<T>)> {
+        (self.endpoints.iter()).map(|(addr, server)| (*addr, &**server))
+    }
+
+    /// The statistics of each endpoint, tagged with its address.
+    pub fn stats(&self) -> Vec<(SocketAddr, StatsSnapshot)> {
+        self.servers()
+            .map(|(addr, server)| (addr, server.stats()))
+            .collect()
+    }
+
+    /// Bind all endpoints without serving clients yet. Fails without serving any endpoint if
+    /// one of them cannot be bound, or with [`io::ErrorKind::InvalidInput`] if its configuration
+    /// fails [`Config::validate`].
+    pub async fn bind(&self) -> io::Result<BoundMultiServer<'_, T>> {
+        let mut listeners = Vec::with_capacity(self.endpoints.len());
+        for (addr, server) in &self.endpoints {
+            server.config().validate().map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid configuration of endpoint {addr}: {e}"),
+                )
+            })?;
+            let listener = TcpListener::bind(addr).await.map_err(|e| {
+                io::Error::new(e.kind(), format!("Could not bind endpoint {addr}: {e}"))
+            })?;
+            listeners.push(listener);
+        }
+        Ok(BoundMultiServer {
+            server: self,
+            listeners,
+        })
+    }
+
+    /// Bind all endpoints and serve clients until the process exits, see
+    /// [`listen_until`](Self::listen_until).
+    pub async fn listen(&self) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        self.listen_until(CancellationToken::new()).await
+    }
+
+    /// Bind all endpoints and serve clients until `shutdown` is cancelled, like
+    /// [`Server::listen_on`] does for a single endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Fails if an endpoint cannot be bound, or with the first error of an endpoint that stops
+    /// serving, which also stops the other endpoints.
+    pub async fn listen_until(&self, shutdown: CancellationToken) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        self.bind().await?.serve_until(shutdown).await
+    }
+}
+
+/// A [`MultiServer`] whose endpoints are bound, see [`MultiServer::bind`].
+#[derive(Debug)]
+pub struct BoundMultiServer<'a, T: XvcServerMut> {
+    server: &'a MultiServer<T>,
+    listeners: Vec<TcpListener>,
+}
+
+impl<T: XvcServerMut> BoundMultiServer<'_, T> {
+    /// The addresses the endpoints are bound to, in the order they were added, with the ports
+    /// assigned by the OS for endpoints on port 0.
+    pub fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.listeners.iter().map(TcpListener::local_addr).collect()
+    }
+
+    /// Serve clients on all endpoints until `shutdown` is cancelled, see
+    /// [`MultiServer::listen_until`].
+    pub async fn serve_until(self, shutdown: CancellationToken) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        // Dropping this future aborts the tasks, which closes their clients
+        let mut endpoints = JoinSet::new();
+        let shutdown = shutdown.child_token();
+        for ((_, server), listener) in self.server.endpoints.iter().zip(self.listeners) {
+            let addr = listener.local_addr()?;
+            log::info!("Serving endpoint {addr}");
+            let server = Arc::clone(server);
+            let shutdown = shutdown.clone();
+            let served = async move { (addr, server.listen_on(listener, shutdown).await) };
+            #[cfg(feature = "tracing")]
+            let served = tracing::Instrument::instrument(served, spans::endpoint(addr));
+            endpoints.spawn(served);
+        }
+
+        let mut result = Ok(());
+        while let Some(joined) = endpoints.join_next().await {
+            let (addr, served) = joined.unwrap_or_else(|e| panic::resume_unwind(e.into_panic()));
+            match served {
+                Ok(()) => log::info!("Endpoint {addr} stopped"),
+                Err(e) => {
+                    log::error!("Endpoint {addr} failed, stopping all endpoints: {e}");
+                    shutdown.cancel();
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
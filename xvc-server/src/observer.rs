@@ -0,0 +1,156 @@
+//! Hooks into the connections of a [`Server`](crate::server::Server), e.g. for usage
+//! accounting.
+//!
+//! An observer is set with [`Builder::observer`](crate::server::Builder::observer):
+//!
+//! ```ignore
+//! use std::sync::Arc;
+//! use xvc_server::{
+//!     observer::{ObserverResult, ServerObserver},
+//!     server::Builder,
+//!     stats::{ConnectionStats, Peer},
+//! };
+//!
+//! struct Accounting;
+//!
+//! impl ServerObserver for Accounting {
+//!     fn on_disconnect(&self, peer: Peer, stats: &ConnectionStats) -> ObserverResult {
+//!         println!("{peer} shifted {} bits in {:?}", stats.bits_shifted, stats.duration);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let server = Builder::new().observer(Arc::new(Accounting)).build(my_server)?;
+//! ```
+use std::{
+    error::Error,
+    fmt::{self, Debug},
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use xvc_protocol::{BorrowedMessage, Message};
+
+use crate::stats::{ConnectionStats, Peer};
+
+/// The result of a [`ServerObserver`] callback. Errors are logged and otherwise ignored.
+pub type ObserverResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// Callbacks for the events of the connections that a server serves. All callbacks do nothing
+/// by default.
+///
+/// Callbacks are called on the task that serves the client, between reading a message and
+/// answering it, so they should return quickly, e.g. by sending the event to a channel.
+/// Errors and panics of callbacks are logged and do not affect the connection.
+///
+/// Clients served with [`Server::handle_stream`](crate::server::Server::handle_stream) are not
+/// observed, as they have no [`Peer`].
+pub trait ServerObserver: Send + Sync {
+    /// A client is served, after waiting in a queue if any.
+    fn on_connect(&self, _peer: Peer) -> ObserverResult {
+        Ok(())
+    }
+
+    /// A message was received from `peer`. The vectors of a shift are borrowed from the buffers
+    /// of the connection; use [`Message::summary`] to log them. The TDI vector of a shift that
+    /// is streamed through the backend (see
+    /// [`XvcServer::shift_chunk_len`](crate::XvcServer::shift_chunk_len)) is empty, as it is
+    /// never held in memory as a whole.
+    fn on_message(&self, _peer: Peer, _message: &BorrowedMessage<'_>) -> ObserverResult {
+        Ok(())
+    }
+
+    /// The answer to a message, `bytes_written` long, was sent to `peer`. `stats` counts the
+    /// connection so far, including this answer.
+    fn on_response(
+        &self,
+        _peer: Peer,
+        _bytes_written: usize,
+        _stats: &ConnectionStats,
+    ) -> ObserverResult {
+        Ok(())
+    }
+
+    /// The connection of `peer` was closed.
+    fn on_disconnect(&self, _peer: Peer, _stats: &ConnectionStats) -> ObserverResult {
+        Ok(())
+    }
+}
+
+impl Debug for dyn ServerObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ServerObserver")
+    }
+}
+
+/// The events of a served connection, which are counted and passed on to the observer.
+pub(crate) struct Observed {
+    observer: Arc<dyn ServerObserver>,
+    peer: Peer,
+    connected: Instant,
+    stats: ConnectionStats,
+}
+
+impl Observed {
+    pub(crate) fn connect(
+        observer: Arc<dyn ServerObserver>,
+        peer: Peer,
+        connection: u64,
+    ) -> Observed {
+        notify("on_connect", || observer.on_connect(peer));
+        Observed {
+            observer,
+            peer,
+            connected: Instant::now(),
+            stats: ConnectionStats {
+                connection,
+                ..ConnectionStats::default()
+            },
+        }
+    }
+
+    pub(crate) fn message(&mut self, message: &BorrowedMessage<'_>) {
+        self.stats.messages += 1;
+        if let Message::Shift { num_bits, .. } = message {
+            self.stats.shifts += 1;
+            self.stats.bits_shifted += u64::from(*num_bits);
+        }
+        notify("on_message", || {
+            self.observer.on_message(self.peer, message)
+        });
+    }
+
+    /// Counts time that the connection was held back by its rate limits. It is reported with
+    /// the next response, or on disconnect.
+    pub(crate) fn throttled(&mut self, throttled: Duration) {
+        self.stats.throttled += throttled;
+    }
+
+    pub(crate) fn response(&mut self, bytes_written: usize) {
+        self.stats.bytes_written += bytes_written as u64;
+        self.stats.duration = self.connected.elapsed();
+        notify("on_response", || {
+            self.observer
+                .on_response(self.peer, bytes_written, &self.stats)
+        });
+    }
+}
+
+impl Drop for Observed {
+    fn drop(&mut self) {
+        self.stats.duration = self.connected.elapsed();
+        notify("on_disconnect", || {
+            self.observer.on_disconnect(self.peer, &self.stats)
+        });
+    }
+}
+
+/// Calls the observer `callback`, logging its error or panic.
+fn notify(name: &str, callback: impl FnOnce() -> ObserverResult) {
+    match panic::catch_unwind(AssertUnwindSafe(callback)) {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::warn!("Observer failed in {}: {}", name, e),
+        Err(_panic) => log::error!("Observer panicked in {}", name),
+    }
+}
@@ -0,0 +1,191 @@
+//! The [PROXY protocol](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt) that load
+//! balancers like haproxy use to pass the address of the client they forward to the server.
+//!
+//! With [`Config::expect_proxy_protocol`](crate::server::Config::expect_proxy_protocol) set,
+//! every TCP connection starts with a version 1 (text) or version 2 (binary) header, which is
+//! read before the first XVC message. The source address it conveys replaces the address of the
+//! load balancer in logs, statistics and the checks of
+//! [`Config::allows`](crate::server::Config::allows).
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    net::TcpStream,
+    sync::mpsc,
+    time::timeout,
+};
+
+use crate::{server::Listener, stats::Peer};
+
+/// The signature that starts a version 2 header.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// The maximum length of a version 1 header, including the terminating CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// The clients of a TCP listener whose PROXY protocol header was read, with the address of
+/// the client that the header conveys.
+///
+/// Connections are accepted and their headers read in background tasks, so a slow client does
+/// not hold up the others. Errors of the listener are passed on.
+pub(crate) struct ProxiedClients {
+    received: mpsc::Receiver<io::Result<(TcpStream, Peer)>>,
+}
+
+impl ProxiedClients {
+    /// Accepts clients from `listener`, until this is dropped. Connections without a valid
+    /// header within `header_timeout` are closed.
+    pub(crate) fn start<L>(mut listener: L, header_timeout: Duration) -> ProxiedClients
+    where
+        L: Listener<Stream = TcpStream> + Send + 'static,
+    {
+        let (sender, received) = mpsc::channel(1);
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, peer) = tokio::select! {
+                    () = sender.closed() => break,
+                    accepted = listener.next_client() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            if sender.send(Err(e)).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    },
+                };
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match timeout(header_timeout, read_header(&mut stream)).await {
+                        Ok(Ok(source)) => {
+                            let peer = source.map_or(peer, Peer::Tcp);
+                            let _ = sender.send(Ok((stream, peer))).await;
+                        }
+                        Ok(Err(e)) => {
+                            log::warn!("Invalid PROXY protocol header from {}: {}", peer, e)
+                        }
+                        Err(_elapsed) => {
+                            log::warn!("No PROXY protocol header from {} in time", peer)
+                        }
+                    }
+                });
+            }
+        });
+        ProxiedClients { received }
+    }
+}
+
+impl Listener for ProxiedClients {
+    type Stream = TcpStream;
+
+    async fn next_client(&mut self) -> io::Result<(TcpStream, Peer)> {
+        self.received
+            .recv()
+            .await
+            .unwrap_or_else(|| Err(io::Error::other("PROXY protocol reader stopped")))
+    }
+
+    fn local_addr(stream: &TcpStream) -> Option<SocketAddr> {
+        stream.local_addr().ok()
+    }
+}
+
+/// Reads a version 1 or version 2 header from `stream`, and not a byte more, e.g. before
+/// passing a connection to [`Server::handle_stream`](crate::server::Server::handle_stream).
+///
+/// Returns the source address of the header, or `None` for headers without one, e.g. those of
+/// health checks by the load balancer itself. Fails with [`io::ErrorKind::InvalidData`] if the
+/// stream does not start with a valid header.
+pub async fn read_header(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<SocketAddr>> {
+    // As long as the shortest XVC command, so a client that sends none is closed right away
+    let mut header = vec![0; 6];
+    stream.read_exact(&mut header).await?;
+    if V2_SIGNATURE.starts_with(&header) {
+        header.resize(V2_SIGNATURE.len(), 0);
+        stream.read_exact(&mut header[6..]).await?;
+        if header != V2_SIGNATURE {
+            return Err(invalid("invalid version 2 signature"));
+        }
+        let mut fixed = [0; 4];
+        stream.read_exact(&mut fixed).await?;
+        let [version_command, family, len @ ..] = fixed;
+        let mut addresses = vec![0; usize::from(u16::from_be_bytes(len))];
+        stream.read_exact(&mut addresses).await?;
+        parse_v2(version_command, family, &addresses)
+    } else if header == b"PROXY " {
+        while !header.ends_with(b"\r\n") {
+            if header.len() == V1_MAX_LEN {
+                return Err(invalid("version 1 header is too long"));
+            }
+            header.push(stream.read_u8().await?);
+        }
+        parse_v1(&header[..header.len() - 2])
+    } else {
+        Err(invalid("connection does not start with a header"))
+    }
+}
+
+/// Parses the line of a version 1 header without the terminating CRLF, e.g.
+/// `PROXY TCP4 192.0.2.1 198.51.100.1 56324 2542`.
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = str::from_utf8(line).map_err(|_| invalid("version 1 header is not ASCII"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        [
+            "PROXY",
+            protocol @ ("TCP4" | "TCP6"),
+            source,
+            _destination,
+            port,
+            _,
+        ] => {
+            let source: IpAddr = source
+                .parse()
+                .map_err(|_| invalid("invalid source address"))?;
+            if source.is_ipv4() != (protocol == "TCP4") {
+                return Err(invalid("source address does not match the protocol"));
+            }
+            let port = port.parse().map_err(|_| invalid("invalid source port"))?;
+            Ok(Some(SocketAddr::new(source, port)))
+        }
+        _ => Err(invalid("malformed version 1 header")),
+    }
+}
+
+/// Parses the remainder of a version 2 header after the signature.
+fn parse_v2(version_command: u8, family: u8, addresses: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version 2 header version"));
+    }
+    match version_command & 0x0f {
+        // LOCAL, sent by the load balancer on its own behalf
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(invalid("unsupported version 2 header command")),
+    }
+    let source = match family >> 4 {
+        // AF_INET: source and destination address, then source and destination port
+        0x1 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            SocketAddr::new(ip.into(), u16::from_be_bytes([addresses[8], addresses[9]]))
+        }
+        // AF_INET6
+        0x2 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[..16].try_into().unwrap();
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            SocketAddr::new(Ipv6Addr::from(ip).into(), port)
+        }
+        0x1 | 0x2 => return Err(invalid("version 2 header is too short for its addresses")),
+        // AF_UNSPEC and AF_UNIX have no address to report
+        _ => return Ok(None),
+    };
+    Ok(Some(source))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -1,43 +1,454 @@
-use std::{io, sync::Arc, time::Duration};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::{self, Display},
+    future::poll_fn,
+    io,
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    pin::pin,
+    sync::{
+        self, Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
 
-use bytes::BytesMut;
+#[cfg(feature = "tls")]
+use crate::tls::{TlsClients, rustls};
+use bytes::{Buf, BytesMut};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream, ToSocketAddrs, tcp::OwnedReadHalf},
-    sync::Mutex,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        Mutex, Notify,
+        mpsc::{self, error::TrySendError},
+        watch,
+    },
     task::block_in_place,
-    time::timeout,
+    time::{sleep, timeout},
 };
 use tokio_util::codec::Decoder;
 use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+#[cfg(unix)]
+use {
+    std::{
+        fs::{self, Permissions},
+        os::{
+            fd::OwnedFd,
+            unix::fs::{FileTypeExt, PermissionsExt},
+        },
+        path::Path,
+    },
+    tokio::net::{UnixListener, UnixStream},
+};
 
-use crate::XvcServer;
+#[cfg(feature = "metrics")]
+use crate::metrics;
+#[cfg(feature = "tracing")]
+use crate::spans;
+use crate::{
+    BackendCapabilities, XvcServerMut,
+    access::IpNetwork,
+    mirror::{Mirror, Mirrored},
+    observer::{Observed, ServerObserver},
+    proxy::ProxiedClients,
+    stats::{Peer, ServerStats, ServerStatus, StatsSnapshot},
+    transcript::TranscriptFile,
+};
 use xvc_protocol::{
-    Message, OwnedMessage, Version, XvcInfo, error::ReadError, tokio_codec::MessageDecoder,
+    BorrowedMessage, HexSummary, Message, Response, ShiftBuffers, VectorLen, Version, XvcCommand,
+    XvcInfo,
+    capture::{CaptureSink, TeeReader, TeeWriter},
+    crc,
+    error::ReadError,
+    rw::MAX_RESYNC_BYTES,
+    tokio_codec::{self, MessageDecoder},
 };
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    /// Maximum JTAG vector size in bytes that the server will accept (default: 10 MiB).
-    pub max_vector_size: u32,
-    /// Timeout applied to each TCP read. Connections that are idle for longer than
-    /// this duration are closed (default: 30 s).
-    pub read_write_timeout: Duration,
+    /// Maximum size of each of the TMS and TDI vectors of a shift that the server will accept
+    /// (default: 10 MiB). Announced in the answer to `getinfo:`. The vectors of a longer shift
+    /// are read and discarded, and the shift is answered with zeros unless `shift_error_policy`
+    /// closes the connection; with `resync_on_error`, it is skipped as malformed instead.
+    /// Backends that support shorter vectors lower the limit, see
+    /// [`BackendCapabilities::max_vector_size`].
+    pub max_vector_size: VectorLen,
+    /// Time that a client may take to start its next command before the connection is closed,
+    /// or `None` to keep idle clients connected (default: 30 s).
+    pub idle_timeout: Option<Duration>,
+    /// Time that a client may take to send the rest of a command once its first bytes arrived
+    /// (default: 30 s). Limits how long a half-sent shift stalls the server, but needs to allow
+    /// for the largest shifts on the slowest links. Also limits TLS handshakes and PROXY
+    /// protocol headers.
+    pub message_timeout: Duration,
+    /// Disable Nagle's algorithm on TCP connections, so small responses like the answer to
+    /// `settck:` are sent right away instead of being held back for up to 40 ms (default:
+    /// `true`).
+    pub tcp_nodelay: bool,
+    /// Send TCP keepalive probes after a connection has been idle for this duration, so peers
+    /// that are gone, e.g. because a firewall dropped the connection, are noticed before the
+    /// next write (default: none). Only read when the server starts listening.
+    pub tcp_keepalive: Option<Duration>,
+    /// Size of the buffer that answers are collected in before they are sent (default: 64 KiB).
+    /// The answers to messages that a client sent without waiting for the previous answers are
+    /// sent together, but an answer is never held back while the server waits for the client.
+    /// With `0`, every answer is sent on its own.
+    pub write_buffer_size: usize,
+    /// Maximum number of bytes per vector that a connection may shift per second (default:
+    /// none). The protocol cannot push back on a client, so a connection that exceeds its
+    /// budget is not read from until the budget is restored, which throttles a client that
+    /// hogs a shared cable. Bursts of up to a second's budget are served right away.
+    pub max_shift_bytes_per_sec: Option<u64>,
+    /// Maximum number of messages that a connection may send per second, like
+    /// `max_shift_bytes_per_sec` (default: none).
+    pub max_messages_per_sec: Option<u64>,
+    /// The TCK periods in nanoseconds that clients may set (default: none, any period). A
+    /// `settck:` outside of the range sets the closest period within it instead, and the
+    /// client is answered the period the backend set, as for any period the hardware cannot
+    /// achieve. The start of the range must not be greater than its end.
+    pub tck_period_range: Option<RangeInclusive<u32>>,
+    /// The TCK period that is set when a client is about to be served, right after
+    /// [`on_connect`](crate::XvcServer::on_connect) (default: [`TckOnConnect::Keep`]).
+    pub tck_on_connect: TckOnConnect,
+    /// The answer to a shift that the backend failed (default: [`ShiftErrorPolicy::SendTdo`]).
+    /// The protocol has no way to report errors, so the error is only logged.
+    pub shift_error_policy: ShiftErrorPolicy,
+    /// Log a warning for every backend call that takes longer than this, with the command,
+    /// its size and the duration (default: none). Only the backend call is timed, not the
+    /// socket I/O, so the warnings point at the driver or the hardware. Streamed shifts are
+    /// timed per chunk.
+    pub slow_op_warn_threshold: Option<Duration>,
+    /// Log and skip commands that are not part of the protocol, e.g. vendor extensions, instead
+    /// of closing the connection (default: `false`). Skipped commands are not answered.
+    pub skip_unknown_commands: bool,
+    /// After a malformed message, skip to the next command instead of closing the connection
+    /// (default: `false`). The connection is still closed if no command follows within
+    /// `resync_scan_limit` bytes, or after `max_resyncs` malformed messages.
+    pub resync_on_error: bool,
+    /// Maximum number of bytes skipped in search of the next command with `resync_on_error`
+    /// (default: [`MAX_RESYNC_BYTES`](xvc_protocol::rw::MAX_RESYNC_BYTES)).
+    pub resync_scan_limit: usize,
+    /// Number of malformed messages that are skipped with `resync_on_error` before the next one
+    /// closes the connection (default: 3). A client that keeps sending them is unlikely to
+    /// recover, and each resync may lose commands.
+    pub max_resyncs: usize,
+    /// Serve only one client at a time, regardless of `max_clients` and `pool` (default: `true`).
+    /// Connections while a client is active are handled according to `client_policy`. JTAG has
+    /// a single master, and the shifts of concurrent clients would corrupt each other's TAP state.
+    pub exclusive_client: bool,
+    /// Maximum number of clients that are served at the same time if `exclusive_client` is
+    /// disabled (default: 1). Connections beyond the limit are handled according to
+    /// `client_policy`.
+    ///
+    /// The backend is called by one client at a time, so each shift runs uninterrupted, but
+    /// the shifts of concurrent clients interleave and change each other's TAP state. Clients
+    /// that share a server therefore have to coordinate, e.g. by working on separate devices.
+    pub max_clients: usize,
+    /// The handling of connections while all clients that may be served are active (default:
+    /// [`ClientPolicy::Reject`]). Does not apply with a `pool`, which queues connections itself.
+    pub client_policy: ClientPolicy,
+    /// Serve clients with a fixed set of workers instead of a task per client (default: none).
+    /// If `exclusive_client` is disabled, the workers limit the number of clients served at the
+    /// same time in place of `max_clients`. Only read when the server starts listening.
+    pub pool: Option<Pool>,
+    /// Stop listening after accepting this many connections, including rejected ones, and
+    /// return from [`Server::listen`] once they are closed (default: none). Only read when the
+    /// server starts listening.
+    pub max_connections: Option<u64>,
+    /// Stop listening after this many failed attempts in a row to accept a connection, and
+    /// fail [`Server::listen`] with the last error (default: none, keep trying). Each failure
+    /// backs off exponentially from 10 ms to 1 s, so e.g. running out of file descriptors does
+    /// not spin the accept loop.
+    pub max_accept_errors: Option<u64>,
+    /// The handling of addresses that [`Server::bind_all`] cannot bind (default:
+    /// [`BindPolicy::FailFast`]).
+    pub bind_policy: BindPolicy,
+    /// The permissions of the socket file of [`Server::listen_unix`], e.g. `0o660` to limit
+    /// access to a group (default: none, as given by the umask).
+    pub unix_socket_mode: Option<u32>,
+    /// The TCP clients that may connect, see [`Config::allows`] (default: empty, all clients).
+    pub allowed_peers: Vec<IpNetwork>,
+    /// The TCP clients that may not connect, even if they are in `allowed_peers`, see
+    /// [`Config::allows`] (default: none).
+    pub denied_peers: Vec<IpNetwork>,
+    /// Expect a [PROXY protocol](crate::proxy) header at the start of every TCP connection, as
+    /// sent by load balancers like haproxy, and use the client address it conveys in place of
+    /// the address of the load balancer (default: `false`). Connections without a valid header
+    /// are closed. Only read when the server starts listening.
+    pub expect_proxy_protocol: bool,
+    /// Capability tokens advertised after the vector length in the answer to `getinfo:`, e.g.
+    /// those of an upstream server that is proxied (default: none).
+    pub capabilities: Vec<String>,
+    /// Offer the [`crc`](xvc_protocol::crc) extension to clients, which protects shifts and
+    /// their TDO responses with CRCs (default: `false`). A shift with a wrong CRC closes the
+    /// connection, regardless of `resync_on_error`.
+    pub crc: bool,
+    /// Copy the bytes exchanged with every client into this sink, see
+    /// [`xvc_protocol::capture`] (default: none).
+    pub capture: Option<CaptureSink>,
+    /// Record the messages of every client and the answers they received into a file of its
+    /// own in this existing directory, in the [`xvc_protocol::transcript`] format (default:
+    /// none). The files are named after the time the client connected, the number of the
+    /// connection, `0` for [`Server::handle_stream`], and the address of the client. Shifts are
+    /// not streamed through the backend of a recorded connection, see
+    /// [`shift_chunk_len`](crate::XvcServer::shift_chunk_len), and shifts discarded as
+    /// oversized are recorded with their header only. A transcript that cannot be written is
+    /// given up with a warning, while the client is served on.
+    pub transcript_path: Option<PathBuf>,
+    /// Send a copy of the bytes exchanged with every client to the observers of this
+    /// [`Mirror`] (default: none).
+    pub mirror: Option<Mirror>,
+    /// Accept TCP connections with a TLS handshake, see [`tls`](crate::tls) (default: none).
+    /// Only read when the server starts listening; Unix domain sockets and
+    /// [`Server::handle_stream`] are not affected.
+    #[cfg(feature = "tls")]
+    pub tls: Option<Arc<rustls::ServerConfig>>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            max_vector_size: 10 * 1024 * 1024,
-            read_write_timeout: Duration::from_secs(30),
+            max_vector_size: VectorLen::from_bytes(10 * 1024 * 1024),
+            idle_timeout: Some(Duration::from_secs(30)),
+            message_timeout: Duration::from_secs(30),
+            tcp_nodelay: true,
+            tcp_keepalive: None,
+            write_buffer_size: 64 * 1024,
+            max_shift_bytes_per_sec: None,
+            max_messages_per_sec: None,
+            tck_period_range: None,
+            tck_on_connect: TckOnConnect::Keep,
+            shift_error_policy: ShiftErrorPolicy::SendTdo,
+            slow_op_warn_threshold: None,
+            skip_unknown_commands: false,
+            resync_on_error: false,
+            resync_scan_limit: MAX_RESYNC_BYTES,
+            max_resyncs: 3,
+            exclusive_client: true,
+            max_clients: 1,
+            client_policy: ClientPolicy::Reject,
+            pool: None,
+            max_connections: None,
+            max_accept_errors: None,
+            bind_policy: BindPolicy::FailFast,
+            unix_socket_mode: None,
+            allowed_peers: Vec::new(),
+            denied_peers: Vec::new(),
+            expect_proxy_protocol: false,
+            capabilities: Vec::new(),
+            crc: false,
+            capture: None,
+            transcript_path: None,
+            mirror: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+impl Config {
+    /// Whether a client from `ip` may connect. Clients in a range of `denied_peers` are rejected,
+    /// even if they are also in a range of `allowed_peers`. Otherwise they are accepted if
+    /// `allowed_peers` is empty or one of its ranges contains them.
+    ///
+    /// Rejected connections are closed right after they are accepted, or with TLS after the
+    /// handshake. Clients of Unix domain sockets are not affected.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        !self.denied_peers.iter().any(|network| network.contains(ip))
+            && (self.allowed_peers.is_empty()
+                || self
+                    .allowed_peers
+                    .iter()
+                    .any(|network| network.contains(ip)))
+    }
+
+    /// Check that the configuration can serve clients, as [`Builder::build`] does. A config
+    /// that fails the check is still accepted by [`Server::new`].
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_vector_size.as_bytes() == 0 {
+            return Err(ConfigError::ZeroMaxVectorSize);
+        }
+        if self.idle_timeout == Some(Duration::ZERO) {
+            return Err(ConfigError::ZeroTimeout("idle_timeout"));
+        }
+        if self.message_timeout.is_zero() {
+            return Err(ConfigError::ZeroTimeout("message_timeout"));
+        }
+        if let Some(range) = &self.tck_period_range
+            && range.is_empty()
+        {
+            return Err(ConfigError::EmptyTckRange {
+                min_ns: *range.start(),
+                max_ns: *range.end(),
+            });
+        }
+        if !self.exclusive_client && self.pool.is_none() && self.max_clients == 0 {
+            return Err(ConfigError::NoClients);
+        }
+        if let Some(pool) = &self.pool
+            && (pool.workers == 0 || pool.pending_queue == 0)
+        {
+            return Err(ConfigError::EmptyPool);
+        }
+        Ok(())
+    }
+}
+
+/// A [`Config`] that cannot serve clients, see [`Config::validate`], or an environment
+/// variable that [`Config::from_env`] cannot parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The environment variable `name` has a `value` that is not valid for it.
+    InvalidVariable {
+        name: String,
+        value: String,
+        reason: String,
+    },
+    /// `max_vector_size` is zero, so every shift would be discarded.
+    ZeroMaxVectorSize,
+    /// The timeout of this name is zero, so every client would time out right away.
+    ZeroTimeout(&'static str),
+    /// The start of `tck_period_range` is greater than its end.
+    EmptyTckRange { min_ns: u32, max_ns: u32 },
+    /// `max_clients` is zero without `exclusive_client` or a `pool`, so every client would be
+    /// rejected.
+    NoClients,
+    /// The `pool` has no workers or no room in its pending queue.
+    EmptyPool,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidVariable {
+                name,
+                value,
+                reason,
+            } => write!(f, "Invalid value {value:?} of {name}: {reason}"),
+            ConfigError::ZeroMaxVectorSize => f.write_str("The maximum vector size is zero"),
+            ConfigError::ZeroTimeout(name) => write!(f, "The {name} is zero"),
+            ConfigError::EmptyTckRange { min_ns, max_ns } => {
+                write!(f, "TCK period range {min_ns}..={max_ns} ns is empty")
+            }
+            ConfigError::NoClients => f.write_str("No clients may be served, max_clients is zero"),
+            ConfigError::EmptyPool => {
+                f.write_str("The pool needs at least one worker and room for a pending client")
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// The TCK period that is set at the start of each connection, see [`Config::tck_on_connect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TckOnConnect {
+    /// Leave the TCK period as the backend has it.
+    Keep,
+    /// Set this period in nanoseconds, e.g. the one that clients assume of a cable they just
+    /// connected to.
+    Period(u32),
+    /// Set the period that the backend last set for a client again, if any, e.g. for backends
+    /// that reset their clock when a client connects.
+    Last,
+}
+
+/// The answer to a shift that the backend failed, see [`Config::shift_error_policy`]. Shifts
+/// longer than [`Config::max_vector_size`] are answered with zeros unless the connection is
+/// closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftErrorPolicy {
+    /// Send the TDO as the backend left it, which is zeros unless the backend wrote part of
+    /// it before it failed.
+    SendTdo,
+    /// Send zeros in place of the TDO, so the client stays in sync but sees data that is
+    /// obviously bogus.
+    ZeroFill,
+    /// Close the connection without answering, so the client fails right away instead of
+    /// working with bogus data. Answers to earlier messages are still sent.
+    CloseConnection,
+}
+
+/// The handling of addresses that [`Server::bind_all`] cannot bind, see
+/// [`Config::bind_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindPolicy {
+    /// Fail if any of the addresses cannot be bound.
+    FailFast,
+    /// Log the addresses that cannot be bound and serve the others. Only fails if none of the
+    /// addresses can be bound.
+    BestEffort,
+}
+
+/// The handling of connections while all clients that may be served are active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientPolicy {
+    /// Accept and close the connection right away, so the client sees the connection closed
+    /// instead of a hang.
+    Reject,
+    /// Hold up to `max_waiting` connections open and serve them in the order they connected
+    /// once active clients disconnect. Further connections are rejected.
+    ///
+    /// Waiting clients are answered `getinfo:`, so tools that poll the server do not time out.
+    /// Any other command is answered once the client is served. A client that waits for longer
+    /// than `hold_timeout` is disconnected.
+    Queue {
+        max_waiting: usize,
+        hold_timeout: Duration,
+    },
+}
+
+/// A fixed number of workers that serve one client at a time, and a bounded queue of accepted
+/// connections that wait for a worker.
+///
+/// A worker that panics while serving a client, e.g. because the backend panicked, is restarted
+/// with the next connection, so the pool does not shrink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pool {
+    /// The number of workers, at least 1 (default: 1).
+    pub workers: usize,
+    /// The number of accepted connections that wait for a worker, at least 1 (default: 1).
+    /// Waiting clients are not answered until a worker serves them.
+    pub pending_queue: usize,
+    /// What happens to new connections while the queue is full (default: close them).
+    pub when_full: QueueFull,
+}
+
+impl Default for Pool {
+    fn default() -> Self {
+        Pool {
+            workers: 1,
+            pending_queue: 1,
+            when_full: QueueFull::Close,
         }
     }
 }
 
+/// The handling of new connections while the pending queue of a [`Pool`] is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueFull {
+    /// Accept and close the connection right away.
+    Close,
+    /// Stop accepting connections until a worker takes one from the queue, leaving new
+    /// connections in the backlog of the listening socket.
+    Wait,
+}
+
 #[derive(Debug)]
-pub struct Server<T: XvcServer> {
-    server: Arc<Mutex<T>>,
-    config: Config,
+pub struct Server<T: XvcServerMut> {
+    server: Arc<sync::Mutex<T>>,
+    clients: Arc<Clients>,
+    stats: Arc<ServerStats>,
+    config: sync::RwLock<Config>,
+    observer: Option<Arc<dyn ServerObserver>>,
+    capabilities: BackendCapabilities,
 }
 
 /// Builder to create a [Server] instance and modify configuration options
@@ -50,12 +461,24 @@ pub struct Server<T: XvcServer> {
 ///
 /// let server = Builder::new()
 ///     .max_vector_size(1024)
-///     .rw_timeout(Duration::from_secs(20))
-///     .build(my_server);
+///     .idle_timeout(None)
+///     .message_timeout(Duration::from_secs(20))
+///     .build(my_server)?;
 /// ```
 #[derive(Default)]
 pub struct Builder {
     config: Config,
+    observer: Option<Arc<dyn ServerObserver>>,
+}
+
+impl From<Config> for Builder {
+    /// Start from `config` instead of the defaults, e.g. one read from a configuration file.
+    fn from(config: Config) -> Self {
+        Builder {
+            config,
+            observer: None,
+        }
+    }
 }
 
 impl Builder {
@@ -64,49 +487,507 @@ impl Builder {
     }
 
     /// Set the highest vector size that this server is expected to receive.
-    pub fn max_vector_size(mut self, size: u32) -> Self {
-        self.config.max_vector_size = size;
+    pub fn max_vector_size(mut self, size: impl Into<VectorLen>) -> Self {
+        self.config.max_vector_size = size.into();
+        self
+    }
+
+    /// Set the time that a client may be idle between commands, or `None` for no limit.
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.idle_timeout = timeout;
+        self
+    }
+
+    /// Set the time that a client may take to complete a command it started.
+    pub fn message_timeout(mut self, timeout: Duration) -> Self {
+        self.config.message_timeout = timeout;
+        self
+    }
+
+    /// Enable or disable Nagle's algorithm on TCP connections.
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.config.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Send TCP keepalive probes after a connection has been idle for `idle`.
+    pub fn tcp_keepalive(mut self, idle: Duration) -> Self {
+        self.config.tcp_keepalive = Some(idle);
+        self
+    }
+
+    /// Set the size of the buffer that answers are collected in before they are sent.
+    pub fn write_buffer_size(mut self, size: usize) -> Self {
+        self.config.write_buffer_size = size;
+        self
+    }
+
+    /// Limit the bytes per vector that a connection may shift per second.
+    pub fn max_shift_bytes_per_sec(mut self, bytes: u64) -> Self {
+        self.config.max_shift_bytes_per_sec = Some(bytes);
+        self
+    }
+
+    /// Limit the messages that a connection may send per second.
+    pub fn max_messages_per_sec(mut self, messages: u64) -> Self {
+        self.config.max_messages_per_sec = Some(messages);
+        self
+    }
+
+    /// Set the TCK period that is set when a client is about to be served.
+    pub fn tck_on_connect(mut self, tck: TckOnConnect) -> Self {
+        self.config.tck_on_connect = tck;
+        self
+    }
+
+    /// Set the answer to a shift that the backend failed.
+    pub fn shift_error_policy(mut self, policy: ShiftErrorPolicy) -> Self {
+        self.config.shift_error_policy = policy;
+        self
+    }
+
+    /// Warn about backend calls that take longer than `threshold`.
+    pub fn slow_op_warn_threshold(mut self, threshold: Duration) -> Self {
+        self.config.slow_op_warn_threshold = Some(threshold);
+        self
+    }
+
+    /// Limit the TCK periods that clients may set to `min_ns..=max_ns`. An empty range fails
+    /// [`build`](Self::build).
+    pub fn tck_range(mut self, min_ns: u32, max_ns: u32) -> Self {
+        self.config.tck_period_range = Some(min_ns..=max_ns);
+        self
+    }
+
+    /// Log and skip unknown commands instead of closing the connection.
+    pub fn skip_unknown_commands(mut self, skip: bool) -> Self {
+        self.config.skip_unknown_commands = skip;
+        self
+    }
+
+    /// Skip to the next command after a malformed message instead of closing the connection.
+    pub fn resync_on_error(mut self, resync: bool) -> Self {
+        self.config.resync_on_error = resync;
+        self
+    }
+
+    /// Set the maximum number of bytes skipped in search of the next command.
+    pub fn resync_scan_limit(mut self, bytes: usize) -> Self {
+        self.config.resync_scan_limit = bytes;
+        self
+    }
+
+    /// Set the number of malformed messages skipped before the connection is closed.
+    pub fn max_resyncs(mut self, resyncs: usize) -> Self {
+        self.config.max_resyncs = resyncs;
+        self
+    }
+
+    /// Serve only one client at a time.
+    pub fn exclusive_client(mut self, exclusive: bool) -> Self {
+        self.config.exclusive_client = exclusive;
+        self
+    }
+
+    /// Set the maximum number of clients that are served at the same time.
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.config.max_clients = max_clients;
+        self
+    }
+
+    /// Set the handling of connections while all clients that may be served are active.
+    pub fn client_policy(mut self, policy: ClientPolicy) -> Self {
+        self.config.client_policy = policy;
+        self
+    }
+
+    /// Serve clients with `workers` workers, see [`Pool`].
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.config.pool.get_or_insert_default().workers = workers;
+        self
+    }
+
+    /// Set the number of connections that wait for a worker, see [`Pool`].
+    pub fn pending_queue(mut self, pending_queue: usize) -> Self {
+        self.config.pool.get_or_insert_default().pending_queue = pending_queue;
+        self
+    }
+
+    /// Set the handling of new connections while the pending queue of the [`Pool`] is full.
+    pub fn when_queue_full(mut self, when_full: QueueFull) -> Self {
+        self.config.pool.get_or_insert_default().when_full = when_full;
+        self
+    }
+
+    /// Stop listening after accepting `max_connections` connections.
+    pub fn max_connections(mut self, max_connections: u64) -> Self {
+        self.config.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Stop listening after `max_accept_errors` failed accepts in a row.
+    pub fn max_accept_errors(mut self, max_accept_errors: u64) -> Self {
+        self.config.max_accept_errors = Some(max_accept_errors);
+        self
+    }
+
+    /// Set the handling of addresses that [`Server::bind_all`] cannot bind.
+    pub fn bind_policy(mut self, policy: BindPolicy) -> Self {
+        self.config.bind_policy = policy;
+        self
+    }
+
+    /// Set the permissions of the socket file of [`Server::listen_unix`].
+    pub fn unix_socket_mode(mut self, mode: u32) -> Self {
+        self.config.unix_socket_mode = Some(mode);
         self
     }
 
-    /// Set the TCP read timeout applied to each message receive.
-    pub fn rw_timeout(mut self, timeout: Duration) -> Self {
-        self.config.read_write_timeout = timeout;
+    /// Allow clients from `networks` to connect, in addition to those allowed before.
+    pub fn allow_peers(mut self, networks: impl IntoIterator<Item = IpNetwork>) -> Self {
+        self.config.allowed_peers.extend(networks);
         self
     }
 
-    /// Build and return the server.
-    pub fn build<T: XvcServer>(self, server: T) -> Server<T> {
-        Server::new(server, self.config)
+    /// Reject clients from `networks`, in addition to those rejected before.
+    pub fn deny_peers(mut self, networks: impl IntoIterator<Item = IpNetwork>) -> Self {
+        self.config.denied_peers.extend(networks);
+        self
+    }
+
+    /// Expect a PROXY protocol header at the start of every TCP connection.
+    pub fn expect_proxy_protocol(mut self, expect: bool) -> Self {
+        self.config.expect_proxy_protocol = expect;
+        self
+    }
+
+    /// Set the capability tokens advertised in the answer to `getinfo:`.
+    pub fn capabilities<S: Into<String>>(
+        mut self,
+        capabilities: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.config.capabilities = capabilities.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Offer the CRC extension to clients.
+    pub fn crc(mut self, crc: bool) -> Self {
+        self.config.crc = crc;
+        self
+    }
+
+    /// Capture the bytes exchanged with every client into `sink`.
+    pub fn capture(mut self, sink: CaptureSink) -> Self {
+        self.config.capture = Some(sink);
+        self
+    }
+
+    /// Record a transcript of every connection into a file in the directory `dir`.
+    pub fn transcript_path(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.transcript_path = Some(dir.into());
+        self
+    }
+
+    /// Send a copy of the bytes exchanged with every client to the observers of `mirror`.
+    pub fn mirror(mut self, mirror: Mirror) -> Self {
+        self.config.mirror = Some(mirror);
+        self
+    }
+
+    /// Accept TCP connections with a TLS handshake, see [`tls`](crate::tls).
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: Arc<rustls::ServerConfig>) -> Self {
+        self.config.tls = Some(config);
+        self
+    }
+
+    /// Report the events of the connections to `observer`.
+    pub fn observer(mut self, observer: Arc<dyn ServerObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Build and return the server, or the reason why the configuration cannot serve clients,
+    /// see [`Config::validate`].
+    pub fn build<T: XvcServerMut>(self, server: T) -> Result<Server<T>, ConfigError> {
+        self.config.validate()?;
+        Ok(Server {
+            observer: self.observer,
+            ..Server::new(server, self.config)
+        })
     }
 }
 
-impl<T: XvcServer> Server<T> {
+impl<T: XvcServerMut> Server<T> {
     /// Create a new server wrapping `server` with the given `config`.
     pub fn new(server: T, config: Config) -> Server<T> {
+        let capabilities = server.capabilities();
         Server {
-            server: Arc::new(Mutex::new(server)),
+            server: Arc::new(sync::Mutex::new(server)),
+            clients: Arc::default(),
+            stats: Arc::new(ServerStats::default()),
+            config: sync::RwLock::new(config),
+            observer: None,
+            capabilities,
+        }
+    }
+
+    /// The configuration applied to new connections.
+    pub fn config(&self) -> Config {
+        self.config
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Replaces the configuration without interrupting the listener.
+    ///
+    /// The new configuration applies to connections accepted afterwards; a connected client
+    /// keeps the configuration it was accepted with. A configuration that fails
+    /// [`Config::validate`] is rejected, and the previous one is kept.
+    pub fn update_config(&self, config: Config) -> Result<(), ConfigError> {
+        config.validate()?;
+        *self
+            .config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = config;
+        Ok(())
+    }
+
+    /// A snapshot of the statistics collected since the server was created.
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// The current connection and backend health state.
+    pub fn status(&self) -> ServerStatus {
+        self.stats.status()
+    }
+
+    /// Subscribes to changes of the [`status`](Self::status), e.g. a client connecting or
+    /// the backend starting to fail.
+    pub fn subscribe_status(&self) -> watch::Receiver<ServerStatus> {
+        self.stats.subscribe()
+    }
+
+    /// The addresses of the clients waiting to be served, in the order they will be served.
+    /// See [`ClientPolicy::Queue`].
+    pub fn waiting_clients(&self) -> Vec<Peer> {
+        self.clients
+            .lock_waiting()
+            .iter()
+            .map(|&(_, peer)| peer)
+            .collect()
+    }
+
+    /// Backend-specific statistics, as reported by [`XvcServerMut::stats`].
+    ///
+    /// If a client is connected, this waits for the backend call in progress (if any) to finish.
+    pub fn backend_stats(&self) -> Vec<(&'static str, u64)> {
+        lock_backend(&self.server).stats()
+    }
+
+    /// The limits of the backend, as reported by [`XvcServerMut::capabilities`] when the server
+    /// was created.
+    pub fn backend_capabilities(&self) -> &BackendCapabilities {
+        &self.capabilities
+    }
+
+    /// The configuration of a new connection: the [`config`](Self::config), with the vector
+    /// size limited to what the backend supports.
+    fn connection_config(&self) -> Config {
+        let mut config = self.config();
+        if let Some(max_vector_size) = self.capabilities.max_vector_size {
+            config.max_vector_size = config.max_vector_size.min(max_vector_size);
+        }
+        config
+    }
+
+    /// Logs the backend and its limits next to the configured ones, so that a configuration
+    /// that the backend cannot serve is noticed.
+    fn log_capabilities(&self) {
+        let capabilities = &self.capabilities;
+        if let Some(description) = &capabilities.description {
+            log::info!("Backend: {description}");
+        }
+        let configured = self.config().max_vector_size;
+        match capabilities.max_vector_size {
+            Some(supported) if supported < configured => log::warn!(
+                "Maximum vector size of {configured} bytes is configured, but the backend only \
+                 supports {supported} bytes; announcing {supported} bytes"
+            ),
+            Some(supported) => log::info!(
+                "Maximum vector size: {configured} bytes, the backend supports {supported} bytes"
+            ),
+            None => log::info!("Maximum vector size: {configured} bytes"),
+        }
+        if let (Some(min), Some(max)) = (
+            capabilities.min_tck_period_ns,
+            capabilities.max_tck_period_ns,
+        ) {
+            log::info!("TCK periods supported by the backend: {min} ns to {max} ns");
+        }
+    }
+
+    /// The [`metrics`](crate::metrics) of this server in the Prometheus text format.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> String {
+        self.stats.metrics.render()
+    }
+
+    /// Bind to `addr` and answer `GET /metrics` with the [`metrics`](Self::metrics) until the
+    /// future is dropped. Runs next to [`listen`](Self::listen), not in its place.
+    #[cfg(feature = "metrics")]
+    pub async fn listen_metrics(&self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        metrics::listen(listener, &self.stats.metrics).await
+    }
+
+    /// Serve a single client connected through `stream` until it disconnects, e.g. over a
+    /// serial port, a vsock or an in-memory pipe.
+    ///
+    /// The configuration applies like for the clients accepted by [`listen`](Self::listen),
+    /// including the timeouts and `capture`, except for the limits on concurrent
+    /// clients: the client is served right away and is not reported in the
+    /// [`status`](Self::status).
+    pub async fn handle_stream(
+        &self,
+        stream: impl AsyncRead + AsyncWrite + Unpin,
+    ) -> Result<(), ReadError>
+    where
+        T: Send + 'static,
+    {
+        let shared = Shared {
+            server: Arc::clone(&self.server),
+            stats: Arc::clone(&self.stats),
+            shutdown: CancellationToken::new(),
+            observer: None,
+        };
+        let config = self.connection_config();
+        let mut transcript = (config.transcript_path.as_deref())
+            .and_then(|dir| block_in_place(|| TranscriptFile::create(dir, None, 0)));
+        let served = handle_client(
+            &shared,
             config,
+            stream,
+            BytesMut::new(),
+            None,
+            transcript.as_mut(),
+        );
+        Connection {
+            id: 0,
+            peer: None,
+            local: None,
         }
+        .scope(served)
+        .await
     }
 
     /// Bind to `addr` and serve clients until the process exits.
     ///
     /// This is the standard production entry point. To shut the server down
     /// programmatically (e.g. in tests), use [`listen_on`](Self::listen_on)
-    /// with a [`CancellationToken`].
+    /// with a [`CancellationToken`]. To find out the port that the OS assigned when binding
+    /// to port 0, use [`bind`](Self::bind).
+    ///
+    /// # Errors
+    ///
+    /// Fails if binding fails, or with the last error of the listener once
+    /// [`Config::max_accept_errors`] connections in a row could not be accepted.
     pub async fn listen(&self, addr: impl ToSocketAddrs) -> io::Result<()>
     where
         T: Send + 'static,
     {
+        self.bind(addr).await?.serve().await
+    }
+
+    /// Bind to all of `addrs` and serve clients until the process exits, see
+    /// [`bind_all`](Self::bind_all).
+    pub async fn listen_all(&self, addrs: impl IntoIterator<Item = SocketAddr>) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        self.bind_all(addrs).await?.serve().await
+    }
+
+    /// Bind to `addr` without serving clients yet.
+    ///
+    /// ```ignore
+    /// let bound = server.bind("127.0.0.1:0").await?;
+    /// log::info!("Listening on {}", bound.local_addr());
+    /// bound.serve().await?;
+    /// ```
+    pub async fn bind(&self, addr: impl ToSocketAddrs) -> io::Result<BoundServer<'_, T>> {
         let listener = TcpListener::bind(addr).await?;
-        self.listen_on(listener, CancellationToken::new()).await
+        let local_addr = listener.local_addr()?;
+        Ok(BoundServer {
+            server: self,
+            listeners: vec![listener],
+            local_addrs: vec![local_addr],
+        })
+    }
+
+    /// Bind to all of `addrs` without serving clients yet. Clients of all addresses are served
+    /// by the same server, so they share its limits on concurrent clients and its statistics.
+    ///
+    /// IPv6 addresses only accept IPv6 clients, regardless of the default of the platform, so
+    /// `0.0.0.0` and `::` on the same port serve both protocols:
+    ///
+    /// ```ignore
+    /// let bound = server
+    ///     .bind_all(["0.0.0.0:2542".parse()?, "[::]:2542".parse()?])
+    ///     .await?;
+    /// log::info!("Listening on {:?}", bound.local_addrs());
+    /// bound.serve().await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails if an address cannot be bound and the [`Config::bind_policy`] is
+    /// [`BindPolicy::FailFast`], and otherwise if none of them can be bound or `addrs` is
+    /// empty.
+    pub async fn bind_all(
+        &self,
+        addrs: impl IntoIterator<Item = SocketAddr>,
+    ) -> io::Result<BoundServer<'_, T>> {
+        let policy = self.config().bind_policy;
+        let mut listeners = Vec::new();
+        let mut local_addrs = Vec::new();
+        let mut failed = None;
+        for addr in addrs {
+            match bind_tcp(addr) {
+                Ok(listener) => {
+                    local_addrs.push(listener.local_addr()?);
+                    listeners.push(listener);
+                }
+                Err(e) => {
+                    let e = io::Error::new(e.kind(), format!("Could not bind {addr}: {e}"));
+                    if policy == BindPolicy::FailFast {
+                        return Err(e);
+                    }
+                    log::warn!("{e}");
+                    failed = Some(e);
+                }
+            }
+        }
+        if listeners.is_empty() {
+            return Err(failed.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "No address to bind to")
+            }));
+        }
+        Ok(BoundServer {
+            server: self,
+            listeners,
+            local_addrs,
+        })
     }
 
     /// Serve clients from a pre-bound `listener` until `shutdown` is cancelled.
     ///
-    /// When `shutdown` is cancelled the accept loop exits cleanly; any connection
-    /// that is already being served runs to completion before the task finishes.
+    /// When `shutdown` is cancelled the accept loop exits cleanly. Connected clients are
+    /// closed once the message in progress is answered, and this returns when all of them are
+    /// closed, so the backend is no longer in use. Dropping the returned future closes the
+    /// clients in the same way, but without waiting for them.
     ///
     /// This entry point is useful when the caller needs to control the server
     /// lifetime programmatically — for example in tests, or to hook into a
@@ -136,146 +1017,1866 @@ impl<T: XvcServer> Server<T> {
     where
         T: Send + 'static,
     {
-        log::info!("Server listening for connections");
+        let max_connections = self.config().max_connections;
+        self.accept_tcp(vec![listener], shutdown, max_connections)
+            .await
+    }
 
-        loop {
-            tokio::select! {
-                _ = shutdown.cancelled() => {
-                    log::info!("Shutdown signal received, stopping listener");
-                    break;
-                }
-                result = listener.accept() => {
-                    match result {
-                        Ok((stream, addr)) => {
-                            let guard = match Arc::clone(&self.server).try_lock_owned() {
-                                Ok(guard) => guard,
-                                Err(_) => {
-                                    log::warn!("Rejected concurrent client from {}: another client is already active", addr);
-                                    continue;
-                                }
-                            };
-                            stream.set_nodelay(true)?;
-                            log::info!("New client connection from {}", addr);
-                            let config = self.config.clone();
-                            tokio::spawn(async move {
-                                if let Err(e) = handle_client(guard, config, stream).await {
-                                    log::error!("Client error: {}", e);
-                                }
-                            });
-                        }
-                        Err(e) => log::error!("Connection error: {}", e),
-                    }
-                }
-            }
-        }
+    /// Serve clients on a `listener` that was bound outside of the server, e.g. passed by systemd
+    /// socket activation, until `shutdown` is cancelled, like [`listen_on`](Self::listen_on).
+    ///
+    /// The listener is switched to nonblocking mode, which the runtime requires. Its other
+    /// socket options are left as they are, while the accepted connections get the options of
+    /// the [`Config`].
+    pub async fn serve_on(
+        &self,
+        listener: std::net::TcpListener,
+        shutdown: CancellationToken,
+    ) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        log::info!("Serving on {}", listener.local_addr()?);
+        self.listen_on(listener, shutdown).await
+    }
 
-        Ok(())
+    /// Serve clients from the TCP `listeners` like [`accept_loop`](Self::accept_loop), behind a
+    /// PROXY protocol header and over TLS if configured.
+    async fn accept_tcp(
+        &self,
+        listeners: Vec<TcpListener>,
+        shutdown: CancellationToken,
+        max_connections: Option<u64>,
+    ) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        let config = self.config();
+        let listener = TcpClients {
+            listeners,
+            next: 0,
+            nodelay: config.tcp_nodelay,
+            keepalive: config.tcp_keepalive,
+        };
+        if config.expect_proxy_protocol {
+            let clients = ProxiedClients::start(listener, config.message_timeout);
+            self.accept_secured(clients, shutdown, max_connections)
+                .await
+        } else {
+            self.accept_secured(listener, shutdown, max_connections)
+                .await
+        }
     }
-}
 
-async fn handle_client<T>(
-    server: tokio::sync::OwnedMutexGuard<T>,
-    config: Config,
-    stream: TcpStream,
+    /// Serve the TCP clients of `listener` over TLS if configured.
+    async fn accept_secured<L>(
+        &self,
+        listener: L,
+        shutdown: CancellationToken,
+        max_connections: Option<u64>,
+    ) -> io::Result<()>
+    where
+        T: Send + 'static,
+        L: Listener<Stream = TcpStream> + Send + 'static,
+    {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = self.config().tls {
+            let handshake_timeout = self.config().message_timeout;
+            let clients = TlsClients::start(listener, tls, handshake_timeout);
+            return self.accept_loop(clients, shutdown, max_connections).await;
+        }
+        self.accept_loop(listener, shutdown, max_connections).await
+    }
+
+    /// Accept exactly one client from `addr`, serve it until it disconnects, and return.
+    ///
+    /// Together with [`bind`](Self::bind), this lets tests run a server that ends on its own.
+    pub async fn serve_once(&self, addr: impl ToSocketAddrs) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        self.bind(addr).await?.serve_once().await
+    }
+
+    /// Bind a Unix domain socket at `path` and serve clients until the process exits, like
+    /// [`listen`](Self::listen).
+    #[cfg(unix)]
+    pub async fn listen_unix(&self, path: impl AsRef<Path>) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        self.listen_unix_until(path, CancellationToken::new()).await
+    }
+
+    /// Bind a Unix domain socket at `path` and serve clients until `shutdown` is cancelled,
+    /// like [`listen_on`](Self::listen_on).
+    ///
+    /// A socket file that a previous server left at `path` is replaced, unless a server still
+    /// listens on it. The permissions of the socket file are set to
+    /// [`Config::unix_socket_mode`], if any, and the file is removed when the server stops,
+    /// also if the returned future is dropped.
+    #[cfg(unix)]
+    pub async fn listen_unix_until(
+        &self,
+        path: impl AsRef<Path>,
+        shutdown: CancellationToken,
+    ) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        let path = path.as_ref();
+        remove_stale_socket(path)?;
+        let listener = UnixListener::bind(path)?;
+        let _socket_file = SocketFile(path);
+        if let Some(mode) = self.config().unix_socket_mode {
+            fs::set_permissions(path, Permissions::from_mode(mode))?;
+        }
+        log::info!("Listening on {}", path.display());
+        self.accept_unix(listener, shutdown).await
+    }
+
+    /// Serve clients on a Unix domain socket `listener` that was bound outside of the server
+    /// until `shutdown` is cancelled, like [`serve_on`](Self::serve_on).
+    ///
+    /// The socket file belongs to whoever bound the listener: it is neither given the
+    /// [`Config::unix_socket_mode`] nor removed when the server stops.
+    #[cfg(unix)]
+    pub async fn serve_unix_on(
+        &self,
+        listener: std::os::unix::net::UnixListener,
+        shutdown: CancellationToken,
+    ) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        listener.set_nonblocking(true)?;
+        let listener = UnixListener::from_std(listener)?;
+        log::info!("Serving on {:?}", listener.local_addr()?);
+        self.accept_unix(listener, shutdown).await
+    }
+
+    /// Serve clients on the listening socket `fd`, a TCP or Unix domain stream socket, until
+    /// `shutdown` is cancelled. This is the entry point for systemd socket activation, which
+    /// passes the sockets as file descriptors starting at 3:
+    ///
+    /// ```ignore
+    /// // SAFETY: LISTEN_FDS names 3 as a socket that is passed to this process
+    /// let fd = unsafe { OwnedFd::from_raw_fd(3) };
+    /// server.serve_on_fd(fd, token).await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails if `fd` is not a TCP or Unix domain stream socket.
+    #[cfg(unix)]
+    pub async fn serve_on_fd(&self, fd: OwnedFd, shutdown: CancellationToken) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        let socket = Socket::from(fd);
+        if socket.r#type()? != Type::STREAM {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Not a stream socket",
+            ));
+        }
+        let local_addr = socket.local_addr()?;
+        if local_addr.is_unix() {
+            self.serve_unix_on(socket.into(), shutdown).await
+        } else if local_addr.as_socket().is_some() {
+            self.serve_on(socket.into(), shutdown).await
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Not a TCP or Unix domain socket",
+            ))
+        }
+    }
+
+    /// Serve clients of the Unix domain socket `listener` like
+    /// [`accept_loop`](Self::accept_loop).
+    #[cfg(unix)]
+    async fn accept_unix(
+        &self,
+        listener: UnixListener,
+        shutdown: CancellationToken,
+    ) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        let clients = UnixClients {
+            listener,
+            connected: 0,
+        };
+        let max_connections = self.config().max_connections;
+        self.accept_loop(clients, shutdown, max_connections).await
+    }
+
+    /// Serve clients from `listener` until `shutdown` is cancelled or `max_connections` were
+    /// accepted, then wait for the connected clients to close.
+    async fn accept_loop<L: Listener>(
+        &self,
+        mut listener: L,
+        shutdown: CancellationToken,
+        mut max_connections: Option<u64>,
+    ) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        log::info!("Server listening for connections");
+        self.log_capabilities();
+        // Dropping this future before it returns closes the connected clients like a shutdown
+        let shutdown = shutdown.child_token();
+        let _close_clients = shutdown.clone().drop_guard();
+        let shared = Shared {
+            server: Arc::clone(&self.server),
+            stats: Arc::clone(&self.stats),
+            shutdown: shutdown.clone(),
+            observer: self.observer.clone(),
+        };
+        let connections = TaskTracker::new();
+        let pool = self
+            .config()
+            .pool
+            .map(|pool| WorkerPool::start(pool, &shared, &connections));
+
+        // The error that the listener gave up on, which is returned once the clients are closed
+        let mut failed = None;
+        loop {
+            if max_connections == Some(0) {
+                log::info!("Accepted the maximum number of connections, stopping listener");
+                break;
+            }
+            let (stream, peer) = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                result = listener.next_client() => match result {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        let failures = self.stats.record_accept_error();
+                        if self.config().max_accept_errors.is_some_and(|max| failures >= max) {
+                            log::error!(
+                                "Failed to accept a connection {failures} times in a row, \
+                                 stopping listener: {e}"
+                            );
+                            failed = Some(e);
+                            break;
+                        }
+                        let delay = accept_backoff(failures);
+                        log::error!("Failed to accept a connection, retrying in {delay:?}: {e}");
+                        tokio::select! {
+                            _ = shutdown.cancelled() => break,
+                            () = sleep(delay) => continue,
+                        }
+                    }
+                },
+            };
+            if let Some(remaining) = &mut max_connections {
+                *remaining -= 1;
+            }
+            let local = L::local_addr(&stream);
+            self.stats.record_connection();
+            let config = self.connection_config();
+            if let Peer::Tcp(addr) = peer
+                && !config.allows(addr.ip())
+            {
+                log::info!("Rejected client from {}: address is not allowed", addr);
+                continue;
+            }
+            let max_clients = match (config.exclusive_client, &pool) {
+                (true, _) => 1,
+                // Queued connections are not counted, the workers limit the active ones
+                (false, Some(_)) => usize::MAX,
+                (false, None) => config.max_clients,
+            };
+            // Clients that are already waiting go first
+            let slot = if self.clients.lock_waiting().is_empty() {
+                ClientSlot::acquire(&self.clients, max_clients)
+            } else {
+                None
+            };
+            let Some(slot) = slot else {
+                match config.client_policy {
+                    ClientPolicy::Queue {
+                        max_waiting,
+                        hold_timeout,
+                    } if pool.is_none() => match Ticket::issue(&self.clients, peer, max_waiting) {
+                        Some(ticket) => {
+                            connections.spawn(wait_for_turn(
+                                shared.clone(),
+                                config,
+                                stream,
+                                local,
+                                ticket,
+                                max_clients,
+                                hold_timeout,
+                            ));
+                        }
+                        None => log::warn!(
+                            "Rejected client from {}: {} clients are already waiting",
+                            peer,
+                            max_waiting
+                        ),
+                    },
+                    _ if config.exclusive_client => {
+                        log::warn!("Rejected client from {}: another client is active", peer);
+                    }
+                    _ => {
+                        log::warn!(
+                            "Rejected client from {}: {} clients are already active",
+                            peer,
+                            max_clients
+                        );
+                    }
+                }
+                continue;
+            };
+            match &pool {
+                Some(pool) => {
+                    let pending = Pending {
+                        stream,
+                        peer,
+                        local,
+                        config,
+                        slot,
+                    };
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        () = pool.submit(pending) => {}
+                    }
+                }
+                None => {
+                    log::info!("New client connection from {}", peer);
+                    connections.spawn(serve_client(
+                        shared.clone(),
+                        config,
+                        stream,
+                        (peer, local),
+                        slot,
+                        BytesMut::new(),
+                    ));
+                }
+            }
+        }
+
+        if shutdown.is_cancelled() {
+            log::info!("Shutdown signal received, stopping listener");
+        }
+        // Closes the pending connection queue, so the workers stop
+        drop(pool);
+        connections.close();
+        if !connections.is_empty() {
+            log::info!("Waiting for connected clients to close");
+        }
+        connections.wait().await;
+        failed.map_or(Ok(()), Err)
+    }
+}
+
+/// A [`Server`] that is bound to one or more local addresses, see [`Server::bind`] and
+/// [`Server::bind_all`].
+#[derive(Debug)]
+pub struct BoundServer<'a, T: XvcServerMut> {
+    server: &'a Server<T>,
+    listeners: Vec<TcpListener>,
+    local_addrs: Vec<SocketAddr>,
+}
+
+impl<T: XvcServerMut> BoundServer<'_, T> {
+    /// The address the server is bound to, with the port assigned by the OS if the server was
+    /// bound to port 0. The first of the [`local_addrs`](Self::local_addrs) of
+    /// [`Server::bind_all`].
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addrs[0]
+    }
+
+    /// The addresses the server is bound to, in the order they were given, without those that
+    /// could not be bound.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Serve clients until the process exits, see [`Server::listen`].
+    pub async fn serve(self) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        self.serve_until(CancellationToken::new()).await
+    }
+
+    /// Serve clients until `shutdown` is cancelled, see [`Server::listen_on`].
+    pub async fn serve_until(self, shutdown: CancellationToken) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        let max_connections = self.server.config().max_connections;
+        self.server
+            .accept_tcp(self.listeners, shutdown, max_connections)
+            .await
+    }
+
+    /// Serve a single client, see [`Server::serve_once`].
+    pub async fn serve_once(self) -> io::Result<()>
+    where
+        T: Send + 'static,
+    {
+        self.server
+            .accept_tcp(self.listeners, CancellationToken::new(), Some(1))
+            .await
+    }
+}
+
+/// The state that the tasks serving clients share with the [`Server`].
+struct Shared<T> {
+    server: Arc<sync::Mutex<T>>,
+    stats: Arc<ServerStats>,
+    /// Cancelled when the server shuts down.
+    shutdown: CancellationToken,
+    observer: Option<Arc<dyn ServerObserver>>,
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Shared {
+            server: Arc::clone(&self.server),
+            stats: Arc::clone(&self.stats),
+            shutdown: self.shutdown.clone(),
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+/// A socket that clients connect to.
+pub(crate) trait Listener {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Waits for the next client to connect.
+    fn next_client(&mut self) -> impl Future<Output = io::Result<(Self::Stream, Peer)>> + Send;
+
+    /// The local address that the client of `stream` connected to, if it has one.
+    fn local_addr(_stream: &Self::Stream) -> Option<SocketAddr> {
+        None
+    }
+}
+
+/// Bind a TCP listener to `addr`. IPv6 listeners only accept IPv6 clients, so that a listener
+/// on `::` does not take the port of a listener on `0.0.0.0` on platforms that default to
+/// dual-stack sockets.
+fn bind_tcp(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    // Like `TcpListener::bind`, to rebind right after a restart
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// The time to wait before accepting again after `failures` failed accepts in a row: 10 ms,
+/// doubled with each failure up to 1 s.
+fn accept_backoff(failures: u64) -> Duration {
+    let doublings = failures.saturating_sub(1).min(7) as u32;
+    (Duration::from_millis(10) * 2u32.pow(doublings)).min(Duration::from_secs(1))
+}
+
+/// TCP listeners and the socket options of the connections they accept.
+struct TcpClients {
+    listeners: Vec<TcpListener>,
+    /// The listener to poll first, so that a busy listener cannot starve the others.
+    next: usize,
+    nodelay: bool,
+    keepalive: Option<Duration>,
+}
+
+impl TcpClients {
+    async fn accept(&mut self) -> io::Result<(TcpStream, SocketAddr)> {
+        poll_fn(|cx| {
+            let count = self.listeners.len();
+            for i in 0..count {
+                let index = (self.next + i) % count;
+                if let Poll::Ready(accepted) = self.listeners[index].poll_accept(cx) {
+                    self.next = (index + 1) % count;
+                    return Poll::Ready(accepted);
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl Listener for TcpClients {
+    type Stream = TcpStream;
+
+    async fn next_client(&mut self) -> io::Result<(TcpStream, Peer)> {
+        let (stream, addr) = self.accept().await?;
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(idle) = self.keepalive {
+            SockRef::from(&stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+        log::debug!(
+            "Connection from {}: TCP_NODELAY {}, keepalive after {:?} idle",
+            addr,
+            stream.nodelay()?,
+            self.keepalive
+        );
+        Ok((stream, Peer::Tcp(addr)))
+    }
+
+    fn local_addr(stream: &TcpStream) -> Option<SocketAddr> {
+        stream.local_addr().ok()
+    }
+}
+
+/// A Unix domain socket and the number of clients that connected to it.
+#[cfg(unix)]
+struct UnixClients {
+    listener: UnixListener,
+    connected: u64,
+}
+
+#[cfg(unix)]
+impl Listener for UnixClients {
+    type Stream = UnixStream;
+
+    async fn next_client(&mut self) -> io::Result<(UnixStream, Peer)> {
+        let (stream, _) = self.listener.accept().await?;
+        self.connected += 1;
+        Ok((stream, Peer::Unix(self.connected)))
+    }
+}
+
+/// Removes the socket file of a Unix domain socket listener when dropped.
+#[cfg(unix)]
+struct SocketFile<'a>(&'a Path);
+
+#[cfg(unix)]
+impl Drop for SocketFile<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(self.0) {
+            log::warn!("Failed to remove socket file {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// Removes the socket file at `path` that a previous server left behind. Fails if a server
+/// still listens on it, and leaves files that are not sockets alone, so binding fails.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("A server is listening on {}", path.display()),
+                ));
+            }
+            log::info!("Removing stale socket file {}", path.display());
+            fs::remove_file(path)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The clients that are served and those that wait for their turn.
+#[derive(Debug, Default)]
+struct Clients {
+    /// The number of clients that are served.
+    active: AtomicUsize,
+    /// The tickets and addresses of the waiting clients, in the order they connected.
+    waiting: sync::Mutex<VecDeque<(u64, Peer)>>,
+    next_ticket: AtomicU64,
+    /// Notified when a client stops being served or stops waiting.
+    changed: Notify,
+}
+
+impl Clients {
+    fn lock_waiting(&self) -> sync::MutexGuard<'_, VecDeque<(u64, Peer)>> {
+        self.waiting
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Counts an accepted client until it is dropped.
+struct ClientSlot(Arc<Clients>);
+
+impl ClientSlot {
+    /// Claims one of `max_clients` slots, or returns `None` if all are taken.
+    fn acquire(clients: &Arc<Clients>, max_clients: usize) -> Option<ClientSlot> {
+        clients
+            .active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |active| {
+                (active < max_clients).then_some(active + 1)
+            })
+            .ok()?;
+        Some(ClientSlot(Arc::clone(clients)))
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::AcqRel);
+        self.0.changed.notify_waiters();
+    }
+}
+
+/// The place of a waiting client in the queue, given up when it is dropped.
+struct Ticket {
+    clients: Arc<Clients>,
+    id: u64,
+    peer: Peer,
+}
+
+impl Ticket {
+    /// Queues the client from `peer`, or returns `None` if `max_waiting` clients already wait.
+    fn issue(clients: &Arc<Clients>, peer: Peer, max_waiting: usize) -> Option<Ticket> {
+        let mut waiting = clients.lock_waiting();
+        if waiting.len() >= max_waiting {
+            return None;
+        }
+        let id = clients.next_ticket.fetch_add(1, Ordering::Relaxed);
+        waiting.push_back((id, peer));
+        log::info!(
+            "Client from {} is waiting at position {} of the queue",
+            peer,
+            waiting.len()
+        );
+        Some(Ticket {
+            clients: Arc::clone(clients),
+            id,
+            peer,
+        })
+    }
+
+    /// Waits until this client is first in the queue and one of `max_clients` slots is free.
+    async fn promote(self, max_clients: usize) -> ClientSlot {
+        loop {
+            let changed = self.clients.changed.notified();
+            tokio::pin!(changed);
+            // Register before checking, so a slot freed in between is not missed
+            changed.as_mut().enable();
+            let first = self.clients.lock_waiting().front().map(|&(id, _)| id);
+            if first == Some(self.id)
+                && let Some(slot) = ClientSlot::acquire(&self.clients, max_clients)
+            {
+                return slot;
+            }
+            changed.await;
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.clients.lock_waiting().retain(|&(id, _)| id != self.id);
+        self.clients.changed.notify_waiters();
+    }
+}
+
+/// Reports a client as connected in the statistics until it is dropped, also if serving the
+/// client panics.
+struct Connected<'a> {
+    stats: &'a ServerStats,
+    peer: Peer,
+    _slot: ClientSlot,
+}
+
+impl Drop for Connected<'_> {
+    fn drop(&mut self) {
+        self.stats.client_disconnected(self.peer);
+    }
+}
+
+/// Tells the backend that the client was disconnected once it is dropped, also if serving the
+/// client panics or is aborted, so that every `on_connect` is followed by one `on_disconnect`.
+struct Session<'a, T: XvcServerMut> {
+    server: &'a sync::Mutex<T>,
+}
+
+impl<T: XvcServerMut> Drop for Session<'_, T> {
+    fn drop(&mut self) {
+        let disconnect = || block_in_place(|| lock_backend(self.server).on_disconnect());
+        if panic::catch_unwind(AssertUnwindSafe(disconnect)).is_err() {
+            log::error!("Backend panicked while disconnecting a client");
+        }
+    }
+}
+
+tokio::task_local! {
+    /// The connection that the current task serves
+    static CONNECTION: Connection;
+}
+
+/// A served connection: the number of the connection, counting from 1, and the client, or `0`
+/// and `None` for [`Server::handle_stream`].
+#[derive(Clone, Copy)]
+struct Connection {
+    id: u64,
+    peer: Option<Peer>,
+    /// The address the client connected to, which tells the endpoints of a
+    /// [`MultiServer`](crate::multi::MultiServer) apart
+    local: Option<SocketAddr>,
+}
+
+impl Connection {
+    /// Serve the connection with `served`, whose log lines are prefixed with the connection.
+    async fn scope<F: Future>(self, served: F) -> F::Output {
+        CONNECTION.scope(self, served).await
+    }
+}
+
+impl Display for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.peer, self.local) {
+            (Some(peer), Some(local)) => write!(f, "[#{} {} -> {}]", self.id, peer, local),
+            (Some(peer), None) => write!(f, "[#{} {}]", self.id, peer),
+            (None, _) => f.write_str("[stream]"),
+        }
+    }
+}
+
+/// The prefix of the log lines of the connection that the current task serves, e.g.
+/// `[#42 192.0.2.1:50000 -> 192.0.2.2:2542] `, so the lines of concurrent clients, and of the
+/// clients of different listeners, can be told apart.
+const CONN: ConnectionPrefix = ConnectionPrefix;
+
+struct ConnectionPrefix;
+
+impl Display for ConnectionPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        CONNECTION
+            .try_with(|connection| write!(f, "{connection} "))
+            .unwrap_or(Ok(()))
+    }
+}
+
+/// An accepted connection that waits for a worker of the pool.
+struct Pending<S> {
+    stream: S,
+    peer: Peer,
+    /// The address the client connected to
+    local: Option<SocketAddr>,
+    config: Config,
+    slot: ClientSlot,
+}
+
+/// The queue of the workers of a [`Pool`]. The workers stop once the queue is dropped and
+/// the connections in it are served.
+struct WorkerPool<S> {
+    queue: mpsc::Sender<Pending<S>>,
+    when_full: QueueFull,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send + 'static> WorkerPool<S> {
+    fn start<T>(pool: Pool, shared: &Shared<T>, tasks: &TaskTracker) -> WorkerPool<S>
+    where
+        T: XvcServerMut + Send + 'static,
+    {
+        log::info!(
+            "Serving clients with {} workers and a queue of {} connections",
+            pool.workers,
+            pool.pending_queue
+        );
+        let (queue, receiver) = mpsc::channel(pool.pending_queue.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        for worker in 0..pool.workers {
+            let receiver = Arc::clone(&receiver);
+            tasks.spawn(run_worker(worker, receiver, shared.clone()));
+        }
+        WorkerPool {
+            queue,
+            when_full: pool.when_full,
+        }
+    }
+
+    /// Queue `pending` for the next idle worker. If the queue is full, the connection is either
+    /// closed or this waits for room, depending on the policy.
+    async fn submit(&self, pending: Pending<S>) {
+        let peer = pending.peer;
+        let queued = match self.when_full {
+            QueueFull::Close => match self.queue.try_send(pending) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_) | TrySendError::Closed(_)) => {
+                    log::warn!("Rejected client from {peer}: the pending connection queue is full");
+                    false
+                }
+            },
+            QueueFull::Wait => self.queue.send(pending).await.is_ok(),
+        };
+        if queued {
+            log::info!("New client connection from {peer}, waiting for a worker");
+        }
+    }
+}
+
+/// Serve the connections of `queue` one after another, until the queue is closed.
+async fn run_worker<T, S>(
+    worker: usize,
+    queue: Arc<Mutex<mpsc::Receiver<Pending<S>>>>,
+    shared: Shared<T>,
+) where
+    T: XvcServerMut + Send + 'static,
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    loop {
+        // The receiver is only locked while waiting, so idle workers take turns
+        let next = queue.lock().await.recv().await;
+        let Some(Pending {
+            stream,
+            peer,
+            local,
+            config,
+            slot,
+        }) = next
+        else {
+            break;
+        };
+        log::info!("Worker {worker} serving client from {peer}");
+        // Serving in a task of its own catches panics, so the worker lives on
+        let client = tokio::spawn(serve_client(
+            shared.clone(),
+            config,
+            stream,
+            (peer, local),
+            slot,
+            BytesMut::new(),
+        ));
+        if let Err(e) = client.await
+            && e.is_panic()
+        {
+            log::error!("Worker {worker} panicked while serving {peer}, restarting it");
+        }
+    }
+}
+
+/// Locks the backend. A panic in a previous backend call does not make the backend unusable.
+fn lock_backend<T>(server: &sync::Mutex<T>) -> sync::MutexGuard<'_, T> {
+    server
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Answer `getinfo:` to the waiting client connected through `stream` to the `local` address
+/// until it is its turn, then serve it. The client is disconnected if it is still waiting after
+/// the hold timeout.
+async fn wait_for_turn<T>(
+    shared: Shared<T>,
+    config: Config,
+    mut stream: impl AsyncRead + AsyncWrite + Unpin,
+    local: Option<SocketAddr>,
+    ticket: Ticket,
+    max_clients: usize,
+    hold_timeout: Duration,
+) where
+    T: XvcServerMut + Send + 'static,
+{
+    let peer = ticket.peer;
+    let mut info = Vec::new();
+    if let Err(e) = Response::Info(server_info(&config)).write_to(&mut info) {
+        log::error!("Client error: {}", e);
+        return;
+    }
+    let promote = ticket.promote(max_clients);
+    tokio::pin!(promote);
+    let deadline = sleep(hold_timeout);
+    tokio::pin!(deadline);
+    let mut buf = BytesMut::new();
+    // Set once the client sent a command other than `getinfo:`, which is left in `buf`
+    let mut blocked = false;
+    let slot = loop {
+        tokio::select! {
+            slot = &mut promote => break slot,
+            () = shared.shutdown.cancelled() => return,
+            () = &mut deadline => {
+                log::warn!("Waiting client from {} timed out, closing connection", peer);
+                return;
+            }
+            read = stream.read_buf(&mut buf), if !blocked => {
+                if !matches!(read, Ok(n) if n > 0) {
+                    log::info!("Waiting client from {} disconnected", peer);
+                    return;
+                }
+                while buf.starts_with(CMD_GET_INFO) {
+                    buf.advance(CMD_GET_INFO.len());
+                    log::info!("Answered GetInfo message of waiting client from {}", peer);
+                    if let Err(e) = stream.write_all(&info).await {
+                        log::error!("Client error: {}", e);
+                        return;
+                    }
+                }
+                blocked = !CMD_GET_INFO.starts_with(&buf[..]);
+            }
+        }
+    };
+    log::info!("Waiting client from {} is now active", peer);
+    serve_client(shared, config, stream, (peer, local), slot, buf).await;
+}
+
+/// Serve the client from `peer` connected through `stream` to the `local` address, if any, until
+/// it disconnects. `buf` holds the bytes received from the client so far.
+async fn serve_client<T>(
+    shared: Shared<T>,
+    config: Config,
+    mut stream: impl AsyncRead + AsyncWrite + Unpin,
+    (peer, local): (Peer, Option<SocketAddr>),
+    slot: ClientSlot,
+    buf: BytesMut,
+) where
+    T: XvcServerMut + Send + 'static,
+{
+    let connection = shared.stats.client_connected(peer);
+    let _connected = Connected {
+        stats: &shared.stats,
+        peer,
+        _slot: slot,
+    };
+    let mut observed = shared
+        .observer
+        .clone()
+        .map(|observer| Observed::connect(observer, peer, connection));
+    let mut transcript = (config.transcript_path.as_deref())
+        .and_then(|dir| block_in_place(|| TranscriptFile::create(dir, Some(peer), connection)));
+    // The stream is only closed once the slot is free, so a client that sees the connection
+    // close can connect again right away
+    let served = handle_client(
+        &shared,
+        config,
+        &mut stream,
+        buf,
+        observed.as_mut(),
+        transcript.as_mut(),
+    );
+    #[cfg(feature = "tracing")]
+    let served = tracing::Instrument::instrument(served, spans::connection(peer, connection));
+    let connection = Connection {
+        id: connection,
+        peer: Some(peer),
+        local,
+    };
+    if let Err(e) = connection.scope(served).await {
+        log::error!("{connection} Client error: {e}");
+    }
+}
+
+async fn handle_client<T>(
+    shared: &Shared<T>,
+    config: Config,
+    stream: impl AsyncRead + AsyncWrite + Unpin,
+    buf: BytesMut,
+    observed: Option<&mut Observed>,
+    transcript: Option<&mut TranscriptFile>,
 ) -> Result<(), ReadError>
 where
-    T: XvcServer + Send + 'static,
+    T: XvcServerMut + Send + 'static,
 {
-    let (mut read_half, mut write_half) = stream.into_split();
-    let mut buf = BytesMut::new();
-    let mut decoder = MessageDecoder::new(config.max_vector_size as usize);
+    let _session = Session {
+        server: &shared.server,
+    };
+    block_in_place(|| start_session(shared, &config));
+    let stream = Mirrored::new(stream, config.mirror.clone());
+    let (read_half, write_half) = tokio::io::split(stream);
+    let result = match config.capture.clone() {
+        Some(sink) => {
+            let read_half = TeeReader::new(read_half, sink.clone());
+            let write_half = TeeWriter::new(write_half, sink);
+            serve(
+                shared, config, buf, read_half, write_half, observed, transcript,
+            )
+            .await
+        }
+        None => {
+            serve(
+                shared, config, buf, read_half, write_half, observed, transcript,
+            )
+            .await
+        }
+    };
+    if let Err(e) = &result {
+        shared.stats.record_protocol_error(e);
+    }
+    result
+}
+
+/// Tells the backend that a client is about to be served, and sets the TCK period of
+/// `config.tck_on_connect`. The backend stays locked in between, so no other connection calls it.
+fn start_session<T: XvcServerMut>(shared: &Shared<T>, config: &Config) {
+    let Shared { server, stats, .. } = shared;
+    let mut backend = lock_backend(server);
+    backend.on_connect();
+    let period_ns = match config.tck_on_connect {
+        TckOnConnect::Keep => return,
+        TckOnConnect::Period(period_ns) => period_ns,
+        TckOnConnect::Last => match stats.tck_period() {
+            Some(period_ns) => period_ns,
+            None => return,
+        },
+    };
+    let start = Instant::now();
+    let result = backend.set_tck(period_ns);
+    record_set_tck(stats, config, period_ns, start.elapsed());
+    stats.record_backend_result(result.is_ok());
+    match result {
+        Ok(period_ns) => {
+            log::debug!("{CONN}Set TCK period of {period_ns} ns for the new connection");
+            stats.record_tck_period(period_ns);
+        }
+        Err(e) => log::error!("{CONN}Set TCK error: {e}"),
+    }
+}
+
+/// Answer the messages in `buf` and those read from `read_half` on `write_half` until the
+/// client disconnects or the server shuts down. The messages and answers are reported to
+/// `observed` and recorded into `transcript`, if any.
+async fn serve<T>(
+    shared: &Shared<T>,
+    config: Config,
+    mut buf: BytesMut,
+    mut read_half: impl AsyncRead + Unpin,
+    write_half: impl AsyncWrite + Unpin,
+    mut observed: Option<&mut Observed>,
+    mut transcript: Option<&mut TranscriptFile>,
+) -> Result<(), ReadError>
+where
+    T: XvcServerMut + Send + 'static,
+{
+    let Shared {
+        server,
+        stats,
+        shutdown,
+        ..
+    } = shared;
+    let max_shift = config.max_vector_size.as_bytes() as usize;
+    let mut decoder = if config.skip_unknown_commands {
+        MessageDecoder::lenient(max_shift)
+    } else {
+        MessageDecoder::new(max_shift)
+    };
+    if config.crc {
+        decoder.offer_crc();
+    }
+    // Shift vectors are decoded into these buffers for the lifetime of the connection, and
+    // answers are encoded into this one, so shifts do not allocate once the buffers are grown
+    let mut shift_buffers = ShiftBuffers::new();
+    let mut out = Vec::new();
+    let mut state = ReadState {
+        // Streamed shifts are never held whole, so they cannot be recorded
+        chunk_len: match transcript {
+            Some(_) => None,
+            None => lock_backend(server).shift_chunk_len(),
+        },
+        resyncs: 0,
+    };
+    let mut tck_clamped = false;
+    let mut rate_limit = RateLimit::new(&config);
+
+    // Answers are only sent once the next message is not in `buf` yet, so the answers to
+    // messages that were sent together are also sent together
+    let mut write_half = BufWriter::with_capacity(config.write_buffer_size, write_half);
 
     loop {
-        match read_message(
-            &mut read_half,
-            &mut buf,
-            &mut decoder,
-            config.read_write_timeout,
-        )
-        .await
-        {
-            Ok(Some(msg)) => {
-                let response = block_in_place(|| compute_response(&*server, &config, msg))?;
-                write_half.write_all(&response).await?;
+        // The backend is called within `read_message` without yielding, so shutting down
+        // never interrupts a message that is being answered
+        let message = {
+            let mut read = pin!(read_message(
+                &mut read_half,
+                &mut buf,
+                &mut decoder,
+                &mut shift_buffers,
+                &config,
+                &mut state,
+                |msg| {
+                    #[cfg(feature = "tracing")]
+                    let _span = spans::MessageSpan::enter(&msg);
+                    if let Some(observed) = observed.as_deref_mut() {
+                        observed.message(&msg);
+                    }
+                    rate_limit.charge(match &msg {
+                        Message::Shift { num_bits, .. } => num_bits.div_ceil(8) as usize,
+                        _ => 0,
+                    });
+                    // The transcript holds the message as the client sent it
+                    let received = transcript.is_some().then(|| msg.clone());
+                    let range = config.tck_period_range.as_ref();
+                    let msg = clamp_tck_period(msg, range, &mut tck_clamped);
+                    // Recording writes to the transcript file, so it blocks like the backend
+                    block_in_place(|| {
+                        let answer = compute_response(
+                            &mut *lock_backend(server),
+                            stats,
+                            &config,
+                            msg,
+                            &mut out,
+                        );
+                        if let (Some(transcript), Some(received)) =
+                            (transcript.as_deref_mut(), &received)
+                        {
+                            match &answer {
+                                Some(Answer::Response(response)) => {
+                                    transcript.record_response(received, response)
+                                }
+                                Some(Answer::Tdo) => transcript.record(received, &out),
+                                Some(Answer::Close) | None => transcript.record(received, &[]),
+                            }
+                        }
+                        answer
+                    })
+                },
+            ));
+            loop {
+                tokio::select! {
+                    biased;
+                    () = shutdown.cancelled() => break None,
+                    message = &mut read => break Some(message),
+                    // Only polled once the next message has to be read from the client
+                    flushed = write_half.flush(), if !write_half.buffer().is_empty() => flushed?,
+                }
+            }
+        };
+        let Some(message) = message else {
+            log::info!("{CONN}Server is shutting down, closing connection");
+            break;
+        };
+        match message {
+            Ok(Some(Received::Answer(answer))) => {
+                match answer {
+                    Answer::Response(response) => {
+                        out.clear();
+                        response.write_to(&mut out)?;
+                    }
+                    Answer::Tdo if decoder.crc_enabled() => {
+                        let crc = crc::crc32(&out);
+                        out.extend_from_slice(&crc.to_le_bytes());
+                    }
+                    Answer::Tdo => {}
+                    Answer::Close => {
+                        write_half.flush().await?;
+                        return Err(backend_failed());
+                    }
+                }
+                write_half.write_all(&out).await?;
+                stats.record_response(out.len());
+                if let Some(observed) = observed.as_deref_mut() {
+                    observed.response(out.len());
+                }
+            }
+            Ok(Some(Received::Streamed(shift))) => {
+                rate_limit.charge(shift.len());
+                // The TDO of a streamed shift is sent as it is shifted
+                write_half.flush().await?;
+                let streamed = stream_shift(
+                    shared,
+                    shift,
+                    &mut buf,
+                    &mut read_half,
+                    write_half.get_mut(),
+                    &mut out,
+                    observed.as_deref_mut(),
+                );
+                #[cfg(feature = "tracing")]
+                let (streamed, span, start) = {
+                    let span = spans::message(&Message::<()>::Shift {
+                        num_bits: shift.num_bits,
+                        tms: (),
+                        tdi: (),
+                    });
+                    let streamed = tracing::Instrument::instrument(streamed, span.clone());
+                    (streamed, span, Instant::now())
+                };
+                let written = match timeout(config.message_timeout, streamed).await {
+                    Ok(written) => written?,
+                    Err(_elapsed) => {
+                        log::warn!(
+                            "{CONN}Client did not complete a shift within {:?}, closing connection",
+                            config.message_timeout
+                        );
+                        return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+                    }
+                };
+                #[cfg(feature = "tracing")]
+                spans::record_duration(&span, start);
+                stats.record_response(written);
+                if let Some(observed) = observed.as_deref_mut() {
+                    observed.response(written);
+                }
+            }
+            Ok(Some(Received::Oversized { num_bits })) => {
+                let len = num_bits.div_ceil(8) as usize;
+                log::warn!(
+                    "{CONN}Discarding a shift of {len} bytes per vector, the maximum is {max_shift}"
+                );
+                rate_limit.charge(len);
+                let crc = decoder.crc_enabled();
+                let discarded = discard_shift(&mut buf, &mut read_half, len, crc);
+                match timeout(config.message_timeout, discarded).await {
+                    Ok(discarded) => discarded?,
+                    Err(_elapsed) => {
+                        log::warn!(
+                            "{CONN}Client did not complete a shift within {:?}, closing connection",
+                            config.message_timeout
+                        );
+                        return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+                    }
+                }
+                let written = send_zeros(&mut write_half, &mut out, len, crc).await?;
+                if let Some(transcript) = transcript.as_deref_mut() {
+                    block_in_place(|| transcript.record_discarded_shift(num_bits));
+                }
+                stats.record_response(written);
+                if let Some(observed) = observed.as_deref_mut() {
+                    observed.response(written);
+                }
             }
             Ok(None) => break,
-            Err(e) => return Err(e),
+            Err(e) => {
+                // The messages before the malformed one are still answered
+                write_half.flush().await?;
+                return Err(e);
+            }
+        }
+
+        let delay = rate_limit.delay();
+        if !delay.is_zero() {
+            // The client gets its answers before it is throttled
+            write_half.flush().await?;
+            let start = Instant::now();
+            tokio::select! {
+                () = shutdown.cancelled() => {}
+                () = sleep(delay) => {}
+            }
+            let throttled = start.elapsed();
+            log::trace!("{CONN}Throttled the connection for {throttled:?}");
+            stats.record_throttled(throttled);
+            if let Some(observed) = observed.as_deref_mut() {
+                observed.throttled(throttled);
+            }
         }
     }
 
+    write_half.flush().await?;
     Ok(())
 }
 
-/// Read one complete message from `read`, respecting `rw_timeout` per read call.
-/// Returns `Ok(None)` on clean EOF or timeout.
-async fn read_message(
-    read: &mut OwnedReadHalf,
+/// Read messages from `read` until `respond` computes an answer for one of them, and return
+/// that answer. The vectors of a `Shift` message are decoded into `shift_buffers`, so a
+/// message only lives for the call to `respond`. With a `chunk_len`, a shift with longer
+/// vectors is not buffered but returned as [`Received::Streamed`] once its header arrived.
+/// Returns `Ok(None)` on clean EOF or if no command starts within the `idle_timeout` of
+/// `config`, and `ReadError::TruncatedMessage` if the client disconnected within a message. A
+/// message that is not complete within the `message_timeout` of its first bytes fails with a
+/// `TimedOut` error.
+///
+/// If `resync_on_error` is set, a malformed message is skipped up to the next command. Its error
+/// is only returned if no command follows within `resync_scan_limit` bytes, or if `state` was
+/// already resynchronized `max_resyncs` times. A shift with a wrong CRC is never skipped, as the
+/// data may have been corrupted anywhere. Otherwise, a shift that is too
+/// long is returned as [`Received::Oversized`] unless the `shift_error_policy` closes the
+/// connection.
+async fn read_message<R>(
+    read: &mut (impl AsyncRead + Unpin),
     buf: &mut BytesMut,
     decoder: &mut MessageDecoder,
-    rw_timeout: Duration,
-) -> Result<Option<OwnedMessage>, ReadError> {
+    shift_buffers: &mut ShiftBuffers,
+    config: &Config,
+    state: &mut ReadState,
+    mut respond: impl FnMut(BorrowedMessage<'_>) -> Option<R>,
+) -> Result<Option<Received<R>>, ReadError> {
+    // The error that is recovered from and the number of bytes skipped so far
+    let mut resyncing: Option<(ReadError, usize)> = None;
+    let Config {
+        idle_timeout,
+        message_timeout,
+        resync_on_error: resync,
+        max_vector_size,
+        ..
+    } = *config;
+    let max_shift = max_vector_size.as_bytes() as usize;
+    // When the first bytes of the message in `buf` arrived
+    let mut started: Option<Instant> = None;
     loop {
-        if let Some(msg) = decoder.decode(buf)? {
-            return Ok(Some(msg));
+        loop {
+            if let Some((error, mut skipped)) = resyncing.take() {
+                let len = buf.len();
+                let found = tokio_codec::resync(buf);
+                skipped += len - buf.len();
+                if skipped > config.resync_scan_limit {
+                    log::warn!("{CONN}No command within {skipped} bytes, closing connection");
+                    return Err(error);
+                } else if found {
+                    log::info!("{CONN}Resynchronized after skipping {skipped} bytes");
+                } else {
+                    resyncing = Some((error, skipped));
+                    break;
+                }
+            }
+            // A long shift is streamed even if it arrived as a whole
+            if let Some(chunk_len) = state.chunk_len
+                && !decoder.crc_enabled()
+                && let Some(shift) = StreamedShift::parse(
+                    buf,
+                    chunk_len,
+                    max_shift,
+                    config.shift_error_policy,
+                    config.slow_op_warn_threshold,
+                )
+            {
+                return Ok(Some(Received::Streamed(shift)));
+            }
+            match decoder.decode_into(buf, shift_buffers) {
+                Ok(Some(msg)) => {
+                    started = None;
+                    if let Some(response) = respond(msg) {
+                        return Ok(Some(Received::Answer(response)));
+                    }
+                }
+                Ok(None) => break,
+                Err(ReadError::TooManyBytes { .. })
+                    if !resync
+                        && config.shift_error_policy != ShiftErrorPolicy::CloseConnection
+                        && buf.starts_with(CMD_SHIFT) =>
+                {
+                    let num_bits = shift_num_bits(buf).expect("the header of the shift was read");
+                    return Ok(Some(Received::Oversized { num_bits }));
+                }
+                Err(e) if resync && !matches!(e, ReadError::CrcMismatch { .. }) => {
+                    if state.resyncs >= config.max_resyncs {
+                        log::warn!(
+                            "{CONN}{e} after {} resyncs, closing connection",
+                            state.resyncs
+                        );
+                        return Err(e);
+                    }
+                    state.resyncs += 1;
+                    log::warn!("{CONN}{e}, skipping to the next command");
+                    // The malformed message may start with a valid command name
+                    buf.advance(1);
+                    resyncing = Some((e, 1));
+                }
+                Err(e) => return Err(e),
+            }
         }
 
-        match timeout(rw_timeout, read.read_buf(buf)).await {
-            Ok(Ok(0)) => return Ok(None), // clean EOF
-            Ok(Ok(_)) => {}               // more bytes, loop and try to decode
+        let idle = buf.is_empty() && resyncing.is_none();
+        let read_timeout = if idle {
+            started = None;
+            idle_timeout
+        } else {
+            let started = *started.get_or_insert_with(Instant::now);
+            Some(message_timeout.saturating_sub(started.elapsed()))
+        };
+        let result = match read_timeout {
+            Some(read_timeout) => timeout(read_timeout, read.read_buf(buf)).await,
+            None => Ok(read.read_buf(buf).await),
+        };
+        match result {
+            Ok(Ok(0)) => {
+                // The client closed the connection before a command followed a malformed one
+                if let Some((error, _)) = resyncing {
+                    return Err(error);
+                }
+                // Clean EOF between messages, or the client disconnected within a message. A
+                // complete message would already have been decoded above.
+                return decoder.decode_eof(buf).map(|_| None);
+            }
+            Ok(Ok(_)) => {} // more bytes, loop and try to decode
             Ok(Err(e)) => return Err(ReadError::from(e)),
-            Err(_elapsed) => {
-                log::warn!("Client read timeout, closing connection");
-                return Ok(None);
+            Err(_elapsed) => match idle_timeout {
+                Some(idle_timeout) if idle => {
+                    log::warn!(
+                        "{CONN}Client was idle for {:?}, closing connection",
+                        idle_timeout
+                    );
+                    return Ok(None);
+                }
+                _ => {
+                    log::warn!(
+                        "{CONN}Client did not complete a message within {:?}, closing connection",
+                        message_timeout
+                    );
+                    return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+                }
+            },
+        }
+    }
+}
+
+/// What [`read_message`] keeps across the messages of a connection.
+struct ReadState {
+    /// The length of the chunks in which the backend takes shifts, see
+    /// [`XvcServerMut::shift_chunk_len`]
+    chunk_len: Option<usize>,
+    /// The number of malformed messages that were skipped
+    resyncs: usize,
+}
+
+/// The budgets of `max_shift_bytes_per_sec` and `max_messages_per_sec` of a connection.
+struct RateLimit {
+    shift_bytes: Option<TokenBucket>,
+    messages: Option<TokenBucket>,
+}
+
+impl RateLimit {
+    fn new(config: &Config) -> RateLimit {
+        RateLimit {
+            shift_bytes: config.max_shift_bytes_per_sec.map(TokenBucket::new),
+            messages: config.max_messages_per_sec.map(TokenBucket::new),
+        }
+    }
+
+    /// Charge a message, which shifts `shift_bytes` per vector.
+    fn charge(&mut self, shift_bytes: usize) {
+        if let Some(bucket) = &mut self.shift_bytes {
+            bucket.take(shift_bytes as f64);
+        }
+        if let Some(bucket) = &mut self.messages {
+            bucket.take(1.0);
+        }
+    }
+
+    /// How long the connection has to wait until it is within its budgets again.
+    fn delay(&mut self) -> Duration {
+        let delay = |bucket: &mut Option<TokenBucket>| bucket.as_mut().map(TokenBucket::delay);
+        let shift_bytes = delay(&mut self.shift_bytes).unwrap_or_default();
+        shift_bytes.max(delay(&mut self.messages).unwrap_or_default())
+    }
+}
+
+/// A budget that is restored at `rate` per second, up to a second's worth. Taking more than
+/// there is puts the budget into debt, which has to be restored before the next message.
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> TokenBucket {
+        // A rate of 0 would never restore the budget
+        let rate = rate.max(1) as f64;
+        TokenBucket {
+            rate,
+            tokens: rate,
+            refilled: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled = now;
+    }
+
+    fn take(&mut self, tokens: f64) {
+        self.refill();
+        self.tokens -= tokens;
+    }
+
+    fn delay(&mut self) -> Duration {
+        self.refill();
+        Duration::from_secs_f64((-self.tokens).max(0.0) / self.rate)
+    }
+}
+
+/// What [`read_message`] read.
+enum Received<R> {
+    /// The answer that `respond` computed for a message
+    Answer(R),
+    /// The header of a shift that is streamed, which is left at the start of the buffer
+    Streamed(StreamedShift),
+    /// The header of a shift of `num_bits`, whose vectors are longer than the server accepts,
+    /// which is left at the start of the buffer
+    Oversized { num_bits: u32 },
+}
+
+/// A shift whose vectors are streamed through the backend in chunks of `chunk_len` bytes.
+#[derive(Clone, Copy)]
+struct StreamedShift {
+    num_bits: u32,
+    chunk_len: usize,
+    /// The answer to a chunk that the backend failed
+    on_error: ShiftErrorPolicy,
+    /// The duration of a chunk that is logged as slow
+    slow_op: Option<Duration>,
+}
+
+impl StreamedShift {
+    /// The shift whose header starts `buf`, if its vectors are longer than `chunk_len`. Shifts
+    /// longer than `max_shift` are left to the decoder to reject.
+    fn parse(
+        buf: &[u8],
+        chunk_len: usize,
+        max_shift: usize,
+        on_error: ShiftErrorPolicy,
+        slow_op: Option<Duration>,
+    ) -> Option<StreamedShift> {
+        let num_bits = shift_num_bits(buf)?;
+        let chunk_len = chunk_len.max(1);
+        let len = num_bits.div_ceil(8) as usize;
+        (chunk_len < len && len <= max_shift).then_some(StreamedShift {
+            num_bits,
+            chunk_len,
+            on_error,
+            slow_op,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.num_bits.div_ceil(8) as usize
+    }
+
+    /// The length of the header that precedes the vectors.
+    const HEADER_LEN: usize = CMD_SHIFT.len() + 4;
+}
+
+/// The number of bits of the shift whose header starts `buf`, if any.
+fn shift_num_bits(buf: &[u8]) -> Option<u32> {
+    let num_bits = buf.strip_prefix(CMD_SHIFT)?.first_chunk()?;
+    Some(u32::from_le_bytes(*num_bits))
+}
+
+/// Answer the `shift` whose header starts `buf` by passing its vectors to the backend in
+/// chunks. TMS is read as a whole, as it precedes TDI on the wire, but each chunk of TDI is
+/// shifted as soon as it arrived. The TDO of a chunk is sent while the client is still sending
+/// TDI, but only as far as the client reads it: clients that only read the answer once they
+/// sent the whole shift would otherwise stall the connection. TDO is collected in `out` until it
+/// was sent, which is shrunk back to a chunk afterwards.
+///
+/// Returns the number of bytes sent.
+async fn stream_shift<T>(
+    shared: &Shared<T>,
+    shift: StreamedShift,
+    buf: &mut BytesMut,
+    read_half: &mut (impl AsyncRead + Unpin),
+    write_half: &mut (impl AsyncWrite + Unpin),
+    out: &mut Vec<u8>,
+    observed: Option<&mut Observed>,
+) -> Result<usize, ReadError>
+where
+    T: XvcServerMut + Send + 'static,
+{
+    let Shared { server, stats, .. } = shared;
+    let StreamedShift {
+        num_bits,
+        chunk_len,
+        on_error,
+        slow_op,
+    } = shift;
+    let len = shift.len();
+    let truncated = |buf: &BytesMut, shifted: usize| ReadError::TruncatedMessage {
+        command: Some(XvcCommand::Shift),
+        read: StreamedShift::HEADER_LEN + len + shifted + buf.len(),
+        expected: StreamedShift::HEADER_LEN + 2 * len,
+    };
+    buf.advance(StreamedShift::HEADER_LEN);
+    while buf.len() < len {
+        buf.reserve(len - buf.len());
+        if read_half.read_buf(buf).await? == 0 {
+            return Err(truncated(buf, 0));
+        }
+    }
+    let tms = buf.split_to(len);
+    log::debug!(
+        "{CONN}Received Shift message: num_bits={}, streamed in chunks of {} bytes",
+        num_bits,
+        chunk_len
+    );
+    if let Some(observed) = observed {
+        // TDI is never held as a whole, so observers only see TMS
+        observed.message(&Message::Shift {
+            num_bits,
+            tms: &tms[..],
+            tdi: &[],
+        });
+    }
+
+    out.clear();
+    // The bytes of `out` that were sent so far
+    let mut sent = 0;
+    let mut shifted = 0;
+    let mut backend_time = Duration::ZERO;
+    while shifted < len {
+        let chunk = chunk_len.min(len - shifted);
+        while buf.len() < chunk {
+            buf.reserve(chunk - buf.len());
+            tokio::select! {
+                read = read_half.read_buf(buf) => if read? == 0 {
+                    return Err(truncated(buf, shifted));
+                },
+                written = write_half.write(&out[sent..]), if sent < out.len() => {
+                    sent += written?;
+                }
+            }
+        }
+        let tdi = buf.split_to(chunk);
+        // Only keep the TDO the client did not read yet
+        out.drain(..sent);
+        sent = 0;
+        let tdo_start = out.len();
+        out.resize(tdo_start + chunk, 0);
+        // Only the last chunk may end within a byte
+        let chunk_bits = if shifted + chunk == len {
+            num_bits - 8 * shifted as u32
+        } else {
+            8 * chunk as u32
+        };
+        let start = Instant::now();
+        let result = block_in_place(|| {
+            let tms = &tms[shifted..shifted + chunk];
+            lock_backend(server).shift(chunk_bits, tms, &tdi, &mut out[tdo_start..])
+        });
+        let elapsed = start.elapsed();
+        backend_time += elapsed;
+        warn_if_slow(
+            slow_op,
+            "shift chunk",
+            format_args!("{chunk_bits} bits"),
+            elapsed,
+        );
+        stats.record_backend_result(result.is_ok());
+        if let Err(e) = result {
+            log::error!("{CONN}Shift error: {e}");
+            match on_error {
+                ShiftErrorPolicy::SendTdo => {}
+                ShiftErrorPolicy::ZeroFill => out[tdo_start..].fill(0),
+                ShiftErrorPolicy::CloseConnection => return Err(backend_failed()),
             }
         }
+        shifted += chunk;
     }
+    stats.record_shift(num_bits, backend_time);
+    write_half.write_all(&out[sent..]).await?;
+    // `out` may have grown to the whole TDO if the client only read it at the end
+    out.clear();
+    out.shrink_to(chunk_len);
+    Ok(len)
 }
 
-fn compute_response<T: XvcServer>(
-    server: &T,
-    config: &Config,
-    msg: OwnedMessage,
-) -> Result<Vec<u8>, ReadError> {
-    let mut buf = Vec::new();
+/// Read the shift with vectors of `len` bytes whose header starts `buf`, and its CRC if `crc`,
+/// and discard it.
+async fn discard_shift(
+    buf: &mut BytesMut,
+    read_half: &mut (impl AsyncRead + Unpin),
+    len: usize,
+    crc: bool,
+) -> Result<(), ReadError> {
+    let expected = StreamedShift::HEADER_LEN + 2 * len + if crc { crc::CRC_LEN } else { 0 };
+    let mut remaining = expected;
+    loop {
+        let discarded = remaining.min(buf.len());
+        buf.advance(discarded);
+        remaining -= discarded;
+        if remaining == 0 {
+            return Ok(());
+        }
+        buf.reserve(remaining.min(DISCARD_CHUNK_LEN));
+        if read_half.read_buf(buf).await? == 0 {
+            return Err(ReadError::TruncatedMessage {
+                command: Some(XvcCommand::Shift),
+                read: expected - remaining,
+                expected,
+            });
+        }
+    }
+}
+
+/// Answer a shift with vectors of `len` bytes with zeros, followed by their CRC if `crc`. The
+/// zeros are sent in pieces of `out`, so the answer to a huge shift is not held at once.
+///
+/// Returns the number of bytes sent.
+async fn send_zeros(
+    write_half: &mut (impl AsyncWrite + Unpin),
+    out: &mut Vec<u8>,
+    len: usize,
+    crc: bool,
+) -> io::Result<usize> {
+    out.clear();
+    out.resize(len.min(DISCARD_CHUNK_LEN), 0);
+    let mut checksum = crc::Crc32::new();
+    let mut sent = 0;
+    while sent < len {
+        let zeros = &out[..out.len().min(len - sent)];
+        if crc {
+            checksum.update(zeros);
+        }
+        write_half.write_all(zeros).await?;
+        sent += zeros.len();
+    }
+    if crc {
+        write_half
+            .write_all(&checksum.finish().to_le_bytes())
+            .await?;
+        sent += crc::CRC_LEN;
+    }
+    Ok(sent)
+}
+
+/// The most bytes of a discarded shift that are buffered at once.
+const DISCARD_CHUNK_LEN: usize = 64 * 1024;
+/// The command that waiting clients are answered.
+const CMD_GET_INFO: &[u8] = b"getinfo:";
+/// The command of a shift, which is streamed if the backend takes shifts in chunks.
+const CMD_SHIFT: &[u8] = b"shift:";
+
+/// The answer to `getinfo:`.
+fn server_info(config: &Config) -> XvcInfo {
+    XvcInfo::new(Version::V1_0, config.max_vector_size)
+        .with_capabilities(config.capabilities.iter().cloned())
+        .with_capabilities(config.crc.then_some(crc::CAPABILITY))
+}
+
+/// `msg`, with the period of a `settck:` clamped into `range`, if any. Only the first period of a
+/// connection that is clamped is warned about, which `warned` records.
+fn clamp_tck_period<'a>(
+    msg: BorrowedMessage<'a>,
+    range: Option<&RangeInclusive<u32>>,
+    warned: &mut bool,
+) -> BorrowedMessage<'a> {
+    let Some(range) = range else {
+        return msg;
+    };
     match msg {
+        Message::SetTck { period_ns } => {
+            let clamped = period_ns.max(*range.start()).min(*range.end());
+            if clamped != period_ns && *warned {
+                log::debug!("{CONN}Clamped TCK period of {period_ns} ns to {clamped} ns");
+            } else if clamped != period_ns {
+                log::warn!(
+                    "{CONN}Client requested a TCK period of {period_ns} ns, setting {clamped} ns \
+                     within the allowed {}..={} ns",
+                    range.start(),
+                    range.end()
+                );
+                *warned = true;
+            }
+            Message::SetTck { period_ns: clamped }
+        }
+        msg => msg,
+    }
+}
+
+/// The number of bytes at the start and end of each vector that are logged at trace level.
+const TRACE_VECTOR_BYTES: usize = 32;
+
+/// The answer to a message, as computed by [`compute_response`].
+enum Answer {
+    Response(Response),
+    /// The TDO of a shift, which the backend wrote into the answer buffer of the connection
+    Tdo,
+    /// No answer, as the connection is closed, see [`ShiftErrorPolicy::CloseConnection`]
+    Close,
+}
+
+/// The error that closes a connection after a failed shift.
+fn backend_failed() -> ReadError {
+    io::Error::other("the backend failed to shift, closing connection").into()
+}
+
+/// Count a backend call that set the TCK period to `period_ns` in `elapsed`.
+fn record_set_tck(stats: &ServerStats, config: &Config, period_ns: u32, elapsed: Duration) {
+    stats.record_set_tck(elapsed);
+    warn_if_slow(
+        config.slow_op_warn_threshold,
+        "settck",
+        format_args!("{period_ns} ns"),
+        elapsed,
+    );
+}
+
+/// Log a warning if the backend call of `command` of `size` took longer than `threshold`.
+fn warn_if_slow(threshold: Option<Duration>, command: &str, size: impl Display, elapsed: Duration) {
+    if threshold.is_some_and(|threshold| elapsed > threshold) {
+        log::warn!("{CONN}Slow backend call: {command} of {size} took {elapsed:?}");
+    }
+}
+
+/// Answer `msg`, or return `None` if it needs no answer. The TDO of a shift is written into
+/// `out`, which is reused for all answers of a connection, instead of a new allocation.
+fn compute_response<T: XvcServerMut>(
+    server: &mut T,
+    stats: &ServerStats,
+    config: &Config,
+    msg: BorrowedMessage<'_>,
+    out: &mut Vec<u8>,
+) -> Option<Answer> {
+    log::trace!("{CONN}Received {}", msg.summary(TRACE_VECTOR_BYTES));
+    let answer = match msg {
         Message::GetInfo => {
-            log::info!("Received GetInfo message");
-            let info = XvcInfo::new(Version::V1_0, config.max_vector_size);
-            info.write_to(&mut buf)?;
-            log::debug!("Sent XVC info response");
+            log::info!("{CONN}Received GetInfo message");
+            Answer::Response(Response::Info(server_info(config)))
         }
         Message::SetTck { period_ns } => {
-            log::debug!("Received SetTck message: period_ns={}", period_ns);
-            match server.set_tck(period_ns) {
+            log::debug!("{CONN}Received SetTck message: period_ns={}", period_ns);
+            let start = Instant::now();
+            let result = server.set_tck(period_ns);
+            record_set_tck(stats, config, period_ns, start.elapsed());
+            stats.record_backend_result(result.is_ok());
+            match result {
                 Ok(ret_period) => {
-                    log::debug!("Set TCK returned: period_ns={}", ret_period);
-                    buf.extend_from_slice(&ret_period.to_le_bytes());
+                    log::debug!("{CONN}Set TCK returned: period_ns={}", ret_period);
+                    stats.record_tck_period(ret_period);
+                    Answer::Response(Response::TckPeriod(ret_period))
                 }
                 Err(e) => {
-                    log::error!("Set TCK error: {e}");
-                    buf.extend_from_slice(&period_ns.to_le_bytes());
+                    log::error!("{CONN}Set TCK error: {e}");
+                    Answer::Response(Response::TckPeriod(period_ns))
                 }
             }
         }
+        Message::Shift { num_bits: 0, .. } => {
+            log::debug!("{CONN}Received Shift message of 0 bits, answered without the backend");
+            out.clear();
+            Answer::Tdo
+        }
         Message::Shift { num_bits, tms, tdi } => {
             log::debug!(
-                "Received Shift message: num_bits={}, tms_len={}, tdi_len={}",
+                "{CONN}Received Shift message: num_bits={}, tms_len={}, tdi_len={}",
                 num_bits,
                 tms.len(),
                 tdi.len()
             );
-            log::trace!("Shift TMS data: {:02x?}", &tms[..]);
-            log::trace!("Shift TDI data: {:02x?}", &tdi[..]);
-            buf = vec![0; tdi.len()];
-            match server.shift(num_bits, &tms, &tdi, &mut buf) {
+            out.clear();
+            out.resize(tdi.len(), 0);
+            let start = Instant::now();
+            let result = server.shift(num_bits, tms, tdi, out);
+            let elapsed = start.elapsed();
+            stats.record_shift(num_bits, elapsed);
+            warn_if_slow(
+                config.slow_op_warn_threshold,
+                "shift",
+                format_args!("{num_bits} bits"),
+                elapsed,
+            );
+            stats.record_backend_result(result.is_ok());
+            match result {
                 Ok(()) => {
-                    log::trace!("Shift result TDO data: {:02x?}", &buf[..]);
+                    let tdo = HexSummary::new(out, TRACE_VECTOR_BYTES);
+                    log::trace!("{CONN}Shift result TDO data: {tdo}");
                 }
                 Err(e) => {
-                    log::error!("Shift error: {e}");
+                    log::error!("{CONN}Shift error: {e}");
+                    match config.shift_error_policy {
+                        ShiftErrorPolicy::SendTdo => {}
+                        ShiftErrorPolicy::ZeroFill => out.fill(0),
+                        ShiftErrorPolicy::CloseConnection => return Some(Answer::Close),
+                    }
                 }
             }
+            Answer::Tdo
         }
-    }
-    Ok(buf)
+        Message::Unknown { name } if config.crc && name == crc::CAPABILITY => {
+            log::info!("{CONN}Client enabled the CRC extension");
+            return None;
+        }
+        Message::Unknown { name } => {
+            log::warn!("{CONN}Skipping unknown command {name:?}");
+            return None;
+        }
+    };
+    Some(answer)
 }
@@ -0,0 +1,253 @@
+//! A backend that models a JTAG chain of IEEE 1149.1 devices, for tests of JTAG code without
+//! hardware.
+//!
+//! Enable with the `testing` feature flag.
+//!
+//! [`SimulatedTap`] clocks the TMS and TDI vectors of each shift bit by bit through a TAP
+//! controller that all devices of the chain share, and returns the TDO that the chain shifts
+//! out. Each [`TapDevice`] has an instruction register, the BYPASS register, an optional ID
+//! register and any number of user-defined data registers.
+//!
+//! ```
+//! use xvc_protocol::jtag::TapState;
+//! use xvc_server::simulated_tap::{SimulatedTap, TapDevice};
+//!
+//! // An XC7Z020 next to TDO, and an ARM DAP next to TDI
+//! let tap = SimulatedTap::new([
+//!     TapDevice::new(6).with_idcode(0x09, 0x2372_7093),
+//!     TapDevice::new(4).with_idcode(0x0E, 0x4BA0_0477),
+//! ]);
+//! assert_eq!(tap.state(), TapState::TestLogicReset);
+//! ```
+use std::{
+    convert::Infallible,
+    sync::{Mutex, MutexGuard},
+};
+
+use xvc_protocol::{
+    BitVector,
+    jtag::{TapState, TapStateMachine},
+};
+
+use crate::XvcServer;
+
+/// A device in a simulated JTAG chain.
+///
+/// Instructions are the values of the instruction register, with bit 0 shifted first. The
+/// all-ones instruction selects BYPASS, as does any instruction that selects no other register.
+/// Test-Logic-Reset selects the IDCODE instruction, or BYPASS for a device without ID register.
+#[derive(Debug, Clone)]
+pub struct TapDevice {
+    ir_len: usize,
+    idcode: Option<(u32, u32)>,
+    registers: Vec<(u32, BitVector)>,
+    instruction: u32,
+    ir_shift: BitVector,
+    dr_shift: BitVector,
+}
+
+impl TapDevice {
+    /// A device with an instruction register of `ir_len` bits, and only the BYPASS register.
+    ///
+    /// # Panics
+    ///
+    /// If `ir_len` is not between 2, the minimum of the standard, and 32.
+    pub fn new(ir_len: usize) -> TapDevice {
+        assert!(
+            (2..=32).contains(&ir_len),
+            "instruction registers of {ir_len} bits are not supported"
+        );
+        let mut device = TapDevice {
+            ir_len,
+            idcode: None,
+            registers: Vec::new(),
+            instruction: 0,
+            ir_shift: BitVector::zeros(ir_len),
+            dr_shift: BitVector::new(),
+        };
+        device.reset();
+        device
+    }
+
+    /// Add an ID register that `instruction` selects and that holds `idcode`.
+    pub fn with_idcode(mut self, instruction: u32, idcode: u32) -> TapDevice {
+        self.idcode = Some((instruction, idcode));
+        self.reset();
+        self
+    }
+
+    /// Add a data register of `len` bits that `instruction` selects. The register starts out
+    /// as zeros, captures its value and takes the shifted bits on Update-DR.
+    pub fn with_register(mut self, instruction: u32, len: usize) -> TapDevice {
+        self.registers.push((instruction, BitVector::zeros(len)));
+        self
+    }
+
+    pub fn ir_len(&self) -> usize {
+        self.ir_len
+    }
+
+    /// The instruction that was last updated.
+    pub fn instruction(&self) -> u32 {
+        self.instruction
+    }
+
+    /// The value of the user-defined register that `instruction` selects.
+    pub fn register(&self, instruction: u32) -> Option<&BitVector> {
+        (self.registers.iter())
+            .find(|(selected_by, _)| *selected_by == instruction)
+            .map(|(_, value)| value)
+    }
+
+    fn bypass(&self) -> u32 {
+        u32::MAX >> (32 - self.ir_len)
+    }
+
+    fn reset(&mut self) {
+        self.instruction = self
+            .idcode
+            .map_or(self.bypass(), |(instruction, _)| instruction);
+    }
+
+    /// Clock a rising edge of TCK in `state` and return the TDO of the device before the edge.
+    fn clock(&mut self, state: TapState, tdi: bool) -> bool {
+        match state {
+            TapState::CaptureIr => {
+                // Bits 1 and 0 capture `01`, the remaining bits are left to the device
+                self.ir_shift = BitVector::zeros(self.ir_len);
+                self.ir_shift.set(0, true);
+                false
+            }
+            TapState::ShiftIr => shift(&mut self.ir_shift, tdi),
+            TapState::CaptureDr => {
+                self.dr_shift = self.selected_register();
+                false
+            }
+            TapState::ShiftDr => shift(&mut self.dr_shift, tdi),
+            _ => false,
+        }
+    }
+
+    /// Take the actions of the state that the TAP controller entered.
+    fn enter(&mut self, state: TapState) {
+        match state {
+            TapState::TestLogicReset => self.reset(),
+            TapState::UpdateIr => {
+                self.instruction = (self.ir_shift.iter())
+                    .enumerate()
+                    .fold(0, |instruction, (i, bit)| instruction | u32::from(bit) << i);
+            }
+            TapState::UpdateDr => {
+                let instruction = self.instruction;
+                if let Some((_, value)) =
+                    (self.registers.iter_mut()).find(|(selected_by, _)| *selected_by == instruction)
+                {
+                    value.clone_from(&self.dr_shift);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn selected_register(&self) -> BitVector {
+        if let Some((_, idcode)) = self.idcode.filter(|(id, _)| *id == self.instruction) {
+            return BitVector::from_bytes(&idcode.to_le_bytes(), 32);
+        }
+        self.register(self.instruction)
+            .cloned()
+            .unwrap_or_else(|| BitVector::zeros(1))
+    }
+}
+
+/// Shift `tdi` into the top of `register` and return the bit that was shifted out of bit 0.
+fn shift(register: &mut BitVector, tdi: bool) -> bool {
+    let Some(tdo) = register.get(0) else {
+        return tdi;
+    };
+    for i in 1..register.len() {
+        let bit = register.get(i).unwrap_or_default();
+        register.set(i - 1, bit);
+    }
+    register.set(register.len() - 1, tdi);
+    tdo
+}
+
+#[derive(Debug)]
+struct Chain {
+    tap: TapStateMachine,
+    devices: Vec<TapDevice>,
+}
+
+/// A chain of simulated [`TapDevice`]s behind a shared TAP controller.
+///
+/// The devices are ordered from TDO to TDI: the first device shifts out to TDO, so its IDCODE
+/// is read first, and the last device takes TDI. The chain starts in Test-Logic-Reset.
+///
+/// Outside of Shift-IR and Shift-DR, TDO is returned as zero.
+#[derive(Debug)]
+pub struct SimulatedTap {
+    chain: Mutex<Chain>,
+}
+
+impl SimulatedTap {
+    pub fn new(devices: impl IntoIterator<Item = TapDevice>) -> SimulatedTap {
+        SimulatedTap {
+            chain: Mutex::new(Chain {
+                tap: TapStateMachine::new(),
+                devices: devices.into_iter().collect(),
+            }),
+        }
+    }
+
+    /// The state of the TAP controller.
+    pub fn state(&self) -> TapState {
+        self.lock().tap.state()
+    }
+
+    /// The devices of the chain, in their current state.
+    pub fn devices(&self) -> Vec<TapDevice> {
+        self.lock().devices.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Chain> {
+        self.chain
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl XvcServer for SimulatedTap {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Infallible> {
+        let mut chain = self.lock();
+        let Chain { tap, devices } = &mut *chain;
+        tdo.fill(0);
+        for i in 0..num_bits as usize {
+            let (byte, mask) = (i / 8, 1 << (i % 8));
+            let state = tap.state();
+            let mut bit = tdi[byte] & mask != 0;
+            for device in devices.iter_mut().rev() {
+                bit = device.clock(state, bit);
+            }
+            if bit {
+                tdo[byte] |= mask;
+            }
+            let next = tap.step(tms[byte] & mask != 0);
+            for device in devices.iter_mut() {
+                device.enter(next);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+//! [`tracing`] spans of connections and messages, with the `tracing` feature.
+//!
+//! Each served client runs in an `info` level `connection` span with the `peer` and the `id`
+//! of the connection, and each message it sends is answered in a `debug` level `message`
+//! span with the `command`, the `num_bits` of a shift and the `duration_us` of the answer.
+//! The `log` records of the server are emitted as before; a subscriber that bridges them with
+//! `tracing-log` places them in these spans.
+use std::{net::SocketAddr, time::Instant};
+
+use tracing::{Span, field, span::EnteredSpan};
+use xvc_protocol::{BorrowedMessage, Message};
+
+use crate::stats::Peer;
+
+/// The span of a [`MultiServer`](crate::multi::MultiServer) endpoint that listens on `addr`.
+pub(crate) fn endpoint(addr: SocketAddr) -> Span {
+    tracing::info_span!("endpoint", addr = %addr)
+}
+
+/// The span of the connection with `peer`, the `id`th client of the server.
+pub(crate) fn connection(peer: Peer, id: u64) -> Span {
+    tracing::info_span!("connection", peer = %peer, id)
+}
+
+/// The entered span of a message, which records how long the message took once it is dropped.
+pub(crate) struct MessageSpan {
+    span: EnteredSpan,
+    start: Instant,
+}
+
+impl MessageSpan {
+    pub(crate) fn enter(msg: &BorrowedMessage<'_>) -> MessageSpan {
+        MessageSpan {
+            span: message(msg).entered(),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for MessageSpan {
+    fn drop(&mut self) {
+        record_duration(&self.span, self.start);
+    }
+}
+
+/// The span of `msg`, whose duration is recorded with [`record_duration`].
+pub(crate) fn message<B>(msg: &Message<B>) -> Span {
+    let (command, num_bits) = match msg {
+        Message::GetInfo => ("getinfo", None),
+        Message::SetTck { .. } => ("settck", None),
+        Message::Shift { num_bits, .. } => ("shift", Some(*num_bits)),
+        Message::Unknown { .. } => ("unknown", None),
+    };
+    tracing::debug_span!("message", command, num_bits, duration_us = field::Empty)
+}
+
+/// Records the time since `start` as the duration of the message of `span`.
+pub(crate) fn record_duration(span: &Span, start: Instant) {
+    let duration_us = u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX);
+    span.record("duration_us", duration_us);
+}
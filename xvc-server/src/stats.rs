@@ -0,0 +1,424 @@
+//! Statistics collected by a running [`Server`](crate::server::Server).
+use std::{
+    fmt::{self, Display},
+    net::SocketAddr,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::watch;
+use xvc_protocol::error::ReadError;
+
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+
+/// Counters shared between the server and its connection handlers.
+#[derive(Debug)]
+pub(crate) struct ServerStats {
+    started: Instant,
+    /// Clients that were served, including those that are still connected
+    connections: AtomicU64,
+    errors: AtomicU64,
+    accept_errors: AtomicU64,
+    /// Failed accepts since the last connection was accepted
+    consecutive_accept_errors: AtomicU64,
+    shifts: AtomicU64,
+    bits_shifted: AtomicU64,
+    backend_time_ns: AtomicU64,
+    worst_backend_time_ns: AtomicU64,
+    bytes_written: AtomicU64,
+    throttled_ns: AtomicU64,
+    shift_latency: AtomicHistogram,
+    set_tck_latency: AtomicHistogram,
+    /// The TCK period that the backend last set
+    tck_period_ns: Mutex<Option<u32>>,
+    /// The connected clients, in the order they connected
+    peers: Mutex<Vec<Peer>>,
+    status: watch::Sender<ServerStatus>,
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: Metrics,
+}
+
+impl Default for ServerStats {
+    fn default() -> Self {
+        ServerStats {
+            started: Instant::now(),
+            connections: AtomicU64::default(),
+            errors: AtomicU64::default(),
+            accept_errors: AtomicU64::default(),
+            consecutive_accept_errors: AtomicU64::default(),
+            shifts: AtomicU64::default(),
+            bits_shifted: AtomicU64::default(),
+            backend_time_ns: AtomicU64::default(),
+            worst_backend_time_ns: AtomicU64::default(),
+            bytes_written: AtomicU64::default(),
+            throttled_ns: AtomicU64::default(),
+            shift_latency: AtomicHistogram::default(),
+            set_tck_latency: AtomicHistogram::default(),
+            tck_period_ns: Mutex::default(),
+            peers: Mutex::default(),
+            status: watch::channel(ServerStatus::default()).0,
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+impl ServerStats {
+    pub(crate) fn record_shift(&self, num_bits: u32, elapsed: Duration) {
+        let elapsed_ns = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.shifts.fetch_add(1, Ordering::Relaxed);
+        self.bits_shifted
+            .fetch_add(num_bits as u64, Ordering::Relaxed);
+        self.backend_time_ns
+            .fetch_add(elapsed_ns, Ordering::Relaxed);
+        self.worst_backend_time_ns
+            .fetch_max(elapsed_ns, Ordering::Relaxed);
+        self.shift_latency.record(elapsed);
+        #[cfg(feature = "metrics")]
+        self.metrics.record_shift(num_bits, elapsed);
+    }
+
+    /// Counts a backend call to set the TCK period that took `elapsed`.
+    pub(crate) fn record_set_tck(&self, elapsed: Duration) {
+        self.set_tck_latency.record(elapsed);
+    }
+
+    /// Counts an accepted connection, whether it is served or not.
+    pub(crate) fn record_connection(&self) {
+        self.consecutive_accept_errors.store(0, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.metrics.record_connection();
+    }
+
+    /// Counts a failed attempt to accept a connection and returns the number of failures since
+    /// the last connection was accepted.
+    pub(crate) fn record_accept_error(&self) -> u64 {
+        self.accept_errors.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.metrics.record_accept_error();
+        self.consecutive_accept_errors
+            .fetch_add(1, Ordering::Relaxed)
+            + 1
+    }
+
+    /// Counts a connection that was closed because of `error`.
+    pub(crate) fn record_protocol_error(&self, error: &ReadError) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.metrics.record_error(error);
+        #[cfg(not(feature = "metrics"))]
+        let _ = error;
+    }
+
+    /// Counts the bytes of an answer sent to a client.
+    pub(crate) fn record_response(&self, bytes_written: usize) {
+        self.bytes_written
+            .fetch_add(bytes_written as u64, Ordering::Relaxed);
+    }
+
+    /// Counts the time that a connection was held back by its rate limits.
+    pub(crate) fn record_throttled(&self, throttled: Duration) {
+        let throttled_ns = u64::try_from(throttled.as_nanos()).unwrap_or(u64::MAX);
+        self.throttled_ns.fetch_add(throttled_ns, Ordering::Relaxed);
+    }
+
+    /// Records the TCK period that the backend set.
+    pub(crate) fn record_tck_period(&self, period_ns: u32) {
+        *self
+            .tck_period_ns
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(period_ns);
+        #[cfg(feature = "metrics")]
+        self.metrics.record_tck_period(period_ns);
+    }
+
+    /// The TCK period that the backend last set, if any.
+    pub(crate) fn tck_period(&self) -> Option<u32> {
+        *self
+            .tck_period_ns
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Reports `peer` as connected and returns the number of the connection, counting from 1.
+    pub(crate) fn client_connected(&self, peer: Peer) -> u64 {
+        let id = self.connections.fetch_add(1, Ordering::Relaxed) + 1;
+        #[cfg(feature = "metrics")]
+        self.metrics.client_connected();
+        self.update_peers(|peers| peers.push(peer));
+        id
+    }
+
+    pub(crate) fn client_disconnected(&self, peer: Peer) {
+        #[cfg(feature = "metrics")]
+        self.metrics.client_disconnected();
+        log::debug!(
+            "Backend latency after {} disconnected: shift {}, settck {}",
+            peer,
+            self.shift_latency.snapshot(),
+            self.set_tck_latency.snapshot()
+        );
+        self.update_peers(|peers| {
+            if let Some(index) = peers.iter().position(|p| *p == peer) {
+                peers.remove(index);
+            }
+        });
+    }
+
+    /// Modifies the connected clients and reports the one that connected first.
+    fn update_peers(&self, modify: impl FnOnce(&mut Vec<Peer>)) {
+        let mut peers = self
+            .peers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        modify(&mut peers);
+        let client = peers.first().copied();
+        self.status.send_if_modified(|status| {
+            let changed = status.client != client;
+            status.client = client;
+            changed
+        });
+    }
+
+    /// Records whether the last backend call succeeded. Subscribers are only notified on changes.
+    pub(crate) fn record_backend_result(&self, healthy: bool) {
+        self.status.send_if_modified(|status| {
+            let changed = status.backend_healthy != healthy;
+            status.backend_healthy = healthy;
+            changed
+        });
+    }
+
+    pub(crate) fn status(&self) -> ServerStatus {
+        *self.status.borrow()
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<ServerStatus> {
+        self.status.subscribe()
+    }
+
+    pub(crate) fn snapshot(&self) -> StatsSnapshot {
+        let active_connections = self
+            .peers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .len();
+        StatsSnapshot {
+            uptime: self.started.elapsed(),
+            connections: self.connections.load(Ordering::Relaxed),
+            active_connections: active_connections as u64,
+            errors: self.errors.load(Ordering::Relaxed),
+            accept_errors: self.accept_errors.load(Ordering::Relaxed),
+            consecutive_accept_errors: self.consecutive_accept_errors.load(Ordering::Relaxed),
+            shifts: self.shifts.load(Ordering::Relaxed),
+            bits_shifted: self.bits_shifted.load(Ordering::Relaxed),
+            total_backend_time: Duration::from_nanos(self.backend_time_ns.load(Ordering::Relaxed)),
+            worst_backend_time: Duration::from_nanos(
+                self.worst_backend_time_ns.load(Ordering::Relaxed),
+            ),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            throttled_time: Duration::from_nanos(self.throttled_ns.load(Ordering::Relaxed)),
+            shift_latency: self.shift_latency.snapshot(),
+            set_tck_latency: self.set_tck_latency.snapshot(),
+            tck_period_ns: self.tck_period(),
+            current_peer: self.status.borrow().client,
+        }
+    }
+}
+
+/// A connected client.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Peer {
+    /// A client connected over TCP from this address.
+    Tcp(SocketAddr),
+    /// A client connected to a Unix domain socket, which has no address of its own. The clients
+    /// of a socket are numbered from 1 in the order they connected.
+    Unix(u64),
+}
+
+impl From<SocketAddr> for Peer {
+    fn from(addr: SocketAddr) -> Self {
+        Peer::Tcp(addr)
+    }
+}
+
+impl Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Peer::Tcp(addr) => addr.fmt(f),
+            Peer::Unix(number) => write!(f, "unix socket client {number}"),
+        }
+    }
+}
+
+/// Connection and health state of a running server.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ServerStatus {
+    /// The currently connected client, if any. If several clients are connected, this is the
+    /// one that connected first.
+    pub client: Option<Peer>,
+    /// `false` if the most recent backend call returned an error.
+    pub backend_healthy: bool,
+}
+
+impl Default for ServerStatus {
+    fn default() -> Self {
+        ServerStatus {
+            client: None,
+            backend_healthy: true,
+        }
+    }
+}
+
+/// The statistics of a single connection, as reported to
+/// [`ServerObserver::on_response`](crate::observer::ServerObserver::on_response) while it is
+/// served and to [`ServerObserver::on_disconnect`](crate::observer::ServerObserver::on_disconnect)
+/// once it is closed.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ConnectionStats {
+    /// The number of the connection, counting from 1 in the order the server served its
+    /// clients, as it prefixes the log lines of the connection.
+    pub connection: u64,
+    /// How long the client was served so far.
+    pub duration: Duration,
+    /// Number of messages received, including unknown commands that were skipped.
+    pub messages: u64,
+    /// Number of shifts received.
+    pub shifts: u64,
+    /// Total number of bits of the shifts received.
+    pub bits_shifted: u64,
+    /// Total number of bytes of the answers sent.
+    pub bytes_written: u64,
+    /// Time that the connection was held back by the rate limits of
+    /// [`Config::max_shift_bytes_per_sec`](crate::server::Config::max_shift_bytes_per_sec) and
+    /// [`Config::max_messages_per_sec`](crate::server::Config::max_messages_per_sec).
+    pub throttled: Duration,
+}
+
+/// A point-in-time copy of the server statistics.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct StatsSnapshot {
+    /// Time since the server was created.
+    pub uptime: Duration,
+    /// Number of clients that were served, including the connected ones. Clients that were
+    /// rejected or gave up waiting in the queue are not counted.
+    pub connections: u64,
+    /// Number of clients that are currently served.
+    pub active_connections: u64,
+    /// Number of connections that were closed because of a protocol or I/O error.
+    pub errors: u64,
+    /// Number of failed attempts to accept a connection.
+    pub accept_errors: u64,
+    /// Number of failed attempts to accept a connection since the last one was accepted, see
+    /// [`Config::max_accept_errors`](crate::server::Config::max_accept_errors).
+    pub consecutive_accept_errors: u64,
+    /// Number of shift operations passed to the backend.
+    pub shifts: u64,
+    /// Total number of bits shifted.
+    pub bits_shifted: u64,
+    /// Accumulated time spent in backend shift calls.
+    pub total_backend_time: Duration,
+    /// Longest single backend shift call.
+    pub worst_backend_time: Duration,
+    /// Total number of bytes of the answers sent to clients.
+    pub bytes_written: u64,
+    /// Total time that connections were held back by their rate limits, see
+    /// [`ConnectionStats::throttled`].
+    pub throttled_time: Duration,
+    /// The durations of the backend shift calls. A shift that is streamed through the backend
+    /// in chunks counts once, with the time of all its chunks.
+    pub shift_latency: LatencyHistogram,
+    /// The durations of the backend calls to set the TCK period.
+    pub set_tck_latency: LatencyHistogram,
+    /// The TCK period that the backend last set, if a client set one.
+    pub tck_period_ns: Option<u32>,
+    /// The currently connected client, if any, as in [`ServerStatus::client`].
+    pub current_peer: Option<Peer>,
+}
+
+impl StatsSnapshot {
+    /// Average duration of a backend shift call, or `None` if no shift was performed.
+    pub fn average_backend_time(&self) -> Option<Duration> {
+        if self.shifts == 0 {
+            return None;
+        }
+        let average_ns = self.total_backend_time.as_nanos() / self.shifts as u128;
+        Some(Duration::from_nanos(average_ns as u64))
+    }
+}
+
+/// Upper bounds of the buckets of a [`LatencyHistogram`]. The last bucket holds the calls that
+/// took longer than the last bound.
+pub const LATENCY_BUCKETS: [Duration; 6] = [
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+];
+
+/// The number of backend calls by duration, in the buckets of [`LATENCY_BUCKETS`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct LatencyHistogram {
+    /// The calls per bucket: `counts[i]` took at most `LATENCY_BUCKETS[i]` and longer than the
+    /// bound before, the last one longer than all bounds.
+    pub counts: [u64; LATENCY_BUCKETS.len() + 1],
+}
+
+impl LatencyHistogram {
+    /// The number of calls in all buckets.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The upper bound of each bucket, `None` for the last one, and its number of calls.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        LATENCY_BUCKETS
+            .iter()
+            .copied()
+            .map(Some)
+            .chain([None])
+            .zip(self.counts.iter().copied())
+    }
+}
+
+/// The non-empty buckets, e.g. `<=10us: 12, <=1ms: 3, >1s: 1`, or `none`.
+impl Display for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.total() == 0 {
+            return f.write_str("none");
+        }
+        let last = LATENCY_BUCKETS[LATENCY_BUCKETS.len() - 1];
+        let mut separator = "";
+        for (bound, count) in self.buckets().filter(|&(_, count)| count > 0) {
+            match bound {
+                Some(bound) => write!(f, "{separator}<={bound:?}: {count}")?,
+                None => write!(f, "{separator}>{last:?}: {count}")?,
+            }
+            separator = ", ";
+        }
+        Ok(())
+    }
+}
+
+/// A [`LatencyHistogram`] that is updated by the connection handlers.
+#[derive(Debug, Default)]
+struct AtomicHistogram([AtomicU64; LATENCY_BUCKETS.len() + 1]);
+
+impl AtomicHistogram {
+    fn record(&self, elapsed: Duration) {
+        let bucket = LATENCY_BUCKETS.partition_point(|&bound| bound < elapsed);
+        self.0[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LatencyHistogram {
+        LatencyHistogram {
+            counts: self.0.each_ref().map(|count| count.load(Ordering::Relaxed)),
+        }
+    }
+}
@@ -0,0 +1,724 @@
+//! Backends for tests of code that talks to a server, e.g. of clients or of the integration of
+//! a server into an application.
+//!
+//! Enable with the `testing` feature flag.
+//!
+//! All backends accept every TCK period, and return TDO with the padding bits of a shift that
+//! ends within a byte cleared, like hardware that shifts only `num_bits`. [`FaultInjecting`]
+//! wraps any backend to make it misbehave, e.g. to test how a client copes with slow or failing
+//! cables. [`ReplayBackend`] answers with the responses of a recorded session instead.
+//! [`serve_in_memory`] serves a backend over a [`memory_pipe`], so a client can be tested
+//! against a server in-process, without sockets.
+//!
+//! ```ignore
+//! let backend = RecordingBackend::default();
+//! let server = Server::new(backend.clone(), Config::default());
+//! // ... serve a client that sets the TCK period
+//! assert_eq!(backend.calls()[1], Call::SetTck { period_ns: 100 });
+//! ```
+use std::{
+    convert::Infallible,
+    error::Error,
+    fmt::{self, Display},
+    io::{self, Read},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, ready},
+    thread,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, Instant, Sleep},
+};
+use xvc_protocol::{
+    Message, OwnedMessage, bits,
+    error::{ReadError, TranscriptError},
+    transcript::TranscriptReader,
+};
+
+use crate::{
+    BackendCapabilities, XvcServer, XvcServerMut,
+    server::{Config, Server},
+};
+
+/// Returns TDI as TDO, so clients can check that their data arrived intact.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoopbackBackend;
+
+impl XvcServer for LoopbackBackend {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(&self, num_bits: u32, _: &[u8], tdi: &[u8], tdo: &mut [u8]) -> Result<(), Infallible> {
+        loop_back(num_bits, tdi, tdo);
+        Ok(())
+    }
+}
+
+/// Returns a pattern of bytes as TDO, repeated as often as the shift needs and starting over
+/// with each shift. The default pattern is empty, which returns zeros.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConstantBackend {
+    pattern: Vec<u8>,
+}
+
+impl ConstantBackend {
+    pub fn new(pattern: impl Into<Vec<u8>>) -> ConstantBackend {
+        ConstantBackend {
+            pattern: pattern.into(),
+        }
+    }
+
+    /// The pattern that is returned as TDO.
+    pub fn pattern(&self) -> &[u8] {
+        &self.pattern
+    }
+}
+
+impl XvcServer for ConstantBackend {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(&self, num_bits: u32, _: &[u8], _: &[u8], tdo: &mut [u8]) -> Result<(), Infallible> {
+        if self.pattern.is_empty() {
+            tdo.fill(0);
+        } else {
+            for (tdo, pattern) in tdo.iter_mut().zip(self.pattern.iter().cycle()) {
+                *tdo = *pattern;
+            }
+        }
+        bits::mask_padding(tdo, num_bits as usize);
+        Ok(())
+    }
+}
+
+/// A call that a [`RecordingBackend`] received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call {
+    /// [`XvcServer::on_connect`]
+    Connect,
+    /// [`XvcServer::set_tck`]
+    SetTck { period_ns: u32 },
+    /// [`XvcServer::shift`], with the vectors it was given
+    Shift {
+        num_bits: u32,
+        tms: Vec<u8>,
+        tdi: Vec<u8>,
+    },
+    /// [`XvcServer::on_disconnect`]
+    Disconnect,
+}
+
+/// Records the calls it receives, and returns TDI as TDO like [`LoopbackBackend`].
+///
+/// Clones share the record, so a test can keep a clone to inspect the calls of the backend
+/// that it passed to a server.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingBackend {
+    calls: Arc<Mutex<Vec<Call>>>,
+}
+
+impl RecordingBackend {
+    /// The calls received so far, in order.
+    pub fn calls(&self) -> Vec<Call> {
+        self.lock().clone()
+    }
+
+    /// Forget the calls received so far.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn record(&self, call: Call) {
+        self.lock().push(call);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Call>> {
+        self.calls
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl XvcServer for RecordingBackend {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        self.record(Call::SetTck { period_ns });
+        Ok(period_ns)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Infallible> {
+        self.record(Call::Shift {
+            num_bits,
+            tms: tms.to_vec(),
+            tdi: tdi.to_vec(),
+        });
+        loop_back(num_bits, tdi, tdo);
+        Ok(())
+    }
+
+    fn on_connect(&self) {
+        self.record(Call::Connect);
+    }
+
+    fn on_disconnect(&self) {
+        self.record(Call::Disconnect);
+    }
+}
+
+/// Copy the `num_bits` bits of `tdi` into `tdo`, with the padding bits cleared.
+fn loop_back(num_bits: u32, tdi: &[u8], tdo: &mut [u8]) {
+    tdo.copy_from_slice(tdi);
+    bits::mask_padding(tdo, num_bits as usize);
+}
+
+/// The faults that a [`FaultInjecting`] backend injects. By default, no faults are injected.
+///
+/// Random faults are drawn from a generator seeded with [`seed`](Faults::seed), so a test that
+/// makes the same calls sees the same faults on every run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Faults {
+    pub seed: u64,
+    /// The probability that a shift is delayed by [`latency`](Faults::latency).
+    pub latency_probability: f64,
+    pub latency: Duration,
+    /// Truncate the TDO of every `n`th shift to [`truncated_tdo_len`](Faults::truncated_tdo_len)
+    /// bytes.
+    ///
+    /// The server always answers a shift with TDO of the full length, so the bytes beyond
+    /// the truncation are zeros, like the TDO of a backend that stopped writing it.
+    pub truncate_every: Option<u64>,
+    pub truncated_tdo_len: usize,
+    /// Added to the period that the backend reports for a TCK period that was set.
+    pub tck_offset_ns: i64,
+    /// The probability that a call of `set_tck` or `shift` fails.
+    pub failure_probability: f64,
+    /// Fail every call of `set_tck` or `shift` after this number of calls.
+    pub fail_after: Option<u64>,
+}
+
+impl Default for Faults {
+    fn default() -> Faults {
+        Faults {
+            seed: 0,
+            latency_probability: 0.0,
+            latency: Duration::ZERO,
+            truncate_every: None,
+            truncated_tdo_len: 0,
+            tck_offset_ns: 0,
+            failure_probability: 0.0,
+            fail_after: None,
+        }
+    }
+}
+
+impl Faults {
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn latency(mut self, probability: f64, latency: Duration) -> Self {
+        self.latency_probability = probability;
+        self.latency = latency;
+        self
+    }
+
+    pub fn truncate_tdo(mut self, every: u64, len: usize) -> Self {
+        self.truncate_every = Some(every);
+        self.truncated_tdo_len = len;
+        self
+    }
+
+    pub fn tck_offset_ns(mut self, offset: i64) -> Self {
+        self.tck_offset_ns = offset;
+        self
+    }
+
+    pub fn failure_probability(mut self, probability: f64) -> Self {
+        self.failure_probability = probability;
+        self
+    }
+
+    pub fn fail_after(mut self, calls: u64) -> Self {
+        self.fail_after = Some(calls);
+        self
+    }
+}
+
+/// The error of a [`FaultInjecting`] backend.
+#[derive(Debug)]
+pub enum FaultError<E> {
+    /// An injected failure of the `call`th call, counted from 1.
+    Injected { call: u64 },
+    /// The wrapped backend failed.
+    Backend(E),
+}
+
+impl<E: Display> Display for FaultError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultError::Injected { call } => write!(f, "Injected failure of call {call}"),
+            FaultError::Backend(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: Error> Error for FaultError<E> {}
+
+#[derive(Debug)]
+struct FaultState {
+    rng: u64,
+    calls: u64,
+    shifts: u64,
+}
+
+impl FaultState {
+    /// A uniformly distributed number in `[0, 1)`, from a SplitMix64 generator.
+    fn next_f64(&mut self) -> f64 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+}
+
+/// Wraps a backend to inject the configured [`Faults`] into its calls.
+///
+/// ```ignore
+/// let faults = Faults::default().seed(7).failure_probability(0.1).truncate_tdo(5, 0);
+/// let server = Server::new(FaultInjecting::new(backend, faults), Config::default());
+/// ```
+#[derive(Debug)]
+pub struct FaultInjecting<T> {
+    inner: T,
+    faults: Faults,
+    state: Mutex<FaultState>,
+}
+
+impl<T> FaultInjecting<T> {
+    pub fn new(inner: T, faults: Faults) -> FaultInjecting<T> {
+        FaultInjecting {
+            inner,
+            state: Mutex::new(FaultState {
+                rng: faults.seed,
+                calls: 0,
+                shifts: 0,
+            }),
+            faults,
+        }
+    }
+
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    /// The number of calls of `set_tck` and `shift` so far, including the failed ones.
+    pub fn calls(&self) -> u64 {
+        self.lock().calls
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Count a call, and fail it if it is due to fail.
+    fn fail<E>(&self, state: &mut FaultState) -> Result<(), FaultError<E>> {
+        state.calls += 1;
+        // Draw for every call, so the random faults don't depend on `fail_after`
+        let random = state.chance(self.faults.failure_probability);
+        if random || self.faults.fail_after.is_some_and(|n| state.calls > n) {
+            return Err(FaultError::Injected { call: state.calls });
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FaultState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T: XvcServer> XvcServer for FaultInjecting<T> {
+    type Err = FaultError<T::Err>;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Self::Err> {
+        self.fail(&mut self.lock())?;
+        let period = self.inner.set_tck(period_ns).map_err(FaultError::Backend)?;
+        let period = i64::from(period).saturating_add(self.faults.tck_offset_ns);
+        Ok(period.clamp(0, i64::from(u32::MAX)) as u32)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Self::Err> {
+        let (delay, truncate) = {
+            let mut state = self.lock();
+            self.fail(&mut state)?;
+            state.shifts += 1;
+            let shifts = state.shifts;
+            (
+                state.chance(self.faults.latency_probability),
+                self.faults
+                    .truncate_every
+                    .is_some_and(|n| n > 0 && shifts.is_multiple_of(n)),
+            )
+        };
+        self.inner
+            .shift(num_bits, tms, tdi, tdo)
+            .map_err(FaultError::Backend)?;
+        if delay {
+            thread::sleep(self.faults.latency);
+        }
+        if truncate && let Some(rest) = tdo.get_mut(self.faults.truncated_tdo_len..) {
+            rest.fill(0);
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        self.inner.stats()
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        self.inner.shift_chunk_len()
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn on_connect(&self) {
+        self.inner.on_connect()
+    }
+
+    fn on_disconnect(&self) {
+        self.inner.on_disconnect()
+    }
+}
+
+/// How a [`ReplayBackend`] matches a call against the recorded message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayMatching {
+    /// A shift matches if its number of bits, TMS and TDI are as recorded.
+    Exact,
+    /// A shift matches if its number of bits is as recorded, e.g. to replay a session whose
+    /// TDI contains data that changes from run to run.
+    BitCount,
+}
+
+/// The error of a [`ReplayBackend`] in strict mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The call does not match the `index`th `settck:` or `shift:` record, counted from 0,
+    /// which is described by `expected`.
+    Mismatch { index: usize, expected: String },
+    /// The call was made after the last record.
+    Exhausted,
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Mismatch { index, expected } => {
+                write!(f, "Call does not match record {index}, expected {expected}")
+            }
+            ReplayError::Exhausted => write!(f, "Call after the last record of the transcript"),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+/// Answers calls with the responses of a recorded [transcript](xvc_protocol::transcript), e.g.
+/// one that a server recorded of a session against real hardware (see
+/// [`Config::transcript_path`](crate::server::Config::transcript_path)), to test a client
+/// against that hardware without it.
+///
+/// Each `set_tck` and `shift` is matched against the next `settck:` or `shift:` record, and
+/// answered with the recorded period or TDO. Other records, e.g. of `getinfo:`, which the server
+/// answers itself, are skipped. A call that does not match is logged, and fails in strict mode
+/// (the default) or is answered with zeros otherwise; either way, the next call is matched
+/// against the next record.
+///
+/// ```ignore
+/// let transcript = std::fs::File::open("session.xvct")?;
+/// let backend = ReplayBackend::new(transcript)?.matching(ReplayMatching::BitCount);
+/// // ... serve the client under test
+/// assert_eq!(backend.remaining(), 0);
+/// ```
+#[derive(Debug)]
+pub struct ReplayBackend {
+    records: Vec<(OwnedMessage, Vec<u8>)>,
+    next: Mutex<usize>,
+    matching: ReplayMatching,
+    strict: bool,
+}
+
+impl ReplayBackend {
+    /// Replay the transcript read from `transcript`.
+    pub fn new(transcript: impl Read) -> Result<ReplayBackend, TranscriptError> {
+        let records = TranscriptReader::new(transcript)?
+            .filter(|record| !matches!(record, Ok((Message::GetInfo | Message::Unknown { .. }, _))))
+            .collect::<Result<_, _>>()?;
+        Ok(ReplayBackend {
+            records,
+            next: Mutex::new(0),
+            matching: ReplayMatching::Exact,
+            strict: true,
+        })
+    }
+
+    /// Set how calls are matched against the records (default: [`ReplayMatching::Exact`]).
+    pub fn matching(mut self, matching: ReplayMatching) -> Self {
+        self.matching = matching;
+        self
+    }
+
+    /// Fail calls that do not match the recording if `strict`, or answer them with zeros.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The number of records that were not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.records.len() - *self.lock()
+    }
+
+    /// Answer a call with `answer` of the next record, which returns `None` if the call does
+    /// not match the record. Returns `Ok(None)` for calls that do not match outside of strict
+    /// mode.
+    fn replay<'a, R>(
+        &'a self,
+        answer: impl FnOnce(&'a OwnedMessage, &'a [u8]) -> Option<R>,
+    ) -> Result<Option<R>, ReplayError> {
+        let mut next = self.lock();
+        let index = *next;
+        let error = match self.records.get(index) {
+            Some((message, response)) => {
+                *next += 1;
+                if let Some(answer) = answer(message, response) {
+                    return Ok(Some(answer));
+                }
+                ReplayError::Mismatch {
+                    index,
+                    expected: message.summary(8).to_string(),
+                }
+            }
+            None => ReplayError::Exhausted,
+        };
+        log::error!("Replay mismatch: {error}");
+        if self.strict { Err(error) } else { Ok(None) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.next
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl XvcServer for ReplayBackend {
+    type Err = ReplayError;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, ReplayError> {
+        let replayed = self.replay(|message, response| match message {
+            Message::SetTck {
+                period_ns: recorded,
+            } if *recorded == period_ns => Some(u32::from_le_bytes(response.try_into().ok()?)),
+            _ => None,
+        })?;
+        Ok(replayed.unwrap_or(period_ns))
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), ReplayError> {
+        let replayed = self.replay(|message, response| match message {
+            Message::Shift {
+                num_bits: recorded,
+                tms: recorded_tms,
+                tdi: recorded_tdi,
+            } if *recorded == num_bits
+                && response.len() == tdo.len()
+                && (self.matching == ReplayMatching::BitCount
+                    || (recorded_tms.as_ref() == tms && recorded_tdi.as_ref() == tdi)) =>
+            {
+                Some(response)
+            }
+            _ => None,
+        })?;
+        match replayed {
+            Some(response) => tdo.copy_from_slice(response),
+            None => tdo.fill(0),
+        }
+        Ok(())
+    }
+}
+
+/// One end of an in-memory connection, see [`memory_pipe`].
+///
+/// The bytes written to one end are read from the other end once the latency of the pipe has
+/// passed since they were written. Shutting down or dropping an end ends the stream of the
+/// other end after the bytes written so far, and writing to an end whose peer was dropped
+/// fails with [`io::ErrorKind::BrokenPipe`].
+pub struct MemoryStream {
+    incoming: mpsc::UnboundedReceiver<(Instant, Bytes)>,
+    outgoing: Option<mpsc::UnboundedSender<(Instant, Bytes)>>,
+    latency: Duration,
+    /// The chunk that is read next, and when it arrives
+    pending: Option<(Instant, Bytes)>,
+    /// Wakes the reader once the pending chunk arrives
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+/// A connection between two in-memory streams that delivers every write after `latency`, e.g.
+/// to test a client against a [`Server`] without sockets, see [`serve_in_memory`]. The pipe
+/// buffers any amount of data, so writes never wait for the reader.
+///
+/// Timing a pipe with latency needs the `time` driver of the tokio runtime.
+pub fn memory_pipe(latency: Duration) -> (MemoryStream, MemoryStream) {
+    let (to_second, from_first) = mpsc::unbounded_channel();
+    let (to_first, from_second) = mpsc::unbounded_channel();
+    let end = |incoming, outgoing| MemoryStream {
+        incoming,
+        outgoing: Some(outgoing),
+        latency,
+        pending: None,
+        delay: None,
+    };
+    (end(from_second, to_second), end(from_first, to_first))
+}
+
+impl AsyncRead for MemoryStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let (arrival, data) = match &mut this.pending {
+            Some(pending) => pending,
+            pending => match ready!(this.incoming.poll_recv(cx)) {
+                Some(chunk) => pending.insert(chunk),
+                // The other end was shut down or dropped
+                None => return Poll::Ready(Ok(())),
+            },
+        };
+        if *arrival > Instant::now() {
+            let delay = this
+                .delay
+                .get_or_insert_with(|| Box::pin(time::sleep_until(*arrival)));
+            delay.as_mut().reset(*arrival);
+            ready!(delay.as_mut().poll(cx));
+        }
+        let len = data.len().min(buf.remaining());
+        buf.put_slice(&data.split_to(len));
+        if data.is_empty() {
+            this.pending = None;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for MemoryStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let arrival = Instant::now() + self.latency;
+        let sent = match &self.outgoing {
+            Some(outgoing) => outgoing
+                .send((arrival, Bytes::copy_from_slice(buf)))
+                .is_ok(),
+            None => false,
+        };
+        if sent {
+            Poll::Ready(Ok(buf.len()))
+        } else {
+            Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.outgoing = None;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl fmt::Debug for MemoryStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStream")
+            .field("latency", &self.latency)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Serve `backend` with `config` on a task of its own over an in-memory [`memory_pipe`] without
+/// latency, and return the end of the client and the task, which ends with the result of
+/// [`Server::handle_stream`] once the client disconnects.
+///
+/// ```ignore
+/// let (stream, served) = serve_in_memory(LoopbackBackend, Config::default());
+/// let mut client = XvcClient::new(stream);
+/// assert_eq!(*client.shift(8, &[0], &[0xa5]).await?, [0xa5]);
+/// drop(client);
+/// served.await??;
+/// ```
+///
+/// Like any server, it needs a multi-thread tokio runtime.
+pub fn serve_in_memory<T>(
+    backend: T,
+    config: Config,
+) -> (MemoryStream, JoinHandle<Result<(), ReadError>>)
+where
+    T: XvcServerMut + Send + 'static,
+{
+    let (client, connection) = memory_pipe(Duration::ZERO);
+    let server = Server::new(backend, config);
+    let served = tokio::spawn(async move { server.handle_stream(connection).await });
+    (client, served)
+}
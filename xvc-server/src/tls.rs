@@ -0,0 +1,122 @@
+//! TLS for client connections, with the `tls` feature.
+//!
+//! XVC itself has neither encryption nor authentication. With [`Config::tls`] set, the server
+//! accepts TCP connections with a TLS handshake and speaks the unchanged protocol on top, so a
+//! server can be reached over untrusted networks. Requiring client certificates additionally
+//! restricts who may drive the JTAG chain.
+//!
+//! ```ignore
+//! use xvc_server::{server::Builder, tls};
+//!
+//! let tls = tls::server_config(cert_chain, key, Some(client_roots))?;
+//! let server = Builder::new().tls(tls).build(my_server)?;
+//! ```
+//!
+//! [`Config::tls`]: crate::server::Config::tls
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use tokio::{net::TcpStream, sync::mpsc, time::timeout};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        RootCertStore, ServerConfig,
+        pki_types::{CertificateDer, PrivateKeyDer},
+        server::WebPkiClientVerifier,
+    },
+    server::TlsStream,
+};
+
+use crate::{server::Listener, stats::Peer};
+
+pub use tokio_rustls::rustls;
+
+/// Server-side TLS settings with the certificate chain `cert_chain` and its private `key`.
+///
+/// If `client_roots` is given, clients have to present a certificate that is signed by one of
+/// these roots; otherwise any client may connect.
+pub fn server_config(
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    client_roots: Option<RootCertStore>,
+) -> Result<Arc<ServerConfig>, rustls::Error> {
+    let builder = ServerConfig::builder();
+    let builder = match client_roots {
+        Some(roots) => {
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .map_err(|e| rustls::Error::General(e.to_string()))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(builder.with_single_cert(cert_chain, key)?))
+}
+
+/// The clients of a TCP listener, or of the [`ProxiedClients`](crate::proxy::ProxiedClients)
+/// of one, that completed the TLS handshake.
+///
+/// Connections are accepted and their handshakes run in background tasks, so a slow or
+/// failing client does not hold up the others. Errors of the listener are passed on, so the
+/// accept loop backs off from them.
+pub(crate) struct TlsClients {
+    handshaken: mpsc::Receiver<io::Result<(TlsStream<TcpStream>, Peer)>>,
+}
+
+impl TlsClients {
+    /// Accepts clients from `listener`, until this is dropped. Handshakes that take longer
+    /// than `handshake_timeout` fail.
+    pub(crate) fn start<L>(
+        mut listener: L,
+        config: Arc<ServerConfig>,
+        handshake_timeout: Duration,
+    ) -> TlsClients
+    where
+        L: Listener<Stream = TcpStream> + Send + 'static,
+    {
+        let (sender, handshaken) = mpsc::channel(1);
+        let acceptor = TlsAcceptor::from(config);
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = tokio::select! {
+                    () = sender.closed() => break,
+                    accepted = listener.next_client() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            if sender.send(Err(e)).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    },
+                };
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match timeout(handshake_timeout, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send(Ok((stream, peer))).await;
+                        }
+                        Ok(Err(e)) => log::warn!("TLS handshake with {} failed: {}", peer, e),
+                        Err(_elapsed) => log::warn!("TLS handshake with {} timed out", peer),
+                    }
+                });
+            }
+        });
+        TlsClients { handshaken }
+    }
+}
+
+impl Listener for TlsClients {
+    type Stream = TlsStream<TcpStream>;
+
+    async fn next_client(&mut self) -> io::Result<(TlsStream<TcpStream>, Peer)> {
+        self.handshaken
+            .recv()
+            .await
+            .unwrap_or_else(|| Err(io::Error::other("TLS acceptor stopped")))
+    }
+
+    fn local_addr(stream: &TlsStream<TcpStream>) -> Option<SocketAddr> {
+        stream.get_ref().0.local_addr().ok()
+    }
+}
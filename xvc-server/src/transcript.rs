@@ -0,0 +1,147 @@
+//! Transcripts of the messages that the clients of a server sent and the answers they received,
+//! see [`Config::transcript_path`](crate::server::Config::transcript_path).
+//!
+//! Each connection is recorded into a file of its own in the
+//! [`xvc_protocol::transcript`] format, named after the time the client connected, the number
+//! of the connection and the address of the client, e.g.
+//! `1760612345678-3-192.168.1.10_50312.xvct`. A file of the same name is never overwritten:
+//! the name gets a suffix instead.
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    mem,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::task::block_in_place;
+use xvc_protocol::{BorrowedMessage, Response, transcript::TranscriptWriter};
+
+use crate::stats::Peer;
+
+/// Records are written in blocks of this size, so the answers to small messages rarely wait
+/// for the file.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// The transcript of a connection, which stops recording after the first error.
+pub(crate) struct TranscriptFile {
+    writer: Option<TranscriptWriter<BufWriter<File>>>,
+    path: PathBuf,
+    /// The encoded response that is recorded next
+    encoded: Vec<u8>,
+}
+
+impl TranscriptFile {
+    /// Start the transcript of the `connection`th connection, from `peer` if its address is
+    /// known, in the directory `dir`. Logs a warning and returns `None` if the file cannot be
+    /// created.
+    pub(crate) fn create(
+        dir: &Path,
+        peer: Option<Peer>,
+        connection: u64,
+    ) -> Option<TranscriptFile> {
+        let connected = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let client = match peer {
+            Some(Peer::Tcp(addr)) => format!("{}_{}", addr.ip(), addr.port()).replace(':', "-"),
+            Some(Peer::Unix(number)) => format!("unix{number}"),
+            None => "stream".to_owned(),
+        };
+        let created = create_new(dir, &format!("{connected}-{connection}-{client}")).and_then(
+            |(file, path)| {
+                let writer = TranscriptWriter::new(BufWriter::with_capacity(BUFFER_SIZE, file))?;
+                Ok((writer, path))
+            },
+        );
+        match created {
+            Ok((writer, path)) => {
+                log::debug!(
+                    "Recording the transcript of the connection to {}",
+                    path.display()
+                );
+                Some(TranscriptFile {
+                    writer: Some(writer),
+                    path,
+                    encoded: Vec::new(),
+                })
+            }
+            Err(e) => {
+                log::warn!(
+                    "Could not create a transcript in {}, not recording the connection: {}",
+                    dir.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Record `message` and the bytes of the `response` it received.
+    pub(crate) fn record(&mut self, message: &BorrowedMessage<'_>, response: &[u8]) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+        if let Err(e) = writer.record(message, response) {
+            self.stop(&e);
+        }
+    }
+
+    /// Record `message` and the `response` it received.
+    pub(crate) fn record_response(&mut self, message: &BorrowedMessage<'_>, response: &Response) {
+        let mut encoded = mem::take(&mut self.encoded);
+        encoded.clear();
+        match response.write_to(&mut encoded) {
+            Ok(()) => self.record(message, &encoded),
+            Err(e) => self.stop(&e),
+        }
+        self.encoded = encoded;
+    }
+
+    /// Record a shift of `num_bits` that was discarded as oversized and answered with zeros.
+    pub(crate) fn record_discarded_shift(&mut self, num_bits: u32) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+        if let Err(e) = writer.record_discarded_shift(num_bits) {
+            self.stop(&e);
+        }
+    }
+
+    fn stop(&mut self, e: &io::Error) {
+        log::warn!(
+            "Could not write the transcript {}, no longer recording the connection: {}",
+            self.path.display(),
+            e
+        );
+        self.writer = None;
+    }
+}
+
+impl Drop for TranscriptFile {
+    fn drop(&mut self) {
+        // Dropped at the end of a connection, on a worker thread of the runtime
+        if let Some(writer) = &mut self.writer
+            && let Err(e) = block_in_place(|| writer.flush())
+        {
+            self.stop(&e);
+        }
+    }
+}
+
+/// Create a file named `name` in `dir` that did not exist before, with a numbered suffix if
+/// the name is taken.
+fn create_new(dir: &Path, name: &str) -> io::Result<(File, PathBuf)> {
+    let mut path = dir.join(format!("{name}.xvct"));
+    let mut suffix = 1;
+    loop {
+        match File::create_new(&path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && suffix < 100 => {
+                suffix += 1;
+                path = dir.join(format!("{name}-{suffix}.xvct"));
+            }
+            created => return created.map(|file| (file, path)),
+        }
+    }
+}
@@ -1,9 +1,14 @@
-use std::{convert::Infallible, net::SocketAddr};
+use std::{
+    convert::Infallible,
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    time::Duration,
+};
 
 use tokio::net::TcpListener;
 use tokio_util::sync::CancellationToken;
 use xvc_server::{
-    XvcServer,
+    XvcServer, XvcServerMut,
     server::{Config, Server},
 };
 
@@ -32,10 +37,18 @@ impl XvcServer for StubBackend {
 /// the address and a cancellation token. Drop or cancel the token to shut the
 /// server down cleanly.
 pub async fn spawn_server(config: Config) -> (SocketAddr, CancellationToken) {
+    spawn_server_with(StubBackend, config).await
+}
+
+/// Like [`spawn_server`], but serving `backend` instead of the [`StubBackend`].
+pub async fn spawn_server_with<T: XvcServerMut + Send + 'static>(
+    backend: T,
+    config: Config,
+) -> (SocketAddr, CancellationToken) {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
     let token = CancellationToken::new();
-    let server = Server::new(StubBackend, config);
+    let server = Server::new(backend, config);
     tokio::spawn({
         let token = token.clone();
         async move {
@@ -44,3 +57,50 @@ pub async fn spawn_server(config: Config) -> (SocketAddr, CancellationToken) {
     });
     (addr, token)
 }
+
+/// Sends the raw bytes of `request` over a new connection and returns everything the server
+/// sends until it closes the connection or stays silent for half a second. Returns once the
+/// server closed the connection.
+pub async fn exchange(addr: SocketAddr, request: &'static [u8]) -> Vec<u8> {
+    tokio::task::spawn_blocking(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        stream.write_all(request).unwrap();
+        let mut response = Vec::new();
+        let mut chunk = [0; 64];
+        while let Ok(n @ 1..) = stream.read(&mut chunk) {
+            response.extend_from_slice(&chunk[..n]);
+        }
+        // Wait until the server is done with the connection, so that it accepts the next one
+        if stream.shutdown(Shutdown::Write).is_ok() {
+            while let Ok(1..) = stream.read(&mut chunk) {}
+        }
+        response
+    })
+    .await
+    .unwrap()
+}
+
+/// Whether `stream` stays silent and open for a while.
+pub fn is_waiting(stream: &mut TcpStream) -> bool {
+    stream
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    matches!(
+        stream.read(&mut [0]).map_err(|e| e.kind()),
+        Err(ErrorKind::WouldBlock | ErrorKind::TimedOut)
+    )
+}
+
+/// Wait until `condition` holds, e.g. once the server handled a disconnect.
+pub async fn eventually(condition: impl Fn() -> bool) {
+    for attempt in 1..=20 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5 * attempt)).await;
+    }
+    panic!("condition not met");
+}
@@ -0,0 +1,41 @@
+//! Exhausts the file descriptors of the process, so it runs on its own.
+#![cfg(unix)]
+
+use std::{fs::File, net::TcpStream};
+
+use xvc_server::server::{Config, Server};
+use xvc_tests::StubBackend;
+
+/// The error of running out of file descriptors.
+const EMFILE: i32 = 24;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn listener_gives_up_after_max_accept_errors() {
+    let config = Config {
+        max_accept_errors: Some(3),
+        ..Config::default()
+    };
+    let server = Server::new(StubBackend, config);
+    let bound = server.bind("127.0.0.1:0").await.unwrap();
+    let addr = bound.local_addr();
+
+    // Take every file descriptor but one, which the client takes, so the server cannot accept
+    let mut files = Vec::new();
+    loop {
+        match File::open("/dev/null") {
+            Ok(file) => files.push(file),
+            Err(e) if e.raw_os_error() == Some(EMFILE) => break,
+            Err(e) => panic!("{e}"),
+        }
+    }
+    files.pop();
+    let _client = TcpStream::connect(addr).unwrap();
+    let error = bound.serve().await.unwrap_err();
+    drop(files);
+
+    assert_eq!(error.raw_os_error(), Some(EMFILE));
+    let stats = server.stats();
+    assert_eq!(stats.accept_errors, 3);
+    assert_eq!(stats.consecutive_accept_errors, 3);
+    assert_eq!(stats.connections, 0);
+}
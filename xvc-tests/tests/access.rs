@@ -0,0 +1,122 @@
+use std::net::IpAddr;
+
+use xvc_client::XvcClient;
+use xvc_server::{
+    access::IpNetwork,
+    server::{Builder, Config},
+};
+use xvc_tests::{StubBackend, spawn_server};
+
+fn networks(networks: &[&str]) -> Vec<IpNetwork> {
+    networks
+        .iter()
+        .map(|network| network.parse().unwrap())
+        .collect()
+}
+
+fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+#[test]
+fn networks_are_parsed_from_addresses_and_cidr_ranges() {
+    let network: IpNetwork = "192.168.1.0/24".parse().unwrap();
+    assert_eq!(network.addr(), ip("192.168.1.0"));
+    assert_eq!(network.prefix_len(), 24);
+    assert_eq!(network.to_string(), "192.168.1.0/24");
+
+    let host: IpNetwork = "::1".parse().unwrap();
+    assert_eq!(host.prefix_len(), 128);
+    assert_eq!(host.to_string(), "::1");
+
+    for invalid in [
+        "",
+        "localhost",
+        "10.0.0.0/33",
+        "fd00::/129",
+        "10.0.0.0/",
+        "/8",
+    ] {
+        assert!(invalid.parse::<IpNetwork>().is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn networks_contain_addresses_of_their_family() {
+    let [v4, v6, any_v4, any_v6] = networks(&["10.0.0.0/8", "fd00::/8", "0.0.0.0/0", "::/0"])
+        .try_into()
+        .unwrap();
+    assert!(v4.contains(ip("10.20.30.40")));
+    assert!(!v4.contains(ip("11.0.0.1")));
+    assert!(v6.contains(ip("fd12:3456::1")));
+    assert!(!v6.contains(ip("fe80::1")));
+    assert!(any_v4.contains(ip("203.0.113.7")));
+    assert!(!any_v4.contains(ip("::1")));
+    assert!(any_v6.contains(ip("2001:db8::1")));
+    // IPv4 clients of dual-stack sockets
+    assert!(v4.contains(ip("::ffff:10.0.0.1")));
+    assert!(!any_v6.contains(ip("::ffff:10.0.0.1")));
+}
+
+#[test]
+fn denied_peers_take_precedence_over_allowed_peers() {
+    let config = Config {
+        allowed_peers: networks(&["192.168.0.0/16", "2001:db8::/32"]),
+        denied_peers: networks(&["192.168.1.13", "2001:db8:bad::/48", "10.0.0.0/8"]),
+        ..Config::default()
+    };
+    assert!(config.allows(ip("192.168.2.1")));
+    assert!(config.allows(ip("2001:db8:1::1")));
+    assert!(!config.allows(ip("192.168.1.13")));
+    assert!(!config.allows(ip("2001:db8:bad::1")));
+    assert!(!config.allows(ip("172.16.0.1")));
+    assert!(!config.allows(ip("::1")));
+}
+
+#[test]
+fn empty_allow_list_allows_all_peers() {
+    let config = Builder::new()
+        .deny_peers(networks(&["fe80::/10", "203.0.113.0/24"]))
+        .build(StubBackend)
+        .unwrap()
+        .config();
+    assert!(config.allows(ip("127.0.0.1")));
+    assert!(config.allows(ip("::1")));
+    assert!(!config.allows(ip("203.0.113.99")));
+    assert!(!config.allows(ip("fe80::1")));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_outside_the_allowed_peers_is_closed() {
+    let config = Config {
+        allowed_peers: networks(&["10.0.0.0/8", "::1"]),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    assert!(client.get_info().await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn denied_client_is_closed() {
+    let config = Config {
+        allowed_peers: networks(&["127.0.0.0/8"]),
+        denied_peers: networks(&["127.0.0.1"]),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    assert!(client.get_info().await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn allowed_client_is_served() {
+    let config = Config {
+        allowed_peers: networks(&["fd00::/8", "127.0.0.0/8"]),
+        denied_peers: networks(&["127.0.0.2"]),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    client.get_info().await.unwrap();
+}
@@ -0,0 +1,73 @@
+use std::convert::Infallible;
+
+use xvc_client::XvcClient;
+use xvc_protocol::VectorLen;
+use xvc_server::{
+    BackendCapabilities, XvcServer,
+    server::{Config, Server},
+};
+use xvc_tests::{exchange, spawn_server_with};
+
+/// Returns TDI as TDO, and shifts at most two bytes at once.
+struct SmallBackend;
+
+impl XvcServer for SmallBackend {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(&self, _: u32, _: &[u8], tdi: &[u8], tdo: &mut [u8]) -> Result<(), Infallible> {
+        assert!(tdi.len() <= 2, "the backend was given {} bytes", tdi.len());
+        tdo.copy_from_slice(tdi);
+        Ok(())
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            max_vector_size: Some(VectorLen::from_bytes(2)),
+            min_tck_period_ns: Some(10),
+            max_tck_period_ns: Some(1000),
+            description: Some("small test backend".into()),
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_info_announces_the_limit_of_the_backend() {
+    let (addr, _token) = spawn_server_with(SmallBackend, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let info = client.get_info().await.unwrap();
+    assert_eq!(info.max_vector_len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_info_announces_a_lower_configured_limit() {
+    let config = Config {
+        max_vector_size: VectorLen::from_bytes(1),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server_with(SmallBackend, config).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let info = client.get_info().await.unwrap();
+    assert_eq!(info.max_vector_len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shifts_beyond_the_limit_of_the_backend_are_discarded() {
+    let (addr, _token) = spawn_server_with(SmallBackend, Config::default()).await;
+    let response = exchange(addr, b"shift:\x10\x00\x00\x00\x00\x00\xAB\xCD").await;
+    assert_eq!(response, [0xAB, 0xCD]);
+    // 17 bits need 3 bytes per vector, so the shift is discarded and answered with zeros
+    let response = exchange(addr, b"shift:\x11\x00\x00\x00\x00\x00\x00\xAB\xCD\x01").await;
+    assert_eq!(response, [0, 0, 0]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_reports_the_capabilities_of_its_backend() {
+    let server = Server::new(SmallBackend, Config::default());
+    assert_eq!(*server.backend_capabilities(), SmallBackend.capabilities());
+    // The configuration is kept as given
+    assert_eq!(server.config().max_vector_size, 10 * 1024 * 1024);
+}
@@ -0,0 +1,104 @@
+use std::{
+    convert::Infallible,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use xvc_client::XvcClient;
+use xvc_server::{
+    XvcServer, XvcServerMut,
+    server::{Config, Server},
+};
+
+/// Returns TDI as TDO and counts the shifts.
+#[derive(Default)]
+struct Counting {
+    shifts: AtomicU32,
+}
+
+impl XvcServer for Counting {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(&self, _: u32, _: &[u8], tdi: &[u8], tdo: &mut [u8]) -> Result<(), Infallible> {
+        self.shifts.fetch_add(1, Ordering::Relaxed);
+        tdo.copy_from_slice(tdi);
+        Ok(())
+    }
+}
+
+/// Like [`Counting`], but with exclusive access to its counter.
+#[derive(Default)]
+struct CountingMut {
+    shifts: u32,
+}
+
+impl XvcServerMut for CountingMut {
+    type Err = Infallible;
+
+    fn set_tck(&mut self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(&mut self, _: u32, _: &[u8], tdi: &[u8], tdo: &mut [u8]) -> Result<(), Infallible> {
+        self.shifts += 1;
+        tdo.copy_from_slice(tdi);
+        Ok(())
+    }
+}
+
+/// Serves a client over an in-memory pipe that shifts a byte.
+async fn shift_once<T: XvcServerMut + Send + 'static>(server: &Server<T>) {
+    let (client, connection) = tokio::io::duplex(4096);
+    let client = async move {
+        let mut client = XvcClient::new(client);
+        let tdo = client.shift(8, &[0], &[0xA5]).await.unwrap();
+        assert_eq!(*tdo, [0xA5]);
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn servers_share_a_backend_through_an_arc() {
+    let backend = Arc::new(Counting::default());
+    let first = Server::new(Arc::clone(&backend), Config::default());
+    let second = Server::new(Arc::clone(&backend), Config::default());
+    shift_once(&first).await;
+    shift_once(&second).await;
+    assert_eq!(backend.shifts.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn servers_share_a_backend_through_a_reference() {
+    static BACKEND: Counting = Counting {
+        shifts: AtomicU32::new(0),
+    };
+    let first = Server::new(&BACKEND, Config::default());
+    let second = Server::new(&BACKEND, Config::default());
+    shift_once(&first).await;
+    shift_once(&second).await;
+    assert_eq!(BACKEND.shifts.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn boxed_backend_is_served() {
+    let backend: Box<dyn XvcServer<Err = Infallible> + Send> = Box::new(Counting::default());
+    let server = Server::new(backend, Config::default());
+    shift_once(&server).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn servers_share_a_mutable_backend_through_a_mutex() {
+    let backend = Arc::new(Mutex::new(CountingMut::default()));
+    let first = Server::new(Arc::clone(&backend), Config::default());
+    let second = Server::new(Arc::clone(&backend), Config::default());
+    shift_once(&first).await;
+    shift_once(&second).await;
+    assert_eq!(backend.lock().unwrap().shifts, 2);
+}
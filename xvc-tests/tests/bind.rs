@@ -0,0 +1,24 @@
+use tokio_util::sync::CancellationToken;
+use xvc_client::XvcClient;
+use xvc_server::server::{Config, Server};
+use xvc_tests::StubBackend;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn bound_server_reports_the_assigned_port() {
+    let server = Server::new(StubBackend, Config::default());
+    let bound = server.bind("127.0.0.1:0").await.unwrap();
+    let addr = bound.local_addr();
+    assert_ne!(addr.port(), 0);
+
+    let token = CancellationToken::new();
+    let client = async {
+        let mut client = XvcClient::connect(addr).await.unwrap();
+        let info = client.get_info().await;
+        drop(client);
+        token.cancel();
+        info
+    };
+    let (served, info) = tokio::join!(bound.serve_until(token.clone()), client);
+    served.unwrap();
+    info.unwrap();
+}
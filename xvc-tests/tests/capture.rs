@@ -0,0 +1,68 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::net::TcpStream;
+use xvc_client::XvcClient;
+use xvc_protocol::capture::{CaptureReader, CaptureSink, Direction, TeeReader, TeeWriter};
+use xvc_server::server::Config;
+use xvc_tests::spawn_server;
+
+/// A capture in memory that can be inspected while it is written
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuf {
+    /// The captured bytes of each direction, concatenated across records
+    fn bytes(&self, direction: Direction) -> Vec<u8> {
+        let capture = self.0.lock().unwrap();
+        CaptureReader::new(capture.as_slice())
+            .map(Result::unwrap)
+            .filter(|record| record.direction == direction)
+            .flat_map(|record| record.data)
+            .collect()
+    }
+}
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_and_client_capture_the_same_bytes() {
+    let server_capture = SharedBuf::default();
+    let config = Config {
+        capture: Some(CaptureSink::new(server_capture.clone())),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+
+    let client_capture = SharedBuf::default();
+    let sink = CaptureSink::new(client_capture.clone());
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let mut client = XvcClient::new(TeeReader::new(TeeWriter::new(tcp, sink.clone()), sink));
+    client.get_info().await.unwrap();
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+
+    let requests = b"getinfo:settck:\x64\x00\x00\x00";
+    let responses = b"xvcServer_v1.0:10485760\n\x64\x00\x00\x00";
+    assert_eq!(client_capture.bytes(Direction::Written), requests);
+    assert_eq!(client_capture.bytes(Direction::Read), responses);
+    assert_eq!(server_capture.bytes(Direction::Read), requests);
+    // The server records its response only after it was sent
+    for _ in 0..100 {
+        if server_capture.bytes(Direction::Written) == responses {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(server_capture.bytes(Direction::Written), responses);
+}
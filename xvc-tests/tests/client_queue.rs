@@ -0,0 +1,109 @@
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+use xvc_client::XvcClient;
+use xvc_server::server::{ClientPolicy, Config};
+use xvc_tests::{is_waiting, spawn_server};
+
+const GET_INFO_RESPONSE: &[u8] = b"xvcServer_v1.0:10485760\n";
+const SET_TCK: &[u8] = b"settck:\x64\x00\x00\x00";
+
+fn queue_config(max_waiting: usize, hold_timeout: Duration) -> Config {
+    Config {
+        client_policy: ClientPolicy::Queue {
+            max_waiting,
+            hold_timeout,
+        },
+        ..Config::default()
+    }
+}
+
+/// Connects and waits for the answer to `getinfo:`, so the client is queued once this returns.
+async fn connect_waiting(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"getinfo:").unwrap();
+    let (stream, response) = read(stream, GET_INFO_RESPONSE.len()).await;
+    assert_eq!(response, GET_INFO_RESPONSE);
+    stream
+}
+
+/// Reads `len` bytes from `stream`, or fewer if the server closes the connection.
+async fn read(mut stream: TcpStream, len: usize) -> (TcpStream, Vec<u8>) {
+    tokio::task::spawn_blocking(move || {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut response = Vec::new();
+        (&mut stream)
+            .take(len as u64)
+            .read_to_end(&mut response)
+            .unwrap();
+        (stream, response)
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn waiting_client_is_served_once_the_active_one_disconnects() {
+    let (addr, _token) = spawn_server(queue_config(1, Duration::from_secs(30))).await;
+    let mut client_a = XvcClient::connect(addr).await.unwrap();
+    client_a.get_info().await.unwrap();
+
+    // The waiting client is answered `getinfo:`, but nothing else
+    let mut waiting = connect_waiting(addr).await;
+    waiting.write_all(SET_TCK).unwrap();
+    assert!(is_waiting(&mut waiting));
+
+    drop(client_a);
+    let (_, response) = read(waiting, 4).await;
+    assert_eq!(response, SET_TCK[7..]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connections_beyond_max_waiting_are_rejected() {
+    let (addr, _token) = spawn_server(queue_config(1, Duration::from_secs(30))).await;
+    let mut client_a = XvcClient::connect(addr).await.unwrap();
+    client_a.get_info().await.unwrap();
+    let _waiting = connect_waiting(addr).await;
+
+    let mut client_c = XvcClient::connect(addr).await.unwrap();
+    assert!(client_c.get_info().await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn waiting_clients_are_served_in_order() {
+    let (addr, _token) = spawn_server(queue_config(2, Duration::from_secs(30))).await;
+    let mut client_a = XvcClient::connect(addr).await.unwrap();
+    client_a.get_info().await.unwrap();
+    let mut first = connect_waiting(addr).await;
+    let mut second = connect_waiting(addr).await;
+    first.write_all(SET_TCK).unwrap();
+    second.write_all(SET_TCK).unwrap();
+
+    drop(client_a);
+    let (first, response) = read(first, 4).await;
+    assert_eq!(response, SET_TCK[7..]);
+    assert!(is_waiting(&mut second));
+
+    drop(first);
+    let (_, response) = read(second, 4).await;
+    assert_eq!(response, SET_TCK[7..]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn abandoned_waiting_client_is_closed_after_the_hold_timeout() {
+    let (addr, _token) = spawn_server(queue_config(1, Duration::from_millis(200))).await;
+    let mut client_a = XvcClient::connect(addr).await.unwrap();
+    client_a.get_info().await.unwrap();
+
+    let waiting = connect_waiting(addr).await;
+    let (_, response) = read(waiting, 1).await;
+    assert!(response.is_empty());
+
+    // The queue has room again
+    let _waiting = connect_waiting(addr).await;
+}
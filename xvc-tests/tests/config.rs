@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use xvc_server::server::{Builder, Config, ConfigError, Pool, ShiftErrorPolicy};
+use xvc_tests::StubBackend;
+
+#[test]
+fn config_is_read_from_variables() {
+    let config = Config::from_vars([
+        ("XVC_MAX_VECTOR_SIZE", "4096"),
+        ("XVC_RW_TIMEOUT_MS", "2000"),
+        ("XVC_MESSAGE_TIMEOUT_MS", "500"),
+        ("XVC_TCP_NODELAY", "0"),
+        ("XVC_TCK_PERIOD_RANGE_NS", "10-1000"),
+        ("XVC_SHIFT_ERROR_POLICY", "zero-fill"),
+        ("XVC_ALLOWED_PEERS", "10.0.0.0/8, 192.0.2.1"),
+        ("XVC_CRC", "true"),
+        ("XVC_SLOW_OP_WARN_MS", ""),
+        ("PATH", "/usr/bin"),
+    ])
+    .unwrap();
+    assert_eq!(config.max_vector_size.as_bytes(), 4096);
+    assert_eq!(config.idle_timeout, Some(Duration::from_secs(2)));
+    assert_eq!(config.message_timeout, Duration::from_millis(500));
+    assert!(!config.tcp_nodelay);
+    assert_eq!(config.tck_period_range, Some(10..=1000));
+    assert_eq!(config.shift_error_policy, ShiftErrorPolicy::ZeroFill);
+    assert_eq!(config.allowed_peers.len(), 2);
+    assert!(config.crc);
+    assert_eq!(config.slow_op_warn_threshold, None);
+    assert!(config.exclusive_client);
+}
+
+#[test]
+fn idle_timeout_can_be_disabled() {
+    let config = Config::from_vars([("XVC_IDLE_TIMEOUT_MS", "none")]).unwrap();
+    assert_eq!(config.idle_timeout, None);
+    assert_eq!(config.message_timeout, Config::default().message_timeout);
+}
+
+#[test]
+fn invalid_variable_is_named() {
+    let error = Config::from_vars([("XVC_TCP_NODELAY", "yes")]).unwrap_err();
+    assert_eq!(
+        error,
+        ConfigError::InvalidVariable {
+            name: "XVC_TCP_NODELAY".to_owned(),
+            value: "yes".to_owned(),
+            reason: "expected true or false".to_owned(),
+        }
+    );
+    assert!(error.to_string().contains("XVC_TCP_NODELAY"));
+
+    for (name, value) in [
+        ("XVC_MAX_VECTOR_SIZE", "0"),
+        ("XVC_MAX_VECTOR_SIZE", "1 MiB"),
+        ("XVC_RW_TIMEOUT_MS", "0"),
+        ("XVC_TCK_PERIOD_RANGE_NS", "1000-10"),
+        ("XVC_DENIED_PEERS", "10.0.0.0/33"),
+    ] {
+        match Config::from_vars([(name, value)]) {
+            Err(ConfigError::InvalidVariable { name: invalid, .. }) => assert_eq!(invalid, name),
+            other => panic!("{name}={value} was not rejected: {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn build_rejects_configs_that_cannot_serve() {
+    let invalid = [
+        (
+            Config {
+                max_vector_size: 0.into(),
+                ..Config::default()
+            },
+            ConfigError::ZeroMaxVectorSize,
+        ),
+        (
+            Config {
+                message_timeout: Duration::ZERO,
+                ..Config::default()
+            },
+            ConfigError::ZeroTimeout("message_timeout"),
+        ),
+        (
+            Config {
+                idle_timeout: Some(Duration::ZERO),
+                ..Config::default()
+            },
+            ConfigError::ZeroTimeout("idle_timeout"),
+        ),
+        (
+            Config {
+                #[allow(clippy::reversed_empty_ranges)]
+                tck_period_range: Some(100..=10),
+                ..Config::default()
+            },
+            ConfigError::EmptyTckRange {
+                min_ns: 100,
+                max_ns: 10,
+            },
+        ),
+        (
+            Config {
+                exclusive_client: false,
+                max_clients: 0,
+                ..Config::default()
+            },
+            ConfigError::NoClients,
+        ),
+        (
+            Config {
+                pool: Some(Pool {
+                    workers: 0,
+                    ..Pool::default()
+                }),
+                ..Config::default()
+            },
+            ConfigError::EmptyPool,
+        ),
+    ];
+    for (config, expected) in invalid {
+        assert_eq!(config.validate(), Err(expected.clone()));
+        assert_eq!(
+            Builder::from(config).build(StubBackend).err(),
+            Some(expected)
+        );
+    }
+    assert!(Builder::new().build(StubBackend).is_ok());
+}
@@ -0,0 +1,54 @@
+use std::convert::Infallible;
+
+use xvc_protocol::Message;
+use xvc_server::{
+    XvcServer,
+    conformance::{Loopback, MAX_VECTOR_SIZE, run_conformance, vectors},
+};
+
+/// Runs TCK at half the requested frequency, like hardware with a coarse clock divider, and
+/// returns zeroed TDO.
+struct CoarseClock;
+
+impl XvcServer for CoarseClock {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns * 2)
+    }
+
+    fn shift(&self, _: u32, _: &[u8], _: &[u8], _: &mut [u8]) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+#[test]
+fn vectors_decode_to_their_messages() {
+    for vector in vectors() {
+        let decoded = Message::from_bytes(&vector.request, MAX_VECTOR_SIZE);
+        match vector.message {
+            Some(message) => {
+                assert_eq!(
+                    decoded.unwrap(),
+                    (message, vector.request.len()),
+                    "{}",
+                    vector.description
+                );
+            }
+            None => assert!(decoded.is_err(), "{}", vector.description),
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn loopback_matches_the_golden_responses() {
+    let report = run_conformance(Loopback).await;
+    assert!(report.is_pass(), "{report}");
+    assert_eq!(report.passed, vectors().len());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backend_dependent_responses_are_compared_in_length() {
+    let report = run_conformance(CoarseClock).await;
+    assert!(report.is_pass(), "{report}");
+}
@@ -0,0 +1,96 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+};
+
+use xvc_client::{Builder, XvcClient};
+use xvc_protocol::{VectorLen, crc, error::ReadError};
+use xvc_server::{server::Config, test_util::LoopbackBackend};
+use xvc_tests::{exchange, spawn_server, spawn_server_with};
+
+fn crc_config() -> Config {
+    Config {
+        max_vector_size: VectorLen::from_bytes(1024),
+        crc: true,
+        ..Config::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shifts_are_protected_if_both_sides_enable_crc() {
+    let (addr, _token) = spawn_server_with(LoopbackBackend, crc_config()).await;
+    let mut client = Builder::new().crc(true).connect(addr).await.unwrap();
+    assert!(!client.crc_enabled());
+
+    let tdi: Vec<u8> = (0..100).collect();
+    let tdo = client.shift(800, &[0; 100], &tdi).await.unwrap();
+    assert_eq!(*tdo, *tdi);
+    assert!(client.crc_enabled());
+
+    let tdo = client
+        .shift_chunks(800, [[0; 100]], tdi.chunks(30))
+        .await
+        .unwrap();
+    assert_eq!(*tdo, *tdi);
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn crc_client_falls_back_to_plain_server() {
+    let (addr, _token) = spawn_server_with(LoopbackBackend, Config::default()).await;
+    let mut client = Builder::new().crc(true).connect(addr).await.unwrap();
+    assert!(client.get_info().await.unwrap().capabilities().is_empty());
+    assert!(!client.crc_enabled());
+    let tdo = client.shift(8, &[0x00], &[0xA5]).await.unwrap();
+    assert_eq!(*tdo, [0xA5]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn plain_client_is_unaffected_by_crc_server() {
+    let (addr, _token) = spawn_server_with(LoopbackBackend, crc_config()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let info = client.get_info().await.unwrap();
+    assert_eq!(info.capabilities(), [crc::CAPABILITY]);
+    let tdo = client.shift(8, &[0x00], &[0xA5]).await.unwrap();
+    assert_eq!(*tdo, [0xA5]);
+    assert!(!client.crc_enabled());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn corrupted_shift_closes_the_connection() {
+    let config = Config {
+        resync_on_error: true,
+        ..crc_config()
+    };
+    let (addr, _token) = spawn_server(config).await;
+    let response = exchange(
+        addr,
+        b"getinfo:crc:shift:\x08\x00\x00\x00\xAA\xBB\x00\x00\x00\x00getinfo:",
+    )
+    .await;
+    // Neither the shift nor the message after it are answered
+    assert_eq!(response, b"xvcServer_v1.0:1024:crc\n");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn corrupted_tdo_is_reported() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 8];
+        stream.read_exact(&mut request).unwrap();
+        stream.write_all(b"xvcServer_v1.0:64:crc\n").unwrap();
+        let mut request = [0u8; 4 + 10 + 2 + crc::CRC_LEN];
+        stream.read_exact(&mut request).unwrap();
+        assert_eq!(&request[..4], crc::CMD_ENABLE);
+        stream.write_all(&[0xAB]).unwrap();
+        stream
+            .write_all(&crc::crc32(&[0xAC]).to_le_bytes())
+            .unwrap();
+    });
+    let mut client = Builder::new().crc(true).connect(addr).await.unwrap();
+    let result = client.shift(8, &[0x00], &[0xFF]).await;
+    assert!(matches!(result, Err(ReadError::CrcMismatch { .. })));
+    server.join().unwrap();
+}
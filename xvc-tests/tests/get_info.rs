@@ -1,5 +1,10 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+};
+
 use xvc_client::XvcClient;
-use xvc_protocol::Version;
+use xvc_protocol::{VectorLen, Version};
 use xvc_server::server::Config;
 use xvc_tests::spawn_server;
 
@@ -14,7 +19,7 @@ async fn get_info_returns_v1_0() {
 #[tokio::test(flavor = "multi_thread")]
 async fn get_info_max_vector_len_matches_config() {
     let config = Config {
-        max_vector_size: 1024,
+        max_vector_size: VectorLen::from_bytes(1024),
         ..Config::default()
     };
     let (addr, _token) = spawn_server(config).await;
@@ -23,6 +28,18 @@ async fn get_info_max_vector_len_matches_config() {
     assert_eq!(info.max_vector_len(), 1024);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn get_info_passes_capabilities_through() {
+    let config = Config {
+        capabilities: vec!["lock".into(), "x-vendor=1".into()],
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let info = client.get_info().await.unwrap();
+    assert_eq!(info.capabilities(), ["lock", "x-vendor=1"]);
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn get_info_can_be_called_multiple_times() {
     let (addr, _token) = spawn_server(Config::default()).await;
@@ -31,3 +48,22 @@ async fn get_info_can_be_called_multiple_times() {
         client.get_info().await.unwrap();
     }
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_info_returns_reported_version() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 8];
+        stream.read_exact(&mut request).unwrap();
+        stream.write_all(b"xvcServer_v1.1:64 lock\n").unwrap();
+    });
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let info = client.get_info().await.unwrap();
+    assert_eq!(info.version(), Version::new(1, 1));
+    assert!(info.version().is_supported());
+    assert_eq!(info.max_vector_len(), 64);
+    assert_eq!(info.capabilities(), ["lock"]);
+    server.join().unwrap();
+}
@@ -0,0 +1,19 @@
+use xvc_client::XvcClient;
+use xvc_server::server::{Config, Server};
+use xvc_tests::StubBackend;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_is_served_over_an_in_memory_pipe() {
+    let server = Server::new(StubBackend, Config::default());
+    let (client, connection) = tokio::io::duplex(4096);
+
+    let client = async move {
+        let mut client = XvcClient::new(client);
+        client.get_info().await.unwrap();
+        assert_eq!(client.set_tck(100).await.unwrap(), 100);
+        assert_eq!(*client.shift(8, &[0], &[0xff]).await.unwrap(), [0]);
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+    assert_eq!(server.stats().shifts, 1);
+}
@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use xvc_client::XvcClient;
+use xvc_protocol::{VectorLen, error::ReadError};
+use xvc_server::{
+    server::{Config, Server},
+    test_util::{LoopbackBackend, memory_pipe, serve_in_memory},
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_is_served_in_memory() {
+    let (stream, served) = serve_in_memory(LoopbackBackend, Config::default());
+    let mut client = XvcClient::new(stream);
+    let info = client.get_info().await.unwrap();
+    assert_eq!(info.max_vector_len(), Config::default().max_vector_size);
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    assert_eq!(*client.shift(8, &[0], &[0xa5]).await.unwrap(), [0xa5]);
+    let len = 100_000;
+    let tdi: Vec<u8> = (0..len).map(|i| i as u8).collect();
+    let tdo = client
+        .shift(8 * len as u32, &vec![0; len], &tdi)
+        .await
+        .unwrap();
+    assert_eq!(*tdo, *tdi);
+
+    drop(client);
+    served.await.unwrap().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oversized_shift_is_answered_with_zeros_in_memory() {
+    let config = Config {
+        max_vector_size: VectorLen::from_bytes(16),
+        ..Config::default()
+    };
+    let (stream, served) = serve_in_memory(LoopbackBackend, config);
+    let mut client = XvcClient::new(stream);
+    let tdo = client.shift(8 * 32, &[0; 32], &[0xff; 32]).await.unwrap();
+    assert_eq!(*tdo, [0; 32]);
+    // The connection is still in sync
+    assert_eq!(*client.shift(8, &[0], &[0xff]).await.unwrap(), [0xff]);
+
+    drop(client);
+    served.await.unwrap().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_that_disconnects_within_a_message_is_reported() {
+    let (mut stream, served) = serve_in_memory(LoopbackBackend, Config::default());
+    stream
+        .write_all(b"shift:\x10\x00\x00\x00\x00")
+        .await
+        .unwrap();
+    stream.shutdown().await.unwrap();
+    match served.await.unwrap() {
+        Err(ReadError::TruncatedMessage { .. }) => {}
+        other => panic!("expected a truncated shift, got {other:?}"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_that_closes_the_connection_ends_the_stream() {
+    let (mut stream, served) = serve_in_memory(LoopbackBackend, Config::default());
+    // Not a command, so the server closes the connection
+    stream.write_all(b"lock:").await.unwrap();
+    assert!(served.await.unwrap().is_err());
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+    let written = stream.write_all(b"getinfo:").await;
+    assert_eq!(written.unwrap_err().kind(), std::io::ErrorKind::BrokenPipe);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pipe_delivers_writes_after_its_latency() {
+    let latency = Duration::from_millis(20);
+    let (stream, connection) = memory_pipe(latency);
+    let server = Server::new(LoopbackBackend, Config::default());
+    let client = async move {
+        let mut client = XvcClient::new(stream);
+        let start = Instant::now();
+        assert_eq!(*client.shift(8, &[0], &[0x3c]).await.unwrap(), [0x3c]);
+        // There and back again
+        assert!(start.elapsed() >= 2 * latency);
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+}
@@ -0,0 +1,91 @@
+use std::net::SocketAddr;
+
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use xvc_client::XvcClient;
+use xvc_server::{
+    server::{BindPolicy, Builder},
+    test_util::ConstantBackend,
+};
+
+fn any_port() -> SocketAddr {
+    "127.0.0.1:0".parse().unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_of_all_addresses_are_served_by_the_same_server() {
+    let server = Builder::new()
+        .exclusive_client(false)
+        .max_clients(2)
+        .build(ConstantBackend::new([0x5A]))
+        .unwrap();
+    let bound = server.bind_all([any_port(), any_port()]).await.unwrap();
+    let addrs = bound.local_addrs().to_vec();
+    assert_eq!(addrs.len(), 2);
+    assert_ne!(addrs[0], addrs[1]);
+    assert_eq!(bound.local_addr(), addrs[0]);
+
+    let token = CancellationToken::new();
+    let clients = async {
+        for addr in &addrs {
+            let mut client = XvcClient::connect(*addr).await.unwrap();
+            assert_eq!(*client.shift(8, &[0], &[0]).await.unwrap(), [0x5A]);
+        }
+        assert_eq!(server.stats().shifts, 2);
+        token.cancel();
+    };
+    let (served, ()) = tokio::join!(bound.serve_until(token.clone()), clients);
+    served.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ipv4_and_ipv6_listen_on_the_same_port() {
+    let Ok(v6) = TcpListener::bind("[::1]:0").await else {
+        // No IPv6 on this host
+        return;
+    };
+    let port = v6.local_addr().unwrap().port();
+    drop(v6);
+    let server = Builder::new().build(ConstantBackend::default()).unwrap();
+    let addrs: [SocketAddr; 2] = [
+        format!("127.0.0.1:{port}").parse().unwrap(),
+        format!("[::1]:{port}").parse().unwrap(),
+    ];
+    let Ok(bound) = server.bind_all(addrs).await else {
+        // The port was taken in the meantime
+        return;
+    };
+    assert_eq!(bound.local_addrs(), addrs);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fail_fast_fails_if_an_address_is_taken() {
+    let taken = TcpListener::bind(any_port()).await.unwrap();
+    let server = Builder::new().build(ConstantBackend::default()).unwrap();
+    let error = server
+        .bind_all([any_port(), taken.local_addr().unwrap()])
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn best_effort_serves_the_addresses_that_could_be_bound() {
+    let taken = TcpListener::bind(any_port()).await.unwrap();
+    let server = Builder::new()
+        .bind_policy(BindPolicy::BestEffort)
+        .build(ConstantBackend::default())
+        .unwrap();
+    let bound = server
+        .bind_all([taken.local_addr().unwrap(), any_port()])
+        .await
+        .unwrap();
+    assert_eq!(bound.local_addrs().len(), 1);
+    assert_ne!(bound.local_addr(), taken.local_addr().unwrap());
+
+    let error = server
+        .bind_all([taken.local_addr().unwrap()])
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+}
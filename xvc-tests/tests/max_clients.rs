@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use xvc_client::XvcClient;
+use xvc_server::server::Config;
+use xvc_tests::{exchange, spawn_server};
+
+fn config(max_clients: usize) -> Config {
+    Config {
+        exclusive_client: false,
+        max_clients,
+        ..Config::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_up_to_the_limit_are_served_concurrently() {
+    let (addr, _token) = spawn_server(config(2)).await;
+
+    let mut client_a = XvcClient::connect(addr).await.unwrap();
+    client_a.get_info().await.unwrap();
+    let mut client_b = XvcClient::connect(addr).await.unwrap();
+    client_b.get_info().await.unwrap();
+
+    // A third client exceeds the limit and its connection is closed
+    let mut client_c = XvcClient::connect(addr).await.unwrap();
+    assert!(client_c.get_info().await.is_err());
+
+    // Interleaved requests of the served clients are answered
+    assert_eq!(*client_a.shift(8, &[0], &[0xA5]).await.unwrap(), [0]);
+    assert_eq!(client_b.set_tck(100).await.unwrap(), 100);
+    client_a.get_info().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn disconnecting_frees_a_slot() {
+    let (addr, _token) = spawn_server(config(2)).await;
+    let mut client_a = XvcClient::connect(addr).await.unwrap();
+    client_a.get_info().await.unwrap();
+
+    // A client that sends garbage is disconnected without affecting the others
+    assert!(exchange(addr, b"bogus:").await.is_empty());
+
+    for attempt in 1..=10 {
+        let mut client_b = XvcClient::connect(addr).await.unwrap();
+        if client_b.get_info().await.is_ok() {
+            client_a.get_info().await.unwrap();
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10 * attempt)).await;
+    }
+    panic!("server did not release the slot of the disconnected client");
+}
@@ -0,0 +1,122 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::sleep,
+};
+use tokio_util::sync::{CancellationToken, DropGuard};
+use xvc_client::XvcClient;
+use xvc_server::server::{Config, Server};
+use xvc_tests::{StubBackend, exchange};
+
+async fn spawn_metered() -> (Arc<Server<StubBackend>>, SocketAddr, DropGuard) {
+    let server = Arc::new(Server::new(StubBackend, Config::default()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let token = CancellationToken::new();
+    tokio::spawn({
+        let server = Arc::clone(&server);
+        let token = token.clone();
+        async move { server.listen_on(listener, token).await.unwrap() }
+    });
+    (server, addr, token.drop_guard())
+}
+
+/// Waits until `metrics` contains `line`, as connections are counted by the server task.
+async fn wait_for_line(server: &Server<StubBackend>, line: &str) -> String {
+    for _ in 0..100 {
+        let metrics = server.metrics();
+        if metrics.lines().any(|l| l == line) {
+            return metrics;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    panic!("metrics do not contain {line:?}:\n{}", server.metrics());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn metrics_count_connections_and_shifts() {
+    let (server, addr, _guard) = spawn_metered().await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    client.shift(16, &[0, 0], &[0xff, 0xff]).await.unwrap();
+    client.shift(8, &[0], &[0xff]).await.unwrap();
+
+    let metrics = wait_for_line(&server, "xvc_clients 1").await;
+    for line in [
+        "xvc_connections_total 1",
+        "xvc_shifts_total 2",
+        "xvc_bits_shifted_total 24",
+        "xvc_tck_period_nanoseconds 100",
+        "xvc_shift_duration_seconds_bucket{le=\"+Inf\"} 2",
+        "xvc_shift_duration_seconds_count 2",
+        "# TYPE xvc_shift_duration_seconds histogram",
+    ] {
+        assert!(
+            metrics.lines().any(|l| l == line),
+            "{line:?} in:\n{metrics}"
+        );
+    }
+
+    drop(client);
+    wait_for_line(&server, "xvc_clients 0").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn metrics_count_protocol_errors_by_kind() {
+    let (server, addr, _guard) = spawn_metered().await;
+
+    assert!(exchange(addr, b"lock:getinfo:").await.is_empty());
+    let metrics = wait_for_line(
+        &server,
+        "xvc_protocol_errors_total{kind=\"invalid_command\"} 1",
+    )
+    .await;
+    assert!(
+        metrics
+            .lines()
+            .any(|l| l == "xvc_protocol_errors_total{kind=\"crc_mismatch\"} 0")
+    );
+}
+
+async fn get(addr: SocketAddr, request: &str) -> String {
+    let mut stream = loop {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => break stream,
+            // The metrics listener may not be bound yet
+            Err(_) => sleep(Duration::from_millis(10)).await,
+        }
+    };
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn metrics_are_served_over_http() {
+    let (server, _addr, _guard) = spawn_metered().await;
+    // Reserve a free port for the metrics listener
+    let metrics_addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    tokio::spawn({
+        let server = Arc::clone(&server);
+        async move { server.listen_metrics(metrics_addr).await }
+    });
+
+    let response = get(metrics_addr, "GET /metrics HTTP/1.1\r\nHost: xvc\r\n\r\n").await;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{head}");
+    assert!(head.contains("Content-Type: text/plain; version=0.0.4"));
+    assert!(body.lines().any(|l| l == "xvc_connections_total 0"));
+
+    let response = get(metrics_addr, "GET / HTTP/1.1\r\n\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    let response = get(metrics_addr, "POST /metrics HTTP/1.1\r\n\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+}
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use tokio::{io::AsyncReadExt, net::TcpListener, net::TcpStream};
+use tokio_util::sync::CancellationToken;
+use xvc_client::XvcClient;
+use xvc_protocol::capture::{CaptureReader, Direction, Record};
+use xvc_server::{
+    mirror::Mirror,
+    server::{Builder, Config},
+    test_util::LoopbackBackend,
+};
+use xvc_tests::{eventually, spawn_server_with};
+
+/// Serve observers of `mirror` on a port of their own, and connect one.
+async fn observe(mirror: &Mirror, token: &CancellationToken) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn({
+        let (mirror, token) = (mirror.clone(), token.clone());
+        async move { mirror.listen_on(listener, token).await }
+    });
+    let observer = TcpStream::connect(addr).await.unwrap();
+    eventually(|| mirror.observers() == 1).await;
+    observer
+}
+
+/// Read records from `observer` until their payloads in each direction add up to `read` and
+/// `written`.
+async fn records(observer: &mut TcpStream, read: &[u8], written: &[u8]) -> Vec<Record> {
+    let mut received = Vec::new();
+    loop {
+        let records: Vec<Record> = CaptureReader::new(received.as_slice())
+            .filter_map(Result::ok)
+            .collect();
+        let payload = |direction| -> Vec<u8> {
+            (records.iter())
+                .filter(|record| record.direction == direction)
+                .flat_map(|record| record.data.iter().copied())
+                .collect()
+        };
+        if payload(Direction::Read) == read && payload(Direction::Written) == written {
+            return records;
+        }
+        let n = tokio::time::timeout(Duration::from_secs(5), observer.read_buf(&mut received))
+            .await
+            .expect("observer did not receive the traffic")
+            .unwrap();
+        assert_ne!(n, 0, "observer was disconnected");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn observer_receives_the_traffic_of_the_client() {
+    let mirror = Mirror::new(64);
+    let token = CancellationToken::new();
+    let mut observer = observe(&mirror, &token).await;
+    let config = Config {
+        mirror: Some(mirror.clone()),
+        ..Config::default()
+    };
+    let (addr, _server_token) = spawn_server_with(LoopbackBackend, config).await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    client.set_tck(100).await.unwrap();
+    client.shift(8, &[0x00], &[0x5A]).await.unwrap();
+    let records = records(
+        &mut observer,
+        b"settck:\x64\x00\x00\x00shift:\x08\x00\x00\x00\x00\x5A",
+        b"\x64\x00\x00\x00\x5A",
+    )
+    .await;
+    assert!(records.iter().all(|record| record.timestamp_us > 0));
+    token.cancel();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_observer_is_disconnected() {
+    let mirror = Mirror::new(2);
+    let token = CancellationToken::new();
+    // Never reads what it is sent
+    let _observer = observe(&mirror, &token).await;
+    let server = Builder::new()
+        .mirror(mirror.clone())
+        .build(LoopbackBackend)
+        .unwrap();
+
+    let (stream, connection) = tokio::io::duplex(64 * 1024);
+    let client = async {
+        let mut client = XvcClient::new(stream);
+        let tdi = vec![0xA5; 1024 * 1024];
+        for _ in 0..16 {
+            let tdo = client.shift(8 * 1024 * 1024, &tdi, &tdi).await.unwrap();
+            assert_eq!(*tdo, *tdi);
+        }
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+    assert_eq!(mirror.observers(), 0);
+    token.cancel();
+}
@@ -0,0 +1,64 @@
+use std::net::SocketAddr;
+
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use xvc_client::XvcClient;
+use xvc_protocol::VectorLen;
+use xvc_server::{multi::MultiServer, server::Config, test_util::ConstantBackend};
+
+fn any_port() -> SocketAddr {
+    "127.0.0.1:0".parse().unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn endpoints_serve_their_own_backend() {
+    let server = MultiServer::new(Config::default())
+        .endpoint(any_port(), ConstantBackend::new([0x11]))
+        .endpoint_with(any_port(), ConstantBackend::new([0x22]), |config| Config {
+            max_vector_size: VectorLen::from_bytes(64),
+            ..config
+        });
+    let bound = server.bind().await.unwrap();
+    let addrs = bound.local_addrs().unwrap();
+    let token = CancellationToken::new();
+    let clients = async {
+        let mut first = XvcClient::connect(addrs[0]).await.unwrap();
+        let mut second = XvcClient::connect(addrs[1]).await.unwrap();
+        assert_eq!(*first.shift(8, &[0], &[0]).await.unwrap(), [0x11]);
+        assert_eq!(*second.shift(8, &[0], &[0]).await.unwrap(), [0x22]);
+        assert_eq!(second.get_info().await.unwrap().max_vector_len(), 64);
+        assert_eq!(
+            first.get_info().await.unwrap().max_vector_len(),
+            10 * 1024 * 1024
+        );
+
+        let stats = server.stats();
+        assert_eq!(stats.len(), 2);
+        assert!(stats.iter().all(|(_, stats)| stats.shifts == 1));
+        token.cancel();
+    };
+    let (served, ()) = tokio::join!(bound.serve_until(token.clone()), clients);
+    served.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn endpoints_with_an_invalid_config_are_not_bound() {
+    let server = MultiServer::new(Config::default())
+        .endpoint(any_port(), ConstantBackend::default())
+        .endpoint_with(any_port(), ConstantBackend::default(), |config| Config {
+            max_vector_size: VectorLen::from_bytes(0),
+            ..config
+        });
+    let error = server.bind().await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn no_endpoint_is_served_if_one_cannot_be_bound() {
+    let taken = TcpListener::bind(any_port()).await.unwrap();
+    let server = MultiServer::new(Config::default())
+        .endpoint(any_port(), ConstantBackend::default())
+        .endpoint(taken.local_addr().unwrap(), ConstantBackend::default());
+    let error = server.listen().await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+}
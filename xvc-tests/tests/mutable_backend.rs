@@ -0,0 +1,73 @@
+use std::convert::Infallible;
+
+use xvc_client::XvcClient;
+use xvc_server::{
+    XvcServerMut,
+    server::{Config, Server},
+};
+use xvc_tests::spawn_server_with;
+
+/// A one-bit shift register between TDI and TDO, whose state is kept in plain fields.
+#[derive(Default)]
+struct ShiftRegister {
+    last_tdi: bool,
+    cycles: u64,
+}
+
+impl XvcServerMut for ShiftRegister {
+    type Err = Infallible;
+
+    fn set_tck(&mut self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(
+        &mut self,
+        num_bits: u32,
+        _tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Infallible> {
+        for bit in 0..num_bits as usize {
+            let mask = 1 << (bit % 8);
+            if self.last_tdi {
+                tdo[bit / 8] |= mask;
+            }
+            self.last_tdi = tdi[bit / 8] & mask != 0;
+        }
+        self.cycles += u64::from(num_bits);
+        Ok(())
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        vec![("cycles", self.cycles)]
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backend_state_is_kept_across_shifts() {
+    let server = Server::new(ShiftRegister::default(), Config::default());
+    let (client, connection) = tokio::io::duplex(4096);
+
+    let client = async move {
+        let mut client = XvcClient::new(client);
+        assert_eq!(
+            *client.shift(8, &[0], &[0b1000_0001]).await.unwrap(),
+            [0b0000_0010]
+        );
+        // The last TDI bit of the previous shift comes out first
+        assert_eq!(*client.shift(4, &[0], &[0b0000]).await.unwrap(), [0b0001]);
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+    assert_eq!(server.backend_stats(), [("cycles", 12)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mutable_backend_is_served_over_tcp() {
+    let (addr, _token) = spawn_server_with(ShiftRegister::default(), Config::default()).await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    assert_eq!(*client.shift(8, &[0], &[0xff]).await.unwrap(), [0xfe]);
+}
@@ -0,0 +1,193 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::sleep,
+};
+use tokio_util::sync::{CancellationToken, DropGuard};
+use xvc_client::XvcClient;
+use xvc_protocol::{BorrowedMessage, Message};
+use xvc_server::{
+    observer::{ObserverResult, ServerObserver},
+    server::{Builder, Server},
+    stats::{ConnectionStats, Peer},
+};
+use xvc_tests::StubBackend;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Event {
+    Connect,
+    Message(String),
+    /// The bytes written and the messages of the connection so far
+    Response(usize, u64),
+    Disconnect(ConnectionStats),
+}
+
+/// Records the events of all connections.
+#[derive(Default)]
+struct Recorder {
+    events: Mutex<Vec<(Peer, Event)>>,
+}
+
+impl Recorder {
+    fn record(&self, peer: Peer, event: Event) -> ObserverResult {
+        self.events.lock().unwrap().push((peer, event));
+        Ok(())
+    }
+
+    /// Waits until a client disconnected and returns the events so far.
+    async fn events_until_disconnect(&self) -> Vec<(Peer, Event)> {
+        loop {
+            let events = self.events.lock().unwrap().clone();
+            if matches!(events.last(), Some((_, Event::Disconnect(_)))) {
+                return events;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+impl ServerObserver for Recorder {
+    fn on_connect(&self, peer: Peer) -> ObserverResult {
+        self.record(peer, Event::Connect)
+    }
+
+    fn on_message(&self, peer: Peer, message: &BorrowedMessage<'_>) -> ObserverResult {
+        self.record(peer, Event::Message(message.summary(1).to_string()))
+    }
+
+    fn on_response(
+        &self,
+        peer: Peer,
+        bytes_written: usize,
+        stats: &ConnectionStats,
+    ) -> ObserverResult {
+        self.record(peer, Event::Response(bytes_written, stats.messages))
+    }
+
+    fn on_disconnect(&self, peer: Peer, stats: &ConnectionStats) -> ObserverResult {
+        let stats = ConnectionStats {
+            duration: Duration::ZERO,
+            ..stats.clone()
+        };
+        self.record(peer, Event::Disconnect(stats))
+    }
+}
+
+/// An observer whose callbacks fail or panic.
+struct Broken;
+
+impl ServerObserver for Broken {
+    fn on_connect(&self, _peer: Peer) -> ObserverResult {
+        Err("accounting database is down".into())
+    }
+
+    fn on_message(&self, _peer: Peer, message: &BorrowedMessage<'_>) -> ObserverResult {
+        if let Message::Shift { .. } = message {
+            panic!("observer bug");
+        }
+        Ok(())
+    }
+
+    fn on_response(
+        &self,
+        _peer: Peer,
+        _bytes_written: usize,
+        _stats: &ConnectionStats,
+    ) -> ObserverResult {
+        Err("accounting database is down".into())
+    }
+}
+
+async fn spawn_observed(server: Server<StubBackend>) -> (SocketAddr, DropGuard) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let token = CancellationToken::new();
+    tokio::spawn({
+        let token = token.clone();
+        async move { server.listen_on(listener, token).await.unwrap() }
+    });
+    (addr, token.drop_guard())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn observer_sees_the_events_of_a_connection() {
+    let recorder = Arc::new(Recorder::default());
+    let server = Builder::new()
+        .observer(Arc::clone(&recorder) as Arc<dyn ServerObserver>)
+        .build(StubBackend)
+        .unwrap();
+    let (addr, _guard) = spawn_observed(server).await;
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let peer = Peer::Tcp(tcp.local_addr().unwrap());
+    let mut client = XvcClient::new(tcp);
+    client.get_info().await.unwrap();
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    client.shift(16, &[0, 0], &[0xff, 0xff]).await.unwrap();
+    drop(client);
+    let info_len = b"xvcServer_v1.0:10485760\n".len();
+
+    let events = recorder.events_until_disconnect().await;
+    let expected = [
+        Event::Connect,
+        Event::Message("getinfo:".to_owned()),
+        Event::Response(info_len, 1),
+        Event::Message("settck: period_ns=100".to_owned()),
+        Event::Response(4, 2),
+        Event::Message("shift: num_bits=16 len=2 tms=0000 tdi=ffff".to_owned()),
+        Event::Response(2, 3),
+        Event::Disconnect(ConnectionStats {
+            connection: 1,
+            duration: Duration::ZERO,
+            messages: 3,
+            shifts: 1,
+            bits_shifted: 16,
+            bytes_written: info_len as u64 + 4 + 2,
+            throttled: Duration::ZERO,
+        }),
+    ];
+    assert_eq!(events, expected.map(|event| (peer, event)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failing_observer_does_not_affect_the_connection() {
+    let server = Builder::new()
+        .observer(Arc::new(Broken))
+        .build(StubBackend)
+        .unwrap();
+    let (addr, _guard) = spawn_observed(server).await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    client.get_info().await.unwrap();
+    assert_eq!(*client.shift(8, &[0], &[0xff]).await.unwrap(), [0]);
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn observer_is_told_the_number_of_each_connection() {
+    let recorder = Arc::new(Recorder::default());
+    let server = Builder::new()
+        .observer(Arc::clone(&recorder) as Arc<dyn ServerObserver>)
+        .build(StubBackend)
+        .unwrap();
+    let (addr, _guard) = spawn_observed(server).await;
+
+    let mut connections = Vec::new();
+    for _ in 0..2 {
+        let mut client = XvcClient::connect(addr).await.unwrap();
+        client.get_info().await.unwrap();
+        drop(client);
+        let events = recorder.events_until_disconnect().await;
+        recorder.events.lock().unwrap().clear();
+        let Some((_, Event::Disconnect(stats))) = events.last() else {
+            unreachable!();
+        };
+        connections.push(stats.connection);
+    }
+    assert_eq!(connections, [1, 2]);
+}
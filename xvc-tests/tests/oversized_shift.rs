@@ -0,0 +1,80 @@
+use tokio::io::AsyncWriteExt;
+use xvc_client::{Builder, XvcClient};
+use xvc_protocol::{VectorLen, error::ReadError};
+use xvc_server::server::{Config, Server, ShiftErrorPolicy};
+use xvc_tests::{StubBackend, exchange, spawn_server};
+
+fn small_config() -> Config {
+    Config {
+        max_vector_size: VectorLen::from_bytes(1024),
+        ..Config::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oversized_shift_is_discarded_and_answered_with_zeros() {
+    let (addr, _token) = spawn_server(small_config()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    // Longer than the server buffers at once while it discards the shift
+    let len = 100_000;
+    let tdo = client
+        .shift(8 * len as u32, &vec![0; len], &vec![0xff; len])
+        .await
+        .unwrap();
+    assert_eq!(*tdo, *vec![0; len]);
+    // The connection is still in sync
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    let tdo = client.shift(8, &[0], &[0xff]).await.unwrap();
+    assert_eq!(*tdo, [0]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oversized_shift_is_answered_with_a_valid_crc() {
+    let config = Config {
+        crc: true,
+        ..small_config()
+    };
+    let (addr, _token) = spawn_server(config).await;
+    let mut client = Builder::new().crc(true).connect(addr).await.unwrap();
+    let tdo = client
+        .shift(8 * 2000, &[0; 2000], &[0; 2000])
+        .await
+        .unwrap();
+    assert_eq!(*tdo, [0; 2000]);
+    assert!(client.crc_enabled());
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oversized_shift_closes_the_connection_if_configured() {
+    let config = Config {
+        max_vector_size: VectorLen::from_bytes(2),
+        shift_error_policy: ShiftErrorPolicy::CloseConnection,
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+    let response = exchange(
+        addr,
+        b"shift:\x11\x00\x00\x00\x00\x00\x00\xAB\xCD\x01getinfo:",
+    )
+    .await;
+    assert!(response.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_that_disconnects_within_an_oversized_shift_is_reported() {
+    let server = Server::new(StubBackend, small_config());
+    let (mut client, connection) = tokio::io::duplex(4096);
+    let client = async move {
+        // A shift of 2000 bytes per vector, of which only 100 bytes arrive
+        client.write_all(b"shift:\x80\x3e\x00\x00").await.unwrap();
+        client.write_all(&[0; 100]).await.unwrap();
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    match served {
+        Err(ReadError::TruncatedMessage { read, expected, .. }) => {
+            assert_eq!((read, expected), (110, 4010));
+        }
+        other => panic!("expected a truncated shift, got {other:?}"),
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    convert::Infallible,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+use xvc_client::XvcClient;
+use xvc_server::{
+    XvcServer,
+    server::{Config, Pool, QueueFull},
+};
+use xvc_tests::{is_waiting, spawn_server, spawn_server_with};
+
+const GET_INFO_RESPONSE: &[u8] = b"xvcServer_v1.0:10485760\n";
+
+fn pool_config(when_full: QueueFull) -> Config {
+    Config {
+        exclusive_client: false,
+        pool: Some(Pool {
+            workers: 1,
+            pending_queue: 1,
+            when_full,
+        }),
+        ..Config::default()
+    }
+}
+
+/// Connects and sends `getinfo:` without waiting for the answer.
+fn request_info(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"getinfo:").unwrap();
+    stream
+}
+
+/// Waits for the answer to `getinfo:` on `stream`.
+async fn read_info(mut stream: TcpStream) -> Vec<u8> {
+    tokio::task::spawn_blocking(move || {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut response = vec![0; GET_INFO_RESPONSE.len()];
+        stream.read_exact(&mut response).unwrap();
+        response
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connections_beyond_the_queue_are_closed() {
+    let (addr, _token) = spawn_server(pool_config(QueueFull::Close)).await;
+    let mut client_a = XvcClient::connect(addr).await.unwrap();
+    client_a.get_info().await.unwrap();
+
+    // The single worker is busy, so the second client waits in the queue
+    let mut queued = request_info(addr);
+    assert!(is_waiting(&mut queued));
+
+    // The queue is full, so the third client is closed right away
+    let mut client_c = XvcClient::connect(addr).await.unwrap();
+    assert!(client_c.get_info().await.is_err());
+
+    drop(client_a);
+    assert_eq!(read_info(queued).await, GET_INFO_RESPONSE);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connections_beyond_the_queue_wait_with_backpressure() {
+    let (addr, _token) = spawn_server(pool_config(QueueFull::Wait)).await;
+    let mut client_a = XvcClient::connect(addr).await.unwrap();
+    client_a.get_info().await.unwrap();
+
+    let mut queued = request_info(addr);
+    let mut backlogged = request_info(addr);
+    assert!(is_waiting(&mut queued));
+    assert!(is_waiting(&mut backlogged));
+
+    drop(client_a);
+    // The queued client stays connected, so the worker stays busy
+    let answer = read_info(queued.try_clone().unwrap()).await;
+    assert_eq!(answer, GET_INFO_RESPONSE);
+    // The queued client is served now, and the backlogged one moved into the queue
+    assert!(is_waiting(&mut backlogged));
+}
+
+/// Panics on every shift.
+struct Faulty;
+
+impl XvcServer for Faulty {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(&self, _: u32, _: &[u8], _: &[u8], _: &mut [u8]) -> Result<(), Infallible> {
+        panic!("backend failure");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn worker_is_restarted_after_a_panic() {
+    let (addr, _token) = spawn_server_with(Faulty, pool_config(QueueFull::Close)).await;
+    for _ in 0..3 {
+        let mut client = XvcClient::connect(addr).await.unwrap();
+        client.get_info().await.unwrap();
+        assert!(client.shift(8, &[0], &[0]).await.is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    net::{Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, duplex},
+    net::{TcpListener, TcpStream},
+};
+use tokio_util::sync::{CancellationToken, DropGuard};
+use xvc_client::XvcClient;
+use xvc_server::{
+    proxy,
+    server::{Config, Server},
+    stats::Peer,
+};
+use xvc_tests::{StubBackend, spawn_server};
+
+/// A version 2 header that proxies a TCP connection from `[2001:db8::7]:40000`, followed by
+/// a TLV that the server skips.
+fn v2_header() -> Vec<u8> {
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    header.extend([0x21, 0x21, 0, 36 + 4]);
+    header.extend(
+        "2001:db8::7"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    header.extend(Ipv6Addr::LOCALHOST.octets());
+    header.extend(40000u16.to_be_bytes());
+    header.extend(2542u16.to_be_bytes());
+    header.extend([0x04, 0, 1, 0]);
+    header
+}
+
+/// Connect to `addr` and send `header` before the XVC messages of the returned client.
+async fn connect_with(addr: SocketAddr, header: &[u8]) -> XvcClient<TcpStream> {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(header).await.unwrap();
+    XvcClient::new(stream)
+}
+
+/// A server that expects PROXY protocol headers, returned to check its status.
+async fn spawn_proxied_server(config: Config) -> (SocketAddr, Arc<Server<StubBackend>>, DropGuard) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Arc::new(Server::new(
+        StubBackend,
+        Config {
+            expect_proxy_protocol: true,
+            ..config
+        },
+    ));
+    let token = CancellationToken::new();
+    tokio::spawn({
+        let (server, token) = (Arc::clone(&server), token.clone());
+        async move { server.listen_on(listener, token).await.unwrap() }
+    });
+    (addr, server, token.drop_guard())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn v1_header_conveys_the_client_address() {
+    let (addr, server, _guard) = spawn_proxied_server(Config::default()).await;
+
+    let mut client = connect_with(addr, b"PROXY TCP4 192.0.2.7 127.0.0.1 40000 2542\r\n").await;
+    client.get_info().await.unwrap();
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    let client_addr = "192.0.2.7:40000".parse().unwrap();
+    assert_eq!(server.status().client, Some(Peer::Tcp(client_addr)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn v2_header_conveys_the_client_address() {
+    let (addr, server, _guard) = spawn_proxied_server(Config::default()).await;
+
+    let mut client = connect_with(addr, &v2_header()).await;
+    client.get_info().await.unwrap();
+    let client_addr = "[2001:db8::7]:40000".parse().unwrap();
+    assert_eq!(server.status().client, Some(Peer::Tcp(client_addr)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn conveyed_address_is_checked_against_the_allowed_peers() {
+    let config = Config {
+        allowed_peers: vec!["2001:db8::/32".parse().unwrap()],
+        ..Config::default()
+    };
+    let (addr, _server, _guard) = spawn_proxied_server(config).await;
+
+    let header = b"PROXY TCP4 127.0.0.1 127.0.0.1 40000 2542\r\n";
+    let mut rejected = connect_with(addr, header).await;
+    assert!(rejected.get_info().await.is_err());
+    drop(rejected);
+
+    // The load balancer itself is not allowed, but the client it proxies is
+    let mut client = connect_with(addr, &v2_header()).await;
+    client.get_info().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connection_without_a_valid_header_is_closed() {
+    let (addr, _server, _guard) = spawn_proxied_server(Config::default()).await;
+
+    for header in [
+        &b""[..],
+        b"PROXY TCP4 not-an-address 127.0.0.1 40000 2542\r\n",
+        b"PROXY TCP6 192.0.2.7 127.0.0.1 40000 2542\r\n",
+        b"\r\n\r\n\0\r\nQUIT\n\x31\x11\0\0",
+    ] {
+        let mut client = connect_with(addr, header).await;
+        assert!(client.get_info().await.is_err(), "{header:?}");
+    }
+
+    let mut client = connect_with(addr, b"PROXY UNKNOWN\r\n").await;
+    client.get_info().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn proxy_protocol_is_off_by_default() {
+    let (addr, _token) = spawn_server(Config::default()).await;
+    let mut client = connect_with(addr, b"PROXY TCP4 192.0.2.7 127.0.0.1 40000 2542\r\n").await;
+    assert!(client.get_info().await.is_err());
+}
+
+#[tokio::test]
+async fn header_is_read_without_the_bytes_that_follow() {
+    let (mut proxy, mut server) = duplex(256);
+    let mut sent = v2_header();
+    sent.extend(b"getinfo:");
+    proxy.write_all(&sent).await.unwrap();
+
+    let source = proxy::read_header(&mut server).await.unwrap();
+    assert_eq!(source, Some("[2001:db8::7]:40000".parse().unwrap()));
+    let mut rest = [0; 8];
+    server.read_exact(&mut rest).await.unwrap();
+    assert_eq!(&rest, b"getinfo:");
+}
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use tokio::io::DuplexStream;
+use xvc_client::XvcClient;
+use xvc_server::server::{Builder, Config, Server};
+use xvc_tests::{StubBackend, spawn_server};
+
+/// Serves `client` over an in-memory pipe until it returns, and returns how long that took.
+async fn serve<F>(
+    server: &Server<StubBackend>,
+    client: impl FnOnce(XvcClient<DuplexStream>) -> F,
+) -> Duration
+where
+    F: Future<Output = ()>,
+{
+    let (stream, connection) = tokio::io::duplex(64 * 1024);
+    let start = Instant::now();
+    let (served, ()) = tokio::join!(
+        server.handle_stream(connection),
+        client(XvcClient::new(stream))
+    );
+    served.unwrap();
+    start.elapsed()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shifts_beyond_the_byte_rate_are_throttled() {
+    let server = Builder::new()
+        .max_shift_bytes_per_sec(100_000)
+        .build(StubBackend)
+        .unwrap();
+    // A second's budget is served right away, the other 100 000 bytes take a second
+    let elapsed = serve(&server, |mut client| async move {
+        for _ in 0..4 {
+            let tdo = client
+                .shift(8 * 50_000, &[0; 50_000], &[0; 50_000])
+                .await
+                .unwrap();
+            assert_eq!(tdo.len(), 50_000);
+        }
+    })
+    .await;
+    assert!(elapsed >= Duration::from_millis(800), "{elapsed:?}");
+    assert!(server.stats().throttled_time >= Duration::from_millis(800));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn messages_beyond_the_message_rate_are_throttled() {
+    let server = Builder::new()
+        .max_messages_per_sec(100)
+        .build(StubBackend)
+        .unwrap();
+    let elapsed = serve(&server, |mut client| async move {
+        for _ in 0..150 {
+            assert_eq!(client.set_tck(100).await.unwrap(), 100);
+        }
+    })
+    .await;
+    assert!(elapsed >= Duration::from_millis(400), "{elapsed:?}");
+    assert!(server.stats().throttled_time >= Duration::from_millis(400));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connections_within_their_budget_are_not_throttled() {
+    let server = Builder::new()
+        .max_shift_bytes_per_sec(1_000_000)
+        .max_messages_per_sec(1_000)
+        .build(StubBackend)
+        .unwrap();
+    serve(&server, |mut client| async move {
+        client.get_info().await.unwrap();
+        client
+            .shift(8 * 1000, &[0; 1000], &[0; 1000])
+            .await
+            .unwrap();
+    })
+    .await;
+    assert_eq!(server.stats().throttled_time, Duration::ZERO);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn each_connection_has_a_budget_of_its_own() {
+    // The first connection may not be closed yet when the second one is accepted
+    let config = Config {
+        max_messages_per_sec: Some(50),
+        exclusive_client: false,
+        max_clients: 2,
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+    for _ in 0..2 {
+        let mut client = XvcClient::connect(addr).await.unwrap();
+        let start = Instant::now();
+        for _ in 0..40 {
+            client.set_tck(100).await.unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+}
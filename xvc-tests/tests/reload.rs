@@ -0,0 +1,83 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use xvc_client::XvcClient;
+use xvc_protocol::VectorLen;
+use xvc_server::{
+    decorators::Switchable,
+    server::{Config, ConfigError, Server},
+    test_util::ConstantBackend,
+};
+use xvc_tests::{StubBackend, spawn_server_with};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn switched_backend_serves_the_next_shift() {
+    let backend = Switchable::new(ConstantBackend::new([0xAA]));
+    let handle = backend.clone();
+    let (addr, _token) = spawn_server_with(backend, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+
+    assert_eq!(*client.shift(8, &[0], &[0]).await.unwrap(), [0xAA]);
+    let previous = handle.switch(ConstantBackend::new([0x55]));
+    assert_eq!(previous.pattern(), [0xAA]);
+    assert_eq!(*client.shift(8, &[0], &[0]).await.unwrap(), [0x55]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn updated_config_applies_to_new_connections() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let token = CancellationToken::new();
+    let server = Arc::new(Server::new(StubBackend, Config::default()));
+    tokio::spawn({
+        let server = Arc::clone(&server);
+        let token = token.clone();
+        async move { server.listen_on(listener, token).await }
+    });
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let default_len = Config::default().max_vector_size;
+    assert_eq!(
+        client.get_info().await.unwrap().max_vector_len(),
+        default_len
+    );
+
+    server
+        .update_config(Config {
+            max_vector_size: VectorLen::from_bytes(1024),
+            ..Config::default()
+        })
+        .unwrap();
+    assert_eq!(server.config().max_vector_size, 1024);
+    // The connected client keeps its configuration
+    assert_eq!(
+        client.get_info().await.unwrap().max_vector_len(),
+        default_len
+    );
+
+    drop(client);
+    while server.status().client.is_some() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    assert_eq!(client.get_info().await.unwrap().max_vector_len(), 1024);
+    token.cancel();
+}
+
+#[test]
+fn invalid_config_is_not_applied() {
+    let server = Server::new(StubBackend, Config::default());
+    let invalid = Config {
+        max_vector_size: VectorLen::from_bytes(0),
+        ..Config::default()
+    };
+    assert_eq!(
+        server.update_config(invalid),
+        Err(ConfigError::ZeroMaxVectorSize)
+    );
+    assert_eq!(
+        server.config().max_vector_size,
+        Config::default().max_vector_size
+    );
+}
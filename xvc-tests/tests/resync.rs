@@ -0,0 +1,57 @@
+use xvc_server::server::Config;
+use xvc_tests::{exchange, spawn_server};
+
+const INFO: &[u8] = b"xvcServer_v1.0:10485760\n";
+
+fn resync_config() -> Config {
+    Config {
+        resync_on_error: true,
+        ..Config::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn garbage_is_skipped_if_configured() {
+    let (addr, _token) = spawn_server(resync_config()).await;
+    let response = exchange(addr, b"\xFF\xFEnot a command\x00getinfo:").await;
+    assert_eq!(response, INFO);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn every_error_is_recovered_from() {
+    let (addr, _token) = spawn_server(resync_config()).await;
+    // An oversized shift whose header is a valid command, followed by more garbage
+    let response = exchange(addr, b"getinfo:shift:\xFF\xFF\xFF\xFF\x01\x02junk getinfo:").await;
+    assert_eq!(response, [INFO, INFO].concat());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn garbage_closes_connection_by_default() {
+    let (addr, _token) = spawn_server(Config::default()).await;
+    let response = exchange(addr, b"\xFF\xFEnot a command\x00getinfo:").await;
+    assert!(response.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connection_is_closed_after_max_resyncs() {
+    let (addr, _token) = spawn_server(resync_config()).await;
+    let response = exchange(
+        addr,
+        b"\xFFgetinfo:\xFFgetinfo:\xFFgetinfo:\xFFgetinfo:getinfo:",
+    )
+    .await;
+    assert_eq!(response, [INFO, INFO, INFO].concat());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resync_gives_up_beyond_the_scan_limit() {
+    let config = Config {
+        resync_scan_limit: 16,
+        ..resync_config()
+    };
+    let (addr, _token) = spawn_server(config).await;
+    let response = exchange(addr, b"\xFFshort junk getinfo:").await;
+    assert_eq!(response, INFO);
+    let response = exchange(addr, b"\xFFa lot more junk than allowed getinfo:").await;
+    assert!(response.is_empty());
+}
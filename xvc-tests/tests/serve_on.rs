@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use tokio_util::sync::CancellationToken;
+use xvc_client::XvcClient;
+use xvc_server::{
+    server::{Config, Server},
+    test_util::ConstantBackend,
+};
+
+fn server() -> Arc<Server<ConstantBackend>> {
+    Arc::new(Server::new(ConstantBackend::new([0x3C]), Config::default()))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn blocking_listener_is_served() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = server();
+    let token = CancellationToken::new();
+    let serving = tokio::spawn({
+        let (server, token) = (Arc::clone(&server), token.clone());
+        async move { server.serve_on(listener, token).await }
+    });
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    assert_eq!(*client.shift(8, &[0], &[0]).await.unwrap(), [0x3C]);
+    drop(client);
+    token.cancel();
+    serving.await.unwrap().unwrap();
+    assert_eq!(server.stats().shifts, 1);
+}
+
+#[cfg(unix)]
+mod fd {
+    use std::os::{fd::OwnedFd, unix::net::UnixListener};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tcp_socket_is_served_from_its_file_descriptor() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = server();
+        let token = CancellationToken::new();
+        let serving = tokio::spawn({
+            let (server, token) = (Arc::clone(&server), token.clone());
+            async move { server.serve_on_fd(OwnedFd::from(listener), token).await }
+        });
+
+        let mut client = XvcClient::connect(addr).await.unwrap();
+        assert_eq!(*client.shift(8, &[0], &[0]).await.unwrap(), [0x3C]);
+        drop(client);
+        token.cancel();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unix_socket_is_served_from_its_file_descriptor() {
+        let path =
+            std::env::temp_dir().join(format!("xvc-{}-serve-on-fd.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = server();
+        let token = CancellationToken::new();
+        let serving = tokio::spawn({
+            let (server, token) = (Arc::clone(&server), token.clone());
+            async move { server.serve_on_fd(OwnedFd::from(listener), token).await }
+        });
+
+        let mut client = XvcClient::connect_unix(&path).await.unwrap();
+        assert_eq!(*client.shift(8, &[0], &[0]).await.unwrap(), [0x3C]);
+        drop(client);
+        token.cancel();
+        serving.await.unwrap().unwrap();
+        // The socket file belongs to whoever bound it
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn file_that_is_no_socket_is_rejected() {
+        let file = std::fs::File::open(std::env::current_exe().unwrap()).unwrap();
+        let served = server()
+            .serve_on_fd(OwnedFd::from(file), CancellationToken::new())
+            .await;
+        assert!(served.is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use xvc_client::XvcClient;
+use xvc_server::server::{Config, Server};
+use xvc_tests::StubBackend;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serve_once_returns_after_the_client_disconnects() {
+    let server = Server::new(StubBackend, Config::default());
+    let bound = server.bind("127.0.0.1:0").await.unwrap();
+    let addr = bound.local_addr();
+
+    let client = async {
+        let mut client = XvcClient::connect(addr).await.unwrap();
+        client.get_info().await.unwrap();
+    };
+    let (served, ()) = tokio::join!(bound.serve_once(), client);
+    served.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn listen_returns_after_max_connections() {
+    let config = Config {
+        max_connections: Some(2),
+        // The second client may connect before the first one is closed
+        exclusive_client: false,
+        max_clients: 2,
+        ..Config::default()
+    };
+    let server = Server::new(StubBackend, config);
+    let bound = server.bind("127.0.0.1:0").await.unwrap();
+    let addr = bound.local_addr();
+
+    let clients = async {
+        for _ in 0..2 {
+            let mut client = XvcClient::connect(addr).await.unwrap();
+            client.get_info().await.unwrap();
+        }
+    };
+    let (served, ()) = tokio::join!(bound.serve(), clients);
+    served.unwrap();
+}
@@ -0,0 +1,103 @@
+use xvc_client::XvcClient;
+use xvc_server::{
+    server::{Config, Server, TckOnConnect},
+    test_util::{Call, RecordingBackend},
+};
+
+/// The shift of [`serve_client`].
+fn shift() -> Call {
+    Call::Shift {
+        num_bits: 8,
+        tms: vec![0],
+        tdi: vec![0],
+    }
+}
+
+/// Serves a client over an in-memory pipe that sets the TCK period to `period_ns`, if any, and
+/// shifts 8 bits.
+async fn serve_client(server: &Server<RecordingBackend>, period_ns: Option<u32>) {
+    let (client, connection) = tokio::io::duplex(4096);
+    let client = async move {
+        let mut client = XvcClient::new(client);
+        if let Some(period_ns) = period_ns {
+            assert_eq!(client.set_tck(period_ns).await.unwrap(), period_ns);
+        }
+        client.shift(8, &[0], &[0]).await.unwrap();
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backend_is_told_about_the_start_and_end_of_a_connection() {
+    let backend = RecordingBackend::default();
+    let server = Server::new(backend.clone(), Config::default());
+    serve_client(&server, Some(100)).await;
+    assert_eq!(
+        backend.calls(),
+        [
+            Call::Connect,
+            Call::SetTck { period_ns: 100 },
+            shift(),
+            Call::Disconnect
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn configured_tck_period_is_set_on_connect() {
+    let backend = RecordingBackend::default();
+    let config = Config {
+        tck_on_connect: TckOnConnect::Period(50),
+        ..Config::default()
+    };
+    let server = Server::new(backend.clone(), config);
+    serve_client(&server, Some(100)).await;
+    serve_client(&server, None).await;
+    assert_eq!(
+        backend.calls(),
+        [
+            Call::Connect,
+            Call::SetTck { period_ns: 50 },
+            Call::SetTck { period_ns: 100 },
+            shift(),
+            Call::Disconnect,
+            Call::Connect,
+            Call::SetTck { period_ns: 50 },
+            shift(),
+            Call::Disconnect
+        ]
+    );
+    assert_eq!(server.stats().tck_period_ns, Some(50));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn last_tck_period_is_set_again_on_connect() {
+    let backend = RecordingBackend::default();
+    let config = Config {
+        tck_on_connect: TckOnConnect::Last,
+        ..Config::default()
+    };
+    let server = Server::new(backend.clone(), config);
+    // No period was set yet, so none is set again
+    serve_client(&server, None).await;
+    serve_client(&server, Some(100)).await;
+    serve_client(&server, None).await;
+    assert_eq!(
+        backend.calls(),
+        [
+            Call::Connect,
+            shift(),
+            Call::Disconnect,
+            Call::Connect,
+            Call::SetTck { period_ns: 100 },
+            shift(),
+            Call::Disconnect,
+            Call::Connect,
+            Call::SetTck { period_ns: 100 },
+            shift(),
+            Call::Disconnect
+        ]
+    );
+    assert_eq!(server.stats().tck_period_ns, Some(100));
+}
@@ -1,6 +1,13 @@
+use std::{
+    convert::Infallible,
+    io::{Read, Write},
+    net::TcpListener,
+};
+
 use xvc_client::XvcClient;
-use xvc_server::server::Config;
-use xvc_tests::spawn_server;
+use xvc_protocol::{BitVector, Message, VectorLen, bits, error::ReadError};
+use xvc_server::{XvcServer, server::Config, test_util::LoopbackBackend};
+use xvc_tests::{exchange, spawn_server, spawn_server_with};
 
 #[tokio::test(flavor = "multi_thread")]
 async fn shift_returns_tdo_of_correct_length() {
@@ -30,3 +37,222 @@ async fn shift_multiple_times_in_sequence() {
         assert_eq!(tdo.len(), num_bytes, "wrong TDO length for {bits} bits");
     }
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn large_shift_payload_arrives_intact() {
+    let (addr, _token) = spawn_server_with(LoopbackBackend, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let num_bytes = 4 * 1024 * 1024;
+    let tms = vec![0u8; num_bytes];
+    let tdi: Vec<u8> = (0..num_bytes).map(|i| (i % 251) as u8).collect();
+    let tdo = client
+        .shift(8 * num_bytes as u32, &tms, &tdi)
+        .await
+        .unwrap();
+    assert!(*tdo == *tdi);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shorter_shift_after_longer_one_returns_only_its_tdo() {
+    let (addr, _token) = spawn_server_with(LoopbackBackend, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let tdo = client.shift(32, &[0; 4], &[0xAB; 4]).await.unwrap();
+    assert_eq!(*tdo, [0xAB; 4]);
+    let tdo = client.shift(8, &[0], &[0x01]).await.unwrap();
+    assert_eq!(*tdo, [0x01]);
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    let tdo = client.shift(16, &[0; 2], &[0x02, 0x03]).await.unwrap();
+    assert_eq!(*tdo, [0x02, 0x03]);
+}
+
+/// Only writes the first byte of the TDO, like hardware that wedged within a transfer.
+struct PartialTdo;
+
+impl XvcServer for PartialTdo {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(&self, _: u32, _: &[u8], _: &[u8], tdo: &mut [u8]) -> Result<(), Infallible> {
+        tdo[0] = 0xAB;
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backend_that_writes_part_of_the_tdo_keeps_the_stream_in_sync() {
+    let (addr, _token) = spawn_server_with(PartialTdo, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    // The backend is given a buffer of the length of the answer, so the rest is zeros
+    let tdo = client.shift(24, &[0; 3], &[0xff; 3]).await.unwrap();
+    assert_eq!(*tdo, [0xAB, 0x00, 0x00]);
+    client.get_info().await.unwrap();
+}
+
+/// Fails the test if the server calls the backend to shift.
+struct NoShifts;
+
+impl XvcServer for NoShifts {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(&self, num_bits: u32, _: &[u8], _: &[u8], _: &mut [u8]) -> Result<(), Infallible> {
+        panic!("the backend was asked to shift {num_bits} bits");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn zero_bit_shift_is_answered_without_backend() {
+    let (addr, _token) = spawn_server_with(NoShifts, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let tdo = client.shift(0, &[], &[]).await.unwrap();
+    assert!(tdo.is_empty());
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn inconsistent_shift_is_rejected_before_sending() {
+    let (addr, _token) = spawn_server_with(NoShifts, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let result = client.shift(12, &[0x00, 0x00], &[0xFF]).await;
+    assert!(matches!(
+        result,
+        Err(ReadError::IoError(e)) if e.kind() == std::io::ErrorKind::InvalidInput
+    ));
+    // Nothing was sent, so the connection is still in sync
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chunked_shift_payload_arrives_intact() {
+    let (addr, _token) = spawn_server_with(LoopbackBackend, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let tdi: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+    let tms = [vec![0; 1], vec![0; 999]];
+    let tdo = client.shift_chunks(8000, tms, tdi.chunks(7)).await.unwrap();
+    assert_eq!(*tdo, *tdi);
+    // The connection stays in sync after a chunked shift
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn overlong_chunks_are_rejected() {
+    let (addr, _token) = spawn_server_with(LoopbackBackend, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let result = client.shift_chunks(8, [[0x00]], [[0xFF], [0xFF]]).await;
+    assert!(matches!(
+        result,
+        Err(ReadError::IoError(e)) if e.kind() == std::io::ErrorKind::InvalidInput
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shift_bits_returns_tdo_bits() {
+    let (addr, _token) = spawn_server_with(LoopbackBackend, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let tdi: BitVector = (0..13).map(|i| i % 3 == 0).collect();
+    let tdo = client
+        .shift_bits(&BitVector::zeros(13), &tdi)
+        .await
+        .unwrap();
+    assert_eq!(tdo, tdi);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connection_closed_mid_response_reports_truncation() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 10 + 2 * 4];
+        stream.read_exact(&mut request).unwrap();
+        stream.write_all(&[0xAB]).unwrap();
+    });
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let result = client.shift(32, &[0; 4], &[0; 4]).await;
+    assert!(matches!(
+        result,
+        Err(ReadError::Truncated {
+            expected: 4,
+            received: 1
+        })
+    ));
+    server.join().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connection_closed_before_response_reports_disconnect() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 10 + 2];
+        stream.read_exact(&mut request).unwrap();
+    });
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let result = client.shift(8, &[0], &[0]).await;
+    assert!(matches!(result, Err(ReadError::Disconnected)));
+    server.join().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn split_shifts_reassemble_tdo() {
+    let config = Config {
+        max_vector_size: VectorLen::from_bytes(16),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server_with(LoopbackBackend, config).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let max_bytes = client.get_info().await.unwrap().max_vector_len().as_bytes() as usize;
+
+    // xorshift, so that the lengths are arbitrary but reproducible
+    let mut state = 0x9E37_79B9_u32;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+    for _ in 0..50 {
+        let num_bits = random() % 1000;
+        let num_bytes = num_bits.div_ceil(8) as usize;
+        let tms: Vec<u8> = (0..num_bytes).map(|_| random() as u8).collect();
+        let tdi: Vec<u8> = (0..num_bytes).map(|_| random() as u8).collect();
+        let shift = Message::try_shift(num_bits, &tms[..], &tdi[..]).unwrap();
+
+        let mut tdo_parts = Vec::new();
+        let mut bits_per_part = Vec::new();
+        for part in shift.split(max_bytes) {
+            let Message::Shift { num_bits, tms, tdi } = part else {
+                unreachable!("a shift is split into shifts");
+            };
+            tdo_parts.push(client.shift(num_bits, tms, tdi).await.unwrap());
+            bits_per_part.push(num_bits);
+        }
+        let tdo = bits::concat_tdo(&tdo_parts, &bits_per_part);
+
+        let mut expected = tdi.clone();
+        bits::mask_padding(&mut expected, num_bits as usize);
+        assert_eq!(*tdo, *expected, "{num_bits} bits");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn max_vector_size_limits_each_vector() {
+    let config = Config {
+        max_vector_size: VectorLen::from_bytes(2),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server_with(LoopbackBackend, config).await;
+    // 16 bits need 2 bytes for each of TMS and TDI, 4 bytes in total
+    let response = exchange(addr, b"shift:\x10\x00\x00\x00\x00\x00\xAB\xCD").await;
+    assert_eq!(response, [0xAB, 0xCD]);
+    // 17 bits need 3 bytes per vector, so the shift is discarded and answered with zeros
+    let response = exchange(addr, b"shift:\x11\x00\x00\x00\x00\x00\x00\xAB\xCD\x01").await;
+    assert_eq!(response, [0, 0, 0]);
+}
@@ -0,0 +1,102 @@
+use std::io;
+
+use xvc_client::XvcClient;
+use xvc_protocol::error::ReadError;
+use xvc_server::{
+    XvcServer,
+    server::{Config, Server, ShiftErrorPolicy},
+};
+
+/// Writes `0xAA` into the first byte of the TDO of every shift, then fails it. Takes shifts in
+/// chunks of `chunk_len` bytes, if any.
+struct FailingBackend {
+    chunk_len: Option<usize>,
+}
+
+impl XvcServer for FailingBackend {
+    type Err = io::Error;
+
+    fn set_tck(&self, period_ns: u32) -> io::Result<u32> {
+        Ok(period_ns)
+    }
+
+    fn shift(&self, _: u32, _: &[u8], _: &[u8], tdo: &mut [u8]) -> io::Result<()> {
+        tdo[0] = 0xAA;
+        Err(io::Error::other("cable unplugged"))
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        self.chunk_len
+    }
+}
+
+/// Serves a client that sets the TCK period and then shifts 16 bits, and returns the TDO it
+/// got, or the error of the shift and of the connection.
+async fn failed_shift(
+    policy: ShiftErrorPolicy,
+    chunk_len: Option<usize>,
+) -> Result<Vec<u8>, (ReadError, ReadError)> {
+    let config = Config {
+        shift_error_policy: policy,
+        ..Config::default()
+    };
+    let server = Server::new(FailingBackend { chunk_len }, config);
+    let (client, connection) = tokio::io::duplex(4096);
+
+    let client = async move {
+        let mut client = XvcClient::new(client);
+        assert_eq!(client.set_tck(100).await.unwrap(), 100);
+        let tdo = client.shift(16, &[0; 2], &[0xff; 2]).await?;
+        // The connection is still in sync
+        assert_eq!(client.set_tck(200).await.unwrap(), 200);
+        Ok::<_, ReadError>(tdo.to_vec())
+    };
+    let (served, tdo) = tokio::join!(server.handle_stream(connection), client);
+    match (served, tdo) {
+        (Ok(()), Ok(tdo)) => Ok(tdo),
+        (Err(served), Err(shift)) => Err((shift, served)),
+        (served, tdo) => panic!("served: {served:?}, TDO: {tdo:?}"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_shift_is_answered_with_the_tdo_as_it_is() {
+    let tdo = failed_shift(ShiftErrorPolicy::SendTdo, None).await.unwrap();
+    assert_eq!(tdo, [0xAA, 0x00]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_shift_is_answered_with_zeros() {
+    let tdo = failed_shift(ShiftErrorPolicy::ZeroFill, None)
+        .await
+        .unwrap();
+    assert_eq!(tdo, [0x00, 0x00]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_shift_closes_the_connection() {
+    let (shift, served) = failed_shift(ShiftErrorPolicy::CloseConnection, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(shift, ReadError::Disconnected), "{shift:?}");
+    assert!(matches!(served, ReadError::IoError(_)), "{served:?}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_chunks_of_a_streamed_shift_follow_the_policy() {
+    let tdo = failed_shift(ShiftErrorPolicy::SendTdo, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(tdo, [0xAA, 0xAA]);
+    let tdo = failed_shift(ShiftErrorPolicy::ZeroFill, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(tdo, [0x00, 0x00]);
+    let (shift, _) = failed_shift(ShiftErrorPolicy::CloseConnection, Some(1))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(shift, ReadError::Disconnected | ReadError::Truncated { .. }),
+        "{shift:?}"
+    );
+}
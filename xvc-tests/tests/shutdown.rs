@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use xvc_client::XvcClient;
+use xvc_server::server::{Config, Server};
+use xvc_tests::StubBackend;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn listen_returns_after_connected_clients_are_closed() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let token = CancellationToken::new();
+    let listening = tokio::spawn({
+        let token = token.clone();
+        async move {
+            let server = Server::new(StubBackend, Config::default());
+            server.listen_on(listener, token).await
+        }
+    });
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    client.get_info().await.unwrap();
+
+    token.cancel();
+    let result = tokio::time::timeout(Duration::from_secs(5), listening)
+        .await
+        .expect("server did not shut down")
+        .unwrap();
+    assert!(result.is_ok());
+
+    // The connected client was closed, not left open
+    assert!(client.get_info().await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dropping_listen_closes_connected_clients() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listening = tokio::spawn(async move {
+        let server = Server::new(StubBackend, Config::default());
+        server.listen_on(listener, CancellationToken::new()).await
+    });
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    client.get_info().await.unwrap();
+
+    listening.abort();
+    assert!(listening.await.unwrap_err().is_cancelled());
+    assert!(client.get_info().await.is_err());
+}
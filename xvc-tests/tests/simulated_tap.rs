@@ -0,0 +1,162 @@
+use xvc_client::XvcClient;
+use xvc_protocol::{
+    BitVector,
+    jtag::{TapState, tms_path},
+};
+use xvc_server::{
+    XvcServer,
+    server::Config,
+    simulated_tap::{SimulatedTap, TapDevice},
+};
+use xvc_tests::spawn_server_with;
+
+const XC7Z020: u32 = 0x2372_7093;
+const ARM_DAP: u32 = 0x4BA0_0477;
+
+fn bits(value: u32, len: usize) -> BitVector {
+    BitVector::from_bytes(&value.to_le_bytes(), len)
+}
+
+/// Clock `tms` with TDI low and return TDO.
+fn clock(tap: &SimulatedTap, tms: &BitVector) -> BitVector {
+    let mut tdo = vec![0; tms.as_bytes().len()];
+    let tdi = BitVector::zeros(tms.len());
+    tap.shift(tms.len() as u32, tms.as_bytes(), tdi.as_bytes(), &mut tdo)
+        .unwrap();
+    BitVector::from_bytes(&tdo, tms.len())
+}
+
+/// Scan `tdi` through `shift`, i.e. Shift-IR or Shift-DR, starting and ending in Run-Test/Idle,
+/// and return the TDO of the scan.
+fn scan(tap: &SimulatedTap, shift: TapState, tdi: &BitVector) -> BitVector {
+    let mut tms = BitVector::from(tms_path(TapState::RunTestIdle, shift));
+    let start = tms.len();
+    let mut all_tdi = BitVector::zeros(start);
+    for (i, bit) in tdi.iter().enumerate() {
+        tms.push_bit(i + 1 == tdi.len());
+        all_tdi.push_bit(bit);
+    }
+    let back = tms_path(shift.next(true), TapState::RunTestIdle);
+    tms.extend_from_bits(back.iter());
+    all_tdi.append(&BitVector::zeros(back.len()));
+
+    let mut tdo = vec![0; tms.as_bytes().len()];
+    tap.shift(
+        tms.len() as u32,
+        tms.as_bytes(),
+        all_tdi.as_bytes(),
+        &mut tdo,
+    )
+    .unwrap();
+    let tdo = BitVector::from_bytes(&tdo, tms.len());
+    tdo.iter().skip(start).take(tdi.len()).collect()
+}
+
+fn idle(tap: &SimulatedTap) {
+    clock(
+        tap,
+        &tms_path(TapState::TestLogicReset, TapState::RunTestIdle).into(),
+    );
+    assert_eq!(tap.state(), TapState::RunTestIdle);
+}
+
+#[test]
+fn idcode_is_read_after_test_logic_reset() {
+    let tap = SimulatedTap::new([TapDevice::new(6).with_idcode(0x09, XC7Z020)]);
+    idle(&tap);
+    assert_eq!(
+        scan(&tap, TapState::ShiftDr, &BitVector::zeros(32)),
+        bits(XC7Z020, 32)
+    );
+    // The ID register captures the IDCODE again on each scan
+    assert_eq!(
+        scan(&tap, TapState::ShiftDr, &BitVector::ones(32)),
+        bits(XC7Z020, 32)
+    );
+}
+
+#[test]
+fn instruction_register_captures_01() {
+    let tap = SimulatedTap::new([TapDevice::new(6), TapDevice::new(4)]);
+    idle(&tap);
+    let tdo = scan(&tap, TapState::ShiftIr, &BitVector::ones(10));
+    assert_eq!(tdo, bits(0b0100_0001, 10));
+}
+
+#[test]
+fn chain_shifts_out_the_device_next_to_tdo_first() {
+    let tap = SimulatedTap::new([
+        TapDevice::new(6).with_idcode(0x09, XC7Z020),
+        TapDevice::new(4),
+        TapDevice::new(4).with_idcode(0x0E, ARM_DAP),
+    ]);
+    idle(&tap);
+    let tdo = scan(&tap, TapState::ShiftDr, &BitVector::zeros(65));
+    let mut expected = bits(XC7Z020, 32);
+    // The device without ID register is in BYPASS
+    expected.push_bit(false);
+    expected.append(&bits(ARM_DAP, 32));
+    assert_eq!(tdo, expected);
+}
+
+#[test]
+fn bypass_delays_tdi_by_one_bit() {
+    let tap = SimulatedTap::new([TapDevice::new(6).with_idcode(0x09, XC7Z020)]);
+    idle(&tap);
+    scan(&tap, TapState::ShiftIr, &BitVector::ones(6));
+    assert_eq!(tap.devices()[0].instruction(), 0x3F);
+    let tdo = scan(&tap, TapState::ShiftDr, &bits(0b1011, 4));
+    assert_eq!(tdo, bits(0b0110, 4));
+}
+
+#[test]
+fn user_register_holds_the_updated_value() {
+    let tap = SimulatedTap::new([TapDevice::new(6).with_register(0x02, 12), TapDevice::new(4)]);
+    idle(&tap);
+    // The first 6 bits shifted end up in the device next to TDO
+    let mut instructions = bits(0x02, 6);
+    instructions.append(&BitVector::ones(4));
+    scan(&tap, TapState::ShiftIr, &instructions);
+
+    let mut value = bits(0xABC, 12);
+    value.push_bit(false);
+    assert_eq!(scan(&tap, TapState::ShiftDr, &value), BitVector::zeros(13));
+    assert_eq!(tap.devices()[0].register(0x02), Some(&bits(0xABC, 12)));
+    let tdo = scan(&tap, TapState::ShiftDr, &BitVector::zeros(13));
+    assert_eq!(tdo.iter().take(12).collect::<BitVector>(), bits(0xABC, 12));
+}
+
+#[test]
+fn five_tms_high_clocks_select_idcode_again() {
+    let tap = SimulatedTap::new([TapDevice::new(6).with_idcode(0x09, XC7Z020)]);
+    idle(&tap);
+    scan(&tap, TapState::ShiftIr, &BitVector::ones(6));
+    clock(
+        &tap,
+        &tms_path(TapState::RunTestIdle, TapState::TestLogicReset).into(),
+    );
+    assert_eq!(tap.devices()[0].instruction(), 0x09);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_reads_the_idcode_of_a_simulated_device() {
+    let tap = SimulatedTap::new([TapDevice::new(6).with_idcode(0x09, XC7Z020)]);
+    let (addr, _token) = spawn_server_with(tap, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    // Reset, then Run-Test/Idle, Select-DR-Scan, Capture-DR and 32 bits in Shift-DR
+    let mut tms = BitVector::ones(5);
+    tms.append(&BitVector::from(tms_path(
+        TapState::TestLogicReset,
+        TapState::ShiftDr,
+    )));
+    let start = tms.len();
+    tms.append(&BitVector::zeros(32));
+    let tdi = BitVector::zeros(tms.len());
+    let tdo = client
+        .shift(tms.len() as u32, tms.as_bytes(), tdi.as_bytes())
+        .await
+        .unwrap();
+    let tdo = BitVector::from_bytes(&tdo, tms.len());
+    let idcode: BitVector = tdo.iter().skip(start).collect();
+    assert_eq!(idcode, bits(XC7Z020, 32));
+}
@@ -31,9 +31,9 @@ async fn new_client_can_connect_after_previous_disconnects() {
         // client_a dropped here, TCP connection closed
     }
 
-    // Retry until the server releases the lock from the previous connection.
+    // Retry until the server releases the slot of the previous connection.
     // The handle_client task processes the EOF asynchronously, so there is a
-    // brief window where try_lock_owned() might still fail.
+    // brief window where the slot is still taken.
     for attempt in 1..=10 {
         let mut client_b = XvcClient::connect(addr).await.unwrap();
         if client_b.get_info().await.is_ok() {
@@ -41,5 +41,22 @@ async fn new_client_can_connect_after_previous_disconnects() {
         }
         tokio::time::sleep(Duration::from_millis(10 * attempt)).await;
     }
-    panic!("server did not release lock after previous client disconnected");
+    panic!("server did not release slot after previous client disconnected");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exclusive_client_overrides_max_clients() {
+    let config = Config {
+        exclusive_client: true,
+        max_clients: 2,
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+
+    // Both clients connect before either sends a request
+    let mut client_a = XvcClient::connect(addr).await.unwrap();
+    let mut client_b = XvcClient::connect(addr).await.unwrap();
+    client_a.get_info().await.unwrap();
+    assert!(client_b.get_info().await.is_err());
+    assert_eq!(client_a.set_tck(100).await.unwrap(), 100);
 }
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use xvc_client::Builder;
+use xvc_server::server::{self, Config};
+use xvc_tests::{StubBackend, spawn_server};
+
+#[test]
+fn nodelay_is_enabled_by_default() {
+    let config = Config::default();
+    assert!(config.tcp_nodelay);
+    assert_eq!(config.tcp_keepalive, None);
+
+    let config = server::Builder::new()
+        .tcp_nodelay(false)
+        .tcp_keepalive(Duration::from_secs(60))
+        .build(StubBackend)
+        .unwrap()
+        .config();
+    assert!(!config.tcp_nodelay);
+    assert_eq!(config.tcp_keepalive, Some(Duration::from_secs(60)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_are_served_with_keepalive() {
+    let config = Config {
+        tcp_keepalive: Some(Duration::from_secs(60)),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+
+    let mut client = Builder::new()
+        .tcp_keepalive(Duration::from_secs(60))
+        .connect(addr)
+        .await
+        .unwrap();
+    client.get_info().await.unwrap();
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_are_served_without_nodelay() {
+    let config = Config {
+        tcp_nodelay: false,
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+
+    let mut client = Builder::new()
+        .tcp_nodelay(false)
+        .connect(addr)
+        .await
+        .unwrap();
+    client.get_info().await.unwrap();
+    assert_eq!(*client.shift(8, &[0], &[0xff]).await.unwrap(), [0]);
+}
@@ -0,0 +1,127 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use tokio::{net::TcpListener, time::sleep};
+use tokio_util::sync::{CancellationToken, DropGuard};
+use xvc_client::XvcClient;
+use xvc_server::{
+    server::{Builder, Config, Server},
+    stats::{LATENCY_BUCKETS, StatsSnapshot},
+    test_util::{FaultInjecting, Faults, LoopbackBackend},
+};
+use xvc_tests::{StubBackend, exchange};
+
+async fn spawn_shared() -> (Arc<Server<StubBackend>>, SocketAddr, DropGuard) {
+    let server = Arc::new(Server::new(StubBackend, Config::default()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let token = CancellationToken::new();
+    tokio::spawn({
+        let server = Arc::clone(&server);
+        let token = token.clone();
+        async move { server.listen_on(listener, token).await.unwrap() }
+    });
+    (server, addr, token.drop_guard())
+}
+
+/// Waits until the statistics satisfy `done`, as the server task updates them asynchronously.
+async fn wait_for(
+    server: &Server<StubBackend>,
+    done: impl Fn(&StatsSnapshot) -> bool,
+) -> StatsSnapshot {
+    for _ in 0..100 {
+        let stats = server.stats();
+        if done(&stats) {
+            return stats;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    panic!("statistics did not change: {:?}", server.stats());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stats_count_connections_and_answers() {
+    let (server, addr, _guard) = spawn_shared().await;
+    let idle = server.stats();
+    assert_eq!(idle.connections, 0);
+    assert_eq!(idle.active_connections, 0);
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    client.shift(16, &[0, 0], &[0xff, 0xff]).await.unwrap();
+
+    // The answer is counted after it was sent
+    let stats = wait_for(&server, |stats| stats.bytes_written == 4 + 2).await;
+    assert_eq!(stats.connections, 1);
+    assert_eq!(stats.active_connections, 1);
+    assert_eq!(stats.shifts, 1);
+    assert_eq!(stats.bits_shifted, 16);
+    assert_eq!(stats.errors, 0);
+    assert!(stats.uptime >= idle.uptime);
+
+    drop(client);
+    let stats = wait_for(&server, |stats| stats.active_connections == 0).await;
+    assert_eq!(stats.connections, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stats_count_connections_closed_by_errors() {
+    let (server, addr, _guard) = spawn_shared().await;
+
+    assert!(exchange(addr, b"lock:getinfo:").await.is_empty());
+    let stats = wait_for(&server, |stats| stats.errors == 1).await;
+    assert_eq!(stats.connections, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backend_latency_is_counted_per_command() {
+    let backend = FaultInjecting::new(
+        LoopbackBackend,
+        Faults::default().latency(1.0, Duration::from_millis(20)),
+    );
+    let server = Builder::new()
+        .slow_op_warn_threshold(Duration::from_millis(5))
+        .build(backend)
+        .unwrap();
+    let (client, connection) = tokio::io::duplex(4096);
+
+    let client = async move {
+        let mut client = XvcClient::new(client);
+        assert_eq!(client.set_tck(100).await.unwrap(), 100);
+        for _ in 0..3 {
+            assert_eq!(*client.shift(8, &[0], &[0xa5]).await.unwrap(), [0xa5]);
+        }
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+
+    let stats = server.stats();
+    assert_eq!(stats.set_tck_latency.total(), 1);
+    assert_eq!(stats.shift_latency.total(), 3);
+    // Only shifts are delayed, by 20 ms, which falls into the bucket of up to 100 ms
+    let slow = LATENCY_BUCKETS
+        .iter()
+        .position(|&bound| bound == Duration::from_millis(100))
+        .unwrap();
+    assert_eq!(stats.shift_latency.counts[slow], 3);
+    assert_eq!(stats.set_tck_latency.counts[slow], 0);
+    assert_eq!(
+        stats
+            .shift_latency
+            .buckets()
+            .map(|(_, count)| count)
+            .sum::<u64>(),
+        3
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn idle_server_has_empty_latency_histograms() {
+    let (server, _addr, _guard) = spawn_shared().await;
+    let stats = server.stats();
+    assert_eq!(stats.shift_latency.total(), 0);
+    assert_eq!(stats.shift_latency.to_string(), "none");
+    assert_eq!(
+        stats.set_tck_latency.buckets().count(),
+        LATENCY_BUCKETS.len() + 1
+    );
+}
@@ -0,0 +1,111 @@
+use std::convert::Infallible;
+
+use xvc_client::XvcClient;
+use xvc_server::{
+    XvcServerMut,
+    server::{Config, Server},
+};
+
+/// Returns TDI as TDO and counts the calls it gets, taking shifts in chunks of `chunk_len`.
+struct ChunkedLoopback {
+    chunk_len: usize,
+    calls: u64,
+    bits: u64,
+    longest_call: u64,
+}
+
+impl ChunkedLoopback {
+    fn new(chunk_len: usize) -> ChunkedLoopback {
+        ChunkedLoopback {
+            chunk_len,
+            calls: 0,
+            bits: 0,
+            longest_call: 0,
+        }
+    }
+}
+
+impl XvcServerMut for ChunkedLoopback {
+    type Err = Infallible;
+
+    fn set_tck(&mut self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(
+        &mut self,
+        num_bits: u32,
+        _tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Infallible> {
+        assert_eq!(tdi.len(), num_bits.div_ceil(8) as usize);
+        tdo.copy_from_slice(tdi);
+        self.calls += 1;
+        self.bits += u64::from(num_bits);
+        self.longest_call = self.longest_call.max(u64::from(num_bits));
+        Ok(())
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("calls", self.calls),
+            ("bits", self.bits),
+            ("longest_call", self.longest_call),
+        ]
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        Some(self.chunk_len)
+    }
+}
+
+/// Serves `shifts` of the given numbers of bits over an in-memory pipe with small buffers,
+/// checking the TDO of each, and returns the backend statistics.
+async fn serve_shifts(chunk_len: usize, shifts: &[u32]) -> Vec<(&'static str, u64)> {
+    let server = Server::new(ChunkedLoopback::new(chunk_len), Config::default());
+    let (client, connection) = tokio::io::duplex(4096);
+
+    let client = async move {
+        let mut client = XvcClient::new(client);
+        for &num_bits in shifts {
+            let len = num_bits.div_ceil(8) as usize;
+            let tdi: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let tdo = client.shift(num_bits, &vec![0; len], &tdi).await.unwrap();
+            assert!(*tdo == *tdi, "wrong TDO for a shift of {num_bits} bits");
+        }
+        // Messages after a streamed shift are still answered
+        assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+    assert_eq!(server.stats().shifts, shifts.len() as u64);
+    server.backend_stats()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn long_shift_is_passed_to_the_backend_in_chunks() {
+    let stats = serve_shifts(4, &[100]).await;
+    assert_eq!(stats, [("calls", 4), ("bits", 100), ("longest_call", 32)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shift_within_the_chunk_length_is_passed_whole() {
+    let stats = serve_shifts(4, &[32, 9]).await;
+    assert_eq!(stats, [("calls", 2), ("bits", 41), ("longest_call", 32)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_that_reads_after_sending_does_not_stall_a_streamed_shift() {
+    // The TDO is far larger than the pipe, and the client only reads it once it sent all TDI
+    let num_bits = 8 * 1024 * 1024;
+    let stats = serve_shifts(64 * 1024, &[num_bits, 12]).await;
+    assert_eq!(
+        stats,
+        [
+            ("calls", 17),
+            ("bits", u64::from(num_bits) + 12),
+            ("longest_call", 8 * 64 * 1024)
+        ]
+    );
+}
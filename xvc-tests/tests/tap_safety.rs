@@ -0,0 +1,178 @@
+use std::{convert::Infallible, sync::Arc};
+
+use xvc_client::XvcClient;
+use xvc_protocol::{
+    BitVector,
+    jtag::{TapState, tms_path},
+};
+use xvc_server::{
+    XvcServer,
+    decorators::TapSafety,
+    server::Config,
+    simulated_tap::{SimulatedTap, TapDevice},
+    test_util::{Call, RecordingBackend},
+};
+use xvc_tests::{eventually, spawn_server_with};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chain_left_in_shift_dr_is_reset() {
+    let tap = Arc::new(SimulatedTap::new([TapDevice::new(6)]));
+    let backend = TapSafety::new(Arc::clone(&tap));
+    let (addr, _token) = spawn_server_with(backend, Config::default()).await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let tms = BitVector::from(tms_path(TapState::TestLogicReset, TapState::ShiftDr));
+    let tdi = BitVector::zeros(tms.len());
+    client
+        .shift(tms.len() as u32, tms.as_bytes(), tdi.as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(tap.state(), TapState::ShiftDr);
+
+    drop(client);
+    eventually(|| tap.state() == TapState::TestLogicReset).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reset_settles_in_idle_if_asked_to() {
+    let tap = Arc::new(SimulatedTap::new([TapDevice::new(6)]));
+    let backend = TapSafety::new(Arc::clone(&tap)).settle_in_idle(true);
+    let (addr, _token) = spawn_server_with(backend, Config::default()).await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let tms = BitVector::from(tms_path(TapState::TestLogicReset, TapState::PauseIr));
+    let tdi = BitVector::zeros(tms.len());
+    client
+        .shift(tms.len() as u32, tms.as_bytes(), tdi.as_bytes())
+        .await
+        .unwrap();
+
+    drop(client);
+    eventually(|| tap.state() == TapState::RunTestIdle).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reset_is_only_shifted_at_disconnect() {
+    let recording = RecordingBackend::default();
+    let (addr, _token) =
+        spawn_server_with(TapSafety::new(recording.clone()), Config::default()).await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    client.set_tck(100).await.unwrap();
+    client.shift(8, &[0x00], &[0xA5]).await.unwrap();
+    client.shift(8, &[0x00], &[0x5A]).await.unwrap();
+    assert_eq!(recording.calls().len(), 4);
+
+    drop(client);
+    eventually(|| recording.calls().len() == 6).await;
+    assert_eq!(
+        recording.calls()[4..],
+        [
+            Call::Shift {
+                num_bits: 5,
+                tms: vec![0x1F],
+                tdi: vec![0x00],
+            },
+            Call::Disconnect,
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reset_waits_for_the_last_of_concurrent_clients() {
+    let recording = RecordingBackend::default();
+    let config = Config {
+        exclusive_client: false,
+        max_clients: 2,
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server_with(TapSafety::new(recording.clone()), config).await;
+    let resets = || {
+        let reset = Call::Shift {
+            num_bits: 5,
+            tms: vec![0x1F],
+            tdi: vec![0x00],
+        };
+        recording
+            .calls()
+            .iter()
+            .filter(|call| **call == reset)
+            .count()
+    };
+
+    let mut first = XvcClient::connect(addr).await.unwrap();
+    let mut second = XvcClient::connect(addr).await.unwrap();
+    first.shift(8, &[0x00], &[0xA5]).await.unwrap();
+    second.shift(8, &[0x00], &[0x5A]).await.unwrap();
+
+    // The second client is still in its session, so the first one leaving does not reset
+    drop(first);
+    eventually(|| recording.calls().contains(&Call::Disconnect)).await;
+    assert_eq!(resets(), 0);
+    second.shift(8, &[0x00], &[0x5A]).await.unwrap();
+
+    drop(second);
+    eventually(|| resets() == 1).await;
+}
+
+/// Panics on shifts of TDI `0xFF`, and passes everything else on to a [`RecordingBackend`].
+struct PanicsOnOnes(RecordingBackend);
+
+impl XvcServer for PanicsOnOnes {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        self.0.set_tck(period_ns)
+    }
+
+    fn shift(
+        &self,
+        num_bits: u32,
+        tms: &[u8],
+        tdi: &[u8],
+        tdo: &mut [u8],
+    ) -> Result<(), Infallible> {
+        assert_ne!(tdi, [0xFF], "backend failure");
+        self.0.shift(num_bits, tms, tdi, tdo)
+    }
+
+    fn on_connect(&self) {
+        self.0.on_connect()
+    }
+
+    fn on_disconnect(&self) {
+        self.0.on_disconnect()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reset_survives_a_backend_panic() {
+    let recording = RecordingBackend::default();
+    let backend = TapSafety::new(PanicsOnOnes(recording.clone()));
+    let (addr, _token) = spawn_server_with(backend, Config::default()).await;
+    let disconnects = || {
+        recording
+            .calls()
+            .iter()
+            .filter(|call| **call == Call::Disconnect)
+            .count()
+    };
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    assert!(client.shift(8, &[0x00], &[0xFF]).await.is_err());
+    eventually(|| disconnects() == 1).await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    client.shift(8, &[0x00], &[0xA5]).await.unwrap();
+    drop(client);
+    eventually(|| disconnects() == 2).await;
+    let calls = recording.calls();
+    assert_eq!(
+        calls[calls.len() - 2],
+        Call::Shift {
+            num_bits: 5,
+            tms: vec![0x1F],
+            tdi: vec![0x00],
+        }
+    );
+}
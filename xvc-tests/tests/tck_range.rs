@@ -0,0 +1,40 @@
+use xvc_client::XvcClient;
+use xvc_server::server::{Builder, Config, ConfigError};
+use xvc_tests::{StubBackend, spawn_server};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tck_period_is_clamped_into_the_range() {
+    let config = Builder::new()
+        .tck_range(30, 1000)
+        .build(StubBackend)
+        .unwrap()
+        .config();
+    assert_eq!(config.tck_period_range, Some(30..=1000));
+    let (addr, _token) = spawn_server(config).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    assert_eq!(client.set_tck(10).await.unwrap(), 30);
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    assert_eq!(client.set_tck(5000).await.unwrap(), 1000);
+    // Clamping again, after the first warning, is answered the same
+    assert_eq!(client.set_tck(1).await.unwrap(), 30);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tck_period_is_passed_through_without_a_range() {
+    let (addr, _token) = spawn_server(Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    assert_eq!(client.set_tck(1).await.unwrap(), 1);
+    assert_eq!(client.set_tck(u32::MAX).await.unwrap(), u32::MAX);
+}
+
+#[test]
+fn empty_tck_range_is_rejected() {
+    let built = Builder::new().tck_range(100, 10).build(StubBackend);
+    assert!(matches!(
+        built,
+        Err(ConfigError::EmptyTckRange {
+            min_ns: 100,
+            max_ns: 10
+        })
+    ));
+}
@@ -0,0 +1,277 @@
+use std::{
+    fs::File,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use xvc_client::XvcClient;
+use xvc_protocol::{Message, transcript::TranscriptWriter};
+use xvc_server::{
+    XvcServer, XvcServerMut,
+    server::{Builder, Config, Server, ShiftErrorPolicy},
+    test_util::{
+        Call, ConstantBackend, FaultError, FaultInjecting, Faults, LoopbackBackend,
+        RecordingBackend, ReplayBackend, ReplayError, ReplayMatching,
+    },
+};
+use xvc_tests::spawn_server_with;
+
+#[test]
+fn loopback_clears_the_padding_bits() {
+    let mut tdo = [0; 2];
+    LoopbackBackend
+        .shift(13, &[0; 2], &[0xFF, 0xFF], &mut tdo)
+        .unwrap();
+    assert_eq!(tdo, [0xFF, 0x1F]);
+    LoopbackBackend.shift(0, &[], &[], &mut []).unwrap();
+}
+
+#[test]
+fn constant_backend_repeats_its_pattern() {
+    let backend = ConstantBackend::new([0x12, 0x34]);
+    let mut tdo = [0; 3];
+    backend.shift(20, &[0; 3], &[0; 3], &mut tdo).unwrap();
+    assert_eq!(tdo, [0x12, 0x34, 0x02]);
+    backend.shift(0, &[], &[], &mut []).unwrap();
+
+    let mut tdo = [0xFF; 2];
+    ConstantBackend::default()
+        .shift(16, &[0; 2], &[0; 2], &mut tdo)
+        .unwrap();
+    assert_eq!(tdo, [0, 0]);
+}
+
+#[test]
+fn recording_backend_records_the_vectors() {
+    let backend = RecordingBackend::default();
+    let mut tdo = [0; 2];
+    backend.set_tck(100).unwrap();
+    backend
+        .shift(9, &[0x01, 0x00], &[0xAB, 0xFF], &mut tdo)
+        .unwrap();
+    assert_eq!(tdo, [0xAB, 0x01]);
+    assert_eq!(
+        backend.calls(),
+        [
+            Call::SetTck { period_ns: 100 },
+            Call::Shift {
+                num_bits: 9,
+                tms: vec![0x01, 0x00],
+                tdi: vec![0xAB, 0xFF],
+            },
+        ]
+    );
+    backend.clear();
+    assert!(backend.calls().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_see_the_pattern_of_a_constant_backend() {
+    let backend = ConstantBackend::new([0xC3]);
+    let (addr, _token) = spawn_server_with(backend, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let tdo = client.shift(12, &[0; 2], &[0; 2]).await.unwrap();
+    assert_eq!(*tdo, [0xC3, 0x03]);
+}
+
+/// Whether each of `calls` shifts of `backend` succeeds.
+fn outcomes(backend: &FaultInjecting<LoopbackBackend>, calls: usize) -> Vec<bool> {
+    (0..calls)
+        .map(|_| backend.shift(8, &[0], &[0], &mut [0]).is_ok())
+        .collect()
+}
+
+#[test]
+fn seeded_faults_are_reproducible() {
+    let faults = Faults::default().seed(42).failure_probability(0.5);
+    let first = outcomes(&FaultInjecting::new(LoopbackBackend, faults.clone()), 64);
+    let second = outcomes(&FaultInjecting::new(LoopbackBackend, faults), 64);
+    assert_eq!(first, second);
+    assert!(first.contains(&true) && first.contains(&false));
+
+    let other = Faults::default().seed(43).failure_probability(0.5);
+    assert_ne!(
+        outcomes(&FaultInjecting::new(LoopbackBackend, other), 64),
+        first
+    );
+}
+
+#[test]
+fn calls_fail_after_the_limit() {
+    let backend = FaultInjecting::new(LoopbackBackend, Faults::default().fail_after(3));
+    assert_eq!(backend.set_tck(100).unwrap(), 100);
+    assert_eq!(outcomes(&backend, 4), [true, true, false, false]);
+    assert!(matches!(
+        backend.set_tck(100),
+        Err(FaultError::Injected { call: 6 })
+    ));
+    assert_eq!(backend.calls(), 6);
+}
+
+#[test]
+fn every_nth_tdo_is_truncated() {
+    let faults = Faults::default().truncate_tdo(2, 1);
+    let backend = FaultInjecting::new(LoopbackBackend, faults);
+    let mut tdo = [0; 3];
+    backend.shift(24, &[0; 3], &[1, 2, 3], &mut tdo).unwrap();
+    assert_eq!(tdo, [1, 2, 3]);
+    backend.shift(24, &[0; 3], &[1, 2, 3], &mut tdo).unwrap();
+    assert_eq!(tdo, [1, 0, 0]);
+    assert_eq!(backend.faults().truncate_every, Some(2));
+}
+
+#[test]
+fn tck_period_is_reported_off() {
+    let backend = FaultInjecting::new(LoopbackBackend, Faults::default().tck_offset_ns(-150));
+    assert_eq!(backend.set_tck(1000).unwrap(), 850);
+    assert_eq!(backend.set_tck(100).unwrap(), 0);
+}
+
+#[test]
+fn shifts_are_delayed() {
+    let faults = Faults::default().latency(1.0, Duration::from_millis(20));
+    let backend = FaultInjecting::new(LoopbackBackend, faults);
+    let start = Instant::now();
+    backend.shift(8, &[0], &[0], &mut [0]).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_see_the_injected_faults() {
+    let faults = Faults::default().truncate_tdo(1, 1).fail_after(2);
+    let backend = FaultInjecting::new(LoopbackBackend, faults);
+    let config = Config {
+        shift_error_policy: ShiftErrorPolicy::CloseConnection,
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server_with(backend, config).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let tdo = client.shift(16, &[0; 2], &[0xAB, 0xCD]).await.unwrap();
+    assert_eq!(*tdo, [0xAB, 0x00]);
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    assert!(client.shift(8, &[0], &[0xAB]).await.is_err());
+}
+
+/// A transcript of a `settck:` of 100 ns, answered with 96 ns, and a shift of 12 bits.
+fn recorded_session() -> Vec<u8> {
+    let mut writer = TranscriptWriter::new(Vec::new()).unwrap();
+    writer
+        .record(&Message::<&[u8]>::GetInfo, b"xvcServer_v1.0:2048\n")
+        .unwrap();
+    writer
+        .record(
+            &Message::<&[u8]>::SetTck { period_ns: 100 },
+            &96u32.to_le_bytes(),
+        )
+        .unwrap();
+    let shift = Message::try_shift(12, &[0x00, 0x00][..], &[0x34, 0x02][..]).unwrap();
+    writer.record(&shift, &[0x12, 0x04]).unwrap();
+    writer.into_inner()
+}
+
+#[test]
+fn replay_answers_with_the_recorded_responses() {
+    let backend = ReplayBackend::new(recorded_session().as_slice()).unwrap();
+    assert_eq!(backend.remaining(), 2);
+    assert_eq!(backend.set_tck(100).unwrap(), 96);
+    let mut tdo = [0; 2];
+    backend.shift(12, &[0, 0], &[0x34, 0x02], &mut tdo).unwrap();
+    assert_eq!(tdo, [0x12, 0x04]);
+    assert_eq!(backend.remaining(), 0);
+    assert_eq!(
+        backend.shift(12, &[0, 0], &[0x34, 0x02], &mut tdo),
+        Err(ReplayError::Exhausted)
+    );
+}
+
+#[test]
+fn strict_replay_fails_calls_that_do_not_match() {
+    let backend = ReplayBackend::new(recorded_session().as_slice()).unwrap();
+    assert!(matches!(
+        backend.set_tck(50),
+        Err(ReplayError::Mismatch { index: 0, .. })
+    ));
+    // The next call is matched against the next record
+    let mut tdo = [0; 2];
+    let error = backend
+        .shift(12, &[0, 0], &[0xFF, 0x0F], &mut tdo)
+        .unwrap_err();
+    assert!(matches!(error, ReplayError::Mismatch { index: 1, .. }));
+}
+
+#[test]
+fn lenient_replay_answers_calls_that_do_not_match_with_zeros() {
+    let backend = ReplayBackend::new(recorded_session().as_slice())
+        .unwrap()
+        .strict(false);
+    assert_eq!(backend.set_tck(50).unwrap(), 50);
+    let mut tdo = [0xFF; 2];
+    backend.shift(12, &[0, 0], &[0xFF, 0x0F], &mut tdo).unwrap();
+    assert_eq!(tdo, [0, 0]);
+}
+
+#[test]
+fn replay_by_bit_count_ignores_the_vectors() {
+    let backend = ReplayBackend::new(recorded_session().as_slice())
+        .unwrap()
+        .matching(ReplayMatching::BitCount);
+    backend.set_tck(100).unwrap();
+    let mut tdo = [0; 2];
+    backend
+        .shift(12, &[0x01, 0], &[0xFF, 0x0F], &mut tdo)
+        .unwrap();
+    assert_eq!(tdo, [0x12, 0x04]);
+}
+
+/// Run the same session on `server` and return the answers the client received.
+async fn session<T>(server: &Server<T>) -> Vec<Vec<u8>>
+where
+    T: XvcServerMut + Send + 'static,
+{
+    let (stream, connection) = tokio::io::duplex(1024);
+    let client = async {
+        let mut client = XvcClient::new(stream);
+        let mut answers = Vec::new();
+        client.get_info().await.unwrap();
+        answers.push(client.set_tck(100).await.unwrap().to_le_bytes().to_vec());
+        answers.push(
+            client
+                .shift(16, &[0x00, 0x80], &[0x12, 0x34])
+                .await
+                .unwrap()
+                .to_vec(),
+        );
+        answers.push(client.set_tck(250).await.unwrap().to_le_bytes().to_vec());
+        answers.push(client.shift(3, &[0x03], &[0x05]).await.unwrap().to_vec());
+        answers
+    };
+    let (served, answers) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+    answers
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recorded_session_is_replayed_identically() {
+    let dir = std::env::temp_dir().join(format!("xvc-{}-replay", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir(&dir).unwrap();
+    let recorded = session(
+        &Builder::new()
+            .transcript_path(&dir)
+            .build(LoopbackBackend)
+            .unwrap(),
+    )
+    .await;
+
+    let transcript = std::fs::read_dir(&dir)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let backend = Arc::new(ReplayBackend::new(File::open(transcript).unwrap()).unwrap());
+    let replayed = session(&Server::new(Arc::clone(&backend), Config::default())).await;
+    assert_eq!(replayed, recorded);
+    assert_eq!(backend.remaining(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+use xvc_client::XvcClient;
+use xvc_server::{XvcServer, decorators::Throttled, server::Config};
+use xvc_tests::{StubBackend, spawn_server_with};
+
+/// Measured throughput never exceeds the cap, and the transfer takes at most five times as long
+/// as the cap allows plus 200 ms, which catches throttling that oversleeps without failing on a
+/// loaded machine.
+fn assert_throughput_within_cap(bits: u64, elapsed: Duration, cap: u64) {
+    let throughput = bits as f64 / elapsed.as_secs_f64();
+    assert!(
+        throughput <= cap as f64,
+        "throughput {throughput:.0} bit/s exceeds cap {cap} bit/s"
+    );
+    let limit =
+        Duration::from_secs_f64(5.0 * bits as f64 / cap as f64) + Duration::from_millis(200);
+    assert!(
+        elapsed < limit,
+        "{bits} bits took {elapsed:?} at a cap of {cap} bit/s, more than {limit:?}"
+    );
+}
+
+#[test]
+fn throttled_backend_respects_bandwidth() {
+    let cap = 100_000;
+    let backend = Throttled::new(StubBackend).with_bandwidth(cap);
+    let tms = [0u8; 125];
+    let tdi = [0u8; 125];
+    let mut tdo = [0u8; 125];
+
+    let start = Instant::now();
+    for _ in 0..8 {
+        backend.shift(1000, &tms, &tdi, &mut tdo).unwrap();
+    }
+    assert_throughput_within_cap(8000, start.elapsed(), cap);
+}
+
+#[test]
+fn throttled_backend_adds_latency_to_every_message() {
+    let latency = Duration::from_millis(20);
+    let backend = Throttled::new(StubBackend).with_latency(latency);
+
+    let start = Instant::now();
+    assert_eq!(backend.set_tck(100).unwrap(), 100);
+    assert!(start.elapsed() >= latency);
+
+    let start = Instant::now();
+    backend.shift(8, &[0], &[0], &mut [0]).unwrap();
+    assert!(start.elapsed() >= latency);
+}
+
+#[test]
+fn unthrottled_backend_is_not_delayed() {
+    let backend = Throttled::new(StubBackend).with_bandwidth(0);
+    let vector = vec![0u8; 125_000];
+    let mut tdo = vec![0u8; 125_000];
+    let start = Instant::now();
+    backend
+        .shift(1_000_000, &vector, &vector, &mut tdo)
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_millis(100));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn throttled_server_respects_bandwidth() {
+    let cap = 64_000;
+    let backend = Throttled::new(StubBackend).with_bandwidth(cap);
+    let (addr, _token) = spawn_server_with(backend, Config::default()).await;
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    let tms = vec![0u8; 512];
+    let tdi = vec![0u8; 512];
+
+    let start = Instant::now();
+    for _ in 0..2 {
+        client.shift(4096, &tms, &tdi).await.unwrap();
+    }
+    assert_throughput_within_cap(8192, start.elapsed(), cap);
+}
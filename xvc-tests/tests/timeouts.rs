@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{sleep, timeout},
+};
+use xvc_client::XvcClient;
+use xvc_server::server::Config;
+use xvc_tests::spawn_server;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn idle_client_stays_connected_without_idle_timeout() {
+    let config = Config {
+        idle_timeout: None,
+        message_timeout: Duration::from_millis(100),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    client.get_info().await.unwrap();
+    // Longer than the message timeout, which only applies within a command
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn idle_client_is_closed_after_idle_timeout() {
+    let config = Config {
+        idle_timeout: Some(Duration::from_millis(100)),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    client.get_info().await.unwrap();
+    sleep(Duration::from_millis(300)).await;
+    assert!(client.get_info().await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stalled_shift_is_closed_after_message_timeout() {
+    let config = Config {
+        idle_timeout: None,
+        message_timeout: Duration::from_millis(100),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+
+    let mut tcp = TcpStream::connect(addr).await.unwrap();
+    // A shift of 16 bits that stops after its TMS vector
+    tcp.write_all(b"shift:\x10\0\0\0\xff\xff").await.unwrap();
+    let mut rest = Vec::new();
+    let closed = timeout(Duration::from_secs(5), tcp.read_to_end(&mut rest))
+        .await
+        .expect("stalled client was not closed");
+    assert!(closed.is_err() || rest.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn message_timeout_restarts_with_each_command() {
+    let config = Config {
+        idle_timeout: None,
+        message_timeout: Duration::from_millis(200),
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+
+    let mut client = XvcClient::connect(addr).await.unwrap();
+    for _ in 0..4 {
+        assert_eq!(*client.shift(8, &[0], &[0xff]).await.unwrap(), [0]);
+        sleep(Duration::from_millis(100)).await;
+    }
+}
@@ -0,0 +1,116 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use tokio_util::sync::DropGuard;
+use xvc_client::{
+    Builder, XvcClient,
+    rustls::{
+        ClientConfig, RootCertStore,
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName},
+    },
+};
+use xvc_server::{server::Config, tls};
+use xvc_tests::spawn_server;
+
+/// A certificate authority that issues the certificates of a test.
+struct Authority {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+impl Authority {
+    fn new() -> Authority {
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        Authority { cert, key }
+    }
+
+    /// Issues a certificate for `name` and returns it with its private key.
+    fn issue(&self, name: &str) -> (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![name.to_owned()])
+            .unwrap()
+            .signed_by(&key, &self.cert, &self.key)
+            .unwrap();
+        let key = PrivatePkcs8KeyDer::from(key.serialize_der());
+        (vec![cert.der().clone()], key.into())
+    }
+
+    fn roots(&self) -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.cert.der().clone()).unwrap();
+        roots
+    }
+}
+
+fn server_name() -> ServerName<'static> {
+    ServerName::try_from("localhost").unwrap()
+}
+
+/// A server with a certificate for `localhost`, and a client configuration that trusts it.
+async fn spawn_tls_server(
+    authority: &Authority,
+    client_roots: Option<RootCertStore>,
+) -> (SocketAddr, DropGuard, Arc<ClientConfig>) {
+    let (chain, key) = authority.issue("localhost");
+    let config = Config {
+        tls: Some(tls::server_config(chain, key, client_roots).unwrap()),
+        ..Config::default()
+    };
+    let (addr, token) = spawn_server(config).await;
+    let client_config = ClientConfig::builder()
+        .with_root_certificates(authority.roots())
+        .with_no_client_auth();
+    (addr, token.drop_guard(), Arc::new(client_config))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_is_served_over_tls() {
+    let authority = Authority::new();
+    let (addr, _server, client_config) = spawn_tls_server(&authority, None).await;
+
+    let mut client = XvcClient::connect_tls(addr, client_config, server_name())
+        .await
+        .unwrap();
+    client.get_info().await.unwrap();
+    assert_eq!(*client.shift(8, &[0], &[0xff]).await.unwrap(), [0]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_handshake_does_not_stop_the_server() {
+    let authority = Authority::new();
+    let (addr, _server, client_config) = spawn_tls_server(&authority, None).await;
+
+    // A client that does not speak TLS is closed
+    let mut plain = XvcClient::connect(addr).await.unwrap();
+    assert!(plain.get_info().await.is_err());
+    drop(plain);
+
+    let mut client = Builder::new()
+        .connect_tls(addr, client_config, server_name())
+        .await
+        .unwrap();
+    client.get_info().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_certificate_is_verified() {
+    let authority = Authority::new();
+    let (addr, _server, anonymous) = spawn_tls_server(&authority, Some(authority.roots())).await;
+
+    // The server rejects the handshake, which TLS 1.3 clients notice on their first read
+    let anonymous = XvcClient::connect_tls(addr, anonymous, server_name()).await;
+    assert!(anonymous.is_err() || anonymous.unwrap().get_info().await.is_err());
+
+    let (chain, key) = authority.issue("client");
+    let client_config = ClientConfig::builder()
+        .with_root_certificates(authority.roots())
+        .with_client_auth_cert(chain, key)
+        .unwrap();
+    let mut client = XvcClient::connect_tls(addr, Arc::new(client_config), server_name())
+        .await
+        .unwrap();
+    client.get_info().await.unwrap();
+}
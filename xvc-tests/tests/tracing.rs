@@ -0,0 +1,132 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Debug,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::{net::TcpStream, time::sleep};
+use tracing::{
+    Event, Metadata, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+};
+use xvc_client::XvcClient;
+use xvc_server::server::Config;
+use xvc_tests::spawn_server;
+
+/// A span that was created, with its fields and the span it was created in.
+#[derive(Debug, Clone)]
+struct RecordedSpan {
+    name: &'static str,
+    fields: HashMap<&'static str, String>,
+    parent: Option<u64>,
+}
+
+impl Visit for RecordedSpan {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.fields.insert(field.name(), format!("{value:?}"));
+    }
+}
+
+static SPANS: LazyLock<Mutex<HashMap<u64, RecordedSpan>>> = LazyLock::new(Mutex::default);
+
+thread_local! {
+    /// The spans entered on this thread, innermost last.
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Records all spans of the process in `SPANS`.
+struct Recorder {
+    next_id: AtomicU64,
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut span = RecordedSpan {
+            name: attrs.metadata().name(),
+            fields: HashMap::new(),
+            parent: ENTERED.with(|entered| entered.borrow().last().copied()),
+        };
+        attrs.record(&mut span);
+        SPANS.lock().unwrap().insert(id, span);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(span) = SPANS.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(span);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, _span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().pop());
+    }
+}
+
+/// The recorded spans named `name`, with their ids.
+fn spans_named(name: &str) -> Vec<(u64, RecordedSpan)> {
+    let spans = SPANS.lock().unwrap();
+    let mut named: Vec<_> = spans
+        .iter()
+        .filter(|(_, span)| span.name == name)
+        .map(|(id, span)| (*id, span.clone()))
+        .collect();
+    named.sort_by_key(|(id, _)| *id);
+    named
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connection_and_messages_are_served_in_spans() {
+    tracing::subscriber::set_global_default(Recorder {
+        next_id: AtomicU64::new(1),
+    })
+    .unwrap();
+    let (addr, _token) = spawn_server(Config::default()).await;
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let peer = tcp.local_addr().unwrap().to_string();
+    let mut client = XvcClient::new(tcp);
+    client.get_info().await.unwrap();
+    client.shift(16, &[0, 0], &[0xff, 0xff]).await.unwrap();
+    drop(client);
+    // The duration of the last message is recorded after its answer was sent
+    sleep(Duration::from_millis(100)).await;
+
+    let connections = spans_named("connection");
+    let [(connection_id, connection)] = &connections[..] else {
+        panic!("expected one connection span: {connections:?}");
+    };
+    assert_eq!(connection.fields["peer"], peer);
+    assert_eq!(connection.fields["id"], "1");
+
+    let messages = spans_named("message");
+    let [(_, getinfo), (_, shift)] = &messages[..] else {
+        panic!("expected two message spans: {messages:?}");
+    };
+    assert_eq!(getinfo.fields["command"], "\"getinfo\"");
+    assert!(!getinfo.fields.contains_key("num_bits"));
+    assert_eq!(shift.fields["command"], "\"shift\"");
+    assert_eq!(shift.fields["num_bits"], "16");
+    for (_, message) in &messages {
+        assert_eq!(message.parent, Some(*connection_id));
+        assert!(message.fields.contains_key("duration_us"), "{message:?}");
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    convert::Infallible,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use xvc_client::XvcClient;
+use xvc_protocol::{
+    Message, ShiftVector, VectorLen,
+    transcript::{TranscriptReader, TranscriptWriter},
+};
+use xvc_server::{
+    XvcServer,
+    server::{Builder, Config},
+    test_util::LoopbackBackend,
+};
+use xvc_tests::spawn_server;
+
+/// Send the messages of `transcript` and assert that each is answered byte for byte as recorded.
+async fn replay(transcript: &[u8], tcp: &mut TcpStream) {
+    for (index, record) in TranscriptReader::new(transcript).unwrap().enumerate() {
+        let (message, expected) = record.unwrap();
+        message.write_to_async(tcp).await.unwrap();
+        let mut response = vec![0; expected.len()];
+        tcp.read_exact(&mut response).await.unwrap();
+        assert_eq!(response, expected, "record {index}: {message}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recorded_session_is_answered_identically() {
+    let mut writer = TranscriptWriter::new(Vec::new()).unwrap();
+    writer
+        .record(&Message::<&[u8]>::GetInfo, b"xvcServer_v1.0:10485760\n")
+        .unwrap();
+    writer
+        .record(
+            &Message::<&[u8]>::SetTck { period_ns: 100 },
+            &[0x64, 0, 0, 0],
+        )
+        .unwrap();
+    // The stub backend answers every shift with zeroes
+    let shift = Message::try_shift(20, &[0xFF, 0x00, 0x01][..], &[0x12, 0x34, 0x05][..]);
+    writer.record(&shift.unwrap(), &[0, 0, 0]).unwrap();
+    let transcript = writer.into_inner();
+
+    let (addr, _token) = spawn_server(Config::default()).await;
+    let mut tcp = TcpStream::connect(addr).await.unwrap();
+    replay(&transcript, &mut tcp).await;
+    tcp.shutdown().await.unwrap();
+}
+
+/// An empty directory that is unique to the test `name`.
+fn transcript_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("xvc-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir(&dir).unwrap();
+    dir
+}
+
+/// The transcript of the only connection that was recorded into `dir`.
+fn recorded(dir: &Path) -> (PathBuf, Vec<u8>) {
+    let files: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    let transcript = std::fs::read(&files[0]).unwrap();
+    (files[0].clone(), transcript)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_records_a_transcript_of_the_connection() {
+    let dir = transcript_dir("transcripts");
+    let server = Builder::new()
+        .transcript_path(&dir)
+        .build(LoopbackBackend)
+        .unwrap();
+    let (stream, connection) = tokio::io::duplex(1024);
+    let client = async {
+        let mut client = XvcClient::new(stream);
+        client.get_info().await.unwrap();
+        client.set_tck(100).await.unwrap();
+        client
+            .shift(12, &[0x00, 0x00], &[0xAB, 0x0C])
+            .await
+            .unwrap();
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+
+    let (file, transcript) = recorded(&dir);
+    assert!(file.to_str().unwrap().ends_with("-0-stream.xvct"));
+    let records: Vec<_> = TranscriptReader::new(transcript.as_slice())
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].0, Message::GetInfo);
+    assert_eq!(records[0].1, b"xvcServer_v1.0:10485760\n");
+    assert_eq!(records[1].0, Message::SetTck { period_ns: 100 });
+    assert_eq!(records[1].1, [100, 0, 0, 0]);
+    assert_eq!(records[2].1, [0xAB, 0x0C]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Returns TDI as TDO, taking shifts a byte at a time, and counts its calls.
+#[derive(Default)]
+struct BytewiseLoopback {
+    calls: AtomicUsize,
+}
+
+impl XvcServer for BytewiseLoopback {
+    type Err = Infallible;
+
+    fn set_tck(&self, period_ns: u32) -> Result<u32, Infallible> {
+        Ok(period_ns)
+    }
+
+    fn shift(&self, _: u32, _: &[u8], tdi: &[u8], tdo: &mut [u8]) -> Result<(), Infallible> {
+        tdo.copy_from_slice(tdi);
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn shift_chunk_len(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn long_and_oversized_shifts_are_recorded() {
+    let dir = transcript_dir("long-shifts");
+    let backend = Arc::new(BytewiseLoopback::default());
+    let server = Builder::new()
+        .transcript_path(&dir)
+        .max_vector_size(VectorLen::from_bytes(4))
+        .build(backend.clone())
+        .unwrap();
+    let (mut stream, connection) = tokio::io::duplex(1024);
+    let client = async {
+        // A shift longer than the chunks of the backend, and one longer than the server accepts
+        stream
+            .write_all(b"shift:\x18\x00\x00\x00\x00\x00\x00\x12\x34\x56")
+            .await
+            .unwrap();
+        stream
+            .write_all(b"shift:\x28\x00\x00\x00\x00\x00\x00\x00\x00\x01\x02\x03\x04\x05")
+            .await
+            .unwrap();
+        stream.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response, [0x12, 0x34, 0x56, 0, 0, 0, 0, 0]);
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+    // The long shift is not streamed, so it is recorded whole
+    assert_eq!(backend.calls.load(Ordering::Relaxed), 1);
+
+    let (_, transcript) = recorded(&dir);
+    // The oversized shift is recorded with its header and the zeros it was answered with
+    let mut discarded = 10u32.to_le_bytes().to_vec();
+    discarded.extend_from_slice(b"shift:\x28\x00\x00\x00");
+    discarded.extend_from_slice(&5u32.to_le_bytes());
+    discarded.extend_from_slice(&[0; 5]);
+    assert!(transcript.ends_with(&discarded));
+    // ... which readers skip
+    let records: Vec<_> = TranscriptReader::new(transcript.as_slice())
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(
+        records[0].0,
+        Message::Shift {
+            num_bits: 24,
+            tms: ShiftVector::from([0, 0, 0]),
+            tdi: ShiftVector::from([0x12, 0x34, 0x56]),
+        }
+    );
+    assert_eq!(records[0].1, [0x12, 0x34, 0x56]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_is_served_if_the_transcript_cannot_be_created() {
+    let server = Builder::new()
+        .transcript_path(std::env::temp_dir().join("xvc-no-such-directory"))
+        .build(LoopbackBackend)
+        .unwrap();
+    let (stream, connection) = tokio::io::duplex(1024);
+    let client = async {
+        let mut client = XvcClient::new(stream);
+        assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+}
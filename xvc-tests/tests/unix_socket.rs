@@ -0,0 +1,74 @@
+#![cfg(unix)]
+
+use std::{
+    io::ErrorKind,
+    os::unix::{fs::PermissionsExt, net::UnixListener},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio_util::sync::CancellationToken;
+use xvc_client::XvcClient;
+use xvc_server::server::{Config, Server};
+use xvc_tests::StubBackend;
+
+/// A socket path that is unique to the test `name`.
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("xvc-{}-{name}.sock", std::process::id()))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_is_served_over_a_unix_socket() {
+    let path = socket_path("serve");
+    let _ = std::fs::remove_file(&path);
+    // A socket file left behind by a previous server
+    drop(UnixListener::bind(&path).unwrap());
+
+    let config = Config {
+        unix_socket_mode: Some(0o600),
+        ..Config::default()
+    };
+    let server = Arc::new(Server::new(StubBackend, config));
+    let token = CancellationToken::new();
+    let listening = tokio::spawn({
+        let (server, path, token) = (Arc::clone(&server), path.clone(), token.clone());
+        async move { server.listen_unix_until(path, token).await }
+    });
+
+    // Retry until the server replaced the stale socket file
+    let mut client = None;
+    for attempt in 1..=10 {
+        if let Ok(connected) = XvcClient::connect_unix(&path).await {
+            client = Some(connected);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10 * attempt)).await;
+    }
+    let mut client = client.expect("server did not listen on the socket");
+    client.get_info().await.unwrap();
+    assert_eq!(client.set_tck(100).await.unwrap(), 100);
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    assert!(server.status().client.is_some());
+
+    drop(client);
+    token.cancel();
+    listening.await.unwrap().unwrap();
+    assert!(!path.exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn socket_of_a_live_server_is_not_replaced() {
+    let path = socket_path("live");
+    let _ = std::fs::remove_file(&path);
+    let _live = UnixListener::bind(&path).unwrap();
+
+    let server = Server::new(StubBackend, Config::default());
+    let error = server
+        .listen_unix_until(&path, CancellationToken::new())
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::AddrInUse);
+    std::fs::remove_file(&path).unwrap();
+}
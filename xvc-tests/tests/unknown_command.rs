@@ -0,0 +1,22 @@
+use xvc_server::server::Config;
+use xvc_tests::{exchange, spawn_server};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unknown_command_is_skipped_if_configured() {
+    let config = Config {
+        skip_unknown_commands: true,
+        ..Config::default()
+    };
+    let (addr, _token) = spawn_server(config).await;
+    let response = exchange(addr, b"lock:settck:\x64\x00\x00\x00unlock:getinfo:").await;
+    let mut expected = b"\x64\x00\x00\x00".to_vec();
+    expected.extend_from_slice(b"xvcServer_v1.0:10485760\n");
+    assert_eq!(response, expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unknown_command_closes_connection_by_default() {
+    let (addr, _token) = spawn_server(Config::default()).await;
+    let response = exchange(addr, b"lock:getinfo:").await;
+    assert!(response.is_empty());
+}
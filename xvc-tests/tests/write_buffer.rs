@@ -0,0 +1,132 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf},
+    time::timeout,
+};
+use xvc_client::XvcClient;
+use xvc_protocol::Message;
+use xvc_server::server::{Config, Server};
+use xvc_tests::StubBackend;
+
+/// The server end of an in-memory pipe that counts the writes of the server.
+struct CountingWrites {
+    stream: DuplexStream,
+    writes: Arc<AtomicUsize>,
+}
+
+impl AsyncRead for CountingWrites {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for CountingWrites {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_write(cx, buf);
+        if let Poll::Ready(Ok(_)) = poll {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Sends `count` `settck:` messages at once, before reading any answer, and returns the number
+/// of writes that the server needed to answer them.
+async fn pipelined_settck(config: Config, count: u32) -> usize {
+    let server = Server::new(StubBackend, config);
+    let (mut client, stream) = tokio::io::duplex(64 * 1024);
+    let writes = Arc::new(AtomicUsize::new(0));
+    let connection = CountingWrites {
+        stream,
+        writes: Arc::clone(&writes),
+    };
+
+    let client = async move {
+        let mut messages = Vec::new();
+        for period in 0..count {
+            Message::<&[u8]>::SetTck { period_ns: period }
+                .write_to(&mut messages)
+                .unwrap();
+        }
+        client.write_all(&messages).await.unwrap();
+        client.shutdown().await.unwrap();
+        for period in 0..count {
+            assert_eq!(client.read_u32_le().await.unwrap(), period);
+        }
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+    writes.load(Ordering::Relaxed)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn answers_to_pipelined_messages_are_sent_together() {
+    let writes = pipelined_settck(Config::default(), 100).await;
+    // All messages are in the pipe before the server reads them, so it never waits for the
+    // client and sends the answers once it reaches the end of the stream
+    assert_eq!(writes, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn answers_are_sent_on_their_own_without_a_write_buffer() {
+    let config = Config {
+        write_buffer_size: 0,
+        ..Config::default()
+    };
+    assert_eq!(pipelined_settck(config, 100).await, 100);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn each_answer_is_sent_before_the_next_message_is_read() {
+    let config = Config {
+        write_buffer_size: 1024 * 1024,
+        ..Config::default()
+    };
+    let server = Server::new(StubBackend, config);
+    let (client, connection) = tokio::io::duplex(4096);
+
+    // The client only sends a message once it received the previous answer, so an answer
+    // that is held back in the buffer times out
+    let client = async move {
+        let mut client = XvcClient::new(client);
+        let answer = Duration::from_secs(5);
+        timeout(answer, client.get_info()).await.unwrap().unwrap();
+        for period in 1..=10 {
+            let set = timeout(answer, client.set_tck(period)).await.unwrap();
+            assert_eq!(set.unwrap(), period);
+            let tdo = timeout(answer, client.shift(8, &[0], &[0xff]))
+                .await
+                .unwrap();
+            assert_eq!(*tdo.unwrap(), [0]);
+        }
+    };
+    let (served, ()) = tokio::join!(server.handle_stream(connection), client);
+    served.unwrap();
+    assert_eq!(server.stats().shifts, 10);
+}